@@ -3,6 +3,16 @@ fn main() {
     println!("cargo:rerun-if-changed=tauri.conf.json");
     println!("cargo:rerun-if-changed=capabilities");
     
+    // The Kotlin plugin classes the Android bridges load (ImeBridgePlugin,
+    // KeystorePlugin, ...) are not in the Android project yet, and registering
+    // a missing class fails at launch; they are only registered with the
+    // native-bridges feature
+    println!("cargo:rustc-check-cfg=cfg(android_bridges)");
+    let android = std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("android");
+    if android && std::env::var_os("CARGO_FEATURE_NATIVE_BRIDGES").is_some() {
+        println!("cargo:rustc-cfg=android_bridges");
+    }
+
     // Build the Tauri application
     tauri_build::build();
 }
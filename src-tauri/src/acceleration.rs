@@ -0,0 +1,181 @@
+// Hardware backends for on-device inference. Candle runs on Metal or CUDA
+// when the crate is built with the matching feature; whether the device can
+// actually use one is probed at runtime, and each model can be pinned to a
+// backend in settings.
+
+use candle_core::Device;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager, State};
+
+use crate::local_llm;
+use crate::settings::{self, InferenceBackend, SettingsState};
+use crate::speech::WHISPER_SAMPLE_RATE;
+use crate::whisper;
+
+// Fastest first
+const PREFERENCE: [InferenceBackend; 3] = [InferenceBackend::Cuda, InferenceBackend::Metal, InferenceBackend::Cpu];
+const DEFAULT_BENCHMARK_SECS: f32 = 10.0;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AccelerationCapabilities {
+    // Backends this build includes
+    pub compiled: Vec<InferenceBackend>,
+    // Backends that initialised on this device
+    pub available: Vec<InferenceBackend>,
+    pub model_backends: HashMap<String, InferenceBackend>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BenchmarkResult {
+    pub backend: InferenceBackend,
+    pub load_ms: Option<u64>,
+    pub transcribe_ms: Option<u64>,
+    // Seconds of processing per second of audio; below 1.0 is faster than real time
+    pub realtime_factor: Option<f64>,
+    pub error: Option<String>,
+}
+
+pub fn device(backend: InferenceBackend) -> Result<Device, String> {
+    match backend {
+        InferenceBackend::Cpu => Ok(Device::Cpu),
+        InferenceBackend::Metal => Device::new_metal(0).map_err(|e| e.to_string()),
+        InferenceBackend::Cuda => Device::new_cuda(0).map_err(|e| e.to_string()),
+    }
+}
+
+fn compiled() -> Vec<InferenceBackend> {
+    let mut backends = Vec::new();
+    if cfg!(feature = "cuda") {
+        backends.push(InferenceBackend::Cuda);
+    }
+    if cfg!(feature = "metal") {
+        backends.push(InferenceBackend::Metal);
+    }
+    backends.push(InferenceBackend::Cpu);
+    backends
+}
+
+// Probed once; creating a GPU device fails when the build or the hardware lacks support
+pub fn available() -> &'static [InferenceBackend] {
+    static AVAILABLE: OnceLock<Vec<InferenceBackend>> = OnceLock::new();
+    AVAILABLE.get_or_init(|| {
+        PREFERENCE
+            .iter()
+            .copied()
+            .filter(|backend| device(*backend).is_ok())
+            .collect()
+    })
+}
+
+// The pinned backend while it is available, otherwise the fastest one
+fn choose(pinned: Option<InferenceBackend>, available: &[InferenceBackend]) -> InferenceBackend {
+    pinned
+        .filter(|backend| available.contains(backend))
+        .or_else(|| available.first().copied())
+        .unwrap_or(InferenceBackend::Cpu)
+}
+
+pub fn backend_for(app_handle: &AppHandle, model_id: &str) -> InferenceBackend {
+    let pinned = settings::current(&app_handle.state::<SettingsState>())
+        .model_backends
+        .get(model_id)
+        .copied();
+    choose(pinned, available())
+}
+
+#[tauri::command]
+pub fn get_acceleration_capabilities(state: State<'_, SettingsState>) -> AccelerationCapabilities {
+    AccelerationCapabilities {
+        compiled: compiled(),
+        available: available().to_vec(),
+        model_backends: settings::current(&state).model_backends,
+    }
+}
+
+// Command to pin a model to a backend, or go back to automatic with None
+#[tauri::command]
+pub fn set_model_backend(
+    model_id: String,
+    backend: Option<InferenceBackend>,
+    app_handle: AppHandle,
+    state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    if let Some(backend) = backend {
+        if !available().contains(&backend) {
+            return Err(format!("{:?} is not available on this device", backend));
+        }
+    }
+    let mut updated = settings::current(&state);
+    match backend {
+        Some(backend) => updated.model_backends.insert(model_id.clone(), backend),
+        None => updated.model_backends.remove(&model_id),
+    };
+    settings::update_settings(updated, app_handle.clone(), state)?;
+    // Reloaded on the new backend at next use
+    whisper::unload(&app_handle, &model_id)?;
+    local_llm::unload(&app_handle, &model_id)
+}
+
+// Command to time an installed model on every available backend with synthetic audio
+#[tauri::command]
+pub async fn benchmark_backends(
+    model_id: String,
+    seconds: Option<f32>,
+    app_handle: AppHandle,
+) -> Result<Vec<BenchmarkResult>, String> {
+    let seconds = seconds.unwrap_or(DEFAULT_BENCHMARK_SECS).clamp(1.0, 30.0);
+    // Quiet noise keeps the decoder from stopping at once on pure silence
+    let pcm: Vec<f32> = (0..(seconds * WHISPER_SAMPLE_RATE as f32) as usize)
+        .map(|_| (rand::random::<f32>() - 0.5) * 0.01)
+        .collect();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        available()
+            .iter()
+            .map(|backend| match whisper::benchmark(&app_handle, &model_id, *backend, &pcm) {
+                Ok((load_ms, transcribe_ms)) => BenchmarkResult {
+                    backend: *backend,
+                    load_ms: Some(load_ms),
+                    transcribe_ms: Some(transcribe_ms),
+                    realtime_factor: Some(transcribe_ms as f64 / 1000.0 / seconds as f64),
+                    error: None,
+                },
+                Err(e) => BenchmarkResult {
+                    backend: *backend,
+                    load_ms: None,
+                    transcribe_ms: None,
+                    realtime_factor: None,
+                    error: Some(e),
+                },
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_is_always_there() {
+        assert_eq!(compiled().last(), Some(&InferenceBackend::Cpu));
+        assert!(device(InferenceBackend::Cpu).is_ok());
+        assert!(available().contains(&InferenceBackend::Cpu));
+        // Probed in order of preference
+        assert_eq!(available().last(), Some(&InferenceBackend::Cpu));
+    }
+
+    #[test]
+    fn pinned_backends_fall_back_when_unavailable() {
+        let gpu = [InferenceBackend::Metal, InferenceBackend::Cpu];
+        assert_eq!(choose(Some(InferenceBackend::Cpu), &gpu), InferenceBackend::Cpu);
+        assert_eq!(choose(None, &gpu), InferenceBackend::Metal);
+        assert_eq!(choose(Some(InferenceBackend::Cuda), &gpu), InferenceBackend::Metal);
+        assert_eq!(choose(Some(InferenceBackend::Cuda), &[InferenceBackend::Cpu]), InferenceBackend::Cpu);
+        assert_eq!(choose(None, &[]), InferenceBackend::Cpu);
+    }
+}
@@ -0,0 +1,164 @@
+// Screen reader response stage: expands abbreviations, describes emojis
+// and flattens markdown tables into sentences.
+
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("e.g.", "for example"),
+    ("i.e.", "that is"),
+    ("etc.", "et cetera"),
+    ("approx.", "approximately"),
+    ("vs.", "versus"),
+    ("min.", "minutes"),
+    ("hrs", "hours"),
+    ("w/o", "without"),
+    ("w/", "with"),
+    ("&", "and"),
+];
+
+const EMOJIS: &[(char, &str)] = &[
+    ('😀', "grinning face"),
+    ('😂', "face with tears of joy"),
+    ('🙂', "smiling face"),
+    ('😢', "crying face"),
+    ('👍', "thumbs up"),
+    ('👎', "thumbs down"),
+    ('❤', "red heart"),
+    ('🔥', "fire"),
+    ('✅', "check mark"),
+    ('❌', "cross mark"),
+    ('⚠', "warning"),
+    ('☀', "sun"),
+    ('🌧', "rain cloud"),
+    ('⏰', "alarm clock"),
+    ('🎉', "party popper"),
+];
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F300..=0x1FAFF | 0x2600..=0x27BF)
+}
+
+fn describe_emojis(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let Some((_, name)) = EMOJIS.iter().find(|(emoji, _)| *emoji == c) {
+            out.push_str(&format!("({})", name));
+        } else if is_emoji(c) {
+            out.push_str("(emoji)");
+        } else if c != '\u{FE0F}' {
+            // Variation selectors are invisible but some readers announce them
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn expand_abbreviations(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(abbr, _)| word.eq_ignore_ascii_case(abbr))
+                .map(|(_, full)| full.to_string())
+                .unwrap_or_else(|| word.to_string())
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn table_cells(line: &str) -> Vec<String> {
+    line.trim()
+        .trim_matches('|')
+        .split('|')
+        .map(|cell| cell.trim().to_string())
+        .collect()
+}
+
+fn is_separator_row(line: &str) -> bool {
+    line.trim()
+        .chars()
+        .all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+// Rewrite markdown tables as one sentence per row
+fn flatten_tables(text: &str) -> String {
+    let mut out = Vec::new();
+    let mut header: Option<Vec<String>> = None;
+
+    for line in text.lines() {
+        if !line.trim_start().starts_with('|') {
+            header = None;
+            out.push(line.to_string());
+            continue;
+        }
+        if is_separator_row(line) {
+            continue;
+        }
+        let cells = table_cells(line);
+        match &header {
+            None => header = Some(cells),
+            Some(columns) => {
+                let sentence = columns
+                    .iter()
+                    .zip(cells.iter())
+                    .map(|(column, value)| format!("{}: {}", column, value))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                out.push(format!("{}.", sentence));
+            }
+        }
+    }
+    out.join("\n")
+}
+
+fn strip_markdown(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            line.trim_start_matches('#')
+                .trim_start()
+                .replace("**", "")
+                .replace("__", "")
+                .replace('`', "")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn screen_reader_friendly(text: &str) -> String {
+    let text = flatten_tables(text);
+    let text = strip_markdown(&text);
+    let text = describe_emojis(&text);
+    expand_abbreviations(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_become_one_sentence_per_row() {
+        let text = "Forecast:\n| Day | High |\n|:---|---:|\n| Mon | 20 |\n| Tue | 18 |\nDone";
+        assert_eq!(flatten_tables(text), "Forecast:\nDay: Mon, High: 20.\nDay: Tue, High: 18.\nDone");
+    }
+
+    #[test]
+    fn emojis_are_described() {
+        assert_eq!(describe_emojis("Great 👍\u{FE0F} 🦀"), "Great (thumbs up) (emoji)");
+        assert_eq!(describe_emojis("No emoji here"), "No emoji here");
+    }
+
+    #[test]
+    fn abbreviations_are_spelled_out() {
+        assert_eq!(
+            expand_abbreviations("Bring snacks, E.g. fruit & nuts w/ water"),
+            "Bring snacks, for example fruit and nuts with water"
+        );
+        assert_eq!(expand_abbreviations("R&D"), "R&D");
+    }
+
+    #[test]
+    fn answers_are_made_screen_reader_friendly() {
+        assert_eq!(
+            screen_reader_friendly("## **Plan** for `today` 🎉\n| Time | What |\n|---|---|\n| 9am | Gym |"),
+            "Plan for today (party popper)\nTime: 9am, What: Gym."
+        );
+    }
+}
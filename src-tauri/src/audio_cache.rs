@@ -0,0 +1,196 @@
+// Cleanup of the recordings cache. Every recording is kept as a WAV file so
+// it can be replayed or transcribed again; those files are deleted once they
+// pass the configured age and the oldest go first when the cache grows too big.
+
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::settings::{self, AudioCacheSettings, SettingsState};
+
+const RECORDINGS_DIR: &str = "recordings";
+const DAY_SECS: u64 = 24 * 60 * 60;
+const MB: u64 = 1024 * 1024;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CleanupReport {
+    pub files: usize,
+    pub bytes_freed: u64,
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+pub fn recordings_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join(RECORDINGS_DIR);
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+// Cached recordings, oldest first
+fn cached_files(app_handle: &AppHandle) -> Result<Vec<CachedFile>, String> {
+    let entries = std::fs::read_dir(recordings_dir(app_handle)?).map_err(|e| e.to_string())?;
+    let mut files: Vec<CachedFile> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| CachedFile {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            })
+        })
+        .collect();
+    files.sort_by_key(|file| file.modified);
+    Ok(files)
+}
+
+fn remove(files: &[CachedFile]) -> CleanupReport {
+    let mut report = CleanupReport::default();
+    for file in files {
+        if std::fs::remove_file(&file.path).is_ok() {
+            report.files += 1;
+            report.bytes_freed += file.size;
+        }
+    }
+    report
+}
+
+// Recordings past the age limit, then the oldest until the cache fits
+fn select_expired(files: Vec<CachedFile>, policy: &AudioCacheSettings, now: SystemTime) -> Vec<CachedFile> {
+    let max_age = Duration::from_secs(policy.max_age_days.saturating_mul(DAY_SECS));
+    let expired = |file: &CachedFile| {
+        policy.max_age_days > 0 && now.duration_since(file.modified).is_ok_and(|age| age > max_age)
+    };
+
+    let mut total: u64 = files.iter().filter(|file| !expired(file)).map(|file| file.size).sum();
+    let limit = policy.max_size_mb.saturating_mul(MB);
+    files
+        .into_iter()
+        .filter(|file| {
+            if expired(file) {
+                return true;
+            }
+            if policy.max_size_mb > 0 && total > limit {
+                total -= file.size;
+                return true;
+            }
+            false
+        })
+        .collect()
+}
+
+pub fn enforce(app_handle: &AppHandle, policy: &AudioCacheSettings) -> Result<CleanupReport, String> {
+    let doomed = select_expired(cached_files(app_handle)?, policy, SystemTime::now());
+    Ok(remove(&doomed))
+}
+
+// Enforce the policy once in the background after startup
+pub fn spawn_cleanup(app_handle: AppHandle) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let policy = settings::current(&app_handle.state::<SettingsState>()).audio_cache;
+        match enforce(&app_handle, &policy) {
+            Ok(report) if report.files > 0 => {
+                tracing::info!(?report, "cleaned up cached recordings");
+                let _ = app_handle.emit("audio_cache://cleaned", report);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!(error = %e, "cleaning up cached recordings failed"),
+        }
+    });
+}
+
+// Command to delete every cached recording, reporting the space freed
+#[tauri::command]
+pub async fn purge_audio_cache(app_handle: AppHandle) -> Result<CleanupReport, String> {
+    tauri::async_runtime::spawn_blocking(move || Ok(remove(&cached_files(&app_handle)?)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn get_audio_cache_policy(settings_state: State<'_, SettingsState>) -> AudioCacheSettings {
+    settings::current(&settings_state).audio_cache
+}
+
+// Command to change the limits; the cache is trimmed to them right away
+#[tauri::command]
+pub fn set_audio_cache_policy(
+    policy: AudioCacheSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<CleanupReport, String> {
+    let mut updated = settings::current(&settings_state);
+    updated.audio_cache = policy.clone();
+    settings::update_settings(updated, app_handle.clone(), settings_state)?;
+    enforce(&app_handle, &policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Files of the given sizes in MB, modified the given number of days before now
+    fn files(now: SystemTime, specs: &[(u64, u64)]) -> Vec<CachedFile> {
+        specs
+            .iter()
+            .enumerate()
+            .map(|(i, (days, mb))| CachedFile {
+                path: PathBuf::from(format!("{}.wav", i)),
+                size: mb * MB,
+                modified: now - Duration::from_secs(days * DAY_SECS),
+            })
+            .collect()
+    }
+
+    fn names(files: &[CachedFile]) -> Vec<String> {
+        files.iter().map(|file| file.path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn old_recordings_expire() {
+        let now = SystemTime::now();
+        let policy = AudioCacheSettings {
+            max_age_days: 7,
+            max_size_mb: 0,
+        };
+        let doomed = select_expired(files(now, &[(10, 1), (8, 1), (2, 1)]), &policy, now);
+        assert_eq!(names(&doomed), vec!["0.wav", "1.wav"]);
+    }
+
+    #[test]
+    fn oldest_go_first_when_the_cache_is_too_big() {
+        let now = SystemTime::now();
+        let policy = AudioCacheSettings {
+            max_age_days: 0,
+            max_size_mb: 5,
+        };
+        // Oldest first, as cached_files returns them
+        let doomed = select_expired(files(now, &[(30, 3), (20, 2), (10, 2), (1, 2)]), &policy, now);
+        assert_eq!(names(&doomed), vec!["0.wav", "1.wav"]);
+    }
+
+    #[test]
+    fn zero_limits_keep_everything() {
+        let now = SystemTime::now();
+        let policy = AudioCacheSettings {
+            max_age_days: 0,
+            max_size_mb: 0,
+        };
+        assert!(select_expired(files(now, &[(365, 900), (1, 900)]), &policy, now).is_empty());
+
+        let huge = AudioCacheSettings {
+            max_age_days: u64::MAX,
+            max_size_mb: u64::MAX,
+        };
+        assert!(select_expired(files(now, &[(365, 900)]), &huge, now).is_empty());
+    }
+}
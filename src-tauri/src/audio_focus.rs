@@ -0,0 +1,257 @@
+// Audio route and focus. While the assistant speaks or listens it takes
+// transient audio focus, so other apps' media ducks and comes back afterwards;
+// the chosen route (speaker, earpiece or Bluetooth) is applied each time.
+// Speaking and listening overlap, so focus is counted and given back when the
+// last holder lets go.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Manager, State,
+};
+
+use crate::settings::{self, AudioRoute, SettingsState};
+
+#[cfg(android_bridges)]
+use serde::Deserialize;
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct RouteRequest {
+    route: AudioRoute,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct RoutesResponse {
+    routes: Vec<AudioRoute>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AudioRoutes {
+    pub current: AudioRoute,
+    // Routes the device can use right now; Bluetooth only while a headset is connected
+    pub available: Vec<AudioRoute>,
+}
+
+#[derive(Default)]
+struct FocusHolders {
+    count: usize,
+    // Whether focus was actually requested, so it is only abandoned if held
+    requested: bool,
+}
+
+impl FocusHolders {
+    // Count a new holder; true for the first, which takes focus
+    fn take(&mut self) -> bool {
+        self.count = self.count.saturating_add(1);
+        self.count == 1
+    }
+
+    // Let one holder go; true when the last one has and focus should be abandoned
+    fn release(&mut self) -> bool {
+        self.count = self.count.saturating_sub(1);
+        if self.count == 0 && self.requested {
+            self.requested = false;
+            return true;
+        }
+        false
+    }
+}
+
+pub struct AudioFocusBridge {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+    holders: Mutex<FocusHolders>,
+}
+
+impl AudioFocusBridge {
+    fn set_route(&self, route: AudioRoute) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("setRoute", RouteRequest { route })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = route;
+            Err("Audio routing is not available on this device".to_string())
+        }
+    }
+
+    fn routes(&self) -> Result<Vec<AudioRoute>, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<RoutesResponse>("availableRoutes", ())
+                .map(|response| response.routes)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            Ok(vec![AudioRoute::Speaker])
+        }
+    }
+
+    // Transient, may-duck focus; the route is applied along with it
+    fn request_focus(&self, route: AudioRoute) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("requestFocus", RouteRequest { route })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = route;
+            Ok(())
+        }
+    }
+
+    fn abandon_focus(&self) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("abandonFocus", ())
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            Ok(())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("audio-focus")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = AudioFocusBridge {
+                handle: api.register_android_plugin("company.atechnology.plates", "AudioFocusPlugin")?,
+                holders: Mutex::new(FocusHolders::default()),
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                AudioFocusBridge {
+                    holders: Mutex::new(FocusHolders::default()),
+                }
+            };
+            app.manage(bridge);
+            Ok(())
+        })
+        .build()
+}
+
+// Held for as long as the assistant is speaking or listening
+pub struct FocusGuard {
+    app_handle: AppHandle,
+}
+
+impl Drop for FocusGuard {
+    fn drop(&mut self) {
+        let bridge = self.app_handle.state::<AudioFocusBridge>();
+        let Ok(mut holders) = bridge.holders.lock() else {
+            return;
+        };
+        if holders.release() {
+            if let Err(e) = bridge.abandon_focus() {
+                tracing::warn!(error = %e, "abandoning audio focus failed");
+            }
+        }
+    }
+}
+
+// Take audio focus for speaking or listening. Failures are only logged:
+// the assistant still speaks when another app keeps focus.
+pub fn acquire(app_handle: &AppHandle) -> FocusGuard {
+    let output = settings::current(&app_handle.state::<SettingsState>()).audio_output;
+    let bridge = app_handle.state::<AudioFocusBridge>();
+    if let Ok(mut holders) = bridge.holders.lock() {
+        if holders.take() {
+            // Without ducking, only the route is applied and other media plays on
+            let result = if output.duck_media {
+                bridge.request_focus(output.route)
+            } else {
+                bridge.set_route(output.route)
+            };
+            match result {
+                Ok(()) => holders.requested = output.duck_media,
+                Err(e) => tracing::debug!(error = %e, "audio focus not taken"),
+            }
+        }
+    }
+    FocusGuard {
+        app_handle: app_handle.clone(),
+    }
+}
+
+#[tauri::command]
+pub fn list_audio_routes(
+    bridge: State<'_, AudioFocusBridge>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<AudioRoutes, String> {
+    Ok(AudioRoutes {
+        current: settings::current(&settings_state).audio_output.route,
+        available: bridge.routes()?,
+    })
+}
+
+// Command to choose where speech is played and recorded; takes effect straight away
+#[tauri::command]
+pub fn set_audio_route(
+    route: AudioRoute,
+    app_handle: AppHandle,
+    bridge: State<'_, AudioFocusBridge>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    if !bridge.routes()?.contains(&route) {
+        return Err("That audio route is not available right now".to_string());
+    }
+    bridge.set_route(route)?;
+    let mut updated = settings::current(&settings_state);
+    updated.audio_output.route = route;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+// Command to turn ducking of other apps' media on or off
+#[tauri::command]
+pub fn set_media_ducking(
+    enabled: bool,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let mut updated = settings::current(&settings_state);
+    updated.audio_output.duck_media = enabled;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn focus_is_abandoned_when_the_last_holder_lets_go() {
+        let mut holders = FocusHolders::default();
+        assert!(holders.take());
+        holders.requested = true;
+        assert!(!holders.take());
+        assert!(!holders.release());
+        assert!(holders.release());
+        assert!(!holders.requested);
+        // A stray release neither underflows nor abandons focus again
+        assert!(!holders.release());
+        assert!(holders.take());
+    }
+
+    #[test]
+    fn focus_taken_without_ducking_is_not_abandoned() {
+        let mut holders = FocusHolders::default();
+        assert!(holders.take());
+        assert!(!holders.release());
+    }
+}
@@ -0,0 +1,156 @@
+// Cleanup of captured speech before it is transcribed: RNNoise noise
+// suppression followed by automatic gain control, so quiet or distant voices
+// in noisy rooms reach the recognizer at a steady level.
+
+use nnnoiseless::DenoiseState;
+use tauri::{AppHandle, State};
+
+use crate::settings::{self, AudioProcessingSettings, SettingsState};
+use crate::speech;
+
+// RNNoise works on 10 ms frames of 48 kHz audio
+const DENOISE_RATE: u32 = 48000;
+// Gain is recomputed every 20 ms
+const AGC_FRAME_MS: u32 = 20;
+// Frames quieter than this are noise; their gain is held rather than raised
+const AGC_GATE: f32 = 0.003;
+const AGC_MAX_GAIN: f32 = 10.0;
+// Gain falls quickly on loud onsets and rises slowly, per frame
+const AGC_ATTACK: f32 = 0.5;
+const AGC_RELEASE: f32 = 0.05;
+
+// Run mono audio through the enabled stages; denoised audio comes back at
+// 48 kHz, so the new rate is returned with it
+pub fn process(mono: Vec<f32>, sample_rate: u32, config: &AudioProcessingSettings) -> (Vec<f32>, u32) {
+    let (mut samples, rate) = if config.noise_suppression {
+        (denoise(&speech::resample(&mono, sample_rate, DENOISE_RATE)), DENOISE_RATE)
+    } else {
+        (mono, sample_rate)
+    };
+    if config.gain_control {
+        gain_control(&mut samples, rate, config.target_level);
+    }
+    (samples, rate)
+}
+
+fn denoise(samples: &[f32]) -> Vec<f32> {
+    let mut state = DenoiseState::new();
+    let mut input = [0.0f32; DenoiseState::FRAME_SIZE];
+    let mut output = [0.0f32; DenoiseState::FRAME_SIZE];
+    let mut denoised = Vec::with_capacity(samples.len() + DenoiseState::FRAME_SIZE);
+    for frame in samples.chunks(DenoiseState::FRAME_SIZE) {
+        // RNNoise expects 16-bit sample values; the last frame is zero padded
+        input.fill(0.0);
+        for (slot, sample) in input.iter_mut().zip(frame) {
+            *slot = sample * i16::MAX as f32;
+        }
+        state.process_frame(&mut output, &input);
+        denoised.extend(output[..frame.len()].iter().map(|sample| sample / i16::MAX as f32));
+    }
+    denoised
+}
+
+// Bring speech towards the target RMS level, interpolating the gain across
+// each frame and clamping peaks
+fn gain_control(samples: &mut [f32], sample_rate: u32, target: f32) {
+    let frame_len = (sample_rate * AGC_FRAME_MS / 1000).max(1) as usize;
+    let mut gain = 1.0f32;
+    for frame in samples.chunks_mut(frame_len) {
+        let level = speech::rms(frame);
+        let wanted = if level < AGC_GATE {
+            gain
+        } else {
+            (target / level).clamp(1.0 / AGC_MAX_GAIN, AGC_MAX_GAIN)
+        };
+        let rate = if wanted < gain { AGC_ATTACK } else { AGC_RELEASE };
+        let next = gain + (wanted - gain) * rate;
+        let len = frame.len() as f32;
+        for (i, sample) in frame.iter_mut().enumerate() {
+            let g = gain + (next - gain) * (i as f32 / len);
+            *sample = (*sample * g).clamp(-1.0, 1.0);
+        }
+        gain = next;
+    }
+}
+
+#[tauri::command]
+pub fn get_audio_processing(settings_state: State<'_, SettingsState>) -> AudioProcessingSettings {
+    settings::current(&settings_state).audio_processing
+}
+
+// Command to turn noise suppression and gain control on or off for recordings
+#[tauri::command]
+pub fn set_audio_processing(
+    config: AudioProcessingSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    if !(0.01..=0.5).contains(&config.target_level) {
+        return Err("The target level must be between 0.01 and 0.5".to_string());
+    }
+    let mut updated = settings::current(&settings_state);
+    updated.audio_processing = config;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    fn tone(amplitude: f32, seconds: f32) -> Vec<f32> {
+        let len = (RATE as f32 * seconds) as usize;
+        (0..len)
+            .map(|i| amplitude * (i as f32 * 440.0 * std::f32::consts::TAU / RATE as f32).sin())
+            .collect()
+    }
+
+    fn last_frame_level(samples: &[f32]) -> f32 {
+        speech::rms(&samples[samples.len() - (RATE * AGC_FRAME_MS / 1000) as usize..])
+    }
+
+    #[test]
+    fn quiet_speech_is_raised_up_to_the_maximum_gain() {
+        let mut samples = tone(0.01, 2.0);
+        gain_control(&mut samples, RATE, 0.1);
+        let level = last_frame_level(&samples);
+        assert!(level > 0.06 && level <= 0.01 * AGC_MAX_GAIN, "{}", level);
+    }
+
+    #[test]
+    fn loud_speech_is_brought_down_and_clamped() {
+        let mut samples = tone(0.9, 1.0);
+        gain_control(&mut samples, RATE, 0.1);
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        let level = last_frame_level(&samples);
+        assert!((level - 0.1).abs() < 0.02, "{}", level);
+    }
+
+    #[test]
+    fn background_noise_is_not_amplified() {
+        let quiet = tone(0.002, 1.0);
+        let mut samples = quiet.clone();
+        gain_control(&mut samples, RATE, 0.1);
+        assert_eq!(samples, quiet);
+    }
+
+    #[test]
+    fn denoised_audio_comes_back_at_48_khz() {
+        let config = AudioProcessingSettings {
+            noise_suppression: true,
+            gain_control: false,
+            target_level: 0.1,
+        };
+        let (samples, rate) = process(tone(0.3, 0.1), RATE, &config);
+        assert_eq!(rate, DENOISE_RATE);
+        assert_eq!(samples.len(), 4800);
+
+        let off = AudioProcessingSettings {
+            noise_suppression: false,
+            gain_control: false,
+            ..config
+        };
+        assert_eq!(process(tone(0.3, 0.1), RATE, &off), (tone(0.3, 0.1), RATE));
+    }
+}
@@ -0,0 +1,348 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+use crate::keystore::Keystore;
+use crate::rest_api;
+use crate::storage;
+
+const AUTOMATIONS_FILE: &str = "automations.json";
+
+// Events are dotted names such as "timer.done", "geofence.entered",
+// "battery.low" or "wakeword.detected"
+pub const EVENT_TIMER_DONE: &str = "timer.done";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Webhook {
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+    // Event that fires this webhook, e.g. "timer.done"
+    pub event: String,
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // Body with {{event}}, {{timestamp}} and {{data.field}} placeholders
+    #[serde(default)]
+    pub body_template: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct AutomationStore {
+    next_id: u64,
+    webhooks: Vec<Webhook>,
+}
+
+pub type AutomationsState = Mutex<AutomationStore>;
+
+impl AutomationStore {
+    fn matching(&self, event: &str) -> Vec<Webhook> {
+        self.webhooks
+            .iter()
+            .filter(|webhook| webhook.enabled && webhook.event == event)
+            .cloned()
+            .collect()
+    }
+
+    // Create a webhook, or update it when the id already exists
+    fn upsert(&mut self, mut webhook: Webhook) -> Webhook {
+        let existing = self
+            .webhooks
+            .iter()
+            .position(|w| webhook.id != 0 && w.id == webhook.id);
+        match existing {
+            Some(index) => self.webhooks[index] = webhook.clone(),
+            None => {
+                self.next_id += 1;
+                webhook.id = self.next_id;
+                self.webhooks.push(webhook.clone());
+            }
+        }
+        webhook
+    }
+}
+
+#[derive(Serialize)]
+pub struct WebhookResult {
+    pub webhook_id: u64,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+pub fn load(app_handle: &AppHandle) -> AutomationStore {
+    storage::load_json(app_handle, AUTOMATIONS_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Strings are escaped as JSON string contents, since bodies are sent as JSON
+// and event data such as message text must not break out of its quotes
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => escape_json(s),
+        other => other.to_string(),
+    }
+}
+
+fn escape_json(text: &str) -> String {
+    let quoted = Value::String(text.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+// Fill in {{placeholders}} from the event and its data
+pub fn render_template(template: &str, event: &str, data: &Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = rest[start + 2..start + end].trim();
+        let replacement = match key {
+            "event" => escape_json(event),
+            "timestamp" => now_secs().to_string(),
+            "data" => data.to_string(),
+            _ => key
+                .strip_prefix("data.")
+                .and_then(|path| data.pointer(&format!("/{}", path.replace('.', "/"))))
+                .map(value_to_string)
+                .unwrap_or_default(),
+        };
+        out.push_str(&replacement);
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// The local API signing key, so receivers can check a webhook came from this device
+fn signing_key(app_handle: &AppHandle) -> Option<String> {
+    rest_api::signing_key(&app_handle.state::<Keystore>())
+        .map_err(|e| tracing::warn!(error = %e, "webhook signing key unavailable"))
+        .ok()
+}
+
+async fn send_webhook(webhook: &Webhook, event: &str, data: &Value, key: Option<&str>) -> WebhookResult {
+    let body = if webhook.body_template.is_empty() {
+        serde_json::json!({ "event": event, "timestamp": now_secs(), "data": data }).to_string()
+    } else {
+        render_template(&webhook.body_template, event, data)
+    };
+
+    let method = reqwest::Method::from_bytes(webhook.method.to_uppercase().as_bytes())
+        .unwrap_or(reqwest::Method::POST);
+    let mut request = reqwest::Client::new()
+        .request(method, &webhook.url)
+        .header("Content-Type", "application/json")
+        .body(body.clone());
+    for (name, value) in &webhook.headers {
+        request = request.header(name, value);
+    }
+    // Signed as "<timestamp>.<body>" so a captured request cannot be replayed later
+    if let Some(key) = key {
+        let timestamp = now_secs();
+        if let Ok(signature) = rest_api::sign(key, format!("{}.{}", timestamp, body).as_bytes()) {
+            request = request.header("X-Plates-Signature", format!("t={},v1={}", timestamp, signature));
+        }
+    }
+
+    match request.send().await {
+        Ok(response) => WebhookResult {
+            webhook_id: webhook.id,
+            status: Some(response.status().as_u16()),
+            error: None,
+        },
+        Err(e) => WebhookResult {
+            webhook_id: webhook.id,
+            status: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+fn webhooks_for(app_handle: &AppHandle, event: &str) -> Vec<Webhook> {
+    let state = app_handle.state::<AutomationsState>();
+    let Ok(store) = state.lock() else {
+        return Vec::new();
+    };
+    store.matching(event)
+}
+
+// Report an event; matching webhooks are sent in the background
+pub fn trigger(app_handle: &AppHandle, event: &str, data: Value) {
+    let webhooks = webhooks_for(app_handle, event);
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let key = signing_key(app_handle);
+    let event = event.to_string();
+    tauri::async_runtime::spawn(async move {
+        for webhook in webhooks {
+            send_webhook(&webhook, &event, &data, key.as_deref()).await;
+        }
+    });
+}
+
+// Send a single webhook in the background regardless of its event, e.g. as a rule action
+pub fn fire(app_handle: &AppHandle, id: u64, event: &str, data: Value) {
+    let webhook = {
+        let state = app_handle.state::<AutomationsState>();
+        let Ok(store) = state.lock() else { return };
+        store.webhooks.iter().find(|webhook| webhook.id == id).cloned()
+    };
+    let Some(webhook) = webhook else { return };
+
+    let key = signing_key(app_handle);
+    let event = event.to_string();
+    tauri::async_runtime::spawn(async move {
+        send_webhook(&webhook, &event, &data, key.as_deref()).await;
+    });
+}
+
+// Command for events the frontend observes, such as geofences and battery level
+#[tauri::command]
+pub fn trigger_automation_event(event: String, data: Option<Value>, app_handle: AppHandle) {
+    trigger(&app_handle, &event, data.unwrap_or(Value::Null));
+}
+
+#[tauri::command]
+pub fn list_webhooks(state: State<'_, AutomationsState>) -> Result<Vec<Webhook>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.webhooks.clone())
+}
+
+// Command to create a webhook, or update it when the id already exists
+#[tauri::command]
+pub fn save_webhook(
+    webhook: Webhook,
+    app_handle: AppHandle,
+    state: State<'_, AutomationsState>,
+) -> Result<Webhook, String> {
+    reqwest::Url::parse(&webhook.url).map_err(|e| e.to_string())?;
+
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let webhook = store.upsert(webhook);
+    storage::save_json(&app_handle, AUTOMATIONS_FILE, &*store)?;
+    Ok(webhook)
+}
+
+#[tauri::command]
+pub fn delete_webhook(
+    id: u64,
+    app_handle: AppHandle,
+    state: State<'_, AutomationsState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.webhooks.retain(|webhook| webhook.id != id);
+    storage::save_json(&app_handle, AUTOMATIONS_FILE, &*store)
+}
+
+// Command to send a webhook once with sample data
+#[tauri::command]
+pub async fn test_webhook(
+    id: u64,
+    app_handle: AppHandle,
+    state: State<'_, AutomationsState>,
+) -> Result<WebhookResult, String> {
+    let webhook = {
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store
+            .webhooks
+            .iter()
+            .find(|webhook| webhook.id == id)
+            .cloned()
+            .ok_or("Webhook not found".to_string())?
+    };
+    let data = serde_json::json!({ "test": true });
+    let key = signing_key(&app_handle);
+    Ok(send_webhook(&webhook, &webhook.event, &data, key.as_deref()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(id: u64, event: &str) -> Webhook {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": "Lights",
+            "event": event,
+            "url": "http://homeassistant.local:8123/api/webhook/lights",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_filled_from_the_event() {
+        let data = serde_json::json!({ "minutes": 5, "timer": { "label": "Tea" } });
+        let template = "{{ event }}: {{data.timer.label}} after {{data.minutes}}m{{data.missing}}";
+        assert_eq!(render_template(template, "timer.done", &data), "timer.done: Tea after 5m");
+        assert_eq!(render_template("{{data}}", "e", &data), data.to_string());
+        assert!(render_template("at {{timestamp}}", "e", &data)[3..].parse::<u64>().is_ok());
+        // An unclosed placeholder is left as written
+        assert_eq!(render_template("a {{event", "e", &data), "a {{event");
+    }
+
+    #[test]
+    fn strings_cannot_break_out_of_a_json_body() {
+        let data = serde_json::json!({ "text": "hi\", \"admin\": true, \"x\": \"\nbye" });
+        let body = render_template(r#"{"message": "{{data.text}}"}"#, "matrix.message", &data);
+        let parsed: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(parsed["message"], data["text"]);
+        assert!(parsed.get("admin").is_none());
+    }
+
+    #[test]
+    fn defaults_and_matching() {
+        let mut store = AutomationStore::default();
+        let saved = store.upsert(webhook(0, EVENT_TIMER_DONE));
+        assert_eq!(saved.id, 1);
+        assert_eq!(saved.method, "POST");
+        assert!(saved.enabled);
+
+        let mut disabled = webhook(0, EVENT_TIMER_DONE);
+        disabled.enabled = false;
+        store.upsert(disabled);
+        store.upsert(webhook(0, "battery.low"));
+        assert_eq!(store.matching(EVENT_TIMER_DONE).len(), 1);
+        assert_eq!(store.matching("wakeword.detected").len(), 0);
+    }
+
+    #[test]
+    fn saving_an_existing_id_updates_it() {
+        let mut store = AutomationStore::default();
+        store.upsert(webhook(0, EVENT_TIMER_DONE));
+        let mut changed = webhook(1, "battery.low");
+        changed.name = "Charger".to_string();
+        assert_eq!(store.upsert(changed).id, 1);
+        assert_eq!(store.webhooks.len(), 1);
+        assert_eq!(store.webhooks[0].name, "Charger");
+
+        // Unknown ids are added with a fresh one
+        assert_eq!(store.upsert(webhook(40, EVENT_TIMER_DONE)).id, 2);
+    }
+}
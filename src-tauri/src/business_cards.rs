@@ -0,0 +1,200 @@
+// Business card capture. The card photo is read with the same OCR as shared
+// images; emails, phone numbers and the website are picked out locally and
+// the name, job title, company and address by the engine. The draft goes out
+// as contacts://card_review for the user to check, and nothing is saved until
+// the UI calls create_contact with the reviewed fields.
+
+use serde::{Serialize, Deserialize};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::contacts::NewContact;
+use crate::engine;
+use crate::settings::{self, SettingsState};
+use crate::share;
+use crate::structured;
+use crate::untrusted;
+
+// Email domains that say nothing about where someone works
+const PERSONAL_DOMAINS: &[&str] = &[
+    "gmail", "googlemail", "outlook", "hotmail", "live", "yahoo", "icloud", "me", "proton", "protonmail", "aol",
+];
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CardReview {
+    pub path: String,
+    // Everything read from the card, so the user can copy what was missed
+    pub text: String,
+    pub contact: NewContact,
+}
+
+// Fields only the engine can tell apart
+#[derive(Deserialize, Default, Debug)]
+struct CardFields {
+    name: Option<String>,
+    job_title: Option<String>,
+    organization: Option<String>,
+    address: Option<String>,
+}
+
+fn trim_token(token: &str) -> &str {
+    token.trim_matches(|c: char| matches!(c, ',' | ';' | '(' | ')' | '<' | '>' | '|' | '"' | '\''))
+}
+
+fn find_emails(text: &str) -> Vec<String> {
+    let mut emails: Vec<String> = Vec::new();
+    for token in text.split_whitespace() {
+        // "E:jane@acme.com" or "email:jane@acme.com"
+        let token = trim_token(token.rsplit(':').next().unwrap_or(token)).trim_end_matches('.');
+        let Some((user, domain)) = token.split_once('@') else {
+            continue;
+        };
+        if !user.is_empty() && domain.contains('.') && !emails.iter().any(|email| email.eq_ignore_ascii_case(token)) {
+            emails.push(token.to_lowercase());
+        }
+    }
+    emails
+}
+
+// Runs of digits and phone punctuation with a plausible number of digits
+fn find_phones(text: &str) -> Vec<String> {
+    let mut phones: Vec<String> = Vec::new();
+    for line in text.lines() {
+        for run in line.split(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '(' | ')' | '-' | '.' | ' ' | '/'))) {
+            let run = run.trim().trim_matches(|c: char| matches!(c, '-' | '.' | '/'));
+            let digits = run.chars().filter(char::is_ascii_digit).count();
+            if (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits) && !phones.iter().any(|phone| phone == run) {
+                phones.push(run.to_string());
+            }
+        }
+    }
+    phones
+}
+
+fn find_website(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .map(|token| trim_token(token).trim_end_matches('.'))
+        .find(|token| {
+            let lower = token.to_lowercase();
+            !lower.contains('@')
+                && (lower.starts_with("www.")
+                    || lower.starts_with("http://")
+                    || lower.starts_with("https://"))
+        })
+        .map(str::to_string)
+}
+
+// Without the engine: the first line that looks like a name, and the company
+// from a work email address
+fn guess_fields(text: &str, emails: &[String]) -> CardFields {
+    let name = text
+        .lines()
+        .map(str::trim)
+        .find(|line| {
+            let words = line.split_whitespace().count();
+            (2..=4).contains(&words)
+                && line.chars().all(|c| c.is_alphabetic() || matches!(c, ' ' | '.' | '-' | '\''))
+                && line.split_whitespace().all(|word| word.chars().next().is_some_and(char::is_uppercase))
+        })
+        .map(str::to_string);
+    let organization = emails
+        .iter()
+        .filter_map(|email| email.split_once('@'))
+        .filter_map(|(_, domain)| domain.split('.').next())
+        .find(|company| !PERSONAL_DOMAINS.contains(company))
+        .map(|company| {
+            let mut chars = company.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        });
+    CardFields {
+        name,
+        organization,
+        ..CardFields::default()
+    }
+}
+
+async fn extract_with_engine(app_handle: &AppHandle, text: &str) -> Result<CardFields, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = format!(
+        "This is the text of a business card.\n\n{}\n\n\
+         Reply with only a JSON object with \"name\" (the person's full name), \"job_title\", \
+         \"organization\" and \"address\" (a single line); use null for anything not on the card.",
+        untrusted::wrap("business card", text)
+    );
+    let response = engine::generate(app_handle, &prompt, &app_settings).await?;
+    let json = structured::extract_json(&response, '{', '}').ok_or("The card could not be understood".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+// Turn the card's text into contact fields
+pub async fn extract(app_handle: &AppHandle, text: &str) -> NewContact {
+    let emails = find_emails(text);
+    let guessed = guess_fields(text, &emails);
+    let fields = match extract_with_engine(app_handle, text).await {
+        Ok(fields) => CardFields {
+            name: fields.name.or(guessed.name),
+            organization: fields.organization.or(guessed.organization),
+            ..fields
+        },
+        Err(e) => {
+            tracing::debug!(error = %e, "business card fields guessed locally");
+            guessed
+        }
+    };
+    let present = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+    NewContact {
+        name: present(fields.name).unwrap_or_default(),
+        phones: find_phones(text),
+        emails,
+        organization: present(fields.organization),
+        job_title: present(fields.job_title),
+        website: find_website(text),
+        address: present(fields.address),
+    }
+}
+
+// Command to read a photo of a business card into a contact draft. The draft
+// is also emitted as contacts://card_review; save it with create_contact.
+#[tauri::command]
+pub async fn scan_business_card(path: String, app_handle: AppHandle) -> Result<CardReview, String> {
+    let text = share::extract_image_text(&app_handle, &path, share::image_mime_type(&path)).await?;
+    if text.trim().is_empty() {
+        return Err("No text was found on the card".to_string());
+    }
+    let contact = extract(&app_handle, &text).await;
+    let review = CardReview { path, text, contact };
+    app_handle
+        .emit("contacts://card_review", &review)
+        .map_err(|e| e.to_string())?;
+    Ok(review)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARD: &str = "Jane Doe\nHead of Research\nAcme Corp\nE:Jane@Acme.com <jane@acme.com>,\nTel: +1 (555) 123-4567\nSuite 100\nwww.acme.com.\n";
+
+    #[test]
+    fn emails_phones_and_websites_are_found() {
+        assert_eq!(find_emails(CARD), vec!["jane@acme.com"]);
+        assert_eq!(find_phones(CARD), vec!["+1 (555) 123-4567"]);
+        assert_eq!(find_website(CARD).as_deref(), Some("www.acme.com"));
+        assert_eq!(find_website("jane@www.acme.com"), None);
+    }
+
+    #[test]
+    fn names_and_companies_are_guessed_without_the_engine() {
+        let fields = guess_fields(CARD, &find_emails(CARD));
+        assert_eq!(fields.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(fields.organization.as_deref(), Some("Acme"));
+
+        let fields = guess_fields("call me\nJohn Smith", &["john@gmail.com".to_string()]);
+        assert_eq!(fields.name.as_deref(), Some("John Smith"));
+        assert_eq!(fields.organization, None);
+    }
+}
@@ -0,0 +1,436 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::dav::{self, DavCredentials};
+use crate::keystore::Keystore;
+use crate::storage;
+
+const SOURCES_FILE: &str = "calendar_sources.json";
+const EVENTS_FILE: &str = "calendar_events.json";
+const SYNC_INTERVAL: Duration = Duration::from_secs(30 * 60);
+// Window of events fetched from CalDAV servers
+const SYNC_PAST_DAYS: i64 = 1;
+const SYNC_FUTURE_DAYS: i64 = 30;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum CalendarSourceKind {
+    Ics { url: String },
+    // The password lives in the keystore under caldav:<id>
+    CalDav { url: String, username: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CalendarSource {
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: CalendarSourceKind,
+    #[serde(default)]
+    pub last_synced: Option<u64>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub source_id: u64,
+    pub summary: String,
+    pub location: Option<String>,
+    pub start: i64,
+    pub end: i64,
+    pub all_day: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct CalendarStore {
+    next_id: u64,
+    sources: Vec<CalendarSource>,
+    #[serde(skip)]
+    events: Vec<CalendarEvent>,
+}
+
+pub type CalendarState = Mutex<CalendarStore>;
+
+pub fn load(app_handle: &AppHandle) -> CalendarStore {
+    let mut store: CalendarStore = storage::load_json(app_handle, SOURCES_FILE);
+    store.events = storage::load_json(app_handle, EVENTS_FILE);
+    store
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn format_utc(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// Parse DATE or DATE-TIME values. Floating and TZID times are treated as UTC.
+fn parse_ics_time(value: &str) -> Option<(i64, bool)> {
+    let digits = |range: std::ops::Range<usize>| value.get(range)?.parse::<i64>().ok();
+    let days = days_from_civil(digits(0..4)?, digits(4..6)?, digits(6..8)?);
+    if value.len() == 8 {
+        return Some((days * 86400, true));
+    }
+    let secs = digits(9..11)? * 3600 + digits(11..13)? * 60 + digits(13..15)?;
+    Some((days * 86400 + secs, false))
+}
+
+fn unescape_ics(text: &str) -> String {
+    text.replace("\\n", "\n")
+        .replace("\\N", "\n")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+// Parse VEVENTs from an iCalendar document.
+// Recurring events only contribute their first occurrence.
+pub fn parse_ics(ics: &str, source_id: u64) -> Vec<CalendarEvent> {
+    // Undo line folding: continuation lines start with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for raw in ics.lines() {
+        match raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')) {
+            Some(rest) => {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(rest);
+                }
+            }
+            None => lines.push(raw.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<CalendarEvent> = None;
+    let mut has_end = false;
+
+    for line in lines {
+        if line == "BEGIN:VEVENT" {
+            current = Some(CalendarEvent {
+                uid: String::new(),
+                source_id,
+                summary: String::new(),
+                location: None,
+                start: 0,
+                end: 0,
+                all_day: false,
+            });
+            has_end = false;
+            continue;
+        }
+        if line == "END:VEVENT" {
+            if let Some(mut event) = current.take() {
+                if !has_end {
+                    event.end = event.start + if event.all_day { 86400 } else { 3600 };
+                }
+                if event.start != 0 {
+                    events.push(event);
+                }
+            }
+            continue;
+        }
+
+        let Some(event) = current.as_mut() else { continue };
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let name = key.split(';').next().unwrap_or_default();
+        match name {
+            "UID" => event.uid = value.to_string(),
+            "SUMMARY" => event.summary = unescape_ics(value),
+            "LOCATION" => event.location = Some(unescape_ics(value)),
+            "DTSTART" => {
+                if let Some((start, all_day)) = parse_ics_time(value) {
+                    event.start = start;
+                    event.all_day = all_day;
+                }
+            }
+            "DTEND" => {
+                if let Some((end, _)) = parse_ics_time(value) {
+                    event.end = end;
+                    has_end = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+async fn fetch_ics(url: &str) -> Result<String, String> {
+    // webcal:// is just http(s) with a calendar hint
+    let url = url.replacen("webcal://", "https://", 1);
+    let response = reqwest::get(&url).await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Calendar fetch failed: {}", response.status()));
+    }
+    response.text().await.map_err(|e| e.to_string())
+}
+
+async fn fetch_caldav(url: &str, credentials: &DavCredentials<'_>) -> Result<Vec<String>, String> {
+    let now = now_secs();
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <c:calendar-query xmlns:d=\"DAV:\" xmlns:c=\"urn:ietf:params:xml:ns:caldav\">\
+         <d:prop><c:calendar-data/></d:prop>\
+         <c:filter><c:comp-filter name=\"VCALENDAR\"><c:comp-filter name=\"VEVENT\">\
+         <c:time-range start=\"{}\" end=\"{}\"/>\
+         </c:comp-filter></c:comp-filter></c:filter>\
+         </c:calendar-query>",
+        format_utc(now - SYNC_PAST_DAYS * 86400),
+        format_utc(now + SYNC_FUTURE_DAYS * 86400)
+    );
+
+    let xml = dav::request("REPORT", url, credentials, "1", body).await?;
+    Ok(dav::extract_elements(&xml, "calendar-data"))
+}
+
+async fn fetch_source(app_handle: &AppHandle, source: &CalendarSource) -> Result<Vec<CalendarEvent>, String> {
+    match &source.kind {
+        CalendarSourceKind::Ics { url } => Ok(parse_ics(&fetch_ics(url).await?, source.id)),
+        CalendarSourceKind::CalDav { url, username } => {
+            let password = app_handle
+                .state::<Keystore>()
+                .get(&format!("caldav:{}", source.id))?
+                .unwrap_or_default();
+            let credentials = DavCredentials { username, password: &password };
+            let documents = fetch_caldav(url, &credentials).await?;
+            Ok(documents
+                .iter()
+                .flat_map(|ics| parse_ics(ics, source.id))
+                .collect())
+        }
+    }
+}
+
+fn save_store(app_handle: &AppHandle, store: &CalendarStore) -> Result<(), String> {
+    storage::save_json(app_handle, SOURCES_FILE, store)?;
+    storage::save_json(app_handle, EVENTS_FILE, &store.events)
+}
+
+// Refresh every source; a failing source keeps its previously synced events
+pub async fn sync_all(app_handle: &AppHandle) -> Result<usize, String> {
+    let sources = {
+        let state = app_handle.state::<CalendarState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store.sources.clone()
+    };
+
+    let mut results = Vec::new();
+    for source in &sources {
+        results.push((source.id, fetch_source(app_handle, source).await));
+    }
+
+    let count = {
+        let state = app_handle.state::<CalendarState>();
+        let mut guard = state.lock().map_err(|e| e.to_string())?;
+        let store = &mut *guard;
+        for (source_id, result) in results {
+            let Some(source) = store.sources.iter_mut().find(|s| s.id == source_id) else {
+                continue;
+            };
+            match result {
+                Ok(events) => {
+                    source.last_synced = Some(now_secs() as u64);
+                    source.last_error = None;
+                    store.events.retain(|event| event.source_id != source_id);
+                    store.events.extend(events);
+                }
+                Err(e) => source.last_error = Some(e),
+            }
+        }
+        store.events.sort_by_key(|event| event.start);
+        save_store(app_handle, store)?;
+        store.events.len()
+    };
+
+    let _ = app_handle.emit("calendar://synced", count);
+    Ok(count)
+}
+
+pub fn spawn_sync_loop(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let _ = sync_all(&app_handle).await;
+            tokio::time::sleep(SYNC_INTERVAL).await;
+        }
+    });
+}
+
+// Events overlapping the next `days` days, for the agenda widget and briefings
+pub fn upcoming_events(app_handle: &AppHandle, days: i64) -> Vec<CalendarEvent> {
+    let state = app_handle.state::<CalendarState>();
+    let Ok(store) = state.lock() else {
+        return Vec::new();
+    };
+    let now = now_secs();
+    let until = now + days * 86400;
+    store
+        .events
+        .iter()
+        .filter(|event| event.end > now && event.start < until)
+        .cloned()
+        .collect()
+}
+
+// Command to subscribe to an ICS URL or a CalDAV calendar
+#[tauri::command]
+pub async fn add_calendar_source(
+    source: CalendarSource,
+    password: Option<String>,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, CalendarState>,
+) -> Result<CalendarSource, String> {
+    let source = {
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.next_id += 1;
+        let source = CalendarSource {
+            id: store.next_id,
+            last_synced: None,
+            last_error: None,
+            ..source
+        };
+        if let (CalendarSourceKind::CalDav { .. }, Some(password)) = (&source.kind, password) {
+            keystore.set(&format!("caldav:{}", source.id), &password)?;
+        }
+        store.sources.push(source.clone());
+        save_store(&app_handle, &store)?;
+        source
+    };
+
+    sync_all(&app_handle).await?;
+    Ok(source)
+}
+
+#[tauri::command]
+pub fn remove_calendar_source(
+    id: u64,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, CalendarState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.sources.retain(|source| source.id != id);
+    store.events.retain(|event| event.source_id != id);
+    keystore.delete(&format!("caldav:{}", id))?;
+    save_store(&app_handle, &store)
+}
+
+#[tauri::command]
+pub fn list_calendar_sources(state: State<'_, CalendarState>) -> Result<Vec<CalendarSource>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.sources.clone())
+}
+
+#[tauri::command]
+pub async fn sync_calendars(app_handle: AppHandle) -> Result<usize, String> {
+    sync_all(&app_handle).await
+}
+
+#[tauri::command]
+pub fn get_agenda(days: Option<i64>, app_handle: AppHandle) -> Vec<CalendarEvent> {
+    upcoming_events(&app_handle, days.unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        for days in [-719468, -1, 0, 59, 11016, 19782, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(format_utc(1_709_210_096), "20240229T123456Z");
+        assert_eq!(format_utc(-1), "19691231T235959Z");
+    }
+
+    #[test]
+    fn ics_times_are_dates_or_date_times() {
+        assert_eq!(parse_ics_time("20240229"), Some((1_709_164_800, true)));
+        assert_eq!(parse_ics_time("20240229T123456Z"), Some((1_709_210_096, false)));
+        assert_eq!(parse_ics_time("2024-02-29"), None);
+        assert_eq!(parse_ics_time("20240229T12"), None);
+    }
+
+    #[test]
+    fn events_are_parsed_from_ics() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:standup@example.com\r\n\
+                   SUMMARY:Stand-up\\, with the\r\n  whole team\r\n\
+                   LOCATION:Room 4\\;B\r\n\
+                   DTSTART;TZID=Europe/Lisbon:20240229T090000\r\n\
+                   DTEND;TZID=Europe/Lisbon:20240229T091500\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:holiday\r\n\
+                   SUMMARY:Holiday\r\n\
+                   DTSTART;VALUE=DATE:20240301\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   UID:undated\r\n\
+                   SUMMARY:No start\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let events = parse_ics(ics, 7);
+        assert_eq!(events.len(), 2);
+
+        let standup = &events[0];
+        assert_eq!(standup.uid, "standup@example.com");
+        assert_eq!(standup.summary, "Stand-up, with the whole team");
+        assert_eq!(standup.location.as_deref(), Some("Room 4;B"));
+        assert_eq!(standup.end - standup.start, 15 * 60);
+        assert_eq!(standup.source_id, 7);
+        assert!(!standup.all_day);
+
+        // All-day events without an end last the day
+        let holiday = &events[1];
+        assert!(holiday.all_day);
+        assert_eq!(holiday.end - holiday.start, 86400);
+    }
+}
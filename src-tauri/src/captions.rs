@@ -0,0 +1,306 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::speech::{self, TranscriptSegment, TranscriptionResult};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CaptionConfig {
+    // Never send caption audio to a cloud provider
+    pub local_only: bool,
+    // Length of each transcribed audio window
+    pub window_ms: u32,
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        CaptionConfig {
+            local_only: false,
+            window_ms: 3000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CaptionEvent {
+    pub seq: u64,
+    pub text: String,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct CaptionStatus {
+    pub active: bool,
+    pub config: CaptionConfig,
+    pub captions_emitted: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    Srt,
+    Vtt,
+    Json,
+}
+
+#[derive(Default)]
+pub struct CaptionState {
+    active: bool,
+    config: CaptionConfig,
+    buffer: Vec<i16>,
+    seq: u64,
+}
+
+pub type CaptionService = Mutex<CaptionState>;
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// HH:MM:SS plus milliseconds; SRT separates them with a comma, WebVTT with a dot
+fn cue_time(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+fn timed_segments(result: &TranscriptionResult) -> Result<&[TranscriptSegment], String> {
+    match result.segments.as_deref() {
+        Some(segments) if !segments.is_empty() => Ok(segments),
+        _ => Err("The transcript has no timestamps to export as captions".to_string()),
+    }
+}
+
+// Speakers are numbered from 0; captions count from 1
+fn speaker_name(speaker: u32) -> String {
+    format!("Speaker {}", speaker + 1)
+}
+
+fn to_srt(segments: &[TranscriptSegment]) -> String {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            let text = match segment.speaker {
+                Some(speaker) => format!("{}: {}", speaker_name(speaker), segment.text.trim()),
+                None => segment.text.trim().to_string(),
+            };
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                cue_time(segment.start, ','),
+                cue_time(segment.end, ','),
+                text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn to_vtt(segments: &[TranscriptSegment]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for segment in segments {
+        // WebVTT voice spans let players style each speaker
+        let text = match segment.speaker {
+            Some(speaker) => format!("<v {}>{}", speaker_name(speaker), segment.text.trim()),
+            None => segment.text.trim().to_string(),
+        };
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{}\n",
+            cue_time(segment.start, '.'),
+            cue_time(segment.end, '.'),
+            text
+        ));
+    }
+    vtt
+}
+
+fn render_transcript(result: &TranscriptionResult, format: TranscriptFormat) -> Result<String, String> {
+    match format {
+        TranscriptFormat::Srt => Ok(to_srt(timed_segments(result)?)),
+        TranscriptFormat::Vtt => Ok(to_vtt(timed_segments(result)?)),
+        TranscriptFormat::Json => serde_json::to_string_pretty(result).map_err(|e| e.to_string()),
+    }
+}
+
+// Start captioning the microphone; the speech module's capture feeds `feed`
+pub fn start(app_handle: &AppHandle, state: &CaptionService, config: CaptionConfig) -> Result<(), String> {
+    {
+        let mut captions = state.lock().map_err(|e| e.to_string())?;
+        captions.active = true;
+        captions.config = config;
+        captions.buffer.clear();
+    }
+    if let Err(e) = speech::start_caption_capture(app_handle) {
+        stop(app_handle, state)?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+pub fn stop(app_handle: &AppHandle, state: &CaptionService) -> Result<(), String> {
+    speech::stop_caption_capture(app_handle);
+    let mut captions = state.lock().map_err(|e| e.to_string())?;
+    captions.active = false;
+    captions.buffer.clear();
+    Ok(())
+}
+
+// Buffer captured audio; once a full window is buffered it is transcribed and
+// emitted as `caption://text`
+pub(crate) async fn feed(app_handle: &AppHandle, samples: &[i16], sample_rate: u32) -> Result<(), String> {
+    let state = app_handle.state::<CaptionService>();
+    let (window, local_only) = {
+        let mut captions = state.lock().map_err(|e| e.to_string())?;
+        if !captions.active {
+            return Ok(());
+        }
+        captions.buffer.extend_from_slice(samples);
+
+        let window_len = (sample_rate as u64 * captions.config.window_ms as u64 / 1000) as usize;
+        if captions.buffer.len() < window_len {
+            return Ok(());
+        }
+        (std::mem::take(&mut captions.buffer), captions.config.local_only)
+    };
+
+    let result = speech::transcribe_samples(app_handle, window, sample_rate, local_only).await?;
+    if result.text.is_empty() {
+        return Ok(());
+    }
+
+    let seq = {
+        let mut captions = state.lock().map_err(|e| e.to_string())?;
+        captions.seq += 1;
+        captions.seq
+    };
+
+    app_handle
+        .emit("caption://text", CaptionEvent {
+            seq,
+            text: result.text,
+            timestamp: now_millis(),
+        })
+        .map_err(|e| e.to_string())
+}
+
+// Command to start the live caption service
+#[tauri::command]
+pub fn start_captions(
+    config: Option<CaptionConfig>,
+    app_handle: AppHandle,
+    state: State<'_, CaptionService>,
+) -> Result<(), String> {
+    start(&app_handle, &state, config.unwrap_or_default())
+}
+
+// Command to stop the live caption service
+#[tauri::command]
+pub fn stop_captions(app_handle: AppHandle, state: State<'_, CaptionService>) -> Result<(), String> {
+    stop(&app_handle, &state)
+}
+
+// Command to save a transcription as a caption file or JSON at a path the user picked
+#[tauri::command]
+pub fn export_transcript(result: TranscriptionResult, format: TranscriptFormat, path: String) -> Result<(), String> {
+    let contents = render_transcript(&result, format)?;
+    std::fs::write(&path, contents).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_caption_status(state: State<'_, CaptionService>) -> Result<CaptionStatus, String> {
+    let captions = state.lock().map_err(|e| e.to_string())?;
+    Ok(CaptionStatus {
+        active: captions.active,
+        config: captions.config.clone(),
+        captions_emitted: captions.seq,
+    })
+}
+
+// Command to feed audio captured elsewhere, such as a call, into the caption service
+#[tauri::command]
+pub async fn push_caption_audio(samples: Vec<i16>, sample_rate: u32, app_handle: AppHandle) -> Result<(), String> {
+    feed(&app_handle, &samples, sample_rate).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start,
+            end,
+            text: text.to_string(),
+            words: Vec::new(),
+            speaker: None,
+        }
+    }
+
+    fn transcript(segments: Option<Vec<TranscriptSegment>>) -> TranscriptionResult {
+        TranscriptionResult {
+            text: "hello there".to_string(),
+            language: Some("en".to_string()),
+            segments,
+        }
+    }
+
+    #[test]
+    fn cue_times_use_the_format_separator() {
+        assert_eq!(cue_time(0.0, ','), "00:00:00,000");
+        assert_eq!(cue_time(3723.4567, ','), "01:02:03,457");
+        assert_eq!(cue_time(61.5, '.'), "00:01:01.500");
+        assert_eq!(cue_time(-2.0, '.'), "00:00:00.000");
+    }
+
+    #[test]
+    fn renders_srt_cues() {
+        let result = transcript(Some(vec![segment(0.0, 1.5, " hello "), segment(1.5, 3.0, "there")]));
+        let srt = render_transcript(&result, TranscriptFormat::Srt).unwrap();
+        assert_eq!(srt, "1\n00:00:00,000 --> 00:00:01,500\nhello\n\n2\n00:00:01,500 --> 00:00:03,000\nthere\n");
+    }
+
+    #[test]
+    fn renders_vtt_cues() {
+        let result = transcript(Some(vec![segment(0.0, 1.5, "hello")]));
+        let vtt = render_transcript(&result, TranscriptFormat::Vtt).unwrap();
+        assert_eq!(vtt, "WEBVTT\n\n00:00:00.000 --> 00:00:01.500\nhello\n");
+    }
+
+    #[test]
+    fn labels_speakers_from_one() {
+        let mut first = segment(0.0, 1.0, "hi");
+        first.speaker = Some(0);
+        let mut second = segment(1.0, 2.0, "hello");
+        second.speaker = Some(1);
+        let result = transcript(Some(vec![first, second, segment(2.0, 3.0, "bye")]));
+        let srt = render_transcript(&result, TranscriptFormat::Srt).unwrap();
+        assert!(srt.contains("\nSpeaker 1: hi\n"));
+        assert!(srt.contains("\nSpeaker 2: hello\n"));
+        assert!(srt.ends_with("\nbye\n"));
+        let vtt = render_transcript(&result, TranscriptFormat::Vtt).unwrap();
+        assert!(vtt.contains("\n<v Speaker 1>hi\n"));
+        assert!(vtt.contains("\n<v Speaker 2>hello\n"));
+        assert!(vtt.ends_with("\nbye\n"));
+    }
+
+    #[test]
+    fn captions_need_timestamps_but_json_does_not() {
+        assert!(render_transcript(&transcript(None), TranscriptFormat::Srt).is_err());
+        assert!(render_transcript(&transcript(Some(Vec::new())), TranscriptFormat::Vtt).is_err());
+        let json = render_transcript(&transcript(None), TranscriptFormat::Json).unwrap();
+        let parsed: TranscriptionResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.text, "hello there");
+        assert_eq!(parsed.language.as_deref(), Some("en"));
+    }
+}
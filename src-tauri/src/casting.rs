@@ -0,0 +1,334 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::State;
+use tokio::net::UdpSocket;
+use url::Url;
+
+use crate::mdns;
+
+const CHROMECAST_SERVICE: &str = "_googlecast._tcp.local.";
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const MEDIA_RENDERER: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CastKind {
+    Chromecast,
+    Dlna,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CastDevice {
+    pub id: String,
+    pub name: String,
+    pub kind: CastKind,
+    pub host: String,
+    pub port: u16,
+    // AVTransport control endpoint for DLNA renderers
+    pub control_url: Option<String>,
+}
+
+pub type CastState = Mutex<Vec<CastDevice>>;
+
+fn discover_chromecasts() -> Vec<CastDevice> {
+    let services = mdns::browse(CHROMECAST_SERVICE, DISCOVERY_TIMEOUT).unwrap_or_default();
+    services
+        .into_iter()
+        .filter_map(|service| {
+            let host = service.addresses.iter().find(|addr| addr.is_ipv4())?.to_string();
+            let name = service
+                .properties
+                .get("fn")
+                .cloned()
+                .unwrap_or_else(|| service.hostname.trim_end_matches('.').to_string());
+            Some(CastDevice {
+                id: service.fullname,
+                name,
+                kind: CastKind::Chromecast,
+                host,
+                port: service.port,
+                control_url: None,
+            })
+        })
+        .collect()
+}
+
+// Text between the first <tag> and </tag> after `from`
+fn xml_value(xml: &str, tag: &str, from: usize) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml[from..].find(&open)? + from + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].trim().to_string())
+}
+
+async fn describe_renderer(location: &str) -> Option<CastDevice> {
+    let description = reqwest::get(location).await.ok()?.text().await.ok()?;
+    parse_renderer(&description, location)
+}
+
+// A renderer from its UPnP device description; None unless it has AVTransport
+fn parse_renderer(description: &str, location: &str) -> Option<CastDevice> {
+    let base = Url::parse(location).ok()?;
+
+    let name = xml_value(description, "friendlyName", 0).unwrap_or_else(|| "DLNA renderer".to_string());
+    let service_at = description.find(AV_TRANSPORT)?;
+    let control_path = xml_value(description, "controlURL", service_at)?;
+    let control_url = base.join(&control_path).ok()?;
+
+    Some(CastDevice {
+        id: xml_value(description, "UDN", 0).unwrap_or_else(|| location.to_string()),
+        name,
+        kind: CastKind::Dlna,
+        host: base.host_str()?.to_string(),
+        port: base.port_or_known_default()?,
+        control_url: Some(control_url.to_string()),
+    })
+}
+
+// The LOCATION header of an SSDP response, where the device description lives
+fn ssdp_location(response: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("location").then(|| value.trim().to_string())
+    })
+}
+
+// Find UPnP media renderers with an SSDP M-SEARCH
+async fn discover_dlna_renderers() -> Result<Vec<CastDevice>, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(|e| e.to_string())?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+        SSDP_ADDR, MEDIA_RENDERER
+    );
+    socket
+        .send_to(search.as_bytes(), SSDP_ADDR)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut locations: Vec<String> = Vec::new();
+    let mut buf = [0u8; 2048];
+    let deadline = tokio::time::Instant::now() + DISCOVERY_TIMEOUT;
+    while let Ok(Ok((len, _))) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        if let Some(location) = ssdp_location(&String::from_utf8_lossy(&buf[..len])) {
+            if !locations.contains(&location) {
+                locations.push(location);
+            }
+        }
+    }
+
+    let mut devices = Vec::new();
+    for location in locations {
+        if let Some(device) = describe_renderer(&location).await {
+            devices.push(device);
+        }
+    }
+    Ok(devices)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+async fn av_transport_action(control_url: &str, action: &str, arguments: &str) -> Result<(), String> {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{action} xmlns:u=\"{service}\"><InstanceID>0</InstanceID>{arguments}</u:{action}></s:Body>\
+         </s:Envelope>",
+        action = action,
+        service = AV_TRANSPORT,
+        arguments = arguments
+    );
+
+    let response = reqwest::Client::new()
+        .post(control_url)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header("SOAPAction", format!("\"{}#{}\"", AV_TRANSPORT, action))
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("{} failed: {}", action, response.status()));
+    }
+    Ok(())
+}
+
+async fn cast_dlna(device: &CastDevice, media_url: &str) -> Result<(), String> {
+    let control_url = device.control_url.as_deref().ok_or("Renderer has no control URL".to_string())?;
+    av_transport_action(
+        control_url,
+        "SetAVTransportURI",
+        &format!(
+            "<CurrentURI>{}</CurrentURI><CurrentURIMetaData></CurrentURIMetaData>",
+            escape_xml(media_url)
+        ),
+    )
+    .await?;
+    av_transport_action(control_url, "Play", "<Speed>1</Speed>").await
+}
+
+// The cast protocol client is blocking, so this runs on a blocking thread
+fn cast_chromecast(device: &CastDevice, media_url: &str, content_type: &str) -> Result<(), String> {
+    use rust_cast::channels::media::{Media, StreamType};
+    use rust_cast::channels::receiver::CastDeviceApp;
+
+    let cast = rust_cast::CastDevice::connect_without_host_verification(device.host.as_str(), device.port)
+        .map_err(|e| e.to_string())?;
+    cast.connection.connect("receiver-0").map_err(|e| e.to_string())?;
+    let app = cast
+        .receiver
+        .launch_app(&CastDeviceApp::DefaultMediaReceiver)
+        .map_err(|e| e.to_string())?;
+    cast.connection
+        .connect(app.transport_id.as_str())
+        .map_err(|e| e.to_string())?;
+    cast.media
+        .load(
+            app.transport_id.as_str(),
+            app.session_id.as_str(),
+            &Media {
+                content_id: media_url.to_string(),
+                content_type: content_type.to_string(),
+                stream_type: StreamType::Buffered,
+                duration: None,
+                metadata: None,
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn stop_chromecast(device: &CastDevice) -> Result<(), String> {
+    let cast = rust_cast::CastDevice::connect_without_host_verification(device.host.as_str(), device.port)
+        .map_err(|e| e.to_string())?;
+    cast.connection.connect("receiver-0").map_err(|e| e.to_string())?;
+    let status = cast.receiver.get_status().map_err(|e| e.to_string())?;
+    for app in status.applications {
+        cast.receiver
+            .stop_app(app.session_id.as_str())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn find_device(state: &CastState, device_id: &str) -> Result<CastDevice, String> {
+    let devices = state.lock().map_err(|e| e.to_string())?;
+    devices
+        .iter()
+        .find(|device| device.id == device_id)
+        .cloned()
+        .ok_or("Cast device not found; refresh the device list".to_string())
+}
+
+// Command to discover Chromecast and DLNA speakers on the LAN
+#[tauri::command]
+pub async fn list_cast_devices(state: State<'_, CastState>) -> Result<Vec<CastDevice>, String> {
+    let chromecasts = tauri::async_runtime::spawn_blocking(discover_chromecasts);
+    let mut devices = discover_dlna_renderers().await.unwrap_or_default();
+    devices.extend(chromecasts.await.map_err(|e| e.to_string())?);
+
+    let mut known = state.lock().map_err(|e| e.to_string())?;
+    *known = devices.clone();
+    Ok(devices)
+}
+
+// Command to play an audio URL (radio stream, briefing audio) on a cast device
+#[tauri::command]
+pub async fn cast_to(
+    device_id: String,
+    media_url: String,
+    content_type: Option<String>,
+    state: State<'_, CastState>,
+) -> Result<(), String> {
+    let device = find_device(&state, &device_id)?;
+    match device.kind {
+        CastKind::Dlna => cast_dlna(&device, &media_url).await,
+        CastKind::Chromecast => {
+            let content_type = content_type.unwrap_or_else(|| "audio/mpeg".to_string());
+            tauri::async_runtime::spawn_blocking(move || {
+                cast_chromecast(&device, &media_url, &content_type)
+            })
+            .await
+            .map_err(|e| e.to_string())?
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn stop_casting(device_id: String, state: State<'_, CastState>) -> Result<(), String> {
+    let device = find_device(&state, &device_id)?;
+    match device.kind {
+        CastKind::Dlna => {
+            let control_url = device.control_url.as_deref().ok_or("Renderer has no control URL".to_string())?;
+            av_transport_action(control_url, "Stop", "").await
+        }
+        CastKind::Chromecast => tauri::async_runtime::spawn_blocking(move || stop_chromecast(&device))
+            .await
+            .map_err(|e| e.to_string())?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = "<?xml version=\"1.0\"?>\
+        <root><device><friendlyName> Living Room </friendlyName><UDN>uuid:1234</UDN>\
+        <serviceList>\
+        <service><serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType>\
+        <controlURL>/rc/control</controlURL></service>\
+        <service><serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType>\
+        <controlURL>/avt/control</controlURL></service>\
+        </serviceList></device></root>";
+
+    #[test]
+    fn xml_values_are_found_after_an_offset() {
+        assert_eq!(xml_value(DESCRIPTION, "friendlyName", 0).as_deref(), Some("Living Room"));
+        assert_eq!(xml_value(DESCRIPTION, "controlURL", 0).as_deref(), Some("/rc/control"));
+        let at = DESCRIPTION.find(AV_TRANSPORT).unwrap();
+        assert_eq!(xml_value(DESCRIPTION, "controlURL", at).as_deref(), Some("/avt/control"));
+        assert_eq!(xml_value(DESCRIPTION, "modelName", 0), None);
+        assert_eq!(xml_value("<open>no close", "open", 0), None);
+    }
+
+    #[test]
+    fn renderers_use_the_av_transport_control_url() {
+        let device = parse_renderer(DESCRIPTION, "http://192.168.1.30:49152/description.xml").unwrap();
+        assert_eq!(device.id, "uuid:1234");
+        assert_eq!(device.name, "Living Room");
+        assert_eq!(device.kind, CastKind::Dlna);
+        assert_eq!(device.host, "192.168.1.30");
+        assert_eq!(device.port, 49152);
+        assert_eq!(device.control_url.as_deref(), Some("http://192.168.1.30:49152/avt/control"));
+
+        let without = DESCRIPTION.replace(AV_TRANSPORT, "urn:other");
+        assert!(parse_renderer(&without, "http://192.168.1.30:49152/description.xml").is_none());
+        assert!(parse_renderer(DESCRIPTION, "not a url").is_none());
+    }
+
+    #[test]
+    fn ssdp_location_header_is_case_insensitive() {
+        let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\n\
+            Location: http://192.168.1.30:49152/d.xml\r\n\r\n";
+        assert_eq!(ssdp_location(response).as_deref(), Some("http://192.168.1.30:49152/d.xml"));
+        assert_eq!(ssdp_location("HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\n\r\n"), None);
+    }
+
+    #[test]
+    fn media_urls_are_escaped() {
+        assert_eq!(
+            escape_xml("http://radio/stream?a=1&b=\"<x>\""),
+            "http://radio/stream?a=1&amp;b=&quot;&lt;x&gt;&quot;"
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use futures_util::StreamExt;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, EventId, Listener, Manager, State};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{tcp::OwnedWriteHalf, TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio_util::codec::{FramedRead, LinesCodec};
+
+use crate::captions::CaptionEvent;
+use crate::engine;
+use crate::rest_api::constant_time_eq;
+use crate::settings::{self, SettingsState};
+use crate::widgets;
+
+const DEFAULT_PORT: u16 = 47800;
+// Longer lines drop the connection
+const MAX_LINE_BYTES: usize = 64 * 1024;
+// Wrong codes a peer may send before it is locked out for a while
+const MAX_FAILED_PAIRINGS: u32 = 5;
+const PAIRING_LOCKOUT: Duration = Duration::from_secs(5 * 60);
+// Wrong codes from anyone before the code is replaced with a new one
+const ROTATE_AFTER_FAILURES: u32 = 3;
+// How long a new connection has to send its hello, and how many may be open
+// at once, paired or not; further connections are closed straight away
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_CONNECTIONS: usize = 8;
+
+// Messages sent by the watch, one JSON object per line
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WatchMessage {
+    Hello { code: String, device_name: Option<String> },
+    StartVoice,
+    StopVoice,
+    Ask { text: String },
+    Briefing,
+}
+
+// Messages sent to the watch
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PhoneMessage {
+    Welcome,
+    Error { message: String },
+    Transcript { text: String },
+    Speak { text: String },
+    Briefing { snippets: Vec<String> },
+}
+
+#[derive(Serialize, Clone)]
+pub struct CompanionStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    pub pairing_code: Option<String>,
+    pub connected_clients: usize,
+}
+
+#[derive(Default)]
+struct PeerFailures {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, PartialEq)]
+enum PairingOutcome {
+    Paired,
+    // The code was wrong; after enough wrong codes it is replaced
+    Rejected { new_code: Option<String> },
+    LockedOut,
+}
+
+// The code a watch has to present, and the wrong guesses made so far
+struct Pairing {
+    code: String,
+    failures: u32,
+    peers: HashMap<IpAddr, PeerFailures>,
+}
+
+impl Pairing {
+    fn new() -> Self {
+        Pairing {
+            code: new_pairing_code(),
+            failures: 0,
+            peers: HashMap::new(),
+        }
+    }
+
+    fn attempt(&mut self, peer: IpAddr, code: &str, now: Instant) -> PairingOutcome {
+        self.peers
+            .retain(|_, failures| failures.count > 0 || failures.locked_until.is_some_and(|until| until > now));
+        let failures = self.peers.entry(peer).or_default();
+        if failures.locked_until.is_some_and(|until| until > now) {
+            return PairingOutcome::LockedOut;
+        }
+        if constant_time_eq(code.as_bytes(), self.code.as_bytes()) {
+            self.peers.remove(&peer);
+            return PairingOutcome::Paired;
+        }
+
+        failures.count += 1;
+        if failures.count >= MAX_FAILED_PAIRINGS {
+            failures.count = 0;
+            failures.locked_until = Some(now + PAIRING_LOCKOUT);
+        }
+        self.failures += 1;
+        let mut new_code = None;
+        if self.failures >= ROTATE_AFTER_FAILURES {
+            self.failures = 0;
+            self.code = new_pairing_code();
+            new_code = Some(self.code.clone());
+        }
+        PairingOutcome::Rejected { new_code }
+    }
+}
+
+#[derive(Default)]
+pub struct CompanionServer {
+    task: Option<JoinHandle<()>>,
+    listener: Option<EventId>,
+    port: Option<u16>,
+    pairing: Option<Arc<Mutex<Pairing>>>,
+    clients: Arc<AtomicUsize>,
+}
+
+pub type CompanionState = Mutex<CompanionServer>;
+
+fn new_pairing_code() -> String {
+    format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+}
+
+async fn send(writer: &mut OwnedWriteHalf, message: &PhoneMessage) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).unwrap_or_default();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+async fn handle_message(app_handle: &AppHandle, message: WatchMessage) -> Option<PhoneMessage> {
+    match message {
+        WatchMessage::Hello { .. } => Some(PhoneMessage::Welcome),
+        // The UI owns the microphone session; transcripts come back through caption events
+        WatchMessage::StartVoice => {
+            let _ = app_handle.emit("companion://start-voice", ());
+            None
+        }
+        WatchMessage::StopVoice => {
+            let _ = app_handle.emit("companion://stop-voice", ());
+            None
+        }
+        WatchMessage::Ask { text } => {
+            let app_settings = settings::current(&app_handle.state::<SettingsState>());
+            Some(match engine::respond(app_handle, &text, &app_settings).await {
+                Ok(text) => PhoneMessage::Speak { text },
+                Err(message) => PhoneMessage::Error { message },
+            })
+        }
+        WatchMessage::Briefing => Some(PhoneMessage::Briefing {
+            snippets: widgets::briefing_snippets(app_handle).await,
+        }),
+    }
+}
+
+async fn handle_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    app_handle: AppHandle,
+    pairing: Arc<Mutex<Pairing>>,
+    outbound: broadcast::Sender<PhoneMessage>,
+    clients: Arc<AtomicUsize>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LINE_BYTES));
+
+    // The first message has to be a hello carrying the pairing code
+    let hello = match tokio::time::timeout(HELLO_TIMEOUT, lines.next()).await {
+        Ok(Some(Ok(line))) => match serde_json::from_str::<WatchMessage>(&line) {
+            Ok(WatchMessage::Hello { code, device_name }) => Some((code, device_name)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let outcome = match &hello {
+        Some((code, _)) => match pairing.lock() {
+            Ok(mut pairing) => pairing.attempt(peer.ip(), code, Instant::now()),
+            Err(_) => return,
+        },
+        None => PairingOutcome::Rejected { new_code: None },
+    };
+    let message = match outcome {
+        PairingOutcome::Paired => None,
+        PairingOutcome::Rejected { new_code } => {
+            if let Some(code) = new_code {
+                let _ = app_handle.emit("companion://pairing-code", code);
+            }
+            Some("Invalid pairing code")
+        }
+        PairingOutcome::LockedOut => Some("Too many wrong pairing codes; try again later"),
+    };
+    if let Some(message) = message {
+        let _ = send(&mut writer, &PhoneMessage::Error {
+            message: message.to_string(),
+        })
+        .await;
+        return;
+    }
+    let device_name = hello
+        .and_then(|(_, device_name)| device_name)
+        .unwrap_or_else(|| "watch".to_string());
+    if send(&mut writer, &PhoneMessage::Welcome).await.is_err() {
+        return;
+    }
+    let _ = app_handle.emit("companion://connected", &device_name);
+
+    clients.fetch_add(1, Ordering::SeqCst);
+    let mut events = outbound.subscribe();
+    loop {
+        tokio::select! {
+            line = lines.next() => {
+                let Some(Ok(line)) = line else { break };
+                let reply = match serde_json::from_str::<WatchMessage>(&line) {
+                    Ok(message) => handle_message(&app_handle, message).await,
+                    Err(e) => Some(PhoneMessage::Error { message: e.to_string() }),
+                };
+                if let Some(reply) = reply {
+                    if send(&mut writer, &reply).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            event = events.recv() => match event {
+                Ok(message) => {
+                    if send(&mut writer, &message).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+    }
+    clients.fetch_sub(1, Ordering::SeqCst);
+}
+
+// Command to start listening for watch connections on the local network
+#[tauri::command]
+pub async fn start_companion_server(
+    port: Option<u16>,
+    app_handle: AppHandle,
+    state: State<'_, CompanionState>,
+) -> Result<CompanionStatus, String> {
+    let port = port.unwrap_or(DEFAULT_PORT);
+    if state.lock().map_err(|e| e.to_string())?.task.is_some() {
+        return Err("Companion server is already running".to_string());
+    }
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut server = state.lock().map_err(|e| e.to_string())?;
+    if server.task.is_some() {
+        return Err("Companion server is already running".to_string());
+    }
+
+    let pairing = Arc::new(Mutex::new(Pairing::new()));
+    let (outbound, _) = broadcast::channel(32);
+
+    // Forward live transcripts to every paired watch
+    let transcripts = outbound.clone();
+    let caption_listener = app_handle.listen("caption://text", move |event| {
+        if let Ok(caption) = serde_json::from_str::<CaptionEvent>(event.payload()) {
+            let _ = transcripts.send(PhoneMessage::Transcript { text: caption.text });
+        }
+    });
+
+    let clients = server.clients.clone();
+    let client_pairing = pairing.clone();
+    let handle = app_handle.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        // Connections live in the set, so aborting this task drops them all
+        let mut connections = JoinSet::new();
+        while let Ok((stream, peer)) = listener.accept().await {
+            while connections.try_join_next().is_some() {}
+            if connections.len() >= MAX_CONNECTIONS {
+                drop(stream);
+                continue;
+            }
+            connections.spawn(handle_client(
+                stream,
+                peer,
+                handle.clone(),
+                client_pairing.clone(),
+                outbound.clone(),
+                clients.clone(),
+            ));
+        }
+    });
+
+    server.task = Some(task);
+    server.listener = Some(caption_listener);
+    server.port = Some(port);
+    server.pairing = Some(pairing);
+    Ok(status(&server))
+}
+
+#[tauri::command]
+pub fn stop_companion_server(
+    app_handle: AppHandle,
+    state: State<'_, CompanionState>,
+) -> Result<(), String> {
+    let mut server = state.lock().map_err(|e| e.to_string())?;
+    // Also aborts every connected watch, which then never counts itself out
+    if let Some(task) = server.task.take() {
+        task.abort();
+    }
+    if let Some(listener) = server.listener.take() {
+        app_handle.unlisten(listener);
+    }
+    server.port = None;
+    server.pairing = None;
+    server.clients.store(0, Ordering::SeqCst);
+    Ok(())
+}
+
+fn status(server: &CompanionServer) -> CompanionStatus {
+    CompanionStatus {
+        running: server.task.is_some(),
+        port: server.port,
+        pairing_code: server
+            .pairing
+            .as_ref()
+            .and_then(|pairing| pairing.lock().ok().map(|pairing| pairing.code.clone())),
+        connected_clients: server.clients.load(Ordering::SeqCst),
+    }
+}
+
+#[tauri::command]
+pub fn get_companion_status(state: State<'_, CompanionState>) -> Result<CompanionStatus, String> {
+    let server = state.lock().map_err(|e| e.to_string())?;
+    Ok(status(&server))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairing(code: &str) -> Pairing {
+        Pairing {
+            code: code.to_string(),
+            failures: 0,
+            peers: HashMap::new(),
+        }
+    }
+
+    fn peer(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    #[test]
+    fn pairs_with_the_right_code() {
+        let mut pairing = pairing("123456");
+        assert_eq!(pairing.attempt(peer(2), "123456", Instant::now()), PairingOutcome::Paired);
+    }
+
+    #[test]
+    fn rotates_the_code_after_repeated_failures() {
+        let mut pairing = pairing("123456");
+        let now = Instant::now();
+        for _ in 1..ROTATE_AFTER_FAILURES {
+            assert_eq!(
+                pairing.attempt(peer(2), "000000", now),
+                PairingOutcome::Rejected { new_code: None }
+            );
+        }
+        let PairingOutcome::Rejected { new_code: Some(code) } = pairing.attempt(peer(3), "000000", now) else {
+            panic!("the code was not rotated");
+        };
+        assert_eq!(code, pairing.code);
+        assert_eq!(code.len(), 6);
+    }
+
+    #[test]
+    fn locks_out_a_peer_that_keeps_guessing() {
+        let mut pairing = pairing("123456");
+        let now = Instant::now();
+        for _ in 0..MAX_FAILED_PAIRINGS {
+            assert!(matches!(pairing.attempt(peer(2), "000000", now), PairingOutcome::Rejected { .. }));
+        }
+        let code = pairing.code.clone();
+        assert_eq!(pairing.attempt(peer(2), &code, now), PairingOutcome::LockedOut);
+        // Other devices are not affected, and the lockout wears off
+        assert_eq!(pairing.attempt(peer(3), &code, now), PairingOutcome::Paired);
+        assert_eq!(pairing.attempt(peer(2), &code, now + PAIRING_LOCKOUT), PairingOutcome::Paired);
+    }
+
+    #[test]
+    fn rejects_codes_of_another_length() {
+        let mut pairing = pairing("123456");
+        assert!(matches!(pairing.attempt(peer(2), "1234567", Instant::now()), PairingOutcome::Rejected { .. }));
+    }
+}
@@ -0,0 +1,209 @@
+// Ledger of the user's data-sharing consents. Every grant and revocation is
+// recorded with its time, and modules check the ledger before sending data to
+// a third party; a missing consent emits consent://required so the UI can ask.
+
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::storage;
+
+const CONSENTS_FILE: &str = "consents.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsentScope {
+    // Recordings sent to the OpenAI Whisper API
+    OpenAiAudio,
+    // Recordings streamed to Deepgram
+    DeepgramAudio,
+    // Prompts, notes and conversation history sent to Gemini
+    GeminiText,
+    // Microphone audio streamed to Gemini Live
+    GeminiAudio,
+    // Replies read aloud by OpenAI text to speech
+    OpenAiSpeech,
+    // Responses checked by the OpenAI moderation API
+    OpenAiModeration,
+    // Coordinates sent to OpenWeather
+    WeatherLocation,
+    // Prompts, notes and conversation history sent to OpenAI chat models
+    OpenAiText,
+    // Prompts, notes and conversation history sent to Anthropic
+    AnthropicText,
+}
+
+impl ConsentScope {
+    fn description(self) -> &'static str {
+        match self {
+            ConsentScope::OpenAiAudio => "send recordings to OpenAI for transcription",
+            ConsentScope::DeepgramAudio => "send recordings to Deepgram for transcription",
+            ConsentScope::GeminiText => "send prompts and context to Google Gemini",
+            ConsentScope::GeminiAudio => "stream your voice to Google Gemini",
+            ConsentScope::OpenAiSpeech => "send replies to OpenAI to be read aloud",
+            ConsentScope::OpenAiModeration => "send responses to OpenAI for moderation",
+            ConsentScope::WeatherLocation => "share your location with OpenWeather",
+            ConsentScope::OpenAiText => "send prompts and context to OpenAI",
+            ConsentScope::AnthropicText => "send prompts and context to Anthropic",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Consent {
+    pub id: u64,
+    pub scope: ConsentScope,
+    pub granted_at: u64,
+    pub revoked_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ConsentStore {
+    next_id: u64,
+    // Every grant ever made, oldest first; revoked ones are kept for the record
+    consents: Vec<Consent>,
+}
+
+pub type ConsentState = Mutex<ConsentStore>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ConsentRequest {
+    pub scope: ConsentScope,
+    pub description: String,
+}
+
+pub fn load(app_handle: &AppHandle) -> ConsentStore {
+    storage::load_json(app_handle, CONSENTS_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl ConsentStore {
+    fn active(&self, scope: ConsentScope) -> Option<&Consent> {
+        self.consents
+            .iter()
+            .find(|consent| consent.scope == scope && consent.revoked_at.is_none())
+    }
+
+    fn grant(&mut self, scope: ConsentScope, now: u64) -> Consent {
+        if let Some(consent) = self.active(scope) {
+            return consent.clone();
+        }
+        self.next_id += 1;
+        let consent = Consent {
+            id: self.next_id,
+            scope,
+            granted_at: now,
+            revoked_at: None,
+        };
+        self.consents.push(consent.clone());
+        consent
+    }
+
+    fn revoke(&mut self, scope: ConsentScope, now: u64) -> Result<(), String> {
+        let consent = self
+            .consents
+            .iter_mut()
+            .find(|consent| consent.scope == scope && consent.revoked_at.is_none())
+            .ok_or("Consent not granted".to_string())?;
+        consent.revoked_at = Some(now);
+        Ok(())
+    }
+}
+
+pub fn granted(app_handle: &AppHandle, scope: ConsentScope) -> bool {
+    let state = app_handle.state::<ConsentState>();
+    let Ok(store) = state.lock() else {
+        return false;
+    };
+    store.active(scope).is_some()
+}
+
+// Fail unless the user has consented, asking the UI to prompt for it
+pub fn require(app_handle: &AppHandle, scope: ConsentScope) -> Result<(), String> {
+    if granted(app_handle, scope) {
+        return Ok(());
+    }
+    let description = scope.description().to_string();
+    let _ = app_handle.emit("consent://required", ConsentRequest { scope, description: description.clone() });
+    Err(format!("Consent required to {}", description))
+}
+
+// Command to list the ledger, including revoked consents
+#[tauri::command]
+pub fn get_consents(state: State<'_, ConsentState>) -> Result<Vec<Consent>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.consents.clone())
+}
+
+// Command to record a consent; granting one already in force returns it unchanged
+#[tauri::command]
+pub fn grant_consent(
+    scope: ConsentScope,
+    app_handle: AppHandle,
+    state: State<'_, ConsentState>,
+) -> Result<Consent, String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let consent = store.grant(scope, now_secs());
+    storage::save_json(&app_handle, CONSENTS_FILE, &*store)?;
+    Ok(consent)
+}
+
+#[tauri::command]
+pub fn revoke_consent(
+    scope: ConsentScope,
+    app_handle: AppHandle,
+    state: State<'_, ConsentState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.revoke(scope, now_secs())?;
+    storage::save_json(&app_handle, CONSENTS_FILE, &*store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn granting_twice_keeps_the_first_consent() {
+        let mut store = ConsentStore::default();
+        let first = store.grant(ConsentScope::DeepgramAudio, 100);
+        let again = store.grant(ConsentScope::DeepgramAudio, 200);
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.granted_at, 100);
+        assert_eq!(store.consents.len(), 1);
+        assert!(store.active(ConsentScope::DeepgramAudio).is_some());
+        assert!(store.active(ConsentScope::OpenAiAudio).is_none());
+    }
+
+    #[test]
+    fn revoked_consents_stay_in_the_ledger() {
+        let mut store = ConsentStore::default();
+        assert_eq!(store.revoke(ConsentScope::GeminiText, 50), Err("Consent not granted".to_string()));
+
+        store.grant(ConsentScope::GeminiText, 100);
+        store.revoke(ConsentScope::GeminiText, 150).unwrap();
+        assert!(store.active(ConsentScope::GeminiText).is_none());
+        assert!(store.revoke(ConsentScope::GeminiText, 160).is_err());
+
+        let renewed = store.grant(ConsentScope::GeminiText, 200);
+        assert_eq!(renewed.id, 2);
+        assert_eq!(store.consents.len(), 2);
+        assert_eq!(store.consents[0].revoked_at, Some(150));
+        assert_eq!(store.active(ConsentScope::GeminiText).map(|consent| consent.id), Some(2));
+    }
+
+    #[test]
+    fn scopes_serialize_in_snake_case() {
+        assert_eq!(serde_json::to_string(&ConsentScope::OpenAiModeration).unwrap(), "\"open_ai_moderation\"");
+        let scope: ConsentScope = serde_json::from_str("\"weather_location\"").unwrap();
+        assert_eq!(scope, ConsentScope::WeatherLocation);
+        assert!(scope.description().contains("OpenWeather"));
+    }
+}
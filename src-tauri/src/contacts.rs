@@ -0,0 +1,544 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Emitter, Manager, State,
+};
+
+use crate::dav::{self, DavCredentials};
+use crate::keystore::Keystore;
+use crate::storage;
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+const ACCOUNTS_FILE: &str = "carddav_accounts.json";
+const CONTACTS_FILE: &str = "carddav_contacts.json";
+const SYNC_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Contact {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub phones: Vec<String>,
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub organization: Option<String>,
+    // Where the contact came from: "device" or "carddav:<account id>"
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+// Fields for a new device contact, e.g. reviewed from a scanned business card
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct NewContact {
+    pub name: String,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub organization: Option<String>,
+    pub job_title: Option<String>,
+    pub website: Option<String>,
+    pub address: Option<String>,
+}
+
+// The password lives in the keystore under carddav:<id>
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CardDavAccount {
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+    pub url: String,
+    pub username: String,
+    #[serde(default)]
+    pub last_synced: Option<u64>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ContactsStore {
+    next_id: u64,
+    accounts: Vec<CardDavAccount>,
+    #[serde(skip)]
+    contacts: Vec<Contact>,
+}
+
+pub type ContactsState = Mutex<ContactsStore>;
+
+// Bridge to the device address book
+pub struct DeviceContacts {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct SearchRequest<'a> {
+    query: &'a str,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct SearchResponse {
+    contacts: Vec<Contact>,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct CreateResponse {
+    contact: Contact,
+}
+
+impl DeviceContacts {
+    pub fn search(&self, query: &str) -> Result<Vec<Contact>, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<SearchResponse>("searchContacts", SearchRequest { query })
+                .map(|response| response.contacts)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = query;
+            Ok(Vec::new())
+        }
+    }
+
+    pub fn create(&self, contact: &NewContact) -> Result<Contact, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<CreateResponse>("createContact", contact)
+                .map(|response| response.contact)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = contact;
+            Err("The address book is not available on this device".to_string())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("contacts")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let device = DeviceContacts {
+                handle: api.register_android_plugin("company.atechnology.plates", "ContactsPlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let device = {
+                let _ = api;
+                DeviceContacts {}
+            };
+            app.manage(device);
+            Ok(())
+        })
+        .build()
+}
+
+pub fn load(app_handle: &AppHandle) -> ContactsStore {
+    let mut store: ContactsStore = storage::load_json(app_handle, ACCOUNTS_FILE);
+    store.contacts = storage::load_json(app_handle, CONTACTS_FILE);
+    store
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn unescape_vcard(text: &str) -> String {
+    text.replace("\\n", "\n")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+// Parse every VCARD in a vCard document
+pub fn parse_vcards(vcf: &str, source: &str) -> Vec<Contact> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in vcf.lines() {
+        match raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')) {
+            Some(rest) => {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(rest);
+                }
+            }
+            None => lines.push(raw.to_string()),
+        }
+    }
+
+    let mut contacts = Vec::new();
+    let mut current: Option<Contact> = None;
+    let mut structured_name: Option<String> = None;
+
+    for line in lines {
+        if line.eq_ignore_ascii_case("BEGIN:VCARD") {
+            current = Some(Contact {
+                id: String::new(),
+                name: String::new(),
+                phones: Vec::new(),
+                emails: Vec::new(),
+                organization: None,
+                sources: vec![source.to_string()],
+            });
+            structured_name = None;
+            continue;
+        }
+        if line.eq_ignore_ascii_case("END:VCARD") {
+            if let Some(mut contact) = current.take() {
+                if contact.name.is_empty() {
+                    contact.name = structured_name.take().unwrap_or_default();
+                }
+                if !contact.name.is_empty() || !contact.phones.is_empty() || !contact.emails.is_empty() {
+                    contacts.push(contact);
+                }
+            }
+            continue;
+        }
+
+        let Some(contact) = current.as_mut() else { continue };
+        let Some((key, value)) = line.split_once(':') else { continue };
+        // Drop parameters and item grouping, e.g. "item1.TEL;TYPE=CELL"
+        let name = key.split(';').next().unwrap_or_default();
+        let name = name.rsplit('.').next().unwrap_or(name).to_ascii_uppercase();
+        match name.as_str() {
+            "UID" => contact.id = format!("{}:{}", source, value),
+            "FN" => contact.name = unescape_vcard(value),
+            "N" => {
+                // N:Family;Given;Additional;Prefix;Suffix
+                let parts: Vec<&str> = value.split(';').collect();
+                let given = parts.get(1).copied().unwrap_or_default();
+                let family = parts.first().copied().unwrap_or_default();
+                structured_name = Some(format!("{} {}", given, family).trim().to_string());
+            }
+            "TEL" => contact.phones.push(value.trim_start_matches("tel:").to_string()),
+            "EMAIL" => contact.emails.push(value.to_string()),
+            "ORG" => contact.organization = Some(unescape_vcard(value).replace(';', ", ")),
+            _ => {}
+        }
+    }
+    contacts
+}
+
+fn normalize_phone(phone: &str) -> String {
+    let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
+    // Compare the last 9 digits so +1 555... and 555... match
+    digits[digits.len().saturating_sub(9)..].to_string()
+}
+
+fn same_person(a: &Contact, b: &Contact) -> bool {
+    let shared_phone = a.phones.iter().any(|p| {
+        let p = normalize_phone(p);
+        !p.is_empty() && b.phones.iter().any(|q| normalize_phone(q) == p)
+    });
+    let shared_email = a
+        .emails
+        .iter()
+        .any(|e| b.emails.iter().any(|f| f.eq_ignore_ascii_case(e)));
+    shared_phone || shared_email || (!a.name.is_empty() && a.name.eq_ignore_ascii_case(&b.name))
+}
+
+// Merge duplicates from different sources into a single contact
+pub fn merge_contacts(contacts: Vec<Contact>) -> Vec<Contact> {
+    let mut merged: Vec<Contact> = Vec::new();
+    for contact in contacts {
+        match merged.iter_mut().find(|existing| same_person(existing, &contact)) {
+            Some(existing) => {
+                for phone in contact.phones {
+                    let normalized = normalize_phone(&phone);
+                    if !existing.phones.iter().any(|p| normalize_phone(p) == normalized) {
+                        existing.phones.push(phone);
+                    }
+                }
+                for email in contact.emails {
+                    if !existing.emails.iter().any(|e| e.eq_ignore_ascii_case(&email)) {
+                        existing.emails.push(email);
+                    }
+                }
+                if existing.organization.is_none() {
+                    existing.organization = contact.organization;
+                }
+                for source in contact.sources {
+                    if !existing.sources.contains(&source) {
+                        existing.sources.push(source);
+                    }
+                }
+            }
+            None => merged.push(contact),
+        }
+    }
+    merged
+}
+
+fn matches_query(contact: &Contact, query: &str) -> bool {
+    let query = query.to_lowercase();
+    let query_digits = normalize_phone(&query);
+    contact.name.to_lowercase().contains(&query)
+        || contact.emails.iter().any(|e| e.to_lowercase().contains(&query))
+        || contact
+            .organization
+            .as_ref()
+            .is_some_and(|org| org.to_lowercase().contains(&query))
+        || (query_digits.len() >= 3 && contact.phones.iter().any(|p| normalize_phone(p).contains(&query_digits)))
+}
+
+async fn fetch_account(keystore: &Keystore, account: &CardDavAccount) -> Result<Vec<Contact>, String> {
+    let password = keystore
+        .get(&format!("carddav:{}", account.id))?
+        .unwrap_or_default();
+    let credentials = DavCredentials {
+        username: &account.username,
+        password: &password,
+    };
+    let body = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+        <card:addressbook-query xmlns:d=\"DAV:\" xmlns:card=\"urn:ietf:params:xml:ns:carddav\">\
+        <d:prop><d:getetag/><card:address-data/></d:prop>\
+        </card:addressbook-query>"
+        .to_string();
+
+    let xml = dav::request("REPORT", &account.url, &credentials, "1", body).await?;
+    let source = format!("carddav:{}", account.id);
+    Ok(dav::extract_elements(&xml, "address-data")
+        .iter()
+        .flat_map(|vcf| parse_vcards(vcf, &source))
+        .collect())
+}
+
+fn save_store(app_handle: &AppHandle, store: &ContactsStore) -> Result<(), String> {
+    storage::save_json(app_handle, ACCOUNTS_FILE, store)?;
+    storage::save_json(app_handle, CONTACTS_FILE, &store.contacts)
+}
+
+pub async fn sync_all(app_handle: &AppHandle) -> Result<usize, String> {
+    let accounts = {
+        let state = app_handle.state::<ContactsState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store.accounts.clone()
+    };
+
+    let mut results = Vec::new();
+    for account in &accounts {
+        let keystore = app_handle.state::<Keystore>();
+        results.push((account.id, fetch_account(&keystore, account).await));
+    }
+
+    let state = app_handle.state::<ContactsState>();
+    let mut guard = state.lock().map_err(|e| e.to_string())?;
+    let store = &mut *guard;
+    for (account_id, result) in results {
+        let Some(account) = store.accounts.iter_mut().find(|a| a.id == account_id) else {
+            continue;
+        };
+        let source = format!("carddav:{}", account_id);
+        match result {
+            Ok(contacts) => {
+                account.last_synced = Some(now_secs());
+                account.last_error = None;
+                store.contacts.retain(|contact| !contact.sources.contains(&source));
+                store.contacts.extend(contacts);
+            }
+            Err(e) => account.last_error = Some(e),
+        }
+    }
+    save_store(app_handle, store)?;
+    let count = store.contacts.len();
+    drop(guard);
+
+    let _ = app_handle.emit("contacts://synced", count);
+    Ok(count)
+}
+
+pub fn spawn_sync_loop(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let _ = sync_all(&app_handle).await;
+            tokio::time::sleep(SYNC_INTERVAL).await;
+        }
+    });
+}
+
+// Command to search device and CardDAV contacts, merging duplicates
+#[tauri::command]
+pub fn search_contacts(
+    query: String,
+    device: State<'_, DeviceContacts>,
+    state: State<'_, ContactsState>,
+) -> Result<Vec<Contact>, String> {
+    let mut found = device.search(&query).unwrap_or_default();
+    for contact in &mut found {
+        if contact.sources.is_empty() {
+            contact.sources.push("device".to_string());
+        }
+    }
+
+    let store = state.lock().map_err(|e| e.to_string())?;
+    found.extend(
+        store
+            .contacts
+            .iter()
+            .filter(|contact| matches_query(contact, &query))
+            .cloned(),
+    );
+    Ok(merge_contacts(found))
+}
+
+#[tauri::command]
+pub async fn add_carddav_account(
+    account: CardDavAccount,
+    password: String,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, ContactsState>,
+) -> Result<CardDavAccount, String> {
+    let account = {
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.next_id += 1;
+        let account = CardDavAccount {
+            id: store.next_id,
+            last_synced: None,
+            last_error: None,
+            ..account
+        };
+        keystore.set(&format!("carddav:{}", account.id), &password)?;
+        store.accounts.push(account.clone());
+        save_store(&app_handle, &store)?;
+        account
+    };
+
+    sync_all(&app_handle).await?;
+    Ok(account)
+}
+
+#[tauri::command]
+pub fn remove_carddav_account(
+    id: u64,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, ContactsState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let source = format!("carddav:{}", id);
+    store.accounts.retain(|account| account.id != id);
+    store.contacts.retain(|contact| !contact.sources.contains(&source));
+    keystore.delete(&source)?;
+    save_store(&app_handle, &store)
+}
+
+#[tauri::command]
+pub fn list_carddav_accounts(state: State<'_, ContactsState>) -> Result<Vec<CardDavAccount>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.accounts.clone())
+}
+
+#[tauri::command]
+pub async fn sync_contacts(app_handle: AppHandle) -> Result<usize, String> {
+    sync_all(&app_handle).await
+}
+
+// Command to save a contact to the device address book, e.g. after the user
+// reviewed a scanned business card
+#[tauri::command]
+pub fn create_contact(contact: NewContact, device: State<'_, DeviceContacts>) -> Result<Contact, String> {
+    let trimmed = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+    let contact = NewContact {
+        name: contact.name.trim().to_string(),
+        phones: contact.phones.into_iter().map(|phone| phone.trim().to_string()).filter(|phone| !phone.is_empty()).collect(),
+        emails: contact.emails.into_iter().map(|email| email.trim().to_string()).filter(|email| !email.is_empty()).collect(),
+        organization: trimmed(contact.organization),
+        job_title: trimmed(contact.job_title),
+        website: trimmed(contact.website),
+        address: trimmed(contact.address),
+    };
+    if contact.name.is_empty() {
+        return Err("A contact needs a name".to_string());
+    }
+    let mut created = device.create(&contact)?;
+    if created.sources.is_empty() {
+        created.sources.push("device".to_string());
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(name: &str, phones: &[&str], emails: &[&str], source: &str) -> Contact {
+        Contact {
+            id: format!("{}:{}", source, name),
+            name: name.to_string(),
+            phones: phones.iter().map(|phone| phone.to_string()).collect(),
+            emails: emails.iter().map(|email| email.to_string()).collect(),
+            organization: None,
+            sources: vec![source.to_string()],
+        }
+    }
+
+    #[test]
+    fn vcards_are_parsed() {
+        let vcf = "BEGIN:VCARD\r\n\
+                   VERSION:3.0\r\n\
+                   UID:42\r\n\
+                   N:Lovelace;Ada;;;\r\n\
+                   item1.TEL;TYPE=CELL:+44 20 7946 0000\r\n\
+                   TEL;VALUE=uri:tel:555-0100\r\n\
+                   EMAIL;TYPE=work:ada@exam\r\n ple.com\r\n\
+                   ORG:Analytical Engines\\, Ltd;Research\r\n\
+                   END:VCARD\r\n\
+                   begin:vcard\r\n\
+                   FN:Nobody\r\n\
+                   end:vcard\r\n\
+                   BEGIN:VCARD\r\n\
+                   NOTE:nothing to go on\r\n\
+                   END:VCARD\r\n";
+        let contacts = parse_vcards(vcf, "carddav:1");
+        assert_eq!(contacts.len(), 2);
+
+        let ada = &contacts[0];
+        assert_eq!(ada.id, "carddav:1:42");
+        assert_eq!(ada.name, "Ada Lovelace");
+        assert_eq!(ada.phones, ["+44 20 7946 0000", "555-0100"]);
+        assert_eq!(ada.emails, ["ada@example.com"]);
+        assert_eq!(ada.organization.as_deref(), Some("Analytical Engines, Ltd, Research"));
+        assert_eq!(ada.sources, ["carddav:1"]);
+        assert_eq!(contacts[1].name, "Nobody");
+    }
+
+    #[test]
+    fn duplicates_from_different_sources_are_merged() {
+        let merged = merge_contacts(vec![
+            contact("Ada Lovelace", &["+1 555 010 0123"], &[], "device"),
+            contact("Ada L.", &["(555) 010-0123", "555 999 8888"], &["ADA@example.com"], "carddav:1"),
+            contact("Charles Babbage", &[], &["ada@example.com"], "carddav:2"),
+            contact("Grace Hopper", &[], &[], "device"),
+        ]);
+        assert_eq!(merged.len(), 2);
+        let ada = &merged[0];
+        assert_eq!(ada.name, "Ada Lovelace");
+        assert_eq!(ada.phones, ["+1 555 010 0123", "555 999 8888"]);
+        assert_eq!(ada.emails, ["ADA@example.com"]);
+        assert_eq!(ada.sources, ["device", "carddav:1", "carddav:2"]);
+    }
+
+    #[test]
+    fn queries_match_names_emails_organizations_and_digits() {
+        let mut ada = contact("Ada Lovelace", &["+44 20 7946 0000"], &["ada@example.com"], "device");
+        ada.organization = Some("Analytical Engines".to_string());
+        assert!(matches_query(&ada, "love"));
+        assert!(matches_query(&ada, "EXAMPLE.COM"));
+        assert!(matches_query(&ada, "engines"));
+        assert!(matches_query(&ada, "7946"));
+        assert!(!matches_query(&ada, "79"));
+        assert!(!matches_query(&ada, "babbage"));
+    }
+}
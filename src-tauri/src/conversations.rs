@@ -0,0 +1,709 @@
+// Conversations with the assistant, kept in an SQLite database in app data
+// so a kill mid-write can't lose the history. Long conversations are folded
+// into a rolling summary when they outgrow the context token budget.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Serialize, Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+
+use crate::settings::{AppSettings, CompressionLevel};
+use crate::storage;
+
+const CONVERSATIONS_DB: &str = "conversations.sqlite3";
+// History from before the database, imported once and then set aside
+const LEGACY_FILE: &str = "conversations.json";
+const IMPORTED_SUFFIX: &str = ".imported";
+// Characters of the first message used as a conversation title
+const TITLE_CHARS: usize = 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+
+    fn parse(text: &str) -> Role {
+        if text == "assistant" {
+            Role::Assistant
+        } else {
+            Role::User
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Message {
+    pub id: u64,
+    pub role: Role,
+    pub text: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Conversation {
+    pub id: u64,
+    pub title: String,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub pinned: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+    // Rolling summary of the messages up to and including `summarized_through`
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub summarized_through: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum PinnedItem {
+    Conversation {
+        conversation_id: u64,
+        title: String,
+        updated_at: u64,
+    },
+    Message {
+        conversation_id: u64,
+        message_id: u64,
+        text: String,
+        created_at: u64,
+    },
+}
+
+// The JSON file conversations were kept in before
+#[derive(Deserialize, Default)]
+struct LegacyStore {
+    #[serde(default)]
+    conversations: Vec<Conversation>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Tables, in write-ahead log mode so writes are atomic and readers don't block
+fn init(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         CREATE TABLE IF NOT EXISTS conversations (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             title TEXT NOT NULL,
+             pinned INTEGER NOT NULL DEFAULT 0,
+             created_at INTEGER NOT NULL,
+             updated_at INTEGER NOT NULL,
+             summary TEXT,
+             summarized_through INTEGER NOT NULL DEFAULT 0
+         );
+         CREATE TABLE IF NOT EXISTS messages (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             conversation_id INTEGER NOT NULL,
+             role TEXT NOT NULL,
+             text TEXT NOT NULL,
+             created_at INTEGER NOT NULL,
+             favorite INTEGER NOT NULL DEFAULT 0
+         );
+         CREATE INDEX IF NOT EXISTS messages_conversation ON messages (conversation_id);",
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Copy conversations from the old JSON store, keeping their ids
+fn import(conn: &mut Connection, legacy: LegacyStore) -> Result<(), String> {
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    for conversation in &legacy.conversations {
+        transaction
+            .execute(
+                "INSERT OR IGNORE INTO conversations
+                 (id, title, pinned, created_at, updated_at, summary, summarized_through)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    conversation.id as i64,
+                    conversation.title,
+                    conversation.pinned,
+                    conversation.created_at as i64,
+                    conversation.updated_at as i64,
+                    conversation.summary,
+                    conversation.summarized_through as i64
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        for message in &conversation.messages {
+            transaction
+                .execute(
+                    "INSERT OR IGNORE INTO messages (id, conversation_id, role, text, created_at, favorite)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        message.id as i64,
+                        conversation.id as i64,
+                        message.role.as_str(),
+                        message.text,
+                        message.created_at as i64,
+                        message.favorite
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    transaction.execute_batch("PRAGMA user_version = 1;").map_err(|e| e.to_string())?;
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+// Opened per call; SQLite handles the locking between them
+fn open(app_handle: &AppHandle) -> Result<Connection, String> {
+    let path = storage::data_file(app_handle, CONVERSATIONS_DB)?;
+    let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+    init(&conn)?;
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if version < 1 {
+        let legacy_path = storage::data_file(app_handle, LEGACY_FILE)?;
+        import(&mut conn, storage::load_json(app_handle, LEGACY_FILE))?;
+        if legacy_path.exists() {
+            let mut imported = legacy_path.clone().into_os_string();
+            imported.push(IMPORTED_SUFFIX);
+            std::fs::rename(&legacy_path, imported).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(conn)
+}
+
+fn read_conversation(row: &Row) -> rusqlite::Result<Conversation> {
+    Ok(Conversation {
+        id: row.get::<_, i64>(0)? as u64,
+        title: row.get(1)?,
+        messages: Vec::new(),
+        pinned: row.get(2)?,
+        created_at: row.get::<_, i64>(3)? as u64,
+        updated_at: row.get::<_, i64>(4)? as u64,
+        summary: row.get(5)?,
+        summarized_through: row.get::<_, i64>(6)? as u64,
+    })
+}
+
+fn read_message(row: &Row) -> rusqlite::Result<Message> {
+    Ok(Message {
+        id: row.get::<_, i64>(0)? as u64,
+        role: Role::parse(&row.get::<_, String>(1)?),
+        text: row.get(2)?,
+        created_at: row.get::<_, i64>(3)? as u64,
+        favorite: row.get(4)?,
+    })
+}
+
+const CONVERSATION_COLUMNS: &str = "id, title, pinned, created_at, updated_at, summary, summarized_through";
+
+// A conversation with its messages, oldest first
+fn find(conn: &Connection, id: u64) -> Result<Conversation, String> {
+    let mut conversation = conn
+        .query_row(
+            &format!("SELECT {} FROM conversations WHERE id = ?1", CONVERSATION_COLUMNS),
+            params![id as i64],
+            read_conversation,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or("Conversation not found".to_string())?;
+    let mut statement = conn
+        .prepare("SELECT id, role, text, created_at, favorite FROM messages WHERE conversation_id = ?1 ORDER BY id")
+        .map_err(|e| e.to_string())?;
+    let rows = statement.query_map(params![id as i64], read_message).map_err(|e| e.to_string())?;
+    conversation.messages = rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+    Ok(conversation)
+}
+
+fn create(conn: &Connection, title: Option<String>, now: u64) -> Result<Conversation, String> {
+    let title = title.unwrap_or_else(|| "New conversation".to_string());
+    conn.execute(
+        "INSERT INTO conversations (title, created_at, updated_at) VALUES (?1, ?2, ?2)",
+        params![title, now as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(Conversation {
+        id: conn.last_insert_rowid() as u64,
+        title,
+        messages: Vec::new(),
+        pinned: false,
+        created_at: now,
+        updated_at: now,
+        summary: None,
+        summarized_through: 0,
+    })
+}
+
+fn insert_message(conn: &mut Connection, conversation_id: u64, role: Role, text: &str, now: u64) -> Result<Message, String> {
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    let existing: i64 = transaction
+        .query_row(
+            "SELECT COUNT(*) FROM messages WHERE conversation_id = ?1",
+            params![conversation_id as i64],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let updated = transaction
+        .execute(
+            "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
+            params![now as i64, conversation_id as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err("Conversation not found".to_string());
+    }
+    if existing == 0 && role == Role::User {
+        let title: String = text.chars().take(TITLE_CHARS).collect();
+        transaction
+            .execute("UPDATE conversations SET title = ?1 WHERE id = ?2", params![title, conversation_id as i64])
+            .map_err(|e| e.to_string())?;
+    }
+    transaction
+        .execute(
+            "INSERT INTO messages (conversation_id, role, text, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![conversation_id as i64, role.as_str(), text, now as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    let id = transaction.last_insert_rowid() as u64;
+    transaction.commit().map_err(|e| e.to_string())?;
+    Ok(Message {
+        id,
+        role,
+        text: text.to_string(),
+        created_at: now,
+        favorite: false,
+    })
+}
+
+// Append a message to a conversation and persist it
+pub fn append(app_handle: &AppHandle, conversation_id: u64, role: Role, text: &str) -> Result<Message, String> {
+    insert_message(&mut open(app_handle)?, conversation_id, role, text, now_secs())
+}
+
+fn prune_before(conn: &mut Connection, cutoff: u64) -> Result<usize, String> {
+    let cutoff = cutoff as i64;
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    // The summary would still describe the removed messages
+    transaction
+        .execute(
+            "UPDATE conversations SET summary = NULL, summarized_through = 0 WHERE id IN
+             (SELECT conversation_id FROM messages WHERE favorite = 0 AND created_at < ?1)",
+            params![cutoff],
+        )
+        .map_err(|e| e.to_string())?;
+    let removed = transaction
+        .execute("DELETE FROM messages WHERE favorite = 0 AND created_at < ?1", params![cutoff])
+        .map_err(|e| e.to_string())?;
+    transaction
+        .execute(
+            "DELETE FROM conversations WHERE pinned = 0 AND updated_at < ?1
+             AND NOT EXISTS (SELECT 1 FROM messages WHERE messages.conversation_id = conversations.id)",
+            params![cutoff],
+        )
+        .map_err(|e| e.to_string())?;
+    transaction.commit().map_err(|e| e.to_string())?;
+    Ok(removed)
+}
+
+// Drop messages created before the cutoff, keeping favorites, and then
+// conversations left empty unless they are pinned. Returns the messages removed.
+pub fn prune(app_handle: &AppHandle, cutoff: u64) -> Result<usize, String> {
+    prune_before(&mut open(app_handle)?, cutoff)
+}
+
+// Rough token estimate; about four characters per token for English text
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count() / 4 + 1
+}
+
+// Share of the token budget kept as verbatim recent messages
+fn recent_share(level: CompressionLevel) -> f32 {
+    match level {
+        CompressionLevel::Light => 0.75,
+        CompressionLevel::Balanced => 0.5,
+        CompressionLevel::Aggressive => 0.25,
+    }
+}
+
+// Index of the first message kept verbatim when the history is over budget;
+// the ones before it are folded into the summary. The newest message is the one
+// being answered and is always kept.
+fn split_for_budget(messages: &[Message], budget: usize, level: CompressionLevel) -> usize {
+    let keep_budget = (budget as f32 * recent_share(level)) as usize;
+    let mut split = messages.len().saturating_sub(1);
+    let mut kept = messages.get(split).map(|message| estimate_tokens(&message.text)).unwrap_or(0);
+    while split > 0 {
+        let tokens = estimate_tokens(&messages[split - 1].text);
+        if kept + tokens > keep_budget {
+            break;
+        }
+        kept += tokens;
+        split -= 1;
+    }
+    split
+}
+
+// Length of the updated summary: what the kept messages leave of the budget,
+// with a floor so a long recent history doesn't squeeze it to nothing
+fn summary_max_words(budget: usize, recent: &[Message]) -> usize {
+    let kept: usize = recent.iter().map(|message| estimate_tokens(&message.text)).sum();
+    (budget.saturating_sub(kept) * 3 / 4).max(50)
+}
+
+fn format_turns(messages: &[Message]) -> String {
+    messages
+        .iter()
+        .map(|message| match message.role {
+            Role::User => format!("User: {}", message.text),
+            Role::Assistant => format!("Assistant: {}", message.text),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+async fn summarize(
+    app_handle: &AppHandle,
+    settings: &AppSettings,
+    previous: Option<&str>,
+    messages: &[Message],
+    max_words: usize,
+) -> Result<String, String> {
+    let prompt = format!(
+        "Update the running summary of a conversation with the new messages. Keep names, facts, \
+         decisions and open questions. Reply with the summary only, in at most {} words.\n\n\
+         Current summary:\n{}\n\nNew messages:\n{}",
+        max_words,
+        previous.unwrap_or("(none)"),
+        format_turns(messages)
+    );
+    crate::engine::generate(app_handle, &prompt, settings).await
+}
+
+// Build the prompt for the latest user message of a conversation. When the
+// history no longer fits the token budget, older turns are folded into the
+// conversation's rolling summary instead of being dropped.
+pub async fn build_prompt(app_handle: &AppHandle, conversation_id: u64, settings: &AppSettings) -> Result<String, String> {
+    let conversation = find(&open(app_handle)?, conversation_id)?;
+    let pending: Vec<Message> = conversation
+        .messages
+        .into_iter()
+        .filter(|message| message.id > conversation.summarized_through)
+        .collect();
+    if pending.is_empty() {
+        return Err("Conversation has no messages".to_string());
+    }
+
+    let budget = settings.context.token_budget.max(1);
+    let total = conversation.summary.as_deref().map(estimate_tokens).unwrap_or(0)
+        + pending.iter().map(|message| estimate_tokens(&message.text)).sum::<usize>();
+
+    let mut summary = conversation.summary;
+    let mut recent = pending.as_slice();
+    if total > budget && pending.len() > 1 {
+        let split = split_for_budget(&pending, budget, settings.context.compression);
+        let older = &pending[..split];
+        if let Some(last) = older.last() {
+            let max_words = summary_max_words(budget, &pending[split..]);
+            let updated = summarize(app_handle, settings, summary.as_deref(), older, max_words).await?;
+
+            open(app_handle)?
+                .execute(
+                    "UPDATE conversations SET summary = ?1, summarized_through = ?2 WHERE id = ?3",
+                    params![updated, last.id as i64, conversation_id as i64],
+                )
+                .map_err(|e| e.to_string())?;
+
+            summary = Some(updated);
+            recent = &pending[split..];
+        }
+    }
+
+    let Some((current, history)) = recent.split_last() else {
+        return Err("Conversation has no messages".to_string());
+    };
+    if summary.is_none() && history.is_empty() {
+        return Ok(current.text.clone());
+    }
+
+    let mut prompt = String::new();
+    if let Some(summary) = summary {
+        prompt.push_str(&format!("Summary of the earlier conversation:\n{}\n\n", summary));
+    }
+    if !history.is_empty() {
+        prompt.push_str(&format!("Recent messages:\n{}\n\n", format_turns(history)));
+    }
+    prompt.push_str(&format!("User: {}\nAssistant:", current.text));
+    Ok(prompt)
+}
+
+fn pinned(conn: &Connection) -> Result<Vec<PinnedItem>, String> {
+    let mut items: Vec<(u64, PinnedItem)> = Vec::new();
+    let mut statement = conn
+        .prepare("SELECT id, title, updated_at FROM conversations WHERE pinned = 1")
+        .map_err(|e| e.to_string())?;
+    let conversations = statement
+        .query_map([], |row| {
+            let updated_at = row.get::<_, i64>(2)? as u64;
+            Ok((
+                updated_at,
+                PinnedItem::Conversation {
+                    conversation_id: row.get::<_, i64>(0)? as u64,
+                    title: row.get(1)?,
+                    updated_at,
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    items.extend(conversations.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?);
+    let mut statement = conn
+        .prepare("SELECT conversation_id, id, text, created_at FROM messages WHERE favorite = 1")
+        .map_err(|e| e.to_string())?;
+    let messages = statement
+        .query_map([], |row| {
+            let created_at = row.get::<_, i64>(3)? as u64;
+            Ok((
+                created_at,
+                PinnedItem::Message {
+                    conversation_id: row.get::<_, i64>(0)? as u64,
+                    message_id: row.get::<_, i64>(1)? as u64,
+                    text: row.get(2)?,
+                    created_at,
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    items.extend(messages.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?);
+    items.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+    Ok(items.into_iter().map(|(_, item)| item).collect())
+}
+
+// Pinned conversations and favorite messages, most recent first
+pub fn pinned_items(app_handle: &AppHandle) -> Vec<PinnedItem> {
+    open(app_handle).and_then(|conn| pinned(&conn)).unwrap_or_default()
+}
+
+// Every conversation with its messages, as JSON in the form of the old store,
+// for encrypted sync
+pub fn export(app_handle: &AppHandle) -> Result<Vec<u8>, String> {
+    let conn = open(app_handle)?;
+    let conversations = list(&conn)?
+        .into_iter()
+        .map(|conversation| find(&conn, conversation.id))
+        .collect::<Result<Vec<_>, _>>()?;
+    serde_json::to_vec(&serde_json::json!({ "conversations": conversations })).map_err(|e| e.to_string())
+}
+
+// Conversations without their messages, pinned first
+fn list(conn: &Connection) -> Result<Vec<Conversation>, String> {
+    let mut statement = conn
+        .prepare(&format!(
+            "SELECT {} FROM conversations ORDER BY pinned DESC, updated_at DESC, id DESC",
+            CONVERSATION_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = statement.query_map([], read_conversation).map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn start_conversation(title: Option<String>, app_handle: AppHandle) -> Result<Conversation, String> {
+    create(&open(&app_handle)?, title, now_secs())
+}
+
+// Same as start_conversation, under the name other clients use
+#[tauri::command]
+pub fn new_conversation(title: Option<String>, app_handle: AppHandle) -> Result<Conversation, String> {
+    start_conversation(title, app_handle)
+}
+
+// Command to list conversations without their messages, pinned first
+#[tauri::command]
+pub fn list_conversations(app_handle: AppHandle) -> Result<Vec<Conversation>, String> {
+    list(&open(&app_handle)?)
+}
+
+#[tauri::command]
+pub fn get_conversation(id: u64, app_handle: AppHandle) -> Result<Conversation, String> {
+    find(&open(&app_handle)?, id)
+}
+
+#[tauri::command]
+pub fn delete_conversation(id: u64, app_handle: AppHandle) -> Result<(), String> {
+    let mut conn = open(&app_handle)?;
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    transaction
+        .execute("DELETE FROM messages WHERE conversation_id = ?1", params![id as i64])
+        .map_err(|e| e.to_string())?;
+    transaction
+        .execute("DELETE FROM conversations WHERE id = ?1", params![id as i64])
+        .map_err(|e| e.to_string())?;
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn pin_conversation(id: u64, pinned: bool, app_handle: AppHandle) -> Result<(), String> {
+    let updated = open(&app_handle)?
+        .execute("UPDATE conversations SET pinned = ?1 WHERE id = ?2", params![pinned, id as i64])
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err("Conversation not found".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn favorite_message(
+    conversation_id: u64,
+    message_id: u64,
+    favorite: bool,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let updated = open(&app_handle)?
+        .execute(
+            "UPDATE messages SET favorite = ?1 WHERE id = ?2 AND conversation_id = ?3",
+            params![favorite, message_id as i64, conversation_id as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err("Message not found".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_pinned(app_handle: AppHandle) -> Vec<PinnedItem> {
+    pinned_items(&app_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn messages_are_stored_in_order_and_title_the_conversation() {
+        let mut conn = conn();
+        let conversation = create(&conn, None, 100).unwrap();
+        insert_message(&mut conn, conversation.id, Role::User, "What's the weather like?", 110).unwrap();
+        insert_message(&mut conn, conversation.id, Role::Assistant, "Sunny.", 120).unwrap();
+
+        let stored = find(&conn, conversation.id).unwrap();
+        assert_eq!(stored.title, "What's the weather like?");
+        assert_eq!(stored.updated_at, 120);
+        let roles: Vec<Role> = stored.messages.iter().map(|message| message.role).collect();
+        assert_eq!(roles, [Role::User, Role::Assistant]);
+        assert!(insert_message(&mut conn, conversation.id + 1, Role::User, "Hi", 130).is_err());
+    }
+
+    #[test]
+    fn pinned_conversations_are_listed_first() {
+        let conn = conn();
+        let older = create(&conn, Some("Older".to_string()), 100).unwrap();
+        create(&conn, Some("Newer".to_string()), 200).unwrap();
+        conn.execute("UPDATE conversations SET pinned = 1 WHERE id = ?1", params![older.id as i64])
+            .unwrap();
+
+        let titles: Vec<String> = list(&conn).unwrap().into_iter().map(|conversation| conversation.title).collect();
+        assert_eq!(titles, ["Older", "Newer"]);
+        assert!(matches!(pinned(&conn).unwrap()[..], [PinnedItem::Conversation { .. }]));
+    }
+
+    #[test]
+    fn pruning_keeps_favorites_and_pinned_conversations() {
+        let mut conn = conn();
+        let kept = create(&conn, None, 100).unwrap();
+        let favorite = insert_message(&mut conn, kept.id, Role::User, "Remember this", 100).unwrap();
+        insert_message(&mut conn, kept.id, Role::Assistant, "Noted", 100).unwrap();
+        conn.execute("UPDATE messages SET favorite = 1 WHERE id = ?1", params![favorite.id as i64])
+            .unwrap();
+        conn.execute("UPDATE conversations SET summary = 'old', summarized_through = 1", [])
+            .unwrap();
+        let dropped = create(&conn, None, 100).unwrap();
+        insert_message(&mut conn, dropped.id, Role::User, "Old question", 100).unwrap();
+
+        assert_eq!(prune_before(&mut conn, 500).unwrap(), 2);
+        let stored = find(&conn, kept.id).unwrap();
+        assert_eq!(stored.messages.len(), 1);
+        assert_eq!(stored.summary, None);
+        assert!(find(&conn, dropped.id).is_err());
+    }
+
+    #[test]
+    fn json_history_is_imported_with_its_ids() {
+        let mut conn = conn();
+        let legacy: LegacyStore = serde_json::from_str(
+            r#"{"next_id": 3, "conversations": [{"id": 1, "title": "Trip", "pinned": true,
+                "created_at": 10, "updated_at": 20, "messages": [
+                    {"id": 2, "role": "user", "text": "Book a train", "created_at": 10},
+                    {"id": 3, "role": "assistant", "text": "Done", "created_at": 20, "favorite": true}
+                ]}]}"#,
+        )
+        .unwrap();
+        import(&mut conn, legacy).unwrap();
+
+        let conversation = find(&conn, 1).unwrap();
+        assert!(conversation.pinned);
+        assert_eq!(conversation.messages.len(), 2);
+        assert!(conversation.messages[1].favorite);
+        // New rows continue after the imported ids
+        let message = insert_message(&mut conn, 1, Role::User, "Thanks", 30).unwrap();
+        assert_eq!(message.id, 4);
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, 1);
+    }
+
+    // Messages estimated at `tokens` each
+    fn messages(count: usize, tokens: usize) -> Vec<Message> {
+        (0..count)
+            .map(|i| Message {
+                id: i as u64 + 1,
+                role: if i % 2 == 0 { Role::User } else { Role::Assistant },
+                text: "x".repeat((tokens - 1) * 4),
+                created_at: 0,
+                favorite: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recent_messages_fill_their_share_of_the_budget() {
+        let history = messages(6, 10);
+        assert_eq!(split_for_budget(&history, 40, CompressionLevel::Light), 3);
+        assert_eq!(split_for_budget(&history, 40, CompressionLevel::Balanced), 4);
+        assert_eq!(split_for_budget(&history, 40, CompressionLevel::Aggressive), 5);
+        assert_eq!(split_for_budget(&history, 1000, CompressionLevel::Aggressive), 0);
+    }
+
+    #[test]
+    fn the_newest_message_is_always_kept() {
+        assert_eq!(split_for_budget(&messages(3, 100), 40, CompressionLevel::Light), 2);
+        assert_eq!(split_for_budget(&messages(1, 100), 1, CompressionLevel::Aggressive), 0);
+        assert_eq!(split_for_budget(&[], 40, CompressionLevel::Light), 0);
+    }
+
+    #[test]
+    fn summaries_get_the_rest_of_the_budget_with_a_floor() {
+        assert_eq!(summary_max_words(400, &messages(2, 50)), 225);
+        assert_eq!(summary_max_words(100, &messages(2, 30)), 50);
+        assert_eq!(summary_max_words(40, &messages(1, 100)), 50);
+    }
+}
@@ -0,0 +1,144 @@
+// Small helpers shared by the CalDAV, CardDAV and WebDAV clients
+
+pub struct DavCredentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+pub fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#13;", "\r")
+        .replace("&amp;", "&")
+}
+
+// Text content of every element with the given local name, ignoring namespace prefixes
+pub fn extract_elements(xml: &str, local_name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(lt) = rest.find('<') {
+        rest = &rest[lt + 1..];
+        let Some(gt) = rest.find('>') else { break };
+        let tag = &rest[..gt];
+        rest = &rest[gt + 1..];
+        // Closing and self-closing tags carry no content
+        if tag.starts_with('/') || tag.ends_with('/') {
+            continue;
+        }
+        let name = tag.split(char::is_whitespace).next().unwrap_or_default();
+        let name = name.rsplit(':').next().unwrap_or(name);
+        if name == local_name {
+            if let Some(close) = rest.find("</") {
+                values.push(unescape_xml(&rest[..close]));
+            }
+        }
+    }
+    values
+}
+
+// Raw inner XML of every element with the given local name, including nested children
+pub fn extract_blocks(xml: &str, local_name: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut rest = xml;
+    while let Some(lt) = rest.find('<') {
+        rest = &rest[lt + 1..];
+        let Some(gt) = rest.find('>') else { break };
+        let tag = &rest[..gt];
+        rest = &rest[gt + 1..];
+        if tag.starts_with('/') || tag.ends_with('/') {
+            continue;
+        }
+        let full_name = tag.split(char::is_whitespace).next().unwrap_or_default();
+        if full_name.rsplit(':').next() == Some(local_name) {
+            let close = format!("</{}>", full_name);
+            if let Some(end) = rest.find(&close) {
+                blocks.push(rest[..end].to_string());
+                rest = &rest[end + close.len()..];
+            }
+        }
+    }
+    blocks
+}
+
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Issue a WebDAV method such as REPORT or PROPFIND and return the response body
+pub async fn request(
+    method: &str,
+    url: &str,
+    credentials: &DavCredentials<'_>,
+    depth: &str,
+    body: String,
+) -> Result<String, String> {
+    let method = reqwest::Method::from_bytes(method.as_bytes()).map_err(|e| e.to_string())?;
+    let response = reqwest::Client::new()
+        .request(method.clone(), url)
+        .basic_auth(credentials.username, Some(credentials.password))
+        .header("Depth", depth)
+        .header("Content-Type", "application/xml; charset=utf-8")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("{} {} failed: {}", method, url, response.status()));
+    }
+    response.text().await.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTISTATUS: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:response>
+    <d:href>/cal/a%20b.ics</d:href>
+    <d:propstat><d:prop><c:calendar-data>BEGIN:VCALENDAR&#13;
+SUMMARY:Fish &amp; chips</c:calendar-data><d:getetag/></d:prop></d:propstat>
+  </d:response>
+  <d:response><d:href>/cal/c.ics</d:href></d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn elements_are_found_by_local_name() {
+        assert_eq!(extract_elements(MULTISTATUS, "href"), ["/cal/a%20b.ics", "/cal/c.ics"]);
+        assert_eq!(extract_elements(MULTISTATUS, "calendar-data"), ["BEGIN:VCALENDAR\r\nSUMMARY:Fish & chips"]);
+        assert!(extract_elements(MULTISTATUS, "getetag").is_empty());
+    }
+
+    #[test]
+    fn blocks_keep_their_children() {
+        let responses = extract_blocks(MULTISTATUS, "response");
+        assert_eq!(responses.len(), 2);
+        assert!(responses[0].contains("<d:propstat>"));
+        assert_eq!(extract_elements(&responses[1], "href"), ["/cal/c.ics"]);
+    }
+
+    #[test]
+    fn hrefs_are_percent_decoded() {
+        assert_eq!(percent_decode("/cal/a%20b%C3%A9.ics"), "/cal/a bé.ics");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
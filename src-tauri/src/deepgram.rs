@@ -0,0 +1,320 @@
+// Deepgram live transcription over a WebSocket. The WAV is streamed in short
+// chunks and Deepgram answers as it goes, so results arrive sooner than with
+// a single upload; final results become the transcript's segments, split by
+// speaker when diarization is on.
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+
+use crate::consent::{self, ConsentScope};
+use crate::endpoints::{self, Endpoint};
+use crate::limits;
+use crate::provider_keys;
+use crate::settings::{self, SettingsState};
+use crate::speech::{self, TranscriptSegment, TranscriptWord, TranscriptionResult};
+use crate::stt;
+use crate::usage::{self, Provider};
+
+// About 200 ms of 16 kHz 16-bit mono audio per message
+const CHUNK_BYTES: usize = 6400;
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    is_final: bool,
+    #[serde(default)]
+    start: f64,
+    #[serde(default)]
+    duration: f64,
+    channel: Option<Channel>,
+}
+
+#[derive(Deserialize)]
+struct Channel {
+    alternatives: Vec<Alternative>,
+}
+
+#[derive(Deserialize)]
+struct Alternative {
+    transcript: String,
+    #[serde(default)]
+    words: Vec<Word>,
+}
+
+#[derive(Deserialize)]
+struct Word {
+    word: String,
+    start: f64,
+    end: f64,
+    // With smart formatting, the word with punctuation and casing applied
+    punctuated_word: Option<String>,
+    // Only with diarization
+    speaker: Option<u32>,
+}
+
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn listen_url(model: &str, smart_format: bool, diarize: bool, language: Option<&str>, vocabulary: &[String]) -> String {
+    // The base URL is http(s); the same host serves the WebSocket API
+    let base = endpoints::base_url(Endpoint::Deepgram)
+        .replacen("https://", "wss://", 1)
+        .replacen("http://", "ws://", 1);
+    let mut url = format!(
+        "{}/v1/listen?model={}&smart_format={}&diarize={}&punctuate=true",
+        base,
+        encode(model),
+        smart_format,
+        diarize
+    );
+    if let Some(language) = language {
+        url.push_str(&format!("&language={}", encode(language)));
+    }
+    // Nova-3 takes key terms; earlier models take keywords with an optional boost
+    let param = if model.starts_with("nova-3") { "keyterm" } else { "keywords" };
+    for phrase in vocabulary {
+        url.push_str(&format!("&{}={}", param, encode(phrase)));
+    }
+    url
+}
+
+// A final result becomes one segment, or one per run of words from the same
+// speaker when diarized
+fn to_segments(message: StreamMessage) -> Vec<TranscriptSegment> {
+    let Some(alternative) = message.channel.and_then(|channel| channel.alternatives.into_iter().next()) else {
+        return Vec::new();
+    };
+    let text = alternative.transcript.trim().to_string();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let diarized = alternative.words.iter().any(|word| word.speaker.is_some());
+    let words = alternative.words.into_iter().map(|word| {
+        let speaker = word.speaker;
+        let word = TranscriptWord {
+            word: word.punctuated_word.unwrap_or(word.word),
+            start: word.start,
+            end: word.end,
+        };
+        (word, speaker)
+    });
+    if !diarized {
+        return vec![TranscriptSegment {
+            start: message.start,
+            end: message.start + message.duration,
+            text,
+            words: words.map(|(word, _)| word).collect(),
+            speaker: None,
+        }];
+    }
+
+    let mut segments: Vec<TranscriptSegment> = Vec::new();
+    for (word, speaker) in words {
+        match segments.last_mut() {
+            Some(segment) if segment.speaker == speaker => {
+                segment.end = word.end;
+                segment.text.push(' ');
+                segment.text.push_str(&word.word);
+                segment.words.push(word);
+            }
+            _ => segments.push(TranscriptSegment {
+                start: word.start,
+                end: word.end,
+                text: word.word.clone(),
+                words: vec![word],
+                speaker,
+            }),
+        }
+    }
+    segments
+}
+
+// Transcribe a WAV file; Deepgram reads the format from its header
+pub async fn transcribe(app_handle: &AppHandle, wav: Vec<u8>) -> Result<TranscriptionResult, String> {
+    consent::require(app_handle, ConsentScope::DeepgramAudio)?;
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let config = app_settings.deepgram;
+    let language = stt::language(&app_settings);
+    let url = listen_url(
+        &config.model,
+        config.smart_format,
+        app_settings.stt_diarization,
+        language.as_deref(),
+        &app_settings.stt_vocabulary,
+    );
+    let duration = speech::wav_duration_secs(&wav);
+    let _permit = limits::acquire(Endpoint::Deepgram).await?;
+
+    // Rotate to the next stored key when one is rejected or rate limited
+    let socket = loop {
+        let key = provider_keys::select_key(app_handle, Provider::Deepgram)?;
+        let mut request = url.as_str().into_client_request().map_err(|e| e.to_string())?;
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Token {}", key.secret).parse().map_err(|_| "Invalid Deepgram key".to_string())?,
+        );
+        match tokio_tungstenite::connect_async(request).await {
+            Ok((socket, _)) => {
+                // The upgrade only succeeds once the key has been accepted
+                provider_keys::report(app_handle, &key.id, 200);
+                break socket;
+            }
+            Err(WsError::Http(response)) => {
+                let status = response.status().as_u16();
+                provider_keys::report(app_handle, &key.id, status);
+                if provider_keys::should_rotate(status) && key.id != provider_keys::ENV_KEY_ID {
+                    continue;
+                }
+                return Err(format!("Deepgram error: {}", response.status()));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let (mut sink, mut stream) = socket.split();
+    let send = async {
+        for chunk in wav.chunks(CHUNK_BYTES) {
+            sink.send(WsMessage::Binary(chunk.to_vec())).await?;
+        }
+        // Deepgram flushes its remaining results and then closes the socket
+        sink.send(WsMessage::Text(json!({ "type": "CloseStream" }).to_string())).await
+    };
+    let receive = async {
+        let mut segments = Vec::new();
+        while let Some(message) = stream.next().await {
+            match message {
+                Ok(WsMessage::Text(text)) => {
+                    let Ok(message) = serde_json::from_str::<StreamMessage>(&text) else {
+                        continue;
+                    };
+                    if message.kind == "Results" && message.is_final {
+                        segments.extend(to_segments(message));
+                    }
+                }
+                Ok(WsMessage::Close(_)) => break,
+                Ok(_) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(segments)
+    };
+    let (sent, received) = tokio::join!(send, receive);
+    sent.map_err(|e| e.to_string())?;
+    let segments: Vec<TranscriptSegment> = received?;
+
+    usage::record(app_handle, Provider::Deepgram, &config.model, 0, 0, duration);
+    Ok(TranscriptionResult {
+        text: segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        language,
+        segments: Some(segments),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(word: &str, start: f64, speaker: Option<u32>) -> Word {
+        Word {
+            word: word.to_lowercase(),
+            start,
+            end: start + 0.5,
+            punctuated_word: Some(word.to_string()),
+            speaker,
+        }
+    }
+
+    fn results(transcript: &str, words: Vec<Word>) -> StreamMessage {
+        StreamMessage {
+            kind: "Results".to_string(),
+            is_final: true,
+            start: 2.0,
+            duration: 1.5,
+            channel: Some(Channel {
+                alternatives: vec![Alternative {
+                    transcript: transcript.to_string(),
+                    words,
+                }],
+            }),
+        }
+    }
+
+    #[test]
+    fn listen_url_carries_the_options() {
+        let url = listen_url("nova-2", true, false, Some("en"), &[]);
+        assert!(url.starts_with("wss://") || url.starts_with("ws://"));
+        assert!(url.ends_with("/v1/listen?model=nova-2&smart_format=true&diarize=false&punctuate=true&language=en"));
+        // Values are encoded, so settings can't add query parameters
+        let url = listen_url("nova-2&redact=false", false, false, Some("en&x=1"), &[]);
+        assert!(url.contains("model=nova-2%26redact%3Dfalse&"));
+        assert!(url.ends_with("&language=en%26x%3D1"));
+    }
+
+    #[test]
+    fn vocabulary_parameter_follows_the_model() {
+        let vocabulary = vec!["Plates".to_string(), "Saoirse Ronan".to_string()];
+        let nova3 = listen_url("nova-3", true, false, None, &vocabulary);
+        assert!(nova3.ends_with("&keyterm=Plates&keyterm=Saoirse+Ronan"));
+        let nova2 = listen_url("nova-2", true, false, None, &vocabulary);
+        assert!(nova2.ends_with("&keywords=Plates&keywords=Saoirse+Ronan"));
+    }
+
+    #[test]
+    fn final_results_become_one_segment() {
+        let words = vec![word("Hello", 2.0, None), word("there.", 2.5, None)];
+        let segments = to_segments(results(" Hello there. ", words));
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].text, "Hello there.");
+        assert_eq!((segments[0].start, segments[0].end), (2.0, 3.5));
+        let words: Vec<_> = segments[0].words.iter().map(|word| word.word.as_str()).collect();
+        assert_eq!(words, vec!["Hello", "there."]);
+        assert!(segments[0].speaker.is_none());
+    }
+
+    #[test]
+    fn diarized_results_split_at_speaker_changes() {
+        let words = vec![
+            word("Hi", 2.0, Some(0)),
+            word("there.", 2.5, Some(0)),
+            word("Hello!", 3.0, Some(1)),
+            word("Bye.", 3.5, Some(0)),
+        ];
+        let segments = to_segments(results("Hi there. Hello! Bye.", words));
+        let runs: Vec<_> = segments
+            .iter()
+            .map(|segment| (segment.speaker, segment.text.as_str(), segment.start, segment.end))
+            .collect();
+        assert_eq!(
+            runs,
+            vec![
+                (Some(0), "Hi there.", 2.0, 3.0),
+                (Some(1), "Hello!", 3.0, 3.5),
+                (Some(0), "Bye.", 3.5, 4.0),
+            ]
+        );
+        assert_eq!(segments[0].words.len(), 2);
+        assert!(listen_url("nova-2", true, true, None, &[]).contains("&diarize=true&"));
+    }
+
+    #[test]
+    fn empty_results_are_dropped() {
+        assert!(to_segments(results("  ", Vec::new())).is_empty());
+        let mut message = results("Hello", Vec::new());
+        message.channel = None;
+        assert!(to_segments(message).is_empty());
+        let parsed: StreamMessage = serde_json::from_str(r#"{"type":"Metadata","request_id":"1"}"#).unwrap();
+        assert_eq!(parsed.kind, "Metadata");
+        assert!(!parsed.is_final);
+    }
+}
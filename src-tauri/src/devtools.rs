@@ -0,0 +1,397 @@
+// Developer console: a token protected WebSocket that runs a small whitelist
+// of backend commands and streams tracing output, so the Rust side can be
+// debugged on a phone from a laptop browser. Only available in developer mode.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State as AxumState,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use tauri::{async_runtime::JoinHandle, AppHandle, Manager, State};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+
+use crate::endpoints;
+use crate::engine;
+use crate::keystore::Keystore;
+use crate::rest_api::{self, constant_time_eq, generate_token};
+use crate::settings::{self, SettingsState};
+
+const TOKEN_SECRET: &str = "dev_console_token";
+const DEFAULT_PORT: u16 = 47802;
+// Lines kept for slow consoles before they start missing output
+const LOG_BUFFER: usize = 512;
+
+// Commands the console may run; anything else is refused
+const COMMANDS: &[&str] = &[
+    "ask",
+    "discover_services",
+    "get_notification_digest",
+    "get_performance_report",
+    "get_prefetch_predictions",
+    "get_rest_api_status",
+    "get_settings",
+    "get_usage",
+    "help",
+    "list_push_subscriptions",
+    "list_rules",
+    "list_timers",
+    "simulate_offline",
+    "sync_calendars",
+];
+
+static LOGS: OnceLock<broadcast::Sender<LogLine>> = OnceLock::new();
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LogLine {
+    pub timestamp: i64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct LineVisitor {
+    message: String,
+    fields: Vec<String>,
+}
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+impl LineVisitor {
+    // The message followed by the event's other fields as name=value
+    fn into_message(self) -> String {
+        let mut message = self.message;
+        for field in self.fields {
+            if !message.is_empty() {
+                message.push(' ');
+            }
+            message.push_str(&field);
+        }
+        message
+    }
+}
+
+// Forwards every event to the console broadcast while someone is listening
+struct ConsoleLayer;
+
+impl<S: Subscriber> Layer<S> for ConsoleLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some(sender) = LOGS.get() else {
+            return;
+        };
+        if sender.receiver_count() == 0 {
+            return;
+        }
+        let mut visitor = LineVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        let _ = sender.send(LogLine {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.into_message(),
+        });
+    }
+}
+
+// Install the global tracing subscriber: stdout plus the console broadcast
+pub fn init_logging() {
+    let (sender, _) = broadcast::channel(LOG_BUFFER);
+    let _ = LOGS.set(sender);
+    let _ = tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .with(ConsoleLayer)
+        .try_init();
+}
+
+#[derive(Clone)]
+struct ConsoleContext {
+    app_handle: AppHandle,
+    token: String,
+}
+
+// Browsers can't set headers on a WebSocket, so the token comes in the query
+#[derive(Deserialize)]
+struct ConsoleQuery {
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ConsoleRequest {
+    id: Option<Value>,
+    command: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Serialize, Clone)]
+pub struct DevConsoleStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    pub allow_lan: bool,
+}
+
+#[derive(Default)]
+pub struct DevConsoleServer {
+    task: Option<JoinHandle<()>>,
+    port: Option<u16>,
+    allow_lan: bool,
+}
+
+pub type DevConsoleState = Mutex<DevConsoleServer>;
+
+fn to_json<T: Serialize>(value: T) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
+async fn invoke(app_handle: &AppHandle, command: &str, args: &Value) -> Result<Value, String> {
+    match command {
+        "help" => to_json(COMMANDS),
+        "ask" => {
+            let text = args.get("text").and_then(Value::as_str).ok_or("Missing text".to_string())?;
+            let app_settings = settings::current(&app_handle.state::<SettingsState>());
+            to_json(engine::respond(app_handle, text, &app_settings).await?)
+        }
+        "discover_services" => to_json(crate::discovery::discover_services().await?),
+        "get_notification_digest" => to_json(crate::notifications::get_notification_digest(app_handle.state())?),
+        "get_performance_report" => to_json(crate::profiler::get_performance_report(app_handle.state())?),
+        "get_prefetch_predictions" => to_json(crate::prefetch::get_prefetch_predictions(app_handle.clone())),
+        "get_rest_api_status" => to_json(rest_api::get_rest_api_status(app_handle.state())?),
+        "get_settings" => to_json(settings::get_settings(app_handle.state())?),
+        "get_usage" => to_json(crate::usage::get_usage(
+            args.get("history").and_then(Value::as_bool),
+            app_handle.state(),
+        )?),
+        "list_push_subscriptions" => to_json(crate::push::list_push_subscriptions(app_handle.state())?),
+        "list_rules" => to_json(crate::rules::list_rules(app_handle.state())?),
+        "list_timers" => to_json(crate::timers::list_timers(app_handle.state())?),
+        "simulate_offline" => {
+            let enabled = args.get("enabled").and_then(Value::as_bool).ok_or("Missing enabled".to_string())?;
+            to_json(simulate_offline(enabled, app_handle.clone())?)
+        }
+        "sync_calendars" => to_json(crate::calendar::sync_all(app_handle).await?),
+        _ => Err(format!("{} is not available from the console", command)),
+    }
+}
+
+async fn handle_request(app_handle: &AppHandle, text: &str) -> Value {
+    let request: ConsoleRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return json!({ "type": "result", "id": null, "ok": false, "error": e.to_string() }),
+    };
+    tracing::info!(command = %request.command, "console command");
+    match invoke(app_handle, &request.command, &request.args).await {
+        Ok(value) => json!({ "type": "result", "id": request.id, "ok": true, "value": value }),
+        Err(error) => json!({ "type": "result", "id": request.id, "ok": false, "error": error }),
+    }
+}
+
+async fn next_log(logs: &mut Option<broadcast::Receiver<LogLine>>) -> Value {
+    let Some(receiver) = logs else {
+        return std::future::pending().await;
+    };
+    match receiver.recv().await {
+        Ok(line) => json!({ "type": "log", "line": line }),
+        Err(RecvError::Lagged(skipped)) => json!({ "type": "dropped", "count": skipped }),
+        Err(RecvError::Closed) => std::future::pending().await,
+    }
+}
+
+async fn session(app_handle: AppHandle, socket: WebSocket) {
+    let (mut sink, mut stream) = socket.split();
+    let mut logs = LOGS.get().map(|sender| sender.subscribe());
+    tracing::info!("debug console connected");
+    loop {
+        let reply = tokio::select! {
+            frame = stream.next() => match frame {
+                Some(Ok(Message::Text(text))) => handle_request(&app_handle, &text).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            line = next_log(&mut logs) => line,
+        };
+        if sink.send(Message::Text(reply.to_string())).await.is_err() {
+            break;
+        }
+    }
+    tracing::info!("debug console disconnected");
+}
+
+async fn console(
+    ws: WebSocketUpgrade,
+    Query(query): Query<ConsoleQuery>,
+    AxumState(context): AxumState<ConsoleContext>,
+) -> Response {
+    let provided = query.token.unwrap_or_default();
+    if !constant_time_eq(context.token.as_bytes(), provided.as_bytes()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    ws.on_upgrade(move |socket| session(context.app_handle, socket))
+}
+
+fn load_or_create_token(keystore: &Keystore) -> Result<String, String> {
+    match keystore.get(TOKEN_SECRET)? {
+        Some(token) => Ok(token),
+        None => {
+            let token = generate_token();
+            keystore.set(TOKEN_SECRET, &token)?;
+            Ok(token)
+        }
+    }
+}
+
+fn server_status(server: &DevConsoleServer) -> DevConsoleStatus {
+    DevConsoleStatus {
+        running: server.task.is_some(),
+        port: server.port,
+        allow_lan: server.allow_lan,
+    }
+}
+
+// Stop the console if it is running; called when developer mode is turned off
+pub fn stop(app_handle: &AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<DevConsoleState>();
+    let mut server = state.lock().map_err(|e| e.to_string())?;
+    if let Some(task) = server.task.take() {
+        task.abort();
+    }
+    server.port = None;
+    Ok(())
+}
+
+// Command to start the console. Requires developer mode; bound to localhost unless allow_lan is set.
+#[tauri::command]
+pub async fn start_dev_console(
+    port: Option<u16>,
+    allow_lan: Option<bool>,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, DevConsoleState>,
+) -> Result<DevConsoleStatus, String> {
+    if !settings::current(&app_handle.state::<SettingsState>()).developer_mode {
+        return Err("Developer mode is off".to_string());
+    }
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let allow_lan = allow_lan.unwrap_or(false);
+    let host = if allow_lan { "0.0.0.0" } else { "127.0.0.1" };
+    let listener = tokio::net::TcpListener::bind((host, port))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let token = load_or_create_token(&keystore)?;
+    let mut server = state.lock().map_err(|e| e.to_string())?;
+    if server.task.is_some() {
+        return Err("Debug console is already running".to_string());
+    }
+    let router = Router::new()
+        .route("/console", get(console))
+        .with_state(ConsoleContext { app_handle, token });
+    server.task = Some(tauri::async_runtime::spawn(async move {
+        let _ = axum::serve(listener, router).await;
+    }));
+    server.port = Some(port);
+    server.allow_lan = allow_lan;
+    tracing::info!(port, allow_lan, "debug console started");
+    Ok(server_status(&server))
+}
+
+#[tauri::command]
+pub fn stop_dev_console(app_handle: AppHandle) -> Result<(), String> {
+    stop(&app_handle)
+}
+
+#[tauri::command]
+pub fn get_dev_console_status(state: State<'_, DevConsoleState>) -> Result<DevConsoleStatus, String> {
+    let server = state.lock().map_err(|e| e.to_string())?;
+    Ok(server_status(&server))
+}
+
+// Command to make every hosted provider fail as if the device were offline, and
+// the network rule trigger see a lost connection, so fallbacks such as cached
+// weather, on-device transcription and queued jobs can be exercised. Cleared
+// when developer mode is turned off.
+#[tauri::command]
+pub fn simulate_offline(enabled: bool, app_handle: AppHandle) -> Result<bool, String> {
+    if enabled && !settings::current(&app_handle.state::<SettingsState>()).developer_mode {
+        return Err("Developer mode is off".to_string());
+    }
+    endpoints::set_simulated_offline(enabled);
+    tracing::info!(enabled, "simulated offline mode");
+    Ok(enabled)
+}
+
+// Command to reveal the console token for the connection URL. Requires developer mode.
+#[tauri::command]
+pub fn get_dev_console_token(app_handle: AppHandle, keystore: State<'_, Keystore>) -> Result<String, String> {
+    if !settings::current(&app_handle.state::<SettingsState>()).developer_mode {
+        return Err("Developer mode is off".to_string());
+    }
+    load_or_create_token(&keystore)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_followed_by_their_fields() {
+        let visitor = LineVisitor {
+            message: "started".to_string(),
+            fields: vec!["port=47802".to_string(), "allow_lan=false".to_string()],
+        };
+        assert_eq!(visitor.into_message(), "started port=47802 allow_lan=false");
+        let fields_only = LineVisitor {
+            message: String::new(),
+            fields: vec!["enabled=true".to_string()],
+        };
+        assert_eq!(fields_only.into_message(), "enabled=true");
+    }
+
+    #[test]
+    fn events_reach_connected_consoles() {
+        let mut receiver = LOGS.get_or_init(|| broadcast::channel(LOG_BUFFER).0).subscribe();
+        let subscriber = tracing_subscriber::registry().with(ConsoleLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(target: "plates::test", command = "help", "console command");
+        });
+        let line = std::iter::from_fn(|| receiver.try_recv().ok())
+            .find(|line| line.target == "plates::test")
+            .unwrap();
+        assert_eq!(line.level, "WARN");
+        assert_eq!(line.message, "console command command=\"help\"");
+    }
+
+    #[test]
+    fn requests_default_to_no_arguments() {
+        let request: ConsoleRequest = serde_json::from_str(r#"{"id":7,"command":"help"}"#).unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(request.command, "help");
+        assert!(request.args.is_null());
+        assert!(COMMANDS.contains(&"help"));
+        assert!(COMMANDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
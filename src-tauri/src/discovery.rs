@@ -0,0 +1,204 @@
+// Zeroconf discovery of self-hosted services the assistant can use
+// (Ollama, Home Assistant, SearXNG, faster-whisper) with one-tap setup.
+
+use serde::{Serialize, Deserialize};
+use std::net::IpAddr;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use crate::features::{self, Feature};
+use crate::mdns::{self, ResolvedService};
+use crate::settings::{self, SettingsState};
+
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const OLLAMA_PORT: u16 = 11434;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceKind {
+    Ollama,
+    HomeAssistant,
+    Searxng,
+    Whisper,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiscoveredService {
+    pub kind: ServiceKind,
+    pub name: String,
+    pub url: String,
+}
+
+// Service types to browse; plain HTTP services are classified by name
+const SERVICE_TYPES: &[(&str, Option<ServiceKind>)] = &[
+    ("_home-assistant._tcp.local.", Some(ServiceKind::HomeAssistant)),
+    ("_ollama._tcp.local.", Some(ServiceKind::Ollama)),
+    ("_searxng._tcp.local.", Some(ServiceKind::Searxng)),
+    ("_whisper._tcp.local.", Some(ServiceKind::Whisper)),
+    ("_http._tcp.local.", None),
+];
+
+const NAME_HINTS: &[(&str, ServiceKind)] = &[
+    ("ollama", ServiceKind::Ollama),
+    ("home assistant", ServiceKind::HomeAssistant),
+    ("home-assistant", ServiceKind::HomeAssistant),
+    ("homeassistant", ServiceKind::HomeAssistant),
+    ("searx", ServiceKind::Searxng),
+    ("whisper", ServiceKind::Whisper),
+];
+
+fn classify(service: &ResolvedService) -> Option<ServiceKind> {
+    if service.port == OLLAMA_PORT {
+        return Some(ServiceKind::Ollama);
+    }
+    let name = service.fullname.to_lowercase();
+    NAME_HINTS
+        .iter()
+        .find(|(hint, _)| name.contains(hint))
+        .map(|(_, kind)| *kind)
+}
+
+// The instance name without the service type, e.g. "Kitchen" from "Kitchen._http._tcp.local."
+fn instance_name(fullname: &str, service_type: &str) -> String {
+    fullname
+        .strip_suffix(service_type)
+        .unwrap_or(fullname)
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn service_url(service: &ResolvedService) -> Option<String> {
+    // Home Assistant advertises its own base URL
+    for key in ["base_url", "internal_url"] {
+        if let Some(url) = service.properties.get(key).filter(|url| !url.is_empty()) {
+            return Some(url.trim_end_matches('/').to_string());
+        }
+    }
+    let address = service
+        .addresses
+        .iter()
+        .find(|address| address.is_ipv4())
+        .or_else(|| service.addresses.first())?;
+    Some(match address {
+        IpAddr::V4(ip) => format!("http://{}:{}", ip, service.port),
+        IpAddr::V6(ip) => format!("http://[{}]:{}", ip, service.port),
+    })
+}
+
+// Browse every known service type in parallel
+pub async fn discover() -> Vec<DiscoveredService> {
+    let browses: Vec<_> = SERVICE_TYPES
+        .iter()
+        .map(|(service_type, kind)| {
+            let service_type = service_type.to_string();
+            let kind = *kind;
+            tauri::async_runtime::spawn_blocking(move || {
+                let services = mdns::browse(&service_type, DISCOVERY_TIMEOUT).unwrap_or_default();
+                (service_type, kind, services)
+            })
+        })
+        .collect();
+
+    let mut found: Vec<DiscoveredService> = Vec::new();
+    for browse in browses {
+        let Ok((service_type, kind, services)) = browse.await else {
+            continue;
+        };
+        for service in services {
+            let Some(kind) = kind.or_else(|| classify(&service)) else {
+                continue;
+            };
+            let Some(url) = service_url(&service) else {
+                continue;
+            };
+            // The same server can answer under a specific and a generic type
+            if found.iter().any(|existing| existing.kind == kind && existing.url == url) {
+                continue;
+            }
+            found.push(DiscoveredService {
+                kind,
+                name: instance_name(&service.fullname, &service_type),
+                url,
+            });
+        }
+    }
+    found
+}
+
+#[tauri::command]
+pub async fn discover_services() -> Result<Vec<DiscoveredService>, String> {
+    Ok(discover().await)
+}
+
+// Command to point the matching setting at a discovered service
+#[tauri::command]
+pub fn configure_service(
+    service: DiscoveredService,
+    app_handle: AppHandle,
+    state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let mut updated = settings::current(&state);
+    match service.kind {
+        ServiceKind::Ollama => updated.ollama.base_url = Some(service.url),
+        ServiceKind::HomeAssistant => updated.services.home_assistant_url = Some(service.url),
+        ServiceKind::Searxng => {
+            features::require(&app_handle, Feature::MetaSearch)?;
+            updated.services.searxng_url = Some(service.url);
+        }
+        ServiceKind::Whisper => updated.services.whisper_url = Some(service.url),
+    }
+    settings::update_settings(updated, app_handle, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn service(fullname: &str, port: u16, addresses: Vec<IpAddr>) -> ResolvedService {
+        ResolvedService {
+            fullname: fullname.to_string(),
+            hostname: "host.local.".to_string(),
+            addresses,
+            port,
+            properties: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn http_services_are_classified_by_port_and_name() {
+        let lan = vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20))];
+        assert_eq!(classify(&service("Box._http._tcp.local.", OLLAMA_PORT, lan.clone())), Some(ServiceKind::Ollama));
+        assert_eq!(
+            classify(&service("Home Assistant._http._tcp.local.", 8123, lan.clone())),
+            Some(ServiceKind::HomeAssistant)
+        );
+        assert_eq!(classify(&service("SearXNG._http._tcp.local.", 8080, lan.clone())), Some(ServiceKind::Searxng));
+        assert_eq!(classify(&service("Printer._http._tcp.local.", 80, lan)), None);
+    }
+
+    #[test]
+    fn instance_names_drop_the_service_type() {
+        assert_eq!(instance_name("Kitchen._http._tcp.local.", "_http._tcp.local."), "Kitchen");
+        assert_eq!(instance_name("odd-name", "_http._tcp.local."), "odd-name");
+    }
+
+    #[test]
+    fn urls_prefer_advertised_then_ipv4() {
+        let v6 = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+        let v4 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        let mut home = service("Home._home-assistant._tcp.local.", 8123, vec![v6, v4]);
+        assert_eq!(service_url(&home).as_deref(), Some("http://192.168.1.20:8123"));
+
+        home.properties.insert("base_url".to_string(), String::new());
+        home.properties.insert("internal_url".to_string(), "http://ha.lan:8123/".to_string());
+        assert_eq!(service_url(&home).as_deref(), Some("http://ha.lan:8123"));
+
+        assert_eq!(
+            service_url(&service("Six._http._tcp.local.", 80, vec![v6])).as_deref(),
+            Some("http://[fe80::1]:80")
+        );
+        assert_eq!(service_url(&service("None._http._tcp.local.", 80, Vec::new())), None);
+    }
+}
@@ -0,0 +1,310 @@
+// Short sound cues for the voice pipeline. The tones are synthesized rather
+// than shipped as audio files, so they are always bundled and cost no assets.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::SampleFormat;
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::settings::{self, SettingsState};
+
+// Fade in and out over this many seconds so tones don't click
+const FADE_SECS: f32 = 0.008;
+// How often playback checks whether it should stop early
+const STOP_POLL: Duration = Duration::from_millis(50);
+// Time for the device buffer to empty before the stream is dropped
+const DRAIN: Duration = Duration::from_millis(80);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EarconKind {
+    StartListening,
+    Error,
+    Success,
+}
+
+impl EarconKind {
+    // Notes as (frequency in Hz, duration in seconds); a frequency of 0 is a rest
+    fn notes(self) -> &'static [(f32, f32)] {
+        match self {
+            EarconKind::StartListening => &[(880.0, 0.07), (1318.5, 0.11)],
+            EarconKind::Error => &[(392.0, 0.12), (0.0, 0.04), (311.1, 0.18)],
+            EarconKind::Success => &[(1760.0, 0.06)],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EarconStore {
+    // Reported by the platform when do not disturb or a focus mode is on
+    do_not_disturb: bool,
+}
+
+pub type EarconsState = Mutex<EarconStore>;
+
+// Mono samples for an earcon at the given output rate
+fn synthesize(kind: EarconKind, sample_rate: u32, volume: f32) -> Vec<f32> {
+    let rate = sample_rate as f32;
+    let fade = (FADE_SECS * rate) as usize;
+    let mut samples = Vec::new();
+    for (frequency, duration) in kind.notes() {
+        let len = (duration * rate) as usize;
+        for i in 0..len {
+            if *frequency == 0.0 {
+                samples.push(0.0);
+                continue;
+            }
+            let envelope = (i.min(len - i) as f32 / fade.max(1) as f32).min(1.0);
+            samples.push((2.0 * PI * frequency * i as f32 / rate).sin() * envelope * volume);
+        }
+    }
+    samples
+}
+
+// Play on the default output device; blocks until the cue has finished
+fn play_blocking(kind: EarconKind, volume: f32) -> Result<(), String> {
+    let (device, config) = output_device()?;
+    let sample_rate = config.sample_rate().0;
+    let samples = synthesize(kind, sample_rate, volume);
+    let duration = Duration::from_secs_f32(samples.len() as f32 / sample_rate as f32);
+
+    let source = Arc::new(Mutex::new(samples.into_iter()));
+    let _stream = start_output(&device, config, move || {
+        source.lock().ok().and_then(|mut source| source.next()).unwrap_or(0.0)
+    })?;
+    std::thread::sleep(duration + DRAIN);
+    Ok(())
+}
+
+// Mono samples handed to play_stream while it is already playing
+pub(crate) struct SampleQueue {
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    // Set once no more samples will be pushed
+    finished: bool,
+}
+
+impl SampleQueue {
+    pub(crate) fn new(sample_rate: u32) -> Self {
+        SampleQueue {
+            samples: VecDeque::new(),
+            sample_rate,
+            finished: false,
+        }
+    }
+
+    pub(crate) fn push(&mut self, samples: &[f32]) {
+        self.samples.extend(samples);
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    // The next output sample, `position` of the way from the first queued
+    // sample to the second, then advance `step` input samples
+    fn next_sample(&mut self, position: &mut f64, step: f64) -> f32 {
+        let (Some(&current), next) = (self.samples.front(), self.samples.get(1).copied()) else {
+            return 0.0;
+        };
+        // Hold the last sample until the next chunk arrives rather than jumping
+        let Some(next) = next.or(self.finished.then_some(current)) else {
+            return current;
+        };
+        let sample = current + (next - current) * *position as f32;
+        *position += step;
+        while *position >= 1.0 && !self.samples.is_empty() {
+            self.samples.pop_front();
+            *position -= 1.0;
+        }
+        sample
+    }
+}
+
+// Play samples as they are queued, resampling on the fly. Blocks until the
+// queue is finished and drained or `stop` is set; an empty queue plays silence,
+// as does setting `paused`, which holds the queue where it is.
+pub(crate) fn play_stream(
+    queue: &Arc<Mutex<SampleQueue>>,
+    stop: &AtomicBool,
+    paused: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let (device, config) = output_device()?;
+    let input_rate = queue.lock().map_err(|e| e.to_string())?.sample_rate;
+    // Input samples to advance per output sample, interpolating in between
+    let step = input_rate as f64 / config.sample_rate().0 as f64;
+    let source = queue.clone();
+    let hold = paused.clone();
+    let mut position = 0.0;
+    let _stream = start_output(&device, config, move || {
+        if hold.load(Ordering::Relaxed) {
+            return 0.0;
+        }
+        source
+            .lock()
+            .map(|mut queue| queue.next_sample(&mut position, step))
+            .unwrap_or(0.0)
+    })?;
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let done = queue
+            .lock()
+            .map(|queue| queue.finished && queue.samples.is_empty())
+            .unwrap_or(true);
+        if done {
+            break;
+        }
+        std::thread::sleep(STOP_POLL);
+    }
+    std::thread::sleep(DRAIN);
+    Ok(())
+}
+
+fn output_device() -> Result<(cpal::Device, cpal::SupportedStreamConfig), String> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or("No audio output available".to_string())?;
+    let config = device.default_output_config().map_err(|e| e.to_string())?;
+    Ok((device, config))
+}
+
+// Start an output stream pulling mono samples from `next`
+fn start_output(
+    device: &cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    mut next: impl FnMut() -> f32 + Send + 'static,
+) -> Result<cpal::Stream, String> {
+    let channels = config.channels().max(1) as usize;
+    let stream = match config.sample_format() {
+        // Each mono sample is written to every channel of the frame
+        SampleFormat::F32 => device.build_output_stream(
+            &config.into(),
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                for frame in data.chunks_mut(channels) {
+                    let sample = next();
+                    frame.fill(sample);
+                }
+            },
+            |_| {},
+            None,
+        ),
+        SampleFormat::I16 => device.build_output_stream(
+            &config.into(),
+            move |data: &mut [i16], _: &cpal::OutputCallbackInfo| {
+                for frame in data.chunks_mut(channels) {
+                    let sample = (next() * i16::MAX as f32) as i16;
+                    frame.fill(sample);
+                }
+            },
+            |_| {},
+            None,
+        ),
+        other => return Err(format!("Unsupported sample format: {:?}", other)),
+    }
+    .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+// Play an earcon unless cues are off or do not disturb is active. Never blocks.
+pub fn play(app_handle: &AppHandle, kind: EarconKind) {
+    let earcons = settings::current(&app_handle.state::<SettingsState>()).earcons;
+    if !earcons.enabled || earcons.volume <= 0.0 {
+        return;
+    }
+    let do_not_disturb = app_handle
+        .state::<EarconsState>()
+        .lock()
+        .map(|store| store.do_not_disturb)
+        .unwrap_or(false);
+    if do_not_disturb && earcons.respect_do_not_disturb {
+        return;
+    }
+
+    let volume = earcons.volume.clamp(0.0, 1.0);
+    std::thread::spawn(move || {
+        let _ = play_blocking(kind, volume);
+    });
+}
+
+#[tauri::command]
+pub fn play_earcon(kind: EarconKind, app_handle: AppHandle) {
+    play(&app_handle, kind);
+}
+
+// Command the platform layer calls when do not disturb or a focus mode changes
+#[tauri::command]
+pub fn set_do_not_disturb(active: bool, state: State<'_, EarconsState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.do_not_disturb = active;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut SampleQueue, step: f64, count: usize) -> Vec<f32> {
+        let mut position = 0.0;
+        (0..count).map(|_| queue.next_sample(&mut position, step)).collect()
+    }
+
+    #[test]
+    fn synthesized_cues_have_the_note_lengths() {
+        for kind in [EarconKind::StartListening, EarconKind::Error, EarconKind::Success] {
+            let samples = synthesize(kind, 48_000, 0.5);
+            let expected: usize = kind.notes().iter().map(|(_, secs)| (secs * 48_000.0) as usize).sum();
+            assert_eq!(samples.len(), expected);
+            assert!(samples.iter().all(|sample| sample.abs() <= 0.5));
+            // Faded in, so the first sample doesn't click
+            assert!(samples[0].abs() < 1e-6);
+        }
+        assert!(synthesize(EarconKind::Success, 48_000, 0.0).iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn rests_are_silent() {
+        let samples = synthesize(EarconKind::Error, 16_000, 1.0);
+        let first = (0.12_f32 * 16_000.0) as usize;
+        let rest = (0.04_f32 * 16_000.0) as usize;
+        assert!(samples[first..first + rest].iter().all(|sample| *sample == 0.0));
+        assert!(samples[..first].iter().any(|sample| sample.abs() > 0.9));
+    }
+
+    #[test]
+    fn queue_upsamples_by_interpolating() {
+        let mut queue = SampleQueue::new(24_000);
+        queue.push(&[0.0, 1.0]);
+        queue.finish();
+        assert_eq!(drain(&mut queue, 0.5, 5), vec![0.0, 0.5, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn queue_downsamples_by_skipping() {
+        let mut queue = SampleQueue::new(96_000);
+        queue.push(&[0.0, 0.25, 0.5, 0.75]);
+        queue.finish();
+        assert_eq!(drain(&mut queue, 2.0, 3), vec![0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn queue_holds_until_more_samples_arrive() {
+        let mut queue = SampleQueue::new(48_000);
+        let mut position = 0.0;
+        assert_eq!(queue.next_sample(&mut position, 1.0), 0.0);
+        queue.push(&[0.3]);
+        // Not finished, so the last sample is held without advancing
+        assert_eq!(queue.next_sample(&mut position, 1.0), 0.3);
+        assert_eq!(queue.next_sample(&mut position, 1.0), 0.3);
+        queue.push(&[0.6]);
+        assert_eq!(queue.next_sample(&mut position, 1.0), 0.3);
+        assert_eq!(queue.next_sample(&mut position, 1.0), 0.6);
+    }
+}
@@ -0,0 +1,116 @@
+// Base URLs of the hosted providers. With the test-support feature they can be
+// redirected, so a harness can point every client at local mock servers. In
+// developer mode the providers can also be made to behave as if the device
+// were offline, to exercise each feature's fallback.
+
+use serde::{Serialize, Deserialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "test-support")]
+use std::collections::HashMap;
+#[cfg(feature = "test-support")]
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+    Gemini,
+    OpenAi,
+    OpenWeather,
+    Deepgram,
+    Anthropic,
+}
+
+impl Endpoint {
+    fn default_url(self) -> &'static str {
+        match self {
+            Endpoint::Gemini => "https://generativelanguage.googleapis.com",
+            Endpoint::OpenAi => "https://api.openai.com",
+            Endpoint::OpenWeather => "https://api.openweathermap.org",
+            Endpoint::Deepgram => "https://api.deepgram.com",
+            Endpoint::Anthropic => "https://api.anthropic.com",
+        }
+    }
+}
+
+pub const OFFLINE_ERROR: &str = "No network connection (simulated)";
+
+static SIMULATED_OFFLINE: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "test-support")]
+fn overrides() -> &'static Mutex<HashMap<Endpoint, String>> {
+    static OVERRIDES: OnceLock<Mutex<HashMap<Endpoint, String>>> = OnceLock::new();
+    OVERRIDES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Base URL without a trailing slash
+pub fn base_url(endpoint: Endpoint) -> String {
+    #[cfg(feature = "test-support")]
+    if let Some(url) = overrides().lock().ok().and_then(|urls| urls.get(&endpoint).cloned()) {
+        return url;
+    }
+    endpoint.default_url().to_string()
+}
+
+pub fn simulated_offline() -> bool {
+    SIMULATED_OFFLINE.load(Ordering::Relaxed)
+}
+
+pub fn set_simulated_offline(offline: bool) {
+    SIMULATED_OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+// Fail as an unreachable host would while offline is simulated
+pub fn ensure_online() -> Result<(), String> {
+    if simulated_offline() {
+        return Err(OFFLINE_ERROR.to_string());
+    }
+    Ok(())
+}
+
+// Redirect an endpoint, or restore the real one with None
+#[cfg(feature = "test-support")]
+pub fn set_base_url(endpoint: Endpoint, url: Option<String>) {
+    if let Ok(mut urls) = overrides().lock() {
+        match url {
+            Some(url) => urls.insert(endpoint, url.trim_end_matches('/').to_string()),
+            None => urls.remove(&endpoint),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_urls_are_https_without_a_trailing_slash() {
+        for endpoint in [
+            Endpoint::Gemini,
+            Endpoint::OpenAi,
+            Endpoint::OpenWeather,
+            Endpoint::Deepgram,
+            Endpoint::Anthropic,
+        ] {
+            let url = endpoint.default_url();
+            assert!(url.starts_with("https://"));
+            assert!(!url.ends_with('/'));
+        }
+    }
+
+    #[test]
+    fn endpoints_use_snake_case() {
+        assert_eq!(serde_json::to_string(&Endpoint::OpenWeather).unwrap(), "\"open_weather\"");
+        let endpoint: Endpoint = serde_json::from_str("\"open_ai\"").unwrap();
+        assert_eq!(endpoint, Endpoint::OpenAi);
+    }
+
+    // Anthropic, which the mock provider harness leaves alone
+    #[cfg(feature = "test-support")]
+    #[test]
+    fn base_url_can_be_redirected() {
+        set_base_url(Endpoint::Anthropic, Some("http://127.0.0.1:9000/".to_string()));
+        assert_eq!(base_url(Endpoint::Anthropic), "http://127.0.0.1:9000");
+        set_base_url(Endpoint::Anthropic, None);
+        assert_eq!(base_url(Endpoint::Anthropic), "https://api.anthropic.com");
+    }
+}
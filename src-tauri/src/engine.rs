@@ -0,0 +1,1132 @@
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio_util::sync::CancellationToken;
+
+use crate::accessibility;
+use crate::consent::{self, ConsentScope};
+use crate::conversations::{self, Role};
+use crate::endpoints::{self, Endpoint};
+use crate::faults;
+use crate::features::{self, Feature};
+use crate::formatter::{self, Block};
+use crate::limits;
+use crate::llm::{self, ChatProvider};
+use crate::moderation;
+use crate::notes;
+use crate::persona;
+use crate::prefetch;
+use crate::profiler;
+use crate::provider_keys;
+use crate::redaction::{self, Redactions};
+use crate::routing::{self, Service};
+use crate::rules;
+use crate::settings::{
+    self, AppSettings, EngineProvider, GenerationSettings, PersonaAction, Profile, SafetyThreshold, SettingsState,
+};
+use crate::structured::{self, StructuredOutput, StructuredRequest};
+use crate::tools::{self, Tool};
+use crate::untrusted;
+use crate::usage::{self, DowngradePolicy, Provider};
+
+const GEMINI_MODEL: &str = "gemini-pro";
+// Cheaper model used once the Gemini budget cap is hit
+const GEMINI_FLASH_MODEL: &str = "gemini-1.5-flash";
+// Models that can be picked for Gemini; the first is the default
+pub const GEMINI_MODELS: &[&str] = &[GEMINI_MODEL, GEMINI_FLASH_MODEL, "gemini-1.5-pro"];
+const HARM_CATEGORIES: [&str; 4] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+];
+// Rounds of tool calls before the model must answer
+const MAX_TOOL_ROUNDS: usize = 4;
+// Notes passed to the model as retrieved context
+const MAX_CONTEXT_NOTES: usize = 3;
+const CITATION_SNIPPET_CHARS: usize = 160;
+// Error for a response stopped by cancel_response
+pub const CANCELLED: &str = "Response cancelled";
+
+// Shared by every streamed response in flight; cancelling swaps in a fresh token
+pub type ResponseCancelState = Mutex<CancellationToken>;
+
+#[derive(Deserialize)]
+struct GeminiResponse {
+    // Streamed events may carry only usage metadata
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(rename = "usageMetadata", default)]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+struct UsageMetadata {
+    #[serde(rename = "promptTokenCount", default)]
+    prompt_token_count: u64,
+    #[serde(rename = "candidatesTokenCount", default)]
+    candidates_token_count: u64,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    #[serde(default)]
+    content: Content,
+    #[serde(rename = "groundingMetadata", default)]
+    grounding_metadata: Option<GroundingMetadata>,
+}
+
+#[derive(Deserialize, Default)]
+struct GroundingMetadata {
+    #[serde(rename = "groundingChunks", default)]
+    grounding_chunks: Vec<GroundingChunk>,
+    #[serde(rename = "groundingSupports", default)]
+    grounding_supports: Vec<GroundingSupport>,
+}
+
+#[derive(Deserialize)]
+struct GroundingChunk {
+    web: Option<WebSource>,
+}
+
+#[derive(Deserialize)]
+struct WebSource {
+    uri: String,
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GroundingSupport {
+    segment: Segment,
+    #[serde(rename = "groundingChunkIndices", default)]
+    grounding_chunk_indices: Vec<usize>,
+    #[serde(rename = "confidenceScores", default)]
+    confidence_scores: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct Segment {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+struct Part {
+    text: Option<String>,
+    #[serde(rename = "functionCall", default)]
+    function_call: Option<FunctionCall>,
+}
+
+// A tool the model asked to run, see tools.rs
+#[derive(Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+// Where part of an answer came from: a web page or a local document such as note:<id>
+#[derive(Serialize, Clone, Debug)]
+pub struct Citation {
+    pub source: String,
+    pub title: Option<String>,
+    pub snippet: String,
+    pub confidence: Option<f32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StreamToken {
+    pub conversation_id: Option<u64>,
+    // Text to append to what has arrived so far
+    pub text: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StreamDone {
+    pub conversation_id: Option<u64>,
+    // The final answer after moderation and response transforms, which can
+    // differ from the streamed text; empty when cancelled or failed
+    pub text: String,
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EngineAnswer {
+    pub text: String,
+    pub citations: Vec<Citation>,
+    pub blocks: Vec<Block>,
+}
+
+// Turn Gemini search grounding metadata into citations, one per supported segment and source
+fn grounding_citations(metadata: GroundingMetadata) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    for support in &metadata.grounding_supports {
+        for (position, index) in support.grounding_chunk_indices.iter().enumerate() {
+            let Some(web) = metadata.grounding_chunks.get(*index).and_then(|chunk| chunk.web.as_ref()) else {
+                continue;
+            };
+            if citations.iter().any(|c| c.source == web.uri && c.snippet == support.segment.text) {
+                continue;
+            }
+            citations.push(Citation {
+                source: web.uri.clone(),
+                title: web.title.clone(),
+                snippet: support.segment.text.clone(),
+                confidence: support.confidence_scores.get(position).copied(),
+            });
+        }
+    }
+    // Sources the model consulted without tying them to a segment
+    for web in metadata.grounding_chunks.iter().filter_map(|chunk| chunk.web.as_ref()) {
+        if !citations.iter().any(|c| c.source == web.uri) {
+            citations.push(Citation {
+                source: web.uri.clone(),
+                title: web.title.clone(),
+                snippet: String::new(),
+                confidence: None,
+            });
+        }
+    }
+    citations
+}
+
+// Generation settings as sent; a child profile always gets the strictest safety threshold
+fn effective_generation(settings: &AppSettings) -> GenerationSettings {
+    let mut generation = settings.generation.clone();
+    if settings.profile == Profile::Child {
+        generation.safety_threshold = Some(SafetyThreshold::BlockLowAndAbove);
+    }
+    generation
+}
+
+fn safety_threshold_name(threshold: SafetyThreshold) -> &'static str {
+    match threshold {
+        SafetyThreshold::BlockNone => "BLOCK_NONE",
+        SafetyThreshold::BlockOnlyHigh => "BLOCK_ONLY_HIGH",
+        SafetyThreshold::BlockMediumAndAbove => "BLOCK_MEDIUM_AND_ABOVE",
+        SafetyThreshold::BlockLowAndAbove => "BLOCK_LOW_AND_ABOVE",
+    }
+}
+
+pub struct GeminiClient {
+    model: String,
+    // System instruction sent with every request, such as the persona
+    system: Option<String>,
+    generation: GenerationSettings,
+    // Functions the model may call before answering
+    tools: Vec<&'static dyn Tool>,
+    // Schema JSON answers must follow, see structured.rs
+    response_schema: Option<Value>,
+    client: reqwest::Client,
+    app_handle: AppHandle,
+}
+
+impl GeminiClient {
+    // Client for the model and generation settings picked in settings that
+    // rotates through stored keys, records usage and honours the budget
+    // downgrade policy
+    pub fn for_app(app_handle: &AppHandle) -> Result<Self, String> {
+        let app_settings = settings::current(&app_handle.state::<SettingsState>());
+        Ok(GeminiClient {
+            model: String::new(),
+            system: None,
+            generation: GenerationSettings::default(),
+            tools: Vec::new(),
+            response_schema: None,
+            client: reqwest::Client::new(),
+            app_handle: app_handle.clone(),
+        }
+        .with_settings(&app_settings))
+    }
+
+    // Use the model and generation settings of one request instead of the saved ones
+    pub fn with_settings(mut self, app_settings: &AppSettings) -> Self {
+        self.model = if usage::active_downgrade(&self.app_handle, Provider::Gemini) == DowngradePolicy::Flash {
+            GEMINI_FLASH_MODEL.to_string()
+        } else {
+            app_settings
+                .llm_models
+                .get(llm::Gemini.id())
+                .cloned()
+                .unwrap_or_else(|| GEMINI_MODEL.to_string())
+        };
+        self.generation = effective_generation(app_settings);
+        self
+    }
+
+    pub fn with_system(mut self, system: Option<String>) -> Self {
+        self.system = system;
+        self
+    }
+
+    pub fn with_tools(mut self, tools: Vec<&'static dyn Tool>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    // Answer in JSON following the schema, in Gemini's responseSchema form
+    pub fn with_response_schema(mut self, schema: Value) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+
+    pub async fn generate(&self, prompt: &str) -> Result<String, String> {
+        let answer = self.generate_content(json!([{ "text": prompt }]), None).await?;
+        Ok(answer.text)
+    }
+
+    // Answer with Google Search grounding and return the sources it used
+    pub async fn generate_grounded(&self, prompt: &str) -> Result<EngineAnswer, String> {
+        self.generate_content(
+            json!([{ "text": prompt }]),
+            Some(json!([{ "google_search_retrieval": {} }])),
+        )
+        .await
+    }
+
+    // Send a prompt together with an image, e.g. to read the text in a photo
+    pub async fn generate_with_image(
+        &self,
+        prompt: &str,
+        mime_type: &str,
+        image: &[u8],
+    ) -> Result<String, String> {
+        let answer = self
+            .generate_content(
+                json!([
+                    { "text": prompt },
+                    { "inline_data": { "mime_type": mime_type, "data": BASE64.encode(image) } }
+                ]),
+                None,
+            )
+            .await?;
+        Ok(answer.text)
+    }
+
+    // Request body for the parts, masked when redaction is on
+    fn request_body(&self, mut parts: Value, tools: Option<Value>) -> Result<(Value, Redactions), String> {
+        consent::require(&self.app_handle, ConsentScope::GeminiText)?;
+        let mut redactions = Redactions::default();
+        if redaction::enabled(&self.app_handle, Provider::Gemini) {
+            for part in parts.as_array_mut().into_iter().flatten() {
+                if let Some(text) = part["text"].as_str() {
+                    part["text"] = Value::String(redactions.mask(text));
+                }
+            }
+        }
+        let mut body = json!({
+            "contents": [{ "role": "user", "parts": parts }]
+        });
+        // Search grounding can't be combined with function calling, nor
+        // function calling with a JSON answer
+        if let Some(tools) = tools {
+            body["tools"] = tools;
+        } else if !self.tools.is_empty() && self.response_schema.is_none() {
+            body["tools"] = tools::declarations(&self.tools);
+        }
+        if let Some(system) = &self.system {
+            body["systemInstruction"] = json!({ "parts": [{ "text": system }] });
+        }
+        let mut config = serde_json::Map::new();
+        if let Some(temperature) = self.generation.temperature {
+            config.insert("temperature".to_string(), json!(temperature));
+        }
+        if let Some(top_p) = self.generation.top_p {
+            config.insert("topP".to_string(), json!(top_p));
+        }
+        if let Some(max_output_tokens) = self.generation.max_output_tokens {
+            config.insert("maxOutputTokens".to_string(), json!(max_output_tokens));
+        }
+        if let Some(schema) = &self.response_schema {
+            config.insert("responseMimeType".to_string(), json!("application/json"));
+            config.insert("responseSchema".to_string(), schema.clone());
+        }
+        if !config.is_empty() {
+            body["generationConfig"] = Value::Object(config);
+        }
+        if let Some(threshold) = self.generation.safety_threshold {
+            body["safetySettings"] = HARM_CATEGORIES
+                .iter()
+                .map(|category| json!({ "category": category, "threshold": safety_threshold_name(threshold) }))
+                .collect();
+        }
+        Ok((body, redactions))
+    }
+
+    // POST to a model method such as generateContent, rotating keys as they are rejected
+    async fn post(&self, method: &str, body: &Value, query: &[(&str, &str)]) -> Result<reqwest::Response, String> {
+        // Each rejected key is marked unusable, so this ends once keys run out
+        loop {
+            let key = provider_keys::select_key(&self.app_handle, Provider::Gemini)?;
+            let response = self
+                .client
+                .post(format!(
+                    "{}/v1beta/models/{}:{}",
+                    endpoints::base_url(Endpoint::Gemini),
+                    self.model,
+                    method
+                ))
+                .query(&[("key", key.secret.as_str())])
+                .query(query)
+                .json(body)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let status = response.status().as_u16();
+            provider_keys::report(&self.app_handle, &key.id, status);
+            if provider_keys::should_rotate(status) && key.id != provider_keys::ENV_KEY_ID {
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(format!("Gemini API error: {}", response.status()));
+            }
+            return Ok(response);
+        }
+    }
+
+    // Run the calls from a model turn and add the turn and their results to
+    // the conversation in `body`, ready for the next round
+    async fn answer_tool_calls(
+        &self,
+        body: &mut Value,
+        calls: Vec<FunctionCall>,
+        redactions: &Redactions,
+    ) -> Result<(), String> {
+        let mut turn = Vec::new();
+        let mut results = Vec::new();
+        for call in calls {
+            // Arguments may quote masked text from the prompt
+            let args = serde_json::from_str(&redactions.restore(&call.args.to_string())).unwrap_or(call.args.clone());
+            let result = tools::call(&self.app_handle, &self.tools, &call.name, args).await;
+            // Results carry text from outside services, such as search snippets
+            let response = json!({ "content": untrusted::wrap(&format!("{} tool", call.name), &result.to_string()) });
+            turn.push(json!({ "functionCall": { "name": call.name, "args": call.args } }));
+            results.push(json!({ "functionResponse": { "name": call.name, "response": response } }));
+        }
+        let contents = body["contents"].as_array_mut().ok_or("Malformed Gemini request".to_string())?;
+        contents.push(json!({ "role": "model", "parts": turn }));
+        contents.push(json!({ "role": "user", "parts": results }));
+        Ok(())
+    }
+
+    fn record_usage(&self, metadata: &UsageMetadata) {
+        usage::record(
+            &self.app_handle,
+            Provider::Gemini,
+            &self.model,
+            metadata.prompt_token_count,
+            metadata.candidates_token_count,
+            0.0,
+        );
+    }
+
+    // Answer a prompt through streamGenerateContent, calling `on_text` with
+    // each new piece of the answer; returns the whole answer. Tool calls the
+    // model makes are run and answered between rounds.
+    pub async fn generate_stream(&self, prompt: &str, mut on_text: impl FnMut(&str)) -> Result<String, String> {
+        let (mut body, redactions) = self.request_body(json!([{ "text": prompt }]), None)?;
+        let mut answer = String::new();
+        for _ in 0..=MAX_TOOL_ROUNDS {
+            let mut calls = Vec::new();
+            {
+                let _permit = limits::acquire(Endpoint::Gemini).await?;
+                let mut response = self.post("streamGenerateContent", &body, &[("alt", "sse")]).await?;
+
+                // Raw SSE bytes not yet split into lines, and masked answer text not
+                // yet restored because it may end inside a placeholder
+                let mut buffer = Vec::new();
+                let mut pending = String::new();
+                let mut usage_metadata = None;
+                while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+                    buffer.extend_from_slice(&chunk);
+                    while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=end).collect();
+                        let line = String::from_utf8_lossy(&line);
+                        let Some(data) = line.trim().strip_prefix("data:") else {
+                            continue;
+                        };
+                        let event: GeminiResponse = serde_json::from_str(data.trim()).map_err(|e| e.to_string())?;
+                        if event.usage_metadata.is_some() {
+                            usage_metadata = event.usage_metadata;
+                        }
+                        for part in event.candidates.into_iter().flat_map(|candidate| candidate.content.parts) {
+                            calls.extend(part.function_call);
+                            pending.push_str(part.text.as_deref().unwrap_or_default());
+                        }
+                        let safe = match pending.rfind('[') {
+                            Some(open) if !pending[open..].contains(']') => open,
+                            _ => pending.len(),
+                        };
+                        if safe > 0 {
+                            let text = redactions.restore(&pending[..safe]);
+                            pending.drain(..safe);
+                            on_text(&text);
+                            answer.push_str(&text);
+                        }
+                    }
+                }
+                if !pending.is_empty() {
+                    let text = redactions.restore(&pending);
+                    on_text(&text);
+                    answer.push_str(&text);
+                }
+                if let Some(metadata) = &usage_metadata {
+                    self.record_usage(metadata);
+                }
+            }
+            if calls.is_empty() {
+                return Ok(answer);
+            }
+            self.answer_tool_calls(&mut body, calls, &redactions).await?;
+        }
+        Err("The assistant used too many tools without answering".to_string())
+    }
+
+    async fn generate_content(&self, parts: Value, tools: Option<Value>) -> Result<EngineAnswer, String> {
+        let (mut body, redactions) = self.request_body(parts, tools)?;
+        for _ in 0..=MAX_TOOL_ROUNDS {
+            let gemini: GeminiResponse = {
+                let _permit = limits::acquire(Endpoint::Gemini).await?;
+                let response = self.post("generateContent", &body, &[]).await?;
+                faults::json(Endpoint::Gemini, response).await?
+            };
+            if let Some(metadata) = &gemini.usage_metadata {
+                self.record_usage(metadata);
+            }
+            let candidate = gemini
+                .candidates
+                .into_iter()
+                .next()
+                .ok_or("No response from Gemini".to_string())?;
+            let mut calls = Vec::new();
+            let mut text = String::new();
+            for part in candidate.content.parts {
+                calls.extend(part.function_call);
+                text.push_str(part.text.as_deref().unwrap_or_default());
+            }
+            if !calls.is_empty() {
+                self.answer_tool_calls(&mut body, calls, &redactions).await?;
+                continue;
+            }
+            let citations = candidate
+                .grounding_metadata
+                .map(grounding_citations)
+                .unwrap_or_default();
+            return Ok(EngineAnswer {
+                text: redactions.restore(&text),
+                citations,
+                blocks: Vec::new(),
+            });
+        }
+        Err("The assistant used too many tools without answering".to_string())
+    }
+}
+
+// Apply the response transformation stages enabled in settings
+pub fn transform_response(text: String, settings: &AppSettings) -> String {
+    let mut text = text;
+    if settings.screen_reader_mode {
+        text = accessibility::screen_reader_friendly(&text);
+    }
+    text
+}
+
+// A response marked up for speech (see ssml.rs): paragraphs get a pause
+// between them, *emphasis* and **strong** emphasis are spoken as such, and ISO
+// dates are read as dates
+pub fn spoken_markup(text: &str) -> String {
+    let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let speak_word = |word: &str| {
+        let core = word.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        let tail = escape(&word[core.len()..]);
+        let is_date = core.len() == 10
+            && core.chars().enumerate().all(|(i, c)| match i {
+                4 | 7 => c == '-',
+                _ => c.is_ascii_digit(),
+            });
+        if is_date {
+            return format!("<say-as interpret-as=\"date\" format=\"ymd\">{}</say-as>{}", core, tail);
+        }
+        for (marker, level) in [("**", "strong"), ("*", "moderate")] {
+            if let Some(inner) = core.strip_prefix(marker).and_then(|core| core.strip_suffix(marker)) {
+                if !inner.is_empty() {
+                    return format!("<emphasis level=\"{}\">{}</emphasis>{}", level, escape(inner), tail);
+                }
+            }
+        }
+        escape(word)
+    };
+    let paragraphs: String = text
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", paragraph.split_whitespace().map(speak_word).collect::<Vec<_>>().join(" ")))
+        .collect();
+    format!("<speak>{}</speak>", paragraphs)
+}
+
+// The provider to use once Auto and the budget policy are resolved; a Gemini
+// budget cap with the local policy sends Gemini prompts to Ollama while local
+// models are enabled, and Auto picks the faster healthy one of the two, or the
+// on-device model while offline
+fn effective_provider(app_handle: &AppHandle, settings: &AppSettings) -> &'static dyn ChatProvider {
+    let local = features::is_enabled(app_handle, Feature::LocalLlm);
+    match settings.engine_provider {
+        EngineProvider::Ollama => &llm::Ollama,
+        EngineProvider::OpenAi => &llm::OpenAi,
+        EngineProvider::Anthropic => &llm::Anthropic,
+        EngineProvider::OnDevice => &llm::OnDevice,
+        EngineProvider::Auto if rules::is_offline(app_handle) && on_device_ready(app_handle, settings) => {
+            &llm::OnDevice
+        }
+        _ if local && usage::active_downgrade(app_handle, Provider::Gemini) == DowngradePolicy::Local => &llm::Ollama,
+        EngineProvider::Auto if local => {
+            let fastest = routing::order(app_handle, Service::Llm, &[llm::Gemini.id(), llm::Ollama.id()]);
+            if fastest.first() == Some(&llm::Ollama.id()) {
+                &llm::Ollama
+            } else {
+                &llm::Gemini
+            }
+        }
+        EngineProvider::Gemini | EngineProvider::Auto => &llm::Gemini,
+    }
+}
+
+fn is_gemini(provider: &dyn ChatProvider) -> bool {
+    provider.id() == llm::Gemini.id()
+}
+
+fn on_device_ready(app_handle: &AppHandle, settings: &AppSettings) -> bool {
+    llm::OnDevice.unavailable(app_handle, settings).is_none()
+}
+
+// Complete a prompt with one provider, recording how it went for Auto routing
+async fn complete_with(
+    app_handle: &AppHandle,
+    provider: &dyn ChatProvider,
+    settings: &AppSettings,
+    text: &str,
+    system: Option<&str>,
+    tools: &[&'static dyn Tool],
+) -> Result<String, String> {
+    let started = Instant::now();
+    // Only Gemini is offered tools
+    let response = if is_gemini(provider) && !tools.is_empty() {
+        match GeminiClient::for_app(app_handle) {
+            Ok(client) => {
+                client
+                    .with_settings(settings)
+                    .with_system(system.map(str::to_string))
+                    .with_tools(tools.to_vec())
+                    .generate(text)
+                    .await
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        provider.complete(app_handle, settings, text, system).await
+    };
+    routing::record(app_handle, Service::Llm, provider.id(), started.elapsed(), response.is_ok());
+    response
+}
+
+// Raw completion from the configured provider, without moderation or transforms;
+// used for internal prompts such as conversation summaries
+pub async fn generate(app_handle: &AppHandle, text: &str, settings: &AppSettings) -> Result<String, String> {
+    complete(app_handle, text, settings, None, &[]).await
+}
+
+async fn complete(
+    app_handle: &AppHandle,
+    text: &str,
+    settings: &AppSettings,
+    system: Option<&str>,
+    tools: &[&'static dyn Tool],
+) -> Result<String, String> {
+    let provider = effective_provider(app_handle, settings);
+    match complete_with(app_handle, provider, settings, text, system, tools).await {
+        Ok(response) => Ok(response),
+        // Auto only chose Ollama for speed, so Gemini can still answer
+        Err(e) if provider.local() && settings.engine_provider == EngineProvider::Auto => {
+            complete_with(app_handle, &llm::Gemini, settings, text, system, tools)
+                .await
+                .map_err(|_| e)
+        }
+        // Auto answers on the device when the cloud fails before the connectivity probe notices
+        Err(e) if settings.engine_provider == EngineProvider::Auto && on_device_ready(app_handle, settings) => {
+            complete_with(app_handle, &llm::OnDevice, settings, text, system, tools)
+                .await
+                .map_err(|_| e)
+        }
+        // Fall back to a local Ollama server when the cloud is unreachable
+        Err(e) if !provider.local() && settings.ollama.use_as_fallback => {
+            complete_with(app_handle, &llm::Ollama, settings, text, system, tools)
+                .await
+                .map_err(|_| e)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Answer a prompt as the configured persona, with the tools it allows, and
+// run the response transforms
+pub async fn respond(app_handle: &AppHandle, text: &str, settings: &AppSettings) -> Result<String, String> {
+    let system = persona::system_instruction(&settings.persona);
+    let tools = tools::available(settings);
+    let response = complete(app_handle, text, settings, Some(&system), &tools).await?;
+    let response = moderation::moderate(app_handle, response, settings).await;
+    Ok(transform_response(response, settings))
+}
+
+// Answer with JSON matching `schema`, checked against it before it is returned
+pub async fn respond_json(
+    app_handle: &AppHandle,
+    prompt: &str,
+    settings: &AppSettings,
+    schema: &Value,
+) -> Result<Value, String> {
+    let provider = effective_provider(app_handle, settings);
+    let started = Instant::now();
+    let response = provider.complete_json(app_handle, settings, prompt, None, schema).await;
+    routing::record(app_handle, Service::Llm, provider.id(), started.elapsed(), response.is_ok());
+    let value = structured::parse(&response?)?;
+    structured::validate(&value, schema)?;
+    Ok(value)
+}
+
+// Answer using relevant notes as retrieved context and, on Gemini, web search
+// grounding; every source the answer draws on comes back as a citation
+pub async fn respond_with_sources(
+    app_handle: &AppHandle,
+    text: &str,
+    settings: &AppSettings,
+) -> Result<EngineAnswer, String> {
+    let notes = notes::relevant_notes(app_handle, text, MAX_CONTEXT_NOTES);
+    let prompt = if notes.is_empty() {
+        text.to_string()
+    } else {
+        let context = notes
+            .iter()
+            .enumerate()
+            .map(|(i, (note, _))| format!("[{}] {}\n{}", i + 1, note.title, untrusted::wrap("note", &note.body)))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        format!(
+            "Use these notes from the user where relevant and cite them as [n].\n\n{}\n\nQuestion: {}",
+            context, text
+        )
+    };
+
+    let system = persona::system_instruction(&settings.persona);
+    let provider = effective_provider(app_handle, settings);
+    let mut answer = if is_gemini(provider) {
+        let client = GeminiClient::for_app(app_handle)?
+            .with_settings(settings)
+            .with_system(Some(system));
+        if persona::allows(&settings.persona, PersonaAction::WebSearch) {
+            client.generate_grounded(&prompt).await?
+        } else {
+            client.generate_content(json!([{ "text": prompt }]), None).await?
+        }
+    } else {
+        EngineAnswer {
+            text: complete_with(app_handle, provider, settings, &prompt, Some(&system), &[]).await?,
+            citations: Vec::new(),
+            blocks: Vec::new(),
+        }
+    };
+
+    // Only notes the answer actually refers to are cited
+    for (i, (note, score)) in notes.into_iter().enumerate() {
+        if answer.text.contains(&format!("[{}]", i + 1)) {
+            answer.citations.push(Citation {
+                source: format!("note:{}", note.id),
+                title: Some(note.title),
+                snippet: note.body.chars().take(CITATION_SNIPPET_CHARS).collect(),
+                confidence: Some(score),
+            });
+        }
+    }
+    let text = moderation::moderate(app_handle, answer.text, settings).await;
+    answer.text = transform_response(text, settings);
+    answer.blocks = formatter::format(&answer.text);
+    Ok(answer)
+}
+
+// Answer a prompt, emitting engine://token as the answer arrives. Gemini and
+// the on-device model stream; child profiles wait for moderation, and other
+// providers' answers arrive as one token.
+async fn respond_streaming(
+    app_handle: &AppHandle,
+    text: &str,
+    settings: &AppSettings,
+    conversation_id: Option<u64>,
+) -> Result<String, String> {
+    let emit = |text: &str| {
+        let _ = app_handle.emit("engine://token", StreamToken {
+            conversation_id,
+            text: text.to_string(),
+        });
+    };
+    let provider = effective_provider(app_handle, settings);
+    let on_device = provider.id() == llm::OnDevice.id();
+    let streams = settings.profile != Profile::Child && (is_gemini(provider) || on_device);
+    if !streams {
+        let response = respond(app_handle, text, settings).await?;
+        emit(&response);
+        return Ok(response);
+    }
+
+    let system = persona::system_instruction(&settings.persona);
+    let started = Instant::now();
+    let response = if on_device {
+        // Generation runs on a blocking thread, so it checks for cancellation itself
+        let handle = app_handle.clone();
+        let token = app_handle
+            .state::<ResponseCancelState>()
+            .lock()
+            .map_err(|e| e.to_string())?
+            .clone();
+        let on_text = move |text: &str| {
+            let _ = handle.emit("engine://token", StreamToken {
+                conversation_id,
+                text: text.to_string(),
+            });
+            !token.is_cancelled()
+        };
+        llm::OnDevice.stream(app_handle, settings, text, Some(&system), on_text).await
+    } else {
+        GeminiClient::for_app(app_handle)?
+            .with_settings(settings)
+            .with_system(Some(system))
+            .with_tools(tools::available(settings))
+            .generate_stream(text, emit)
+            .await
+    };
+    routing::record(app_handle, Service::Llm, provider.id(), started.elapsed(), response.is_ok());
+    Ok(transform_response(response?, settings))
+}
+
+// Changes to the generation settings for one prompt; unset fields keep the saved values
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct GenerationOverride {
+    // Model for the prompt's provider, as listed by list_llm_providers
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+    pub safety_threshold: Option<SafetyThreshold>,
+}
+
+fn validate_generation(generation: &GenerationSettings) -> Result<(), String> {
+    if generation.temperature.is_some_and(|temperature| !(0.0..=2.0).contains(&temperature)) {
+        return Err("Temperature must be between 0 and 2".to_string());
+    }
+    if generation.top_p.is_some_and(|top_p| !(top_p > 0.0 && top_p <= 1.0)) {
+        return Err("Top P must be above 0 and at most 1".to_string());
+    }
+    if generation.max_output_tokens == Some(0) {
+        return Err("The answer must be allowed at least one token".to_string());
+    }
+    Ok(())
+}
+
+// Settings for one request, with the chat provider and generation overrides it asked for
+fn request_settings(
+    settings_state: &SettingsState,
+    provider: Option<EngineProvider>,
+    generation: Option<GenerationOverride>,
+) -> Result<AppSettings, String> {
+    let mut settings = settings::current(settings_state);
+    if let Some(provider) = provider {
+        settings.engine_provider = provider;
+    }
+    if let Some(generation) = generation {
+        apply_override(&mut settings, generation)?;
+    }
+    Ok(settings)
+}
+
+fn apply_override(settings: &mut AppSettings, generation: GenerationOverride) -> Result<(), String> {
+    if let Some(model) = generation.model {
+        let chat = llm::for_setting(settings.engine_provider)
+            .ok_or("Choose a provider to pick a model for one prompt".to_string())?;
+        llm::choose_model(settings, chat, model);
+    }
+    settings.generation = GenerationSettings {
+        temperature: generation.temperature.or(settings.generation.temperature),
+        top_p: generation.top_p.or(settings.generation.top_p),
+        max_output_tokens: generation.max_output_tokens.or(settings.generation.max_output_tokens),
+        safety_threshold: generation.safety_threshold.or(settings.generation.safety_threshold),
+    };
+    validate_generation(&settings.generation)
+}
+
+#[tauri::command]
+pub fn get_generation_settings(settings_state: State<'_, SettingsState>) -> GenerationSettings {
+    settings::current(&settings_state).generation
+}
+
+// Command to save the sampling parameters and safety threshold sent with prompts
+#[tauri::command]
+pub fn set_generation_settings(
+    generation: GenerationSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    validate_generation(&generation)?;
+    let mut updated = settings::current(&settings_state);
+    updated.generation = generation;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+// Command to answer a prompt, recording both turns when a conversation is
+// given. With `stream` set the answer is also emitted as it arrives, as
+// engine://token events followed by engine://done. `provider` and
+// `generation` override the chat provider and generation settings for this
+// prompt only.
+#[tauri::command]
+pub async fn process_text_input(
+    text: String,
+    conversation_id: Option<u64>,
+    stream: Option<bool>,
+    provider: Option<EngineProvider>,
+    generation: Option<GenerationOverride>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<String, String> {
+    prefetch::record_activity(&app_handle);
+    let started = Instant::now();
+    let settings = request_settings(&settings_state, provider, generation)?;
+    let prompt = match conversation_id {
+        Some(id) => {
+            conversations::append(&app_handle, id, Role::User, &text)?;
+            conversations::build_prompt(&app_handle, id, &settings).await?
+        }
+        None => text,
+    };
+    let response = if stream.unwrap_or(false) {
+        let token = app_handle
+            .state::<ResponseCancelState>()
+            .lock()
+            .map_err(|e| e.to_string())?
+            .clone();
+        let result = tokio::select! {
+            result = respond_streaming(&app_handle, &prompt, &settings, conversation_id) => result,
+            _ = token.cancelled() => Err(CANCELLED.to_string()),
+        };
+        let _ = app_handle.emit("engine://done", StreamDone {
+            conversation_id,
+            text: result.clone().unwrap_or_default(),
+            cancelled: result.as_ref().is_err_and(|e| e == CANCELLED),
+            error: result.as_ref().err().filter(|e| *e != CANCELLED).cloned(),
+        });
+        result?
+    } else {
+        respond(&app_handle, &prompt, &settings).await?
+    };
+    profiler::record_first(&app_handle, "first_response", started.elapsed());
+    if let Some(id) = conversation_id {
+        conversations::append(&app_handle, id, Role::Assistant, &response)?;
+    }
+    Ok(response)
+}
+
+// Command for answers the UI reads as data: the model replies in JSON
+// constrained to the requested schema (responseSchema on Gemini, json_schema
+// on OpenAI, instructions elsewhere), which is checked and returned typed.
+// `provider` and `generation` work as for process_text_input.
+#[tauri::command]
+pub async fn process_structured_input(
+    text: String,
+    output: StructuredRequest,
+    provider: Option<EngineProvider>,
+    generation: Option<GenerationOverride>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<StructuredOutput, String> {
+    prefetch::record_activity(&app_handle);
+    let settings = request_settings(&settings_state, provider, generation)?;
+    let schema = structured::schema(&output)?;
+    let value = respond_json(&app_handle, &structured::prompt(&output, &text), &settings, &schema).await?;
+    structured::typed(output, value)
+}
+
+// Command to stop every streamed response in flight; each ends with
+// engine://done marked cancelled
+#[tauri::command]
+pub fn cancel_response(state: State<'_, ResponseCancelState>) -> Result<(), String> {
+    let mut token = state.lock().map_err(|e| e.to_string())?;
+    token.cancel();
+    *token = CancellationToken::new();
+    Ok(())
+}
+
+// Command for answers shown with tappable sources
+#[tauri::command]
+pub async fn ask_with_sources(
+    text: String,
+    provider: Option<EngineProvider>,
+    generation: Option<GenerationOverride>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<EngineAnswer, String> {
+    prefetch::record_activity(&app_handle);
+    let started = Instant::now();
+    let settings = request_settings(&settings_state, provider, generation)?;
+    let answer = respond_with_sources(&app_handle, &text, &settings).await?;
+    profiler::record_first(&app_handle, "first_response", started.elapsed());
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_reader_mode_transforms_responses() {
+        let text = "**Done** 👍".to_string();
+        assert_eq!(transform_response(text.clone(), &AppSettings::default()), text);
+        let settings = AppSettings {
+            screen_reader_mode: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            transform_response(text.clone(), &settings),
+            accessibility::screen_reader_friendly(&text)
+        );
+    }
+
+    #[test]
+    fn grounding_becomes_one_citation_per_segment_and_source() {
+        let metadata: GroundingMetadata = serde_json::from_value(json!({
+            "groundingChunks": [
+                { "web": { "uri": "https://a.example", "title": "A" } },
+                { "web": { "uri": "https://b.example" } },
+                {},
+            ],
+            "groundingSupports": [
+                {
+                    "segment": { "text": "The sky is blue" },
+                    "groundingChunkIndices": [0, 2, 5],
+                    "confidenceScores": [0.9, 0.5],
+                },
+                { "segment": { "text": "The sky is blue" }, "groundingChunkIndices": [0] },
+            ],
+        }))
+        .unwrap();
+        let citations = grounding_citations(metadata);
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[0].source, "https://a.example");
+        assert_eq!(citations[0].title.as_deref(), Some("A"));
+        assert_eq!(citations[0].snippet, "The sky is blue");
+        assert_eq!(citations[0].confidence, Some(0.9));
+        // Consulted without supporting a segment
+        assert_eq!(citations[1].source, "https://b.example");
+        assert_eq!((citations[1].snippet.as_str(), citations[1].confidence), ("", None));
+    }
+
+    #[test]
+    fn spoken_markup_marks_paragraphs_emphasis_and_dates() {
+        assert_eq!(
+            spoken_markup("Meet on 2026-03-18.\n\n It is *really* **very** <big> & fun! "),
+            "<speak><p>Meet on <say-as interpret-as=\"date\" format=\"ymd\">2026-03-18</say-as>.</p>\
+             <p>It is <emphasis level=\"moderate\">really</emphasis> <emphasis level=\"strong\">very</emphasis> \
+             &lt;big&gt; &amp; fun!</p></speak>"
+        );
+        assert_eq!(spoken_markup("** 2026-3-18"), "<speak><p>** 2026-3-18</p></speak>");
+        assert_eq!(spoken_markup("\n\n"), "<speak></speak>");
+    }
+
+    #[test]
+    fn generation_settings_are_validated() {
+        assert!(validate_generation(&GenerationSettings::default()).is_ok());
+        let valid = GenerationSettings {
+            temperature: Some(2.0),
+            top_p: Some(1.0),
+            max_output_tokens: Some(1),
+            ..Default::default()
+        };
+        assert!(validate_generation(&valid).is_ok());
+        for invalid in [
+            GenerationSettings {
+                temperature: Some(2.5),
+                ..Default::default()
+            },
+            GenerationSettings {
+                temperature: Some(f32::NAN),
+                ..Default::default()
+            },
+            GenerationSettings {
+                top_p: Some(0.0),
+                ..Default::default()
+            },
+            GenerationSettings {
+                max_output_tokens: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(validate_generation(&invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn overrides_replace_only_what_they_set() {
+        let mut settings = AppSettings {
+            engine_provider: EngineProvider::OpenAi,
+            generation: GenerationSettings {
+                temperature: Some(0.3),
+                max_output_tokens: Some(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let generation = GenerationOverride {
+            model: Some("gpt-4o".to_string()),
+            temperature: Some(0.9),
+            ..Default::default()
+        };
+        apply_override(&mut settings, generation).unwrap();
+        assert_eq!(settings.llm_models.get("openai").map(String::as_str), Some("gpt-4o"));
+        assert_eq!(
+            (settings.generation.temperature, settings.generation.max_output_tokens),
+            (Some(0.9), Some(100))
+        );
+
+        let too_hot = GenerationOverride {
+            temperature: Some(3.0),
+            ..Default::default()
+        };
+        assert!(apply_override(&mut settings, too_hot).is_err());
+        settings.engine_provider = EngineProvider::Auto;
+        let model = GenerationOverride {
+            model: Some("gpt-4o".to_string()),
+            ..Default::default()
+        };
+        assert!(apply_override(&mut settings, model).is_err());
+    }
+
+    #[test]
+    fn child_profiles_get_the_strictest_safety_threshold() {
+        assert_eq!(effective_generation(&AppSettings::default()).safety_threshold, None);
+        let child = AppSettings {
+            profile: Profile::Child,
+            ..Default::default()
+        };
+        let threshold = effective_generation(&child).safety_threshold.unwrap();
+        assert_eq!(threshold, SafetyThreshold::BlockLowAndAbove);
+        assert_eq!(safety_threshold_name(threshold), "BLOCK_LOW_AND_ABOVE");
+    }
+}
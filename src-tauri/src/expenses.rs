@@ -0,0 +1,632 @@
+// Expense quick-capture. A phrase like "I spent 12 dollars on lunch at Joe's"
+// is parsed locally when it can be (amount, currency, category, merchant);
+// anything the local parser can't place goes to the engine for structured
+// output. Receipts parsed by receipts.rs are filed here too, with their line
+// items and a copy of the photo. Expenses are kept in an SQLite database in
+// app data, summarized for the spending widget and exportable as CSV.
+
+use chrono::{Local, TimeZone};
+use rusqlite::{params, Connection};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+use crate::engine;
+use crate::settings::{self, SettingsState};
+use crate::speech;
+use crate::storage;
+use crate::structured;
+use crate::untrusted;
+
+const EXPENSES_DB: &str = "expenses.sqlite3";
+const DEFAULT_CURRENCY: &str = "USD";
+const OTHER_CATEGORY: &str = "other";
+const MAX_TEXT_CHARS: usize = 300;
+const DAY_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_SUMMARY_DAYS: u64 = 7;
+
+// Categories and words that put an expense in them
+const CATEGORIES: &[(&str, &[&str])] = &[
+    ("food", &["lunch", "dinner", "breakfast", "brunch", "coffee", "restaurant", "takeaway", "takeout", "pizza", "snack", "cafe", "meal"]),
+    ("groceries", &["groceries", "grocery", "supermarket", "market"]),
+    ("transport", &["taxi", "uber", "lyft", "bus", "train", "metro", "subway", "fuel", "gas", "petrol", "parking", "ticket"]),
+    ("entertainment", &["movie", "movies", "cinema", "concert", "game", "games", "show", "drinks", "bar"]),
+    ("shopping", &["clothes", "shoes", "gift", "gifts", "book", "books"]),
+    ("bills", &["rent", "electricity", "water", "internet", "phone", "bill", "subscription"]),
+    ("health", &["pharmacy", "medicine", "doctor", "dentist", "gym"]),
+];
+
+// Currency words and symbols, with their ISO codes
+const CURRENCIES: &[(&str, &str)] = &[
+    ("$", "USD"),
+    ("dollar", "USD"),
+    ("buck", "USD"),
+    ("€", "EUR"),
+    ("euro", "EUR"),
+    ("£", "GBP"),
+    ("pound", "GBP"),
+    ("quid", "GBP"),
+    ("¥", "JPY"),
+    ("yen", "JPY"),
+];
+
+// Words that end a merchant name ("at Joe's for lunch")
+const MERCHANT_STOPS: &[&str] = &["for", "on", "today", "yesterday", "this", "last"];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Expense {
+    pub id: i64,
+    pub amount: f64,
+    pub currency: String,
+    pub category: String,
+    pub merchant: Option<String>,
+    // The phrase the expense was captured from
+    pub description: String,
+    pub spent_at: u64,
+    // Photo of the receipt, relative to the app data dir
+    pub receipt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExpenseItem {
+    pub description: String,
+    pub quantity: Option<f64>,
+    pub amount: f64,
+}
+
+// A parsed receipt ready to be filed
+#[derive(Clone, Debug)]
+pub struct NewReceipt {
+    pub amount: f64,
+    pub currency: Option<String>,
+    pub category: Option<String>,
+    pub merchant: Option<String>,
+    pub spent_at: u64,
+    pub items: Vec<ExpenseItem>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpendingTotal {
+    pub currency: String,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub currency: String,
+    pub amount: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpendingSummary {
+    pub days: u64,
+    pub count: u64,
+    // Per currency, largest first; amounts in different currencies aren't added up
+    pub totals: Vec<SpendingTotal>,
+    pub by_category: Vec<CategoryTotal>,
+}
+
+// What a phrase was parsed into, locally or by the engine
+#[derive(Deserialize, Default, Debug)]
+struct ParsedExpense {
+    amount: Option<f64>,
+    currency: Option<String>,
+    category: Option<String>,
+    merchant: Option<String>,
+    #[serde(default)]
+    yesterday: bool,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Opened per call; SQLite handles the locking between them
+fn open(app_handle: &AppHandle) -> Result<Connection, String> {
+    let path = storage::data_file(app_handle, EXPENSES_DB)?;
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    // Write-ahead logging keeps writes atomic if the app is killed mid-way
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         CREATE TABLE IF NOT EXISTS expenses (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             amount_cents INTEGER NOT NULL,
+             currency TEXT NOT NULL,
+             category TEXT NOT NULL,
+             merchant TEXT,
+             description TEXT NOT NULL,
+             spent_at INTEGER NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS expenses_spent_at ON expenses (spent_at);
+         CREATE TABLE IF NOT EXISTS expense_items (
+             expense_id INTEGER NOT NULL,
+             position INTEGER NOT NULL,
+             description TEXT NOT NULL,
+             quantity REAL,
+             amount_cents INTEGER NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS expense_items_expense ON expense_items (expense_id);",
+    )
+    .map_err(|e| e.to_string())?;
+    // Databases from before receipts lack the receipt column
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if version < 1 {
+        conn.execute_batch("ALTER TABLE expenses ADD COLUMN receipt TEXT; PRAGMA user_version = 1;")
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(conn)
+}
+
+fn to_cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn from_cents(cents: i64) -> f64 {
+    cents as f64 / 100.0
+}
+
+pub fn clean_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'').to_lowercase()
+}
+
+pub fn currency_of(word: &str) -> Option<&'static str> {
+    let word = word.strip_suffix('s').unwrap_or(word);
+    CURRENCIES.iter().find(|(name, _)| *name == word).map(|(_, code)| *code)
+}
+
+pub fn category_of(words: &[String]) -> Option<&'static str> {
+    CATEGORIES
+        .iter()
+        .find(|(_, keywords)| words.iter().any(|word| keywords.contains(&word.as_str())))
+        .map(|(category, _)| *category)
+}
+
+// "$12.50", "12 dollars", "1,200 yen"; the currency may come before or after
+fn parse_amount(tokens: &[&str]) -> Option<(f64, Option<&'static str>)> {
+    for (index, token) in tokens.iter().enumerate() {
+        let symbol = token.chars().next().map(|c| c.to_string()).and_then(|c| currency_of(&c));
+        let number = token.trim_start_matches(|c: char| !c.is_ascii_digit()).trim_end_matches(|c: char| !c.is_ascii_digit());
+        let Ok(amount) = number.replace(',', "").parse::<f64>() else {
+            continue;
+        };
+        if amount <= 0.0 {
+            continue;
+        }
+        let word = tokens.get(index + 1).and_then(|next| currency_of(&clean_word(next)));
+        return Some((amount, symbol.or(word)));
+    }
+    None
+}
+
+fn parse_merchant(tokens: &[&str]) -> Option<String> {
+    let at = tokens.iter().position(|token| clean_word(token) == "at")?;
+    let words: Vec<&str> = tokens[at + 1..]
+        .iter()
+        .take_while(|token| !MERCHANT_STOPS.contains(&clean_word(token).as_str()))
+        .take(4)
+        .map(|token| token.trim_matches(|c: char| matches!(c, ',' | '.' | '!' | '?')))
+        .collect();
+    let merchant = words.join(" ");
+    (!merchant.is_empty()).then_some(merchant)
+}
+
+fn parse_locally(text: &str) -> ParsedExpense {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let words: Vec<String> = tokens.iter().map(|token| clean_word(token)).collect();
+    let amount = parse_amount(&tokens);
+    ParsedExpense {
+        amount: amount.map(|(amount, _)| amount),
+        currency: amount.and_then(|(_, currency)| currency).map(str::to_string),
+        category: category_of(&words).map(str::to_string),
+        merchant: parse_merchant(&tokens),
+        yesterday: words.iter().any(|word| word == "yesterday"),
+    }
+}
+
+async fn parse_with_engine(app_handle: &AppHandle, text: &str) -> Result<ParsedExpense, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = format!(
+        "Extract the expense from the phrase below.\n\n{}\n\n\
+         Reply with only a JSON object with \"amount\" (a number, or null if none is given), \
+         \"currency\" (an ISO 4217 code, or null), \"category\" (one of {}, or \"{}\"), \
+         \"merchant\" (a name, or null) and \"yesterday\" (true if it was spent yesterday).",
+        untrusted::wrap("expense", text),
+        categories().join(", "),
+        OTHER_CATEGORY
+    );
+    let response = engine::generate(app_handle, &prompt, &app_settings).await?;
+    let json = structured::extract_json(&response, '{', '}').ok_or("The expense could not be understood".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+// The currency of the last expense, so "12 on lunch" follows earlier entries
+fn last_currency(conn: &Connection) -> Option<String> {
+    conn.query_row("SELECT currency FROM expenses ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+        .ok()
+}
+
+fn read_expense(row: &rusqlite::Row) -> rusqlite::Result<Expense> {
+    Ok(Expense {
+        id: row.get(0)?,
+        amount: from_cents(row.get(1)?),
+        currency: row.get(2)?,
+        category: row.get(3)?,
+        merchant: row.get(4)?,
+        description: row.get(5)?,
+        spent_at: row.get::<_, i64>(6)? as u64,
+        receipt: row.get(7)?,
+    })
+}
+
+// Category names the engine may choose from
+pub fn categories() -> Vec<&'static str> {
+    CATEGORIES.iter().map(|(category, _)| *category).collect()
+}
+
+fn normalize_currency(conn: &Connection, currency: Option<String>) -> String {
+    currency
+        .map(|currency| currency.trim().to_uppercase())
+        .filter(|currency| currency.len() == 3)
+        .or_else(|| last_currency(conn))
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string())
+}
+
+fn normalize_category(category: Option<String>) -> String {
+    category
+        .map(|category| category.trim().to_lowercase())
+        .filter(|category| category == OTHER_CATEGORY || CATEGORIES.iter().any(|(name, _)| name == category))
+        .unwrap_or_else(|| OTHER_CATEGORY.to_string())
+}
+
+fn normalize_merchant(merchant: Option<String>) -> Option<String> {
+    merchant.map(|merchant| merchant.trim().to_string()).filter(|merchant| !merchant.is_empty())
+}
+
+fn expenses_since(app_handle: &AppHandle, since: u64) -> Result<Vec<Expense>, String> {
+    let conn = open(app_handle)?;
+    let mut statement = conn
+        .prepare(
+            "SELECT id, amount_cents, currency, category, merchant, description, spent_at, receipt
+             FROM expenses WHERE spent_at >= ?1 ORDER BY spent_at DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map(params![since as i64], read_expense)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+fn since_days(days: u64) -> u64 {
+    now_secs().saturating_sub(days * DAY_SECS)
+}
+
+// Parse and store an expense phrase
+pub async fn capture(app_handle: &AppHandle, text: &str) -> Result<Expense, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Say what you spent, e.g. \"12 dollars on lunch\"".to_string());
+    }
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(format!("An expense can be at most {} characters", MAX_TEXT_CHARS));
+    }
+    let mut parsed = parse_locally(text);
+    if parsed.amount.is_none() || parsed.category.is_none() {
+        match parse_with_engine(app_handle, text).await {
+            Ok(structured) => {
+                parsed = ParsedExpense {
+                    amount: parsed.amount.or(structured.amount),
+                    currency: parsed.currency.or(structured.currency),
+                    category: parsed.category.or(structured.category),
+                    merchant: parsed.merchant.or(structured.merchant),
+                    yesterday: parsed.yesterday || structured.yesterday,
+                }
+            }
+            // A local amount is enough to keep the expense, filed under "other"
+            Err(e) if parsed.amount.is_some() => tracing::debug!(error = %e, "expense parsing fell back to local"),
+            Err(e) => return Err(e),
+        }
+    }
+    let amount = parsed
+        .amount
+        .filter(|amount| *amount > 0.0)
+        .ok_or("No amount was found in that".to_string())?;
+
+    let conn = open(app_handle)?;
+    let currency = normalize_currency(&conn, parsed.currency);
+    let category = normalize_category(parsed.category);
+    let merchant = normalize_merchant(parsed.merchant);
+    let spent_at = if parsed.yesterday { now_secs() - DAY_SECS } else { now_secs() };
+
+    conn.execute(
+        "INSERT INTO expenses (amount_cents, currency, category, merchant, description, spent_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![to_cents(amount), currency, category, merchant, text, spent_at as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(Expense {
+        id: conn.last_insert_rowid(),
+        amount: from_cents(to_cents(amount)),
+        currency,
+        category,
+        merchant,
+        description: text.to_string(),
+        spent_at,
+        receipt: None,
+    })
+}
+
+// Store a parsed receipt as an expense with its line items, keeping a copy of the photo
+pub fn file_receipt(app_handle: &AppHandle, receipt: NewReceipt, image: &Path) -> Result<Expense, String> {
+    if receipt.amount <= 0.0 {
+        return Err("No total was found on the receipt".to_string());
+    }
+    let mut conn = open(app_handle)?;
+    let currency = normalize_currency(&conn, receipt.currency);
+    let category = normalize_category(receipt.category);
+    let merchant = normalize_merchant(receipt.merchant);
+    let description = match &merchant {
+        Some(merchant) => format!("Receipt from {}", merchant),
+        None => "Receipt".to_string(),
+    };
+
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    transaction
+        .execute(
+            "INSERT INTO expenses (amount_cents, currency, category, merchant, description, spent_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![to_cents(receipt.amount), currency, category, merchant, description, receipt.spent_at as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    let id = transaction.last_insert_rowid();
+    for (position, item) in receipt.items.iter().enumerate() {
+        transaction
+            .execute(
+                "INSERT INTO expense_items (expense_id, position, description, quantity, amount_cents)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, position as i64, item.description, item.quantity, to_cents(item.amount)],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    let extension = image
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("jpg")
+        .to_lowercase();
+    let stored = format!("receipts/{}.{}", id, extension);
+    std::fs::copy(image, storage::data_file(app_handle, &stored)?).map_err(|e| e.to_string())?;
+    transaction
+        .execute("UPDATE expenses SET receipt = ?1 WHERE id = ?2", params![stored, id])
+        .map_err(|e| e.to_string())?;
+    transaction.commit().map_err(|e| e.to_string())?;
+
+    Ok(Expense {
+        id,
+        amount: from_cents(to_cents(receipt.amount)),
+        currency,
+        category,
+        merchant,
+        description,
+        spent_at: receipt.spent_at,
+        receipt: Some(stored),
+    })
+}
+
+// Command to log an expense from a phrase, typed or as the path of a recording
+// to transcribe first
+#[tauri::command]
+pub async fn log_expense(text: Option<String>, recording: Option<String>, app_handle: AppHandle) -> Result<Expense, String> {
+    let text = match (text, recording) {
+        (Some(text), _) => text,
+        (None, Some(path)) => {
+            let wav = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+            speech::transcribe_wav(&app_handle, wav).await?.text
+        }
+        (None, None) => return Err("No expense was given".to_string()),
+    };
+    capture(&app_handle, &text).await
+}
+
+// Command to list expenses from the last `days` days, newest first
+#[tauri::command]
+pub fn list_expenses(days: Option<u64>, app_handle: AppHandle) -> Result<Vec<Expense>, String> {
+    expenses_since(&app_handle, since_days(days.unwrap_or(30)))
+}
+
+// Command to list the line items of an expense filed from a receipt
+#[tauri::command]
+pub fn get_expense_items(id: i64, app_handle: AppHandle) -> Result<Vec<ExpenseItem>, String> {
+    let conn = open(&app_handle)?;
+    let mut statement = conn
+        .prepare(
+            "SELECT description, quantity, amount_cents FROM expense_items
+             WHERE expense_id = ?1 ORDER BY position",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map(params![id], |row| {
+            Ok(ExpenseItem {
+                description: row.get(0)?,
+                quantity: row.get(1)?,
+                amount: from_cents(row.get(2)?),
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_expense(id: i64, app_handle: AppHandle) -> Result<(), String> {
+    let conn = open(&app_handle)?;
+    let receipt: Option<String> = conn
+        .query_row("SELECT receipt FROM expenses WHERE id = ?1", params![id], |row| row.get(0))
+        .map_err(|_| "Expense not found".to_string())?;
+    conn.execute("DELETE FROM expenses WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM expense_items WHERE expense_id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    if let Some(receipt) = receipt {
+        let _ = std::fs::remove_file(storage::data_file(&app_handle, &receipt)?);
+    }
+    Ok(())
+}
+
+pub fn spending_summary(app_handle: &AppHandle, days: u64) -> Result<SpendingSummary, String> {
+    let conn = open(app_handle)?;
+    let since = since_days(days) as i64;
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM expenses WHERE spent_at >= ?1", params![since], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+
+    let mut statement = conn
+        .prepare(
+            "SELECT currency, SUM(amount_cents) AS total FROM expenses WHERE spent_at >= ?1
+             GROUP BY currency ORDER BY total DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let totals = statement
+        .query_map(params![since], |row| {
+            Ok(SpendingTotal {
+                currency: row.get(0)?,
+                amount: from_cents(row.get(1)?),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut statement = conn
+        .prepare(
+            "SELECT category, currency, SUM(amount_cents) AS total FROM expenses WHERE spent_at >= ?1
+             GROUP BY category, currency ORDER BY total DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let by_category = statement
+        .query_map(params![since], |row| {
+            Ok(CategoryTotal {
+                category: row.get(0)?,
+                currency: row.get(1)?,
+                amount: from_cents(row.get(2)?),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(SpendingSummary {
+        days,
+        count: count as u64,
+        totals,
+        by_category,
+    })
+}
+
+// Command for the spending widget: totals over the last `days` days (7 by default)
+#[tauri::command]
+pub fn get_spending_summary(days: Option<u64>, app_handle: AppHandle) -> Result<SpendingSummary, String> {
+    spending_summary(&app_handle, days.unwrap_or(DEFAULT_SUMMARY_DAYS))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Command to write expenses from the last `days` days (all by default) as CSV
+// to a path the user picked
+#[tauri::command]
+pub fn export_expenses(path: String, days: Option<u64>, app_handle: AppHandle) -> Result<usize, String> {
+    let since = days.map(since_days).unwrap_or(0);
+    let expenses = expenses_since(&app_handle, since)?;
+    let mut csv = String::from("date,amount,currency,category,merchant,description\n");
+    for expense in &expenses {
+        let date = Local
+            .timestamp_opt(expense.spent_at as i64, 0)
+            .single()
+            .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{:.2},{},{},{},{}\n",
+            date,
+            expense.amount,
+            expense.currency,
+            csv_field(&expense.category),
+            csv_field(expense.merchant.as_deref().unwrap_or_default()),
+            csv_field(&expense.description)
+        ));
+    }
+    std::fs::write(&path, csv).map_err(|e| e.to_string())?;
+    Ok(expenses.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrases_are_parsed_locally() {
+        let parsed = parse_locally("I spent 12 dollars on lunch at Joe's Diner today");
+        assert_eq!(parsed.amount, Some(12.0));
+        assert_eq!(parsed.currency.as_deref(), Some("USD"));
+        assert_eq!(parsed.category.as_deref(), Some("food"));
+        assert_eq!(parsed.merchant.as_deref(), Some("Joe's Diner"));
+        assert!(!parsed.yesterday);
+
+        let parsed = parse_locally("€8.50 taxi yesterday");
+        assert_eq!((parsed.amount, parsed.currency.as_deref()), (Some(8.5), Some("EUR")));
+        assert_eq!(parsed.category.as_deref(), Some("transport"));
+        assert!(parsed.yesterday);
+
+        let parsed = parse_locally("1,200 yen at the market.");
+        assert_eq!((parsed.amount, parsed.currency.as_deref()), (Some(1200.0), Some("JPY")));
+        assert_eq!(parsed.merchant.as_deref(), Some("the market"));
+    }
+
+    #[test]
+    fn phrases_without_an_amount_are_left_to_the_engine() {
+        let parsed = parse_locally("bought some books");
+        assert_eq!(parsed.amount, None);
+        assert_eq!(parsed.category.as_deref(), Some("shopping"));
+        assert_eq!(parse_locally("0 dollars on coffee").amount, None);
+    }
+
+    #[test]
+    fn engine_answers_are_normalized() {
+        assert_eq!(normalize_category(Some(" Groceries ".to_string())), "groceries");
+        assert_eq!(normalize_category(Some("yachts".to_string())), OTHER_CATEGORY);
+        assert_eq!(normalize_category(None), OTHER_CATEGORY);
+        assert_eq!(normalize_merchant(Some("  ".to_string())), None);
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE expenses (id INTEGER PRIMARY KEY, currency TEXT NOT NULL);")
+            .unwrap();
+        assert_eq!(normalize_currency(&conn, Some("gbp".to_string())), "GBP");
+        assert_eq!(normalize_currency(&conn, Some("pounds".to_string())), DEFAULT_CURRENCY);
+        conn.execute("INSERT INTO expenses (currency) VALUES ('EUR')", []).unwrap();
+        assert_eq!(normalize_currency(&conn, None), "EUR");
+    }
+
+    #[test]
+    fn amounts_are_stored_in_cents() {
+        assert_eq!(to_cents(12.345), 1235);
+        assert_eq!(to_cents(0.1 + 0.2), 30);
+        assert_eq!(from_cents(1999), 19.99);
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("lunch"), "lunch");
+        assert_eq!(csv_field("Joe's, downtown"), "\"Joe's, downtown\"");
+        assert_eq!(csv_field("the \"big\" one"), "\"the \"\"big\"\" one\"");
+    }
+}
@@ -0,0 +1,142 @@
+// Fault injection for the hosted providers, available in developer mode.
+// Requests can be made slow, failed outright or given a truncated JSON body,
+// so the retry and fallback paths in transcription, the engine and the
+// weather widgets can be tried out on a real device.
+
+use serde::de::DeserializeOwned;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::endpoints::Endpoint;
+use crate::settings::{self, SettingsState};
+
+const MAX_LATENCY_MS: u64 = 60_000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FaultConfig {
+    // Share of requests, 0.0 to 1.0, failed before they are sent
+    pub error_rate: f64,
+    // Delay added before every request
+    pub latency_ms: u64,
+    // Share of responses, 0.0 to 1.0, whose body is cut off mid-JSON
+    pub malformed_rate: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct EndpointFaults {
+    pub endpoint: Endpoint,
+    pub faults: FaultConfig,
+}
+
+fn faults() -> &'static Mutex<HashMap<Endpoint, FaultConfig>> {
+    static FAULTS: OnceLock<Mutex<HashMap<Endpoint, FaultConfig>>> = OnceLock::new();
+    FAULTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn config(endpoint: Endpoint) -> Option<FaultConfig> {
+    faults().lock().ok().and_then(|faults| faults.get(&endpoint).cloned())
+}
+
+// Delay or fail a request about to be sent
+pub async fn before_request(endpoint: Endpoint) -> Result<(), String> {
+    let Some(config) = config(endpoint) else {
+        return Ok(());
+    };
+    if config.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(config.latency_ms)).await;
+    }
+    if rand::random::<f64>() < config.error_rate {
+        tracing::debug!(?endpoint, "injected request failure");
+        return Err(format!("Injected fault: {:?} returned 503 Service Unavailable", endpoint));
+    }
+    Ok(())
+}
+
+// Read a JSON response body, truncating it first when a malformed response is due
+pub async fn json<T: DeserializeOwned>(endpoint: Endpoint, response: reqwest::Response) -> Result<T, String> {
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    let malformed = config(endpoint).is_some_and(|config| rand::random::<f64>() < config.malformed_rate);
+    let body = if malformed {
+        tracing::debug!(?endpoint, "injected malformed response");
+        &body[..body.len() / 2]
+    } else {
+        &body[..]
+    };
+    serde_json::from_slice(body).map_err(|e| e.to_string())
+}
+
+fn check(config: &FaultConfig) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&config.error_rate) || !(0.0..=1.0).contains(&config.malformed_rate) {
+        return Err("Rates must be between 0 and 1".to_string());
+    }
+    if config.latency_ms > MAX_LATENCY_MS {
+        return Err(format!("Latency can be at most {} ms", MAX_LATENCY_MS));
+    }
+    Ok(())
+}
+
+pub fn clear() {
+    if let Ok(mut faults) = faults().lock() {
+        faults.clear();
+    }
+}
+
+#[tauri::command]
+pub fn get_fault_injection() -> Result<Vec<EndpointFaults>, String> {
+    let faults = faults().lock().map_err(|e| e.to_string())?;
+    Ok(faults
+        .iter()
+        .map(|(endpoint, config)| EndpointFaults { endpoint: *endpoint, faults: config.clone() })
+        .collect())
+}
+
+// Command to inject faults into one provider's requests, or stop with None.
+// Requires developer mode; everything is cleared when it is turned off.
+#[tauri::command]
+pub fn set_fault_injection(endpoint: Endpoint, config: Option<FaultConfig>, app_handle: AppHandle) -> Result<(), String> {
+    if !settings::current(&app_handle.state::<SettingsState>()).developer_mode {
+        return Err("Developer mode is off".to_string());
+    }
+    if let Some(config) = &config {
+        check(config)?;
+    }
+    let mut faults = faults().lock().map_err(|e| e.to_string())?;
+    match config {
+        Some(config) => faults.insert(endpoint, config),
+        None => faults.remove(&endpoint),
+    };
+    tracing::info!(?endpoint, "fault injection updated");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configs_are_checked() {
+        assert!(check(&FaultConfig { error_rate: 0.5, latency_ms: 200, malformed_rate: 1.0 }).is_ok());
+        assert!(check(&FaultConfig { error_rate: 1.5, ..FaultConfig::default() }).is_err());
+        assert!(check(&FaultConfig { malformed_rate: -0.1, ..FaultConfig::default() }).is_err());
+        assert!(check(&FaultConfig { error_rate: f64::NAN, ..FaultConfig::default() }).is_err());
+        assert!(check(&FaultConfig { latency_ms: MAX_LATENCY_MS + 1, ..FaultConfig::default() }).is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_fail_and_slow_down_as_configured() {
+        // Deepgram isn't called by the other tests, so its faults can't leak into them
+        let config = FaultConfig { error_rate: 1.0, latency_ms: 20, malformed_rate: 0.0 };
+        faults().lock().unwrap().insert(Endpoint::Deepgram, config);
+        let started = std::time::Instant::now();
+        let error = before_request(Endpoint::Deepgram).await.unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(error, "Injected fault: Deepgram returned 503 Service Unavailable");
+
+        faults().lock().unwrap().remove(&Endpoint::Deepgram);
+        assert!(before_request(Endpoint::Deepgram).await.is_ok());
+    }
+}
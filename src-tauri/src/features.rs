@@ -0,0 +1,301 @@
+// Feature flags for experimental subsystems. A flag is resolved from a local
+// override, then the optional remote config (with a percentage rollout), then
+// its built-in default.
+
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::storage;
+
+const FEATURES_FILE: &str = "features.json";
+const REMOTE_REFRESH: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    WakeWord,
+    LocalLlm,
+    MetaSearch,
+}
+
+impl Feature {
+    const ALL: [Feature; 3] = [Feature::WakeWord, Feature::LocalLlm, Feature::MetaSearch];
+
+    fn name(self) -> &'static str {
+        match self {
+            Feature::WakeWord => "wake_word",
+            Feature::LocalLlm => "local_llm",
+            Feature::MetaSearch => "meta_search",
+        }
+    }
+
+    // Local models already ship; the others are still experimental
+    fn default_enabled(self) -> bool {
+        matches!(self, Feature::LocalLlm)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RemoteFlag {
+    pub enabled: bool,
+    // Percentage of installs that get the flag; everyone when unset
+    #[serde(default)]
+    pub rollout: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct RemoteConfig {
+    #[serde(default)]
+    flags: HashMap<Feature, RemoteFlag>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagSource {
+    Default,
+    Remote,
+    Local,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FlagStatus {
+    pub feature: Feature,
+    pub enabled: bool,
+    pub source: FlagSource,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct FeaturesStore {
+    // Stable random id that places this install in rollout buckets
+    install_id: String,
+    overrides: HashMap<Feature, bool>,
+    remote_url: Option<String>,
+    remote: HashMap<Feature, RemoteFlag>,
+    fetched_at: Option<u64>,
+}
+
+pub type FeaturesState = Mutex<FeaturesStore>;
+
+pub fn load(app_handle: &AppHandle) -> FeaturesStore {
+    let mut store: FeaturesStore = storage::load_json(app_handle, FEATURES_FILE);
+    if store.install_id.is_empty() {
+        let bytes: [u8; 16] = rand::thread_rng().gen();
+        store.install_id = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let _ = storage::save_json(app_handle, FEATURES_FILE, &store);
+    }
+    store
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// 0..100, the same for an install and feature on every run
+fn rollout_bucket(install_id: &str, feature: Feature) -> u8 {
+    let digest = Sha256::digest(format!("{}:{}", install_id, feature.name()));
+    (u16::from_be_bytes([digest[0], digest[1]]) % 100) as u8
+}
+
+fn resolve(store: &FeaturesStore, feature: Feature) -> FlagStatus {
+    let (enabled, source) = if let Some(enabled) = store.overrides.get(&feature) {
+        (*enabled, FlagSource::Local)
+    } else if let Some(remote) = store.remote.get(&feature) {
+        let in_rollout = remote
+            .rollout
+            .map(|percent| rollout_bucket(&store.install_id, feature) < percent)
+            .unwrap_or(true);
+        (remote.enabled && in_rollout, FlagSource::Remote)
+    } else {
+        (feature.default_enabled(), FlagSource::Default)
+    };
+    FlagStatus {
+        feature,
+        enabled,
+        source,
+    }
+}
+
+// Whether a subsystem may run; command handlers check this before doing gated work
+pub fn is_enabled(app_handle: &AppHandle, feature: Feature) -> bool {
+    app_handle
+        .state::<FeaturesState>()
+        .lock()
+        .map(|store| resolve(&store, feature).enabled)
+        .unwrap_or(feature.default_enabled())
+}
+
+// Error for handlers of a disabled subsystem
+pub fn require(app_handle: &AppHandle, feature: Feature) -> Result<(), String> {
+    if is_enabled(app_handle, feature) {
+        Ok(())
+    } else {
+        Err(format!("The {} feature is not enabled", feature.name()))
+    }
+}
+
+fn statuses(store: &FeaturesStore) -> Vec<FlagStatus> {
+    Feature::ALL.iter().map(|feature| resolve(store, *feature)).collect()
+}
+
+// Fetch the remote config, if one is set, and emit the resolved flags
+async fn refresh(app_handle: &AppHandle) -> Result<Vec<FlagStatus>, String> {
+    let url = {
+        let state = app_handle.state::<FeaturesState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store.remote_url.clone()
+    };
+    if let Some(url) = url {
+        let config: RemoteConfig = reqwest::get(&url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        let state = app_handle.state::<FeaturesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.remote = config.flags;
+        store.fetched_at = Some(now_secs());
+        storage::save_json(app_handle, FEATURES_FILE, &*store)?;
+    }
+
+    let state = app_handle.state::<FeaturesState>();
+    let flags = statuses(&*state.lock().map_err(|e| e.to_string())?);
+    let _ = app_handle.emit("features://changed", &flags);
+    Ok(flags)
+}
+
+// Refresh the remote config at startup and every few hours after
+pub fn spawn_refresh(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let _ = refresh(&app_handle).await;
+            tokio::time::sleep(REMOTE_REFRESH).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_feature_flags(state: State<'_, FeaturesState>) -> Result<Vec<FlagStatus>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(statuses(&store))
+}
+
+// Command to force a flag on or off on this device; None clears the override
+#[tauri::command]
+pub fn set_feature_override(
+    feature: Feature,
+    enabled: Option<bool>,
+    app_handle: AppHandle,
+    state: State<'_, FeaturesState>,
+) -> Result<FlagStatus, String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    match enabled {
+        Some(enabled) => store.overrides.insert(feature, enabled),
+        None => store.overrides.remove(&feature),
+    };
+    storage::save_json(&app_handle, FEATURES_FILE, &*store)?;
+    let status = resolve(&store, feature);
+    let _ = app_handle.emit("features://changed", statuses(&store));
+    Ok(status)
+}
+
+// Command to set or clear the remote flag config URL and fetch it straight away
+#[tauri::command]
+pub async fn configure_feature_remote(url: Option<String>, app_handle: AppHandle) -> Result<Vec<FlagStatus>, String> {
+    {
+        let state = app_handle.state::<FeaturesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        if url.is_none() {
+            store.remote.clear();
+            store.fetched_at = None;
+        }
+        store.remote_url = url;
+        storage::save_json(&app_handle, FEATURES_FILE, &*store)?;
+    }
+    refresh(&app_handle).await
+}
+
+#[tauri::command]
+pub async fn refresh_feature_flags(app_handle: AppHandle) -> Result<Vec<FlagStatus>, String> {
+    refresh(&app_handle).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(install_id: &str) -> FeaturesStore {
+        FeaturesStore {
+            install_id: install_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn defaults_apply_without_config() {
+        let flags = statuses(&store("install"));
+        assert_eq!(flags.len(), Feature::ALL.len());
+        assert!(flags.iter().all(|flag| flag.source == FlagSource::Default));
+        assert!(resolve(&store("install"), Feature::LocalLlm).enabled);
+        assert!(!resolve(&store("install"), Feature::WakeWord).enabled);
+    }
+
+    #[test]
+    fn local_overrides_win_over_remote() {
+        let mut store = store("install");
+        store.remote.insert(Feature::WakeWord, RemoteFlag { enabled: true, rollout: None });
+        let status = resolve(&store, Feature::WakeWord);
+        assert!(status.enabled);
+        assert_eq!(status.source, FlagSource::Remote);
+
+        store.overrides.insert(Feature::WakeWord, false);
+        let status = resolve(&store, Feature::WakeWord);
+        assert!(!status.enabled);
+        assert_eq!(status.source, FlagSource::Local);
+
+        store.remote.insert(Feature::LocalLlm, RemoteFlag { enabled: false, rollout: None });
+        assert!(!resolve(&store, Feature::LocalLlm).enabled);
+    }
+
+    #[test]
+    fn rollout_buckets_are_stable() {
+        assert_eq!(
+            rollout_bucket("install", Feature::MetaSearch),
+            rollout_bucket("install", Feature::MetaSearch)
+        );
+        let ids: Vec<String> = (0..400).map(|i| format!("install-{}", i)).collect();
+        assert!(ids.iter().all(|id| rollout_bucket(id, Feature::WakeWord) < 100));
+
+        let enabled = |percent| {
+            ids.iter()
+                .filter(|id| {
+                    let mut store = store(id);
+                    store.remote.insert(Feature::WakeWord, RemoteFlag { enabled: true, rollout: Some(percent) });
+                    resolve(&store, Feature::WakeWord).enabled
+                })
+                .count()
+        };
+        assert_eq!(enabled(0), 0);
+        assert_eq!(enabled(100), ids.len());
+        let half = enabled(50);
+        assert!(half > 150 && half < 250);
+    }
+
+    #[test]
+    fn remote_config_parses() {
+        let json = r#"{"flags": {"meta_search": {"enabled": true, "rollout": 25}, "wake_word": {"enabled": false}}}"#;
+        let config: RemoteConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.flags[&Feature::MetaSearch].rollout, Some(25));
+        assert!(!config.flags[&Feature::WakeWord].enabled);
+        assert!(serde_json::from_str::<RemoteConfig>("{}").unwrap().flags.is_empty());
+    }
+}
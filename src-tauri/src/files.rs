@@ -0,0 +1,494 @@
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
+use url::Url;
+
+use crate::dav::{self, DavCredentials};
+use crate::keystore::Keystore;
+use crate::storage;
+
+const MOUNTS_FILE: &str = "webdav_mounts.json";
+const INDEX_FILE: &str = "webdav_index.json";
+// Keep indexing bounded on very large shares
+const MAX_ENTRIES: usize = 10000;
+const MAX_DEPTH: usize = 8;
+
+const PROPFIND_BODY: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+    <d:propfind xmlns:d=\"DAV:\"><d:prop>\
+    <d:getcontentlength/><d:getlastmodified/><d:getcontenttype/><d:resourcetype/>\
+    </d:prop></d:propfind>";
+
+// Words that carry no meaning in "find my tax PDF" style queries
+const STOPWORDS: &[&str] = &[
+    "find", "my", "the", "a", "an", "show", "me", "where", "is", "file", "files", "open", "get", "for",
+];
+
+// Spoken file types and the extensions they stand for
+const TYPE_WORDS: &[(&str, &[&str])] = &[
+    ("pdf", &["pdf"]),
+    ("pdfs", &["pdf"]),
+    ("photo", &["jpg", "jpeg", "png", "heic"]),
+    ("photos", &["jpg", "jpeg", "png", "heic"]),
+    ("picture", &["jpg", "jpeg", "png", "heic"]),
+    ("spreadsheet", &["xlsx", "xls", "ods", "csv"]),
+    ("document", &["doc", "docx", "odt", "pdf", "txt", "md"]),
+    ("presentation", &["pptx", "ppt", "odp", "key"]),
+    ("video", &["mp4", "mov", "mkv"]),
+    ("song", &["mp3", "flac", "m4a", "ogg"]),
+];
+
+// The password lives in the keystore under webdav:<id>
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebDavMount {
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+    pub url: String,
+    pub username: String,
+    #[serde(default)]
+    pub last_indexed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileEntry {
+    pub mount_id: u64,
+    pub href: String,
+    pub name: String,
+    pub path: String,
+    pub size: Option<u64>,
+    pub modified: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FileHit {
+    #[serde(flatten)]
+    pub entry: FileEntry,
+    pub score: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct FilesStore {
+    next_id: u64,
+    mounts: Vec<WebDavMount>,
+    #[serde(skip)]
+    index: Vec<FileEntry>,
+}
+
+pub type FilesState = Mutex<FilesStore>;
+
+pub fn load(app_handle: &AppHandle) -> FilesStore {
+    let mut store: FilesStore = storage::load_json(app_handle, MOUNTS_FILE);
+    store.index = storage::load_json(app_handle, INDEX_FILE);
+    store
+}
+
+fn save_store(app_handle: &AppHandle, store: &FilesStore) -> Result<(), String> {
+    storage::save_json(app_handle, MOUNTS_FILE, store)?;
+    storage::save_json(app_handle, INDEX_FILE, &store.index)
+}
+
+fn password_for(app_handle: &AppHandle, mount_id: u64) -> Result<String, String> {
+    Ok(app_handle
+        .state::<Keystore>()
+        .get(&format!("webdav:{}", mount_id))?
+        .unwrap_or_default())
+}
+
+fn find_mount(app_handle: &AppHandle, mount_id: u64) -> Result<WebDavMount, String> {
+    let state = app_handle.state::<FilesState>();
+    let store = state.lock().map_err(|e| e.to_string())?;
+    store
+        .mounts
+        .iter()
+        .find(|mount| mount.id == mount_id)
+        .cloned()
+        .ok_or("WebDAV share not found".to_string())
+}
+
+// Walk the share breadth-first with Depth: 1 PROPFINDs, which every server allows
+async fn crawl(mount: &WebDavMount, password: &str) -> Result<Vec<FileEntry>, String> {
+    let base = Url::parse(&mount.url).map_err(|e| e.to_string())?;
+    let base_path = dav::percent_decode(base.path());
+    let credentials = DavCredentials {
+        username: &mount.username,
+        password,
+    };
+
+    let mut entries = Vec::new();
+    let mut queue = VecDeque::from([(base.clone(), 0usize)]);
+    while let Some((url, depth)) = queue.pop_front() {
+        let xml = dav::request("PROPFIND", url.as_str(), &credentials, "1", PROPFIND_BODY.to_string()).await?;
+        let (collections, files) = read_listing(mount, &base_path, &url, &xml);
+        if depth + 1 < MAX_DEPTH {
+            queue.extend(collections.into_iter().map(|collection| (collection, depth + 1)));
+        }
+        for file in files {
+            entries.push(file);
+            if entries.len() >= MAX_ENTRIES {
+                return Ok(entries);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+// The collections and files in one PROPFIND listing of `url`. Anything off
+// the share is dropped, so a hostile server can't have the password sent
+// elsewhere.
+fn read_listing(mount: &WebDavMount, base_path: &str, url: &Url, xml: &str) -> (Vec<Url>, Vec<FileEntry>) {
+    let mut collections = Vec::new();
+    let mut files = Vec::new();
+    for response in dav::extract_blocks(xml, "response") {
+        let Some(href) = dav::extract_elements(&response, "href").into_iter().next() else {
+            continue;
+        };
+        let Ok(target) = url.join(&href) else { continue };
+        // The collection itself is listed first
+        if target.path().trim_end_matches('/') == url.path().trim_end_matches('/') {
+            continue;
+        }
+        if !belongs_to(mount, target.as_str()) {
+            continue;
+        }
+
+        let is_dir = response.contains(":collection") || response.contains("<collection");
+        if is_dir {
+            collections.push(target);
+            continue;
+        }
+
+        let path = dav::percent_decode(target.path());
+        let relative = path.strip_prefix(base_path).unwrap_or(&path).to_string();
+        files.push(FileEntry {
+            mount_id: mount.id,
+            href: target.to_string(),
+            name: relative.rsplit('/').next().unwrap_or_default().to_string(),
+            path: relative,
+            size: dav::extract_elements(&response, "getcontentlength")
+                .first()
+                .and_then(|size| size.parse().ok()),
+            modified: dav::extract_elements(&response, "getlastmodified").into_iter().next(),
+            content_type: dav::extract_elements(&response, "getcontenttype").into_iter().next(),
+        });
+    }
+    (collections, files)
+}
+
+pub async fn index_mount(app_handle: &AppHandle, mount_id: u64) -> Result<usize, String> {
+    let mount = find_mount(app_handle, mount_id)?;
+    let password = password_for(app_handle, mount_id)?;
+    let entries = crawl(&mount, &password).await?;
+    let count = entries.len();
+
+    let state = app_handle.state::<FilesState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.index.retain(|entry| entry.mount_id != mount_id);
+    store.index.extend(entries);
+    if let Some(mount) = store.mounts.iter_mut().find(|mount| mount.id == mount_id) {
+        mount.last_indexed = Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        );
+    }
+    save_store(app_handle, &store)?;
+    drop(store);
+
+    let _ = app_handle.emit("files://indexed", count);
+    Ok(count)
+}
+
+// Rank indexed files against a natural language query
+pub fn search(app_handle: &AppHandle, query: &str, limit: usize) -> Vec<FileHit> {
+    let state = app_handle.state::<FilesState>();
+    let Ok(store) = state.lock() else {
+        return Vec::new();
+    };
+    rank(&store.index, query, limit)
+}
+
+fn rank(index: &[FileEntry], query: &str, limit: usize) -> Vec<FileHit> {
+    let query = query.to_lowercase();
+    let mut extensions: Vec<&str> = Vec::new();
+    let mut terms: Vec<String> = Vec::new();
+    for word in query.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        if let Some((_, exts)) = TYPE_WORDS.iter().find(|(type_word, _)| *type_word == word) {
+            extensions.extend_from_slice(exts);
+        } else if !STOPWORDS.contains(&word) {
+            terms.push(word.to_string());
+        }
+    }
+    if terms.is_empty() && extensions.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hits: Vec<FileHit> = index
+        .iter()
+        .filter_map(|entry| {
+            let name = entry.name.to_lowercase();
+            let path = entry.path.to_lowercase();
+            if !extensions.is_empty() {
+                let extension = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or_default();
+                if !extensions.contains(&extension) {
+                    return None;
+                }
+            }
+            let mut score = 1;
+            for term in &terms {
+                if name.contains(term.as_str()) {
+                    score += 3;
+                } else if path.contains(term.as_str()) {
+                    score += 1;
+                } else {
+                    return None;
+                }
+            }
+            Some(FileHit {
+                entry: entry.clone(),
+                score,
+            })
+        })
+        .collect();
+
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    hits.truncate(limit);
+    hits
+}
+
+// Same scheme, host and port as the share, and under its path, so the
+// share's password is never sent anywhere else
+fn belongs_to(mount: &WebDavMount, href: &str) -> bool {
+    let (Ok(base), Ok(target)) = (Url::parse(&mount.url), Url::parse(href)) else {
+        return false;
+    };
+    let base_path = format!("{}/", base.path().trim_end_matches('/'));
+    base.scheme() == target.scheme()
+        && base.host_str() == target.host_str()
+        && base.port_or_known_default() == target.port_or_known_default()
+        && target.path().starts_with(&base_path)
+}
+
+// The file name to save a download under; decoded separators can't climb out
+// of the cache directory
+fn local_name(href: &str) -> String {
+    let name = dav::percent_decode(href.trim_end_matches('/').rsplit('/').next().unwrap_or_default());
+    std::path::Path::new(&name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("download")
+        .to_string()
+}
+
+async fn download(app_handle: &AppHandle, mount_id: u64, href: &str) -> Result<String, String> {
+    let mount = find_mount(app_handle, mount_id)?;
+    if !belongs_to(&mount, href) {
+        return Err("File does not belong to this share".to_string());
+    }
+    let password = password_for(app_handle, mount_id)?;
+
+    let response = reqwest::Client::new()
+        .get(href)
+        .basic_auth(&mount.username, Some(&password))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Download failed: {}", response.status()));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+
+    let name = local_name(href);
+    let dir = app_handle
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join("webdav");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join(name);
+    std::fs::write(&path, &bytes).map_err(|e| e.to_string())?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// Command to add a read-only WebDAV/Nextcloud share and index it
+#[tauri::command]
+pub async fn add_webdav_mount(
+    mount: WebDavMount,
+    password: String,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, FilesState>,
+) -> Result<WebDavMount, String> {
+    Url::parse(&mount.url).map_err(|e| e.to_string())?;
+    let mount = {
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.next_id += 1;
+        let mount = WebDavMount {
+            id: store.next_id,
+            last_indexed: None,
+            ..mount
+        };
+        keystore.set(&format!("webdav:{}", mount.id), &password)?;
+        store.mounts.push(mount.clone());
+        save_store(&app_handle, &store)?;
+        mount
+    };
+
+    index_mount(&app_handle, mount.id).await?;
+    Ok(mount)
+}
+
+#[tauri::command]
+pub fn remove_webdav_mount(
+    id: u64,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, FilesState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.mounts.retain(|mount| mount.id != id);
+    store.index.retain(|entry| entry.mount_id != id);
+    keystore.delete(&format!("webdav:{}", id))?;
+    save_store(&app_handle, &store)
+}
+
+#[tauri::command]
+pub fn list_webdav_mounts(state: State<'_, FilesState>) -> Result<Vec<WebDavMount>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.mounts.clone())
+}
+
+#[tauri::command]
+pub async fn reindex_webdav_mount(id: u64, app_handle: AppHandle) -> Result<usize, String> {
+    index_mount(&app_handle, id).await
+}
+
+#[tauri::command]
+pub fn search_files(query: String, limit: Option<usize>, app_handle: AppHandle) -> Vec<FileHit> {
+    search(&app_handle, &query, limit.unwrap_or(20))
+}
+
+// Command to download a file into the cache and return its local path
+#[tauri::command]
+pub async fn download_file(mount_id: u64, href: String, app_handle: AppHandle) -> Result<String, String> {
+    download(&app_handle, mount_id, &href).await
+}
+
+// Command to download a file and open it with the default app
+#[tauri::command]
+pub async fn open_file(mount_id: u64, href: String, app_handle: AppHandle) -> Result<(), String> {
+    let path = download(&app_handle, mount_id, &href).await?;
+    app_handle
+        .opener()
+        .open_path(path, None::<&str>)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> FileEntry {
+        FileEntry {
+            mount_id: 1,
+            href: format!("https://cloud.example.com/dav/{}", path),
+            name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            path: path.to_string(),
+            size: None,
+            modified: None,
+            content_type: None,
+        }
+    }
+
+    fn mount(url: &str) -> WebDavMount {
+        WebDavMount {
+            id: 1,
+            name: "Cloud".to_string(),
+            url: url.to_string(),
+            username: "me".to_string(),
+            last_indexed: None,
+        }
+    }
+
+    #[test]
+    fn type_words_filter_by_extension() {
+        let index = vec![
+            entry("Taxes/tax-2024.pdf"),
+            entry("Taxes/tax-2024.xlsx"),
+            entry("Receipts/lunch.pdf"),
+        ];
+        let hits = rank(&index, "find my tax PDF", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].entry.name, "tax-2024.pdf");
+
+        let photos = rank(&[entry("Trip/beach.HEIC"), entry("Trip/notes.txt")], "photos", 10);
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].entry.name, "beach.HEIC");
+    }
+
+    #[test]
+    fn name_matches_rank_above_path_matches() {
+        let index = vec![entry("Invoices/march.pdf"), entry("Archive/invoices-2023.zip"), entry("notes.txt")];
+        let hits = rank(&index, "invoices", 10);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].entry.name, "invoices-2023.zip");
+        assert_eq!(hits[0].score, 4);
+        assert_eq!(hits[1].score, 2);
+
+        assert_eq!(rank(&index, "invoices", 1).len(), 1);
+        assert!(rank(&index, "find my file", 10).is_empty());
+        assert!(rank(&index, "invoices budget", 10).is_empty());
+    }
+
+    #[test]
+    fn downloads_stay_on_the_share() {
+        let share = mount("https://cloud.example.com/dav/");
+        assert!(belongs_to(&share, "https://cloud.example.com/dav/Taxes/tax.pdf"));
+        assert!(belongs_to(&share, "https://cloud.example.com:443/dav/tax.pdf"));
+        assert!(!belongs_to(&share, "https://cloud.example.com.evil.net/dav/tax.pdf"));
+        assert!(!belongs_to(&share, "https://cloud.example.com/dav-other/tax.pdf"));
+        assert!(!belongs_to(&share, "http://cloud.example.com/dav/tax.pdf"));
+        assert!(!belongs_to(&share, "https://cloud.example.com:8443/dav/tax.pdf"));
+        assert!(!belongs_to(&share, "not a url"));
+
+        let root = mount("https://cloud.example.com");
+        assert!(belongs_to(&root, "https://cloud.example.com/tax.pdf"));
+        assert!(!belongs_to(&root, "https://cloud.example.com@evil.net/tax.pdf"));
+    }
+
+    #[test]
+    fn listings_stay_on_the_share() {
+        let share = mount("https://cloud.example.com/dav/");
+        let url = Url::parse(&share.url).unwrap();
+        let xml = "<d:multistatus xmlns:d=\"DAV:\">\
+            <d:response><d:href>/dav/</d:href><d:propstat><d:prop>\
+            <d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>\
+            <d:response><d:href>/dav/Taxes/</d:href><d:propstat><d:prop>\
+            <d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>\
+            <d:response><d:href>https://attacker.example/x/</d:href><d:propstat><d:prop>\
+            <d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>\
+            <d:response><d:href>/other/secret.pdf</d:href><d:propstat><d:prop>\
+            <d:getcontentlength>10</d:getcontentlength></d:prop></d:propstat></d:response>\
+            <d:response><d:href>/dav/My%20Taxes.pdf</d:href><d:propstat><d:prop>\
+            <d:getcontentlength>2048</d:getcontentlength></d:prop></d:propstat></d:response>\
+            </d:multistatus>";
+        let (collections, files) = read_listing(&share, "/dav/", &url, xml);
+        assert_eq!(
+            collections.iter().map(Url::as_str).collect::<Vec<_>>(),
+            ["https://cloud.example.com/dav/Taxes/"]
+        );
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].name.as_str(), files[0].size), ("My Taxes.pdf", Some(2048)));
+    }
+
+    #[test]
+    fn local_names_cannot_escape_the_cache() {
+        assert_eq!(local_name("https://cloud.example.com/dav/My%20Taxes.pdf"), "My Taxes.pdf");
+        assert_eq!(local_name("https://cloud.example.com/dav/..%2F..%2Fsecrets"), "secrets");
+        assert_eq!(local_name("https://cloud.example.com/dav/%2E%2E"), "download");
+        assert_eq!(local_name("https://cloud.example.com/dav/"), "dav");
+    }
+}
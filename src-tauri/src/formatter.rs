@@ -0,0 +1,275 @@
+// Structured response formatting: parses engine markdown into typed blocks
+// so the frontend can render rich answers without its own markdown parser.
+
+use serde::Serialize;
+
+const ACTION_SCHEME: &str = "plates://";
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Link {
+    pub text: String,
+    pub url: String,
+}
+
+// A run of inline text with markdown emphasis removed and links pulled out
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Inline {
+    pub text: String,
+    pub links: Vec<Link>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Heading { level: u8, content: Inline },
+    Paragraph { content: Inline },
+    Code { language: Option<String>, code: String },
+    List { ordered: bool, items: Vec<Inline> },
+    // A plates:// link, rendered as a button that runs the intent
+    Action { label: String, uri: String },
+}
+
+fn is_url_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ')' | ']' | '<' | '>' | '"')
+}
+
+// Parse inline markdown; plates:// links are returned separately as actions
+fn parse_inline(text: &str, actions: &mut Vec<Link>) -> Inline {
+    let mut out = String::with_capacity(text.len());
+    let mut links = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        // [label](url)
+        if let Some(after) = rest.strip_prefix('[') {
+            if let Some(close) = after.find("](") {
+                if let Some(end) = after[close + 2..].find(')') {
+                    let label = &after[..close];
+                    let url = &after[close + 2..close + 2 + end];
+                    let label = parse_inline(label, actions).text;
+                    if url.starts_with(ACTION_SCHEME) {
+                        actions.push(Link { text: label, url: url.to_string() });
+                    } else {
+                        out.push_str(&label);
+                        links.push(Link { text: label, url: url.to_string() });
+                    }
+                    rest = &after[close + 2 + end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        // Bare URLs
+        if rest.starts_with("https://") || rest.starts_with("http://") {
+            let end = rest.find(|c: char| !is_url_char(c)).unwrap_or(rest.len());
+            // Trailing punctuation belongs to the sentence, not the URL
+            let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            out.push_str(url);
+            links.push(Link { text: url.to_string(), url: url.to_string() });
+            rest = &rest[url.len()..];
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix("**").or_else(|| rest.strip_prefix("__")) {
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix('`') {
+            rest = after;
+            continue;
+        }
+
+        let mut chars = rest.chars();
+        if let Some(c) = chars.next() {
+            out.push(c);
+        }
+        rest = chars.as_str();
+    }
+
+    Inline { text: out.trim().to_string(), links }
+}
+
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) {
+        line[level..].strip_prefix(' ').map(|text| (level as u8, text))
+    } else {
+        None
+    }
+}
+
+// The item text and whether the list is ordered, for "- item", "* item" or "1. item"
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(text) = line.strip_prefix(bullet) {
+            return Some((false, text));
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..]
+        .strip_prefix(". ")
+        .or_else(|| line[digits..].strip_prefix(") "))
+        .map(|text| (true, text))
+}
+
+struct Builder {
+    blocks: Vec<Block>,
+    paragraph: Vec<String>,
+    list: Option<(bool, Vec<Inline>)>,
+    actions: Vec<Link>,
+}
+
+impl Builder {
+    fn flush(&mut self) {
+        if !self.paragraph.is_empty() {
+            let content = parse_inline(&self.paragraph.join(" "), &mut self.actions);
+            self.paragraph.clear();
+            if !content.text.is_empty() {
+                self.blocks.push(Block::Paragraph { content });
+            }
+        }
+        if let Some((ordered, items)) = self.list.take() {
+            self.blocks.push(Block::List { ordered, items });
+        }
+        // Actions follow the block that mentioned them
+        for action in self.actions.drain(..) {
+            self.blocks.push(Block::Action { label: action.text, uri: action.url });
+        }
+    }
+}
+
+pub fn format(text: &str) -> Vec<Block> {
+    let mut builder = Builder {
+        blocks: Vec::new(),
+        paragraph: Vec::new(),
+        list: None,
+        actions: Vec::new(),
+    };
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        if let Some(info) = trimmed.strip_prefix("```") {
+            builder.flush();
+            let language = Some(info.trim().to_string()).filter(|language| !language.is_empty());
+            let mut code = Vec::new();
+            for line in lines.by_ref() {
+                if line.trim_start().starts_with("```") {
+                    break;
+                }
+                code.push(line);
+            }
+            builder.blocks.push(Block::Code { language, code: code.join("\n") });
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            builder.flush();
+            continue;
+        }
+
+        if let Some((level, text)) = heading(trimmed) {
+            builder.flush();
+            let content = parse_inline(text, &mut builder.actions);
+            builder.blocks.push(Block::Heading { level, content });
+            builder.flush();
+            continue;
+        }
+
+        if let Some((ordered, text)) = list_item(trimmed) {
+            let item = parse_inline(text, &mut builder.actions);
+            match &mut builder.list {
+                Some((current, items)) if *current == ordered && builder.paragraph.is_empty() => items.push(item),
+                _ => {
+                    builder.flush();
+                    builder.list = Some((ordered, vec![item]));
+                }
+            }
+            continue;
+        }
+
+        // An indented line continues the previous list item
+        if line.starts_with(' ') && builder.paragraph.is_empty() {
+            if let Some((_, items)) = &mut builder.list {
+                if let Some(last) = items.last_mut() {
+                    let more = parse_inline(trimmed, &mut builder.actions);
+                    last.text = format!("{} {}", last.text, more.text);
+                    last.links.extend(more.links);
+                    continue;
+                }
+            }
+        }
+
+        if builder.list.is_some() {
+            builder.flush();
+        }
+        builder.paragraph.push(trimmed.to_string());
+    }
+
+    builder.flush();
+    builder.blocks
+}
+
+// Command to format arbitrary text, e.g. a stored conversation message
+#[tauri::command]
+pub fn format_response(text: String) -> Vec<Block> {
+    format(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(text: &str) -> Inline {
+        Inline { text: text.to_string(), links: Vec::new() }
+    }
+
+    #[test]
+    fn markdown_becomes_blocks() {
+        let blocks = format("# Plan\n\nSome **bold** and `code`\ncontinued.\n\n1. First\n2. Second\n   more\n- Loose\n\n```rust\nfn main() {}\n```");
+        assert_eq!(
+            blocks,
+            [
+                Block::Heading { level: 1, content: inline("Plan") },
+                Block::Paragraph { content: inline("Some bold and code continued.") },
+                Block::List { ordered: true, items: vec![inline("First"), inline("Second more")] },
+                Block::List { ordered: false, items: vec![inline("Loose")] },
+                Block::Code { language: Some("rust".to_string()), code: "fn main() {}".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn links_are_pulled_out_of_text() {
+        let blocks = format("See [the docs](https://example.com/docs) or https://example.org/a.");
+        let [Block::Paragraph { content }] = &blocks[..] else {
+            panic!("expected one paragraph, got {:?}", blocks);
+        };
+        assert_eq!(content.text, "See the docs or https://example.org/a.");
+        let urls: Vec<&str> = content.links.iter().map(|link| link.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/docs", "https://example.org/a"]);
+    }
+
+    #[test]
+    fn plates_links_become_actions_after_their_block() {
+        let blocks = format("Done. [Start a timer](plates://timer?minutes=5)\n\nNext");
+        assert_eq!(
+            blocks,
+            [
+                Block::Paragraph { content: inline("Done.") },
+                Block::Action { label: "Start a timer".to_string(), uri: "plates://timer?minutes=5".to_string() },
+                Block::Paragraph { content: inline("Next") },
+            ]
+        );
+    }
+
+    #[test]
+    fn text_that_only_looks_like_markup_is_kept() {
+        assert_eq!(format("#hashtag")[0], Block::Paragraph { content: inline("#hashtag") });
+        assert_eq!(format("[not a link]")[0], Block::Paragraph { content: inline("[not a link]") });
+    }
+}
@@ -0,0 +1,467 @@
+// Conversation with Gemini over a single Live API WebSocket. The connection
+// stays open across turns, audio is streamed as it is captured, and Gemini's
+// own voice detection interrupts a reply as soon as the user talks over it.
+// Recordings can also be transcribed over a short-lived session, which is how
+// the Gemini Live transcription backend works.
+//
+// Events: live://input (what Gemini heard), live://delta (reply text so far),
+// live://turn-complete, live://interrupted and live://closed.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, Manager, State};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+
+use crate::consent::{self, ConsentScope};
+use crate::endpoints::{self, Endpoint};
+use crate::persona;
+use crate::provider_keys;
+use crate::settings::{self, AppSettings, SettingsState};
+use crate::speech::{self, TranscriptionResult, WHISPER_SAMPLE_RATE};
+use crate::stt;
+use crate::usage::{self, Provider};
+
+const LIVE_MODEL: &str = "gemini-2.0-flash-live-001";
+const LIVE_PATH: &str = "/ws/google.ai.generativelanguage.v1beta.GenerativeService.BidiGenerateContent";
+// Audio per message when streaming a recording, one second
+const TRANSCRIBE_CHUNK_SAMPLES: usize = WHISPER_SAMPLE_RATE as usize;
+// How long to wait for the transcript once the whole recording is sent
+const TRANSCRIBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerMessage {
+    setup_complete: Option<serde_json::Value>,
+    server_content: Option<ServerContent>,
+    usage_metadata: Option<LiveUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerContent {
+    model_turn: Option<ModelTurn>,
+    input_transcription: Option<Transcription>,
+    #[serde(default)]
+    turn_complete: bool,
+    #[serde(default)]
+    interrupted: bool,
+}
+
+#[derive(Deserialize)]
+struct ModelTurn {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+struct Part {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Transcription {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LiveUsage {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    response_token_count: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LiveTurn {
+    pub session: u64,
+    pub text: String,
+}
+
+struct LiveSession {
+    id: u64,
+    outgoing: mpsc::UnboundedSender<WsMessage>,
+    task: JoinHandle<()>,
+    // Audio sent so far, for usage accounting
+    audio_samples: u64,
+}
+
+#[derive(Default)]
+pub struct LiveSessions {
+    session: Option<LiveSession>,
+    last_id: u64,
+}
+
+pub type LiveState = Mutex<LiveSessions>;
+
+fn live_url(key: &str) -> String {
+    // The base URL is http(s); the same host serves the WebSocket API
+    let base = endpoints::base_url(Endpoint::Gemini)
+        .replacen("https://", "wss://", 1)
+        .replacen("http://", "ws://", 1);
+    format!("{}{}?key={}", base, LIVE_PATH, key)
+}
+
+// Gemini sends its JSON messages as binary frames, but accept text as well
+fn parse(message: &WsMessage) -> Option<ServerMessage> {
+    match message {
+        WsMessage::Text(text) => serde_json::from_str(text).ok(),
+        WsMessage::Binary(bytes) => serde_json::from_slice(bytes).ok(),
+        _ => None,
+    }
+}
+
+fn vocabulary_instruction(vocabulary: &[String]) -> Option<String> {
+    (!vocabulary.is_empty()).then(|| {
+        format!(
+            "The user may say these names and terms; spell them as written here: {}.",
+            vocabulary.join(", ")
+        )
+    })
+}
+
+// The session setup: the persona as the system instruction, plus the user's
+// vocabulary so names and jargon are heard and spelled the way they expect
+fn setup_message(app_settings: &AppSettings) -> Value {
+    let mut instruction = persona::system_instruction(&app_settings.persona);
+    if let Some(vocabulary) = vocabulary_instruction(&app_settings.stt_vocabulary) {
+        instruction.push_str(&format!("\n\n{}", vocabulary));
+    }
+    setup(instruction)
+}
+
+// Setup for transcribing a recording; only the input transcription is used
+fn transcription_setup(app_settings: &AppSettings, language: Option<&str>) -> Value {
+    let mut instruction = "You are listening to a recording so it can be transcribed. Do not reply to it.".to_string();
+    if let Some(language) = language {
+        instruction.push_str(&format!(" The speech is in the language with code {}.", language));
+    }
+    if let Some(vocabulary) = vocabulary_instruction(&app_settings.stt_vocabulary) {
+        instruction.push_str(&format!(" {}", vocabulary));
+    }
+    setup(instruction)
+}
+
+fn setup(instruction: String) -> Value {
+    json!({
+        "setup": {
+            "model": format!("models/{}", LIVE_MODEL),
+            "generationConfig": { "responseModalities": ["TEXT"] },
+            "systemInstruction": { "parts": [{ "text": instruction }] },
+            "inputAudioTranscription": {},
+        }
+    })
+}
+
+type LiveSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+fn audio_message(pcm: &[i16]) -> WsMessage {
+    let bytes: Vec<u8> = pcm.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+    let message = json!({
+        "realtimeInput": {
+            "audio": {
+                "data": BASE64.encode(bytes),
+                "mimeType": format!("audio/pcm;rate={}", WHISPER_SAMPLE_RATE),
+            }
+        }
+    });
+    WsMessage::Text(message.to_string())
+}
+
+fn audio_end_message() -> WsMessage {
+    WsMessage::Text(json!({ "realtimeInput": { "audioStreamEnd": true } }).to_string())
+}
+
+fn record_usage(app_handle: &AppHandle, usage: &LiveUsage) {
+    usage::record(
+        app_handle,
+        Provider::Gemini,
+        LIVE_MODEL,
+        usage.prompt_token_count,
+        usage.response_token_count,
+        0.0,
+    );
+}
+
+// Connect and send the setup message, rotating to the next stored key when one is rejected
+async fn connect(app_handle: &AppHandle, setup: Value) -> Result<LiveSocket, String> {
+    endpoints::ensure_online()?;
+    let mut socket = loop {
+        let key = provider_keys::select_key(app_handle, Provider::Gemini)?;
+        match tokio_tungstenite::connect_async(live_url(&key.secret)).await {
+            Ok((socket, _)) => {
+                provider_keys::report(app_handle, &key.id, 200);
+                break socket;
+            }
+            Err(WsError::Http(response)) => {
+                let status = response.status().as_u16();
+                provider_keys::report(app_handle, &key.id, status);
+                if provider_keys::should_rotate(status) && key.id != provider_keys::ENV_KEY_ID {
+                    continue;
+                }
+                return Err(format!("Gemini Live error: {}", response.status()));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    socket
+        .send(WsMessage::Text(setup.to_string()))
+        .await
+        .map_err(|e| e.to_string())?;
+    // Audio sent before the setup is acknowledged would be dropped
+    while let Some(message) = socket.next().await {
+        match message.map_err(|e| e.to_string())? {
+            WsMessage::Close(frame) => {
+                let reason = frame.map(|frame| frame.reason.to_string()).unwrap_or_default();
+                return Err(format!("Gemini Live closed the session: {}", reason));
+            }
+            message => {
+                if parse(&message).is_some_and(|message| message.setup_complete.is_some()) {
+                    return Ok(socket);
+                }
+            }
+        }
+    }
+    Err("Gemini Live closed the session".to_string())
+}
+
+// Relay audio out and replies in until either side closes
+fn spawn_session(
+    app_handle: &AppHandle,
+    id: u64,
+    socket: LiveSocket,
+    mut outgoing: mpsc::UnboundedReceiver<WsMessage>,
+) -> JoinHandle<()> {
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let (mut sink, mut stream) = socket.split();
+        let mut reply = String::new();
+        loop {
+            tokio::select! {
+                message = outgoing.recv() => {
+                    let Some(message) = message else { break };
+                    if sink.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                message = stream.next() => {
+                    match message {
+                        Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(message)) => {
+                            if let Some(message) = parse(&message) {
+                                handle_message(&handle, id, message, &mut reply);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let _ = sink.close().await;
+        close(&handle, id);
+    })
+}
+
+fn handle_message(app_handle: &AppHandle, id: u64, message: ServerMessage, reply: &mut String) {
+    if let Some(usage) = &message.usage_metadata {
+        record_usage(app_handle, usage);
+    }
+    let Some(content) = message.server_content else {
+        return;
+    };
+    if let Some(input) = content.input_transcription.filter(|input| !input.text.is_empty()) {
+        let _ = app_handle.emit("live://input", LiveTurn { session: id, text: input.text });
+    }
+    // The user spoke over the reply; whatever was said of it so far is discarded
+    if content.interrupted {
+        reply.clear();
+        let _ = app_handle.emit("live://interrupted", id);
+    }
+    let text: String = content
+        .model_turn
+        .into_iter()
+        .flat_map(|turn| turn.parts)
+        .filter_map(|part| part.text)
+        .collect();
+    if !text.is_empty() {
+        reply.push_str(&text);
+        let _ = app_handle.emit("live://delta", LiveTurn { session: id, text: reply.clone() });
+    }
+    if content.turn_complete && !reply.is_empty() {
+        let _ = app_handle.emit("live://turn-complete", LiveTurn { session: id, text: std::mem::take(reply) });
+    }
+}
+
+// Forget a session whose connection has ended and record the audio it streamed
+fn close(app_handle: &AppHandle, id: u64) {
+    let session = {
+        let state = app_handle.state::<LiveState>();
+        let Ok(mut sessions) = state.lock() else {
+            return;
+        };
+        if sessions.session.as_ref().map(|session| session.id) != Some(id) {
+            return;
+        }
+        sessions.session.take()
+    };
+    if let Some(session) = session {
+        let seconds = session.audio_samples as f64 / WHISPER_SAMPLE_RATE as f64;
+        usage::record(app_handle, Provider::Gemini, LIVE_MODEL, 0, 0, seconds);
+        let _ = app_handle.emit("live://closed", id);
+    }
+}
+
+// Transcribe a mono 16-bit WAV over its own Live session: stream the audio,
+// end the stream and collect Gemini's input transcription until its turn ends
+pub async fn transcribe(app_handle: &AppHandle, wav: Vec<u8>) -> Result<TranscriptionResult, String> {
+    consent::require(app_handle, ConsentScope::GeminiAudio)?;
+    let (samples, sample_rate) = speech::decode_wav(&wav)?;
+    let pcm = speech::to_whisper_pcm(&samples, sample_rate, 1);
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let language = stt::language(&app_settings);
+    let mut socket = connect(app_handle, transcription_setup(&app_settings, language.as_deref())).await?;
+
+    for chunk in pcm.chunks(TRANSCRIBE_CHUNK_SAMPLES) {
+        socket.send(audio_message(chunk)).await.map_err(|e| e.to_string())?;
+    }
+    socket.send(audio_end_message()).await.map_err(|e| e.to_string())?;
+
+    let mut text = String::new();
+    let received = tokio::time::timeout(TRANSCRIBE_TIMEOUT, async {
+        while let Some(message) = socket.next().await {
+            let message = message.map_err(|e| e.to_string())?;
+            if matches!(message, WsMessage::Close(_)) {
+                break;
+            }
+            let Some(message) = parse(&message) else {
+                continue;
+            };
+            if let Some(usage) = &message.usage_metadata {
+                record_usage(app_handle, usage);
+            }
+            let Some(content) = message.server_content else {
+                continue;
+            };
+            if let Some(input) = content.input_transcription {
+                text.push_str(&input.text);
+            }
+            if content.turn_complete {
+                break;
+            }
+        }
+        Ok::<_, String>(())
+    })
+    .await;
+    let _ = socket.close(None).await;
+    usage::record(app_handle, Provider::Gemini, LIVE_MODEL, 0, 0, pcm.len() as f64 / WHISPER_SAMPLE_RATE as f64);
+    received.map_err(|_| "Gemini Live did not finish the transcript in time".to_string())??;
+
+    Ok(TranscriptionResult {
+        text: text.trim().to_string(),
+        language,
+        segments: None,
+    })
+}
+
+// Command to open a Live session; returns its id for the events it emits
+#[tauri::command]
+pub async fn live_session_start(app_handle: AppHandle, state: State<'_, LiveState>) -> Result<u64, String> {
+    consent::require(&app_handle, ConsentScope::GeminiAudio)?;
+    if state.lock().map_err(|e| e.to_string())?.session.is_some() {
+        return Err("A Live session is already open".to_string());
+    }
+    let setup = setup_message(&settings::current(&app_handle.state::<SettingsState>()));
+    let socket = connect(&app_handle, setup).await?;
+
+    let mut sessions = state.lock().map_err(|e| e.to_string())?;
+    if sessions.session.is_some() {
+        return Err("A Live session is already open".to_string());
+    }
+    sessions.last_id += 1;
+    let id = sessions.last_id;
+    let (outgoing, receiver) = mpsc::unbounded_channel();
+    let task = spawn_session(&app_handle, id, socket, receiver);
+    sessions.session = Some(LiveSession { id, outgoing, task, audio_samples: 0 });
+    Ok(id)
+}
+
+// Command to stream a chunk of 16 kHz mono 16-bit audio. `end_of_speech`
+// tells Gemini the microphone has been turned off, so it answers without
+// waiting for silence.
+#[tauri::command]
+pub fn live_session_send_audio(
+    pcm: Vec<i16>,
+    end_of_speech: Option<bool>,
+    state: State<'_, LiveState>,
+) -> Result<(), String> {
+    let mut sessions = state.lock().map_err(|e| e.to_string())?;
+    let session = sessions.session.as_mut().ok_or("No Live session is open".to_string())?;
+    if !pcm.is_empty() {
+        session
+            .outgoing
+            .send(audio_message(&pcm))
+            .map_err(|_| "The Live session has closed".to_string())?;
+        session.audio_samples += pcm.len() as u64;
+    }
+    if end_of_speech.unwrap_or(false) {
+        session
+            .outgoing
+            .send(audio_end_message())
+            .map_err(|_| "The Live session has closed".to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn live_session_stop(app_handle: AppHandle, state: State<'_, LiveState>) -> Result<(), String> {
+    let id = {
+        let sessions = state.lock().map_err(|e| e.to_string())?;
+        let session = sessions.session.as_ref().ok_or("No Live session is open".to_string())?;
+        session.task.abort();
+        session.id
+    };
+    close(&app_handle, id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(setup: &Value) -> &str {
+        setup["setup"]["systemInstruction"]["parts"][0]["text"].as_str().unwrap()
+    }
+
+    #[test]
+    fn setup_carries_the_persona() {
+        let app_settings = AppSettings::default();
+        let setup = setup_message(&app_settings);
+        assert_eq!(instruction(&setup), persona::system_instruction(&app_settings.persona));
+        assert_eq!(setup["setup"]["model"], format!("models/{}", LIVE_MODEL));
+    }
+
+    #[test]
+    fn setup_lists_the_vocabulary() {
+        let mut app_settings = AppSettings::default();
+        app_settings.stt_vocabulary = vec!["Tauri".to_string(), "Ngozi".to_string()];
+        let setup = setup_message(&app_settings);
+        assert!(instruction(&setup).ends_with("spell them as written here: Tauri, Ngozi."));
+    }
+
+    #[test]
+    fn transcription_setup_leaves_out_the_persona() {
+        let mut app_settings = AppSettings::default();
+        app_settings.stt_vocabulary = vec!["Tauri".to_string()];
+        let setup = transcription_setup(&app_settings, Some("de"));
+        let text = instruction(&setup);
+        assert!(!text.contains(&app_settings.persona.name));
+        assert!(text.contains("code de"));
+        assert!(text.contains("Tauri"));
+    }
+}
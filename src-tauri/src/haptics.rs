@@ -0,0 +1,125 @@
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+#[cfg(mobile)]
+use tauri_plugin_haptics::HapticsExt;
+
+// Longest pattern we will play, to keep a bad pattern from buzzing forever
+const MAX_PATTERN_MS: u32 = 5000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum HapticPreset {
+    Success,
+    Warning,
+    Error,
+    Selection,
+    WakeWordDetected,
+    TimerDone,
+}
+
+impl HapticPreset {
+    // Alternating vibrate/pause durations in milliseconds
+    pub fn pattern(self) -> Vec<u32> {
+        match self {
+            HapticPreset::Success => vec![30, 60, 30],
+            HapticPreset::Warning => vec![80, 80, 80],
+            HapticPreset::Error => vec![120, 60, 120, 60, 120],
+            HapticPreset::Selection => vec![15],
+            HapticPreset::WakeWordDetected => vec![40, 40, 80],
+            HapticPreset::TimerDone => vec![250, 150, 250, 150, 250],
+        }
+    }
+}
+
+fn vibrate_once(app_handle: &AppHandle, duration_ms: u32) -> Result<(), String> {
+    #[cfg(mobile)]
+    {
+        app_handle.haptics().vibrate(duration_ms).map_err(|e| e.to_string())
+    }
+    #[cfg(not(mobile))]
+    {
+        // Desktop has no vibration motor
+        let _ = (app_handle, duration_ms);
+        Ok(())
+    }
+}
+
+// Summed as u64 so a pattern of huge durations can't overflow past the check
+fn check_pattern(pattern: &[u32]) -> Result<(), String> {
+    let total: u64 = pattern.iter().map(|duration| *duration as u64).sum();
+    if total > MAX_PATTERN_MS as u64 {
+        return Err(format!("Pattern is longer than {}ms", MAX_PATTERN_MS));
+    }
+    Ok(())
+}
+
+// Play a vibration pattern in the background
+pub fn play_pattern(app_handle: &AppHandle, pattern: Vec<u32>) -> Result<(), String> {
+    check_pattern(&pattern)?;
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        for (i, duration) in pattern.into_iter().enumerate() {
+            if i % 2 == 0 && vibrate_once(&app_handle, duration).is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(duration as u64)).await;
+        }
+    });
+    Ok(())
+}
+
+// Play a semantic preset; used by backend events such as wake word or timers
+pub fn play_preset(app_handle: &AppHandle, preset: HapticPreset) -> Result<(), String> {
+    play_pattern(app_handle, preset.pattern())
+}
+
+// Command to vibrate with a custom pattern
+#[tauri::command]
+pub fn vibrate(pattern: Vec<u32>, app_handle: AppHandle) -> Result<(), String> {
+    play_pattern(&app_handle, pattern)
+}
+
+// Command to play a named haptic preset
+#[tauri::command]
+pub fn haptic_feedback(preset: HapticPreset, app_handle: AppHandle) -> Result<(), String> {
+    play_preset(&app_handle, preset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_fit_the_limit() {
+        for preset in [
+            HapticPreset::Success,
+            HapticPreset::Warning,
+            HapticPreset::Error,
+            HapticPreset::Selection,
+            HapticPreset::WakeWordDetected,
+            HapticPreset::TimerDone,
+        ] {
+            let pattern = preset.pattern();
+            // Patterns start and end with a vibration
+            assert_eq!(pattern.len() % 2, 1);
+            assert!(check_pattern(&pattern).is_ok());
+        }
+    }
+
+    #[test]
+    fn long_patterns_are_rejected() {
+        assert!(check_pattern(&[]).is_ok());
+        assert!(check_pattern(&[MAX_PATTERN_MS]).is_ok());
+        assert!(check_pattern(&[MAX_PATTERN_MS, 1]).is_err());
+        assert!(check_pattern(&[u32::MAX, u32::MAX, 2]).is_err());
+    }
+
+    #[test]
+    fn presets_use_kebab_case() {
+        let preset: HapticPreset = serde_json::from_str("\"wake-word-detected\"").unwrap();
+        assert_eq!(preset.pattern(), HapticPreset::WakeWordDetected.pattern());
+    }
+}
@@ -0,0 +1,270 @@
+// Steps and water through the platform health store (Health Connect on
+// Android). Water is also kept in health.json so the day's total works
+// without a health store or before access is granted; steps only come from
+// the platform.
+
+use chrono::{Local, TimeZone};
+use serde::{Serialize, Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Manager, State,
+};
+
+use crate::storage;
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+const HEALTH_FILE: &str = "health.json";
+pub const GLASS_ML: u32 = 250;
+const MAX_WATER_ML: u32 = 5000;
+// Local water entries older than this are dropped
+const WATER_KEEP_SECS: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct WaterEntry {
+    at: u64,
+    ml: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct HealthStore {
+    water: Vec<WaterEntry>,
+}
+
+impl HealthStore {
+    fn add_water(&mut self, ml: u32, now: u64) -> Result<(), String> {
+        if ml == 0 || ml > MAX_WATER_ML {
+            return Err(format!("Log between 1 and {} ml of water", MAX_WATER_ML));
+        }
+        self.water.retain(|entry| now.saturating_sub(entry.at) < WATER_KEEP_SECS);
+        self.water.push(WaterEntry { at: now, ml });
+        Ok(())
+    }
+
+    fn water_since(&self, start: u64) -> u32 {
+        self.water.iter().filter(|entry| entry.at >= start).map(|entry| entry.ml).sum()
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DailyActivity {
+    // YYYY-MM-DD, local time
+    pub date: String,
+    // None when the health store is unavailable or access wasn't granted
+    pub steps: Option<u64>,
+    pub water_ml: u32,
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RangeRequest {
+    start_secs: u64,
+    end_secs: u64,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct StepsResponse {
+    steps: u64,
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WaterRequest {
+    ml: u32,
+    at_secs: u64,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct AccessResponse {
+    granted: bool,
+}
+
+pub struct HealthBridge {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+}
+
+impl HealthBridge {
+    // Ask for read access to steps and write access to hydration
+    fn request_access(&self) -> Result<bool, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<AccessResponse>("requestPermissions", ())
+                .map(|response| response.granted)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            Err("No health store is available on this device".to_string())
+        }
+    }
+
+    fn steps(&self, start_secs: u64, end_secs: u64) -> Result<u64, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<StepsResponse>("readSteps", RangeRequest { start_secs, end_secs })
+                .map(|response| response.steps)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = (start_secs, end_secs);
+            Err("No health store is available on this device".to_string())
+        }
+    }
+
+    fn log_water(&self, ml: u32, at_secs: u64) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("logHydration", WaterRequest { ml, at_secs })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = (ml, at_secs);
+            Err("No health store is available on this device".to_string())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("health")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = HealthBridge {
+                handle: api.register_android_plugin("company.atechnology.plates", "HealthPlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                HealthBridge {}
+            };
+            app.manage(bridge);
+            Ok(())
+        })
+        .build()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Start of today in local time, as Unix seconds
+fn start_of_today() -> u64 {
+    Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map(|midnight| midnight.timestamp().max(0) as u64)
+        .unwrap_or_else(|| now_secs() - now_secs() % (24 * 60 * 60))
+}
+
+// Record water locally and in the health store when there is one
+pub fn log_water(app_handle: &AppHandle, ml: u32) -> Result<DailyActivity, String> {
+    let now = now_secs();
+    let mut store: HealthStore = storage::load_json(app_handle, HEALTH_FILE);
+    store.add_water(ml, now)?;
+    storage::save_json(app_handle, HEALTH_FILE, &store)?;
+
+    if let Err(e) = app_handle.state::<HealthBridge>().log_water(ml, now) {
+        tracing::debug!(error = %e, "water not written to the health store");
+    }
+    Ok(daily_activity(app_handle))
+}
+
+// Today's steps and water, for the activity widget and briefings
+pub fn daily_activity(app_handle: &AppHandle) -> DailyActivity {
+    let start = start_of_today();
+    let store: HealthStore = storage::load_json(app_handle, HEALTH_FILE);
+    let steps = match app_handle.state::<HealthBridge>().steps(start, now_secs()) {
+        Ok(steps) => Some(steps),
+        Err(e) => {
+            tracing::debug!(error = %e, "steps unavailable");
+            None
+        }
+    };
+    DailyActivity {
+        date: Local::now().format("%Y-%m-%d").to_string(),
+        steps,
+        water_ml: store.water_since(start),
+    }
+}
+
+// Command to ask for health store access; returns whether it was granted
+#[tauri::command]
+pub fn request_health_access(bridge: State<'_, HealthBridge>) -> Result<bool, String> {
+    bridge.request_access()
+}
+
+// Command to log water; `ml` defaults to one glass
+#[tauri::command]
+pub fn log_water_intake(ml: Option<u32>, app_handle: AppHandle) -> Result<DailyActivity, String> {
+    log_water(&app_handle, ml.unwrap_or(GLASS_ML))
+}
+
+#[tauri::command]
+pub fn get_daily_activity(app_handle: AppHandle) -> DailyActivity {
+    daily_activity(&app_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn water_amounts_are_checked() {
+        let mut store = HealthStore::default();
+        assert!(store.add_water(0, 1_000).is_err());
+        assert!(store.add_water(MAX_WATER_ML + 1, 1_000).is_err());
+        assert!(store.water.is_empty());
+        store.add_water(GLASS_ML, 1_000).unwrap();
+        store.add_water(MAX_WATER_ML, 2_000).unwrap();
+        assert_eq!(store.water.len(), 2);
+    }
+
+    #[test]
+    fn water_totals_and_retention() {
+        let mut store = HealthStore::default();
+        store.add_water(300, 1_000).unwrap();
+        store.add_water(GLASS_ML, 5_000).unwrap();
+        store.add_water(GLASS_ML, 6_000).unwrap();
+        assert_eq!(store.water_since(0), 800);
+        assert_eq!(store.water_since(5_000), 500);
+        assert_eq!(store.water_since(7_000), 0);
+
+        // A month later the old entries are dropped
+        store.add_water(100, 1_000 + WATER_KEEP_SECS).unwrap();
+        assert_eq!(store.water.len(), 3);
+        assert_eq!(store.water_since(0), 600);
+    }
+
+    #[test]
+    fn today_starts_before_now() {
+        let start = start_of_today();
+        let now = now_secs();
+        assert!(start <= now);
+        // 25 hours covers a daylight saving change
+        assert!(now - start < 25 * 60 * 60);
+    }
+
+    #[cfg(not(android_bridges))]
+    #[test]
+    fn desktop_has_no_health_store() {
+        let bridge = HealthBridge {};
+        assert!(bridge.request_access().is_err());
+        assert!(bridge.steps(0, now_secs()).is_err());
+        assert!(bridge.log_water(GLASS_ML, now_secs()).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, EventId, Listener, Manager, Runtime, State,
+};
+
+use crate::captions::{self, CaptionConfig, CaptionEvent, CaptionService};
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+// Android side of the bridge: an InputMethodService that forwards commitText
+// calls to the currently focused input connection.
+#[cfg(android_bridges)]
+const PLUGIN_IDENTIFIER: &str = "company.atechnology.plates";
+
+#[derive(Serialize)]
+struct CommitText {
+    text: String,
+}
+
+pub struct ImeBridge<R: Runtime> {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<R>,
+    #[cfg(not(android_bridges))]
+    _runtime: std::marker::PhantomData<fn() -> R>,
+}
+
+impl<R: Runtime> ImeBridge<R> {
+    // Insert text into whichever field currently has focus
+    pub fn commit_text(&self, text: String) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("commitText", CommitText { text })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = CommitText { text };
+            Err("Voice typing is only available on Android".to_string())
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ImeSession {
+    listener: Option<EventId>,
+}
+
+pub type ImeState = Mutex<ImeSession>;
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("ime")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = ImeBridge {
+                handle: api.register_android_plugin(PLUGIN_IDENTIFIER, "ImeBridgePlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                ImeBridge::<R> { _runtime: std::marker::PhantomData }
+            };
+            app.manage(bridge);
+            app.manage(ImeState::default());
+            Ok(())
+        })
+        .build()
+}
+
+// Command to start dictating into the focused field of another app.
+// Reuses the live caption stream and commits each caption as it arrives.
+#[tauri::command]
+pub fn start_ime_dictation(
+    local_only: Option<bool>,
+    app_handle: AppHandle,
+    ime_state: State<'_, ImeState>,
+    caption_state: State<'_, CaptionService>,
+) -> Result<(), String> {
+    let mut session = ime_state.lock().map_err(|e| e.to_string())?;
+    if session.listener.is_some() {
+        return Ok(());
+    }
+
+    let handle = app_handle.clone();
+    let listener = app_handle.listen("caption://text", move |event| {
+        let Ok(caption) = serde_json::from_str::<CaptionEvent>(event.payload()) else {
+            return;
+        };
+        let bridge = handle.state::<ImeBridge<tauri::Wry>>();
+        let _ = bridge.commit_text(format!("{} ", caption.text));
+    });
+    session.listener = Some(listener);
+
+    captions::start(&app_handle, &caption_state, CaptionConfig {
+        local_only: local_only.unwrap_or(false),
+        ..CaptionConfig::default()
+    })
+}
+
+// Command to stop voice typing
+#[tauri::command]
+pub fn stop_ime_dictation(
+    app_handle: AppHandle,
+    ime_state: State<'_, ImeState>,
+    caption_state: State<'_, CaptionService>,
+) -> Result<(), String> {
+    let mut session = ime_state.lock().map_err(|e| e.to_string())?;
+    if let Some(listener) = session.listener.take() {
+        app_handle.unlisten(listener);
+    }
+    captions::stop(&app_handle, &caption_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(android_bridges))]
+    #[test]
+    fn voice_typing_needs_android() {
+        let bridge = ImeBridge::<tauri::test::MockRuntime> {
+            _runtime: std::marker::PhantomData,
+        };
+        assert!(bridge.commit_text("hello ".to_string()).is_err());
+    }
+
+    #[test]
+    fn commit_payload_shape() {
+        let payload = serde_json::to_value(CommitText { text: "hello ".to_string() }).unwrap();
+        assert_eq!(payload, serde_json::json!({ "text": "hello " }));
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+use url::Url;
+
+use crate::engine;
+use crate::expenses::{self, Expense};
+use crate::health::{self, DailyActivity};
+use crate::notes::{self, Note};
+use crate::photos::{self, PhotoSearch};
+use crate::qr::{self, WifiQr};
+use crate::settings::{self, SettingsState};
+use crate::timers::{self, Timer};
+
+// Links waiting for the user to confirm them; older ones are dropped
+const MAX_PENDING: usize = 10;
+
+// Actions the assistant can be asked to perform from outside the UI
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Intent {
+    Ask { query: String },
+    Timer { minutes: u64, label: Option<String> },
+    Note { text: String },
+    Expense { text: String },
+    Water { ml: u32 },
+    Photos { query: String },
+    Open { screen: String },
+    Wifi,
+}
+
+impl Intent {
+    // Intents that call the engine, save something or reveal the Wi-Fi
+    // password; a link from outside the app only runs these once the user confirms
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(
+            self,
+            Intent::Ask { .. } | Intent::Note { .. } | Intent::Expense { .. } | Intent::Water { .. } | Intent::Wifi
+        )
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IntentOutcome {
+    Answer { text: String },
+    TimerStarted { timer: Timer },
+    NoteSaved { note: Note },
+    ExpenseLogged { expense: Expense },
+    WaterLogged { activity: DailyActivity },
+    PhotosFound { search: PhotoSearch },
+    Navigate { screen: String },
+    WifiShared { qr: WifiQr },
+    // Held until confirm_intent is called with the id
+    AwaitingConfirmation { id: u64 },
+}
+
+#[derive(Serialize, Clone)]
+struct IntentEvent {
+    intent: Intent,
+    outcome: IntentOutcome,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PendingIntent {
+    pub id: u64,
+    pub intent: Intent,
+}
+
+#[derive(Default)]
+pub struct PendingIntents {
+    next_id: u64,
+    intents: Vec<PendingIntent>,
+}
+
+pub type PendingIntentsState = Mutex<PendingIntents>;
+
+fn query_param(uri: &Url, name: &str) -> Option<String> {
+    uri.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+// Parse a plates:// URI, e.g. plates://ask?q=... or plates://timer?mins=10
+pub fn parse_uri(uri: &str) -> Result<Intent, String> {
+    let uri = Url::parse(uri).map_err(|e| e.to_string())?;
+    if uri.scheme() != "plates" {
+        return Err(format!("Unsupported scheme: {}", uri.scheme()));
+    }
+
+    match uri.host_str().unwrap_or_default() {
+        "ask" => {
+            let query = query_param(&uri, "q").ok_or("Missing q parameter".to_string())?;
+            Ok(Intent::Ask { query })
+        }
+        "timer" => {
+            let minutes = query_param(&uri, "mins")
+                .ok_or("Missing mins parameter".to_string())?
+                .parse::<u64>()
+                .map_err(|e| e.to_string())?;
+            Ok(Intent::Timer {
+                minutes,
+                label: query_param(&uri, "label"),
+            })
+        }
+        "note" => {
+            let text = query_param(&uri, "text").ok_or("Missing text parameter".to_string())?;
+            Ok(Intent::Note { text })
+        }
+        "expense" => {
+            let text = query_param(&uri, "text").ok_or("Missing text parameter".to_string())?;
+            Ok(Intent::Expense { text })
+        }
+        // plates://water logs a glass; glasses= or ml= give the amount
+        "water" => {
+            let ml = match (query_param(&uri, "ml"), query_param(&uri, "glasses")) {
+                (Some(ml), _) => ml.parse::<u32>().map_err(|e| e.to_string())?,
+                (None, Some(glasses)) => glasses
+                    .parse::<u32>()
+                    .map_err(|e| e.to_string())?
+                    .checked_mul(health::GLASS_ML)
+                    .ok_or("Too many glasses".to_string())?,
+                (None, None) => health::GLASS_ML,
+            };
+            Ok(Intent::Water { ml })
+        }
+        "photos" => {
+            let query = query_param(&uri, "q").ok_or("Missing q parameter".to_string())?;
+            Ok(Intent::Photos { query })
+        }
+        "open" => {
+            let screen = query_param(&uri, "screen").ok_or("Missing screen parameter".to_string())?;
+            Ok(Intent::Open { screen })
+        }
+        // plates://wifi shows the code for the home network
+        "wifi" => Ok(Intent::Wifi),
+        other => Err(format!("Unknown action: {}", other)),
+    }
+}
+
+// Run an intent and emit `intent://dispatched` so the UI can follow along
+pub async fn dispatch(app_handle: &AppHandle, intent: Intent) -> Result<IntentOutcome, String> {
+    let outcome = match intent.clone() {
+        Intent::Ask { query } => {
+            let app_settings = settings::current(&app_handle.state::<SettingsState>());
+            let text = engine::respond(app_handle, &query, &app_settings).await?;
+            IntentOutcome::Answer { text }
+        }
+        Intent::Timer { minutes, label } => {
+            let seconds = minutes.checked_mul(60).ok_or("The timer is too long".to_string())?;
+            let timer = timers::start_timer(app_handle, seconds, label)?;
+            IntentOutcome::TimerStarted { timer }
+        }
+        Intent::Note { text } => {
+            let title = text.chars().take(60).collect();
+            let note = notes::add_note(app_handle, title, text, Some("intent".to_string()))?;
+            IntentOutcome::NoteSaved { note }
+        }
+        Intent::Expense { text } => {
+            let expense = expenses::capture(app_handle, &text).await?;
+            IntentOutcome::ExpenseLogged { expense }
+        }
+        Intent::Water { ml } => {
+            let activity = health::log_water(app_handle, ml)?;
+            IntentOutcome::WaterLogged { activity }
+        }
+        Intent::Photos { query } => {
+            let search = photos::search(app_handle, &query)?;
+            IntentOutcome::PhotosFound { search }
+        }
+        Intent::Open { screen } => IntentOutcome::Navigate { screen },
+        Intent::Wifi => IntentOutcome::WifiShared {
+            qr: qr::home_wifi_qr(app_handle)?,
+        },
+    };
+
+    app_handle
+        .emit("intent://dispatched", IntentEvent {
+            intent,
+            outcome: outcome.clone(),
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(outcome)
+}
+
+// Run an intent from a link opened outside the app, such as a deep link or an
+// NFC tag. Ones that need confirming are held and `intent://confirm` is emitted.
+pub async fn dispatch_link(app_handle: &AppHandle, intent: Intent) -> Result<IntentOutcome, String> {
+    if !intent.needs_confirmation() {
+        return dispatch(app_handle, intent).await;
+    }
+    let pending = {
+        let state = app_handle.state::<PendingIntentsState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.next_id += 1;
+        let pending = PendingIntent {
+            id: store.next_id,
+            intent,
+        };
+        store.intents.push(pending.clone());
+        if store.intents.len() > MAX_PENDING {
+            store.intents.remove(0);
+        }
+        pending
+    };
+    app_handle.emit("intent://confirm", &pending).map_err(|e| e.to_string())?;
+    Ok(IntentOutcome::AwaitingConfirmation { id: pending.id })
+}
+
+// Command to run a plates:// URI from the frontend
+#[tauri::command]
+pub async fn handle_deep_link(uri: String, app_handle: AppHandle) -> Result<IntentOutcome, String> {
+    let intent = parse_uri(&uri)?;
+    dispatch_link(&app_handle, intent).await
+}
+
+#[tauri::command]
+pub fn list_pending_intents(state: State<'_, PendingIntentsState>) -> Result<Vec<PendingIntent>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.intents.clone())
+}
+
+// Command to run a held intent once the user has agreed to it
+#[tauri::command]
+pub async fn confirm_intent(id: u64, app_handle: AppHandle) -> Result<IntentOutcome, String> {
+    let intent = {
+        let state = app_handle.state::<PendingIntentsState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        let index = store
+            .intents
+            .iter()
+            .position(|pending| pending.id == id)
+            .ok_or("The action has expired".to_string())?;
+        store.intents.remove(index).intent
+    };
+    dispatch(&app_handle, intent).await
+}
+
+#[tauri::command]
+pub fn dismiss_intent(id: u64, state: State<'_, PendingIntentsState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.intents.retain(|pending| pending.id != id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_timer_link() {
+        let Intent::Timer { minutes, label } = parse_uri("plates://timer?mins=10&label=Tea").unwrap() else {
+            panic!("not a timer");
+        };
+        assert_eq!(minutes, 10);
+        assert_eq!(label.as_deref(), Some("Tea"));
+    }
+
+    #[test]
+    fn converts_glasses_of_water() {
+        assert!(matches!(parse_uri("plates://water?glasses=2"), Ok(Intent::Water { ml: 500 })));
+        assert!(matches!(parse_uri("plates://water"), Ok(Intent::Water { ml }) if ml == health::GLASS_ML));
+    }
+
+    #[test]
+    fn rejects_an_overflowing_number_of_glasses() {
+        assert!(parse_uri("plates://water?glasses=4294967295").is_err());
+    }
+
+    #[test]
+    fn rejects_other_schemes_and_actions() {
+        assert!(parse_uri("https://example.com/ask?q=hi").is_err());
+        assert!(parse_uri("plates://format?disk=c").is_err());
+    }
+
+    #[test]
+    fn links_that_write_ask_or_share_secrets_need_confirmation() {
+        for uri in [
+            "plates://ask?q=hi",
+            "plates://note?text=x",
+            "plates://expense?text=5",
+            "plates://water",
+            "plates://wifi",
+        ] {
+            assert!(parse_uri(uri).unwrap().needs_confirmation(), "{}", uri);
+        }
+        for uri in ["plates://open?screen=notes", "plates://photos?q=cat"] {
+            assert!(!parse_uri(uri).unwrap().needs_confirmation(), "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    Manager,
+};
+
+#[cfg(android_bridges)]
+use serde::{Serialize, Deserialize};
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+#[cfg(not(target_os = "android"))]
+const SERVICE: &str = "company.atechnology.plates";
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct SecretRequest<'a> {
+    name: &'a str,
+    value: Option<&'a str>,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct SecretResponse {
+    value: Option<String>,
+}
+
+// Secrets live in the platform keystore: the OS keychain on desktop and iOS,
+// and an Android Keystore backed plugin on Android. Android builds without
+// the native bridges have nowhere safe to keep secrets and refuse them.
+pub struct Keystore {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+}
+
+impl Keystore {
+    pub fn get(&self, name: &str) -> Result<Option<String>, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<SecretResponse>("getSecret", SecretRequest { name, value: None })
+                .map(|response| response.value)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            let entry = keyring::Entry::new(SERVICE, name).map_err(|e| e.to_string())?;
+            match entry.get_password() {
+                Ok(value) => Ok(Some(value)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        #[cfg(all(target_os = "android", not(android_bridges)))]
+        {
+            let _ = name;
+            Ok(None)
+        }
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("setSecret", SecretRequest { name, value: Some(value) })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            let entry = keyring::Entry::new(SERVICE, name).map_err(|e| e.to_string())?;
+            entry.set_password(value).map_err(|e| e.to_string())
+        }
+        #[cfg(all(target_os = "android", not(android_bridges)))]
+        {
+            let _ = (name, value);
+            Err("Secure storage is not available on this device".to_string())
+        }
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("deleteSecret", SecretRequest { name, value: None })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            let entry = keyring::Entry::new(SERVICE, name).map_err(|e| e.to_string())?;
+            match entry.delete_password() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        #[cfg(all(target_os = "android", not(android_bridges)))]
+        {
+            let _ = name;
+            Ok(())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("keystore")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let keystore = Keystore {
+                handle: api.register_android_plugin("company.atechnology.plates", "KeystorePlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let keystore = {
+                let _ = api;
+                Keystore {}
+            };
+            app.manage(keystore);
+            Ok(())
+        })
+        .build()
+}
@@ -1,12 +1,99 @@
-
-
+mod acceleration;
+mod accessibility;
+mod audio_cache;
+mod audio_focus;
+mod audio_processing;
+mod automations;
+mod business_cards;
+mod calendar;
+mod captions;
+mod casting;
+mod companion;
+mod consent;
+mod contacts;
+mod conversations;
+mod dav;
+mod deepgram;
+mod devtools;
+mod discovery;
+mod earcons;
+mod endpoints;
+mod engine;
+mod expenses;
+mod faults;
+mod features;
+mod files;
+mod formatter;
+mod gemini_live;
+mod haptics;
+mod health;
+mod ime;
+mod intents;
+mod keystore;
+mod limits;
+mod llm;
+mod local_llm;
+mod matrix;
+mod mdns;
+mod memory;
+mod models;
+mod moderation;
+mod nfc;
+mod notes;
+mod notifications;
+mod ollama;
+mod persona;
+mod photos;
+mod piper;
+mod practice;
+mod prefetch;
+mod profiler;
+mod provider_keys;
+mod push;
+mod qr;
+mod quiz;
+mod receipts;
+mod recipes;
+mod redaction;
+mod rest_api;
+mod retention;
+mod routing;
+mod rules;
+mod scans;
+mod search;
+mod settings;
+mod share;
+mod speech;
+mod ssml;
+mod storage;
+mod stories;
+mod structured;
+mod stt;
+mod sync_crypto;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+mod thermal;
+mod timers;
+mod tools;
+mod totp;
+mod transcription_jobs;
+mod tts;
+mod untrusted;
+mod usage;
+mod wakeword;
+mod whisper;
+mod widgets;
+mod windows;
 
 use tauri::Manager;
+use tauri_plugin_deep_link::DeepLinkExt;
 use serde::{Serialize, Deserialize};
 use tauri_plugin_system_info::{commands::battery, model::{Battery, BatteryState}};
 use reqwest;
 use dotenv::dotenv;
 use std::env;
+use consent::ConsentScope;
+use endpoints::Endpoint;
 
 // Define the greet command that was referenced but not implemented
 #[tauri::command]
@@ -79,23 +166,34 @@ struct Weather {
     icon: String,
 }
 
-#[derive(Serialize)]
-struct WeatherData {
-    temperature: String,
-    icon: String,
+#[derive(Serialize, Clone)]
+pub(crate) struct WeatherData {
+    pub(crate) temperature: String,
+    // None when the response names no conditions
+    pub(crate) icon: Option<String>,
 }
 
 // Weather command
 #[tauri::command]
-async fn get_weather(lat: i8, lon: i8) -> Result<WeatherData, String> {
+async fn get_weather(lat: i8, lon: i8, app_handle: tauri::AppHandle) -> Result<WeatherData, String> {
+    fetch_weather(&app_handle, lat.into(), lon.into()).await
+}
+
+// Fetch current weather; shared by the weather command and widgets
+pub(crate) async fn fetch_weather(app_handle: &tauri::AppHandle, lat: f64, lon: f64) -> Result<WeatherData, String> {
+    consent::require(app_handle, ConsentScope::WeatherLocation)?;
     dotenv().ok();
     let api_key = env::var("OPENWEATHER_API_KEY").map_err(|_| "API key not found".to_string())?;
     
     let url = format!(
-        "https://api.openweathermap.org/data/2.5/weather?lat={}&lon={}&appid={}&units=imperial",
-        lat, lon, api_key
+        "{}/data/2.5/weather?lat={}&lon={}&appid={}&units=imperial",
+        endpoints::base_url(Endpoint::OpenWeather),
+        lat,
+        lon,
+        api_key
     );
     
+    let _permit = limits::acquire(Endpoint::OpenWeather).await?;
     let client = reqwest::Client::new();
     let response = client
         .get(&url)
@@ -103,40 +201,472 @@ async fn get_weather(lat: i8, lon: i8) -> Result<WeatherData, String> {
         .await
         .map_err(|e| e.to_string())?;
         
-    let weather_data: OpenWeatherResponse = response
-        .json()
-        .await
-        .map_err(|e| e.to_string())?;
+    let weather_data: OpenWeatherResponse = faults::json(Endpoint::OpenWeather, response).await?;
     
     Ok(WeatherData {
         temperature: format!("{:.0}°F", weather_data.main.temp),
-        icon: format!("https://openweathermap.org/img/wn/{}@2x.png", weather_data.weather[0].icon),
+        icon: weather_data
+            .weather
+            .first()
+            .map(|weather| format!("https://openweathermap.org/img/wn/{}@2x.png", weather.icon)),
     })
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
+// Forecast data used by weather rule triggers
+#[derive(Deserialize)]
+struct OneCallResponse {
+    current: OneCallCurrent,
+    #[serde(default)]
+    minutely: Vec<MinutelyPrecipitation>,
+    #[serde(default)]
+    alerts: Vec<WeatherAlert>,
+}
+
+#[derive(Deserialize)]
+struct OneCallCurrent {
+    temp: f64,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct MinutelyPrecipitation {
+    pub(crate) dt: i64,
+    // mm/h
+    pub(crate) precipitation: f64,
+}
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct WeatherAlert {
+    pub(crate) event: String,
+    pub(crate) start: i64,
+    #[serde(default)]
+    pub(crate) description: String,
+}
+
+pub(crate) struct Forecast {
+    // Degrees Fahrenheit, like the weather widget
+    pub(crate) temperature: f64,
+    pub(crate) minutely: Vec<MinutelyPrecipitation>,
+    pub(crate) alerts: Vec<WeatherAlert>,
+}
+
+// Fetch current temperature, the next hour of precipitation and active alerts
+pub(crate) async fn fetch_forecast(app_handle: &tauri::AppHandle, lat: f64, lon: f64) -> Result<Forecast, String> {
+    consent::require(app_handle, ConsentScope::WeatherLocation)?;
+    dotenv().ok();
+    let api_key = env::var("OPENWEATHER_API_KEY").map_err(|_| "API key not found".to_string())?;
+
+    let url = format!(
+        "{}/data/3.0/onecall?lat={}&lon={}&appid={}&units=imperial&exclude=hourly,daily",
+        endpoints::base_url(Endpoint::OpenWeather),
+        lat,
+        lon,
+        api_key
+    );
+    let _permit = limits::acquire(Endpoint::OpenWeather).await?;
+    let response = reqwest::Client::new()
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Forecast request failed: {}", response.status()));
+    }
+    let forecast: OneCallResponse = faults::json(Endpoint::OpenWeather, response).await?;
+
+    Ok(Forecast {
+        temperature: forecast.current.temp,
+        minutely: forecast.minutely,
+        alerts: forecast.alerts,
+    })
+}
+
+// The app with every plugin, state and command; run() adds the generated
+// context, and tests build it with a mock one
+pub(crate) fn builder() -> tauri::Builder<tauri::Wry> {
+    let builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(ime::init())
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(widgets::init())
+        .plugin(keystore::init())
+        .plugin(contacts::init())
+        .plugin(nfc::init())
+        .plugin(push::init())
+        .plugin(audio_focus::init())
+        .plugin(health::init())
+        .plugin(photos::init())
+        .plugin(qr::init());
+
+    #[cfg(mobile)]
+    let builder = builder
+        .plugin(tauri_plugin_haptics::init())
+        .plugin(tauri_plugin_biometric::init());
+
+    builder
+        .manage(captions::CaptionService::default())
+        .manage(timers::TimersState::default())
+        .manage(intents::PendingIntentsState::default())
+        .manage(companion::CompanionState::default())
+        .manage(rest_api::RestApiState::default())
+        .manage(casting::CastState::default())
+        .manage(notifications::NotificationsState::default())
+        .manage(speech::SpeechState::default())
+        .manage(earcons::EarconsState::default())
+        .manage(whisper::WhisperState::default())
+        .manage(piper::PiperState::default())
+        .manage(local_llm::LocalLlmState::default())
+        .manage(devtools::DevConsoleState::default())
+        .manage(models::ModelsState::default())
+        .manage(wakeword::WakeWordState::default())
+        .manage(thermal::ThermalState::default())
+        .manage(stt::CancelState::default())
+        .manage(engine::ResponseCancelState::default())
+        .manage(gemini_live::LiveState::default())
+        .manage(tts::TtsState::default())
+        .manage(quiz::QuizState::default())
+        .manage(stories::StoryState::default())
+        .manage(practice::PracticeState::default())
+        .manage(sync_crypto::SyncKeyState::default())
         // Add location and microphone permissions plugins
-        .setup(|_app| {
+        .setup(|app| {
+            let mut startup = profiler::StartupTimer::start();
+            app.manage(storage::RecoveryState::new(storage::recover_state(app.handle())));
+            app.manage(profiler::ProfilerState::new(profiler::load(app.handle())));
+            startup.mark("recovery");
+            let app_settings = settings::load(app.handle());
+            limits::configure(&app_settings.concurrency);
+            app.manage(settings::SettingsState::new(app_settings));
+            startup.mark("settings");
+            app.manage(notes::NotesState::new(notes::load(app.handle())));
+            app.manage(consent::ConsentState::new(consent::load(app.handle())));
+            app.manage(routing::RoutingState::new(routing::load(app.handle())));
+            app.manage(automations::AutomationsState::new(automations::load(app.handle())));
+            startup.mark("notes");
+            app.manage(calendar::CalendarState::new(calendar::load(app.handle())));
+            calendar::spawn_sync_loop(app.handle().clone());
+            app.manage(contacts::ContactsState::new(contacts::load(app.handle())));
+            contacts::spawn_sync_loop(app.handle().clone());
+            startup.mark("calendar_contacts");
+            app.manage(files::FilesState::new(files::load(app.handle())));
+            startup.mark("files");
+            app.manage(matrix::MatrixState::new(matrix::load(app.handle())));
+            matrix::spawn_sync(app.handle().clone());
+            startup.mark("matrix");
+            app.manage(rules::RulesState::new(rules::load(app.handle())));
+            rules::spawn_scheduler(app.handle().clone());
+            app.manage(usage::UsageState::new(usage::load(app.handle())));
+            app.manage(provider_keys::ProviderKeysState::new(provider_keys::load(app.handle())));
+            app.manage(totp::TotpState::new(totp::load(app.handle())));
+            startup.mark("rules_usage");
+            app.manage(prefetch::PrefetchState::new(prefetch::load(app.handle())));
+            prefetch::spawn_prefetcher(app.handle().clone());
+            app.manage(push::PushState::new(push::load(app.handle())));
+            push::spawn_relay(app.handle().clone());
+            startup.mark("prefetch_push");
+            app.manage(features::FeaturesState::new(features::load(app.handle())));
+            features::spawn_refresh(app.handle().clone());
+            startup.mark("features");
+            wakeword::spawn_listener(app.handle().clone());
+            startup.mark("wakeword");
+            memory::spawn_monitor(app.handle().clone());
+            retention::spawn_pruner(app.handle().clone());
+            audio_cache::spawn_cleanup(app.handle().clone());
+
+            widgets::spawn_refresh_loop(app.handle().clone());
+
+            // Route plates:// links through the intent router
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    let handle = handle.clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Ok(intent) = intents::parse_uri(url.as_str()) {
+                            let _ = intents::dispatch_link(&handle, intent).await;
+                        }
+                    });
+                }
+            });
+
             #[cfg(mobile)]
             {
                 // Request permissions on mobile
                 // This is a placeholder - actual implementation would use platform-specific APIs
             }
+            startup.mark("widgets");
+            startup.finish(app.handle());
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(profiler::timed(tauri::generate_handler![
             greet,
             is_first_run,
             complete_tutorial,
             set_as_launcher,
             get_battery_level,
             get_battery_state,
-            get_weather
-        ])
+            get_weather,
+            speech::transcribe_audio,
+            captions::start_captions,
+            captions::stop_captions,
+            captions::get_caption_status,
+            captions::push_caption_audio,
+            captions::export_transcript,
+            haptics::vibrate,
+            haptics::haptic_feedback,
+            settings::get_settings,
+            settings::update_settings,
+            engine::process_text_input,
+            engine::process_structured_input,
+            engine::cancel_response,
+            engine::get_generation_settings,
+            engine::set_generation_settings,
+            engine::ask_with_sources,
+            formatter::format_response,
+            ime::start_ime_dictation,
+            ime::stop_ime_dictation,
+            notes::save_note,
+            notes::list_notes,
+            notes::delete_note,
+            share::on_shared_content,
+            timers::set_timer,
+            timers::list_timers,
+            timers::cancel_timer,
+            intents::handle_deep_link,
+            intents::list_pending_intents,
+            intents::confirm_intent,
+            intents::dismiss_intent,
+            widgets::get_widget_data,
+            widgets::configure_widgets,
+            widgets::refresh_widgets,
+            companion::start_companion_server,
+            companion::stop_companion_server,
+            companion::get_companion_status,
+            ollama::detect_ollama,
+            ollama::list_ollama_models,
+            automations::trigger_automation_event,
+            automations::list_webhooks,
+            automations::save_webhook,
+            automations::delete_webhook,
+            automations::test_webhook,
+            rest_api::start_rest_api,
+            rest_api::stop_rest_api,
+            rest_api::get_rest_api_status,
+            rest_api::list_api_clients,
+            rest_api::create_api_client,
+            rest_api::revoke_client,
+            rest_api::rotate_api_signing_key,
+            casting::list_cast_devices,
+            casting::cast_to,
+            casting::stop_casting,
+            calendar::add_calendar_source,
+            calendar::remove_calendar_source,
+            calendar::list_calendar_sources,
+            calendar::sync_calendars,
+            calendar::get_agenda,
+            contacts::search_contacts,
+            contacts::add_carddav_account,
+            contacts::remove_carddav_account,
+            contacts::list_carddav_accounts,
+            contacts::sync_contacts,
+            files::add_webdav_mount,
+            files::remove_webdav_mount,
+            files::list_webdav_mounts,
+            files::reindex_webdav_mount,
+            files::search_files,
+            files::download_file,
+            files::open_file,
+            search::search_everything,
+            notifications::get_notification_digest,
+            notifications::dismiss_notification,
+            notifications::clear_notification_digest,
+            matrix::matrix_login,
+            matrix::matrix_logout,
+            matrix::get_matrix_status,
+            matrix::suggest_matrix_reply,
+            matrix::send_matrix_message,
+            rules::list_rules,
+            rules::save_rule,
+            rules::delete_rule,
+            rules::run_rule,
+            rules::report_location,
+            nfc::on_nfc_tag,
+            nfc::write_tag,
+            qr::generate_wifi_qr,
+            qr::join_wifi_from_qr,
+            totp::import_totp_uri,
+            totp::list_totp_accounts,
+            totp::get_code,
+            totp::remove_totp_account,
+            usage::get_usage,
+            usage::get_budgets,
+            usage::set_budget,
+            usage::remove_budget,
+            provider_keys::add_provider_key,
+            provider_keys::remove_provider_key,
+            provider_keys::reset_provider_key,
+            provider_keys::get_provider_keys_status,
+            conversations::start_conversation,
+            conversations::new_conversation,
+            conversations::list_conversations,
+            conversations::get_conversation,
+            conversations::delete_conversation,
+            conversations::pin_conversation,
+            conversations::favorite_message,
+            conversations::get_pinned,
+            prefetch::get_prefetch_predictions,
+            prefetch::get_wake_history,
+            discovery::discover_services,
+            discovery::configure_service,
+            push::get_push_config,
+            push::configure_push,
+            push::subscribe_push,
+            push::unsubscribe_push,
+            push::list_push_subscriptions,
+            push::on_push_endpoint,
+            push::on_push_message,
+            push::on_push_unregistered,
+            speech::list_input_devices,
+            speech::select_input_device,
+            speech::start_recording,
+            speech::stop_recording,
+            speech::ptt_begin,
+            speech::ptt_end,
+            speech::set_vad_config,
+            speech::set_stt_language,
+            earcons::play_earcon,
+            earcons::set_do_not_disturb,
+            windows::list_displays,
+            windows::open_panel,
+            windows::close_panel,
+            windows::list_panels,
+            windows::send_to_panel,
+            devtools::start_dev_console,
+            devtools::stop_dev_console,
+            devtools::get_dev_console_status,
+            devtools::get_dev_console_token,
+            devtools::simulate_offline,
+            faults::get_fault_injection,
+            faults::set_fault_injection,
+            models::list_available_models,
+            models::get_model_status,
+            models::download_model,
+            models::delete_model,
+            features::get_feature_flags,
+            features::set_feature_override,
+            features::configure_feature_remote,
+            features::refresh_feature_flags,
+            wakeword::set_wake_word_enabled,
+            wakeword::set_wake_word_sensitivity,
+            wakeword::get_wake_word_status,
+            storage::get_recovery_report,
+            memory::get_model_memory_usage,
+            memory::on_memory_pressure,
+            thermal::on_thermal_state,
+            thermal::get_performance_mode,
+            acceleration::get_acceleration_capabilities,
+            acceleration::set_model_backend,
+            acceleration::benchmark_backends,
+            profiler::get_performance_report,
+            limits::get_concurrency_limits,
+            limits::set_concurrency_limit,
+            stt::list_stt_backends,
+            stt::set_stt_backend,
+            stt::set_stt_fallback,
+            stt::set_stt_diarization,
+            routing::get_routing_stats,
+            stt::cancel_transcription,
+            models::check_model_updates,
+            models::rollback_model,
+            sync_crypto::get_sync_encryption_status,
+            sync_crypto::enable_sync_encryption,
+            sync_crypto::unlock_sync_encryption,
+            sync_crypto::lock_sync_encryption,
+            sync_crypto::change_sync_passphrase,
+            sync_crypto::rotate_sync_key,
+            sync_crypto::recover_sync_encryption,
+            sync_crypto::seal_sync_payload,
+            sync_crypto::open_sync_payload,
+            retention::get_retention_policies,
+            retention::update_retention_policies,
+            speech::set_stt_vocabulary,
+            redaction::set_redaction,
+            redaction::preview_redaction,
+            consent::get_consents,
+            consent::grant_consent,
+            consent::revoke_consent,
+            gemini_live::live_session_start,
+            gemini_live::live_session_send_audio,
+            gemini_live::live_session_stop,
+            audio_processing::get_audio_processing,
+            audio_processing::set_audio_processing,
+            audio_cache::get_audio_cache_policy,
+            audio_cache::set_audio_cache_policy,
+            audio_cache::purge_audio_cache,
+            tts::speak,
+            tts::stop_speaking,
+            tts::pause_speaking,
+            tts::resume_speaking,
+            tts::list_tts_backends,
+            tts::set_tts_settings,
+            quiz::start_quiz,
+            quiz::answer_quiz,
+            quiz::next_quiz_question,
+            quiz::stop_quiz,
+            quiz::get_quiz_stats,
+            stories::generate_story,
+            stories::list_stories,
+            stories::play_story,
+            stories::stop_story,
+            practice::start_practice,
+            practice::practice_reply,
+            practice::set_practice_rate,
+            practice::stop_practice,
+            practice::get_practice_progress,
+            audio_focus::list_audio_routes,
+            audio_focus::set_audio_route,
+            audio_focus::set_media_ducking,
+            recipes::what_can_i_cook,
+            recipes::save_recipe,
+            recipes::list_recipes,
+            recipes::delete_recipe,
+            recipes::plan_meal,
+            recipes::unplan_meal,
+            recipes::get_meal_plan,
+            recipes::get_shopping_list,
+            expenses::log_expense,
+            expenses::list_expenses,
+            expenses::delete_expense,
+            expenses::get_expense_items,
+            expenses::get_spending_summary,
+            expenses::export_expenses,
+            receipts::parse_receipt,
+            health::request_health_access,
+            health::log_water_intake,
+            health::get_daily_activity,
+            persona::get_persona,
+            persona::list_persona_presets,
+            persona::set_persona,
+            photos::index_photos,
+            photos::clear_photo_index,
+            photos::search_photos,
+            scans::scan_document,
+            scans::list_scans,
+            scans::delete_scan,
+            scans::export_scan,
+            scans::open_scan,
+            llm::list_llm_providers,
+            llm::set_llm_provider,
+            business_cards::scan_business_card,
+            contacts::create_contact
+        ]))
         .plugin(tauri_plugin_geolocation::init())
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    profiler::init();
+    devtools::init_logging();
+
+    builder()
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
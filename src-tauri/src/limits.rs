@@ -0,0 +1,181 @@
+// Caps on concurrent requests to each hosted provider, so bursts such as a
+// briefing fetching weather, calendar and an answer at once queue up instead
+// of tripping the provider's rate limits.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::{AppHandle, State};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::endpoints::{self, Endpoint};
+use crate::faults;
+use crate::settings::{self, ConcurrencyLimits, SettingsState};
+
+const ENDPOINTS: [Endpoint; 5] = [
+    Endpoint::Gemini,
+    Endpoint::OpenAi,
+    Endpoint::OpenWeather,
+    Endpoint::Deepgram,
+    Endpoint::Anthropic,
+];
+
+struct Limiter {
+    limit: usize,
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<AtomicUsize>,
+}
+
+impl Limiter {
+    fn new(limit: usize) -> Self {
+        Limiter {
+            limit,
+            semaphore: Arc::new(Semaphore::new(limit)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn status(&self, provider: Endpoint) -> LimitStatus {
+        LimitStatus {
+            provider,
+            limit: self.limit,
+            in_flight: self.limit.saturating_sub(self.semaphore.available_permits()),
+            waiting: self.waiting.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LimitStatus {
+    pub provider: Endpoint,
+    pub limit: usize,
+    pub in_flight: usize,
+    pub waiting: usize,
+}
+
+fn limiters() -> &'static Mutex<HashMap<Endpoint, Limiter>> {
+    static LIMITERS: OnceLock<Mutex<HashMap<Endpoint, Limiter>>> = OnceLock::new();
+    LIMITERS.get_or_init(|| {
+        let defaults = ConcurrencyLimits::default();
+        Mutex::new(
+            ENDPOINTS
+                .iter()
+                .map(|endpoint| (*endpoint, Limiter::new(defaults.get(*endpoint))))
+                .collect(),
+        )
+    })
+}
+
+// Apply the configured limits. A changed limit gets a fresh semaphore;
+// requests already holding the old one finish undisturbed.
+pub fn configure(limits: &ConcurrencyLimits) {
+    if let Ok(mut limiters) = limiters().lock() {
+        reconfigure(&mut limiters, limits);
+    }
+}
+
+fn reconfigure(limiters: &mut HashMap<Endpoint, Limiter>, limits: &ConcurrencyLimits) {
+    for endpoint in ENDPOINTS {
+        let limit = limits.get(endpoint).max(1);
+        if limiters.get(&endpoint).map(|limiter| limiter.limit) != Some(limit) {
+            limiters.insert(endpoint, Limiter::new(limit));
+        }
+    }
+}
+
+// Wait for a free slot; hold the permit until the response has been read
+pub async fn acquire(endpoint: Endpoint) -> Result<OwnedSemaphorePermit, String> {
+    endpoints::ensure_online()?;
+    let (semaphore, waiting) = {
+        let limiters = limiters().lock().map_err(|e| e.to_string())?;
+        let limiter = limiters
+            .get(&endpoint)
+            .ok_or(format!("No limiter for {:?}", endpoint))?;
+        (limiter.semaphore.clone(), limiter.waiting.clone())
+    };
+    let permit = wait_for_slot(semaphore, waiting).await?;
+    // Injected latency holds the slot, as a slow provider would
+    faults::before_request(endpoint).await?;
+    Ok(permit)
+}
+
+// Counted as waiting until the permit is granted
+async fn wait_for_slot(semaphore: Arc<Semaphore>, waiting: Arc<AtomicUsize>) -> Result<OwnedSemaphorePermit, String> {
+    waiting.fetch_add(1, Ordering::Relaxed);
+    let permit = semaphore.acquire_owned().await;
+    waiting.fetch_sub(1, Ordering::Relaxed);
+    permit.map_err(|e| e.to_string())
+}
+
+fn statuses() -> Result<Vec<LimitStatus>, String> {
+    let limiters = limiters().lock().map_err(|e| e.to_string())?;
+    Ok(ENDPOINTS
+        .iter()
+        .filter_map(|endpoint| limiters.get(endpoint).map(|limiter| limiter.status(*endpoint)))
+        .collect())
+}
+
+#[tauri::command]
+pub fn get_concurrency_limits() -> Result<Vec<LimitStatus>, String> {
+    statuses()
+}
+
+// Command to change how many requests may run at once against a provider
+#[tauri::command]
+pub fn set_concurrency_limit(
+    provider: Endpoint,
+    limit: usize,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<LimitStatus>, String> {
+    if limit == 0 {
+        return Err("The limit must be at least 1".to_string());
+    }
+    let mut updated = settings::current(&settings_state);
+    updated.concurrency.set(provider, limit);
+    settings::update_settings(updated, app_handle, settings_state)?;
+    statuses()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn requests_past_the_limit_wait_for_a_slot() {
+        let limiter = Limiter::new(1);
+        let slot = || wait_for_slot(limiter.semaphore.clone(), limiter.waiting.clone());
+
+        let permit = slot().await.unwrap();
+        assert_eq!(limiter.status(Endpoint::Deepgram).in_flight, 1);
+        let second = tokio::spawn(slot());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(limiter.status(Endpoint::Deepgram).waiting, 1);
+        assert!(!second.is_finished());
+
+        drop(permit);
+        let permit = tokio::time::timeout(Duration::from_secs(1), second).await.unwrap().unwrap().unwrap();
+        let status = limiter.status(Endpoint::Deepgram);
+        assert_eq!((status.in_flight, status.waiting), (1, 0));
+        drop(permit);
+        assert_eq!(limiter.status(Endpoint::Deepgram).in_flight, 0);
+    }
+
+    #[test]
+    fn only_changed_limits_get_a_new_semaphore() {
+        let mut limiters = HashMap::new();
+        reconfigure(&mut limiters, &ConcurrencyLimits::default());
+        assert_eq!(limiters.len(), ENDPOINTS.len());
+        let gemini = limiters[&Endpoint::Gemini].semaphore.clone();
+
+        let mut limits = ConcurrencyLimits::default();
+        limits.set(Endpoint::OpenAi, 0);
+        reconfigure(&mut limiters, &limits);
+        // Zero would block every request, so at least one is let through
+        assert_eq!(limiters[&Endpoint::OpenAi].limit, 1);
+        assert!(Arc::ptr_eq(&limiters[&Endpoint::Gemini].semaphore, &gemini));
+    }
+}
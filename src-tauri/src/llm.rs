@@ -0,0 +1,708 @@
+// Chat model providers. Each vendor implements ChatProvider and is listed in
+// PROVIDERS; the engine picks one from settings, or per request, and each
+// provider has its own model list and chosen model. Gemini-only features such
+// as search grounding and streaming stay in the engine.
+
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, State};
+
+use crate::consent::{self, ConsentScope};
+use crate::endpoints::{self, Endpoint};
+use crate::engine::{self, GeminiClient};
+use crate::faults;
+use crate::features::{self, Feature};
+use crate::limits;
+use crate::local_llm;
+use crate::models;
+use crate::ollama::{self, OllamaClient};
+use crate::provider_keys;
+use crate::redaction::{self, Redactions};
+use crate::settings::{self, AppSettings, EngineProvider, GenerationSettings, SettingsState};
+use crate::structured;
+use crate::usage::{self, Provider};
+
+// The first model of each list is the default
+const OPENAI_MODELS: &[&str] = &["gpt-4o-mini", "gpt-4o"];
+const ANTHROPIC_MODELS: &[&str] = &["claude-3-5-haiku-latest", "claude-3-5-sonnet-latest"];
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const ANTHROPIC_MAX_TOKENS: u32 = 1024;
+
+pub trait ChatProvider: Send + Sync {
+    // Stable id, also used for routing stats and per-provider model choices
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    // Whether prompts stay on the device or local network
+    fn local(&self) -> bool;
+    // Why the provider cannot be used right now, such as a missing key or consent
+    fn unavailable(&self, app_handle: &AppHandle, app_settings: &AppSettings) -> Option<String>;
+    // Models that can be picked for this provider
+    fn models<'a>(&'a self, app_settings: &'a AppSettings) -> BoxFuture<'a, Vec<String>>;
+    // The model picked in settings; the provider's default when unset
+    fn model(&self, app_settings: &AppSettings) -> Option<String> {
+        app_settings.llm_models.get(self.id()).cloned()
+    }
+    // Answer a prompt, with an optional system instruction such as the persona
+    fn complete<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String, String>>;
+    // Answer with JSON matching `schema`; providers without a JSON mode are
+    // asked for it in the prompt
+    fn complete_json<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+        schema: &'a Value,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            let prompt = format!("{}\n\nReply with only JSON matching this JSON schema:\n{}", prompt, schema);
+            self.complete(app_handle, app_settings, &prompt, system).await
+        }
+        .boxed()
+    }
+}
+
+fn static_models(models: &[&str]) -> BoxFuture<'static, Vec<String>> {
+    let models: Vec<String> = models.iter().map(|model| model.to_string()).collect();
+    async move { models }.boxed()
+}
+
+// What a hosted provider needs before a prompt can be sent
+fn hosted_unavailable(app_handle: &AppHandle, scope: ConsentScope, provider: Provider, name: &str) -> Option<String> {
+    if !consent::granted(app_handle, scope) {
+        return Some(format!("Sending prompts to {} has not been consented to", name));
+    }
+    if let Err(e) = endpoints::ensure_online() {
+        return Some(e);
+    }
+    provider_keys::select_key(app_handle, provider).err()
+}
+
+pub struct Gemini;
+
+impl ChatProvider for Gemini {
+    fn id(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn name(&self) -> &'static str {
+        "Google Gemini"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        hosted_unavailable(app_handle, ConsentScope::GeminiText, Provider::Gemini, "Google Gemini")
+    }
+
+    fn models<'a>(&'a self, _app_settings: &'a AppSettings) -> BoxFuture<'a, Vec<String>> {
+        static_models(engine::GEMINI_MODELS)
+    }
+
+    fn complete<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            GeminiClient::for_app(app_handle)?
+                .with_settings(app_settings)
+                .with_system(system.map(str::to_string))
+                .generate(prompt)
+                .await
+        }
+        .boxed()
+    }
+
+    fn complete_json<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+        schema: &'a Value,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            GeminiClient::for_app(app_handle)?
+                .with_settings(app_settings)
+                .with_system(system.map(str::to_string))
+                .with_response_schema(structured::for_gemini(schema))
+                .generate(prompt)
+                .await
+        }
+        .boxed()
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAiCompletion {
+    choices: Vec<OpenAiChoice>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice {
+    message: OpenAiMessage,
+}
+
+#[derive(Deserialize)]
+struct OpenAiMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+fn openai_body(
+    model: &str,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+    schema: Option<&Value>,
+) -> Value {
+    let mut messages = Vec::new();
+    if let Some(system) = system {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    messages.push(json!({ "role": "user", "content": prompt }));
+    let mut body = json!({ "model": model, "messages": messages });
+    if let Some(temperature) = generation.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = generation.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    if let Some(max_output_tokens) = generation.max_output_tokens {
+        body["max_tokens"] = json!(max_output_tokens);
+    }
+    if let Some(schema) = schema {
+        body["response_format"] = json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": schema },
+        });
+    }
+    body
+}
+
+async fn complete_with_openai(
+    app_handle: &AppHandle,
+    model: &str,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+    schema: Option<&Value>,
+) -> Result<String, String> {
+    consent::require(app_handle, ConsentScope::OpenAiText)?;
+    // OpenAI keys and usage are tracked under the Whisper provider
+    let mut redactions = Redactions::default();
+    let prompt = if redaction::enabled(app_handle, Provider::Whisper) {
+        redactions.mask(prompt)
+    } else {
+        prompt.to_string()
+    };
+    let body = openai_body(model, &prompt, system, generation, schema);
+
+    let key = provider_keys::select_key(app_handle, Provider::Whisper)?;
+    let _permit = limits::acquire(Endpoint::OpenAi).await?;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", endpoints::base_url(Endpoint::OpenAi)))
+        .bearer_auth(&key.secret)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    provider_keys::report(app_handle, &key.id, response.status().as_u16());
+    if !response.status().is_success() {
+        return Err(format!("OpenAI API error: {}", response.status()));
+    }
+
+    let completion: OpenAiCompletion = faults::json(Endpoint::OpenAi, response).await?;
+    if let Some(tokens) = &completion.usage {
+        usage::record(app_handle, Provider::Whisper, model, tokens.prompt_tokens, tokens.completion_tokens, 0.0);
+    }
+    let text = completion
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .ok_or("No response from OpenAI".to_string())?;
+    Ok(redactions.restore(&text))
+}
+
+pub struct OpenAi;
+
+impl ChatProvider for OpenAi {
+    fn id(&self) -> &'static str {
+        "openai"
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        hosted_unavailable(app_handle, ConsentScope::OpenAiText, Provider::Whisper, "OpenAI")
+    }
+
+    fn models<'a>(&'a self, _app_settings: &'a AppSettings) -> BoxFuture<'a, Vec<String>> {
+        static_models(OPENAI_MODELS)
+    }
+
+    fn complete<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            let model = self.model(app_settings).unwrap_or_else(|| OPENAI_MODELS[0].to_string());
+            complete_with_openai(app_handle, &model, prompt, system, &app_settings.generation, None).await
+        }
+        .boxed()
+    }
+
+    fn complete_json<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+        schema: &'a Value,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            let model = self.model(app_settings).unwrap_or_else(|| OPENAI_MODELS[0].to_string());
+            complete_with_openai(app_handle, &model, prompt, system, &app_settings.generation, Some(schema)).await
+        }
+        .boxed()
+    }
+}
+
+#[derive(Deserialize)]
+struct AnthropicMessage {
+    content: Vec<AnthropicContent>,
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicContent {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+fn anthropic_body(model: &str, prompt: &str, system: Option<&str>, generation: &GenerationSettings) -> Value {
+    let mut body = json!({
+        "model": model,
+        "max_tokens": generation.max_output_tokens.unwrap_or(ANTHROPIC_MAX_TOKENS),
+        "messages": [{ "role": "user", "content": prompt }],
+    });
+    if let Some(system) = system {
+        body["system"] = json!(system);
+    }
+    // Anthropic's temperature only goes up to 1
+    if let Some(temperature) = generation.temperature {
+        body["temperature"] = json!(temperature.min(1.0));
+    }
+    if let Some(top_p) = generation.top_p {
+        body["top_p"] = json!(top_p);
+    }
+    body
+}
+
+async fn complete_with_anthropic(
+    app_handle: &AppHandle,
+    model: &str,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+) -> Result<String, String> {
+    consent::require(app_handle, ConsentScope::AnthropicText)?;
+    let mut redactions = Redactions::default();
+    let prompt = if redaction::enabled(app_handle, Provider::Anthropic) {
+        redactions.mask(prompt)
+    } else {
+        prompt.to_string()
+    };
+    let body = anthropic_body(model, &prompt, system, generation);
+
+    let key = provider_keys::select_key(app_handle, Provider::Anthropic)?;
+    let _permit = limits::acquire(Endpoint::Anthropic).await?;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", endpoints::base_url(Endpoint::Anthropic)))
+        .header("x-api-key", &key.secret)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    provider_keys::report(app_handle, &key.id, response.status().as_u16());
+    if !response.status().is_success() {
+        return Err(format!("Anthropic API error: {}", response.status()));
+    }
+
+    let message: AnthropicMessage = faults::json(Endpoint::Anthropic, response).await?;
+    if let Some(tokens) = &message.usage {
+        usage::record(app_handle, Provider::Anthropic, model, tokens.input_tokens, tokens.output_tokens, 0.0);
+    }
+    let text = message
+        .content
+        .into_iter()
+        .filter_map(|content| content.text)
+        .collect::<Vec<_>>()
+        .join("");
+    if text.is_empty() {
+        return Err("No response from Anthropic".to_string());
+    }
+    Ok(redactions.restore(&text))
+}
+
+pub struct Anthropic;
+
+impl ChatProvider for Anthropic {
+    fn id(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn name(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        hosted_unavailable(app_handle, ConsentScope::AnthropicText, Provider::Anthropic, "Anthropic")
+    }
+
+    fn models<'a>(&'a self, _app_settings: &'a AppSettings) -> BoxFuture<'a, Vec<String>> {
+        static_models(ANTHROPIC_MODELS)
+    }
+
+    fn complete<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            let model = self.model(app_settings).unwrap_or_else(|| ANTHROPIC_MODELS[0].to_string());
+            complete_with_anthropic(app_handle, &model, prompt, system, &app_settings.generation).await
+        }
+        .boxed()
+    }
+}
+
+pub struct Ollama;
+
+impl ChatProvider for Ollama {
+    fn id(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    fn local(&self) -> bool {
+        true
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        features::require(app_handle, Feature::LocalLlm).err()
+    }
+
+    // Models installed on the server; none when it can't be reached
+    fn models<'a>(&'a self, app_settings: &'a AppSettings) -> BoxFuture<'a, Vec<String>> {
+        async move {
+            OllamaClient::from_settings(&app_settings.ollama)
+                .list_models()
+                .await
+                .map(|models| models.into_iter().map(|model| model.name).collect())
+                .unwrap_or_default()
+        }
+        .boxed()
+    }
+
+    fn model(&self, app_settings: &AppSettings) -> Option<String> {
+        app_settings.ollama.model.clone()
+    }
+
+    fn complete<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            features::require(app_handle, Feature::LocalLlm)?;
+            let response = ollama::respond(&app_settings.ollama, prompt, system, &app_settings.generation).await?;
+            let model = app_settings.ollama.model.as_deref().unwrap_or("default");
+            usage::record(app_handle, Provider::Ollama, model, 0, 0, 0.0);
+            Ok(response)
+        }
+        .boxed()
+    }
+}
+
+pub struct OnDevice;
+
+impl OnDevice {
+    // The chosen model when installed, otherwise the smallest installed one
+    fn installed_model(&self, app_handle: &AppHandle, app_settings: &AppSettings) -> Option<String> {
+        self.model(app_settings)
+            .into_iter()
+            .chain(models::CHAT_MODELS.iter().map(|id| id.to_string()))
+            .find(|id| models::is_model_installed(app_handle, id))
+    }
+
+    // Answer a prompt, passing each piece of the answer to `on_text` as it is
+    // generated; the answer stops early when `on_text` returns false
+    pub async fn stream(
+        &self,
+        app_handle: &AppHandle,
+        app_settings: &AppSettings,
+        prompt: &str,
+        system: Option<&str>,
+        on_text: impl FnMut(&str) -> bool + Send + 'static,
+    ) -> Result<String, String> {
+        let model = self
+            .installed_model(app_handle, app_settings)
+            .ok_or("No on-device chat model has been downloaded".to_string())?;
+        local_llm::complete(app_handle, &model, prompt, system, &app_settings.generation, on_text).await
+    }
+}
+
+impl ChatProvider for OnDevice {
+    fn id(&self) -> &'static str {
+        "on_device"
+    }
+
+    fn name(&self) -> &'static str {
+        "On-device model"
+    }
+
+    fn local(&self) -> bool {
+        true
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, app_settings: &AppSettings) -> Option<String> {
+        if let Err(e) = features::require(app_handle, Feature::LocalLlm) {
+            return Some(e);
+        }
+        if self.installed_model(app_handle, app_settings).is_none() {
+            return Some("No on-device chat model has been downloaded".to_string());
+        }
+        None
+    }
+
+    fn models<'a>(&'a self, _app_settings: &'a AppSettings) -> BoxFuture<'a, Vec<String>> {
+        static_models(models::CHAT_MODELS)
+    }
+
+    fn complete<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        app_settings: &'a AppSettings,
+        prompt: &'a str,
+        system: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<String, String>> {
+        async move {
+            features::require(app_handle, Feature::LocalLlm)?;
+            self.stream(app_handle, app_settings, prompt, system, |_| true).await
+        }
+        .boxed()
+    }
+}
+
+static PROVIDERS: &[&dyn ChatProvider] = &[&Gemini, &OpenAi, &Anthropic, &Ollama, &OnDevice];
+
+// Pick the model a provider uses, in the settings where that provider keeps it
+pub fn choose_model(app_settings: &mut AppSettings, provider: &dyn ChatProvider, model: String) {
+    if provider.id() == Ollama.id() {
+        app_settings.ollama.model = Some(model);
+    } else {
+        app_settings.llm_models.insert(provider.id().to_string(), model);
+    }
+}
+
+// The provider behind a setting; None for Auto, which the engine resolves
+pub fn for_setting(provider: EngineProvider) -> Option<&'static dyn ChatProvider> {
+    match provider {
+        EngineProvider::Gemini => Some(&Gemini),
+        EngineProvider::OpenAi => Some(&OpenAi),
+        EngineProvider::Anthropic => Some(&Anthropic),
+        EngineProvider::Ollama => Some(&Ollama),
+        EngineProvider::OnDevice => Some(&OnDevice),
+        EngineProvider::Auto => None,
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LlmProviderInfo {
+    pub id: String,
+    pub name: String,
+    pub local: bool,
+    pub available: bool,
+    pub unavailable_reason: Option<String>,
+    pub selected: bool,
+    pub models: Vec<String>,
+    // The model picked for this provider; its default when unset
+    pub model: Option<String>,
+}
+
+async fn list(app_handle: &AppHandle, app_settings: &AppSettings) -> Vec<LlmProviderInfo> {
+    let selected = for_setting(app_settings.engine_provider).map(|provider| provider.id());
+    let mut providers = Vec::with_capacity(PROVIDERS.len());
+    for provider in PROVIDERS {
+        let reason = provider.unavailable(app_handle, app_settings);
+        providers.push(LlmProviderInfo {
+            id: provider.id().to_string(),
+            name: provider.name().to_string(),
+            local: provider.local(),
+            available: reason.is_none(),
+            unavailable_reason: reason,
+            selected: selected == Some(provider.id()),
+            models: provider.models(app_settings).await,
+            model: provider.model(app_settings),
+        });
+    }
+    providers
+}
+
+#[tauri::command]
+pub async fn list_llm_providers(
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<LlmProviderInfo>, String> {
+    Ok(list(&app_handle, &settings::current(&settings_state)).await)
+}
+
+// Command to choose the chat provider used by default and, optionally, the
+// model it should use
+#[tauri::command]
+pub async fn set_llm_provider(
+    provider: EngineProvider,
+    model: Option<String>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<LlmProviderInfo>, String> {
+    let mut updated = settings::current(&settings_state);
+    updated.engine_provider = provider;
+    if let Some(model) = model {
+        let chat = for_setting(provider).ok_or("Pick a provider before choosing its model".to_string())?;
+        if !chat.models(&updated).await.contains(&model) {
+            return Err(format!("{} does not offer the model {}", chat.name(), model));
+        }
+        choose_model(&mut updated, chat, model);
+    }
+    settings::update_settings(updated.clone(), app_handle.clone(), settings_state)?;
+    Ok(list(&app_handle, &updated).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_map_to_providers() {
+        assert!(for_setting(EngineProvider::Auto).is_none());
+        for provider in [
+            EngineProvider::Gemini,
+            EngineProvider::OpenAi,
+            EngineProvider::Anthropic,
+            EngineProvider::Ollama,
+            EngineProvider::OnDevice,
+        ] {
+            let chat = for_setting(provider).unwrap();
+            assert!(PROVIDERS.iter().any(|listed| listed.id() == chat.id()));
+        }
+        let mut ids: Vec<&str> = PROVIDERS.iter().map(|provider| provider.id()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), PROVIDERS.len());
+    }
+
+    #[test]
+    fn openai_body_sends_the_system_prompt_first() {
+        let body = openai_body("gpt-4o", "Hi", Some("Be brief"), &GenerationSettings::default(), None);
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(
+            body["messages"],
+            json!([{ "role": "system", "content": "Be brief" }, { "role": "user", "content": "Hi" }])
+        );
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("response_format").is_none());
+    }
+
+    #[test]
+    fn anthropic_body_keeps_the_system_prompt_apart() {
+        let body = anthropic_body("claude", "Hi", Some("Be brief"), &GenerationSettings::default());
+        assert_eq!(body["system"], "Be brief");
+        assert_eq!(body["messages"], json!([{ "role": "user", "content": "Hi" }]));
+        assert_eq!(body["max_tokens"], ANTHROPIC_MAX_TOKENS);
+        assert!(anthropic_body("claude", "Hi", None, &GenerationSettings::default()).get("system").is_none());
+    }
+
+    #[test]
+    fn generation_settings_reach_each_provider() {
+        let generation = GenerationSettings {
+            temperature: Some(1.5),
+            top_p: Some(0.5),
+            max_output_tokens: Some(200),
+            ..Default::default()
+        };
+        let body = openai_body("gpt-4o", "Hi", None, &generation, None);
+        assert_eq!((body["temperature"].clone(), body["top_p"].clone()), (json!(1.5), json!(0.5)));
+        assert_eq!(body["max_tokens"], 200);
+        let body = anthropic_body("claude", "Hi", None, &generation);
+        assert_eq!((body["temperature"].clone(), body["top_p"].clone()), (json!(1.0), json!(0.5)));
+        assert_eq!(body["max_tokens"], 200);
+    }
+
+    #[test]
+    fn chosen_models_are_kept_per_provider() {
+        let mut app_settings = AppSettings::default();
+        choose_model(&mut app_settings, &OpenAi, "gpt-4o".to_string());
+        choose_model(&mut app_settings, &Ollama, "llama3".to_string());
+        assert_eq!(OpenAi.model(&app_settings).as_deref(), Some("gpt-4o"));
+        assert_eq!(Ollama.model(&app_settings).as_deref(), Some("llama3"));
+        assert_eq!(Anthropic.model(&app_settings), None);
+        assert!(!app_settings.llm_models.contains_key(Ollama.id()));
+    }
+
+    #[test]
+    fn schema_asks_openai_for_json() {
+        let schema = json!({ "type": "object" });
+        let body = openai_body("gpt-4o", "Hi", None, &GenerationSettings::default(), Some(&schema));
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["schema"], schema);
+    }
+}
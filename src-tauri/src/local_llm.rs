@@ -0,0 +1,346 @@
+// On-device chat with a small quantized model run by Candle, so prompts can
+// be answered without a network. Models live next to the Whisper ones under
+// models/<id>/ as model.gguf and the base model's tokenizer.json; the
+// architecture is read from the GGUF metadata.
+
+use candle_core::quantized::gguf_file;
+use candle_core::{DType, Device, Tensor};
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::{quantized_phi3, quantized_qwen2};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tokenizers::Tokenizer;
+
+use crate::acceleration;
+use crate::settings::{GenerationSettings, InferenceBackend};
+use crate::whisper::{self, ResidentModel};
+
+const MAX_NEW_TOKENS: usize = 512;
+// Longer prompts lose their beginning, which for conversations is the oldest turns
+const MAX_PROMPT_TOKENS: usize = 3072;
+const TEMPERATURE: f64 = 0.7;
+const TOP_P: f64 = 0.9;
+const REPEAT_PENALTY: f32 = 1.1;
+const REPEAT_LAST_N: usize = 64;
+const SEED: u64 = 299_792_458;
+
+enum Model {
+    Phi3(quantized_phi3::ModelWeights),
+    Qwen2(quantized_qwen2::ModelWeights),
+}
+
+impl Model {
+    // Logits for the token after `x`; a position of 0 starts a fresh cache
+    fn forward(&mut self, x: &Tensor, position: usize) -> candle_core::Result<Tensor> {
+        match self {
+            Model::Phi3(model) => model.forward(x, position),
+            Model::Qwen2(model) => model.forward(x, position),
+        }
+    }
+
+    fn template(&self) -> Template {
+        match self {
+            Model::Phi3(_) => Template::Phi3,
+            Model::Qwen2(_) => Template::Qwen2,
+        }
+    }
+}
+
+// The chat format a model was tuned on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Template {
+    Phi3,
+    Qwen2,
+}
+
+impl Template {
+    // The prompt in the model's chat template, ending where its answer begins
+    fn chat_prompt(self, prompt: &str, system: Option<&str>) -> String {
+        match self {
+            Template::Phi3 => {
+                let system = system.map(|system| format!("<|system|>\n{}<|end|>\n", system)).unwrap_or_default();
+                format!("{}<|user|>\n{}<|end|>\n<|assistant|>\n", system, prompt)
+            }
+            Template::Qwen2 => {
+                let system = system.unwrap_or("You are a helpful assistant.");
+                format!(
+                    "<|im_start|>system\n{}<|im_end|>\n<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n",
+                    system, prompt
+                )
+            }
+        }
+    }
+
+    fn stop_tokens(self) -> &'static [&'static str] {
+        match self {
+            Template::Phi3 => &["<|end|>", "<|endoftext|>"],
+            Template::Qwen2 => &["<|im_end|>", "<|endoftext|>"],
+        }
+    }
+}
+
+pub struct LoadedModel {
+    id: String,
+    backend: InferenceBackend,
+    device: Device,
+    model: Model,
+    tokenizer: Tokenizer,
+    // Size of the weights on disk, a close estimate of resident memory
+    bytes: u64,
+    last_used: Instant,
+}
+
+// The loaded model is kept between prompts; loading takes seconds
+#[derive(Default)]
+pub struct LocalLlmStore {
+    loaded: Option<LoadedModel>,
+}
+
+pub type LocalLlmState = Mutex<LocalLlmStore>;
+
+fn load(app_handle: &AppHandle, id: &str, backend: InferenceBackend) -> Result<LoadedModel, String> {
+    let dir = whisper::model_dir(app_handle, id)?;
+    if !dir.exists() {
+        return Err(format!("Offline model {} is not installed", id));
+    }
+    load_dir(&dir, id, backend)
+}
+
+fn load_dir(dir: &Path, id: &str, backend: InferenceBackend) -> Result<LoadedModel, String> {
+    let device = acceleration::device(backend)?;
+    let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| e.to_string())?;
+    let weights = dir.join("model.gguf");
+    let bytes = std::fs::metadata(&weights).map(|metadata| metadata.len()).unwrap_or(0);
+    let mut file = std::fs::File::open(&weights).map_err(|e| e.to_string())?;
+    let content = gguf_file::Content::read(&mut file).map_err(|e| e.to_string())?;
+    let architecture = content
+        .metadata
+        .get("general.architecture")
+        .and_then(|value| value.to_string().ok())
+        .cloned()
+        .unwrap_or_default();
+    let model = match architecture.as_str() {
+        "phi3" => Model::Phi3(
+            quantized_phi3::ModelWeights::from_gguf(false, content, &mut file, &device).map_err(|e| e.to_string())?,
+        ),
+        "qwen2" => Model::Qwen2(
+            quantized_qwen2::ModelWeights::from_gguf(content, &mut file, &device).map_err(|e| e.to_string())?,
+        ),
+        other => return Err(format!("Unsupported model architecture: {}", other)),
+    };
+
+    Ok(LoadedModel {
+        id: id.to_string(),
+        backend,
+        device,
+        model,
+        tokenizer,
+        bytes,
+        last_used: Instant::now(),
+    })
+}
+
+// Drop the start of a prompt that would not fit the context
+fn fit_prompt(tokenizer: &Tokenizer, prompt: &str) -> Result<String, String> {
+    let encoding = tokenizer.encode(prompt, false).map_err(|e| e.to_string())?;
+    let ids = encoding.get_ids();
+    if ids.len() <= MAX_PROMPT_TOKENS {
+        return Ok(prompt.to_string());
+    }
+    tokenizer
+        .decode(&ids[ids.len() - MAX_PROMPT_TOKENS..], false)
+        .map_err(|e| e.to_string())
+}
+
+// The text decoded since `emitted` bytes were passed on; None while the last
+// character is still incomplete
+fn new_text(text: &str, emitted: usize) -> Option<&str> {
+    if text.ends_with('\u{fffd}') {
+        return None;
+    }
+    text.get(emitted..).filter(|piece| !piece.is_empty())
+}
+
+// Run `input` through the model and sample the next token
+fn step(
+    loaded: &mut LoadedModel,
+    processor: &mut LogitsProcessor,
+    input: &[u32],
+    position: usize,
+    generated: &[u32],
+) -> candle_core::Result<u32> {
+    let x = Tensor::new(input, &loaded.device)?.unsqueeze(0)?;
+    let logits = loaded.model.forward(&x, position)?.squeeze(0)?.to_dtype(DType::F32)?;
+    let recent = &generated[generated.len().saturating_sub(REPEAT_LAST_N)..];
+    let logits = candle_transformers::utils::apply_repeat_penalty(&logits, REPEAT_PENALTY, recent)?;
+    processor.sample(&logits)
+}
+
+// Generate an answer, passing each new piece of text to `on_text` as it is
+// decoded; generation stops early when `on_text` returns false
+fn generate(
+    loaded: &mut LoadedModel,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+    on_text: &mut dyn FnMut(&str) -> bool,
+) -> Result<String, String> {
+    let template = loaded.model.template();
+    let prompt = template.chat_prompt(&fit_prompt(&loaded.tokenizer, prompt)?, system);
+    let stop: Vec<u32> = template
+        .stop_tokens()
+        .iter()
+        .filter_map(|token| loaded.tokenizer.token_to_id(token))
+        .collect();
+    let mut input = loaded
+        .tokenizer
+        .encode(prompt, true)
+        .map_err(|e| e.to_string())?
+        .get_ids()
+        .to_vec();
+    let mut processor = LogitsProcessor::new(
+        SEED,
+        Some(generation.temperature.map(f64::from).unwrap_or(TEMPERATURE)),
+        Some(generation.top_p.map(f64::from).unwrap_or(TOP_P)),
+    );
+    let mut generated: Vec<u32> = Vec::new();
+    let mut position = 0;
+    let mut emitted = 0;
+    for _ in 0..generation.max_output_tokens.map(|tokens| tokens as usize).unwrap_or(MAX_NEW_TOKENS) {
+        let next = step(loaded, &mut processor, &input, position, &generated).map_err(|e| e.to_string())?;
+        position += input.len();
+        if stop.contains(&next) {
+            break;
+        }
+        generated.push(next);
+        input = vec![next];
+        // Decoding everything so far keeps multi-token characters and spacing intact
+        let text = loaded.tokenizer.decode(&generated, true).map_err(|e| e.to_string())?;
+        if let Some(piece) = new_text(&text, emitted) {
+            let wanted = on_text(piece);
+            emitted = text.len();
+            if !wanted {
+                break;
+            }
+        }
+    }
+    let text = loaded.tokenizer.decode(&generated, true).map_err(|e| e.to_string())?;
+    Ok(text.trim().to_string())
+}
+
+// Drop the cached model if it is the given one, before its files are replaced or deleted
+pub fn unload(app_handle: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app_handle.state::<LocalLlmState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if store.loaded.as_ref().is_some_and(|loaded| loaded.id == id) {
+        store.loaded = None;
+    }
+    Ok(())
+}
+
+// The cached model, if any, for memory reporting
+pub fn resident(app_handle: &AppHandle) -> Option<ResidentModel> {
+    let state = app_handle.state::<LocalLlmState>();
+    let store = state.lock().ok()?;
+    store.loaded.as_ref().map(|loaded| ResidentModel {
+        id: loaded.id.clone(),
+        bytes: loaded.bytes,
+        idle_secs: loaded.last_used.elapsed().as_secs(),
+    })
+}
+
+// Drop the cached model once it has been unused for `max_idle`; returns what was unloaded
+pub fn unload_idle(app_handle: &AppHandle, max_idle: Duration) -> Option<ResidentModel> {
+    let state = app_handle.state::<LocalLlmState>();
+    let mut store = state.lock().ok()?;
+    if store.loaded.as_ref()?.last_used.elapsed() < max_idle {
+        return None;
+    }
+    store.loaded.take().map(|loaded| ResidentModel {
+        idle_secs: loaded.last_used.elapsed().as_secs(),
+        id: loaded.id,
+        bytes: loaded.bytes,
+    })
+}
+
+// Load the model files in `dir` on the CPU and generate a few tokens, to
+// check downloaded files before they replace an installed model. Blocks.
+pub fn smoke_test(dir: &Path) -> Result<(), String> {
+    let mut loaded = load_dir(dir, "smoke-test", InferenceBackend::Cpu)?;
+    let prompt = loaded.model.template().chat_prompt("Say hello.", None);
+    let input = loaded.tokenizer.encode(prompt, true).map_err(|e| e.to_string())?.get_ids().to_vec();
+    let mut processor = LogitsProcessor::new(SEED, Some(TEMPERATURE), Some(TOP_P));
+    step(&mut loaded, &mut processor, &input, 0, &[])
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Answer a prompt with the given installed model, entirely on device; each
+// piece of the answer goes to `on_text` as soon as it is generated, and
+// returning false from it stops the answer there
+pub async fn complete(
+    app_handle: &AppHandle,
+    model_id: &str,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+    mut on_text: impl FnMut(&str) -> bool + Send + 'static,
+) -> Result<String, String> {
+    let handle = app_handle.clone();
+    let model_id = model_id.to_string();
+    let prompt = prompt.to_string();
+    let system = system.map(str::to_string);
+    let generation = generation.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let backend = acceleration::backend_for(&handle, &model_id);
+        let state = handle.state::<LocalLlmState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        let stale = store
+            .loaded
+            .as_ref()
+            .map(|loaded| loaded.id != model_id || loaded.backend != backend)
+            .unwrap_or(true);
+        if stale {
+            store.loaded = None;
+            store.loaded = Some(load(&handle, &model_id, backend)?);
+        }
+        let loaded = store.loaded.as_mut().ok_or("Offline model is not loaded".to_string())?;
+        let response = generate(loaded, &prompt, system.as_deref(), &generation, &mut on_text);
+        loaded.last_used = Instant::now();
+        response
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_prompts_end_where_the_answer_begins() {
+        assert_eq!(
+            Template::Phi3.chat_prompt("Hi", Some("Be brief")),
+            "<|system|>\nBe brief<|end|>\n<|user|>\nHi<|end|>\n<|assistant|>\n"
+        );
+        assert_eq!(Template::Phi3.chat_prompt("Hi", None), "<|user|>\nHi<|end|>\n<|assistant|>\n");
+        let qwen = Template::Qwen2.chat_prompt("Hi", None);
+        assert!(qwen.starts_with("<|im_start|>system\nYou are a helpful assistant.<|im_end|>\n"));
+        assert!(qwen.ends_with("<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"));
+        for template in [Template::Phi3, Template::Qwen2] {
+            assert!(template.stop_tokens().contains(&"<|endoftext|>"));
+        }
+    }
+
+    #[test]
+    fn new_text_waits_for_whole_characters() {
+        assert_eq!(new_text("Hello", 0), Some("Hello"));
+        assert_eq!(new_text("Hello there", 5), Some(" there"));
+        assert_eq!(new_text("Hello", 5), None);
+        assert_eq!(new_text("Caf\u{fffd}", 0), None);
+        // An offset that no longer falls on a character boundary is skipped
+        assert_eq!(new_text("Café", 4), None);
+    }
+}
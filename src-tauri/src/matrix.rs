@@ -0,0 +1,396 @@
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+use url::Url;
+
+use crate::keystore::Keystore;
+use crate::settings::{self, SettingsState};
+use crate::storage;
+use crate::untrusted;
+
+const ACCOUNT_FILE: &str = "matrix.json";
+const TOKEN_KEY: &str = "matrix_token";
+// Long-poll timeout handed to the homeserver
+const SYNC_TIMEOUT_MS: u64 = 30000;
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+// Recent messages kept per room as context for reply suggestions
+const ROOM_HISTORY: usize = 20;
+
+const SYNC_FILTER: &str = r#"{"room":{"timeline":{"limit":10,"types":["m.room.message","m.room.name"]},"state":{"types":["m.room.name"]},"ephemeral":{"not_types":["*"]}},"presence":{"not_types":["*"]},"account_data":{"not_types":["*"]}}"#;
+
+// The access token lives in the keystore under matrix_token
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatrixAccount {
+    pub homeserver: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    // Sync token from the last successful /sync
+    #[serde(default)]
+    pub since: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MatrixMessage {
+    pub room_id: String,
+    pub sender: String,
+    pub body: String,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MatrixStatus {
+    pub account: Option<MatrixAccount>,
+    pub syncing: bool,
+}
+
+#[derive(Default)]
+pub struct MatrixStore {
+    account: Option<MatrixAccount>,
+    room_names: HashMap<String, String>,
+    history: HashMap<String, Vec<MatrixMessage>>,
+    task: Option<tauri::async_runtime::JoinHandle<()>>,
+}
+
+pub type MatrixState = Mutex<MatrixStore>;
+
+pub fn load(app_handle: &AppHandle) -> MatrixStore {
+    let account: Option<MatrixAccount> = storage::load_json(app_handle, ACCOUNT_FILE);
+    MatrixStore {
+        account,
+        ..Default::default()
+    }
+}
+
+fn endpoint(homeserver: &str, segments: &[&str]) -> Result<Url, String> {
+    let mut url = Url::parse(homeserver).map_err(|e| e.to_string())?;
+    url.path_segments_mut()
+        .map_err(|_| "Invalid homeserver URL".to_string())?
+        .pop_if_empty()
+        .extend(["_matrix", "client", "v3"])
+        .extend(segments);
+    Ok(url)
+}
+
+fn token(app_handle: &AppHandle) -> Result<String, String> {
+    app_handle
+        .state::<Keystore>()
+        .get(TOKEN_KEY)?
+        .ok_or("Not signed in to Matrix".to_string())
+}
+
+fn account(app_handle: &AppHandle) -> Result<MatrixAccount, String> {
+    let state = app_handle.state::<MatrixState>();
+    let store = state.lock().map_err(|e| e.to_string())?;
+    store.account.clone().ok_or("Not signed in to Matrix".to_string())
+}
+
+// Record room names and messages from a /sync response; returns the new
+// messages from other people, with the room name in the sender
+fn record_sync(store: &mut MatrixStore, response: &Value, user_id: &str, initial: bool) -> Vec<MatrixMessage> {
+    let Some(rooms) = response["rooms"]["join"].as_object() else {
+        return Vec::new();
+    };
+
+    let mut incoming = Vec::new();
+    for (room_id, room) in rooms {
+        let state_events = room["state"]["events"].as_array().into_iter().flatten();
+        let timeline = room["timeline"]["events"].as_array().into_iter().flatten();
+        for event in state_events.chain(timeline) {
+            match event["type"].as_str() {
+                Some("m.room.name") => {
+                    if let Some(name) = event["content"]["name"].as_str() {
+                        store.room_names.insert(room_id.clone(), name.to_string());
+                    }
+                }
+                Some("m.room.message") => {
+                    let message = MatrixMessage {
+                        room_id: room_id.clone(),
+                        sender: event["sender"].as_str().unwrap_or_default().to_string(),
+                        body: event["content"]["body"].as_str().unwrap_or_default().to_string(),
+                        timestamp: event["origin_server_ts"].as_u64().unwrap_or(0),
+                    };
+                    let history = store.history.entry(room_id.clone()).or_default();
+                    history.push(message.clone());
+                    if history.len() > ROOM_HISTORY {
+                        history.remove(0);
+                    }
+                    if !initial && message.sender != user_id {
+                        incoming.push(message);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for message in &mut incoming {
+        if let Some(name) = store.room_names.get(&message.room_id) {
+            message.sender = format!("{} in {}", message.sender, name);
+        }
+    }
+    incoming
+}
+
+// Record new messages and surface the ones from other people in the digest
+fn apply_sync(app_handle: &AppHandle, response: &Value, initial: bool) -> Result<(), String> {
+    let user_id = account(app_handle)?.user_id;
+    let incoming = {
+        let state = app_handle.state::<MatrixState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        record_sync(&mut store, response, &user_id, initial)
+    };
+    for message in incoming {
+        crate::notifications::push(app_handle, "matrix", message.sender, message.body, Some(message.room_id))?;
+    }
+    Ok(())
+}
+
+async fn sync_once(app_handle: &AppHandle) -> Result<(), String> {
+    let account = account(app_handle)?;
+    let token = token(app_handle)?;
+    let initial = account.since.is_none();
+
+    let mut request = reqwest::Client::new()
+        .get(endpoint(&account.homeserver, &["sync"])?)
+        .bearer_auth(&token)
+        .timeout(Duration::from_millis(SYNC_TIMEOUT_MS + 15000))
+        .query(&[("filter", SYNC_FILTER)]);
+    if let Some(since) = &account.since {
+        request = request.query(&[("since", since.as_str()), ("timeout", SYNC_TIMEOUT_MS.to_string().as_str())]);
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Matrix sync failed: {}", response.status()));
+    }
+    let body: Value = response.json().await.map_err(|e| e.to_string())?;
+
+    // The first sync only seeds history so old messages don't flood the digest
+    apply_sync(app_handle, &body, initial)?;
+
+    let state = app_handle.state::<MatrixState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if let Some(account) = store.account.as_mut() {
+        account.since = body["next_batch"].as_str().map(|s| s.to_string());
+    }
+    storage::save_json(app_handle, ACCOUNT_FILE, &store.account)
+}
+
+// Start long-polling the homeserver if an account is configured
+pub fn spawn_sync(app_handle: AppHandle) {
+    let state = app_handle.state::<MatrixState>();
+    let Ok(mut store) = state.lock() else { return };
+    if store.account.is_none() {
+        return;
+    }
+    if let Some(task) = store.task.take() {
+        task.abort();
+    }
+
+    let handle = app_handle.clone();
+    store.task = Some(tauri::async_runtime::spawn(async move {
+        loop {
+            if sync_once(&handle).await.is_err() {
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }));
+}
+
+// Command to sign in with a password; only the access token is kept
+#[tauri::command]
+pub async fn matrix_login(
+    homeserver: String,
+    username: String,
+    password: String,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+) -> Result<MatrixAccount, String> {
+    let body = json!({
+        "type": "m.login.password",
+        "identifier": { "type": "m.id.user", "user": username },
+        "password": password,
+        "initial_device_display_name": "Plates",
+    });
+    let response = reqwest::Client::new()
+        .post(endpoint(&homeserver, &["login"])?)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Matrix login failed: {}", response.status()));
+    }
+    let login: Value = response.json().await.map_err(|e| e.to_string())?;
+    let access_token = login["access_token"]
+        .as_str()
+        .ok_or("Matrix login returned no access token")?;
+    keystore.set(TOKEN_KEY, access_token)?;
+
+    let account = MatrixAccount {
+        homeserver,
+        user_id: login["user_id"].as_str().unwrap_or(username.as_str()).to_string(),
+        device_id: login["device_id"].as_str().map(|s| s.to_string()),
+        since: None,
+    };
+    {
+        let state = app_handle.state::<MatrixState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.account = Some(account.clone());
+        store.history.clear();
+        storage::save_json(&app_handle, ACCOUNT_FILE, &store.account)?;
+    }
+
+    spawn_sync(app_handle);
+    Ok(account)
+}
+
+#[tauri::command]
+pub fn matrix_logout(
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, MatrixState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if let Some(task) = store.task.take() {
+        task.abort();
+    }
+    store.account = None;
+    store.history.clear();
+    store.room_names.clear();
+    keystore.delete(TOKEN_KEY)?;
+    storage::save_json(&app_handle, ACCOUNT_FILE, &store.account)
+}
+
+#[tauri::command]
+pub fn get_matrix_status(state: State<'_, MatrixState>) -> Result<MatrixStatus, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(MatrixStatus {
+        account: store.account.clone(),
+        syncing: store.task.is_some(),
+    })
+}
+
+// Command to draft a short reply to a room using the engine
+#[tauri::command]
+pub async fn suggest_matrix_reply(
+    room_id: String,
+    instruction: Option<String>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<String, String> {
+    let (user_id, transcript) = {
+        let state = app_handle.state::<MatrixState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        let user_id = store.account.as_ref().map(|a| a.user_id.clone()).unwrap_or_default();
+        let transcript = store
+            .history
+            .get(&room_id)
+            .map(|messages| {
+                messages
+                    .iter()
+                    .map(|m| format!("{}: {}", m.sender, m.body))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        (user_id, transcript)
+    };
+    if transcript.is_empty() {
+        return Err("No recent messages in this room".to_string());
+    }
+
+    let mut prompt = format!(
+        "You are {}. Write one short, casual chat reply to the conversation below. Reply with the message text only.\n\n{}",
+        user_id,
+        untrusted::wrap("chat message", &transcript)
+    );
+    if let Some(instruction) = instruction {
+        prompt.push_str(&format!("\n\nThe reply should: {}", instruction));
+    }
+    let settings = settings::current(&settings_state);
+    crate::engine::respond(&app_handle, &prompt, &settings).await
+}
+
+#[tauri::command]
+pub async fn send_matrix_message(room_id: String, text: String, app_handle: AppHandle) -> Result<(), String> {
+    let account = account(&app_handle)?;
+    let token = token(&app_handle)?;
+    let txn_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+        .to_string();
+    let url = endpoint(
+        &account.homeserver,
+        &["rooms", &room_id, "send", "m.room.message", &txn_id],
+    )?;
+
+    let response = reqwest::Client::new()
+        .put(url)
+        .bearer_auth(&token)
+        .json(&json!({ "msgtype": "m.text", "body": text }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Sending message failed: {}", response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(sender: &str, body: &str) -> Value {
+        json!({ "type": "m.room.message", "sender": sender, "content": { "body": body }, "origin_server_ts": 1 })
+    }
+
+    #[test]
+    fn endpoints_are_built_under_the_client_api() {
+        assert_eq!(
+            endpoint("https://matrix.example.org/", &["sync"]).unwrap().as_str(),
+            "https://matrix.example.org/_matrix/client/v3/sync"
+        );
+        let url = endpoint("https://example.org/matrix", &["rooms", "!room/a?b:example.org", "send"]).unwrap();
+        assert_eq!(url.path(), "/matrix/_matrix/client/v3/rooms/!room%2Fa%3Fb:example.org/send");
+        assert!(endpoint("not a url", &["sync"]).is_err());
+    }
+
+    #[test]
+    fn syncs_surface_messages_from_others_after_the_first() {
+        let mut store = MatrixStore::default();
+        let response = |events: Vec<Value>| {
+            json!({ "rooms": { "join": { "!team:example.org": {
+                "state": { "events": [{ "type": "m.room.name", "content": { "name": "Team" } }] },
+                "timeline": { "events": events }
+            } } } })
+        };
+
+        let seeded = record_sync(&mut store, &response(vec![message("@ada:example.org", "Morning")]), "@me:example.org", true);
+        assert!(seeded.is_empty());
+
+        let events = vec![message("@ada:example.org", "Lunch?"), message("@me:example.org", "Sure")];
+        let incoming = record_sync(&mut store, &response(events), "@me:example.org", false);
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].sender, "@ada:example.org in Team");
+        assert_eq!(incoming[0].body, "Lunch?");
+        assert_eq!(store.history["!team:example.org"].len(), 3);
+        assert!(record_sync(&mut store, &json!({ "next_batch": "s1" }), "@me:example.org", false).is_empty());
+    }
+
+    #[test]
+    fn room_history_is_capped() {
+        let mut store = MatrixStore::default();
+        let events: Vec<Value> = (0..ROOM_HISTORY + 5).map(|i| message("@ada:example.org", &i.to_string())).collect();
+        record_sync(&mut store, &json!({ "rooms": { "join": { "!r": { "timeline": { "events": events } } } } }), "@me", true);
+        let history = &store.history["!r"];
+        assert_eq!(history.len(), ROOM_HISTORY);
+        assert_eq!(history[0].body, "5");
+    }
+}
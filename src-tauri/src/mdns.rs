@@ -0,0 +1,48 @@
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ResolvedService {
+    pub fullname: String,
+    pub hostname: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub properties: HashMap<String, String>,
+}
+
+// Browse the LAN for a service type (e.g. "_googlecast._tcp.local.") for up to `timeout`.
+// Blocking; call from spawn_blocking.
+pub fn browse(service_type: &str, timeout: Duration) -> Result<Vec<ResolvedService>, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| e.to_string())?;
+    let receiver = daemon.browse(service_type).map_err(|e| e.to_string())?;
+    let deadline = Instant::now() + timeout;
+    let mut services: Vec<ResolvedService> = Vec::new();
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = receiver.recv_timeout(remaining) else {
+            break;
+        };
+        if let ServiceEvent::ServiceResolved(info) = event {
+            if services.iter().any(|s| s.fullname == info.get_fullname()) {
+                continue;
+            }
+            services.push(ResolvedService {
+                fullname: info.get_fullname().to_string(),
+                hostname: info.get_hostname().to_string(),
+                addresses: info.get_addresses().iter().copied().collect(),
+                port: info.get_port(),
+                properties: info
+                    .get_properties()
+                    .iter()
+                    .map(|property| (property.key().to_string(), property.val_str().to_string()))
+                    .collect(),
+            });
+        }
+    }
+
+    let _ = daemon.shutdown();
+    Ok(services)
+}
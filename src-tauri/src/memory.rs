@@ -0,0 +1,181 @@
+// Keeps on-device models from crowding out the rest of the phone: idle models
+// are unloaded after the configured timeout, and everything that can be
+// reloaded later is released when the OS reports memory pressure.
+
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::local_llm;
+use crate::ollama::{self, OllamaClient};
+use crate::settings::{self, SettingsState};
+use crate::whisper;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Under moderate pressure, models used within this window are kept
+const MODERATE_KEEP: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelRuntime {
+    Whisper,
+    Ollama,
+    // Chat models run in the app
+    Local,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelMemory {
+    pub runtime: ModelRuntime,
+    pub model: String,
+    pub bytes: u64,
+    // Unknown for Ollama, which tracks its own idle time
+    pub idle_secs: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MemoryUsage {
+    pub models: Vec<ModelMemory>,
+    pub total_bytes: u64,
+    pub idle_unload_secs: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PressureLevel {
+    Moderate,
+    Critical,
+}
+
+impl PressureLevel {
+    // How recently a model must have been used to survive this pressure
+    fn keep(self) -> Duration {
+        match self {
+            PressureLevel::Moderate => MODERATE_KEEP,
+            PressureLevel::Critical => Duration::ZERO,
+        }
+    }
+}
+
+// Sizes come partly from the Ollama server, so the sum saturates
+fn total_bytes(models: &[ModelMemory]) -> u64 {
+    models.iter().fold(0, |total, model| total.saturating_add(model.bytes))
+}
+
+// Unload idle models on a timer, per the memory settings
+pub fn spawn_monitor(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            let idle_secs = settings::current(&app_handle.state::<SettingsState>()).memory.idle_unload_secs;
+            if idle_secs == 0 {
+                continue;
+            }
+            let max_idle = Duration::from_secs(idle_secs);
+            let released: Vec<String> = whisper::unload_idle(&app_handle, max_idle)
+                .into_iter()
+                .chain(local_llm::unload_idle(&app_handle, max_idle))
+                .map(|model| model.id)
+                .collect();
+            if !released.is_empty() {
+                let _ = app_handle.emit("memory://released", released);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_model_memory_usage(app_handle: AppHandle) -> Result<MemoryUsage, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let mut models: Vec<ModelMemory> = whisper::resident(&app_handle)
+        .map(|model| ModelMemory {
+            runtime: ModelRuntime::Whisper,
+            model: model.id,
+            bytes: model.bytes,
+            idle_secs: Some(model.idle_secs),
+        })
+        .into_iter()
+        .collect();
+    models.extend(local_llm::resident(&app_handle).map(|model| ModelMemory {
+        runtime: ModelRuntime::Local,
+        model: model.id,
+        bytes: model.bytes,
+        idle_secs: Some(model.idle_secs),
+    }));
+
+    // A remote Ollama server's memory is not this device's concern
+    if ollama::is_local(&app_settings.ollama) {
+        let running = OllamaClient::from_settings(&app_settings.ollama)
+            .running_models()
+            .await
+            .unwrap_or_default();
+        models.extend(running.into_iter().map(|model| ModelMemory {
+            runtime: ModelRuntime::Ollama,
+            model: model.name,
+            bytes: model.size,
+            idle_secs: None,
+        }));
+    }
+
+    Ok(MemoryUsage {
+        total_bytes: total_bytes(&models),
+        models,
+        idle_unload_secs: app_settings.memory.idle_unload_secs,
+    })
+}
+
+// Command the platform layer calls on memory warnings (onTrimMemory, didReceiveMemoryWarning).
+// Moderate pressure drops models that are not in use; critical pressure drops everything.
+#[tauri::command]
+pub async fn on_memory_pressure(level: PressureLevel, app_handle: AppHandle) -> Result<Vec<String>, String> {
+    let keep = level.keep();
+    let mut released = Vec::new();
+    if let Some(model) = whisper::unload_idle(&app_handle, keep) {
+        released.push(model.id);
+    }
+    if let Some(model) = local_llm::unload_idle(&app_handle, keep) {
+        released.push(model.id);
+    }
+
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    if level == PressureLevel::Critical && ollama::is_local(&app_settings.ollama) {
+        let client = OllamaClient::from_settings(&app_settings.ollama);
+        for model in client.running_models().await.unwrap_or_default() {
+            if client.unload(&model.name).await.is_ok() {
+                released.push(model.name);
+            }
+        }
+    }
+
+    if !released.is_empty() {
+        let _ = app_handle.emit("memory://released", &released);
+    }
+    Ok(released)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure_levels_come_from_the_platform() {
+        let level: PressureLevel = serde_json::from_str("\"moderate\"").unwrap();
+        assert_eq!(level.keep(), MODERATE_KEEP);
+        let level: PressureLevel = serde_json::from_str("\"critical\"").unwrap();
+        assert_eq!(level.keep(), Duration::ZERO);
+        assert!(serde_json::from_str::<PressureLevel>("\"low\"").is_err());
+    }
+
+    #[test]
+    fn total_bytes_saturates() {
+        let model = |bytes: u64| ModelMemory {
+            runtime: ModelRuntime::Ollama,
+            model: "llama3".to_string(),
+            bytes,
+            idle_secs: None,
+        };
+        assert_eq!(total_bytes(&[]), 0);
+        assert_eq!(total_bytes(&[model(1), model(2)]), 3);
+        assert_eq!(total_bytes(&[model(u64::MAX), model(2)]), u64::MAX);
+    }
+}
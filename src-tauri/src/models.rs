@@ -0,0 +1,836 @@
+// Downloadable Whisper models for offline transcription, Piper voices for
+// offline speech and small chat models for offline answers. Files are fetched
+// from Hugging Face into the layout whisper.rs, piper.rs or local_llm.rs
+// expects and checked against the size and the LFS sha256 or git blob id
+// the hub reports before the model is marked installed.
+// Updates only fetch the files whose hub oid changed, and a model that fails
+// its smoke test never replaces the installed one.
+
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::io::AsyncWriteExt;
+
+use crate::local_llm;
+use crate::piper;
+use crate::whisper;
+
+const HUB_URL: &str = "https://huggingface.co";
+// Progress events are sent at most once per this many bytes
+const PROGRESS_STEP: u64 = 512 * 1024;
+// Hub oids of the installed files, kept in the model dir
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    Whisper,
+    Voice,
+    Llm,
+}
+
+struct CatalogModel {
+    id: &'static str,
+    name: &'static str,
+    kind: ModelKind,
+    repo: &'static str,
+    revision: &'static str,
+    // (file in the repo, file name in the model dir)
+    files: &'static [(&'static str, &'static str)],
+    // Repo to take tokenizer.json from, at main, when the model repo has none
+    tokenizer_repo: Option<&'static str>,
+}
+
+const CATALOG: &[CatalogModel] = &[
+    CatalogModel {
+        id: "whisper-tiny-en-q80",
+        name: "Whisper tiny (English, quantized)",
+        repo: "lmz/candle-whisper",
+        kind: ModelKind::Whisper,
+        revision: "main",
+        files: &[
+            ("config-tiny-en.json", "config.json"),
+            ("tokenizer-tiny-en.json", "tokenizer.json"),
+            ("model-tiny-en-q80.gguf", "model.gguf"),
+        ],
+        tokenizer_repo: None,
+    },
+    CatalogModel {
+        id: "whisper-tiny-q80",
+        name: "Whisper tiny (multilingual, quantized)",
+        repo: "lmz/candle-whisper",
+        kind: ModelKind::Whisper,
+        revision: "main",
+        files: &[
+            ("config-tiny.json", "config.json"),
+            ("tokenizer-tiny.json", "tokenizer.json"),
+            ("model-tiny-q80.gguf", "model.gguf"),
+        ],
+        tokenizer_repo: None,
+    },
+    CatalogModel {
+        id: "whisper-tiny",
+        name: "Whisper tiny (multilingual)",
+        repo: "openai/whisper-tiny",
+        kind: ModelKind::Whisper,
+        revision: "main",
+        files: &[
+            ("config.json", "config.json"),
+            ("tokenizer.json", "tokenizer.json"),
+            ("model.safetensors", "model.safetensors"),
+        ],
+        tokenizer_repo: None,
+    },
+    CatalogModel {
+        id: "whisper-small",
+        name: "Whisper small (multilingual)",
+        repo: "openai/whisper-small",
+        kind: ModelKind::Whisper,
+        revision: "main",
+        files: &[
+            ("config.json", "config.json"),
+            ("tokenizer.json", "tokenizer.json"),
+            ("model.safetensors", "model.safetensors"),
+        ],
+        tokenizer_repo: None,
+    },
+    CatalogModel {
+        id: "piper-en-us-amy-medium",
+        name: "Amy (US English voice)",
+        repo: "rhasspy/piper-voices",
+        kind: ModelKind::Voice,
+        revision: "v1.0.0",
+        files: &[
+            ("en/en_US/amy/medium/en_US-amy-medium.onnx", "model.onnx"),
+            ("en/en_US/amy/medium/en_US-amy-medium.onnx.json", "model.onnx.json"),
+        ],
+        tokenizer_repo: None,
+    },
+    CatalogModel {
+        id: "piper-en-gb-alan-medium",
+        name: "Alan (British English voice)",
+        repo: "rhasspy/piper-voices",
+        kind: ModelKind::Voice,
+        revision: "v1.0.0",
+        files: &[
+            ("en/en_GB/alan/medium/en_GB-alan-medium.onnx", "model.onnx"),
+            ("en/en_GB/alan/medium/en_GB-alan-medium.onnx.json", "model.onnx.json"),
+        ],
+        tokenizer_repo: None,
+    },
+    CatalogModel {
+        id: "qwen2.5-0.5b-instruct-q4",
+        name: "Qwen2.5 0.5B (chat, quantized)",
+        repo: "Qwen/Qwen2.5-0.5B-Instruct-GGUF",
+        kind: ModelKind::Llm,
+        revision: "main",
+        files: &[("qwen2.5-0.5b-instruct-q4_k_m.gguf", "model.gguf")],
+        tokenizer_repo: Some("Qwen/Qwen2.5-0.5B-Instruct"),
+    },
+    CatalogModel {
+        id: "phi-3-mini-4k-instruct-q4",
+        name: "Phi-3 mini (chat, quantized)",
+        repo: "microsoft/Phi-3-mini-4k-instruct-gguf",
+        kind: ModelKind::Llm,
+        revision: "main",
+        files: &[("Phi-3-mini-4k-instruct-q4.gguf", "model.gguf")],
+        tokenizer_repo: Some("microsoft/Phi-3-mini-4k-instruct"),
+    },
+];
+
+// Ids of the voices above, for the local speech backend
+pub const PIPER_VOICES: &[&str] = &["piper-en-us-amy-medium", "piper-en-gb-alan-medium"];
+// Ids of the chat models above, smallest first, for the on-device chat provider
+pub const CHAT_MODELS: &[&str] = &["qwen2.5-0.5b-instruct-q4", "phi-3-mini-4k-instruct-q4"];
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallState {
+    NotInstalled,
+    Downloading,
+    Installed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelStatus {
+    pub id: String,
+    pub name: String,
+    pub kind: ModelKind,
+    pub state: InstallState,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+    pub size_on_disk: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelUpdate {
+    pub id: String,
+    // Files that differ from the hub, by their name in the model dir
+    pub changed_files: Vec<String>,
+    pub download_bytes: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DownloadProgress {
+    pub id: String,
+    pub file: String,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+}
+
+// Entry from the hub's tree listing; oid is the git blob id, which changes
+// with the content, and lfs is present for large files only
+#[derive(Deserialize, Clone)]
+struct HubFile {
+    path: String,
+    oid: String,
+    size: u64,
+    lfs: Option<HubLfs>,
+    // Where the file was listed
+    #[serde(skip)]
+    repo: &'static str,
+    #[serde(skip)]
+    revision: &'static str,
+}
+
+#[derive(Deserialize, Clone)]
+struct HubLfs {
+    oid: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    // File name in the model dir to hub oid
+    files: HashMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct ModelsStore {
+    downloads: HashMap<String, DownloadProgress>,
+}
+
+pub type ModelsState = Mutex<ModelsStore>;
+
+fn catalog_model(id: &str) -> Result<&'static CatalogModel, String> {
+    CATALOG
+        .iter()
+        .find(|model| model.id == id)
+        .ok_or(format!("Unknown model: {}", id))
+}
+
+// Everything a model needs as (repo, revision, file in the repo, file name in the model dir)
+fn sources(model: &CatalogModel) -> Vec<(&'static str, &'static str, &'static str, &'static str)> {
+    let mut sources: Vec<_> = model
+        .files
+        .iter()
+        .map(|(remote_name, local_name)| (model.repo, model.revision, *remote_name, *local_name))
+        .collect();
+    if let Some(repo) = model.tokenizer_repo {
+        sources.push((repo, "main", "tokenizer.json", "tokenizer.json"));
+    }
+    sources
+}
+
+// Sizes come from the hub, so their total must not overflow
+fn total_size<'a>(files: impl Iterator<Item = &'a HubFile>) -> u64 {
+    files.fold(0, |total, file| total.saturating_add(file.size))
+}
+
+fn is_installed(model: &CatalogModel, dir: &Path) -> bool {
+    sources(model).iter().all(|(_, _, _, local_name)| dir.join(local_name).exists())
+}
+
+pub fn is_model_installed(app_handle: &AppHandle, id: &str) -> bool {
+    catalog_model(id)
+        .and_then(|model| Ok(is_installed(model, &whisper::model_dir(app_handle, model.id)?)))
+        .unwrap_or(false)
+}
+
+// Drop a loaded copy of the model before its files change
+fn unload(app_handle: &AppHandle, model: &CatalogModel) -> Result<(), String> {
+    match model.kind {
+        ModelKind::Whisper => whisper::unload(app_handle, model.id),
+        ModelKind::Voice => piper::unload(app_handle, model.id),
+        ModelKind::Llm => local_llm::unload(app_handle, model.id),
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn status(app_handle: &AppHandle, model: &CatalogModel) -> Result<ModelStatus, String> {
+    let download = app_handle
+        .state::<ModelsState>()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .downloads
+        .get(model.id)
+        .cloned();
+    let dir = whisper::model_dir(app_handle, model.id)?;
+    let state = if download.is_some() {
+        InstallState::Downloading
+    } else if is_installed(model, &dir) {
+        InstallState::Installed
+    } else {
+        InstallState::NotInstalled
+    };
+    Ok(ModelStatus {
+        id: model.id.to_string(),
+        name: model.name.to_string(),
+        kind: model.kind,
+        size_on_disk: if state == InstallState::Installed { dir_size(&dir) } else { 0 },
+        state,
+        downloaded_bytes: download.as_ref().map(|d| d.downloaded_bytes).unwrap_or(0),
+        total_bytes: download.and_then(|d| d.total_bytes),
+    })
+}
+
+fn set_progress(app_handle: &AppHandle, progress: DownloadProgress) {
+    if let Ok(mut store) = app_handle.state::<ModelsState>().lock() {
+        store.downloads.insert(progress.id.clone(), progress.clone());
+    }
+    let _ = app_handle.emit("models://progress", progress);
+}
+
+// The hub lists one directory at a time, so every directory holding one of the model's files is listed
+async fn hub_listing(client: &reqwest::Client, model: &CatalogModel) -> Result<Vec<HubFile>, String> {
+    let mut dirs: Vec<(&'static str, &'static str, &str)> = sources(model)
+        .into_iter()
+        .map(|(repo, revision, remote_name, _)| {
+            (repo, revision, remote_name.rsplit_once('/').map(|(dir, _)| dir).unwrap_or(""))
+        })
+        .collect();
+    dirs.dedup();
+    let mut listing = Vec::new();
+    for (repo, revision, dir) in dirs {
+        let files: Vec<HubFile> = client
+            .get(if dir.is_empty() {
+                format!("{}/api/models/{}/tree/{}", HUB_URL, repo, revision)
+            } else {
+                format!("{}/api/models/{}/tree/{}/{}", HUB_URL, repo, revision, dir)
+            })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        listing.extend(files.into_iter().map(|file| HubFile { repo, revision, ..file }));
+    }
+    Ok(listing)
+}
+
+// Checksum of a download in the form the hub reports it: the sha256 for LFS
+// files, the git blob id (sha1 of "blob {size}\0" and the content) for the rest
+enum FileHasher {
+    Lfs(Sha256),
+    Blob(Sha1),
+}
+
+impl FileHasher {
+    fn for_file(remote: &HubFile) -> Self {
+        match remote.lfs {
+            Some(_) => FileHasher::Lfs(Sha256::new()),
+            None => {
+                let mut hasher = Sha1::new();
+                hasher.update(format!("blob {}\0", remote.size));
+                FileHasher::Blob(hasher)
+            }
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            FileHasher::Lfs(hasher) => hasher.update(chunk),
+            FileHasher::Blob(hasher) => hasher.update(chunk),
+        }
+    }
+
+    fn matches(self, remote: &HubFile) -> bool {
+        let (digest, expected) = match (self, &remote.lfs) {
+            (FileHasher::Lfs(hasher), Some(lfs)) => (hasher.finalize().to_vec(), &lfs.oid),
+            (FileHasher::Blob(hasher), None) => (hasher.finalize().to_vec(), &remote.oid),
+            _ => return false,
+        };
+        let digest: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        digest.eq_ignore_ascii_case(expected)
+    }
+}
+
+// Stream one file to disk, hashing as it goes, then verify size and checksum
+async fn fetch_file(
+    app_handle: &AppHandle,
+    client: &reqwest::Client,
+    model: &CatalogModel,
+    remote: &HubFile,
+    target: &Path,
+    downloaded: &mut u64,
+    total: Option<u64>,
+) -> Result<(), String> {
+    let url = format!("{}/{}/resolve/{}/{}", HUB_URL, remote.repo, remote.revision, remote.path);
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let mut file = tokio::fs::File::create(target).await.map_err(|e| e.to_string())?;
+    let mut hasher = FileHasher::for_file(remote);
+    let mut written = 0u64;
+    let mut reported = *downloaded;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        hasher.update(&chunk);
+        written = written.saturating_add(chunk.len() as u64);
+        *downloaded = downloaded.saturating_add(chunk.len() as u64);
+        if *downloaded - reported >= PROGRESS_STEP {
+            reported = *downloaded;
+            set_progress(
+                app_handle,
+                DownloadProgress {
+                    id: model.id.to_string(),
+                    file: remote.path.clone(),
+                    downloaded_bytes: *downloaded,
+                    total_bytes: total,
+                },
+            );
+        }
+    }
+    file.flush().await.map_err(|e| e.to_string())?;
+
+    let _ = app_handle.emit("models://verifying", json!({ "id": model.id, "file": remote.path }));
+    if written != remote.size {
+        return Err(format!("{} is {} bytes, expected {}", remote.path, written, remote.size));
+    }
+    if !hasher.matches(remote) {
+        return Err(format!("Checksum mismatch for {}", remote.path));
+    }
+    Ok(())
+}
+
+fn file_sha256(path: &Path) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn read_manifest(dir: &Path) -> Manifest {
+    std::fs::read_to_string(dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+// Whether the installed copy of a file matches the hub's. Installs from
+// before the manifest was kept are checked by hashing their large files.
+fn unchanged(dir: &Path, manifest: &Manifest, remote: &HubFile, local_name: &str) -> bool {
+    let path = dir.join(local_name);
+    if !path.exists() {
+        return false;
+    }
+    if let Some(oid) = manifest.files.get(local_name) {
+        return *oid == remote.oid;
+    }
+    match &remote.lfs {
+        Some(lfs) => file_sha256(&path).is_ok_and(|digest| digest.eq_ignore_ascii_case(&lfs.oid)),
+        None => false,
+    }
+}
+
+// The hub's files for a model, each with its name in the model dir and
+// whether the installed copy can be kept
+async fn plan(
+    app_handle: &AppHandle,
+    client: &reqwest::Client,
+    model: &CatalogModel,
+) -> Result<Vec<(HubFile, &'static str, bool)>, String> {
+    let listing = hub_listing(client, model).await?;
+    let mut remotes = Vec::new();
+    for (repo, _, remote_name, local_name) in sources(model) {
+        let remote = listing
+            .iter()
+            .find(|file| file.repo == repo && file.path == remote_name)
+            .cloned()
+            .ok_or(format!("{} is missing from {}", remote_name, repo))?;
+        remotes.push((remote, local_name));
+    }
+
+    let dir = whisper::model_dir(app_handle, model.id)?;
+    if !is_installed(model, &dir) {
+        return Ok(remotes.into_iter().map(|(remote, local_name)| (remote, local_name, false)).collect());
+    }
+    // Hashing large files takes a while
+    tauri::async_runtime::spawn_blocking(move || {
+        let manifest = read_manifest(&dir);
+        remotes
+            .into_iter()
+            .map(|(remote, local_name)| {
+                let keep = unchanged(&dir, &manifest, &remote, local_name);
+                (remote, local_name, keep)
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Ids such as qwen2.5-0.5b contain dots, so the suffix is appended rather
+// than swapped in as an extension
+fn sibling_dir(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn previous_dir(dir: &Path) -> PathBuf {
+    sibling_dir(dir, "previous")
+}
+
+// Stage the model with changed files downloaded and unchanged ones copied
+// from the installed model. An update must pass a smoke test before it is
+// swapped in; the replaced model is kept for rollback_model.
+async fn download(app_handle: &AppHandle, model: &CatalogModel) -> Result<(), String> {
+    let client = reqwest::Client::new();
+    let files = plan(app_handle, &client, model).await?;
+    let total = Some(total_size(files.iter().filter(|(_, _, keep)| !keep).map(|(remote, _, _)| remote)));
+
+    let dir = whisper::model_dir(app_handle, model.id)?;
+    let updating = is_installed(model, &dir);
+    let staging = sibling_dir(&dir, "partial");
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    tokio::fs::create_dir_all(&staging).await.map_err(|e| e.to_string())?;
+
+    let mut manifest = Manifest::default();
+    let mut downloaded = 0u64;
+    for (remote, local_name, keep) in &files {
+        let target = staging.join(local_name);
+        let result = if *keep {
+            tokio::fs::copy(dir.join(local_name), &target)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        } else {
+            fetch_file(app_handle, &client, model, remote, &target, &mut downloaded, total).await
+        };
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return Err(e);
+        }
+        manifest.files.insert(local_name.to_string(), remote.oid.clone());
+    }
+    let manifest = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    tokio::fs::write(staging.join(MANIFEST_FILE), manifest)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if updating {
+        let _ = app_handle.emit("models://verifying", json!({ "id": model.id, "file": "" }));
+        let candidate = staging.clone();
+        let kind = model.kind;
+        let tested = tauri::async_runtime::spawn_blocking(move || match kind {
+            ModelKind::Whisper => whisper::smoke_test(&candidate),
+            ModelKind::Voice => piper::smoke_test(&candidate),
+            ModelKind::Llm => local_llm::smoke_test(&candidate),
+        })
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Err(e) = tested {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return Err(format!("The updated model failed its smoke test and was not installed: {}", e));
+        }
+    }
+
+    unload(app_handle, model)?;
+    let previous = previous_dir(&dir);
+    let _ = tokio::fs::remove_dir_all(&previous).await;
+    if updating {
+        tokio::fs::rename(&dir, &previous).await.map_err(|e| e.to_string())?;
+    } else {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+    tokio::fs::rename(&staging, &dir).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_available_models(app_handle: AppHandle) -> Result<Vec<ModelStatus>, String> {
+    CATALOG.iter().map(|model| status(&app_handle, model)).collect()
+}
+
+// Command to compare installed models with the hub; only models with changes are returned
+#[tauri::command]
+pub async fn check_model_updates(app_handle: AppHandle) -> Result<Vec<ModelUpdate>, String> {
+    let client = reqwest::Client::new();
+    let mut updates = Vec::new();
+    for model in CATALOG {
+        if !is_installed(model, &whisper::model_dir(&app_handle, model.id)?) {
+            continue;
+        }
+        let changed: Vec<(HubFile, &str, bool)> = plan(&app_handle, &client, model)
+            .await?
+            .into_iter()
+            .filter(|(_, _, keep)| !keep)
+            .collect();
+        if changed.is_empty() {
+            continue;
+        }
+        updates.push(ModelUpdate {
+            id: model.id.to_string(),
+            download_bytes: total_size(changed.iter().map(|(remote, _, _)| remote)),
+            changed_files: changed.into_iter().map(|(_, local_name, _)| local_name.to_string()).collect(),
+        });
+    }
+    Ok(updates)
+}
+
+#[tauri::command]
+pub fn get_model_status(id: String, app_handle: AppHandle) -> Result<ModelStatus, String> {
+    status(&app_handle, catalog_model(&id)?)
+}
+
+// Command to fetch a model, or update an installed one to the hub's files;
+// progress arrives as models://progress events
+#[tauri::command]
+pub async fn download_model(
+    id: String,
+    app_handle: AppHandle,
+    state: State<'_, ModelsState>,
+) -> Result<ModelStatus, String> {
+    let model = catalog_model(&id)?;
+    {
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        if store.downloads.contains_key(model.id) {
+            return Err("Model is already downloading".to_string());
+        }
+        store.downloads.insert(
+            id.clone(),
+            DownloadProgress {
+                id: id.clone(),
+                file: String::new(),
+                downloaded_bytes: 0,
+                total_bytes: None,
+            },
+        );
+    }
+
+    let result = download(&app_handle, model).await;
+    state.lock().map_err(|e| e.to_string())?.downloads.remove(&id);
+    match result {
+        Ok(()) => {
+            let _ = app_handle.emit("models://installed", &id);
+            status(&app_handle, model)
+        }
+        Err(e) => {
+            let _ = app_handle.emit("models://failed", json!({ "id": id, "error": e }));
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+pub fn delete_model(id: String, app_handle: AppHandle, state: State<'_, ModelsState>) -> Result<(), String> {
+    let model = catalog_model(&id)?;
+    if state.lock().map_err(|e| e.to_string())?.downloads.contains_key(model.id) {
+        return Err("Model is still downloading".to_string());
+    }
+    unload(&app_handle, model)?;
+    let dir = whisper::model_dir(&app_handle, model.id)?;
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    let previous = previous_dir(&dir);
+    if previous.exists() {
+        std::fs::remove_dir_all(&previous).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Command to go back to the files an update replaced
+#[tauri::command]
+pub fn rollback_model(id: String, app_handle: AppHandle, state: State<'_, ModelsState>) -> Result<ModelStatus, String> {
+    let model = catalog_model(&id)?;
+    if state.lock().map_err(|e| e.to_string())?.downloads.contains_key(model.id) {
+        return Err("Model is still downloading".to_string());
+    }
+    let dir = whisper::model_dir(&app_handle, model.id)?;
+    let previous = previous_dir(&dir);
+    if !is_installed(model, &previous) {
+        return Err("There is no earlier version of this model to go back to".to_string());
+    }
+    unload(&app_handle, model)?;
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&previous, &dir).map_err(|e| e.to_string())?;
+    let _ = app_handle.emit("models://installed", &id);
+    status(&app_handle, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("plates-models-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn hub_file(path: &str, size: u64) -> HubFile {
+        HubFile {
+            path: path.to_string(),
+            oid: "abc".to_string(),
+            size,
+            lfs: None,
+            repo: "",
+            revision: "",
+        }
+    }
+
+    #[test]
+    fn catalog_ids_are_unique_and_lists_match_kinds() {
+        for (i, model) in CATALOG.iter().enumerate() {
+            assert!(CATALOG[..i].iter().all(|other| other.id != model.id), "{} is listed twice", model.id);
+        }
+        for id in PIPER_VOICES {
+            assert_eq!(catalog_model(id).unwrap().kind, ModelKind::Voice);
+        }
+        for id in CHAT_MODELS {
+            assert_eq!(catalog_model(id).unwrap().kind, ModelKind::Llm);
+        }
+        assert!(catalog_model("whisper-huge").is_err());
+    }
+
+    #[test]
+    fn sources_map_repo_files_to_model_files() {
+        let tiny = catalog_model("whisper-tiny-en-q80").unwrap();
+        let sources = sources(tiny);
+        assert_eq!(sources.len(), 3);
+        assert_eq!(sources[2], ("lmz/candle-whisper", "main", "model-tiny-en-q80.gguf", "model.gguf"));
+    }
+
+    #[test]
+    fn installed_once_every_file_is_present() {
+        let dir = temp_dir("installed");
+        let tiny = catalog_model("whisper-tiny").unwrap();
+        assert!(!is_installed(tiny, &dir));
+        for name in ["config.json", "tokenizer.json"] {
+            std::fs::write(dir.join(name), "{}").unwrap();
+        }
+        assert!(!is_installed(tiny, &dir));
+        std::fs::write(dir.join("model.safetensors"), [0u8; 16]).unwrap();
+        assert!(is_installed(tiny, &dir));
+        assert_eq!(dir_size(&dir), 20);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn download_sizes_saturate() {
+        let files = [hub_file("a", u64::MAX), hub_file("b", 10)];
+        assert_eq!(total_size(files.iter()), u64::MAX);
+        assert_eq!(total_size(files[1..].iter()), 10);
+    }
+
+    #[test]
+    fn manifest_oids_decide_which_files_changed() {
+        let dir = temp_dir("manifest");
+        std::fs::write(dir.join("config.json"), "{}").unwrap();
+        std::fs::write(
+            dir.join(MANIFEST_FILE),
+            serde_json::json!({ "files": { "config.json": "abc" } }).to_string(),
+        )
+        .unwrap();
+        let manifest = read_manifest(&dir);
+        let remote = hub_file("config.json", 2);
+        assert!(unchanged(&dir, &manifest, &remote, "config.json"));
+        let updated = HubFile {
+            oid: "def".to_string(),
+            ..remote.clone()
+        };
+        assert!(!unchanged(&dir, &manifest, &updated, "config.json"));
+        // A file missing from disk always has to be fetched
+        assert!(!unchanged(&dir, &manifest, &remote, "tokenizer.json"));
+        assert!(read_manifest(&dir.join("missing")).files.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn installs_without_a_manifest_are_checked_by_hash() {
+        let dir = temp_dir("hash");
+        std::fs::write(dir.join("model.gguf"), b"weights").unwrap();
+        let digest = file_sha256(&dir.join("model.gguf")).unwrap();
+        let mut remote = hub_file("model.gguf", 7);
+        remote.lfs = Some(HubLfs {
+            oid: digest.to_uppercase(),
+        });
+        assert!(unchanged(&dir, &Manifest::default(), &remote, "model.gguf"));
+        remote.lfs = Some(HubLfs { oid: "00".repeat(32) });
+        assert!(!unchanged(&dir, &Manifest::default(), &remote, "model.gguf"));
+        // Small files have no hash to compare, so they are fetched again
+        remote.lfs = None;
+        assert!(!unchanged(&dir, &Manifest::default(), &remote, "model.gguf"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn downloads_are_checked_against_the_hub_checksum() {
+        let hashed = |remote: &HubFile, content: &[u8]| {
+            let mut hasher = FileHasher::for_file(remote);
+            for chunk in content.chunks(3) {
+                hasher.update(chunk);
+            }
+            hasher.matches(remote)
+        };
+        // `echo hello | git hash-object --stdin`
+        let small = HubFile {
+            oid: "CE013625030BA8DBA906F756967F9E9CA394464A".to_string(),
+            ..hub_file("config.json", 6)
+        };
+        assert!(hashed(&small, b"hello\n"));
+        assert!(!hashed(&small, b"hellO\n"));
+
+        let large = HubFile {
+            lfs: Some(HubLfs {
+                oid: "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03".to_string(),
+            }),
+            ..hub_file("model.gguf", 6)
+        };
+        assert!(hashed(&large, b"hello\n"));
+        assert!(!hashed(&large, b"hello!"));
+    }
+
+    #[test]
+    fn previous_versions_sit_next_to_the_model() {
+        let dir = Path::new("/data/models/whisper-tiny");
+        assert_eq!(previous_dir(dir), Path::new("/data/models/whisper-tiny.previous"));
+        let qwen = Path::new("/data/models/qwen2.5-0.5b-instruct-q4");
+        assert_eq!(previous_dir(qwen), Path::new("/data/models/qwen2.5-0.5b-instruct-q4.previous"));
+        assert_eq!(sibling_dir(qwen, "partial"), Path::new("/data/models/qwen2.5-0.5b-instruct-q4.partial"));
+    }
+
+    #[test]
+    fn parses_hub_listings() {
+        let raw = r#"[{"type":"file","path":"model.gguf","oid":"1f","size":42,"lfs":{"oid":"ab12","size":42}},
+            {"type":"file","path":"config.json","oid":"2e","size":7}]"#;
+        let files: Vec<HubFile> = serde_json::from_str(raw).unwrap();
+        assert_eq!(files[0].lfs.as_ref().map(|lfs| lfs.oid.as_str()), Some("ab12"));
+        assert_eq!((files[1].path.as_str(), files[1].size), ("config.json", 7));
+        assert!(files[1].lfs.is_none());
+    }
+}
@@ -0,0 +1,201 @@
+// Output moderation for child profiles: a local word list classifier,
+// optionally backed by the OpenAI moderation endpoint.
+
+use serde::{Serialize, Deserialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::consent::{self, ConsentScope};
+use crate::endpoints::{self, Endpoint};
+use crate::faults;
+use crate::limits;
+use crate::provider_keys;
+use crate::redaction::{self, Redactions};
+use crate::settings::{AppSettings, Profile};
+use crate::usage::Provider;
+
+const BLOCKED_REPLACEMENT: &str =
+    "Sorry, I can't help with that. Maybe ask a grown-up, or try asking something else!";
+
+// Words that are masked rather than blocking the whole response
+const PROFANITY: &[&str] = &["damn", "hell", "crap", "shit", "fuck", "bitch", "bastard", "ass"];
+
+// Categories that block the response outright
+const BLOCKED_TERMS: &[(&str, &[&str])] = &[
+    ("violence", &["kill", "murder", "gun", "stab", "shoot", "bomb", "weapon"]),
+    ("sexual", &["sex", "porn", "nude", "naked", "erotic"]),
+    ("self-harm", &["suicide", "self-harm", "cut yourself", "overdose"]),
+    ("drugs", &["cocaine", "heroin", "meth", "weed", "marijuana", "vape"]),
+    ("alcohol", &["vodka", "whiskey", "get drunk"]),
+];
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "verdict", rename_all = "lowercase")]
+pub enum Verdict {
+    Allow,
+    Rewrite { text: String },
+    Block { categories: Vec<String> },
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[derive(Deserialize)]
+struct ModerationResult {
+    flagged: bool,
+    #[serde(default)]
+    categories: std::collections::HashMap<String, bool>,
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_string())
+        .collect()
+}
+
+// Classify text with the local word lists
+pub fn classify_locally(text: &str) -> Verdict {
+    let lowered = text.to_lowercase();
+    let tokens = words(text);
+    let categories: Vec<String> = BLOCKED_TERMS
+        .iter()
+        .filter(|(_, terms)| {
+            terms.iter().any(|term| {
+                if term.contains(' ') {
+                    lowered.contains(term)
+                } else {
+                    tokens.iter().any(|token| token == term)
+                }
+            })
+        })
+        .map(|(category, _)| category.to_string())
+        .collect();
+    if !categories.is_empty() {
+        return Verdict::Block { categories };
+    }
+
+    let mut rewritten = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+        if PROFANITY.contains(&word.to_lowercase().as_str()) {
+            rewritten.push_str(&"*".repeat(word.chars().count()));
+            changed = true;
+        } else {
+            rewritten.push_str(&word);
+        }
+        word.clear();
+        rewritten.push(c);
+    }
+    rewritten.pop();
+
+    if changed {
+        Verdict::Rewrite { text: rewritten }
+    } else {
+        Verdict::Allow
+    }
+}
+
+async fn classify_with_api(app_handle: &AppHandle, text: &str) -> Result<Verdict, String> {
+    consent::require(app_handle, ConsentScope::OpenAiModeration)?;
+    // Placeholders do not change the verdict, so nothing needs restoring
+    let text = if redaction::enabled(app_handle, Provider::Whisper) {
+        Redactions::default().mask(text)
+    } else {
+        text.to_string()
+    };
+    let key = provider_keys::select_key(app_handle, Provider::Whisper)?;
+    let _permit = limits::acquire(Endpoint::OpenAi).await?;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/moderations", endpoints::base_url(Endpoint::OpenAi)))
+        .bearer_auth(&key.secret)
+        .json(&serde_json::json!({ "model": "omni-moderation-latest", "input": text }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    provider_keys::report(app_handle, &key.id, response.status().as_u16());
+    if !response.status().is_success() {
+        return Err(format!("Moderation API error: {}", response.status()));
+    }
+
+    let moderation: ModerationResponse = faults::json(Endpoint::OpenAi, response).await?;
+    let Some(result) = moderation.results.into_iter().next() else {
+        return Ok(Verdict::Allow);
+    };
+    if !result.flagged {
+        return Ok(Verdict::Allow);
+    }
+    let categories = result
+        .categories
+        .into_iter()
+        .filter(|(_, flagged)| *flagged)
+        .map(|(category, _)| category)
+        .collect();
+    Ok(Verdict::Block { categories })
+}
+
+// Classify with the local word lists and, when enabled, the moderation API
+pub async fn classify(app_handle: &AppHandle, text: &str, settings: &AppSettings) -> Verdict {
+    let verdict = classify_locally(text);
+    if !matches!(verdict, Verdict::Block { .. }) && settings.use_moderation_api {
+        // The local list stays in charge when the endpoint is unreachable
+        if let Ok(Verdict::Block { categories }) = classify_with_api(app_handle, text).await {
+            return Verdict::Block { categories };
+        }
+    }
+    verdict
+}
+
+// Moderate a response before it reaches the UI or TTS. Only enforced for child profiles.
+pub async fn moderate(app_handle: &AppHandle, text: String, settings: &AppSettings) -> String {
+    if settings.profile != Profile::Child {
+        return text;
+    }
+
+    match classify(app_handle, &text, settings).await {
+        Verdict::Allow => text,
+        Verdict::Rewrite { text } => text,
+        Verdict::Block { categories } => {
+            let _ = app_handle.emit("moderation://blocked", &categories);
+            BLOCKED_REPLACEMENT.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn harmful_topics_block_the_response() {
+        assert_eq!(
+            classify_locally("How do I build a BOMB or get some weed?"),
+            Verdict::Block { categories: vec!["violence".to_string(), "drugs".to_string()] }
+        );
+        assert_eq!(
+            classify_locally("Grown-ups sometimes get drunk at parties"),
+            Verdict::Block { categories: vec!["alcohol".to_string()] }
+        );
+    }
+
+    #[test]
+    fn whole_words_only_are_matched() {
+        assert_eq!(classify_locally("Skills, a shotgun wedding and a class assessment"), Verdict::Allow);
+        assert_eq!(classify_locally("Dinosaurs lived long ago."), Verdict::Allow);
+    }
+
+    #[test]
+    fn profanity_is_masked() {
+        assert_eq!(
+            classify_locally("Well, Damn! That was hell."),
+            Verdict::Rewrite { text: "Well, ****! That was ****.".to_string() }
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Serialize, Deserialize};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Emitter, Manager, State,
+};
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NfcTag {
+    // Hex serial number of the tag
+    pub id: String,
+    // Decoded NDEF text and URI records
+    #[serde(default)]
+    pub records: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct NfcTagResult {
+    pub tag: NfcTag,
+    pub rules_fired: usize,
+    pub intent: Option<crate::intents::IntentOutcome>,
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct WriteRequest<'a> {
+    payload: &'a str,
+    uri: bool,
+}
+
+pub struct NfcBridge {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+}
+
+impl NfcBridge {
+    // Waits for the user to hold a tag to the phone, then writes one NDEF record
+    fn write(&self, payload: &str) -> Result<NfcTag, String> {
+        #[cfg(android_bridges)]
+        {
+            let uri = payload.contains("://");
+            self.handle
+                .run_mobile_plugin::<NfcTag>("writeTag", WriteRequest { payload, uri })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = payload;
+            Err("NFC is not available on this device".to_string())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("nfc")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = NfcBridge {
+                handle: api.register_android_plugin("company.atechnology.plates", "NfcPlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                NfcBridge {}
+            };
+            app.manage(bridge);
+            Ok(())
+        })
+        .build()
+}
+
+// Command called by the native NFC handler whenever a tag is read
+#[tauri::command]
+pub async fn on_nfc_tag(tag: NfcTag, app_handle: AppHandle) -> Result<NfcTagResult, String> {
+    let rules_fired = crate::rules::on_nfc_tag(&app_handle, &tag.id, &tag.records)?;
+
+    // Tags carrying a plates:// link work without any rule configured
+    let mut intent = None;
+    if rules_fired == 0 {
+        if let Some(uri) = tag.records.iter().find(|record| record.starts_with("plates://")) {
+            let parsed = crate::intents::parse_uri(uri)?;
+            intent = Some(crate::intents::dispatch_link(&app_handle, parsed).await?);
+        }
+    }
+
+    let result = NfcTagResult {
+        tag,
+        rules_fired,
+        intent,
+    };
+    let _ = app_handle.emit("nfc://tag", &result);
+    Ok(result)
+}
+
+// Command to provision a tag with a text payload or a URI such as plates://timer?minutes=5
+#[tauri::command]
+pub async fn write_tag(payload: String, bridge: State<'_, NfcBridge>) -> Result<NfcTag, String> {
+    if payload.is_empty() {
+        return Err("Nothing to write".to_string());
+    }
+    bridge.write(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_without_records_parse() {
+        let tag: NfcTag = serde_json::from_str(r#"{"id": "04a224b2c35e80"}"#).unwrap();
+        assert_eq!(tag.id, "04a224b2c35e80");
+        assert!(tag.records.is_empty());
+
+        let tag: NfcTag =
+            serde_json::from_str(r#"{"id": "04", "records": ["plates://timer?minutes=5", "kitchen"]}"#).unwrap();
+        assert_eq!(tag.records.len(), 2);
+    }
+
+    #[cfg(not(android_bridges))]
+    #[test]
+    fn desktop_cannot_write_tags() {
+        assert!(NfcBridge {}.write("plates://timer?minutes=5").is_err());
+    }
+}
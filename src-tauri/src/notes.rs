@@ -0,0 +1,188 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+use crate::storage;
+
+const NOTES_FILE: &str = "notes.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Note {
+    pub id: u64,
+    pub title: String,
+    pub body: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct NoteStore {
+    next_id: u64,
+    notes: Vec<Note>,
+}
+
+pub type NotesState = Mutex<NoteStore>;
+
+impl NoteStore {
+    fn add(&mut self, title: String, body: String, source: Option<String>, now: u64) -> Note {
+        self.next_id += 1;
+        let note = Note {
+            id: self.next_id,
+            title,
+            body,
+            created_at: now,
+            source,
+        };
+        self.notes.push(note.clone());
+        note
+    }
+
+    fn relevant(&self, query: &str, limit: usize) -> Vec<(Note, f32)> {
+        let words: Vec<String> = query
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.len() > 2)
+            .map(|word| word.to_string())
+            .collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scored: Vec<(Note, f32)> = self
+            .notes
+            .iter()
+            .filter_map(|note| {
+                let text = format!("{} {}", note.title, note.body).to_lowercase();
+                let matched = words.iter().filter(|word| text.contains(word.as_str())).count();
+                let score = matched as f32 / words.len() as f32;
+                (score >= 0.5).then(|| (note.clone(), score))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+pub fn load(app_handle: &AppHandle) -> NoteStore {
+    storage::load_json(app_handle, NOTES_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Add a note and persist the store; used by other modules that produce notes
+pub fn add_note(
+    app_handle: &AppHandle,
+    title: String,
+    body: String,
+    source: Option<String>,
+) -> Result<Note, String> {
+    let state = app_handle.state::<NotesState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let note = store.add(title, body, source, now_secs());
+    storage::save_json(app_handle, NOTES_FILE, &*store)?;
+    Ok(note)
+}
+
+pub fn get_note(app_handle: &AppHandle, id: u64) -> Result<Note, String> {
+    let state = app_handle.state::<NotesState>();
+    let store = state.lock().map_err(|e| e.to_string())?;
+    store
+        .notes
+        .iter()
+        .find(|note| note.id == id)
+        .cloned()
+        .ok_or("Note not found".to_string())
+}
+
+// Notes produced by another module, newest first
+pub fn notes_from(app_handle: &AppHandle, source: &str) -> Result<Vec<Note>, String> {
+    let state = app_handle.state::<NotesState>();
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store
+        .notes
+        .iter()
+        .rev()
+        .filter(|note| note.source.as_deref() == Some(source))
+        .cloned()
+        .collect())
+}
+
+// Notes sharing the most words with a query, with the fraction of query words matched
+pub fn relevant_notes(app_handle: &AppHandle, query: &str, limit: usize) -> Vec<(Note, f32)> {
+    let state = app_handle.state::<NotesState>();
+    let Ok(store) = state.lock() else {
+        return Vec::new();
+    };
+    store.relevant(query, limit)
+}
+
+#[tauri::command]
+pub fn save_note(title: String, body: String, app_handle: AppHandle) -> Result<Note, String> {
+    add_note(&app_handle, title, body, None)
+}
+
+#[tauri::command]
+pub fn list_notes(state: State<'_, NotesState>) -> Result<Vec<Note>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.notes.iter().rev().cloned().collect())
+}
+
+#[tauri::command]
+pub fn delete_note(id: u64, app_handle: AppHandle, state: State<'_, NotesState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.notes.retain(|note| note.id != id);
+    storage::save_json(&app_handle, NOTES_FILE, &*store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> NoteStore {
+        let mut store = NoteStore::default();
+        store.add("Garden".to_string(), "Plant tomatoes and basil in May".to_string(), None, 1);
+        store.add("Shopping".to_string(), "Buy basil, tomatoes, bread".to_string(), None, 2);
+        store.add("Meeting".to_string(), "Quarterly budget review".to_string(), Some("meetings".to_string()), 3);
+        store
+    }
+
+    #[test]
+    fn ids_count_up() {
+        let store = store();
+        let ids: Vec<u64> = store.notes.iter().map(|note| note.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(store.notes[2].source.as_deref(), Some("meetings"));
+    }
+
+    #[test]
+    fn relevant_notes_need_half_the_words() {
+        let store = store();
+        let hits = store.relevant("When do I plant tomatoes?", 5);
+        // "when" and "plant" and "tomatoes" count; "do" and "I" are too short
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0.title, "Garden");
+        assert!((hits[0].1 - 2.0 / 3.0).abs() < 1e-6);
+
+        let hits = store.relevant("basil tomatoes", 5);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(store.relevant("basil tomatoes", 1).len(), 1);
+        assert!(store.relevant("budget for the garden party", 5).is_empty());
+        assert!(store.relevant("a b", 5).is_empty());
+    }
+
+    #[test]
+    fn best_matches_come_first() {
+        let store = store();
+        let hits = store.relevant("garden basil bread", 5);
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].1 >= hits[1].1);
+    }
+}
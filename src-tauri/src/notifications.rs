@@ -0,0 +1,121 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+// Oldest items are dropped once the digest grows past this
+const MAX_DIGEST_ITEMS: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DigestItem {
+    pub id: u64,
+    // Module that produced the item, e.g. "matrix"
+    pub source: String,
+    pub title: String,
+    pub body: String,
+    pub received_at: u64,
+    // Source specific handle used to act on the item, such as a room id
+    pub reference: Option<String>,
+}
+
+#[derive(Default)]
+pub struct DigestStore {
+    next_id: u64,
+    items: Vec<DigestItem>,
+}
+
+pub type NotificationsState = Mutex<DigestStore>;
+
+impl DigestStore {
+    fn add(&mut self, source: &str, title: String, body: String, reference: Option<String>, now: u64) -> DigestItem {
+        self.next_id += 1;
+        let item = DigestItem {
+            id: self.next_id,
+            source: source.to_string(),
+            title,
+            body,
+            received_at: now,
+            reference,
+        };
+        self.items.push(item.clone());
+        if self.items.len() > MAX_DIGEST_ITEMS {
+            let excess = self.items.len() - MAX_DIGEST_ITEMS;
+            self.items.drain(..excess);
+        }
+        item
+    }
+}
+
+// Add an item to the digest and let the frontend know
+pub fn push(
+    app_handle: &AppHandle,
+    source: &str,
+    title: String,
+    body: String,
+    reference: Option<String>,
+) -> Result<DigestItem, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let item = {
+        let state = app_handle.state::<NotificationsState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.add(source, title, body, reference, now)
+    };
+
+    let _ = app_handle.emit("notifications://item", &item);
+    crate::rules::on_notification(app_handle, &item);
+    Ok(item)
+}
+
+// Command to read the digest, newest first
+#[tauri::command]
+pub fn get_notification_digest(state: State<'_, NotificationsState>) -> Result<Vec<DigestItem>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.items.iter().rev().cloned().collect())
+}
+
+#[tauri::command]
+pub fn dismiss_notification(id: u64, state: State<'_, NotificationsState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.items.retain(|item| item.id != id);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn clear_notification_digest(source: Option<String>, state: State<'_, NotificationsState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    match source {
+        Some(source) => store.items.retain(|item| item.source != source),
+        None => store.items.clear(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_keeps_the_newest_items() {
+        let mut store = DigestStore::default();
+        for i in 0..MAX_DIGEST_ITEMS + 5 {
+            store.add("matrix", format!("Message {}", i), String::new(), Some("!room".to_string()), i as u64);
+        }
+        assert_eq!(store.items.len(), MAX_DIGEST_ITEMS);
+        assert_eq!(store.items[0].id, 6);
+        assert_eq!(store.items.last().map(|item| item.id), Some(MAX_DIGEST_ITEMS as u64 + 5));
+    }
+
+    #[test]
+    fn ids_stay_unique_after_trimming() {
+        let mut store = DigestStore::default();
+        let first = store.add("rules", "A".to_string(), String::new(), None, 1);
+        store.items.clear();
+        let second = store.add("rules", "B".to_string(), String::new(), None, 2);
+        assert_ne!(first.id, second.id);
+        assert_eq!(second.source, "rules");
+        assert_eq!(second.received_at, 2);
+    }
+}
@@ -0,0 +1,296 @@
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use std::time::Duration;
+use tauri::State;
+
+use crate::settings::{self, GenerationSettings, OllamaSettings, SettingsState};
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+// Detection should fail fast when nothing is listening
+const PROBE_TIMEOUT: Duration = Duration::from_millis(800);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OllamaModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified_at: String,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<OllamaModel>,
+}
+
+// A model Ollama currently holds in memory
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunningModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+#[derive(Deserialize)]
+struct PsResponse {
+    models: Vec<RunningModel>,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    response: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OllamaStatus {
+    pub available: bool,
+    pub base_url: Option<String>,
+    pub models: Vec<OllamaModel>,
+}
+
+pub struct OllamaClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl OllamaClient {
+    pub fn new(base_url: &str) -> Self {
+        OllamaClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn from_settings(settings: &OllamaSettings) -> Self {
+        OllamaClient::new(settings.base_url.as_deref().unwrap_or(DEFAULT_OLLAMA_URL))
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<OllamaModel>, String> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let tags: TagsResponse = response.json().await.map_err(|e| e.to_string())?;
+        Ok(tags.models)
+    }
+
+    pub async fn running_models(&self) -> Result<Vec<RunningModel>, String> {
+        let response = self
+            .client
+            .get(format!("{}/api/ps", self.base_url))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let ps: PsResponse = response.json().await.map_err(|e| e.to_string())?;
+        Ok(ps.models)
+    }
+
+    // Ask Ollama to evict a model from memory right away
+    pub async fn unload(&self, model: &str) -> Result<(), String> {
+        self.client
+            .post(format!("{}/api/generate", self.base_url))
+            .json(&json!({ "model": model, "keep_alive": 0 }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    pub async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        system: Option<&str>,
+        generation: &GenerationSettings,
+    ) -> Result<String, String> {
+        let response = self
+            .client
+            .post(format!("{}/api/generate", self.base_url))
+            .json(&generate_body(model, prompt, system, generation))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!("Ollama error: {}", response.status()));
+        }
+
+        let generated: GenerateResponse = response.json().await.map_err(|e| e.to_string())?;
+        Ok(generated.response)
+    }
+}
+
+fn generate_body(
+    model: &str,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+) -> serde_json::Value {
+    let mut body = json!({ "model": model, "prompt": prompt, "stream": false });
+    if let Some(system) = system {
+        body["system"] = json!(system);
+    }
+    let mut options = serde_json::Map::new();
+    if let Some(temperature) = generation.temperature {
+        options.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = generation.top_p {
+        options.insert("top_p".to_string(), json!(top_p));
+    }
+    if let Some(max_output_tokens) = generation.max_output_tokens {
+        options.insert("num_predict".to_string(), json!(max_output_tokens));
+    }
+    if !options.is_empty() {
+        body["options"] = serde_json::Value::Object(options);
+    }
+    body
+}
+
+// Whether Ollama runs on this device, so its models share this device's memory
+pub fn is_local(settings: &OllamaSettings) -> bool {
+    let base_url = settings.base_url.as_deref().unwrap_or(DEFAULT_OLLAMA_URL);
+    url::Url::parse(base_url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| matches!(host, "localhost" | "127.0.0.1" | "[::1]")))
+        .unwrap_or(false)
+}
+
+// Answer a prompt with the configured Ollama model, defaulting to the first one installed
+pub async fn respond(
+    settings: &OllamaSettings,
+    prompt: &str,
+    system: Option<&str>,
+    generation: &GenerationSettings,
+) -> Result<String, String> {
+    let client = OllamaClient::from_settings(settings);
+    let model = match &settings.model {
+        Some(model) => model.clone(),
+        None => client
+            .list_models()
+            .await?
+            .into_iter()
+            .next()
+            .map(|model| model.name)
+            .ok_or("No Ollama models installed".to_string())?,
+    };
+    client.generate(&model, prompt, system, generation).await
+}
+
+// The configured host, then localhost
+fn candidates(settings: &OllamaSettings) -> Vec<String> {
+    let mut candidates = Vec::new();
+    if let Some(url) = &settings.base_url {
+        candidates.push(url.clone());
+    }
+    if !candidates.iter().any(|url| url == DEFAULT_OLLAMA_URL) {
+        candidates.push(DEFAULT_OLLAMA_URL.to_string());
+    }
+    candidates
+}
+
+// Probe the candidate hosts for a running Ollama server
+pub async fn detect(settings: &OllamaSettings) -> OllamaStatus {
+    for url in candidates(settings) {
+        if let Ok(models) = OllamaClient::new(&url).list_models().await {
+            return OllamaStatus {
+                available: true,
+                base_url: Some(url),
+                models,
+            };
+        }
+    }
+
+    OllamaStatus {
+        available: false,
+        base_url: None,
+        models: Vec::new(),
+    }
+}
+
+#[tauri::command]
+pub async fn detect_ollama(state: State<'_, SettingsState>) -> Result<OllamaStatus, String> {
+    let ollama = settings::current(&state).ollama;
+    Ok(detect(&ollama).await)
+}
+
+#[tauri::command]
+pub async fn list_ollama_models(state: State<'_, SettingsState>) -> Result<Vec<OllamaModel>, String> {
+    let ollama = settings::current(&state).ollama;
+    OllamaClient::from_settings(&ollama).list_models().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_url(base_url: Option<&str>) -> OllamaSettings {
+        OllamaSettings {
+            base_url: base_url.map(|url| url.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn probes_the_configured_host_before_localhost() {
+        assert_eq!(candidates(&with_url(None)), vec![DEFAULT_OLLAMA_URL]);
+        assert_eq!(candidates(&with_url(Some(DEFAULT_OLLAMA_URL))), vec![DEFAULT_OLLAMA_URL]);
+        assert_eq!(
+            candidates(&with_url(Some("http://nas.local:11434"))),
+            vec!["http://nas.local:11434", DEFAULT_OLLAMA_URL]
+        );
+    }
+
+    #[test]
+    fn local_hosts_share_this_devices_memory() {
+        assert!(is_local(&with_url(None)));
+        assert!(is_local(&with_url(Some("http://127.0.0.1:11434"))));
+        assert!(is_local(&with_url(Some("http://[::1]:11434"))));
+        assert!(!is_local(&with_url(Some("http://nas.local:11434"))));
+        assert!(!is_local(&with_url(Some("not a url"))));
+    }
+
+    #[test]
+    fn client_trims_the_trailing_slash() {
+        assert_eq!(OllamaClient::new("http://nas.local:11434/").base_url, "http://nas.local:11434");
+        assert_eq!(OllamaClient::from_settings(&with_url(None)).base_url, DEFAULT_OLLAMA_URL);
+    }
+
+    #[test]
+    fn builds_generate_requests() {
+        let body = generate_body("llama3", "Hi", Some("Be brief"), &GenerationSettings::default());
+        assert_eq!(body, json!({ "model": "llama3", "prompt": "Hi", "stream": false, "system": "Be brief" }));
+        assert!(generate_body("llama3", "Hi", None, &GenerationSettings::default())
+            .get("system")
+            .is_none());
+    }
+
+    #[test]
+    fn generation_settings_become_options() {
+        let generation = GenerationSettings {
+            temperature: Some(0.5),
+            top_p: Some(0.25),
+            max_output_tokens: Some(256),
+            safety_threshold: None,
+        };
+        let body = generate_body("llama3", "Hi", None, &generation);
+        assert_eq!(body["options"], json!({ "temperature": 0.5, "top_p": 0.25, "num_predict": 256 }));
+        assert!(generate_body("llama3", "Hi", None, &GenerationSettings::default())
+            .get("options")
+            .is_none());
+    }
+
+    #[test]
+    fn parses_installed_models() {
+        let raw = r#"{"models":[{"name":"llama3:8b","size":4661224676,"modified_at":"2024-05-01T10:00:00Z"},
+            {"name":"phi3"}]}"#;
+        let tags: TagsResponse = serde_json::from_str(raw).unwrap();
+        assert_eq!(tags.models[0].name, "llama3:8b");
+        assert_eq!(tags.models[0].size, 4_661_224_676);
+        assert_eq!((tags.models[1].size, tags.models[1].modified_at.as_str()), (0, ""));
+    }
+}
@@ -0,0 +1,179 @@
+// The assistant persona: name, tone, brevity and the actions it may offer,
+// turned into the system instruction sent with user-facing answers. Internal
+// prompts (summaries, grading, parsing) go without it.
+
+use tauri::{AppHandle, State};
+
+use crate::settings::{self, PersonaAction, PersonaBrevity, PersonaSettings, PersonaTone, SettingsState};
+
+const MAX_NAME_CHARS: usize = 40;
+const MAX_INSTRUCTIONS_CHARS: usize = 1000;
+
+fn preset(id: &str, name: &str, tone: PersonaTone, brevity: PersonaBrevity, instructions: &str) -> PersonaSettings {
+    PersonaSettings {
+        preset: Some(id.to_string()),
+        name: name.to_string(),
+        tone,
+        brevity,
+        allowed_actions: PersonaAction::ALL.to_vec(),
+        instructions: instructions.to_string(),
+    }
+}
+
+fn presets() -> Vec<PersonaSettings> {
+    vec![
+        PersonaSettings::default(),
+        preset("concise", "Plates", PersonaTone::Professional, PersonaBrevity::Brief, ""),
+        preset(
+            "tutor",
+            "Plates",
+            PersonaTone::Calm,
+            PersonaBrevity::Detailed,
+            "Explain things step by step and check that the user is following before moving on.",
+        ),
+        preset("playful", "Plates", PersonaTone::Playful, PersonaBrevity::Balanced, ""),
+    ]
+}
+
+fn action_name(action: PersonaAction) -> &'static str {
+    match action {
+        PersonaAction::WebSearch => "looking things up on the web",
+        PersonaAction::Timers => "timers",
+        PersonaAction::Notes => "notes",
+        PersonaAction::Calendar => "the calendar",
+        PersonaAction::Expenses => "tracking expenses",
+        PersonaAction::Recipes => "recipes and meal plans",
+        PersonaAction::OpenLinks => "opening web pages",
+    }
+}
+
+pub fn allows(persona: &PersonaSettings, action: PersonaAction) -> bool {
+    persona.allowed_actions.contains(&action)
+}
+
+pub fn system_instruction(persona: &PersonaSettings) -> String {
+    let tone = match persona.tone {
+        PersonaTone::Friendly => "Be warm and friendly.",
+        PersonaTone::Professional => "Be professional and to the point.",
+        PersonaTone::Playful => "Be playful and light-hearted, without getting in the way of the answer.",
+        PersonaTone::Calm => "Be calm, patient and reassuring.",
+    };
+    let brevity = match persona.brevity {
+        PersonaBrevity::Brief => "Keep answers to a sentence or two unless asked for more.",
+        PersonaBrevity::Balanced => "Keep answers short enough to read aloud comfortably.",
+        PersonaBrevity::Detailed => "Give thorough answers with the reasoning behind them.",
+    };
+    let mut instruction = format!(
+        "You are {}, a personal assistant in the Plates app. {} {}",
+        persona.name, tone, brevity
+    );
+    let actions: Vec<&str> = persona.allowed_actions.iter().map(|action| action_name(*action)).collect();
+    if actions.is_empty() {
+        instruction.push_str(" Only answer questions; don't offer to do anything in the app.");
+    } else {
+        instruction.push_str(&format!(
+            " Besides answering, you can only offer help with {}.",
+            actions.join(", ")
+        ));
+    }
+    if !persona.instructions.trim().is_empty() {
+        instruction.push_str(&format!("\n\n{}", persona.instructions.trim()));
+    }
+    instruction
+}
+
+// Trim the name, check lengths and drop repeated actions
+fn checked(persona: PersonaSettings) -> Result<PersonaSettings, String> {
+    let mut persona = persona;
+    persona.name = persona.name.trim().to_string();
+    if persona.name.is_empty() || persona.name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("The assistant's name must be 1 to {} characters", MAX_NAME_CHARS));
+    }
+    if persona.instructions.chars().count() > MAX_INSTRUCTIONS_CHARS {
+        return Err(format!("Instructions can be at most {} characters", MAX_INSTRUCTIONS_CHARS));
+    }
+    let mut actions = Vec::new();
+    for action in persona.allowed_actions {
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    }
+    persona.allowed_actions = actions;
+    Ok(persona)
+}
+
+#[tauri::command]
+pub fn get_persona(settings_state: State<'_, SettingsState>) -> PersonaSettings {
+    settings::current(&settings_state).persona
+}
+
+#[tauri::command]
+pub fn list_persona_presets() -> Vec<PersonaSettings> {
+    presets()
+}
+
+// Command to change the persona, e.g. to a preset from list_persona_presets
+#[tauri::command]
+pub fn set_persona(
+    persona: PersonaSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let mut updated = settings::current(&settings_state);
+    updated.persona = checked(persona)?;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_describe_tone_brevity_and_actions() {
+        let instruction = system_instruction(&PersonaSettings::default());
+        assert!(instruction.starts_with("You are Plates, a personal assistant in the Plates app. Be warm and friendly."));
+        assert!(instruction.contains("looking things up on the web, timers, notes"));
+
+        let tutor = presets().into_iter().find(|preset| preset.preset.as_deref() == Some("tutor")).unwrap();
+        let instruction = system_instruction(&tutor);
+        assert!(instruction.contains("Be calm, patient and reassuring. Give thorough answers"));
+        assert!(instruction.ends_with("\n\nExplain things step by step and check that the user is following before moving on."));
+    }
+
+    #[test]
+    fn personas_without_actions_only_answer() {
+        let persona = PersonaSettings {
+            allowed_actions: vec![PersonaAction::Timers],
+            ..PersonaSettings::default()
+        };
+        assert!(allows(&persona, PersonaAction::Timers));
+        assert!(!allows(&persona, PersonaAction::WebSearch));
+        assert!(system_instruction(&persona).ends_with("you can only offer help with timers."));
+
+        let persona = PersonaSettings {
+            allowed_actions: Vec::new(),
+            ..PersonaSettings::default()
+        };
+        assert!(system_instruction(&persona).ends_with("don't offer to do anything in the app."));
+    }
+
+    #[test]
+    fn personas_are_checked_before_saving() {
+        let persona = checked(PersonaSettings {
+            name: "  Robin ".to_string(),
+            allowed_actions: vec![PersonaAction::Notes, PersonaAction::Notes, PersonaAction::Timers],
+            ..PersonaSettings::default()
+        })
+        .unwrap();
+        assert_eq!(persona.name, "Robin");
+        assert_eq!(persona.allowed_actions, vec![PersonaAction::Notes, PersonaAction::Timers]);
+
+        let unnamed = PersonaSettings { name: " ".to_string(), ..PersonaSettings::default() };
+        assert!(checked(unnamed).is_err());
+        let rambling = PersonaSettings {
+            instructions: "a".repeat(MAX_INSTRUCTIONS_CHARS + 1),
+            ..PersonaSettings::default()
+        };
+        assert!(checked(rambling).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+// On-device photo search. The photo library is indexed through the platform
+// (MediaStore on Android) into an SQLite database in app data: when each photo
+// was taken, where, the text recognized in it and, when photo captions are on,
+// labels for what it shows. Queries like "photos from the beach last summer"
+// are parsed locally into a date range and search terms; nothing leaves the
+// device.
+
+use chrono::{Datelike, Duration, Local, NaiveDate, TimeZone};
+use rusqlite::{params, Connection};
+use serde::{Serialize, Deserialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Emitter, Manager, State,
+};
+
+use crate::settings::{self, SettingsState};
+use crate::speech;
+use crate::storage;
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+const PHOTOS_DB: &str = "photos.sqlite3";
+const PAGE_SIZE: u32 = 200;
+const MAX_RESULTS: usize = 100;
+
+// Words in a query that say nothing about which photos are meant
+const STOPWORDS: &[&str] = &[
+    "show", "me", "find", "get", "open", "my", "the", "a", "an", "some", "all", "any", "of", "from", "with",
+    "in", "at", "on", "during", "taken", "that", "i", "photo", "photos", "picture", "pictures", "pic", "pics",
+    "image", "images", "and", "this", "last", "past", "week", "month", "year",
+];
+
+// Seasons by the month they start in (northern hemisphere)
+const SEASONS: &[(&str, u32)] = &[("spring", 3), ("summer", 6), ("autumn", 9), ("fall", 9), ("winter", 12)];
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LibraryPhoto {
+    // MediaStore id; ids only grow, so the largest one indexed is the cursor
+    id: i64,
+    uri: String,
+    taken_at: u64,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+struct PhotoAnalysis {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    labels: Vec<String>,
+    // Locality from the device's offline geocoder, when it has one
+    place: Option<String>,
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ListRequest {
+    after_id: i64,
+    limit: u32,
+}
+
+#[cfg(android_bridges)]
+#[derive(Deserialize)]
+struct ListResponse {
+    photos: Vec<LibraryPhoto>,
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct AnalyzeRequest {
+    uri: String,
+    captions: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PhotoMatch {
+    pub uri: String,
+    pub taken_at: u64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub place: Option<String>,
+    pub labels: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PhotoSearch {
+    // How the query was understood, so the UI can say what it is showing
+    pub from: Option<u64>,
+    pub until: Option<u64>,
+    pub terms: Vec<String>,
+    pub photos: Vec<PhotoMatch>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IndexProgress {
+    pub indexed: usize,
+    pub total: usize,
+}
+
+pub struct PhotoLibrary {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+    indexing: AtomicBool,
+}
+
+impl PhotoLibrary {
+    // Photos added after `after_id`, oldest first
+    fn list(&self, after_id: i64, limit: u32) -> Result<Vec<LibraryPhoto>, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<ListResponse>("listPhotos", ListRequest { after_id, limit })
+                .map(|response| response.photos)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = (after_id, limit);
+            Err("The photo library is not available on this device".to_string())
+        }
+    }
+
+    // Text recognition and, with captions, image labeling; both run on device
+    fn analyze(&self, uri: &str, captions: bool) -> Result<PhotoAnalysis, String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<PhotoAnalysis>("analyzePhoto", AnalyzeRequest {
+                    uri: uri.to_string(),
+                    captions,
+                })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = (uri, captions);
+            Err("The photo library is not available on this device".to_string())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("photos")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let library = PhotoLibrary {
+                handle: api.register_android_plugin("company.atechnology.plates", "PhotosPlugin")?,
+                indexing: AtomicBool::new(false),
+            };
+            #[cfg(not(android_bridges))]
+            let library = {
+                let _ = api;
+                PhotoLibrary {
+                    indexing: AtomicBool::new(false),
+                }
+            };
+            app.manage(library);
+            Ok(())
+        })
+        .build()
+}
+
+fn open(app_handle: &AppHandle) -> Result<Connection, String> {
+    let path = storage::data_file(app_handle, PHOTOS_DB)?;
+    let conn = Connection::open(path).map_err(|e| e.to_string())?;
+    create_tables(&conn)?;
+    Ok(conn)
+}
+
+fn create_tables(conn: &Connection) -> Result<(), String> {
+    // Write-ahead logging keeps the index intact if the app is killed mid-way
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         CREATE TABLE IF NOT EXISTS photos (
+             media_id INTEGER PRIMARY KEY,
+             uri TEXT NOT NULL,
+             taken_at INTEGER NOT NULL,
+             latitude REAL,
+             longitude REAL,
+             place TEXT,
+             text TEXT NOT NULL,
+             labels TEXT NOT NULL,
+             -- Place, labels and text lowercased, for matching query terms
+             haystack TEXT NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS photos_taken_at ON photos (taken_at);",
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn index_photo(conn: &Connection, photo: &LibraryPhoto, analysis: PhotoAnalysis) -> Result<(), String> {
+    let haystack = format!(
+        "{}\n{}\n{}",
+        analysis.place.as_deref().unwrap_or_default(),
+        analysis.labels.join("\n"),
+        analysis.text
+    )
+    .to_lowercase();
+    let labels = serde_json::to_string(&analysis.labels).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO photos (media_id, uri, taken_at, latitude, longitude, place, text, labels, haystack)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            photo.id,
+            photo.uri,
+            photo.taken_at as i64,
+            photo.latitude,
+            photo.longitude,
+            analysis.place,
+            analysis.text,
+            labels,
+            haystack
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Index photos added since the last run, emitting `photos://indexed` after each page
+fn index_new(app_handle: &AppHandle) -> Result<IndexProgress, String> {
+    let library = app_handle.state::<PhotoLibrary>();
+    let captions = settings::current(&app_handle.state::<SettingsState>()).photo_captions;
+    let conn = open(app_handle)?;
+    let mut cursor: i64 = conn
+        .query_row("SELECT COALESCE(MAX(media_id), 0) FROM photos", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let mut indexed = 0;
+    loop {
+        let page = library.list(cursor, PAGE_SIZE)?;
+        for photo in &page {
+            // A photo that can't be read is still findable by date
+            let analysis = library.analyze(&photo.uri, captions).unwrap_or_else(|e| {
+                tracing::debug!(error = %e, "photo not analyzed");
+                PhotoAnalysis::default()
+            });
+            index_photo(&conn, photo, analysis)?;
+            cursor = cursor.max(photo.id);
+            indexed += 1;
+        }
+        let total: i64 = conn
+            .query_row("SELECT COUNT(*) FROM photos", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let progress = IndexProgress {
+            indexed,
+            total: total as usize,
+        };
+        let _ = app_handle.emit("photos://indexed", progress.clone());
+        if page.len() < PAGE_SIZE as usize {
+            return Ok(progress);
+        }
+    }
+}
+
+fn clean_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'').to_lowercase()
+}
+
+// "beaches" matches "beach", "puppies" matches "puppy"
+fn stem(term: &str) -> &str {
+    if term.len() <= 3 {
+        return term;
+    }
+    if ["ches", "shes", "xes", "sses"].iter().any(|suffix| term.ends_with(suffix)) {
+        &term[..term.len() - 2]
+    } else if let Some(stem) = term.strip_suffix("ies") {
+        stem
+    } else if term.ends_with('s') && !term.ends_with("ss") {
+        &term[..term.len() - 1]
+    } else {
+        term
+    }
+}
+
+fn month_start(year: i32, month: u32) -> Option<NaiveDate> {
+    let months = year * 12 + month as i32 - 1;
+    NaiveDate::from_ymd_opt(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1)
+}
+
+// Start (inclusive) and end (exclusive) of `months` months from year/month
+fn months_range(year: i32, month: u32, months: u32) -> Option<(NaiveDate, NaiveDate)> {
+    Some((month_start(year, month)?, month_start(year, month + months)?))
+}
+
+// The latest occurrence of a month or season that has started, or with `ended`,
+// that is already over
+fn latest(today: NaiveDate, month: u32, months: u32, ended: bool) -> Option<(NaiveDate, NaiveDate)> {
+    (today.year() - 2..=today.year())
+        .rev()
+        .filter_map(|year| months_range(year, month, months))
+        .find(|(start, end)| if ended { *end <= today } else { *start <= today })
+}
+
+// A month or season as its first month and length in months
+fn period_of(word: &str) -> Option<(u32, u32)> {
+    SEASONS
+        .iter()
+        .find(|(season, _)| *season == word)
+        .map(|(_, month)| (*month, 3))
+        .or_else(|| word.parse::<chrono::Month>().ok().map(|month| (month.number_from_month(), 1)))
+}
+
+fn local_secs(date: NaiveDate) -> u64 {
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| Local.from_local_datetime(&midnight).earliest())
+        .map(|midnight| midnight.timestamp().max(0) as u64)
+        .unwrap_or(0)
+}
+
+// Split a query into a date range and the words to look for in the photos
+fn parse_query(query: &str, today: NaiveDate) -> (Option<(NaiveDate, NaiveDate)>, Vec<String>) {
+    let words: Vec<String> = query.split_whitespace().map(clean_word).filter(|word| !word.is_empty()).collect();
+    let week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let tomorrow = today + Duration::days(1);
+
+    let mut range = None;
+    // A month or season, and whether it was "last" rather than "this" one
+    let mut period: Option<(u32, u32, bool)> = None;
+    let mut year = None;
+    let mut terms: Vec<String> = Vec::new();
+    let mut previous = "";
+    for word in &words {
+        let word = word.as_str();
+        let last = previous == "last" || previous == "past";
+        let relative = last || previous == "this";
+        previous = word;
+        match word {
+            "today" => range = Some((today, tomorrow)),
+            "yesterday" => range = Some((today - Duration::days(1), today)),
+            "week" if last => range = Some((week_start - Duration::days(7), week_start)),
+            "week" if relative => range = Some((week_start, tomorrow)),
+            // month_start wraps month 0 round to December of the year before
+            "month" if last => range = months_range(today.year(), today.month() - 1, 1),
+            "month" if relative => range = months_range(today.year(), today.month(), 1),
+            "year" if last => range = months_range(today.year() - 1, 1, 12),
+            "year" if relative => range = months_range(today.year(), 1, 12),
+            _ => {
+                if let Some((month, months)) = period_of(word) {
+                    period = Some((month, months, last));
+                } else if let Some(number) = word.parse::<i32>().ok().filter(|number| (1900..=today.year()).contains(number)) {
+                    year = Some(number);
+                } else if !STOPWORDS.contains(&word) {
+                    let term = stem(word).to_string();
+                    if !terms.contains(&term) {
+                        terms.push(term);
+                    }
+                }
+            }
+        }
+    }
+
+    let range = range.or_else(|| match (period, year) {
+        (Some((month, months, _)), Some(year)) => months_range(year, month, months),
+        (Some((month, months, ended)), None) => latest(today, month, months, ended),
+        (None, Some(year)) => months_range(year, 1, 12),
+        (None, None) => None,
+    });
+    (range, terms)
+}
+
+fn read_match(row: &rusqlite::Row) -> rusqlite::Result<PhotoMatch> {
+    let labels: String = row.get(5)?;
+    Ok(PhotoMatch {
+        uri: row.get(0)?,
+        taken_at: row.get::<_, i64>(1)? as u64,
+        latitude: row.get(2)?,
+        longitude: row.get(3)?,
+        place: row.get(4)?,
+        labels: serde_json::from_str(&labels).unwrap_or_default(),
+    })
+}
+
+// Terms are matched literally, so LIKE wildcards in them are escaped
+fn like_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn find(conn: &Connection, from: Option<u64>, until: Option<u64>, terms: &[String]) -> Result<Vec<PhotoMatch>, String> {
+    let mut sql = String::from(
+        "SELECT uri, taken_at, latitude, longitude, place, labels FROM photos WHERE taken_at >= ?1 AND taken_at < ?2",
+    );
+    let mut values: Vec<rusqlite::types::Value> = vec![
+        (from.unwrap_or(0) as i64).into(),
+        (until.map(|until| until as i64).unwrap_or(i64::MAX)).into(),
+    ];
+    for term in terms {
+        values.push(like_pattern(term).into());
+        sql.push_str(&format!(" AND haystack LIKE ?{} ESCAPE '\\'", values.len()));
+    }
+    sql.push_str(&format!(" ORDER BY taken_at DESC LIMIT {}", MAX_RESULTS));
+
+    let mut statement = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let photos = statement
+        .query_map(rusqlite::params_from_iter(values), read_match)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(photos)
+}
+
+pub fn search(app_handle: &AppHandle, query: &str) -> Result<PhotoSearch, String> {
+    let (range, terms) = parse_query(query, Local::now().date_naive());
+    let from = range.map(|(start, _)| local_secs(start));
+    let until = range.map(|(_, end)| local_secs(end));
+    let photos = find(&open(app_handle)?, from, until, &terms)?;
+    Ok(PhotoSearch {
+        from,
+        until,
+        terms,
+        photos,
+    })
+}
+
+// Command to index photos added since the last run; the first run indexes the
+// whole library, which can take a while
+#[tauri::command]
+pub async fn index_photos(app_handle: AppHandle, library: State<'_, PhotoLibrary>) -> Result<IndexProgress, String> {
+    if library.indexing.swap(true, Ordering::SeqCst) {
+        return Err("Photos are already being indexed".to_string());
+    }
+    let handle = app_handle.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || index_new(&handle))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+    library.indexing.store(false, Ordering::SeqCst);
+    result
+}
+
+// Command to drop the index, e.g. after turning photo captions on or off so
+// the next index_photos run labels every photo again
+#[tauri::command]
+pub fn clear_photo_index(app_handle: AppHandle) -> Result<(), String> {
+    let conn = open(&app_handle)?;
+    conn.execute("DELETE FROM photos", []).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Command to search photos by a spoken or typed query, e.g. "show photos from
+// the beach last summer"; a recording path is transcribed first
+#[tauri::command]
+pub async fn search_photos(query: Option<String>, recording: Option<String>, app_handle: AppHandle) -> Result<PhotoSearch, String> {
+    let query = match (query, recording) {
+        (Some(query), _) => query,
+        (None, Some(path)) => {
+            let wav = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+            speech::transcribe_wav(&app_handle, wav).await?.text
+        }
+        (None, None) => return Err("No search was given".to_string()),
+    };
+    search(&app_handle, &query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn range(from: (i32, u32, u32), until: (i32, u32, u32)) -> Option<(NaiveDate, NaiveDate)> {
+        Some((date(from.0, from.1, from.2), date(until.0, until.1, until.2)))
+    }
+
+    #[test]
+    fn stems_plurals() {
+        assert_eq!(stem("beaches"), "beach");
+        assert_eq!(stem("boxes"), "box");
+        assert_eq!(stem("dresses"), "dress");
+        assert_eq!(stem("puppies"), "pupp");
+        assert_eq!(stem("cats"), "cat");
+        assert_eq!(stem("glass"), "glass");
+        assert_eq!(stem("bus"), "bus");
+    }
+
+    #[test]
+    fn parses_seasons_and_terms() {
+        // A Wednesday
+        let today = date(2026, 3, 18);
+        let (dates, terms) = parse_query("Show me photos from the beach last summer", today);
+        assert_eq!(dates, range((2025, 6, 1), (2025, 9, 1)));
+        assert_eq!(terms, vec!["beach"]);
+
+        // A month that has started is this year's; one that has not is last year's
+        assert_eq!(parse_query("march", today).0, range((2026, 3, 1), (2026, 4, 1)));
+        assert_eq!(parse_query("december", today).0, range((2025, 12, 1), (2026, 1, 1)));
+        assert_eq!(parse_query("winter 2024", today).0, range((2024, 12, 1), (2025, 3, 1)));
+        let (dates, terms) = parse_query("receipts from 2024", today);
+        assert_eq!(dates, range((2024, 1, 1), (2025, 1, 1)));
+        assert_eq!(terms, vec!["receipt"]);
+    }
+
+    #[test]
+    fn parses_relative_ranges() {
+        let today = date(2026, 3, 18);
+        assert_eq!(parse_query("today", today).0, range((2026, 3, 18), (2026, 3, 19)));
+        assert_eq!(parse_query("yesterday", today).0, range((2026, 3, 17), (2026, 3, 18)));
+        assert_eq!(parse_query("last week", today).0, range((2026, 3, 9), (2026, 3, 16)));
+        assert_eq!(parse_query("this week", today).0, range((2026, 3, 16), (2026, 3, 19)));
+        assert_eq!(parse_query("last month", today).0, range((2026, 2, 1), (2026, 3, 1)));
+        assert_eq!(parse_query("last month", date(2026, 1, 5)).0, range((2025, 12, 1), (2026, 1, 1)));
+        assert_eq!(parse_query("this year", today).0, range((2026, 1, 1), (2027, 1, 1)));
+        // Years in the future are search terms, not dates
+        assert_eq!(parse_query("2030 dogs", today), (None, vec!["2030".to_string(), "dog".to_string()]));
+    }
+
+    fn photo(id: i64, taken_at: u64, text: &str) -> (LibraryPhoto, PhotoAnalysis) {
+        let photo = LibraryPhoto {
+            id,
+            uri: format!("content://media/{}", id),
+            taken_at,
+            latitude: None,
+            longitude: None,
+        };
+        let analysis = PhotoAnalysis {
+            text: text.to_string(),
+            labels: vec!["Beach".to_string()],
+            place: Some("Brighton".to_string()),
+        };
+        (photo, analysis)
+    }
+
+    #[test]
+    fn finds_indexed_photos_by_date_and_terms() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        for (id, taken_at, text) in [(1, 100, "Sunset"), (2, 200, "Menu"), (3, 300, "sunset pier")] {
+            let (photo, analysis) = photo(id, taken_at, text);
+            index_photo(&conn, &photo, analysis).unwrap();
+        }
+
+        let uris = |photos: Vec<PhotoMatch>| photos.into_iter().map(|photo| photo.uri).collect::<Vec<_>>();
+        let sunsets = find(&conn, None, None, &["sunset".to_string()]).unwrap();
+        assert_eq!(uris(sunsets.clone()), vec!["content://media/3", "content://media/1"]);
+        assert_eq!(sunsets[0].labels, vec!["Beach"]);
+        assert_eq!(sunsets[0].place.as_deref(), Some("Brighton"));
+        assert_eq!(uris(find(&conn, Some(150), Some(300), &[]).unwrap()), vec!["content://media/2"]);
+        let everywhere = find(&conn, None, None, &["brighton".to_string(), "beach".to_string()]).unwrap();
+        assert_eq!(everywhere.len(), 3);
+
+        // Wildcards in a term are matched literally
+        assert!(find(&conn, None, None, &["su_set".to_string()]).unwrap().is_empty());
+        assert!(find(&conn, None, None, &["%".to_string()]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+// On-device Piper voices. A voice lives in the app data dir under
+// models/<id>/ as model.onnx with its model.onnx.json config, and is run with
+// ONNX Runtime after espeak-ng turns the text into phonemes.
+
+use piper_rs::synth::PiperSpeechSynthesizer;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+use crate::whisper;
+
+const CONFIG_FILE: &str = "model.onnx.json";
+
+#[derive(Deserialize)]
+struct VoiceConfig {
+    audio: AudioConfig,
+}
+
+#[derive(Deserialize)]
+struct AudioConfig {
+    sample_rate: u32,
+}
+
+pub struct LoadedVoice {
+    id: String,
+    synthesizer: PiperSpeechSynthesizer,
+    sample_rate: u32,
+}
+
+// The loaded voice is kept between utterances; loading takes a second or two
+#[derive(Default)]
+pub struct PiperStore {
+    loaded: Option<LoadedVoice>,
+}
+
+pub type PiperState = Mutex<PiperStore>;
+
+fn load_dir(dir: &Path, id: &str) -> Result<LoadedVoice, String> {
+    let config_path = dir.join(CONFIG_FILE);
+    let config = std::fs::read_to_string(&config_path).map_err(|e| e.to_string())?;
+    let config: VoiceConfig = serde_json::from_str(&config).map_err(|e| e.to_string())?;
+    // The ONNX file is found next to its config
+    let model = piper_rs::from_config_path(&config_path).map_err(|e| e.to_string())?;
+    let synthesizer = PiperSpeechSynthesizer::new(model).map_err(|e| e.to_string())?;
+    Ok(LoadedVoice {
+        id: id.to_string(),
+        synthesizer,
+        sample_rate: config.audio.sample_rate,
+    })
+}
+
+fn run(loaded: &LoadedVoice, text: &str) -> Result<Vec<f32>, String> {
+    let mut samples = Vec::new();
+    let audio = loaded
+        .synthesizer
+        .synthesize_parallel(text.to_string(), None)
+        .map_err(|e| e.to_string())?;
+    for chunk in audio {
+        samples.extend(chunk.map_err(|e| e.to_string())?.into_vec());
+    }
+    Ok(samples)
+}
+
+// Speak `text` with an installed voice, returning mono samples and their
+// rate. Blocks for the duration.
+pub fn synthesize(app_handle: &AppHandle, id: &str, text: &str) -> Result<(Vec<f32>, u32), String> {
+    let state = app_handle.state::<PiperState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if store.loaded.as_ref().map(|loaded| loaded.id.as_str()) != Some(id) {
+        store.loaded = None;
+        store.loaded = Some(load_dir(&whisper::model_dir(app_handle, id)?, id)?);
+    }
+    let loaded = store.loaded.as_ref().ok_or("Voice is not loaded".to_string())?;
+    Ok((run(loaded, text)?, loaded.sample_rate))
+}
+
+// Drop the cached voice if it is the given one, before its files are replaced or deleted
+pub fn unload(app_handle: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app_handle.state::<PiperState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if store.loaded.as_ref().is_some_and(|loaded| loaded.id == id) {
+        store.loaded = None;
+    }
+    Ok(())
+}
+
+// Load the voice files in `dir` and say a word, to check downloaded files
+// before they replace an installed voice. Blocks.
+pub fn smoke_test(dir: &Path) -> Result<(), String> {
+    let loaded = load_dir(dir, "smoke-test")?;
+    if run(&loaded, "Hello.")?.is_empty() {
+        return Err("The voice produced no audio".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voice_config_reads_the_sample_rate() {
+        let json = r#"{"audio": {"sample_rate": 22050, "quality": "medium"}, "espeak": {"voice": "en-us"}}"#;
+        let config: VoiceConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.audio.sample_rate, 22050);
+    }
+
+    #[test]
+    fn missing_voices_fail_the_smoke_test() {
+        let dir = std::env::temp_dir().join(format!("plates-piper-{}", std::process::id()));
+        assert!(smoke_test(&dir).is_err());
+    }
+}
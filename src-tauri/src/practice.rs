@@ -0,0 +1,471 @@
+// Conversation practice in a language being learned. Speech is transcribed
+// in the target language, the engine corrects each message with a short
+// explanation and carries the conversation on, and replies can be read aloud
+// at a slower pace. Words met along the way are counted per language in
+// practice.json.
+
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+use crate::engine;
+use crate::settings::{self, SettingsState};
+use crate::storage;
+use crate::structured;
+use crate::stt;
+use crate::tts;
+use crate::whisper;
+
+const PRACTICE_FILE: &str = "practice.json";
+// Learners usually want replies a little slower than normal speech
+const DEFAULT_RATE: f32 = 0.85;
+// Earlier messages included in each prompt
+const MAX_HISTORY: usize = 12;
+const MAX_TOPIC_CHARS: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PracticeLevel {
+    #[default]
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+impl PracticeLevel {
+    fn describe(self) -> &'static str {
+        match self {
+            PracticeLevel::Beginner => "a beginner; use short sentences and very common words",
+            PracticeLevel::Intermediate => "an intermediate learner; use everyday language",
+            PracticeLevel::Advanced => "an advanced learner; speak naturally, idioms included",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TutorReply {
+    // The learner's message corrected, or empty when it was already right
+    #[serde(default)]
+    corrected: String,
+    #[serde(default)]
+    explanation: String,
+    reply: String,
+    #[serde(default)]
+    vocabulary: Vec<String>,
+}
+
+#[derive(Clone)]
+struct PracticeSession {
+    id: u64,
+    language: &'static str,
+    level: PracticeLevel,
+    topic: Option<String>,
+    rate: f32,
+    // (learner, tutor) message pairs, oldest first; the opening line has no learner message
+    history: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct PracticeSessions {
+    session: Option<PracticeSession>,
+    last_id: u64,
+}
+
+pub type PracticeState = Mutex<PracticeSessions>;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VocabularyWord {
+    pub word: String,
+    pub times_seen: u32,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LanguageProgress {
+    // ISO code
+    pub language: String,
+    pub sessions: u64,
+    pub messages: u64,
+    pub corrections: u64,
+    pub vocabulary: Vec<VocabularyWord>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ProgressStore {
+    languages: Vec<LanguageProgress>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PracticeTurn {
+    pub session: u64,
+    // What was heard or typed; empty for the opening line
+    pub said: String,
+    pub corrected: Option<String>,
+    pub explanation: Option<String>,
+    pub reply: String,
+    // Words seen for the first time in this language
+    pub new_words: Vec<String>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn language_name(code: &str) -> &'static str {
+    whisper::LANGUAGES
+        .iter()
+        .find(|(language, _)| *language == code)
+        .map(|(_, name)| *name)
+        .unwrap_or("the language")
+}
+
+// Count a message's vocabulary and outcome; returns the words not seen before
+fn record(
+    store: &mut ProgressStore,
+    language: &str,
+    words: &[String],
+    new_session: bool,
+    corrected: bool,
+    now: u64,
+) -> Vec<String> {
+    let index = match store.languages.iter().position(|progress| progress.language == language) {
+        Some(index) => index,
+        None => {
+            store.languages.push(LanguageProgress {
+                language: language.to_string(),
+                sessions: 0,
+                messages: 0,
+                corrections: 0,
+                vocabulary: Vec::new(),
+            });
+            store.languages.len() - 1
+        }
+    };
+    let progress = &mut store.languages[index];
+    if new_session {
+        progress.sessions += 1;
+    } else {
+        progress.messages += 1;
+    }
+    if corrected {
+        progress.corrections += 1;
+    }
+
+    let mut new_words = Vec::new();
+    for word in words {
+        let word = word.trim().to_lowercase();
+        if word.is_empty() {
+            continue;
+        }
+        match progress.vocabulary.iter_mut().find(|entry| entry.word == word) {
+            Some(entry) => {
+                entry.times_seen += 1;
+                entry.last_seen = now;
+            }
+            None => {
+                progress.vocabulary.push(VocabularyWord {
+                    word: word.clone(),
+                    times_seen: 1,
+                    first_seen: now,
+                    last_seen: now,
+                });
+                new_words.push(word);
+            }
+        }
+    }
+    new_words
+}
+
+fn record_progress(
+    app_handle: &AppHandle,
+    language: &str,
+    words: &[String],
+    new_session: bool,
+    corrected: bool,
+) -> Result<Vec<String>, String> {
+    let mut store: ProgressStore = storage::load_json(app_handle, PRACTICE_FILE);
+    let new_words = record(&mut store, language, words, new_session, corrected, now_secs());
+    storage::save_json(app_handle, PRACTICE_FILE, &store)?;
+    Ok(new_words)
+}
+
+fn build_prompt(session: &PracticeSession, message: Option<&str>) -> String {
+    let language = language_name(session.language);
+    let mut prompt = format!(
+        "You are a friendly {} tutor having a spoken conversation with {}. ",
+        language,
+        session.level.describe()
+    );
+    if let Some(topic) = &session.topic {
+        prompt.push_str(&format!("The conversation is about {}. ", topic));
+    }
+    let history: String = session
+        .history
+        .iter()
+        .rev()
+        .take(MAX_HISTORY)
+        .rev()
+        .map(|(learner, tutor)| {
+            if learner.is_empty() {
+                format!("Tutor: {}\n", tutor)
+            } else {
+                format!("Learner: {}\nTutor: {}\n", learner, tutor)
+            }
+        })
+        .collect();
+    match message {
+        None => prompt.push_str(&format!(
+            "Open the conversation with a greeting and one question, in {}.\n\n\
+             Reply with only a JSON object with \"reply\" and \"vocabulary\" (the base forms of the \
+             {} words in your reply worth learning).",
+            language, language
+        )),
+        Some(message) => prompt.push_str(&format!(
+            "The learner's words were transcribed from speech, so ignore punctuation and capitalisation.\n\n\
+             {}Learner: {}\n\n\
+             Reply with only a JSON object with \"corrected\" (the learner's message with grammar and \
+             word choice fixed, or an empty string if it was fine), \"explanation\" (one or two sentences \
+             in English on what was wrong, or empty), \"reply\" (your next line in {}, continuing the \
+             conversation) and \"vocabulary\" (the base forms of the {} words in the learner's message \
+             and your reply worth learning).",
+            history, message, language, language
+        )),
+    }
+    prompt
+}
+
+async fn ask_tutor(app_handle: &AppHandle, prompt: &str) -> Result<TutorReply, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let response = engine::generate(app_handle, prompt, &app_settings).await?;
+    let json = structured::extract_json(&response, '{', '}').ok_or("The tutor did not answer".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+// Start reading the tutor's line aloud; playback carries on after this returns
+async fn speak_reply(app_handle: &AppHandle, reply: &str, rate: f32) {
+    if let Err(e) = tts::say(app_handle, reply, None, Some(rate)).await {
+        tracing::warn!(error = %e, "reading practice reply failed");
+    }
+}
+
+fn check_rate(rate: f32) -> Result<(), String> {
+    if !(0.25..=4.0).contains(&rate) {
+        return Err("The speaking rate must be between 0.25 and 4".to_string());
+    }
+    Ok(())
+}
+
+// Command to start practising a language, by ISO code or name, replacing any
+// session in progress; the tutor opens the conversation
+#[tauri::command]
+pub async fn start_practice(
+    language: String,
+    level: Option<PracticeLevel>,
+    topic: Option<String>,
+    rate: Option<f32>,
+    speak: Option<bool>,
+    app_handle: AppHandle,
+    state: State<'_, PracticeState>,
+) -> Result<PracticeTurn, String> {
+    let language = whisper::language_code(&language).ok_or(format!("Unsupported language: {}", language))?;
+    let topic = topic.map(|topic| topic.trim().to_string()).filter(|topic| !topic.is_empty());
+    if topic.as_ref().is_some_and(|topic| topic.chars().count() > MAX_TOPIC_CHARS) {
+        return Err(format!("The topic can be at most {} characters", MAX_TOPIC_CHARS));
+    }
+    let rate = rate.unwrap_or(DEFAULT_RATE);
+    check_rate(rate)?;
+
+    let mut session = PracticeSession {
+        id: 0,
+        language,
+        level: level.unwrap_or_default(),
+        topic,
+        rate,
+        history: Vec::new(),
+    };
+    let tutor = ask_tutor(&app_handle, &build_prompt(&session, None)).await?;
+    let new_words = record_progress(&app_handle, language, &tutor.vocabulary, true, false)?;
+
+    let id = {
+        let mut sessions = state.lock().map_err(|e| e.to_string())?;
+        sessions.last_id += 1;
+        session.id = sessions.last_id;
+        session.history.push((String::new(), tutor.reply.clone()));
+        sessions.session = Some(session);
+        sessions.last_id
+    };
+    if speak.unwrap_or(true) {
+        speak_reply(&app_handle, &tutor.reply, rate).await;
+    }
+    Ok(PracticeTurn {
+        session: id,
+        said: String::new(),
+        corrected: None,
+        explanation: None,
+        reply: tutor.reply,
+        new_words,
+    })
+}
+
+// Command to answer the tutor, either as text or as the path of a recording,
+// which is transcribed in the language being practised
+#[tauri::command]
+pub async fn practice_reply(
+    session: u64,
+    text: Option<String>,
+    recording: Option<String>,
+    speak: Option<bool>,
+    app_handle: AppHandle,
+    state: State<'_, PracticeState>,
+) -> Result<PracticeTurn, String> {
+    let current = state
+        .lock()
+        .map_err(|e| e.to_string())?
+        .session
+        .clone()
+        .filter(|current| current.id == session)
+        .ok_or("That practice session has ended".to_string())?;
+    let language = current.language;
+
+    let said = match (text, recording) {
+        (Some(text), _) => text,
+        (None, Some(path)) => {
+            let wav = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+            stt::transcribe_in(&app_handle, wav, language).await?.text
+        }
+        (None, None) => return Err("Nothing was said".to_string()),
+    };
+    let said = said.trim().to_string();
+    if said.is_empty() {
+        return Err("Nothing was said".to_string());
+    }
+
+    let tutor = ask_tutor(&app_handle, &build_prompt(&current, Some(&said))).await?;
+    let corrected = Some(tutor.corrected.trim().to_string())
+        .filter(|corrected| !corrected.is_empty() && !corrected.eq_ignore_ascii_case(&said));
+    let explanation = Some(tutor.explanation.trim().to_string()).filter(|explanation| !explanation.is_empty());
+    let new_words = record_progress(&app_handle, language, &tutor.vocabulary, false, corrected.is_some())?;
+
+    let rate = {
+        let mut sessions = state.lock().map_err(|e| e.to_string())?;
+        let current = sessions
+            .session
+            .as_mut()
+            .filter(|current| current.id == session)
+            .ok_or("That practice session has ended".to_string())?;
+        current.history.push((said.clone(), tutor.reply.clone()));
+        current.rate
+    };
+    if speak.unwrap_or(true) {
+        speak_reply(&app_handle, &tutor.reply, rate).await;
+    }
+    Ok(PracticeTurn {
+        session,
+        said,
+        corrected,
+        explanation,
+        reply: tutor.reply,
+        new_words,
+    })
+}
+
+// Command to change how fast the tutor's replies are read
+#[tauri::command]
+pub fn set_practice_rate(session: u64, rate: f32, state: State<'_, PracticeState>) -> Result<(), String> {
+    check_rate(rate)?;
+    let mut sessions = state.lock().map_err(|e| e.to_string())?;
+    let current = sessions
+        .session
+        .as_mut()
+        .filter(|current| current.id == session)
+        .ok_or("That practice session has ended".to_string())?;
+    current.rate = rate;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_practice(state: State<'_, PracticeState>) -> Result<(), String> {
+    state.lock().map_err(|e| e.to_string())?.session = None;
+    Ok(())
+}
+
+// Command listing progress per language, or for one language by code or name
+#[tauri::command]
+pub fn get_practice_progress(language: Option<String>, app_handle: AppHandle) -> Result<Vec<LanguageProgress>, String> {
+    let store: ProgressStore = storage::load_json(&app_handle, PRACTICE_FILE);
+    let wanted = match language {
+        Some(language) => {
+            Some(whisper::language_code(&language).ok_or(format!("Unsupported language: {}", language))?)
+        }
+        None => None,
+    };
+    Ok(store
+        .languages
+        .into_iter()
+        .filter(|progress| match wanted {
+            Some(code) => progress.language == code,
+            None => true,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(history: Vec<(String, String)>) -> PracticeSession {
+        PracticeSession {
+            id: 1,
+            language: "es",
+            level: PracticeLevel::Beginner,
+            topic: Some("food".to_string()),
+            rate: DEFAULT_RATE,
+            history,
+        }
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn vocabulary_is_counted_per_language() {
+        let mut store = ProgressStore::default();
+        assert_eq!(record(&mut store, "es", &words(&["Hola", "comer", " "]), true, false, 10), vec!["hola", "comer"]);
+        assert_eq!(record(&mut store, "es", &words(&["comer", "beber"]), false, true, 20), vec!["beber"]);
+        assert_eq!(record(&mut store, "fr", &words(&["manger"]), true, false, 30), vec!["manger"]);
+
+        let spanish = &store.languages[0];
+        assert_eq!((spanish.sessions, spanish.messages, spanish.corrections), (1, 1, 1));
+        let comer = spanish.vocabulary.iter().find(|entry| entry.word == "comer").unwrap();
+        assert_eq!((comer.times_seen, comer.first_seen, comer.last_seen), (2, 10, 20));
+        assert_eq!(store.languages[1].language, "fr");
+    }
+
+    #[test]
+    fn prompts_carry_the_recent_conversation() {
+        let opening = build_prompt(&session(Vec::new()), None);
+        assert!(opening.contains("friendly spanish tutor"));
+        assert!(opening.contains("about food"));
+        assert!(opening.contains("Open the conversation"));
+
+        let mut history = vec![(String::new(), "¡Hola!".to_string())];
+        history.extend((1..=MAX_HISTORY).map(|turn| (format!("learner {}", turn), format!("tutor {}", turn))));
+        let prompt = build_prompt(&session(history), Some("yo como pan"));
+        assert!(!prompt.contains("¡Hola!"));
+        assert!(prompt.contains("Learner: learner 1\nTutor: tutor 1\n"));
+        assert!(prompt.contains(&format!("Tutor: tutor {}\nLearner: yo como pan", MAX_HISTORY)));
+    }
+
+    #[test]
+    fn speaking_rates_are_bounded() {
+        assert!(check_rate(DEFAULT_RATE).is_ok());
+        assert!(check_rate(0.1).is_err());
+        assert!(check_rate(f32::NAN).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+// Speculative prefetch: predicts the next likely interactions (the morning
+// check after waking, the agenda before a meeting) and warms their data while
+// the device is idle and the power policy allows background work.
+
+use chrono::{Local, NaiveDateTime, Timelike};
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_system_info::model::BatteryState;
+
+use crate::settings::{self, PowerPolicy, SettingsState};
+use crate::storage;
+use crate::thermal;
+use crate::widgets::{self, WidgetKind};
+
+const PREFETCH_FILE: &str = "prefetch.json";
+const PREFETCH_TICK: Duration = Duration::from_secs(5 * 60);
+// No interaction for this long counts as idle
+const IDLE_SECS: u64 = 10 * 60;
+// Warm caches this long before the predicted wake time or meeting start
+const WAKE_LEAD_MINS: i64 = 45;
+const MEETING_LEAD_SECS: i64 = 30 * 60;
+// Days of wake times kept, and needed before predicting one
+const WAKE_HISTORY_DAYS: usize = 14;
+const MIN_WAKE_SAMPLES: usize = 3;
+// Activity before this hour is not treated as waking up
+const EARLIEST_WAKE_HOUR: u32 = 4;
+const MAX_WARMED: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WakeSample {
+    pub date: String,
+    // Minutes after local midnight of the first interaction that day
+    pub minute: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Prediction {
+    Wake { minute: u32 },
+    Meeting { summary: String, start: i64 },
+}
+
+impl Prediction {
+    // Identifies a prediction so its data is only warmed once
+    fn key(&self) -> String {
+        match self {
+            Prediction::Wake { .. } => format!("wake:{}", Local::now().format("%Y-%m-%d")),
+            Prediction::Meeting { start, .. } => format!("meeting:{}", start),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct PrefetchStore {
+    wake_samples: Vec<WakeSample>,
+    #[serde(skip)]
+    last_activity: u64,
+    #[serde(skip)]
+    warmed: Vec<String>,
+}
+
+pub type PrefetchState = Mutex<PrefetchStore>;
+
+impl PrefetchStore {
+    // Returns whether a new wake time was learned
+    fn note_activity(&mut self, now: u64, local: NaiveDateTime) -> bool {
+        self.last_activity = now;
+        let date = local.format("%Y-%m-%d").to_string();
+        if local.hour() < EARLIEST_WAKE_HOUR || self.wake_samples.iter().any(|sample| sample.date == date) {
+            return false;
+        }
+        self.wake_samples.push(WakeSample {
+            date,
+            minute: local.hour() * 60 + local.minute(),
+        });
+        let excess = self.wake_samples.len().saturating_sub(WAKE_HISTORY_DAYS);
+        self.wake_samples.drain(..excess);
+        true
+    }
+}
+
+pub fn load(app_handle: &AppHandle) -> PrefetchStore {
+    storage::load_json(app_handle, PREFETCH_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Note a user interaction; the first one each morning is learned as the wake time
+pub fn record_activity(app_handle: &AppHandle) {
+    let state = app_handle.state::<PrefetchState>();
+    let Ok(mut store) = state.lock() else {
+        return;
+    };
+    if !store.note_activity(now_secs(), Local::now().naive_local()) {
+        return;
+    }
+    let _ = storage::save_json(app_handle, PREFETCH_FILE, &*store);
+}
+
+// Median of the learned wake times, once there are enough of them
+fn usual_wake_minute(samples: &[WakeSample]) -> Option<u32> {
+    if samples.len() < MIN_WAKE_SAMPLES {
+        return None;
+    }
+    let mut minutes: Vec<u32> = samples.iter().map(|sample| sample.minute).collect();
+    minutes.sort_unstable();
+    Some(minutes[minutes.len() / 2])
+}
+
+fn wake_soon(wake_minute: u32, now_minute: u32) -> bool {
+    (0..=WAKE_LEAD_MINS).contains(&(wake_minute as i64 - now_minute as i64))
+}
+
+// Interactions expected soon enough that their data is worth warming now
+pub fn predictions(app_handle: &AppHandle) -> Vec<Prediction> {
+    let mut predictions = Vec::new();
+
+    let (wake, woke_today) = {
+        let state = app_handle.state::<PrefetchState>();
+        let Ok(store) = state.lock() else {
+            return predictions;
+        };
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        (
+            usual_wake_minute(&store.wake_samples),
+            store.wake_samples.iter().any(|sample| sample.date == today),
+        )
+    };
+    if let Some(minute) = wake {
+        let now = Local::now();
+        if !woke_today && wake_soon(minute, now.hour() * 60 + now.minute()) {
+            predictions.push(Prediction::Wake { minute });
+        }
+    }
+
+    let now = now_secs() as i64;
+    for event in crate::calendar::upcoming_events(app_handle, 1) {
+        let starts_in = event.start - now;
+        if !event.all_day && (0..=MEETING_LEAD_SECS).contains(&starts_in) {
+            predictions.push(Prediction::Meeting {
+                summary: event.summary,
+                start: event.start,
+            });
+        }
+    }
+    predictions
+}
+
+// Whether the power policy allows background work right now
+fn power_allows(app_handle: &AppHandle, policy: &PowerPolicy) -> bool {
+    if !policy.prefetch {
+        return false;
+    }
+    let Some(sys_info) = app_handle.try_state::<tauri_plugin_system_info::SysInfoState>() else {
+        // Without battery information (e.g. desktops) there is nothing to protect
+        return true;
+    };
+    let Ok(batteries) = tauri_plugin_system_info::commands::battery::batteries(sys_info) else {
+        return true;
+    };
+    let Some(battery) = batteries.first() else {
+        return true;
+    };
+    let charging = matches!(battery.state, BatteryState::Charging | BatteryState::Full);
+    battery_allows(policy, charging, battery.state_of_charge)
+}
+
+fn battery_allows(policy: &PowerPolicy, charging: bool, level: u8) -> bool {
+    if policy.require_charging {
+        charging
+    } else {
+        charging || level >= policy.min_battery
+    }
+}
+
+fn is_idle(app_handle: &AppHandle) -> bool {
+    let state = app_handle.state::<PrefetchState>();
+    state
+        .lock()
+        .map(|store| now_secs().saturating_sub(store.last_activity) >= IDLE_SECS)
+        .unwrap_or(false)
+}
+
+async fn warm(app_handle: &AppHandle, prediction: &Prediction) {
+    match prediction {
+        Prediction::Wake { .. } => {
+            let _ = crate::calendar::sync_all(app_handle).await;
+            widgets::refresh_now(app_handle, &[WidgetKind::Weather, WidgetKind::Agenda]).await;
+        }
+        Prediction::Meeting { .. } => {
+            let _ = crate::calendar::sync_all(app_handle).await;
+            widgets::refresh_now(app_handle, &[WidgetKind::Agenda]).await;
+        }
+    }
+}
+
+async fn run_once(app_handle: &AppHandle) {
+    let policy = settings::current(&app_handle.state::<SettingsState>()).power;
+    if !is_idle(app_handle) || !power_allows(app_handle, &policy) || !thermal::allows_background(app_handle) {
+        return;
+    }
+
+    for prediction in predictions(app_handle) {
+        let key = prediction.key();
+        {
+            let state = app_handle.state::<PrefetchState>();
+            let Ok(mut store) = state.lock() else {
+                return;
+            };
+            if store.warmed.contains(&key) {
+                continue;
+            }
+            store.warmed.push(key);
+            if store.warmed.len() > MAX_WARMED {
+                store.warmed.remove(0);
+            }
+        }
+        warm(app_handle, &prediction).await;
+    }
+}
+
+pub fn spawn_prefetcher(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(PREFETCH_TICK).await;
+            run_once(&app_handle).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_prefetch_predictions(app_handle: AppHandle) -> Vec<Prediction> {
+    predictions(&app_handle)
+}
+
+#[tauri::command]
+pub fn get_wake_history(state: State<'_, PrefetchState>) -> Result<Vec<WakeSample>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.wake_samples.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn first_activity_after_four_is_the_wake_time() {
+        let mut store = PrefetchStore::default();
+        assert!(!store.note_activity(1, at(1, 2, 30)));
+        assert!(store.note_activity(2, at(1, 7, 15)));
+        assert!(!store.note_activity(3, at(1, 9, 0)));
+        assert_eq!(store.wake_samples.len(), 1);
+        assert_eq!(store.wake_samples[0].minute, 7 * 60 + 15);
+        assert_eq!(store.last_activity, 3);
+    }
+
+    #[test]
+    fn wake_history_is_capped() {
+        let mut store = PrefetchStore::default();
+        for day in 1..=20 {
+            store.note_activity(day as u64, at(day, 7, 0));
+        }
+        assert_eq!(store.wake_samples.len(), WAKE_HISTORY_DAYS);
+        assert_eq!(store.wake_samples[0].date, "2024-03-07");
+    }
+
+    #[test]
+    fn usual_wake_is_the_median() {
+        let sample = |minute| WakeSample {
+            date: String::new(),
+            minute,
+        };
+        assert_eq!(usual_wake_minute(&[sample(420), sample(400)]), None);
+        assert_eq!(usual_wake_minute(&[sample(480), sample(400), sample(420), sample(900)]), Some(480));
+        assert_eq!(usual_wake_minute(&[sample(480), sample(400), sample(420)]), Some(420));
+    }
+
+    #[test]
+    fn wake_is_predicted_shortly_before() {
+        assert!(wake_soon(420, 420));
+        assert!(wake_soon(420, 420 - WAKE_LEAD_MINS as u32));
+        assert!(!wake_soon(420, 419 - WAKE_LEAD_MINS as u32));
+        assert!(!wake_soon(420, 421));
+    }
+
+    #[test]
+    fn power_policy_checks_battery() {
+        let strict = PowerPolicy::default();
+        assert!(battery_allows(&strict, true, 10));
+        assert!(!battery_allows(&strict, false, 100));
+
+        let relaxed = PowerPolicy {
+            require_charging: false,
+            min_battery: 50,
+            ..PowerPolicy::default()
+        };
+        assert!(battery_allows(&relaxed, false, 50));
+        assert!(!battery_allows(&relaxed, false, 49));
+        assert!(battery_allows(&relaxed, true, 5));
+    }
+}
@@ -0,0 +1,220 @@
+// Cold start and first-use timings. Each launch records how long every
+// service took to initialize and how long the first command, transcription
+// and response took; recent launches are kept for percentiles.
+
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State, Wry};
+use tauri::ipc::Invoke;
+
+use crate::storage;
+
+const PERFORMANCE_FILE: &str = "performance.json";
+// Launches kept per metric
+const MAX_SAMPLES: usize = 50;
+
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Timing {
+    pub metric: String,
+    pub ms: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MetricSummary {
+    pub metric: String,
+    pub samples: usize,
+    pub last_ms: u64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PerformanceReport {
+    // Timings from this launch, in the order they happened
+    pub launch: Vec<Timing>,
+    pub first_command: Option<String>,
+    pub metrics: Vec<MetricSummary>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ProfilerStore {
+    samples: BTreeMap<String, VecDeque<u64>>,
+    #[serde(skip)]
+    launch: Vec<Timing>,
+    #[serde(skip)]
+    first_command: Option<String>,
+}
+
+pub type ProfilerState = Mutex<ProfilerStore>;
+
+// Times consecutive phases of setup, before the profiler state exists
+pub struct StartupTimer {
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl StartupTimer {
+    pub fn start() -> Self {
+        StartupTimer {
+            last: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    // End the current phase under the given name and start the next
+    pub fn mark(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.phases.push((phase, now - self.last));
+        self.last = now;
+    }
+
+    pub fn finish(self, app_handle: &AppHandle) {
+        let state = app_handle.state::<ProfilerState>();
+        let Ok(mut store) = state.lock() else {
+            return;
+        };
+        for (phase, elapsed) in self.phases {
+            push(&mut store, format!("startup.{}", phase), elapsed);
+        }
+        push(&mut store, "startup.total".to_string(), since_start());
+        let _ = storage::save_json(app_handle, PERFORMANCE_FILE, &*store);
+    }
+}
+
+// Called first thing in run(), so timings include process startup
+pub fn init() {
+    PROCESS_START.get_or_init(Instant::now);
+}
+
+fn since_start() -> Duration {
+    PROCESS_START.get_or_init(Instant::now).elapsed()
+}
+
+pub fn load(app_handle: &AppHandle) -> ProfilerStore {
+    storage::load_json(app_handle, PERFORMANCE_FILE)
+}
+
+fn push(store: &mut ProfilerStore, metric: String, elapsed: Duration) {
+    let ms = elapsed.as_millis() as u64;
+    let samples = store.samples.entry(metric.clone()).or_default();
+    samples.push_back(ms);
+    while samples.len() > MAX_SAMPLES {
+        samples.pop_front();
+    }
+    store.launch.push(Timing { metric, ms });
+}
+
+// Record a metric the first time it happens in this launch; later calls are ignored
+pub fn record_first(app_handle: &AppHandle, metric: &str, elapsed: Duration) {
+    let Some(state) = app_handle.try_state::<ProfilerState>() else {
+        return;
+    };
+    let Ok(mut store) = state.lock() else {
+        return;
+    };
+    if store.launch.iter().any(|timing| timing.metric == metric) {
+        return;
+    }
+    push(&mut store, metric.to_string(), elapsed);
+    let _ = storage::save_json(app_handle, PERFORMANCE_FILE, &*store);
+}
+
+// Wrap the command handler so the first command of a launch is timed from process start
+pub fn timed<F>(handler: F) -> impl Fn(Invoke<Wry>) -> bool + Send + Sync + 'static
+where
+    F: Fn(Invoke<Wry>) -> bool + Send + Sync + 'static,
+{
+    let seen = AtomicBool::new(false);
+    move |invoke| {
+        if !seen.swap(true, Ordering::Relaxed) {
+            let command = invoke.message.command().to_string();
+            let webview = invoke.message.webview();
+            let app_handle = webview.app_handle();
+            record_first(app_handle, "first_command", since_start());
+            if let Some(state) = app_handle.try_state::<ProfilerState>() {
+                if let Ok(mut store) = state.lock() {
+                    store.first_command = Some(command);
+                }
+            }
+        }
+        handler(invoke)
+    }
+}
+
+// Nearest-rank percentile of sorted samples
+pub(crate) fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[tauri::command]
+pub fn get_performance_report(state: State<'_, ProfilerState>) -> Result<PerformanceReport, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    let metrics = store
+        .samples
+        .iter()
+        .filter(|(_, samples)| !samples.is_empty())
+        .map(|(metric, samples)| {
+            let mut sorted: Vec<u64> = samples.iter().copied().collect();
+            sorted.sort_unstable();
+            MetricSummary {
+                metric: metric.clone(),
+                samples: sorted.len(),
+                last_ms: samples.back().copied().unwrap_or(0),
+                p50_ms: percentile(&sorted, 50),
+                p90_ms: percentile(&sorted, 90),
+                p99_ms: percentile(&sorted, 99),
+            }
+        })
+        .collect();
+    Ok(PerformanceReport {
+        launch: store.launch.clone(),
+        first_command: store.first_command.clone(),
+        metrics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let sorted: Vec<u64> = (1..=10).collect();
+        assert_eq!(percentile(&sorted, 50), 5);
+        assert_eq!(percentile(&sorted, 90), 9);
+        assert_eq!(percentile(&sorted, 99), 10);
+        assert_eq!(percentile(&sorted, 0), 1);
+        assert_eq!(percentile(&[42], 99), 42);
+    }
+
+    #[test]
+    fn samples_are_capped_per_metric() {
+        let mut store = ProfilerStore::default();
+        for ms in 0..MAX_SAMPLES as u64 + 3 {
+            push(&mut store, "startup.total".to_string(), Duration::from_millis(ms));
+        }
+        push(&mut store, "first_command".to_string(), Duration::from_millis(7));
+        let samples = &store.samples["startup.total"];
+        assert_eq!(samples.len(), MAX_SAMPLES);
+        assert_eq!(samples.front(), Some(&3));
+        // Every push of this launch is listed, in order
+        assert_eq!(store.launch.len(), MAX_SAMPLES + 4);
+        assert_eq!(store.launch.last().map(|timing| timing.ms), Some(7));
+    }
+
+    #[test]
+    fn startup_phases_are_named_in_order() {
+        let mut timer = StartupTimer::start();
+        timer.mark("settings");
+        timer.mark("plugins");
+        let names: Vec<&str> = timer.phases.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["settings", "plugins"]);
+    }
+}
@@ -0,0 +1,330 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+
+use crate::keystore::Keystore;
+use crate::storage;
+use crate::usage::Provider;
+
+const KEYS_FILE: &str = "provider_keys.json";
+// How long a key that hit a rate limit is skipped
+const RATE_LIMIT_COOLDOWN_SECS: u64 = 60;
+// Id of the key read from .env, used once every stored key is exhausted
+pub const ENV_KEY_ID: &str = "env";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyHealth {
+    Healthy,
+    RateLimited,
+    Invalid,
+}
+
+// Metadata for a stored key; the secret lives in the keystore under api_key:<id>
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProviderKey {
+    pub id: String,
+    pub provider: Provider,
+    pub label: String,
+    pub health: KeyHealth,
+    #[serde(default)]
+    pub limited_until: Option<u64>,
+    #[serde(default)]
+    pub failures: u32,
+    #[serde(default)]
+    pub last_status: Option<u16>,
+    #[serde(default)]
+    pub last_used: Option<u64>,
+}
+
+pub struct ApiKey {
+    pub id: String,
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ProviderKeysStore {
+    next_id: u64,
+    keys: Vec<ProviderKey>,
+}
+
+pub type ProviderKeysState = Mutex<ProviderKeysStore>;
+
+pub fn load(app_handle: &AppHandle) -> ProviderKeysStore {
+    storage::load_json(app_handle, KEYS_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn env_var(provider: Provider) -> Option<&'static str> {
+    match provider {
+        Provider::Gemini => Some("GEMINI_API_KEY"),
+        Provider::Whisper => Some("OPENAI_API_KEY"),
+        Provider::Ollama => None,
+        Provider::Deepgram => Some("DEEPGRAM_API_KEY"),
+        Provider::Anthropic => Some("ANTHROPIC_API_KEY"),
+    }
+}
+
+fn usable(key: &ProviderKey, now: u64) -> bool {
+    match key.health {
+        KeyHealth::Healthy => true,
+        KeyHealth::RateLimited => key.limited_until.map(|until| now >= until).unwrap_or(true),
+        KeyHealth::Invalid => false,
+    }
+}
+
+impl ProviderKeysStore {
+    // Ids of the provider's usable keys, in rotation order
+    fn candidates(&self, provider: Provider, now: u64) -> Vec<String> {
+        self.keys
+            .iter()
+            .filter(|key| key.provider == provider && usable(key, now))
+            .map(|key| key.id.clone())
+            .collect()
+    }
+
+    // Apply a call's outcome to the key; false when the key is unknown
+    fn record(&mut self, key_id: &str, status: u16, now: u64) -> bool {
+        let Some(key) = self.keys.iter_mut().find(|key| key.id == key_id) else {
+            return false;
+        };
+        key.last_used = Some(now);
+        key.last_status = Some(status);
+        match status {
+            401 | 403 => {
+                key.health = KeyHealth::Invalid;
+                key.failures = key.failures.saturating_add(1);
+            }
+            429 => {
+                key.health = KeyHealth::RateLimited;
+                key.limited_until = Some(now.saturating_add(RATE_LIMIT_COOLDOWN_SECS));
+                key.failures = key.failures.saturating_add(1);
+            }
+            200..=299 => {
+                key.health = KeyHealth::Healthy;
+                key.limited_until = None;
+                key.failures = 0;
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+// The key to use next: the first usable stored key in order, otherwise the .env key
+pub fn select_key(app_handle: &AppHandle, provider: Provider) -> Result<ApiKey, String> {
+    let candidates = {
+        let state = app_handle.state::<ProviderKeysState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store.candidates(provider, now_secs())
+    };
+
+    let keystore = app_handle.state::<Keystore>();
+    for id in candidates {
+        if let Some(secret) = keystore.get(&format!("api_key:{}", id))? {
+            return Ok(ApiKey { id, secret });
+        }
+    }
+
+    dotenv::dotenv().ok();
+    env_var(provider)
+        .and_then(|name| std::env::var(name).ok())
+        .map(|secret| ApiKey {
+            id: ENV_KEY_ID.to_string(),
+            secret,
+        })
+        .ok_or("No usable API key; all keys are rate limited or invalid".to_string())
+}
+
+// Whether a response status means the next key should be tried
+pub fn should_rotate(status: u16) -> bool {
+    matches!(status, 401 | 403 | 429)
+}
+
+// Record the outcome of a call made with a key
+pub fn report(app_handle: &AppHandle, key_id: &str, status: u16) {
+    if key_id == ENV_KEY_ID {
+        return;
+    }
+    let state = app_handle.state::<ProviderKeysState>();
+    let Ok(mut store) = state.lock() else { return };
+    if store.record(key_id, status, now_secs()) {
+        let _ = storage::save_json(app_handle, KEYS_FILE, &*store);
+    }
+}
+
+// Command to store another API key for a provider
+#[tauri::command]
+pub fn add_provider_key(
+    provider: Provider,
+    label: String,
+    secret: String,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, ProviderKeysState>,
+) -> Result<ProviderKey, String> {
+    if secret.trim().is_empty() {
+        return Err("API key is empty".to_string());
+    }
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.next_id += 1;
+    let key = ProviderKey {
+        id: store.next_id.to_string(),
+        provider,
+        label,
+        health: KeyHealth::Healthy,
+        limited_until: None,
+        failures: 0,
+        last_status: None,
+        last_used: None,
+    };
+    keystore.set(&format!("api_key:{}", key.id), secret.trim())?;
+    store.keys.push(key.clone());
+    storage::save_json(&app_handle, KEYS_FILE, &*store)?;
+    Ok(key)
+}
+
+#[tauri::command]
+pub fn remove_provider_key(
+    id: String,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, ProviderKeysState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.keys.retain(|key| key.id != id);
+    keystore.delete(&format!("api_key:{}", id))?;
+    storage::save_json(&app_handle, KEYS_FILE, &*store)
+}
+
+// Command to mark a key healthy again, e.g. after fixing billing
+#[tauri::command]
+pub fn reset_provider_key(id: String, app_handle: AppHandle, state: State<'_, ProviderKeysState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let key = store
+        .keys
+        .iter_mut()
+        .find(|key| key.id == id)
+        .ok_or("Key not found".to_string())?;
+    key.health = KeyHealth::Healthy;
+    key.limited_until = None;
+    key.failures = 0;
+    storage::save_json(&app_handle, KEYS_FILE, &*store)
+}
+
+// Command reporting the health of every stored key, in rotation order
+#[tauri::command]
+pub fn get_provider_keys_status(
+    provider: Option<Provider>,
+    state: State<'_, ProviderKeysState>,
+) -> Result<Vec<ProviderKey>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    let now = now_secs();
+    Ok(store
+        .keys
+        .iter()
+        .filter(|key| provider.map(|p| key.provider == p).unwrap_or(true))
+        .cloned()
+        .map(|mut key| {
+            // Cooldowns expire lazily; report them as healthy once over
+            if key.health == KeyHealth::RateLimited && usable(&key, now) {
+                key.health = KeyHealth::Healthy;
+                key.limited_until = None;
+            }
+            key
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: &str, provider: Provider) -> ProviderKey {
+        ProviderKey {
+            id: id.to_string(),
+            provider,
+            label: format!("key {}", id),
+            health: KeyHealth::Healthy,
+            limited_until: None,
+            failures: 0,
+            last_status: None,
+            last_used: None,
+        }
+    }
+
+    fn store() -> ProviderKeysStore {
+        ProviderKeysStore {
+            next_id: 3,
+            keys: vec![
+                key("1", Provider::Gemini),
+                key("2", Provider::Anthropic),
+                key("3", Provider::Gemini),
+            ],
+        }
+    }
+
+    #[test]
+    fn rotates_on_auth_and_rate_limit_statuses() {
+        assert!(should_rotate(401));
+        assert!(should_rotate(403));
+        assert!(should_rotate(429));
+        assert!(!should_rotate(200));
+        assert!(!should_rotate(500));
+    }
+
+    #[test]
+    fn candidates_keep_order_and_provider() {
+        assert_eq!(store().candidates(Provider::Gemini, 0), vec!["1", "3"]);
+        assert_eq!(store().candidates(Provider::Anthropic, 0), vec!["2"]);
+        assert!(store().candidates(Provider::Ollama, 0).is_empty());
+    }
+
+    #[test]
+    fn rate_limited_key_is_skipped_until_the_cooldown_ends() {
+        let mut store = store();
+        assert!(store.record("1", 429, 1_000));
+        let limited = &store.keys[0];
+        assert_eq!(limited.health, KeyHealth::RateLimited);
+        assert_eq!(limited.limited_until, Some(1_000 + RATE_LIMIT_COOLDOWN_SECS));
+        assert_eq!(limited.failures, 1);
+        assert_eq!(store.candidates(Provider::Gemini, 1_001), vec!["3"]);
+        assert_eq!(store.candidates(Provider::Gemini, 1_000 + RATE_LIMIT_COOLDOWN_SECS), vec!["1", "3"]);
+
+        // A cooldown far in the future does not overflow
+        assert!(store.record("1", 429, u64::MAX));
+        assert_eq!(store.keys[0].limited_until, Some(u64::MAX));
+    }
+
+    #[test]
+    fn invalid_keys_stay_out_until_a_success() {
+        let mut store = store();
+        assert!(store.record("3", 401, 10));
+        assert_eq!(store.keys[2].health, KeyHealth::Invalid);
+        assert_eq!(store.candidates(Provider::Gemini, u64::MAX), vec!["1"]);
+
+        assert!(store.record("3", 204, 20));
+        let key = &store.keys[2];
+        assert_eq!(key.health, KeyHealth::Healthy);
+        assert_eq!(key.failures, 0);
+        assert_eq!(key.last_status, Some(204));
+        assert_eq!(key.last_used, Some(20));
+    }
+
+    #[test]
+    fn other_statuses_only_note_the_call() {
+        let mut store = store();
+        assert!(store.record("2", 500, 5));
+        assert_eq!(store.keys[1].health, KeyHealth::Healthy);
+        assert_eq!(store.keys[1].last_status, Some(500));
+        assert!(!store.record("missing", 429, 5));
+        assert!(!store.record(ENV_KEY_ID, 429, 5));
+    }
+}
@@ -0,0 +1,564 @@
+// Push delivery without Google services: UnifiedPush through a distributor
+// app on Android, or a self-hosted WebSocket relay. Server-side events
+// (package updates, shared reminders) arrive here as subscriptions to topics.
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Emitter, Manager, State,
+};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use url::{Host, Url};
+
+use crate::keystore::Keystore;
+use crate::storage;
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+const PUSH_FILE: &str = "push.json";
+// The relay access token lives in the keystore under push_relay_token
+const RELAY_TOKEN_KEY: &str = "push_relay_token";
+const RELAY_RETRY_MIN: Duration = Duration::from_secs(5);
+const RELAY_RETRY_MAX: Duration = Duration::from_secs(300);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PushTransport {
+    #[default]
+    UnifiedPush,
+    Relay,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    // Waiting for the distributor endpoint or the relay connection
+    Pending,
+    Active,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PushConfig {
+    pub transport: PushTransport,
+    // wss:// URL of the relay when the relay transport is used
+    pub relay_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PushSubscription {
+    // Topic name, also used as the UnifiedPush instance
+    pub topic: String,
+    pub status: SubscriptionStatus,
+    // UnifiedPush endpoint the server posts to
+    pub endpoint: Option<String>,
+    pub error: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum PushKind {
+    #[default]
+    Notification,
+    // Something changed server side; pull calendars and contacts again
+    Sync,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PushPayload {
+    topic: Option<String>,
+    kind: PushKind,
+    title: Option<String>,
+    body: String,
+    reference: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PushEvent {
+    pub topic: Option<String>,
+    pub title: String,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct PushStore {
+    config: PushConfig,
+    subscriptions: Vec<PushSubscription>,
+    #[serde(skip)]
+    relay_task: Option<tauri::async_runtime::JoinHandle<()>>,
+}
+
+pub type PushState = Mutex<PushStore>;
+
+pub fn load(app_handle: &AppHandle) -> PushStore {
+    storage::load_json(app_handle, PUSH_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct InstanceRequest<'a> {
+    instance: &'a str,
+}
+
+pub struct PushBridge {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+}
+
+impl PushBridge {
+    // Ask the UnifiedPush distributor for an endpoint; it arrives via on_push_endpoint
+    fn register(&self, instance: &str) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("register", InstanceRequest { instance })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = instance;
+            Err("UnifiedPush is not available on this device".to_string())
+        }
+    }
+
+    fn unregister(&self, instance: &str) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            self.handle
+                .run_mobile_plugin::<()>("unregister", InstanceRequest { instance })
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = instance;
+            Err("UnifiedPush is not available on this device".to_string())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("push")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = PushBridge {
+                handle: api.register_android_plugin("company.atechnology.plates", "PushPlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                PushBridge {}
+            };
+            app.manage(bridge);
+            Ok(())
+        })
+        .build()
+}
+
+impl PushStore {
+    // Add a pending subscription; returns the trimmed topic
+    fn add(&mut self, topic: &str, now: u64) -> Result<String, String> {
+        let topic = topic.trim().to_string();
+        if topic.is_empty() {
+            return Err("Topic is required".to_string());
+        }
+        if self.subscriptions.iter().any(|subscription| subscription.topic == topic) {
+            return Err("Already subscribed to this topic".to_string());
+        }
+        self.subscriptions.push(PushSubscription {
+            topic: topic.clone(),
+            status: SubscriptionStatus::Pending,
+            endpoint: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        });
+        Ok(topic)
+    }
+
+    // Update subscriptions matching `topic`, all of them when None
+    fn update(&mut self, topic: Option<&str>, now: u64, update: impl Fn(&mut PushSubscription)) {
+        for subscription in self
+            .subscriptions
+            .iter_mut()
+            .filter(|subscription| topic.is_none() || topic == Some(subscription.topic.as_str()))
+        {
+            update(subscription);
+            subscription.updated_at = now;
+        }
+    }
+}
+
+fn update_subscriptions(
+    app_handle: &AppHandle,
+    topic: Option<&str>,
+    update: impl Fn(&mut PushSubscription),
+) -> Result<(), String> {
+    let state = app_handle.state::<PushState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.update(topic, now_secs(), update);
+    storage::save_json(app_handle, PUSH_FILE, &*store)
+}
+
+// Anything that is not a JSON payload is shown as the body of a notification;
+// the payload's own topic wins over the one it arrived on
+fn parse_payload(topic: Option<&str>, raw: &str) -> PushPayload {
+    let mut payload: PushPayload = serde_json::from_str(raw).unwrap_or_else(|_| PushPayload {
+        body: raw.to_string(),
+        ..Default::default()
+    });
+    payload.topic = payload.topic.or_else(|| topic.map(|topic| topic.to_string()));
+    payload
+}
+
+// Route one pushed message: sync requests pull fresh data, anything else goes to the digest
+fn handle_message(app_handle: &AppHandle, topic: Option<&str>, raw: &str) -> Result<(), String> {
+    let payload = parse_payload(topic, raw);
+    let topic = payload.topic;
+
+    match payload.kind {
+        PushKind::Sync => {
+            let handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let _ = crate::calendar::sync_all(&handle).await;
+                let _ = crate::contacts::sync_all(&handle).await;
+            });
+        }
+        PushKind::Notification => {
+            let title = payload
+                .title
+                .or_else(|| topic.clone())
+                .unwrap_or_else(|| "Push".to_string());
+            crate::notifications::push(app_handle, "push", title.clone(), payload.body.clone(), payload.reference)?;
+            let _ = app_handle.emit(
+                "push://message",
+                PushEvent {
+                    topic,
+                    title,
+                    body: payload.body,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn relay_session(app_handle: &AppHandle, url: &str, topics: Vec<String>) -> Result<(), String> {
+    let token = app_handle.state::<Keystore>().get(RELAY_TOKEN_KEY)?;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.map_err(|e| e.to_string())?;
+    let hello = json!({ "type": "subscribe", "token": token, "topics": topics });
+    socket
+        .send(WsMessage::Text(hello.to_string()))
+        .await
+        .map_err(|e| e.to_string())?;
+    update_subscriptions(app_handle, None, |subscription| {
+        subscription.status = SubscriptionStatus::Active;
+        subscription.error = None;
+    })?;
+
+    while let Some(frame) = socket.next().await {
+        match frame.map_err(|e| e.to_string())? {
+            WsMessage::Text(text) => {
+                // One bad message shouldn't drop the connection
+                if let Err(e) = handle_message(app_handle, None, &text) {
+                    tracing::warn!(error = %e, "push message not handled");
+                }
+            }
+            WsMessage::Close(_) => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// The relay is sent the token, so only TLS is allowed, except to this device
+fn check_relay_url(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid relay URL: {}", e))?;
+    let loopback = match url.host() {
+        Some(Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    };
+    match url.scheme() {
+        "wss" => Ok(()),
+        "ws" if loopback => Ok(()),
+        _ => Err("The relay URL must start with wss://".to_string()),
+    }
+}
+
+// (Re)start the relay connection for the current config and subscriptions
+fn restart_relay(app_handle: &AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<PushState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if let Some(task) = store.relay_task.take() {
+        task.abort();
+    }
+    if store.config.transport != PushTransport::Relay || store.subscriptions.is_empty() {
+        return Ok(());
+    }
+    let Some(url) = store.config.relay_url.clone() else {
+        return Ok(());
+    };
+    check_relay_url(&url)?;
+    let topics: Vec<String> = store.subscriptions.iter().map(|subscription| subscription.topic.clone()).collect();
+
+    let handle = app_handle.clone();
+    store.relay_task = Some(tauri::async_runtime::spawn(async move {
+        let mut delay = RELAY_RETRY_MIN;
+        loop {
+            match relay_session(&handle, &url, topics.clone()).await {
+                Ok(()) => delay = RELAY_RETRY_MIN,
+                Err(e) => {
+                    tracing::warn!(error = %e, "push relay disconnected");
+                    let _ = update_subscriptions(&handle, None, |subscription| {
+                        subscription.status = SubscriptionStatus::Pending;
+                        subscription.error = Some(e.clone());
+                    });
+                    delay = (delay * 2).min(RELAY_RETRY_MAX);
+                }
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }));
+    Ok(())
+}
+
+// Connect the relay at startup when it is the configured transport
+pub fn spawn_relay(app_handle: AppHandle) {
+    let _ = restart_relay(&app_handle);
+}
+
+// Register every subscription with the configured transport
+fn activate_all(app_handle: &AppHandle, bridge: &PushBridge) -> Result<(), String> {
+    let (transport, topics) = {
+        let state = app_handle.state::<PushState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        let topics: Vec<String> = store.subscriptions.iter().map(|subscription| subscription.topic.clone()).collect();
+        (store.config.transport, topics)
+    };
+    if transport == PushTransport::UnifiedPush {
+        for topic in topics {
+            if let Err(e) = bridge.register(&topic) {
+                update_subscriptions(app_handle, Some(&topic), |subscription| {
+                    subscription.status = SubscriptionStatus::Failed;
+                    subscription.error = Some(e.clone());
+                })?;
+            }
+        }
+    }
+    restart_relay(app_handle)
+}
+
+#[tauri::command]
+pub fn get_push_config(state: State<'_, PushState>) -> Result<PushConfig, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.config.clone())
+}
+
+// Command to switch transport; existing subscriptions move over to it
+#[tauri::command]
+pub fn configure_push(
+    config: PushConfig,
+    relay_token: Option<String>,
+    app_handle: AppHandle,
+    bridge: State<'_, PushBridge>,
+    keystore: State<'_, Keystore>,
+) -> Result<(), String> {
+    if let Some(url) = &config.relay_url {
+        check_relay_url(url)?;
+    }
+    if let Some(token) = relay_token {
+        keystore.set(RELAY_TOKEN_KEY, &token)?;
+    }
+    {
+        let state = app_handle.state::<PushState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        // Leaving UnifiedPush releases the distributor endpoints
+        if store.config.transport == PushTransport::UnifiedPush && config.transport != PushTransport::UnifiedPush {
+            for subscription in &store.subscriptions {
+                let _ = bridge.unregister(&subscription.topic);
+            }
+        }
+        store.config = config;
+        for subscription in store.subscriptions.iter_mut() {
+            subscription.status = SubscriptionStatus::Pending;
+            subscription.endpoint = None;
+            subscription.error = None;
+        }
+        storage::save_json(&app_handle, PUSH_FILE, &*store)?;
+    }
+    activate_all(&app_handle, &bridge)
+}
+
+#[tauri::command]
+pub fn subscribe_push(
+    topic: String,
+    app_handle: AppHandle,
+    bridge: State<'_, PushBridge>,
+) -> Result<PushSubscription, String> {
+    let topic = {
+        let state = app_handle.state::<PushState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        let topic = store.add(&topic, now_secs())?;
+        storage::save_json(&app_handle, PUSH_FILE, &*store)?;
+        topic
+    };
+    activate_all(&app_handle, &bridge)?;
+
+    let state = app_handle.state::<PushState>();
+    let store = state.lock().map_err(|e| e.to_string())?;
+    store
+        .subscriptions
+        .iter()
+        .find(|subscription| subscription.topic == topic)
+        .cloned()
+        .ok_or("Subscription not found".to_string())
+}
+
+#[tauri::command]
+pub fn unsubscribe_push(
+    topic: String,
+    app_handle: AppHandle,
+    bridge: State<'_, PushBridge>,
+) -> Result<(), String> {
+    let transport = {
+        let state = app_handle.state::<PushState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.subscriptions.retain(|subscription| subscription.topic != topic);
+        storage::save_json(&app_handle, PUSH_FILE, &*store)?;
+        store.config.transport
+    };
+    match transport {
+        PushTransport::UnifiedPush => bridge.unregister(&topic),
+        PushTransport::Relay => restart_relay(&app_handle),
+    }
+}
+
+#[tauri::command]
+pub fn list_push_subscriptions(state: State<'_, PushState>) -> Result<Vec<PushSubscription>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.subscriptions.clone())
+}
+
+// Command called by the native UnifiedPush receiver when the distributor hands out an endpoint
+#[tauri::command]
+pub fn on_push_endpoint(instance: String, endpoint: String, app_handle: AppHandle) -> Result<(), String> {
+    update_subscriptions(&app_handle, Some(&instance), |subscription| {
+        subscription.status = SubscriptionStatus::Active;
+        subscription.endpoint = Some(endpoint.clone());
+        subscription.error = None;
+    })?;
+    // The frontend hands the endpoint to whichever server publishes this topic
+    let _ = app_handle.emit("push://endpoint", json!({ "topic": instance, "endpoint": endpoint }));
+    Ok(())
+}
+
+// Command called by the native UnifiedPush receiver for each message
+#[tauri::command]
+pub fn on_push_message(instance: String, message: String, app_handle: AppHandle) -> Result<(), String> {
+    handle_message(&app_handle, Some(&instance), &message)
+}
+
+// Command called when the distributor drops or refuses a registration
+#[tauri::command]
+pub fn on_push_unregistered(instance: String, reason: Option<String>, app_handle: AppHandle) -> Result<(), String> {
+    update_subscriptions(&app_handle, Some(&instance), |subscription| {
+        subscription.status = SubscriptionStatus::Failed;
+        subscription.endpoint = None;
+        subscription.error = Some(reason.clone().unwrap_or_else(|| "Unregistered by the distributor".to_string()));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_messages_become_notifications() {
+        let payload = parse_payload(Some("updates"), "Package 42 shipped");
+        assert!(matches!(payload.kind, PushKind::Notification));
+        assert_eq!(payload.body, "Package 42 shipped");
+        assert_eq!(payload.topic.as_deref(), Some("updates"));
+        assert!(payload.title.is_none());
+    }
+
+    #[test]
+    fn json_payloads_are_parsed() {
+        let raw = r#"{"topic":"family","title":"Reminder","body":"Dinner at 7","reference":"r1"}"#;
+        let payload = parse_payload(Some("updates"), raw);
+        assert_eq!(payload.topic.as_deref(), Some("family"));
+        assert_eq!(payload.title.as_deref(), Some("Reminder"));
+        assert_eq!(payload.body, "Dinner at 7");
+        assert_eq!(payload.reference.as_deref(), Some("r1"));
+
+        let sync = parse_payload(None, r#"{"kind":"sync"}"#);
+        assert!(matches!(sync.kind, PushKind::Sync));
+        assert!(sync.topic.is_none());
+    }
+
+    #[test]
+    fn subscriptions_are_trimmed_and_unique() {
+        let mut store = PushStore::default();
+        assert_eq!(store.add("  news ", 5).unwrap(), "news");
+        assert!(store.add("news", 6).is_err());
+        assert!(store.add("   ", 6).is_err());
+        let subscription = &store.subscriptions[0];
+        assert_eq!(subscription.status, SubscriptionStatus::Pending);
+        assert_eq!((subscription.created_at, subscription.updated_at), (5, 5));
+    }
+
+    #[test]
+    fn updates_only_touch_the_matching_topic() {
+        let mut store = PushStore::default();
+        store.add("news", 1).unwrap();
+        store.add("family", 1).unwrap();
+        store.update(Some("family"), 9, |subscription| subscription.status = SubscriptionStatus::Active);
+        assert_eq!(store.subscriptions[0].status, SubscriptionStatus::Pending);
+        assert_eq!(store.subscriptions[1].status, SubscriptionStatus::Active);
+        assert_eq!(store.subscriptions[1].updated_at, 9);
+
+        store.update(None, 10, |subscription| subscription.status = SubscriptionStatus::Failed);
+        assert!(store
+            .subscriptions
+            .iter()
+            .all(|subscription| subscription.status == SubscriptionStatus::Failed && subscription.updated_at == 10));
+    }
+
+    #[test]
+    fn config_defaults_to_unified_push() {
+        let config: PushConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.transport, PushTransport::UnifiedPush);
+        let raw = r#"{"transport":"relay","relay_url":"wss://relay.example"}"#;
+        let relay: PushConfig = serde_json::from_str(raw).unwrap();
+        assert_eq!(relay.transport, PushTransport::Relay);
+        assert_eq!(relay.relay_url.as_deref(), Some("wss://relay.example"));
+    }
+
+    #[test]
+    fn relay_tokens_only_travel_over_tls() {
+        assert!(check_relay_url("wss://relay.example/push").is_ok());
+        assert!(check_relay_url("ws://localhost:8080").is_ok());
+        assert!(check_relay_url("ws://127.0.0.1:8080").is_ok());
+        assert!(check_relay_url("ws://[::1]:8080").is_ok());
+        assert!(check_relay_url("ws://relay.example").is_err());
+        assert!(check_relay_url("ws://192.168.1.10").is_err());
+        assert!(check_relay_url("https://relay.example").is_err());
+        assert!(check_relay_url("relay.example").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+// Wi-Fi QR codes. Networks are encoded in the WIFI: format phone cameras
+// understand, e.g. WIFI:T:WPA;S:Home;P:secret;; and rendered to a PNG. The
+// last network shared is kept in the keystore as the home network, so
+// plates://wifi can show it again. Joining from a scanned code goes through
+// the Android bridge, which asks the system to connect.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use image::{codecs::png::PngEncoder, GrayImage, Luma};
+use qrcode::{Color, QrCode};
+use serde::{Serialize, Deserialize};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Manager, State,
+};
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+use crate::keystore::Keystore;
+
+const HOME_WIFI_SECRET: &str = "wifi:home";
+// Pixels per QR module, and the blank border in modules scanners need
+const MODULE_PX: u32 = 8;
+const QUIET_ZONE: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WifiSecurity {
+    Wpa,
+    Wep,
+    Open,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WifiNetwork {
+    pub ssid: String,
+    pub password: Option<String>,
+    pub security: WifiSecurity,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WifiQr {
+    pub ssid: String,
+    // The text the code carries
+    pub payload: String,
+    // data:image/png;base64,... ready for an <img>
+    pub image: String,
+}
+
+// Special characters are backslash escaped in WIFI: fields
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub fn wifi_payload(network: &WifiNetwork) -> String {
+    let mut payload = format!("WIFI:S:{};", escape(&network.ssid));
+    match (network.security, &network.password) {
+        (WifiSecurity::Open, _) | (_, None) => payload.push_str("T:nopass;"),
+        (security, Some(password)) => {
+            let kind = if security == WifiSecurity::Wep { "WEP" } else { "WPA" };
+            payload.push_str(&format!("T:{};P:{};", kind, escape(password)));
+        }
+    }
+    if network.hidden {
+        payload.push_str("H:true;");
+    }
+    payload.push(';');
+    payload
+}
+
+// Read a WIFI: code; fields may come in any order
+pub fn parse_wifi_payload(payload: &str) -> Result<WifiNetwork, String> {
+    let body = payload
+        .trim()
+        .strip_prefix("WIFI:")
+        .ok_or("Not a Wi-Fi QR code".to_string())?;
+
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut current = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            ';' => {
+                if let Some((key, value)) = current.split_once(':') {
+                    fields.push((key.to_uppercase(), value.to_string()));
+                }
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+
+    let ssid = field("S").filter(|ssid| !ssid.is_empty()).ok_or("The code has no network name".to_string())?;
+    let password = field("P").filter(|password| !password.is_empty());
+    let security = match field("T").unwrap_or_default().to_uppercase().as_str() {
+        "WEP" => WifiSecurity::Wep,
+        "" | "NOPASS" if password.is_none() => WifiSecurity::Open,
+        _ => WifiSecurity::Wpa,
+    };
+    Ok(WifiNetwork {
+        ssid,
+        password,
+        security,
+        hidden: field("H").is_some_and(|hidden| hidden.eq_ignore_ascii_case("true")),
+    })
+}
+
+fn render_png(payload: &str) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(payload.as_bytes()).map_err(|e| e.to_string())?;
+    let width = code.width() as u32;
+    let colors = code.to_colors();
+    let size = (width + QUIET_ZONE * 2) * MODULE_PX;
+    let image = GrayImage::from_fn(size, size, |x, y| {
+        let (column, row) = (x / MODULE_PX, y / MODULE_PX);
+        let inside = (QUIET_ZONE..QUIET_ZONE + width).contains(&column) && (QUIET_ZONE..QUIET_ZONE + width).contains(&row);
+        let dark = inside && colors[((row - QUIET_ZONE) * width + column - QUIET_ZONE) as usize] == Color::Dark;
+        Luma([if dark { 0 } else { 255 }])
+    });
+    let mut png = Vec::new();
+    image.write_with_encoder(PngEncoder::new(&mut png)).map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+fn wifi_qr(network: &WifiNetwork) -> Result<WifiQr, String> {
+    let payload = wifi_payload(network);
+    let image = format!("data:image/png;base64,{}", BASE64.encode(render_png(&payload)?));
+    Ok(WifiQr {
+        ssid: network.ssid.clone(),
+        payload,
+        image,
+    })
+}
+
+// The code for the home network saved by generate_wifi_qr
+pub fn home_wifi_qr(app_handle: &AppHandle) -> Result<WifiQr, String> {
+    let payload = app_handle
+        .state::<Keystore>()
+        .get(HOME_WIFI_SECRET)?
+        .ok_or("No home network has been shared yet".to_string())?;
+    wifi_qr(&parse_wifi_payload(&payload)?)
+}
+
+#[cfg(android_bridges)]
+#[derive(Serialize)]
+struct JoinRequest<'a> {
+    ssid: &'a str,
+    password: Option<&'a str>,
+    security: WifiSecurity,
+    hidden: bool,
+}
+
+// Bridge to the system Wi-Fi settings
+pub struct WifiBridge {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<tauri::Wry>,
+}
+
+impl WifiBridge {
+    // Suggests the network to the system, which asks the user before connecting
+    fn join(&self, network: &WifiNetwork) -> Result<(), String> {
+        #[cfg(android_bridges)]
+        {
+            let request = JoinRequest {
+                ssid: &network.ssid,
+                password: network.password.as_deref(),
+                security: network.security,
+                hidden: network.hidden,
+            };
+            self.handle
+                .run_mobile_plugin::<()>("joinNetwork", request)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(not(android_bridges))]
+        {
+            let _ = network;
+            Err("Joining Wi-Fi networks is not available on this device".to_string())
+        }
+    }
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("wifi")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = WifiBridge {
+                handle: api.register_android_plugin("company.atechnology.plates", "WifiPlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                WifiBridge {}
+            };
+            app.manage(bridge);
+            Ok(())
+        })
+        .build()
+}
+
+// Command to make a QR code others can scan to join a network; without a
+// password the network is open. It is remembered as the home network.
+#[tauri::command]
+pub fn generate_wifi_qr(
+    ssid: String,
+    password: Option<String>,
+    hidden: Option<bool>,
+    keystore: State<'_, Keystore>,
+) -> Result<WifiQr, String> {
+    if ssid.trim().is_empty() {
+        return Err("Network name is required".to_string());
+    }
+    let password = password.filter(|password| !password.is_empty());
+    let network = WifiNetwork {
+        security: if password.is_some() { WifiSecurity::Wpa } else { WifiSecurity::Open },
+        ssid,
+        password,
+        hidden: hidden.unwrap_or(false),
+    };
+    let qr = wifi_qr(&network)?;
+    keystore.set(HOME_WIFI_SECRET, &qr.payload)?;
+    Ok(qr)
+}
+
+// Command to join the network in a scanned WIFI: code
+#[tauri::command]
+pub fn join_wifi_from_qr(payload: String, bridge: State<'_, WifiBridge>) -> Result<WifiNetwork, String> {
+    let network = parse_wifi_payload(&payload)?;
+    bridge.join(&network)?;
+    Ok(WifiNetwork {
+        password: None,
+        ..network
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ssid: &str, password: Option<&str>, security: WifiSecurity) -> WifiNetwork {
+        WifiNetwork {
+            ssid: ssid.to_string(),
+            password: password.map(str::to_string),
+            security,
+            hidden: false,
+        }
+    }
+
+    #[test]
+    fn payloads_escape_special_characters() {
+        let home = network("Home;Net", Some(r#"pa:ss,"w\rd"#), WifiSecurity::Wpa);
+        assert_eq!(wifi_payload(&home), r#"WIFI:S:Home\;Net;T:WPA;P:pa\:ss\,\"w\\rd;;"#);
+        let cafe = WifiNetwork {
+            hidden: true,
+            ..network("Cafe", None, WifiSecurity::Open)
+        };
+        assert_eq!(wifi_payload(&cafe), "WIFI:S:Cafe;T:nopass;H:true;;");
+    }
+
+    #[test]
+    fn payloads_parse_back() {
+        let home = network("Home;Net", Some(r#"pa:ss,"w\rd"#), WifiSecurity::Wep);
+        let parsed = parse_wifi_payload(&wifi_payload(&home)).unwrap();
+        assert_eq!(parsed.ssid, home.ssid);
+        assert_eq!(parsed.password, home.password);
+        assert_eq!(parsed.security, WifiSecurity::Wep);
+        assert!(!parsed.hidden);
+    }
+
+    #[test]
+    fn fields_may_come_in_any_order() {
+        let parsed = parse_wifi_payload("WIFI:P:secret;H:TRUE;S:Office;;").unwrap();
+        assert_eq!(parsed.ssid, "Office");
+        assert_eq!(parsed.security, WifiSecurity::Wpa);
+        assert!(parsed.hidden);
+        assert_eq!(parse_wifi_payload("WIFI:S:Guest;;").unwrap().security, WifiSecurity::Open);
+    }
+
+    #[test]
+    fn other_codes_are_refused() {
+        assert!(parse_wifi_payload("https://example.com").is_err());
+        assert!(parse_wifi_payload("WIFI:T:WPA;P:secret;;").is_err());
+    }
+
+    #[test]
+    fn codes_render_to_png() {
+        let qr = wifi_qr(&network("Home", Some("secret"), WifiSecurity::Wpa)).unwrap();
+        let png = BASE64.decode(qr.image.strip_prefix("data:image/png;base64,").unwrap()).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.width(), image.height());
+        assert_eq!(image.width() % MODULE_PX, 0);
+    }
+}
@@ -0,0 +1,357 @@
+// "Quiz me" mode. The engine writes a handful of questions on a topic, each
+// answer (typed, or a recording transcribed like any other) is graded by the
+// engine, and streaks across quizzes are kept in quiz.json.
+//
+// A quiz moves Asking -> Answered -> Asking ... -> Finished; next_quiz_question
+// advances past an answered question.
+
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+use crate::engine;
+use crate::settings::{self, SettingsState};
+use crate::speech;
+use crate::storage;
+use crate::structured;
+
+const QUIZ_FILE: &str = "quiz.json";
+const DEFAULT_QUESTIONS: usize = 5;
+const MAX_QUESTIONS: usize = 20;
+const MAX_TOPIC_CHARS: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct QuizItem {
+    question: String,
+    answer: String,
+}
+
+#[derive(Deserialize)]
+struct Grade {
+    correct: bool,
+    #[serde(default)]
+    feedback: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuizPhase {
+    Asking,
+    Answered,
+    Finished,
+}
+
+struct QuizSession {
+    id: u64,
+    topic: String,
+    items: Vec<QuizItem>,
+    current: usize,
+    score: usize,
+    phase: QuizPhase,
+}
+
+#[derive(Default)]
+pub struct QuizSessions {
+    session: Option<QuizSession>,
+    last_id: u64,
+}
+
+pub type QuizState = Mutex<QuizSessions>;
+
+// Persisted across quizzes
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QuizStats {
+    pub quizzes_played: u64,
+    pub questions_answered: u64,
+    pub correct_answers: u64,
+    // Correct answers in a row, carried over from one quiz to the next
+    pub current_streak: u64,
+    pub best_streak: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QuizTurn {
+    pub session: u64,
+    pub topic: String,
+    pub phase: QuizPhase,
+    // 1-based number of the current question
+    pub number: usize,
+    pub total: usize,
+    pub question: String,
+    pub score: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QuizResult {
+    pub turn: QuizTurn,
+    // What was heard or typed
+    pub answer: String,
+    pub correct: bool,
+    pub expected: String,
+    pub feedback: String,
+    pub stats: QuizStats,
+}
+
+fn turn(session: &QuizSession) -> QuizTurn {
+    QuizTurn {
+        session: session.id,
+        topic: session.topic.clone(),
+        phase: session.phase,
+        number: session.current + 1,
+        total: session.items.len(),
+        question: session
+            .items
+            .get(session.current)
+            .map(|item| item.question.clone())
+            .unwrap_or_default(),
+        score: session.score,
+    }
+}
+
+// Score an answer to the current question and carry it into the stats
+fn record_answer(quiz: &mut QuizSession, stats: &mut QuizStats, correct: bool) {
+    if correct {
+        quiz.score += 1;
+    }
+    quiz.phase = if quiz.current + 1 == quiz.items.len() {
+        QuizPhase::Finished
+    } else {
+        QuizPhase::Answered
+    };
+
+    stats.questions_answered += 1;
+    if correct {
+        stats.correct_answers += 1;
+        stats.current_streak += 1;
+        stats.best_streak = stats.best_streak.max(stats.current_streak);
+    } else {
+        stats.current_streak = 0;
+    }
+    if quiz.phase == QuizPhase::Finished {
+        stats.quizzes_played += 1;
+    }
+}
+
+fn advance(quiz: &mut QuizSession) -> Result<(), String> {
+    match quiz.phase {
+        QuizPhase::Asking => Err("The current question has not been answered".to_string()),
+        QuizPhase::Finished => Err("The quiz is over".to_string()),
+        QuizPhase::Answered => {
+            quiz.current += 1;
+            quiz.phase = QuizPhase::Asking;
+            Ok(())
+        }
+    }
+}
+
+fn load_stats(app_handle: &AppHandle) -> QuizStats {
+    storage::load_json(app_handle, QUIZ_FILE)
+}
+
+async fn generate_items(app_handle: &AppHandle, topic: &str, count: usize) -> Result<Vec<QuizItem>, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = format!(
+        "Write {} quiz questions about {}. Each should be answerable aloud in a few words. \
+         Reply with only a JSON array of objects with \"question\" and \"answer\" fields.",
+        count, topic
+    );
+    let response = engine::generate(app_handle, &prompt, &app_settings).await?;
+    let json = structured::extract_json(&response, '[', ']').ok_or("The quiz could not be generated".to_string())?;
+    let items: Vec<QuizItem> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let items: Vec<QuizItem> = items
+        .into_iter()
+        .filter(|item| !item.question.trim().is_empty())
+        .take(count)
+        .collect();
+    if items.is_empty() {
+        return Err("The quiz could not be generated".to_string());
+    }
+    Ok(items)
+}
+
+async fn grade(app_handle: &AppHandle, item: &QuizItem, answer: &str) -> Result<Grade, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    // Spoken answers come through transcription, so spelling and filler words don't count
+    let prompt = format!(
+        "Grade a spoken quiz answer. Ignore spelling, transcription mistakes and filler words; \
+         accept answers that mean the same as the expected one.\n\
+         Question: {}\nExpected answer: {}\nGiven answer: {}\n\
+         Reply with only a JSON object with \"correct\" (true or false) and \"feedback\" \
+         (one short sentence to read aloud).",
+        item.question, item.answer, answer
+    );
+    let response = engine::generate(app_handle, &prompt, &app_settings).await?;
+    let json = structured::extract_json(&response, '{', '}').ok_or("The answer could not be graded".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+// Command to start a quiz on a topic, replacing any quiz in progress; returns the first question
+#[tauri::command]
+pub async fn start_quiz(
+    topic: String,
+    questions: Option<usize>,
+    app_handle: AppHandle,
+    state: State<'_, QuizState>,
+) -> Result<QuizTurn, String> {
+    let topic = topic.trim().to_string();
+    if topic.is_empty() {
+        return Err("Pick a topic for the quiz".to_string());
+    }
+    if topic.chars().count() > MAX_TOPIC_CHARS {
+        return Err(format!("The topic can be at most {} characters", MAX_TOPIC_CHARS));
+    }
+    let count = questions.unwrap_or(DEFAULT_QUESTIONS);
+    if count == 0 || count > MAX_QUESTIONS {
+        return Err(format!("A quiz has between 1 and {} questions", MAX_QUESTIONS));
+    }
+    let items = generate_items(&app_handle, &topic, count).await?;
+
+    let mut sessions = state.lock().map_err(|e| e.to_string())?;
+    sessions.last_id += 1;
+    let session = QuizSession {
+        id: sessions.last_id,
+        topic,
+        items,
+        current: 0,
+        score: 0,
+        phase: QuizPhase::Asking,
+    };
+    let first = turn(&session);
+    sessions.session = Some(session);
+    Ok(first)
+}
+
+// Command to answer the current question, either as text or as the path of a
+// recording to transcribe first
+#[tauri::command]
+pub async fn answer_quiz(
+    session: u64,
+    answer: Option<String>,
+    recording: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, QuizState>,
+) -> Result<QuizResult, String> {
+    let item = {
+        let sessions = state.lock().map_err(|e| e.to_string())?;
+        let quiz = sessions
+            .session
+            .as_ref()
+            .filter(|quiz| quiz.id == session)
+            .ok_or("That quiz is no longer running".to_string())?;
+        if quiz.phase != QuizPhase::Asking {
+            return Err("There is no question waiting for an answer".to_string());
+        }
+        quiz.items[quiz.current].clone()
+    };
+
+    let answer = match (answer, recording) {
+        (Some(answer), _) => answer,
+        (None, Some(path)) => {
+            let wav = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+            speech::transcribe_wav(&app_handle, wav).await?.text
+        }
+        (None, None) => return Err("No answer was given".to_string()),
+    };
+    let answer = answer.trim().to_string();
+    let grade = if answer.is_empty() {
+        Grade { correct: false, feedback: String::new() }
+    } else {
+        grade(&app_handle, &item, &answer).await?
+    };
+
+    let mut sessions = state.lock().map_err(|e| e.to_string())?;
+    // The quiz may have been replaced or stopped while grading
+    let quiz = sessions
+        .session
+        .as_mut()
+        .filter(|quiz| quiz.id == session && quiz.phase == QuizPhase::Asking)
+        .ok_or("That quiz is no longer running".to_string())?;
+    let mut stats = load_stats(&app_handle);
+    record_answer(quiz, &mut stats, grade.correct);
+    storage::save_json(&app_handle, QUIZ_FILE, &stats)?;
+
+    Ok(QuizResult {
+        turn: turn(quiz),
+        answer,
+        correct: grade.correct,
+        expected: item.answer,
+        feedback: grade.feedback,
+        stats,
+    })
+}
+
+// Command to move on to the next question once the current one is answered
+#[tauri::command]
+pub fn next_quiz_question(session: u64, state: State<'_, QuizState>) -> Result<QuizTurn, String> {
+    let mut sessions = state.lock().map_err(|e| e.to_string())?;
+    let quiz = sessions
+        .session
+        .as_mut()
+        .filter(|quiz| quiz.id == session)
+        .ok_or("That quiz is no longer running".to_string())?;
+    advance(quiz)?;
+    Ok(turn(quiz))
+}
+
+#[tauri::command]
+pub fn stop_quiz(state: State<'_, QuizState>) -> Result<(), String> {
+    state.lock().map_err(|e| e.to_string())?.session = None;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_quiz_stats(app_handle: AppHandle) -> QuizStats {
+    load_stats(&app_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiz(questions: usize) -> QuizSession {
+        QuizSession {
+            id: 1,
+            topic: "Capitals".to_string(),
+            items: (1..=questions)
+                .map(|number| QuizItem {
+                    question: format!("Question {}", number),
+                    answer: format!("Answer {}", number),
+                })
+                .collect(),
+            current: 0,
+            score: 0,
+            phase: QuizPhase::Asking,
+        }
+    }
+
+    #[test]
+    fn a_quiz_moves_from_question_to_question_until_finished() {
+        let mut quiz = quiz(2);
+        let mut stats = QuizStats::default();
+        assert!(advance(&mut quiz).is_err());
+
+        record_answer(&mut quiz, &mut stats, true);
+        assert_eq!(quiz.phase, QuizPhase::Answered);
+        advance(&mut quiz).unwrap();
+        let second = turn(&quiz);
+        assert_eq!((second.number, second.total, second.score), (2, 2, 1));
+        assert_eq!(second.question, "Question 2");
+
+        record_answer(&mut quiz, &mut stats, false);
+        assert_eq!(quiz.phase, QuizPhase::Finished);
+        assert_eq!(advance(&mut quiz).unwrap_err(), "The quiz is over");
+        assert_eq!((stats.quizzes_played, stats.questions_answered, stats.correct_answers), (1, 2, 1));
+    }
+
+    #[test]
+    fn streaks_carry_across_quizzes_and_reset_on_a_wrong_answer() {
+        let mut stats = QuizStats::default();
+        for correct in [true, true, false, true] {
+            let mut quiz = quiz(1);
+            record_answer(&mut quiz, &mut stats, correct);
+        }
+        assert_eq!(stats.current_streak, 1);
+        assert_eq!(stats.best_streak, 2);
+        assert_eq!(stats.quizzes_played, 4);
+    }
+}
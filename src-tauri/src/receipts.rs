@@ -0,0 +1,292 @@
+// Receipt capture. The photo is read with the same OCR as shared images and
+// the engine pulls the merchant, date, total and line items out as JSON; any
+// of them it misses are found from the receipt's lines locally. The result is
+// filed as an expense with its items and a copy of the photo.
+
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+use crate::engine;
+use crate::expenses::{self, Expense, ExpenseItem, NewReceipt};
+use crate::settings::{self, SettingsState};
+use crate::share;
+use crate::structured;
+use crate::untrusted;
+
+// Words on lines that carry an amount but are not items
+const SUMMARY_WORDS: &[&str] = &[
+    "total", "subtotal", "sub", "tax", "vat", "change", "cash", "card", "visa", "mastercard", "tip", "balance",
+    "due", "discount", "paid", "tendered",
+];
+const TOTAL_WORDS: &[&str] = &["total", "due", "balance"];
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d", "%m/%d/%Y", "%d/%m/%Y", "%d.%m.%Y", "%m/%d/%y", "%d/%m/%y", "%d.%m.%y", "%b %d %Y", "%d %b %Y",
+];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ParsedReceipt {
+    pub expense: Expense,
+    pub items: Vec<ExpenseItem>,
+    // Everything read from the receipt
+    pub text: String,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct ReceiptFields {
+    merchant: Option<String>,
+    // YYYY-MM-DD
+    date: Option<String>,
+    total: Option<f64>,
+    currency: Option<String>,
+    category: Option<String>,
+    items: Vec<ItemFields>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct ItemFields {
+    description: Option<String>,
+    quantity: Option<f64>,
+    amount: Option<f64>,
+}
+
+fn words(line: &str) -> Vec<String> {
+    line.split_whitespace().map(expenses::clean_word).collect()
+}
+
+// The last price on a line, e.g. "Milk 2 x 1.20   2.40" or "TOTAL $12.50"
+fn line_amount(line: &str) -> Option<f64> {
+    line.split_whitespace().rev().find_map(|token| {
+        let number = token.trim_matches(|c: char| !c.is_ascii_digit());
+        let (whole, cents) = number.rsplit_once(['.', ','])?;
+        if whole.is_empty() || cents.len() != 2 {
+            return None;
+        }
+        format!("{}.{}", whole.replace([',', '.'], ""), cents).parse().ok()
+    })
+}
+
+fn find_total(lines: &[&str]) -> Option<f64> {
+    lines
+        .iter()
+        .rev()
+        .filter(|line| {
+            let words = words(line);
+            words.iter().any(|word| TOTAL_WORDS.contains(&word.as_str()))
+                && !words.iter().any(|word| word == "subtotal" || word == "sub")
+        })
+        .find_map(|line| line_amount(line))
+}
+
+// Lines with a price and a description that aren't totals, taxes or payments
+fn find_items(lines: &[&str]) -> Vec<ExpenseItem> {
+    lines
+        .iter()
+        .filter(|line| !words(line).iter().any(|word| SUMMARY_WORDS.contains(&word.as_str())))
+        .filter_map(|line| {
+            let amount = line_amount(line)?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let end = tokens.iter().rposition(|token| token.chars().any(char::is_alphabetic))? + 1;
+            let description = tokens[..end].join(" ");
+            (description.chars().filter(|c| c.is_alphabetic()).count() >= 2).then_some(ExpenseItem {
+                description,
+                quantity: None,
+                amount,
+            })
+        })
+        .collect()
+}
+
+// The shop's name is usually the first line of text without a price
+fn find_merchant(lines: &[&str]) -> Option<String> {
+    lines
+        .iter()
+        .find(|line| line.chars().filter(|c| c.is_alphabetic()).count() >= 3 && line_amount(line).is_none())
+        .map(|line| line.to_string())
+}
+
+fn find_date(lines: &[&str]) -> Option<NaiveDate> {
+    for line in lines {
+        let tokens: Vec<&str> = line
+            .split_whitespace()
+            .map(|token| token.trim_matches(|c: char| matches!(c, ',' | ':' | ';')))
+            .collect();
+        for size in 1..=3 {
+            for window in tokens.windows(size) {
+                let candidate = window.join(" ");
+                let date = DATE_FORMATS
+                    .iter()
+                    .find_map(|format| NaiveDate::parse_from_str(&candidate, format).ok());
+                if date.is_some() {
+                    return date;
+                }
+            }
+        }
+    }
+    None
+}
+
+fn find_currency(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .find_map(|token| {
+            let symbol = token.chars().next().and_then(|c| expenses::currency_of(&c.to_string()));
+            symbol.or_else(|| expenses::currency_of(&expenses::clean_word(token)))
+        })
+        .map(str::to_string)
+}
+
+// Midday on the receipt's date, or now for today's receipts and unreadable dates
+fn spent_at(date: Option<NaiveDate>) -> u64 {
+    let now = Local::now();
+    date.filter(|date| *date < now.date_naive())
+        .and_then(|date| date.and_hms_opt(12, 0, 0))
+        .and_then(|noon| Local.from_local_datetime(&noon).single())
+        .unwrap_or(now)
+        .timestamp() as u64
+}
+
+async fn extract_with_engine(app_handle: &AppHandle, text: &str) -> Result<ReceiptFields, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = format!(
+        "This is the text of a receipt.\n\n{}\n\n\
+         Reply with only a JSON object with \"merchant\", \"date\" (YYYY-MM-DD), \"total\" (a number), \
+         \"currency\" (an ISO 4217 code), \"category\" (one of {}, or \"other\") and \"items\" (a list of \
+         objects with \"description\", \"quantity\" and \"amount\", the line's price); use null for \
+         anything not on the receipt.",
+        untrusted::wrap("receipt", text),
+        expenses::categories().join(", ")
+    );
+    let response = engine::generate(app_handle, &prompt, &app_settings).await?;
+    parse_fields(&response)
+}
+
+// The fields in the model's reply, which echoes untrusted receipt text
+fn parse_fields(response: &str) -> Result<ReceiptFields, String> {
+    let json = structured::extract_json(response, '{', '}').ok_or("The receipt could not be understood".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+// Turn the receipt's text into an expense to file
+async fn extract(app_handle: &AppHandle, text: &str) -> NewReceipt {
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let fields = extract_with_engine(app_handle, text).await.unwrap_or_else(|e| {
+        tracing::debug!(error = %e, "receipt fields found locally");
+        ReceiptFields::default()
+    });
+
+    let mut items: Vec<ExpenseItem> = fields
+        .items
+        .into_iter()
+        .filter_map(|item| {
+            let description = item.description.map(|description| description.trim().to_string())?;
+            Some(ExpenseItem {
+                description,
+                quantity: item.quantity.filter(|quantity| *quantity > 0.0),
+                amount: item.amount?,
+            })
+        })
+        .filter(|item| !item.description.is_empty())
+        .collect();
+    if items.is_empty() {
+        items = find_items(&lines);
+    }
+    let merchant = fields.merchant.or_else(|| find_merchant(&lines));
+    let category = fields.category.or_else(|| {
+        let keywords: Vec<String> = merchant
+            .iter()
+            .chain(items.iter().map(|item| &item.description))
+            .flat_map(|text| words(text))
+            .collect();
+        expenses::category_of(&keywords).map(str::to_string)
+    });
+    let amount = fields
+        .total
+        .filter(|total| *total > 0.0)
+        .or_else(|| find_total(&lines))
+        .or_else(|| (!items.is_empty()).then(|| items.iter().map(|item| item.amount).sum()))
+        .unwrap_or(0.0);
+    let date = fields
+        .date
+        .and_then(|date| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok())
+        .or_else(|| find_date(&lines));
+
+    NewReceipt {
+        amount,
+        currency: fields.currency.or_else(|| find_currency(text)),
+        category,
+        merchant,
+        spent_at: spent_at(date),
+        items,
+    }
+}
+
+// Command to read a photo of a receipt and file it as an expense, with its
+// line items and the photo attached
+#[tauri::command]
+pub async fn parse_receipt(image_path: String, app_handle: AppHandle) -> Result<ParsedReceipt, String> {
+    let text = share::extract_image_text(&app_handle, &image_path, share::image_mime_type(&image_path)).await?;
+    if text.trim().is_empty() {
+        return Err("No text was found on the receipt".to_string());
+    }
+    let receipt = extract(&app_handle, &text).await;
+    let items = receipt.items.clone();
+    let expense = expenses::file_receipt(&app_handle, receipt, Path::new(&image_path))?;
+    Ok(ParsedReceipt { expense, items, text })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECEIPT: &str = "Corner Grocery\n12 High St\n03/14/2024 10:22\nMilk 2 x 1.20   2.40\nBread 3.10\n\
+                           SUBTOTAL 5.50\nTAX 0.44\nTOTAL $5.94\nCASH 10.00\nCHANGE 4.06\n";
+
+    #[test]
+    fn amounts_are_the_last_price_on_a_line() {
+        assert_eq!(line_amount("Milk 2 x 1.20   2.40"), Some(2.40));
+        assert_eq!(line_amount("TOTAL $12.50"), Some(12.50));
+        assert_eq!(line_amount("Summe 1.234,56 EUR"), Some(1234.56));
+        assert_eq!(line_amount("12 High St"), None);
+        assert_eq!(line_amount("Open 10:22"), None);
+    }
+
+    #[test]
+    fn receipt_lines_are_read_locally() {
+        let lines: Vec<&str> = RECEIPT.lines().collect();
+        assert_eq!(find_merchant(&lines).as_deref(), Some("Corner Grocery"));
+        assert_eq!(find_date(&lines), NaiveDate::from_ymd_opt(2024, 3, 14));
+        assert_eq!(find_total(&lines), Some(5.94));
+        assert_eq!(find_currency(RECEIPT).as_deref(), Some("USD"));
+        let items: Vec<(String, f64)> = find_items(&lines).into_iter().map(|item| (item.description, item.amount)).collect();
+        assert_eq!(items, vec![("Milk 2 x".to_string(), 2.40), ("Bread".to_string(), 3.10)]);
+    }
+
+    #[test]
+    fn dates_in_other_formats_are_found() {
+        assert_eq!(find_date(&["Date: 2024-03-14"]), NaiveDate::from_ymd_opt(2024, 3, 14));
+        assert_eq!(find_date(&["14.03.2024 Kasse 2"]), NaiveDate::from_ymd_opt(2024, 3, 14));
+        assert_eq!(find_date(&["Mar 14, 2024 at 10:22"]), NaiveDate::from_ymd_opt(2024, 3, 14));
+        assert_eq!(find_date(&["Thank you"]), None);
+    }
+
+    #[test]
+    fn fields_are_read_from_the_reply() {
+        let fields = parse_fields("Sure:\n```json\n{\"merchant\": \"Corner Grocery\", \"total\": 5.94}\n```").unwrap();
+        assert_eq!(fields.merchant.as_deref(), Some("Corner Grocery"));
+        assert_eq!(fields.total, Some(5.94));
+        assert!(parse_fields("} x {").is_err());
+        assert!(parse_fields("no fields").is_err());
+    }
+
+    #[test]
+    fn past_receipts_are_filed_at_midday() {
+        let noon = Local.with_ymd_and_hms(2024, 3, 14, 12, 0, 0).unwrap().timestamp() as u64;
+        assert_eq!(spent_at(NaiveDate::from_ymd_opt(2024, 3, 14)), noon);
+        let now = Local::now().timestamp() as u64;
+        assert!(spent_at(None) >= now);
+        assert!(spent_at(NaiveDate::from_ymd_opt(2999, 1, 1)) >= now);
+    }
+}
@@ -0,0 +1,633 @@
+// Recipes and the weekly meal plan. "What can I cook?" has the engine suggest
+// structured recipes built around the ingredients the user has; whether each
+// ingredient is already at hand is worked out here rather than taken from the
+// model. Kept recipes are assigned to days in recipes.json, and the week's
+// shopping list merges their ingredients, converting units where they differ.
+
+use chrono::{Datelike, Duration, Local, NaiveDate};
+use serde::{Serialize, Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+use crate::engine;
+use crate::settings::{self, SettingsState};
+use crate::storage;
+use crate::structured;
+use crate::untrusted;
+
+const RECIPES_FILE: &str = "recipes.json";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_RECIPES: usize = 3;
+const MAX_RECIPES: usize = 6;
+const MAX_INGREDIENTS: usize = 40;
+const MAX_INGREDIENT_CHARS: usize = 60;
+// Assumed to be in every kitchen, so never listed as missing
+const STAPLES: &[&str] = &["salt", "pepper", "water", "oil", "olive oil"];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecipeIngredient {
+    pub name: String,
+    #[serde(default)]
+    pub quantity: String,
+    // Filled in locally from the ingredients given
+    #[serde(default)]
+    pub have: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Recipe {
+    pub title: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub servings: u32,
+    #[serde(default)]
+    pub minutes: u32,
+    pub ingredients: Vec<RecipeIngredient>,
+    pub steps: Vec<String>,
+    // Names of ingredients still to buy
+    #[serde(default)]
+    pub missing: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedRecipe {
+    pub id: u64,
+    pub saved_at: u64,
+    #[serde(flatten)]
+    pub recipe: Recipe,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Meal {
+    Breakfast,
+    Lunch,
+    Dinner,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PlannedMeal {
+    // YYYY-MM-DD, which sorts by date
+    date: String,
+    meal: Meal,
+    recipe: u64,
+    servings: u32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RecipeBook {
+    next_id: u64,
+    recipes: Vec<SavedRecipe>,
+    plan: Vec<PlannedMeal>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PlannedMealView {
+    pub meal: Meal,
+    pub recipe: u64,
+    pub title: String,
+    pub servings: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PlannedDay {
+    pub date: String,
+    pub meals: Vec<PlannedMealView>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ShoppingItem {
+    pub name: String,
+    // One entry per kind of measure, e.g. "450 g" and "2 pieces"; quantities
+    // that aren't numbers ("a pinch") are listed as given
+    pub amounts: Vec<String>,
+    // Titles of the planned recipes that need it
+    pub recipes: Vec<String>,
+}
+
+// What a quantity measures, in grams, millilitres or a count of some unit
+#[derive(Clone, Debug, PartialEq)]
+enum Measure {
+    Mass,
+    Volume,
+    Count(String),
+}
+
+// Summed amounts, one per measure
+type Totals = Vec<(f64, Measure)>;
+
+// Unit names and their size in grams or millilitres
+const MASS_UNITS: &[(&str, f64)] = &[
+    ("g", 1.0),
+    ("gram", 1.0),
+    ("kg", 1000.0),
+    ("kilogram", 1000.0),
+    ("oz", 28.35),
+    ("ounce", 28.35),
+    ("lb", 453.6),
+    ("pound", 453.6),
+];
+const VOLUME_UNITS: &[(&str, f64)] = &[
+    ("ml", 1.0),
+    ("millilitre", 1.0),
+    ("milliliter", 1.0),
+    ("l", 1000.0),
+    ("litre", 1000.0),
+    ("liter", 1000.0),
+    ("tsp", 4.93),
+    ("teaspoon", 4.93),
+    ("tbsp", 14.79),
+    ("tablespoon", 14.79),
+    ("cup", 240.0),
+    ("fl oz", 29.57),
+    ("pint", 473.0),
+];
+
+fn normalize(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    // "tomatoes" and "tomato" are the same thing
+    name.strip_suffix("es")
+        .filter(|stem| stem.ends_with('o'))
+        .or_else(|| name.strip_suffix('s'))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+fn have_ingredient(name: &str, available: &[String]) -> bool {
+    let name = normalize(name);
+    STAPLES.iter().any(|staple| name == *staple)
+        || available
+            .iter()
+            .any(|have| !have.is_empty() && (name == *have || name.contains(have.as_str())))
+}
+
+fn mark_available(recipe: &mut Recipe, available: &[String]) {
+    for ingredient in &mut recipe.ingredients {
+        ingredient.have = have_ingredient(&ingredient.name, available);
+    }
+    recipe.missing = recipe
+        .ingredients
+        .iter()
+        .filter(|ingredient| !ingredient.have)
+        .map(|ingredient| ingredient.name.clone())
+        .collect();
+}
+
+// Command to suggest recipes using mostly the given ingredients, those
+// needing the fewest extra ingredients first
+#[tauri::command]
+pub async fn what_can_i_cook(
+    ingredients: Vec<String>,
+    count: Option<usize>,
+    app_handle: AppHandle,
+) -> Result<Vec<Recipe>, String> {
+    let ingredients: Vec<String> = ingredients
+        .iter()
+        .map(|ingredient| ingredient.trim().to_string())
+        .filter(|ingredient| !ingredient.is_empty())
+        .collect();
+    if ingredients.is_empty() {
+        return Err("List a few ingredients to cook with".to_string());
+    }
+    if ingredients.len() > MAX_INGREDIENTS {
+        return Err(format!("At most {} ingredients can be used", MAX_INGREDIENTS));
+    }
+    if ingredients.iter().any(|ingredient| ingredient.chars().count() > MAX_INGREDIENT_CHARS) {
+        return Err(format!("Ingredient names can be at most {} characters", MAX_INGREDIENT_CHARS));
+    }
+    let count = count.unwrap_or(DEFAULT_RECIPES);
+    if count == 0 || count > MAX_RECIPES {
+        return Err(format!("Between 1 and {} recipes can be suggested", MAX_RECIPES));
+    }
+
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = format!(
+        "Suggest {} home-cooking recipes that use mostly the ingredients below and as few \
+         others as possible.\n\n{}\n\n\
+         Reply with only a JSON array of objects with \"title\", \"summary\" (one sentence), \
+         \"servings\" and \"minutes\" numbers, \"ingredients\" (an array of objects with \"name\" \
+         and \"quantity\") and \"steps\" (an array of short instructions).",
+        count,
+        untrusted::wrap("ingredients", &ingredients.join(", "))
+    );
+    let response = engine::generate(&app_handle, &prompt, &app_settings).await?;
+    let json = structured::extract_json(&response, '[', ']').ok_or("No recipes could be suggested".to_string())?;
+    let recipes: Vec<Recipe> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+
+    let available: Vec<String> = ingredients.iter().map(|ingredient| normalize(ingredient)).collect();
+    let mut recipes: Vec<Recipe> = recipes
+        .into_iter()
+        .filter(|recipe| !recipe.title.trim().is_empty() && !recipe.steps.is_empty())
+        .take(count)
+        .collect();
+    if recipes.is_empty() {
+        return Err("No recipes could be suggested".to_string());
+    }
+    for recipe in &mut recipes {
+        mark_available(recipe, &available);
+    }
+    recipes.sort_by_key(|recipe| recipe.missing.len());
+    Ok(recipes)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn load(app_handle: &AppHandle) -> RecipeBook {
+    storage::load_json(app_handle, RECIPES_FILE)
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date.trim(), DATE_FORMAT).map_err(|_| "Dates are written as YYYY-MM-DD".to_string())
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+// Monday of the week containing `date`, or of this week
+fn week_start(date: Option<String>) -> Result<NaiveDate, String> {
+    let date = match date {
+        Some(date) => parse_date(&date)?,
+        None => Local::now().date_naive(),
+    };
+    Ok(date - Duration::days(date.weekday().num_days_from_monday() as i64))
+}
+
+// Leading number of a quantity: "2", "1.5", "1/2", "1 1/2" or "½"
+fn parse_number(text: &str) -> Option<(f64, &str)> {
+    let text = text.trim_start();
+    let vulgar = [('½', 0.5), ('¼', 0.25), ('¾', 0.75), ('⅓', 1.0 / 3.0), ('⅔', 2.0 / 3.0)];
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '/' || c == ' '))
+        .unwrap_or(text.len());
+    let mut value = 0.0;
+    let mut parsed = false;
+    for word in text[..end].split_whitespace() {
+        let part = match word.split_once('/') {
+            Some((numerator, denominator)) => {
+                let denominator: f64 = denominator.parse().ok()?;
+                (denominator != 0.0).then_some(numerator.parse::<f64>().ok()? / denominator)?
+            }
+            None => word.parse::<f64>().ok()?,
+        };
+        value += part;
+        parsed = true;
+    }
+    let mut rest = &text[end..];
+    if let Some((_, fraction)) = vulgar.iter().find(|(c, _)| rest.starts_with(*c)) {
+        value += fraction;
+        parsed = true;
+        rest = &rest[rest.chars().next().map(char::len_utf8).unwrap_or(0)..];
+    }
+    parsed.then_some((value, rest.trim()))
+}
+
+fn find_unit(unit: &str, units: &[(&str, f64)]) -> Option<f64> {
+    let unit = unit.trim_end_matches('.');
+    units
+        .iter()
+        .find(|(name, _)| unit == *name || unit.strip_suffix('s') == Some(*name))
+        .map(|(_, size)| *size)
+}
+
+// "200 g" -> (200, Mass); "2 cloves" -> (2, Count("clove")); None for "a pinch"
+fn parse_quantity(quantity: &str) -> Option<(f64, Measure)> {
+    let (value, unit) = parse_number(quantity)?;
+    let unit = unit.to_lowercase();
+    if let Some(size) = find_unit(&unit, MASS_UNITS) {
+        return Some((value * size, Measure::Mass));
+    }
+    if let Some(size) = find_unit(&unit, VOLUME_UNITS) {
+        return Some((value * size, Measure::Volume));
+    }
+    // Anything else counts pieces of the unit named, if any
+    let label = unit.split_whitespace().next().map(normalize).unwrap_or_default();
+    Some((value, Measure::Count(label)))
+}
+
+fn format_value(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded.fract() == 0.0 {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+fn format_amount(value: f64, measure: &Measure) -> String {
+    match measure {
+        Measure::Mass if value >= 1000.0 => format!("{} kg", format_value(value / 1000.0)),
+        Measure::Mass => format!("{} g", value.round()),
+        Measure::Volume if value >= 1000.0 => format!("{} l", format_value(value / 1000.0)),
+        Measure::Volume => format!("{} ml", value.round()),
+        Measure::Count(label) if label.is_empty() => format_value(value),
+        Measure::Count(label) if value > 1.0 => format!("{} {}s", format_value(value), label),
+        Measure::Count(label) => format!("{} {}", format_value(value), label),
+    }
+}
+
+// Command to keep a recipe for the meal plan
+#[tauri::command]
+pub fn save_recipe(recipe: Recipe, app_handle: AppHandle) -> Result<SavedRecipe, String> {
+    if recipe.title.trim().is_empty() {
+        return Err("A recipe needs a title".to_string());
+    }
+    let mut book = load(&app_handle);
+    book.next_id += 1;
+    let saved = SavedRecipe {
+        id: book.next_id,
+        saved_at: now_secs(),
+        recipe,
+    };
+    book.recipes.push(saved.clone());
+    storage::save_json(&app_handle, RECIPES_FILE, &book)?;
+    Ok(saved)
+}
+
+#[tauri::command]
+pub fn list_recipes(app_handle: AppHandle) -> Vec<SavedRecipe> {
+    load(&app_handle).recipes
+}
+
+// Command to delete a kept recipe; it is also taken off the meal plan
+#[tauri::command]
+pub fn delete_recipe(id: u64, app_handle: AppHandle) -> Result<(), String> {
+    let mut book = load(&app_handle);
+    let before = book.recipes.len();
+    book.recipes.retain(|saved| saved.id != id);
+    if book.recipes.len() == before {
+        return Err("Recipe not found".to_string());
+    }
+    book.plan.retain(|planned| planned.recipe != id);
+    storage::save_json(&app_handle, RECIPES_FILE, &book)
+}
+
+// Command to put a kept recipe on a day (YYYY-MM-DD), replacing whatever was
+// planned for that meal. Servings default to the recipe's own.
+#[tauri::command]
+pub fn plan_meal(
+    date: String,
+    meal: Meal,
+    recipe: u64,
+    servings: Option<u32>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let date = format_date(parse_date(&date)?);
+    let mut book = load(&app_handle);
+    let saved = book
+        .recipes
+        .iter()
+        .find(|saved| saved.id == recipe)
+        .ok_or("Recipe not found".to_string())?;
+    let servings = servings.unwrap_or(saved.recipe.servings.max(1));
+    if servings == 0 {
+        return Err("Plan at least one serving".to_string());
+    }
+    book.plan.retain(|planned| planned.date != date || planned.meal != meal);
+    book.plan.push(PlannedMeal { date, meal, recipe, servings });
+    storage::save_json(&app_handle, RECIPES_FILE, &book)
+}
+
+#[tauri::command]
+pub fn unplan_meal(date: String, meal: Meal, app_handle: AppHandle) -> Result<(), String> {
+    let date = format_date(parse_date(&date)?);
+    let mut book = load(&app_handle);
+    book.plan.retain(|planned| planned.date != date || planned.meal != meal);
+    storage::save_json(&app_handle, RECIPES_FILE, &book)
+}
+
+fn planned_days(book: &RecipeBook, start: NaiveDate) -> Vec<PlannedDay> {
+    (0..7)
+        .map(|offset| format_date(start + Duration::days(offset)))
+        .map(|date| {
+            let mut meals: Vec<PlannedMealView> = book
+                .plan
+                .iter()
+                .filter(|planned| planned.date == date)
+                .filter_map(|planned| {
+                    let saved = book.recipes.iter().find(|saved| saved.id == planned.recipe)?;
+                    Some(PlannedMealView {
+                        meal: planned.meal,
+                        recipe: saved.id,
+                        title: saved.recipe.title.clone(),
+                        servings: planned.servings,
+                    })
+                })
+                .collect();
+            meals.sort_by_key(|view| view.meal);
+            PlannedDay { date, meals }
+        })
+        .collect()
+}
+
+// Command to get the seven days from the Monday of the week containing
+// `week_of` (this week by default)
+#[tauri::command]
+pub fn get_meal_plan(week_of: Option<String>, app_handle: AppHandle) -> Result<Vec<PlannedDay>, String> {
+    Ok(planned_days(&load(&app_handle), week_start(week_of)?))
+}
+
+fn shopping_list(book: &RecipeBook, start: NaiveDate) -> Vec<ShoppingItem> {
+    let (first, last) = (format_date(start), format_date(start + Duration::days(6)));
+
+    // Per ingredient: summed amounts per measure, unparsed quantities and recipe titles
+    let mut items: Vec<(String, ShoppingItem, Totals)> = Vec::new();
+    for planned in book.plan.iter().filter(|planned| planned.date >= first && planned.date <= last) {
+        let Some(saved) = book.recipes.iter().find(|saved| saved.id == planned.recipe) else {
+            continue;
+        };
+        let scale = match saved.recipe.servings {
+            0 => 1.0,
+            servings => planned.servings as f64 / servings as f64,
+        };
+        for ingredient in &saved.recipe.ingredients {
+            let key = normalize(&ingredient.name);
+            if key.is_empty() || STAPLES.contains(&key.as_str()) {
+                continue;
+            }
+            let index = match items.iter().position(|(existing, _, _)| *existing == key) {
+                Some(index) => index,
+                None => {
+                    let item = ShoppingItem {
+                        name: ingredient.name.trim().to_lowercase(),
+                        amounts: Vec::new(),
+                        recipes: Vec::new(),
+                    };
+                    items.push((key, item, Vec::new()));
+                    items.len() - 1
+                }
+            };
+            let (_, item, totals) = &mut items[index];
+            if !item.recipes.contains(&saved.recipe.title) {
+                item.recipes.push(saved.recipe.title.clone());
+            }
+            match parse_quantity(&ingredient.quantity) {
+                Some((value, measure)) => match totals.iter_mut().find(|(_, existing)| *existing == measure) {
+                    Some((total, _)) => *total += value * scale,
+                    None => totals.push((value * scale, measure)),
+                },
+                None => {
+                    let quantity = ingredient.quantity.trim();
+                    if !quantity.is_empty() && !item.amounts.iter().any(|amount| amount == quantity) {
+                        item.amounts.push(quantity.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut list: Vec<ShoppingItem> = items
+        .into_iter()
+        .map(|(_, mut item, totals)| {
+            let mut amounts: Vec<String> = totals.iter().map(|(value, measure)| format_amount(*value, measure)).collect();
+            amounts.append(&mut item.amounts);
+            item.amounts = amounts;
+            item
+        })
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    list
+}
+
+// Command to list everything the week's planned meals need, scaled to the
+// planned servings, with the same ingredient merged across recipes. Basic
+// staples are left off.
+#[tauri::command]
+pub fn get_shopping_list(week_of: Option<String>, app_handle: AppHandle) -> Result<Vec<ShoppingItem>, String> {
+    Ok(shopping_list(&load(&app_handle), week_start(week_of)?))
+}
+
+// "Dinner: Lentil soup" lines for today's planned meals, for the briefing
+pub fn todays_meals(app_handle: &AppHandle) -> Vec<String> {
+    let today = Local::now().date_naive();
+    planned_days(&load(app_handle), today)
+        .into_iter()
+        .next()
+        .map(|day| {
+            day.meals
+                .into_iter()
+                .map(|view| format!("{:?}: {}", view.meal, view.title))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(title: &str, servings: u32, ingredients: &[(&str, &str)]) -> Recipe {
+        Recipe {
+            title: title.to_string(),
+            summary: String::new(),
+            servings,
+            minutes: 30,
+            ingredients: ingredients
+                .iter()
+                .map(|(name, quantity)| RecipeIngredient {
+                    name: name.to_string(),
+                    quantity: quantity.to_string(),
+                    have: false,
+                })
+                .collect(),
+            steps: vec!["Cook".to_string()],
+            missing: Vec::new(),
+        }
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        parse_date(text).unwrap()
+    }
+
+    #[test]
+    fn plurals_are_the_same_ingredient() {
+        assert_eq!(normalize(" Tomatoes "), "tomato");
+        assert_eq!(normalize("eggs"), "egg");
+        assert_eq!(normalize("chives"), "chive");
+        assert_eq!(normalize("rice"), "rice");
+    }
+
+    #[test]
+    fn missing_ingredients_leave_out_staples_and_what_is_at_hand() {
+        let mut soup = recipe(
+            "Tomato soup",
+            2,
+            &[("Cherry tomatoes", "300 g"), ("Olive oil", "1 tbsp"), ("Salt", ""), ("Cream", "100 ml")],
+        );
+        mark_available(&mut soup, &[normalize("tomato")]);
+        assert!(soup.ingredients[0].have);
+        assert!(soup.ingredients[1].have);
+        assert_eq!(soup.missing, vec!["Cream"]);
+    }
+
+    #[test]
+    fn quantities_are_converted_to_grams_millilitres_or_counts() {
+        assert_eq!(parse_quantity("200 g"), Some((200.0, Measure::Mass)));
+        assert_eq!(parse_quantity("1 1/2 cups"), Some((360.0, Measure::Volume)));
+        assert_eq!(parse_quantity("1½ kg"), Some((1500.0, Measure::Mass)));
+        assert_eq!(parse_quantity("2 cloves"), Some((2.0, Measure::Count("clove".to_string()))));
+        assert_eq!(parse_quantity("3"), Some((3.0, Measure::Count(String::new()))));
+        assert_eq!(parse_quantity("a pinch"), None);
+        assert_eq!(parse_quantity("1/0 cup"), None);
+    }
+
+    #[test]
+    fn amounts_are_written_in_the_largest_sensible_unit() {
+        assert_eq!(format_amount(1800.0, &Measure::Mass), "1.8 kg");
+        assert_eq!(format_amount(450.4, &Measure::Mass), "450 g");
+        assert_eq!(format_amount(250.0, &Measure::Volume), "250 ml");
+        assert_eq!(format_amount(4.0, &Measure::Count("clove".to_string())), "4 cloves");
+        assert_eq!(format_amount(1.0, &Measure::Count("clove".to_string())), "1 clove");
+        assert_eq!(format_amount(2.5, &Measure::Count(String::new())), "2.5");
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        assert_eq!(week_start(Some("2024-03-14".to_string())).unwrap(), date("2024-03-11"));
+        assert_eq!(week_start(Some("2024-03-11".to_string())).unwrap(), date("2024-03-11"));
+        assert!(week_start(Some("14/03/2024".to_string())).is_err());
+    }
+
+    #[test]
+    fn the_shopping_list_merges_and_scales_the_week_of_meals() {
+        let saved = |id: u64, recipe: Recipe| SavedRecipe { id, saved_at: 0, recipe };
+        let planned = |date: &str, meal: Meal, recipe: u64, servings: u32| PlannedMeal {
+            date: date.to_string(),
+            meal,
+            recipe,
+            servings,
+        };
+        let book = RecipeBook {
+            next_id: 2,
+            recipes: vec![
+                saved(1, recipe("Pasta", 2, &[("Tomatoes", "400 g"), ("Garlic", "2 cloves"), ("Salt", "a pinch")])),
+                saved(2, recipe("Salad", 4, &[("tomato", "1 kg"), ("Basil", "a handful")])),
+            ],
+            plan: vec![
+                planned("2024-03-11", Meal::Dinner, 1, 4),
+                planned("2024-03-12", Meal::Lunch, 2, 4),
+                planned("2024-03-12", Meal::Breakfast, 1, 2),
+                planned("2024-03-18", Meal::Dinner, 2, 8),
+            ],
+        };
+
+        let list = shopping_list(&book, date("2024-03-11"));
+        let names: Vec<&str> = list.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["basil", "garlic", "tomatoes"]);
+        assert_eq!(list[0].amounts, vec!["a handful"]);
+        assert_eq!(list[1].amounts, vec!["6 cloves"]);
+        assert_eq!(list[2].amounts, vec!["2.2 kg"]);
+        assert_eq!(list[2].recipes, vec!["Pasta", "Salad"]);
+
+        let days = planned_days(&book, date("2024-03-11"));
+        assert_eq!(days.len(), 7);
+        let tuesday: Vec<Meal> = days[1].meals.iter().map(|view| view.meal).collect();
+        assert_eq!(tuesday, vec![Meal::Breakfast, Meal::Lunch]);
+    }
+}
@@ -0,0 +1,295 @@
+// On-device redaction of personal details before text goes to a cloud
+// provider. Emails, phone numbers and street addresses are replaced with
+// placeholders such as [EMAIL_1], and the placeholders in the reply are put
+// back, so the provider never sees the originals.
+
+use std::ops::Range;
+use tauri::{AppHandle, Manager, State};
+
+use crate::settings::{self, SettingsState};
+use crate::usage::Provider;
+
+// Phone numbers need this many digits, unless written with a leading +, so
+// dates and amounts are left alone
+const MIN_PHONE_DIGITS: usize = 9;
+const MAX_PHONE_DIGITS: usize = 15;
+// Capitalised words allowed between a house number and the street type
+const MAX_STREET_WORDS: usize = 3;
+const STREET_TYPES: &[&str] = &[
+    "street", "st", "avenue", "ave", "road", "rd", "boulevard", "blvd", "lane", "ln", "drive", "dr", "court", "ct",
+    "way", "place", "pl", "terrace", "close", "crescent", "square", "sq", "parkway", "pkwy", "highway", "hwy",
+];
+// Text goes to Gemini and, for moderation, to OpenAI under the Whisper keys;
+// the other providers only receive audio or run locally
+const TEXT_PROVIDERS: [Provider; 2] = [Provider::Gemini, Provider::Whisper];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Entity {
+    Email,
+    Phone,
+    Address,
+}
+
+impl Entity {
+    fn label(self) -> &'static str {
+        match self {
+            Entity::Email => "EMAIL",
+            Entity::Phone => "PHONE",
+            Entity::Address => "ADDRESS",
+        }
+    }
+}
+
+// Placeholders handed out so far, reused when the same value appears again
+#[derive(Default)]
+pub struct Redactions {
+    entities: Vec<(String, String)>,
+}
+
+impl Redactions {
+    fn placeholder(&mut self, entity: Entity, original: &str) -> String {
+        if let Some((placeholder, _)) = self.entities.iter().find(|(_, known)| known == original) {
+            return placeholder.clone();
+        }
+        let count = self
+            .entities
+            .iter()
+            .filter(|(placeholder, _)| placeholder.starts_with(&format!("[{}_", entity.label())))
+            .count();
+        let placeholder = format!("[{}_{}]", entity.label(), count + 1);
+        self.entities.push((placeholder.clone(), original.to_string()));
+        placeholder
+    }
+
+    // Replace every detected entity with its placeholder
+    pub fn mask(&mut self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut last = 0;
+        for (range, entity) in detect(text) {
+            masked.push_str(&text[last..range.start]);
+            masked.push_str(&self.placeholder(entity, &text[range.clone()]));
+            last = range.end;
+        }
+        masked.push_str(&text[last..]);
+        masked
+    }
+
+    // Put the originals back in a reply
+    pub fn restore(&self, text: &str) -> String {
+        self.entities
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, original)| text.replace(placeholder, original))
+    }
+}
+
+// Whitespace separated words with their byte ranges
+fn words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push(s..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+    words
+}
+
+// Narrow a word to exclude surrounding punctuation, keeping the given characters
+fn trim_word(text: &str, word: Range<usize>, keep: impl Fn(char) -> bool) -> Range<usize> {
+    let slice = &text[word.clone()];
+    let start = word.start + (slice.len() - slice.trim_start_matches(|c: char| !keep(c)).len());
+    let end = word.start + slice.trim_end_matches(|c: char| !keep(c)).len();
+    start..end.max(start)
+}
+
+fn is_email(candidate: &str) -> bool {
+    let Some((local, domain)) = candidate.split_once('@') else {
+        return false;
+    };
+    let labels: Vec<&str> = domain.split('.').collect();
+    !local.is_empty()
+        && local.chars().all(|c| c.is_ascii_alphanumeric() || "._%+-".contains(c))
+        && labels.len() >= 2
+        && labels
+            .iter()
+            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        && labels.last().is_some_and(|tld| tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+fn emails(text: &str) -> Vec<Range<usize>> {
+    words(text)
+        .into_iter()
+        .map(|word| trim_word(text, word, |c| c.is_ascii_alphanumeric()))
+        .filter(|range| is_email(&text[range.clone()]))
+        .collect()
+}
+
+fn phones(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let starts = bytes[i].is_ascii_digit() || bytes[i] == b'+' || bytes[i] == b'(';
+        if !starts || (i > 0 && bytes[i - 1].is_ascii_alphanumeric()) {
+            i += 1;
+            continue;
+        }
+        // Digit groups with at most two separators between them, as in "(555) 123-4567"
+        let international = bytes[i] == b'+';
+        let mut digits = 0;
+        let mut separators = 0;
+        let mut end = i;
+        let mut j = if international { i + 1 } else { i };
+        while j < bytes.len() {
+            if bytes[j].is_ascii_digit() {
+                digits += 1;
+                separators = 0;
+                end = j + 1;
+            } else if b" -.()".contains(&bytes[j]) && separators < 2 {
+                separators += 1;
+            } else {
+                break;
+            }
+            j += 1;
+        }
+        let enough = digits >= MIN_PHONE_DIGITS || (international && digits >= 7);
+        let followed_by_word = bytes.get(end).is_some_and(|b| b.is_ascii_alphanumeric());
+        if enough && digits <= MAX_PHONE_DIGITS && !followed_by_word {
+            found.push(i..end);
+        }
+        i = end.max(i + 1);
+    }
+    found
+}
+
+fn is_house_number(word: &str) -> bool {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    !digits.is_empty() && digits.len() <= 5 && digits.chars().all(|c| c.is_ascii_digit()) && word.len() - digits.len() <= 1
+}
+
+// A house number, up to a few capitalised words and a street type, as in
+// "221B Baker Street" or "1600 Amphitheatre Pkwy"
+fn addresses(text: &str) -> Vec<Range<usize>> {
+    let words: Vec<Range<usize>> = words(text)
+        .into_iter()
+        .map(|word| trim_word(text, word, |c| c.is_alphanumeric()))
+        .collect();
+    let mut found = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        if !STREET_TYPES.contains(&text[word.clone()].to_lowercase().as_str()) {
+            continue;
+        }
+        for names in 1..=MAX_STREET_WORDS.min(i.saturating_sub(1)) {
+            let capitalised = words[i - names..i]
+                .iter()
+                .all(|name| text[name.clone()].chars().next().is_some_and(|c| c.is_uppercase()));
+            if !capitalised {
+                break;
+            }
+            let number = &words[i - names - 1];
+            if is_house_number(&text[number.clone()]) {
+                found.push(number.start..word.end);
+                break;
+            }
+        }
+    }
+    found
+}
+
+// Every entity in the text, in order and without overlaps
+fn detect(text: &str) -> Vec<(Range<usize>, Entity)> {
+    let spans = emails(text)
+        .into_iter()
+        .map(|range| (range, Entity::Email))
+        .chain(addresses(text).into_iter().map(|range| (range, Entity::Address)))
+        .chain(phones(text).into_iter().map(|range| (range, Entity::Phone)));
+    // Earlier kinds win an overlap, so a number inside an email stays part of it
+    let mut kept: Vec<(Range<usize>, Entity)> = Vec::new();
+    for (range, entity) in spans {
+        if !kept.iter().any(|(other, _)| range.start < other.end && other.start < range.end) {
+            kept.push((range, entity));
+        }
+    }
+    kept.sort_by_key(|(range, _)| range.start);
+    kept
+}
+
+// Whether text sent to the provider is redacted, per the privacy settings
+pub fn enabled(app_handle: &AppHandle, provider: Provider) -> bool {
+    settings::current(&app_handle.state::<SettingsState>())
+        .privacy
+        .redacted_providers
+        .contains(&provider)
+}
+
+// Command to turn redaction on or off for a cloud provider
+#[tauri::command]
+pub fn set_redaction(
+    provider: Provider,
+    enabled: bool,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<Provider>, String> {
+    if !TEXT_PROVIDERS.contains(&provider) {
+        return Err(format!("{:?} does not receive text", provider));
+    }
+    let mut updated = settings::current(&settings_state);
+    let providers = &mut updated.privacy.redacted_providers;
+    providers.retain(|p| *p != provider);
+    if enabled {
+        providers.push(provider);
+    }
+    let providers = providers.clone();
+    settings::update_settings(updated, app_handle, settings_state)?;
+    Ok(providers)
+}
+
+// Command showing what a provider would receive, for the privacy settings screen
+#[tauri::command]
+pub fn preview_redaction(text: String) -> String {
+    Redactions::default().mask(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn details_are_masked_and_restored() {
+        let text = "Email jane.doe@example.co.uk or call (555) 123-4567, I'm at 221B Baker Street.";
+        let mut redactions = Redactions::default();
+        let masked = redactions.mask(text);
+        assert_eq!(masked, "Email [EMAIL_1] or call [PHONE_1], I'm at [ADDRESS_1].");
+        assert_eq!(redactions.restore("Sure, I'll write to [EMAIL_1]."), "Sure, I'll write to jane.doe@example.co.uk.");
+        assert_eq!(redactions.restore(&masked), text);
+    }
+
+    #[test]
+    fn repeated_values_share_a_placeholder() {
+        let mut redactions = Redactions::default();
+        let masked = redactions.mask("a@example.com, b@example.com and a@example.com");
+        assert_eq!(masked, "[EMAIL_1], [EMAIL_2] and [EMAIL_1]");
+    }
+
+    #[test]
+    fn international_numbers_need_fewer_digits() {
+        let mut redactions = Redactions::default();
+        assert_eq!(redactions.mask("Ring +44 20 7946"), "Ring [PHONE_1]");
+        assert_eq!(redactions.mask("Ring 20 7946"), "Ring 20 7946");
+    }
+
+    #[test]
+    fn dates_amounts_and_ordinary_words_are_left_alone() {
+        let text = "On 2024-05-01 I paid 1,250.00 for 3 tickets to Main Street Festival, order AB123456789.";
+        assert_eq!(Redactions::default().mask(text), text);
+        assert_eq!(Redactions::default().mask("not an email@localhost"), "not an email@localhost");
+    }
+}
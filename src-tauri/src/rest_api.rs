@@ -0,0 +1,442 @@
+use axum::{
+    extract::{Request, State as AxumState},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tauri::{async_runtime::JoinHandle, AppHandle, Manager, State};
+
+use crate::engine;
+use crate::keystore::Keystore;
+use crate::settings::{self, SettingsState};
+use crate::storage;
+use crate::timers::{self, Timer, TimersState};
+use crate::widgets;
+
+// The shared token used before per-client tokens; removed when the signing key is created
+const LEGACY_TOKEN_SECRET: &str = "rest_api_token";
+const SIGNING_KEY_SECRET: &str = "rest_api_signing_key";
+const CLIENTS_FILE: &str = "rest_api_clients.json";
+const DEFAULT_PORT: u16 = 47801;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// A device or script allowed to call the API. Its token is signed with the
+// keystore key and never stored, so only the metadata lives on disk.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiClient {
+    pub id: String,
+    pub name: String,
+    pub created_at: u64,
+    // Unix seconds; None never expires
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IssuedToken {
+    pub client: ApiClient,
+    // Shown once; the client sends it as a bearer token
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ClientStore {
+    clients: Vec<ApiClient>,
+}
+
+#[derive(Clone)]
+struct ApiContext {
+    app_handle: AppHandle,
+    key: Arc<Mutex<String>>,
+    clients: Arc<Mutex<Vec<ApiClient>>>,
+    started_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct AskRequest {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct AskResponse {
+    answer: String,
+}
+
+#[derive(Deserialize)]
+struct TimerRequest {
+    minutes: u64,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BriefingResponse {
+    snippets: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    version: String,
+    uptime_secs: u64,
+    active_timers: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RestApiStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    pub allow_lan: bool,
+}
+
+#[derive(Default)]
+pub struct RestApiServer {
+    task: Option<JoinHandle<()>>,
+    port: Option<u16>,
+    allow_lan: bool,
+    key: Arc<Mutex<String>>,
+    clients: Arc<Mutex<Vec<ApiClient>>>,
+}
+
+pub type RestApiState = Mutex<RestApiServer>;
+
+type ApiError = (StatusCode, String);
+
+fn internal(message: String) -> ApiError {
+    (StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+pub(crate) fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Compare without bailing out early so timing does not leak the token
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Hex HMAC-SHA256 of a message; also signs outgoing webhooks
+pub(crate) fn sign(key: &str, message: &[u8]) -> Result<String, String> {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).map_err(|e| e.to_string())?;
+    mac.update(message);
+    Ok(mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// Load the signing key from the keystore, creating one on first use
+pub(crate) fn signing_key(keystore: &Keystore) -> Result<String, String> {
+    match keystore.get(SIGNING_KEY_SECRET)? {
+        Some(key) => Ok(key),
+        None => {
+            let key = generate_token();
+            keystore.set(SIGNING_KEY_SECRET, &key)?;
+            keystore.delete(LEGACY_TOKEN_SECRET)?;
+            Ok(key)
+        }
+    }
+}
+
+fn expiry(now: u64, valid_days: u64) -> Result<u64, String> {
+    valid_days
+        .checked_mul(24 * 60 * 60)
+        .and_then(|secs| now.checked_add(secs))
+        .ok_or("The token would be valid for too long".to_string())
+}
+
+// Tokens are "<client id>.<expiry or 0>.<signature>"
+fn client_token(key: &str, client: &ApiClient) -> Result<String, String> {
+    let claims = format!("{}.{}", client.id, client.expires_at.unwrap_or(0));
+    Ok(format!("{}.{}", claims, sign(key, claims.as_bytes())?))
+}
+
+// The client a token was issued to, if it is genuine, unexpired and not revoked
+fn verify_token(key: &str, clients: &[ApiClient], token: &str) -> Option<ApiClient> {
+    let (claims, signature) = token.rsplit_once('.')?;
+    let expected = sign(key, claims.as_bytes()).ok()?;
+    if !constant_time_eq(expected.as_bytes(), signature.as_bytes()) {
+        return None;
+    }
+    let (id, expires_at) = claims.split_once('.')?;
+    let expires_at: u64 = expires_at.parse().ok()?;
+    if expires_at != 0 && expires_at <= now_secs() {
+        return None;
+    }
+    clients
+        .iter()
+        .find(|client| client.id == id && client.expires_at.unwrap_or(0) == expires_at)
+        .cloned()
+}
+
+fn load_clients(app_handle: &AppHandle) -> Vec<ApiClient> {
+    storage::load_json::<ClientStore>(app_handle, CLIENTS_FILE).clients
+}
+
+fn save_clients(app_handle: &AppHandle, clients: &[ApiClient]) -> Result<(), String> {
+    storage::save_json(app_handle, CLIENTS_FILE, &ClientStore { clients: clients.to_vec() })
+}
+
+async fn require_token(
+    AxumState(context): AxumState<ApiContext>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let provided = request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .to_string();
+
+    let authorized = {
+        let key = context.key.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let clients = context.clients.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        !key.is_empty() && verify_token(&key, &clients, &provided).is_some()
+    };
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+async fn ask(
+    AxumState(context): AxumState<ApiContext>,
+    Json(request): Json<AskRequest>,
+) -> Result<Json<AskResponse>, ApiError> {
+    let app_settings = settings::current(&context.app_handle.state::<SettingsState>());
+    let answer = engine::respond(&context.app_handle, &request.text, &app_settings)
+        .await
+        .map_err(internal)?;
+    Ok(Json(AskResponse { answer }))
+}
+
+async fn timer(
+    AxumState(context): AxumState<ApiContext>,
+    Json(request): Json<TimerRequest>,
+) -> Result<Json<Timer>, ApiError> {
+    let seconds = request
+        .minutes
+        .checked_mul(60)
+        .ok_or((StatusCode::BAD_REQUEST, "The timer is too long".to_string()))?;
+    timers::start_timer(&context.app_handle, seconds, request.label)
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+async fn briefing(AxumState(context): AxumState<ApiContext>) -> Json<BriefingResponse> {
+    Json(BriefingResponse {
+        snippets: widgets::briefing_snippets(&context.app_handle).await,
+    })
+}
+
+async fn status(AxumState(context): AxumState<ApiContext>) -> Result<Json<StatusResponse>, ApiError> {
+    let active_timers = timers::list_timers(context.app_handle.state::<TimersState>())
+        .map_err(internal)?
+        .len();
+    Ok(Json(StatusResponse {
+        version: context.app_handle.package_info().version.to_string(),
+        uptime_secs: context.started_at.elapsed().as_secs(),
+        active_timers,
+    }))
+}
+
+fn router(context: ApiContext) -> Router {
+    Router::new()
+        .route("/v1/ask", post(ask))
+        .route("/v1/timer", post(timer))
+        .route("/v1/briefing", get(briefing))
+        .route("/v1/status", get(status))
+        .layer(middleware::from_fn_with_state(context.clone(), require_token))
+        .with_state(context)
+}
+
+fn server_status(server: &RestApiServer) -> RestApiStatus {
+    RestApiStatus {
+        running: server.task.is_some(),
+        port: server.port,
+        allow_lan: server.allow_lan,
+    }
+}
+
+// Command to start the local control API. Bound to localhost unless allow_lan is set.
+#[tauri::command]
+pub async fn start_rest_api(
+    port: Option<u16>,
+    allow_lan: Option<bool>,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, RestApiState>,
+) -> Result<RestApiStatus, String> {
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let allow_lan = allow_lan.unwrap_or(false);
+    let host = if allow_lan { "0.0.0.0" } else { "127.0.0.1" };
+    if state.lock().map_err(|e| e.to_string())?.task.is_some() {
+        return Err("Local API is already running".to_string());
+    }
+    let listener = tokio::net::TcpListener::bind((host, port))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let key = signing_key(&keystore)?;
+    let clients = load_clients(&app_handle);
+    let mut server = state.lock().map_err(|e| e.to_string())?;
+    if server.task.is_some() {
+        return Err("Local API is already running".to_string());
+    }
+    *server.key.lock().map_err(|e| e.to_string())? = key;
+    *server.clients.lock().map_err(|e| e.to_string())? = clients;
+
+    let app = router(ApiContext {
+        app_handle,
+        key: server.key.clone(),
+        clients: server.clients.clone(),
+        started_at: Instant::now(),
+    });
+    server.task = Some(tauri::async_runtime::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    }));
+    server.port = Some(port);
+    server.allow_lan = allow_lan;
+    Ok(server_status(&server))
+}
+
+#[tauri::command]
+pub fn stop_rest_api(state: State<'_, RestApiState>) -> Result<(), String> {
+    let mut server = state.lock().map_err(|e| e.to_string())?;
+    if let Some(task) = server.task.take() {
+        task.abort();
+    }
+    server.port = None;
+    server.allow_lan = false;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_rest_api_status(state: State<'_, RestApiState>) -> Result<RestApiStatus, String> {
+    let server = state.lock().map_err(|e| e.to_string())?;
+    Ok(server_status(&server))
+}
+
+#[tauri::command]
+pub fn list_api_clients(app_handle: AppHandle) -> Vec<ApiClient> {
+    load_clients(&app_handle)
+}
+
+// Command to issue a token for a new client, valid for the given number of days or forever
+#[tauri::command]
+pub fn create_api_client(
+    name: String,
+    valid_days: Option<u64>,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, RestApiState>,
+) -> Result<IssuedToken, String> {
+    let id: [u8; 8] = rand::thread_rng().gen();
+    let now = now_secs();
+    let client = ApiClient {
+        id: id.iter().map(|b| format!("{:02x}", b)).collect(),
+        name,
+        created_at: now,
+        expires_at: valid_days.map(|days| expiry(now, days)).transpose()?,
+    };
+    let token = client_token(&signing_key(&keystore)?, &client)?;
+    let mut clients = load_clients(&app_handle);
+    clients.push(client.clone());
+    save_clients(&app_handle, &clients)?;
+    let server = state.lock().map_err(|e| e.to_string())?;
+    *server.clients.lock().map_err(|e| e.to_string())? = clients;
+    Ok(IssuedToken { client, token })
+}
+
+// Command to revoke a client's token; takes effect immediately on a running server
+#[tauri::command]
+pub fn revoke_client(
+    client_id: String,
+    app_handle: AppHandle,
+    state: State<'_, RestApiState>,
+) -> Result<(), String> {
+    let mut clients = load_clients(&app_handle);
+    let before = clients.len();
+    clients.retain(|client| client.id != client_id);
+    if clients.len() == before {
+        return Err("Client not found".to_string());
+    }
+    save_clients(&app_handle, &clients)?;
+    let server = state.lock().map_err(|e| e.to_string())?;
+    *server.clients.lock().map_err(|e| e.to_string())? = clients;
+    Ok(())
+}
+
+// Command to replace the signing key, revoking every client token and
+// changing the webhook signatures
+#[tauri::command]
+pub fn rotate_api_signing_key(
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, RestApiState>,
+) -> Result<(), String> {
+    let key = generate_token();
+    keystore.set(SIGNING_KEY_SECRET, &key)?;
+    save_clients(&app_handle, &[])?;
+    let server = state.lock().map_err(|e| e.to_string())?;
+    *server.key.lock().map_err(|e| e.to_string())? = key;
+    server.clients.lock().map_err(|e| e.to_string())?.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(expires_at: Option<u64>) -> ApiClient {
+        ApiClient {
+            id: "0123456789abcdef".to_string(),
+            name: "Shortcuts".to_string(),
+            created_at: 0,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn accepts_a_token_it_issued() {
+        let client = client(None);
+        let token = client_token("key", &client).unwrap();
+        let verified = verify_token("key", std::slice::from_ref(&client), &token).unwrap();
+        assert_eq!(verified.id, client.id);
+    }
+
+    #[test]
+    fn rejects_forged_revoked_and_expired_tokens() {
+        let live = client(None);
+        let token = client_token("key", &live).unwrap();
+        assert!(verify_token("other key", std::slice::from_ref(&live), &token).is_none());
+        assert!(verify_token("key", &[], &token).is_none());
+        assert!(verify_token("key", std::slice::from_ref(&live), &format!("{}0", token)).is_none());
+
+        let expired = client(Some(1));
+        let token = client_token("key", &expired).unwrap();
+        assert!(verify_token("key", &[expired], &token).is_none());
+    }
+
+    #[test]
+    fn expiry_overflow_is_an_error() {
+        assert_eq!(expiry(100, 1), Ok(100 + 24 * 60 * 60));
+        assert!(expiry(100, u64::MAX / 1000).is_err());
+    }
+}
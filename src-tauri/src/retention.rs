@@ -0,0 +1,156 @@
+// Retention policies for personal data. A background job deletes recordings
+// and transcription jobs, conversation messages, the saved location and log
+// files once they are older than their configured number of days.
+
+use serde::Serialize;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audio_cache;
+use crate::conversations;
+use crate::settings::{self, RetentionSettings, SettingsState};
+use crate::storage;
+use crate::transcription_jobs;
+use crate::widgets;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PruneReport {
+    // Recordings and saved transcription jobs
+    pub transcript_files: usize,
+    pub messages: usize,
+    pub location_cleared: bool,
+    pub log_files: usize,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Start of the window to keep, or None when the data is kept forever
+fn cutoff(days: u64) -> Option<u64> {
+    (days > 0).then(|| now_secs().saturating_sub(days.saturating_mul(DAY_SECS)))
+}
+
+// Delete files in a directory last modified before the cutoff
+fn prune_dir(dir: &Path, cutoff: u64) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let cutoff = UNIX_EPOCH + Duration::from_secs(cutoff);
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified < cutoff)
+        })
+        .filter(|entry| entry.path().is_file() && std::fs::remove_file(entry.path()).is_ok())
+        .count()
+}
+
+pub fn prune(app_handle: &AppHandle) -> Result<PruneReport, String> {
+    let policies = settings::current(&app_handle.state::<SettingsState>()).retention;
+    let mut report = PruneReport::default();
+    if let Some(cutoff) = cutoff(policies.transcripts_days) {
+        if let Ok(dir) = audio_cache::recordings_dir(app_handle) {
+            report.transcript_files += prune_dir(&dir, cutoff);
+        }
+        if let Ok(dir) = storage::data_file(app_handle, transcription_jobs::JOBS_DIR) {
+            report.transcript_files += prune_dir(&dir, cutoff);
+        }
+    }
+    if let Some(cutoff) = cutoff(policies.conversations_days) {
+        report.messages = conversations::prune(app_handle, cutoff)?;
+    }
+    if let Some(cutoff) = cutoff(policies.location_days) {
+        report.location_cleared = widgets::forget_location(app_handle, cutoff)?;
+    }
+    if let Some(cutoff) = cutoff(policies.logs_days) {
+        if let Ok(dir) = app_handle.path().app_log_dir() {
+            report.log_files = prune_dir(&dir, cutoff);
+        }
+    }
+    Ok(report)
+}
+
+fn prune_and_report(app_handle: &AppHandle) {
+    match prune(app_handle) {
+        Ok(report) => {
+            if report.transcript_files + report.messages + report.log_files > 0 || report.location_cleared {
+                tracing::info!(?report, "pruned expired data");
+                let _ = app_handle.emit("retention://pruned", report);
+            }
+        }
+        Err(e) => tracing::warn!(error = %e, "pruning expired data failed"),
+    }
+}
+
+// Prune at startup and then every few hours
+pub fn spawn_pruner(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            prune_and_report(&app_handle);
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_retention_policies(settings_state: State<'_, SettingsState>) -> RetentionSettings {
+    settings::current(&settings_state).retention
+}
+
+// Command to change the policies; data outside a shortened window is pruned right away
+#[tauri::command]
+pub fn update_retention_policies(
+    policies: RetentionSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<RetentionSettings, String> {
+    let mut updated = settings::current(&settings_state);
+    updated.retention = policies.clone();
+    settings::update_settings(updated, app_handle.clone(), settings_state)?;
+    prune_and_report(&app_handle);
+    Ok(policies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn cutoff_is_none_when_kept_forever() {
+        assert_eq!(cutoff(0), None);
+        let week = cutoff(7).unwrap();
+        assert!(now_secs() - week >= 7 * DAY_SECS);
+        assert!(now_secs() - week < 7 * DAY_SECS + 60);
+        assert_eq!(cutoff(u64::MAX), Some(0));
+    }
+
+    #[test]
+    fn prune_dir_removes_only_old_files() {
+        let dir = std::env::temp_dir().join(format!("plates-retention-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let old = File::create(dir.join("old.wav")).unwrap();
+        old.set_modified(SystemTime::now() - Duration::from_secs(10 * DAY_SECS)).unwrap();
+        File::create(dir.join("new.wav")).unwrap();
+
+        assert_eq!(prune_dir(&dir, cutoff(7).unwrap()), 1);
+        assert!(!dir.join("old.wav").exists());
+        assert!(dir.join("new.wav").exists());
+        // Directories are left alone
+        assert!(dir.join("nested").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(prune_dir(&dir, now_secs()), 0);
+    }
+}
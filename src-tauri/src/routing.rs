@@ -0,0 +1,233 @@
+// Latency and failures of recent transcription and language model calls per
+// provider. Automatic routing prefers the historically fastest provider that
+// is healthy, e.g. on-device Whisper while the network is slow.
+
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::profiler;
+use crate::storage;
+
+const ROUTING_FILE: &str = "routing.json";
+// Calls kept per provider
+const MAX_SAMPLES: usize = 50;
+// Fewer successful calls than this say too little to route on
+const MIN_SAMPLES: usize = 3;
+// A provider failing more than this share of recent calls is skipped
+const MAX_ERROR_RATE: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    Stt,
+    Llm,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProviderSamples {
+    service: Service,
+    provider: String,
+    // Durations of successful calls
+    latencies_ms: VecDeque<u64>,
+    // Whether each recent call succeeded
+    outcomes: VecDeque<bool>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RoutingStore {
+    providers: Vec<ProviderSamples>,
+}
+
+pub type RoutingState = Mutex<RoutingStore>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ProviderStats {
+    pub service: Service,
+    pub provider: String,
+    pub calls: usize,
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub error_rate: f64,
+    pub healthy: bool,
+}
+
+pub fn load(app_handle: &AppHandle) -> RoutingStore {
+    storage::load_json(app_handle, ROUTING_FILE)
+}
+
+fn summarize(samples: &ProviderSamples) -> ProviderStats {
+    let mut sorted: Vec<u64> = samples.latencies_ms.iter().copied().collect();
+    sorted.sort_unstable();
+    let percentile = |percent| (!sorted.is_empty()).then(|| profiler::percentile(&sorted, percent));
+    let failures = samples.outcomes.iter().filter(|ok| !**ok).count();
+    let error_rate = if samples.outcomes.is_empty() {
+        0.0
+    } else {
+        failures as f64 / samples.outcomes.len() as f64
+    };
+    ProviderStats {
+        service: samples.service,
+        provider: samples.provider.clone(),
+        calls: samples.outcomes.len(),
+        p50_ms: percentile(50),
+        p90_ms: percentile(90),
+        p99_ms: percentile(99),
+        error_rate,
+        healthy: error_rate <= MAX_ERROR_RATE,
+    }
+}
+
+impl RoutingStore {
+    fn add(&mut self, service: Service, provider: &str, elapsed: Duration, ok: bool) {
+        let index = match self
+            .providers
+        .iter()
+            .position(|samples| samples.service == service && samples.provider == provider)
+        {
+            Some(index) => index,
+            None => {
+                self.providers.push(ProviderSamples {
+                    service,
+                    provider: provider.to_string(),
+                    latencies_ms: VecDeque::new(),
+                    outcomes: VecDeque::new(),
+                });
+                self.providers.len() - 1
+            }
+        };
+        let samples = &mut self.providers[index];
+        if ok {
+            samples.latencies_ms.push_back(elapsed.as_millis() as u64);
+            if samples.latencies_ms.len() > MAX_SAMPLES {
+                samples.latencies_ms.pop_front();
+            }
+        }
+        samples.outcomes.push_back(ok);
+        if samples.outcomes.len() > MAX_SAMPLES {
+            samples.outcomes.pop_front();
+        }
+    }
+
+    // Healthy providers with enough history by median latency, then untried
+    // ones in the order given, then unhealthy ones
+    fn order<'a>(&self, service: Service, candidates: &[&'a str]) -> Vec<&'a str> {
+        let ranks: Vec<(String, (u8, u64))> = self
+            .providers
+            .iter()
+            .filter(|samples| samples.service == service)
+            .map(|samples| {
+                let stats = summarize(samples);
+                let rank = if !stats.healthy {
+                    (2, 0)
+                } else if samples.latencies_ms.len() >= MIN_SAMPLES {
+                    (0, stats.p50_ms.unwrap_or(u64::MAX))
+                } else {
+                    (1, 0)
+                };
+                (stats.provider, rank)
+            })
+            .collect();
+        let mut ordered = candidates.to_vec();
+        ordered.sort_by_key(|candidate| {
+            ranks
+                .iter()
+                .find(|(provider, _)| provider == candidate)
+                .map(|(_, rank)| *rank)
+                .unwrap_or((1, 0))
+        });
+        ordered
+    }
+}
+
+// Record how long a call took and whether it succeeded
+pub fn record(app_handle: &AppHandle, service: Service, provider: &str, elapsed: Duration, ok: bool) {
+    let Some(state) = app_handle.try_state::<RoutingState>() else {
+        return;
+    };
+    let Ok(mut store) = state.lock() else {
+        return;
+    };
+    store.add(service, provider, elapsed, ok);
+    let _ = storage::save_json(app_handle, ROUTING_FILE, &*store);
+}
+
+// Order candidate providers for automatic routing
+pub fn order<'a>(app_handle: &AppHandle, service: Service, candidates: &[&'a str]) -> Vec<&'a str> {
+    match app_handle.try_state::<RoutingState>() {
+        Some(state) => match state.lock() {
+            Ok(store) => store.order(service, candidates),
+            Err(_) => candidates.to_vec(),
+        },
+        None => candidates.to_vec(),
+    }
+}
+
+// Command listing latency percentiles and health for every provider used so far
+#[tauri::command]
+pub fn get_routing_stats(state: State<'_, RoutingState>) -> Result<Vec<ProviderStats>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.providers.iter().map(summarize).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calls(store: &mut RoutingStore, service: Service, provider: &str, millis: &[u64]) {
+        for ms in millis {
+            store.add(service, provider, Duration::from_millis(*ms), true);
+        }
+    }
+
+    #[test]
+    fn summarize_reports_percentiles_and_errors() {
+        let mut store = RoutingStore::default();
+        calls(&mut store, Service::Stt, "deepgram", &[100, 300, 200, 400]);
+        store.add(Service::Stt, "deepgram", Duration::from_millis(5000), false);
+
+        let stats = summarize(&store.providers[0]);
+        assert_eq!(stats.calls, 5);
+        assert_eq!(stats.p50_ms, Some(200));
+        assert_eq!(stats.p99_ms, Some(400));
+        assert!((stats.error_rate - 0.2).abs() < 1e-9);
+        assert!(stats.healthy);
+
+        for _ in 0..6 {
+            store.add(Service::Stt, "deepgram", Duration::ZERO, false);
+        }
+        assert!(!summarize(&store.providers[0]).healthy);
+    }
+
+    #[test]
+    fn samples_are_capped() {
+        let mut store = RoutingStore::default();
+        calls(&mut store, Service::Llm, "openai", &[10; MAX_SAMPLES + 5]);
+        assert_eq!(store.providers[0].latencies_ms.len(), MAX_SAMPLES);
+        assert_eq!(store.providers[0].outcomes.len(), MAX_SAMPLES);
+    }
+
+    #[test]
+    fn order_prefers_fast_healthy_providers() {
+        let mut store = RoutingStore::default();
+        calls(&mut store, Service::Stt, "deepgram", &[900, 900, 900]);
+        calls(&mut store, Service::Stt, "whisper", &[200, 250, 300]);
+        // Too few calls to rank on
+        calls(&mut store, Service::Stt, "assemblyai", &[50]);
+        for _ in 0..3 {
+            store.add(Service::Stt, "openai", Duration::ZERO, false);
+        }
+        // Other services don't count
+        calls(&mut store, Service::Llm, "untried", &[1, 1, 1]);
+
+        let candidates = ["openai", "untried", "assemblyai", "deepgram", "whisper"];
+        assert_eq!(
+            store.order(Service::Stt, &candidates),
+            vec!["whisper", "deepgram", "untried", "assemblyai", "openai"]
+        );
+        assert_eq!(RoutingStore::default().order(Service::Llm, &["a", "b"]), vec!["a", "b"]);
+    }
+}
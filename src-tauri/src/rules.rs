@@ -0,0 +1,660 @@
+use chrono::{Datelike, Local, Timelike};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::endpoints;
+use crate::notifications::DigestItem;
+use crate::storage;
+
+const RULES_FILE: &str = "rules.json";
+const SCHEDULER_TICK: Duration = Duration::from_secs(30);
+// Host probed to detect network changes
+const CONNECTIVITY_PROBE: &str = "1.1.1.1:53";
+// Forecasts are fetched at most this often, and only when a weather rule exists
+const WEATHER_INTERVAL_SECS: u64 = 10 * 60;
+// Minute-by-minute precipitation only covers the next hour
+const MAX_RAIN_LEAD_MINS: u32 = 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GeofenceEdge {
+    Enter,
+    Exit,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    // Local time; days are 0 (Monday) to 6, empty means every day
+    Time {
+        hour: u32,
+        minute: u32,
+        #[serde(default)]
+        days: Vec<u32>,
+    },
+    Geofence {
+        latitude: f64,
+        longitude: f64,
+        radius_m: f64,
+        on: GeofenceEdge,
+    },
+    // Fires once when the charge drops below the threshold
+    Battery { below: u8 },
+    NetworkChange {
+        #[serde(default)]
+        connected: Option<bool>,
+    },
+    NotificationKeyword { keyword: String },
+    // Matches a tag by id, by one of its text/URI records, or both
+    NfcTag {
+        #[serde(default)]
+        tag_id: Option<String>,
+        #[serde(default)]
+        payload: Option<String>,
+    },
+    // Rain expected to start within the next N minutes (at most 60)
+    RainSoon { within_minutes: u32 },
+    // Temperature in °F crossing above and/or below a threshold
+    Temperature {
+        #[serde(default)]
+        above: Option<f64>,
+        #[serde(default)]
+        below: Option<f64>,
+    },
+    // A new weather alert, optionally only when its event name contains a keyword
+    WeatherAlert {
+        #[serde(default)]
+        keyword: Option<String>,
+    },
+}
+
+impl Trigger {
+    fn is_weather(&self) -> bool {
+        matches!(
+            self,
+            Trigger::RainSoon { .. } | Trigger::Temperature { .. } | Trigger::WeatherAlert { .. }
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Scene { name: String },
+    Notification { title: String, body: String },
+    Announce { text: String },
+    Webhook { webhook_id: u64 },
+    StartTimer {
+        seconds: u64,
+        #[serde(default)]
+        label: Option<String>,
+    },
+    // A URL or app URI scheme handed to the OS
+    OpenApp { target: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rule {
+    #[serde(default)]
+    pub id: u64,
+    pub name: String,
+    pub trigger: Trigger,
+    pub actions: Vec<Action>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub last_fired: Option<u64>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RulesStore {
+    next_id: u64,
+    rules: Vec<Rule>,
+    // Last observed conditions, used to fire on edges rather than levels
+    #[serde(skip)]
+    inside_geofence: HashMap<u64, bool>,
+    #[serde(skip)]
+    battery_level: Option<u8>,
+    #[serde(skip)]
+    connected: Option<bool>,
+    #[serde(skip)]
+    location: Option<(f64, f64)>,
+    #[serde(skip)]
+    weather_checked_at: u64,
+    #[serde(skip)]
+    temperature: Option<f64>,
+    #[serde(skip)]
+    rain_expected: HashMap<u64, bool>,
+    // Active alerts already seen, as "event@start"
+    #[serde(skip)]
+    seen_alerts: Vec<String>,
+}
+
+pub type RulesState = Mutex<RulesStore>;
+
+#[derive(Serialize, Clone)]
+struct RuleFired {
+    rule_id: u64,
+    name: String,
+}
+
+pub fn load(app_handle: &AppHandle) -> RulesStore {
+    storage::load_json(app_handle, RULES_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Great-circle distance in meters
+fn distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    6_371_000.0 * 2.0 * a.sqrt().asin()
+}
+
+fn run_actions(app_handle: &AppHandle, rule: &Rule) {
+    let data = json!({ "rule_id": rule.id, "rule": rule.name });
+    for action in &rule.actions {
+        match action {
+            Action::Scene { name } => {
+                let _ = app_handle.emit("rules://scene", name);
+            }
+            Action::Notification { title, body } => {
+                let _ = crate::notifications::push(app_handle, "rules", title.clone(), body.clone(), None);
+            }
+            Action::Announce { text } => {
+                let _ = app_handle.emit("rules://announce", text);
+            }
+            Action::Webhook { webhook_id } => {
+                crate::automations::fire(app_handle, *webhook_id, "rule.fired", data.clone());
+            }
+            Action::StartTimer { seconds, label } => {
+                let _ = crate::timers::start_timer(app_handle, *seconds, label.clone());
+            }
+            Action::OpenApp { target } => {
+                let _ = app_handle.opener().open_url(target, None::<&str>);
+            }
+        }
+    }
+    let _ = app_handle.emit(
+        "rules://fired",
+        RuleFired {
+            rule_id: rule.id,
+            name: rule.name.clone(),
+        },
+    );
+}
+
+// Run every enabled rule whose trigger matches, then record when it fired
+fn fire_matching(app_handle: &AppHandle, matches: impl Fn(&Rule, &mut RulesStore) -> bool) -> Result<usize, String> {
+    let fired: Vec<Rule> = {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        let now = now_secs();
+        let mut fired = Vec::new();
+        for index in 0..store.rules.len() {
+            let rule = store.rules[index].clone();
+            if rule.enabled && matches(&rule, &mut *store) {
+                store.rules[index].last_fired = Some(now);
+                fired.push(store.rules[index].clone());
+            }
+        }
+        if !fired.is_empty() {
+            storage::save_json(app_handle, RULES_FILE, &*store)?;
+        }
+        fired
+    };
+
+    for rule in &fired {
+        run_actions(app_handle, rule);
+    }
+    Ok(fired.len())
+}
+
+fn evaluate_time(app_handle: &AppHandle) -> Result<(), String> {
+    let now = Local::now();
+    let timestamp = now_secs();
+    fire_matching(app_handle, |rule, _| match &rule.trigger {
+        Trigger::Time { hour, minute, days } => {
+            *hour == now.hour()
+                && *minute == now.minute()
+                && (days.is_empty() || days.contains(&now.weekday().num_days_from_monday()))
+                // A tick can land twice in the same minute
+                && rule.last_fired.map(|at| timestamp.saturating_sub(at) >= 60).unwrap_or(true)
+        }
+        _ => false,
+    })?;
+    Ok(())
+}
+
+fn read_battery(app_handle: &AppHandle) -> Option<u8> {
+    let state = app_handle.try_state::<tauri_plugin_system_info::SysInfoState>()?;
+    let batteries = tauri_plugin_system_info::commands::battery::batteries(state).ok()?;
+    batteries.first().map(|battery| battery.state_of_charge)
+}
+
+fn evaluate_battery(app_handle: &AppHandle) -> Result<(), String> {
+    let Some(level) = read_battery(app_handle) else {
+        return Ok(());
+    };
+    let previous = {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.battery_level.replace(level)
+    };
+    let Some(previous) = previous else {
+        return Ok(());
+    };
+    fire_matching(app_handle, |rule, _| match &rule.trigger {
+        Trigger::Battery { below } => previous >= *below && level < *below,
+        _ => false,
+    })?;
+    Ok(())
+}
+
+// Whether the last connectivity probe failed, or offline is being simulated
+pub fn is_offline(app_handle: &AppHandle) -> bool {
+    endpoints::simulated_offline()
+        || app_handle
+            .state::<RulesState>()
+            .lock()
+            .is_ok_and(|store| store.connected == Some(false))
+}
+
+async fn evaluate_network(app_handle: &AppHandle) -> Result<(), String> {
+    let connected = !endpoints::simulated_offline() && tokio::time::timeout(
+        Duration::from_secs(3),
+        tokio::net::TcpStream::connect(CONNECTIVITY_PROBE),
+    )
+    .await
+    .map(|result| result.is_ok())
+    .unwrap_or(false);
+
+    let previous = {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.connected.replace(connected)
+    };
+    if previous.is_none() || previous == Some(connected) {
+        return Ok(());
+    }
+    fire_matching(app_handle, |rule, _| match &rule.trigger {
+        Trigger::NetworkChange { connected: wanted } => wanted.map(|w| w == connected).unwrap_or(true),
+        _ => false,
+    })?;
+    Ok(())
+}
+
+// Whether rain is now expected within the rule's lead time when it wasn't at the
+// last forecast
+fn rain_starts(rule: &Rule, store: &mut RulesStore, minutely: &[crate::MinutelyPrecipitation], now: i64) -> bool {
+    match &rule.trigger {
+        Trigger::RainSoon { within_minutes } => {
+            let until = now + (*within_minutes).min(MAX_RAIN_LEAD_MINS) as i64 * 60;
+            let expected = minutely
+                .iter()
+                .any(|minute| minute.dt >= now && minute.dt <= until && minute.precipitation > 0.0);
+            let was_expected = store.rain_expected.insert(rule.id, expected);
+            expected && was_expected == Some(false)
+        }
+        _ => false,
+    }
+}
+
+// Whether the temperature crossed a threshold since the last forecast
+fn crosses_temperature(trigger: &Trigger, previous: Option<f64>, temperature: f64) -> bool {
+    match (trigger, previous) {
+        (Trigger::Temperature { above, below }, Some(previous)) => {
+            above.map(|t| previous <= t && temperature > t).unwrap_or(false)
+                || below.map(|t| previous >= t && temperature < t).unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+// Lowercased event names of alerts not seen before; only active alerts are
+// remembered, so the list stays small
+fn take_new_alerts(store: &mut RulesStore, alerts: &[crate::WeatherAlert]) -> Vec<String> {
+    let current: Vec<String> = alerts.iter().map(|alert| format!("{}@{}", alert.event, alert.start)).collect();
+    let new_alerts = alerts
+        .iter()
+        .zip(&current)
+        .filter(|(_, key)| !store.seen_alerts.contains(key))
+        .map(|(alert, _)| alert.event.to_lowercase())
+        .collect();
+    store.seen_alerts = current;
+    new_alerts
+}
+
+fn matches_alert(trigger: &Trigger, new_alerts: &[String]) -> bool {
+    match trigger {
+        Trigger::WeatherAlert { keyword } => new_alerts.iter().any(|event| {
+            keyword
+                .as_ref()
+                .map(|keyword| event.contains(&keyword.to_lowercase()))
+                .unwrap_or(true)
+        }),
+        _ => false,
+    }
+}
+
+async fn evaluate_weather(app_handle: &AppHandle) -> Result<(), String> {
+    let location = {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        let now = now_secs();
+        let wanted = store.rules.iter().any(|rule| rule.enabled && rule.trigger.is_weather());
+        if !wanted || now.saturating_sub(store.weather_checked_at) < WEATHER_INTERVAL_SECS {
+            return Ok(());
+        }
+        store.weather_checked_at = now;
+        store.location
+    };
+    let Some((latitude, longitude)) = location else {
+        return Ok(());
+    };
+    let forecast = crate::fetch_forecast(app_handle, latitude, longitude).await?;
+
+    let now = now_secs() as i64;
+    let temperature = forecast.temperature;
+    let previous_temperature = {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.temperature.replace(temperature)
+    };
+    let new_alerts = {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        take_new_alerts(&mut store, &forecast.alerts)
+    };
+
+    fire_matching(app_handle, |rule, store| {
+        rain_starts(rule, store, &forecast.minutely, now)
+            || crosses_temperature(&rule.trigger, previous_temperature, temperature)
+            || matches_alert(&rule.trigger, &new_alerts)
+    })?;
+
+    for alert in forecast.alerts.iter().filter(|alert| new_alerts.contains(&alert.event.to_lowercase())) {
+        let _ = app_handle.emit("rules://weather-alert", json!({ "event": alert.event, "description": alert.description }));
+    }
+    Ok(())
+}
+
+// Called for every item added to the notification digest
+pub fn on_notification(app_handle: &AppHandle, item: &DigestItem) {
+    // Items produced by rules never trigger rules, which would loop
+    if item.source == "rules" {
+        return;
+    }
+    let text = format!("{} {}", item.title, item.body).to_lowercase();
+    let _ = fire_matching(app_handle, |rule, _| match &rule.trigger {
+        Trigger::NotificationKeyword { keyword } => {
+            !keyword.is_empty() && text.contains(&keyword.to_lowercase())
+        }
+        _ => false,
+    });
+}
+
+fn matches_tag(trigger: &Trigger, tag_id: &str, records: &[String]) -> bool {
+    match trigger {
+        Trigger::NfcTag { tag_id: wanted_id, payload } => {
+            (wanted_id.is_some() || payload.is_some())
+                && wanted_id.as_ref().map(|id| id.eq_ignore_ascii_case(tag_id)).unwrap_or(true)
+                && payload.as_ref().map(|p| records.contains(p)).unwrap_or(true)
+        }
+        _ => false,
+    }
+}
+
+// Called when an NFC tag is read; returns how many rules fired
+pub fn on_nfc_tag(app_handle: &AppHandle, tag_id: &str, records: &[String]) -> Result<usize, String> {
+    fire_matching(app_handle, |rule, _| matches_tag(&rule.trigger, tag_id, records))
+}
+
+// The rules scheduler: time, battery, network and weather triggers are polled here
+pub fn spawn_scheduler(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let _ = evaluate_time(&app_handle);
+            let _ = evaluate_battery(&app_handle);
+            let _ = evaluate_network(&app_handle).await;
+            let _ = evaluate_weather(&app_handle).await;
+            tokio::time::sleep(SCHEDULER_TICK).await;
+        }
+    });
+}
+
+// The last location the frontend reported, if any
+pub fn last_location(app_handle: &AppHandle) -> Option<(f64, f64)> {
+    let state = app_handle.state::<RulesState>();
+    let store = state.lock().ok()?;
+    store.location
+}
+
+// Whether a location update crosses the rule's geofence in the direction it fires on
+fn crosses_geofence(rule: &Rule, store: &mut RulesStore, latitude: f64, longitude: f64) -> bool {
+    match &rule.trigger {
+        Trigger::Geofence { latitude: lat, longitude: lon, radius_m, on } => {
+            let inside = distance_m(latitude, longitude, *lat, *lon) <= *radius_m;
+            let was_inside = store.inside_geofence.insert(rule.id, inside);
+            match (was_inside, on) {
+                (Some(false), GeofenceEdge::Enter) => inside,
+                (Some(true), GeofenceEdge::Exit) => !inside,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+// Command the frontend calls with location updates to drive geofence and weather rules
+#[tauri::command]
+pub fn report_location(latitude: f64, longitude: f64, app_handle: AppHandle) -> Result<(), String> {
+    {
+        let state = app_handle.state::<RulesState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        // Weather triggers use the last reported location
+        store.location = Some((latitude, longitude));
+    }
+    fire_matching(&app_handle, |rule, store| crosses_geofence(rule, store, latitude, longitude))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_rules(state: State<'_, RulesState>) -> Result<Vec<Rule>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.rules.clone())
+}
+
+// Command to create a rule, or update it when the id already exists
+#[tauri::command]
+pub fn save_rule(rule: Rule, app_handle: AppHandle, state: State<'_, RulesState>) -> Result<Rule, String> {
+    if rule.actions.is_empty() {
+        return Err("A rule needs at least one action".to_string());
+    }
+
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let mut rule = rule;
+    let existing = store
+        .rules
+        .iter()
+        .position(|r| rule.id != 0 && r.id == rule.id);
+    match existing {
+        Some(index) => store.rules[index] = rule.clone(),
+        None => {
+            store.next_id += 1;
+            rule.id = store.next_id;
+            store.rules.push(rule.clone());
+        }
+    }
+    store.inside_geofence.remove(&rule.id);
+    storage::save_json(&app_handle, RULES_FILE, &*store)?;
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn delete_rule(id: u64, app_handle: AppHandle, state: State<'_, RulesState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.rules.retain(|rule| rule.id != id);
+    store.inside_geofence.remove(&id);
+    storage::save_json(&app_handle, RULES_FILE, &*store)
+}
+
+// Command to run a rule's actions immediately
+#[tauri::command]
+pub fn run_rule(id: u64, app_handle: AppHandle, state: State<'_, RulesState>) -> Result<(), String> {
+    let rule = {
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store
+            .rules
+            .iter()
+            .find(|rule| rule.id == id)
+            .cloned()
+            .ok_or("Rule not found".to_string())?
+    };
+    run_actions(&app_handle, &rule);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(json: serde_json::Value) -> Rule {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn distances_are_great_circle_meters() {
+        assert_eq!(distance_m(51.5, -0.12, 51.5, -0.12), 0.0);
+        // London to Paris is about 344 km
+        let london_paris = distance_m(51.5074, -0.1278, 48.8566, 2.3522);
+        assert!((london_paris - 343_500.0).abs() < 1_500.0, "{}", london_paris);
+    }
+
+    #[test]
+    fn rules_deserialize_with_defaults() {
+        let saved = rule(json!({
+            "name": "Rain",
+            "trigger": { "type": "rain_soon", "within_minutes": 15 },
+            "actions": [{ "type": "announce", "text": "Take an umbrella" }]
+        }));
+        assert_eq!(saved.id, 0);
+        assert!(saved.enabled);
+        assert!(saved.last_fired.is_none());
+        assert!(saved.trigger.is_weather());
+        assert!(!Trigger::Battery { below: 20 }.is_weather());
+    }
+
+    #[test]
+    fn geofences_fire_on_the_edge_they_watch() {
+        let home = |id: u64, on: &str| {
+            rule(json!({
+                "id": id,
+                "name": "Home",
+                "trigger": { "type": "geofence", "latitude": 51.5, "longitude": -0.12, "radius_m": 200.0, "on": on },
+                "actions": [{ "type": "scene", "name": "welcome" }]
+            }))
+        };
+        let (enter, exit) = (home(1, "enter"), home(2, "exit"));
+        let mut store = RulesStore::default();
+        let (away, here) = ((51.52, -0.12), (51.5005, -0.12));
+
+        // The first report only records where the user is
+        assert!(!crosses_geofence(&enter, &mut store, here.0, here.1));
+        assert!(!crosses_geofence(&enter, &mut store, away.0, away.1));
+        assert!(crosses_geofence(&enter, &mut store, here.0, here.1));
+        assert!(!crosses_geofence(&enter, &mut store, here.0, here.1));
+
+        assert!(!crosses_geofence(&exit, &mut store, here.0, here.1));
+        assert!(crosses_geofence(&exit, &mut store, away.0, away.1));
+    }
+
+    #[test]
+    fn nfc_tags_match_by_id_or_record() {
+        let records = vec!["plates://scene/morning".to_string()];
+        let by_id = Trigger::NfcTag { tag_id: Some("04A2".to_string()), payload: None };
+        let by_record = Trigger::NfcTag { tag_id: None, payload: Some(records[0].clone()) };
+        let by_both = Trigger::NfcTag { tag_id: Some("04a2".to_string()), payload: Some("other".to_string()) };
+        let any = Trigger::NfcTag { tag_id: None, payload: None };
+
+        assert!(matches_tag(&by_id, "04a2", &[]));
+        assert!(!matches_tag(&by_id, "ffff", &records));
+        assert!(matches_tag(&by_record, "ffff", &records));
+        assert!(!matches_tag(&by_both, "04A2", &records));
+        assert!(!matches_tag(&any, "04A2", &records));
+    }
+
+    #[test]
+    fn rain_fires_when_it_comes_within_the_lead() {
+        let soon = rule(json!({
+            "id": 1,
+            "name": "Rain",
+            "trigger": { "type": "rain_soon", "within_minutes": 240 },
+            "actions": []
+        }));
+        let rain_at = |dt: i64| vec![crate::MinutelyPrecipitation { dt, precipitation: 0.4 }];
+        let mut store = RulesStore::default();
+
+        // The first forecast only records the outlook
+        assert!(!rain_starts(&soon, &mut store, &rain_at(600), 0));
+        assert!(!rain_starts(&soon, &mut store, &[], 0));
+        // The lead is capped at an hour however far ahead the rule asks
+        assert!(!rain_starts(&soon, &mut store, &rain_at(61 * 60), 0));
+        assert!(rain_starts(&soon, &mut store, &rain_at(60 * 60), 0));
+        assert!(!rain_starts(&soon, &mut store, &rain_at(60), 0));
+    }
+
+    #[test]
+    fn temperatures_fire_on_crossing() {
+        let above = Trigger::Temperature { above: Some(80.0), below: None };
+        let below = Trigger::Temperature { above: None, below: Some(32.0) };
+
+        assert!(crosses_temperature(&above, Some(79.0), 81.0));
+        assert!(crosses_temperature(&above, Some(80.0), 80.5));
+        assert!(!crosses_temperature(&above, Some(81.0), 85.0));
+        assert!(!crosses_temperature(&above, Some(81.0), 70.0));
+        assert!(crosses_temperature(&below, Some(33.0), 31.0));
+        assert!(!crosses_temperature(&below, Some(30.0), 28.0));
+        assert!(!crosses_temperature(&below, Some(30.0), 40.0));
+        // Nothing to compare against on the first forecast
+        assert!(!crosses_temperature(&below, None, 20.0));
+    }
+
+    #[test]
+    fn alerts_fire_once_and_match_keywords() {
+        let alert = |event: &str, start: i64| crate::WeatherAlert {
+            event: event.to_string(),
+            start,
+            description: String::new(),
+        };
+        let mut store = RulesStore::default();
+
+        let new_alerts = take_new_alerts(&mut store, &[alert("Flood Warning", 100)]);
+        assert_eq!(new_alerts, ["flood warning"]);
+        assert!(matches_alert(&Trigger::WeatherAlert { keyword: None }, &new_alerts));
+        assert!(matches_alert(&Trigger::WeatherAlert { keyword: Some("FLOOD".to_string()) }, &new_alerts));
+        assert!(!matches_alert(&Trigger::WeatherAlert { keyword: Some("heat".to_string()) }, &new_alerts));
+
+        // Still active, so not new; a later alert of the same kind is
+        assert!(take_new_alerts(&mut store, &[alert("Flood Warning", 100)]).is_empty());
+        let reissued = take_new_alerts(&mut store, &[alert("Flood Warning", 100), alert("Flood Warning", 900)]);
+        assert_eq!(reissued, ["flood warning"]);
+        assert!(!matches_alert(&Trigger::WeatherAlert { keyword: None }, &[]));
+    }
+}
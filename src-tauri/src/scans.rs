@@ -0,0 +1,521 @@
+// Document scanning. A photo of a page is straightened (the page outline is
+// found automatically unless the UI passes its corners), cleaned up to black
+// and white, read by the engine and saved as a searchable PDF: each page image
+// with its text laid over it invisibly. Page images are kept under scans/ so
+// pages can be added to a scan later; scans.json holds the index.
+
+use chrono::{Local, TimeZone};
+use image::{codecs::jpeg::JpegEncoder, imageops, GrayImage, Luma};
+use imageproc::contours::{find_contours, BorderType};
+use imageproc::contrast::{adaptive_threshold, otsu_level, threshold, ThresholdType};
+use imageproc::filter::gaussian_blur_f32;
+use imageproc::geometric_transformations::{warp_into, Interpolation, Projection};
+use imageproc::geometry::{approximate_polygon_dp, arc_length};
+use imageproc::point::Point;
+use serde::{Serialize, Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+use tauri_plugin_opener::OpenerExt;
+
+use crate::engine::GeminiClient;
+use crate::storage;
+
+const SCANS_FILE: &str = "scans.json";
+// Longest side of the image the page outline is looked for in
+const DETECT_SIZE: u32 = 800;
+const MAX_PAGE_SIZE: u32 = 2000;
+// An outline must cover this share of the photo to be taken as the page
+const MIN_PAGE_AREA: f64 = 0.2;
+const JPEG_QUALITY: u8 = 80;
+// Pages are laid out A4 wide, in PDF points
+const PAGE_WIDTH_PT: f32 = 595.0;
+const PAGE_MARGIN_PT: f32 = 36.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Corner {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScanPage {
+    // Page image, relative to the app data directory
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Scan {
+    pub id: u64,
+    pub title: String,
+    pub created_at: u64,
+    pub pages: Vec<ScanPage>,
+    pub pdf_path: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ScanIndex {
+    next_id: u64,
+    scans: Vec<Scan>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn polygon_area(points: &[Point<i32>]) -> f64 {
+    let doubled: i64 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64)
+        .sum();
+    doubled.abs() as f64 / 2.0
+}
+
+// The largest four-sided outline of a bright region, in image coordinates
+fn detect_page(gray: &GrayImage) -> Option<[Corner; 4]> {
+    let scale = (DETECT_SIZE as f32 / gray.width().max(gray.height()) as f32).min(1.0);
+    let width = ((gray.width() as f32 * scale).round() as u32).max(1);
+    let height = ((gray.height() as f32 * scale).round() as u32).max(1);
+    let small = imageops::resize(gray, width, height, imageops::FilterType::Triangle);
+    let blurred = gaussian_blur_f32(&small, 2.0);
+    let mask = threshold(&blurred, otsu_level(&blurred), ThresholdType::Binary);
+    let min_area = MIN_PAGE_AREA * (width * height) as f64;
+
+    let (_, quad) = find_contours::<i32>(&mask)
+        .into_iter()
+        .filter(|contour| matches!(contour.border_type, BorderType::Outer))
+        .filter_map(|contour| {
+            let epsilon = 0.02 * arc_length(&contour.points, true);
+            let mut quad = approximate_polygon_dp(&contour.points, epsilon, true);
+            if quad.len() == 5 && quad.first() == quad.last() {
+                quad.pop();
+            }
+            (quad.len() == 4).then(|| (polygon_area(&quad), quad))
+        })
+        .filter(|(area, _)| *area >= min_area)
+        .max_by(|a, b| a.0.total_cmp(&b.0))?;
+    let corners: Vec<Corner> = quad
+        .iter()
+        .map(|point| Corner {
+            x: point.x as f32 / scale,
+            y: point.y as f32 / scale,
+        })
+        .collect();
+    corners.try_into().ok()
+}
+
+// Top-left, top-right, bottom-right, bottom-left
+fn order_corners(corners: [Corner; 4]) -> [Corner; 4] {
+    let pick = |key: &dyn Fn(&Corner) -> f32, largest: bool| {
+        let mut sorted = corners;
+        sorted.sort_by(|a, b| key(a).total_cmp(&key(b)));
+        if largest { sorted[3] } else { sorted[0] }
+    };
+    [
+        pick(&|c| c.x + c.y, false),
+        pick(&|c| c.x - c.y, true),
+        pick(&|c| c.x + c.y, true),
+        pick(&|c| c.x - c.y, false),
+    ]
+}
+
+fn distance(a: Corner, b: Corner) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+fn fit(gray: GrayImage) -> GrayImage {
+    let longest = gray.width().max(gray.height());
+    if longest <= MAX_PAGE_SIZE {
+        return gray;
+    }
+    let scale = MAX_PAGE_SIZE as f32 / longest as f32;
+    let width = ((gray.width() as f32 * scale).round() as u32).max(1);
+    let height = ((gray.height() as f32 * scale).round() as u32).max(1);
+    imageops::resize(&gray, width, height, imageops::FilterType::Triangle)
+}
+
+// Warp the page outline to an upright rectangle; the whole photo when no
+// outline is given or found
+fn straighten(gray: GrayImage, corners: Option<[Corner; 4]>) -> GrayImage {
+    let corners = corners.or_else(|| detect_page(&gray)).map(order_corners);
+    let Some([top_left, top_right, bottom_right, bottom_left]) = corners else {
+        return fit(gray);
+    };
+    let width = distance(top_left, top_right).max(distance(bottom_left, bottom_right));
+    let height = distance(top_left, bottom_left).max(distance(top_right, bottom_right));
+    let scale = (MAX_PAGE_SIZE as f32 / width.max(height)).min(1.0);
+    let (width, height) = ((width * scale).round().max(1.0), (height * scale).round().max(1.0));
+    let from = [top_left, top_right, bottom_right, bottom_left].map(|corner| (corner.x, corner.y));
+    let to = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    let Some(projection) = Projection::from_control_points(from, to) else {
+        return fit(gray);
+    };
+    let mut page = GrayImage::new(width as u32, height as u32);
+    warp_into(&gray, &projection, Interpolation::Bilinear, Luma([255]), &mut page);
+    page
+}
+
+// Straighten and binarize a photo into a page JPEG, returned with its size
+fn process(path: &str, corners: Option<[Corner; 4]>) -> Result<(Vec<u8>, u32, u32), String> {
+    let gray = image::open(path).map_err(|e| e.to_string())?.to_luma8();
+    let page = straighten(gray, corners);
+    // Adaptive, so shadows across the page don't turn into black patches
+    let page = adaptive_threshold(&page, (page.width() / 40).max(5));
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode_image(&page)
+        .map_err(|e| e.to_string())?;
+    Ok((jpeg, page.width(), page.height()))
+}
+
+// PDF string literal; characters outside Latin-1 can't be drawn with a
+// standard font and become '?'
+fn pdf_text(text: &str) -> Vec<u8> {
+    let mut bytes = vec![b'('];
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => bytes.extend([b'\\', c as u8]),
+            c if (' '..='\u{ff}').contains(&c) && !('\u{7f}'..'\u{a0}').contains(&c) => bytes.push(c as u32 as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes.push(b')');
+    bytes
+}
+
+// A PDF with one page per image and its text in render mode 3 (invisible), so
+// viewers can search and select it. The text isn't placed word by word over
+// the image, only line by line down the page.
+fn write_pdf(pages: &[(Vec<u8>, &ScanPage)]) -> Vec<u8> {
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    let kids: Vec<String> = (0..pages.len()).map(|index| format!("{} 0 R", 4 + index * 3)).collect();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).into_bytes());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec());
+
+    for (index, (jpeg, page)) in pages.iter().enumerate() {
+        let image_id = 5 + index * 3;
+        let contents_id = 6 + index * 3;
+        let width = PAGE_WIDTH_PT;
+        let height = PAGE_WIDTH_PT * page.height as f32 / page.width.max(1) as f32;
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                width, height, image_id, contents_id
+            )
+            .into_bytes(),
+        );
+
+        let mut image = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray \
+             /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+            page.width,
+            page.height,
+            jpeg.len()
+        )
+        .into_bytes();
+        image.extend_from_slice(jpeg);
+        image.extend_from_slice(b"\nendstream");
+        objects.push(image);
+
+        let lines: Vec<&str> = page.text.lines().collect();
+        let size = ((height - 2.0 * PAGE_MARGIN_PT) / lines.len().max(1) as f32).clamp(1.0, 12.0);
+        let mut content = format!("q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q\n", width, height).into_bytes();
+        content.extend(
+            format!(
+                "BT 3 Tr /F1 {:.2} Tf {:.2} TL {:.2} {:.2} Td\n",
+                size,
+                size,
+                PAGE_MARGIN_PT,
+                height - PAGE_MARGIN_PT
+            )
+            .into_bytes(),
+        );
+        for line in lines {
+            content.extend(pdf_text(line));
+            content.extend(b" Tj T*\n");
+        }
+        content.extend(b"ET");
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", index + 1).into_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .into_bytes(),
+    );
+    pdf
+}
+
+fn save_pdf(app_handle: &AppHandle, scan: &Scan) -> Result<(), String> {
+    let mut pages = Vec::with_capacity(scan.pages.len());
+    for page in &scan.pages {
+        let jpeg = std::fs::read(storage::data_file(app_handle, &page.image)?).map_err(|e| e.to_string())?;
+        pages.push((jpeg, page));
+    }
+    std::fs::write(&scan.pdf_path, write_pdf(&pages)).map_err(|e| e.to_string())
+}
+
+async fn read_text(app_handle: &AppHandle, jpeg: &[u8]) -> Result<String, String> {
+    GeminiClient::for_app(app_handle)?
+        .generate_with_image(
+            "Transcribe all text on this scanned page, keeping its line breaks. Reply with the text only.",
+            "image/jpeg",
+            jpeg,
+        )
+        .await
+}
+
+fn find_scan(index: &ScanIndex, id: u64) -> Result<&Scan, String> {
+    index
+        .scans
+        .iter()
+        .find(|scan| scan.id == id)
+        .ok_or("Scan not found".to_string())
+}
+
+// Scans whose title or text contains the query, newest first
+pub fn search(app_handle: &AppHandle, query: &str) -> Vec<Scan> {
+    let query = query.trim().to_lowercase();
+    let index: ScanIndex = storage::load_json(app_handle, SCANS_FILE);
+    let mut scans: Vec<Scan> = index
+        .scans
+        .into_iter()
+        .filter(|scan| {
+            query.is_empty()
+                || scan.title.to_lowercase().contains(&query)
+                || scan.pages.iter().any(|page| page.text.to_lowercase().contains(&query))
+        })
+        .collect();
+    scans.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    scans
+}
+
+// Command to scan a captured or imported photo of a page. `corners` are the
+// page's corners in the photo when the user adjusted them; with `scan_id` the
+// page is added to an existing scan.
+#[tauri::command]
+pub async fn scan_document(
+    path: String,
+    corners: Option<Vec<Corner>>,
+    title: Option<String>,
+    scan_id: Option<u64>,
+    app_handle: AppHandle,
+) -> Result<Scan, String> {
+    let corners = match corners {
+        Some(corners) => Some(<[Corner; 4]>::try_from(corners).map_err(|_| "A page needs exactly four corners".to_string())?),
+        None => None,
+    };
+    let (jpeg, width, height) = tauri::async_runtime::spawn_blocking(move || process(&path, corners))
+        .await
+        .map_err(|e| e.to_string())??;
+    // The page is kept even when it can't be read, e.g. while offline
+    let text = read_text(&app_handle, &jpeg).await.unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "scanned page not read");
+        String::new()
+    });
+
+    let title = title.map(|title| title.trim().to_string()).filter(|title| !title.is_empty());
+    let mut index: ScanIndex = storage::load_json(&app_handle, SCANS_FILE);
+    let position = match scan_id {
+        Some(id) => index
+            .scans
+            .iter()
+            .position(|scan| scan.id == id)
+            .ok_or("Scan not found".to_string())?,
+        None => {
+            index.next_id += 1;
+            let id = index.next_id;
+            let created_at = now_secs();
+            let title = title.clone().unwrap_or_else(|| {
+                text.lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(|line| line.chars().take(60).collect())
+                    .unwrap_or_else(|| {
+                        let date = Local
+                            .timestamp_opt(created_at as i64, 0)
+                            .single()
+                            .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+                            .unwrap_or_default();
+                        format!("Scan {}", date)
+                    })
+            });
+            let pdf_path = storage::data_file(&app_handle, &format!("scans/{}.pdf", id))?;
+            index.scans.push(Scan {
+                id,
+                title,
+                created_at,
+                pages: Vec::new(),
+                pdf_path: pdf_path.to_string_lossy().into_owned(),
+            });
+            index.scans.len() - 1
+        }
+    };
+
+    let scan = &mut index.scans[position];
+    if let Some(title) = title {
+        scan.title = title;
+    }
+    let image = format!("scans/{}/page-{}.jpg", scan.id, scan.pages.len() + 1);
+    std::fs::write(storage::data_file(&app_handle, &image)?, &jpeg).map_err(|e| e.to_string())?;
+    scan.pages.push(ScanPage {
+        image,
+        width,
+        height,
+        text,
+    });
+    let scan = scan.clone();
+    save_pdf(&app_handle, &scan)?;
+    storage::save_json(&app_handle, SCANS_FILE, &index)?;
+    Ok(scan)
+}
+
+// Command to list scans, newest first, optionally only those matching a query
+#[tauri::command]
+pub fn list_scans(query: Option<String>, app_handle: AppHandle) -> Vec<Scan> {
+    search(&app_handle, query.as_deref().unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn delete_scan(id: u64, app_handle: AppHandle) -> Result<(), String> {
+    let mut index: ScanIndex = storage::load_json(&app_handle, SCANS_FILE);
+    let scan = find_scan(&index, id)?.clone();
+    index.scans.retain(|scan| scan.id != id);
+    storage::save_json(&app_handle, SCANS_FILE, &index)?;
+    let _ = std::fs::remove_file(&scan.pdf_path);
+    let _ = std::fs::remove_dir_all(storage::data_file(&app_handle, &format!("scans/{}", id))?);
+    Ok(())
+}
+
+// Command to copy a scan's PDF to a path the user picked
+#[tauri::command]
+pub fn export_scan(id: u64, path: String, app_handle: AppHandle) -> Result<(), String> {
+    let index: ScanIndex = storage::load_json(&app_handle, SCANS_FILE);
+    let scan = find_scan(&index, id)?;
+    std::fs::copy(&scan.pdf_path, &path).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Command to open a scan's PDF in the system viewer, which offers sharing
+#[tauri::command]
+pub fn open_scan(id: u64, app_handle: AppHandle) -> Result<(), String> {
+    let index: ScanIndex = storage::load_json(&app_handle, SCANS_FILE);
+    let scan = find_scan(&index, id)?;
+    app_handle
+        .opener()
+        .open_path(scan.pdf_path.clone(), None::<&str>)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corner(x: f32, y: f32) -> Corner {
+        Corner { x, y }
+    }
+
+    fn near(a: Corner, b: Corner) -> bool {
+        distance(a, b) <= 2.0
+    }
+
+    #[test]
+    fn corners_are_ordered_clockwise_from_the_top_left() {
+        let ordered = order_corners([corner(90.0, 110.0), corner(10.0, 5.0), corner(5.0, 100.0), corner(100.0, 0.0)]);
+        let expected = [corner(10.0, 5.0), corner(100.0, 0.0), corner(90.0, 110.0), corner(5.0, 100.0)];
+        assert!(ordered.iter().zip(expected).all(|(a, b)| near(*a, b)));
+    }
+
+    #[test]
+    fn polygon_areas_ignore_winding() {
+        let square = [Point::new(0, 0), Point::new(10, 0), Point::new(10, 10), Point::new(0, 10)];
+        assert_eq!(polygon_area(&square), 100.0);
+        let mut reversed = square;
+        reversed.reverse();
+        assert_eq!(polygon_area(&reversed), 100.0);
+    }
+
+    #[test]
+    fn bright_pages_are_found_on_a_dark_background() {
+        let photo = GrayImage::from_fn(400, 300, |x, y| {
+            let page = (60..340).contains(&x) && (40..260).contains(&y);
+            Luma([if page { 230 } else { 30 }])
+        });
+        let corners = order_corners(detect_page(&photo).unwrap());
+        let expected = [corner(60.0, 40.0), corner(339.0, 40.0), corner(339.0, 259.0), corner(60.0, 259.0)];
+        assert!(corners.iter().zip(expected).all(|(a, b)| near(*a, b)), "{:?}", corners);
+
+        assert!(detect_page(&GrayImage::from_pixel(400, 300, Luma([30]))).is_none());
+    }
+
+    #[test]
+    fn pages_are_warped_to_the_outline_size() {
+        let photo = GrayImage::from_pixel(400, 300, Luma([200]));
+        let corners = [corner(250.0, 50.0), corner(50.0, 50.0), corner(50.0, 150.0), corner(250.0, 150.0)];
+        let page = straighten(photo, Some(corners));
+        assert_eq!((page.width(), page.height()), (200, 100));
+        let whole = straighten(GrayImage::from_pixel(4000, 1000, Luma([200])), None);
+        assert_eq!((whole.width(), whole.height()), (MAX_PAGE_SIZE, 500));
+    }
+
+    #[test]
+    fn pdf_strings_are_escaped_and_latin1() {
+        assert_eq!(pdf_text(r"a(b)\c"), br"(a\(b\)\\c)".to_vec());
+        assert_eq!(pdf_text("café ✓"), vec![b'(', b'c', b'a', b'f', 0xe9, b' ', b'?', b')']);
+    }
+
+    #[test]
+    fn pdf_cross_references_point_at_their_objects() {
+        let page = ScanPage {
+            image: "scans/1/page-1.jpg".to_string(),
+            width: 100,
+            height: 200,
+            text: "Hello (world)\nSecond line".to_string(),
+        };
+        // ASCII stand-in for the JPEG, so byte offsets survive the conversion to text
+        let pdf = write_pdf(&[(b"jpeg".to_vec(), &page)]);
+        let text = String::from_utf8(pdf).unwrap();
+        assert!(text.starts_with("%PDF-1.4\n") && text.ends_with("%%EOF\n"));
+        assert!(text.contains(r"(Hello \(world\)) Tj T*"));
+        assert!(text.contains("/MediaBox [0 0 595.00 1190.00]"));
+
+        let startxref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(text[startxref..].starts_with("xref\n0 7\n"));
+        let offsets: Vec<usize> = text[startxref..]
+            .lines()
+            .skip(3)
+            .take(6)
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+        for (index, offset) in offsets.into_iter().enumerate() {
+            assert!(text[offset..].starts_with(&format!("{} 0 obj\n", index + 1)));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::contacts::{ContactsState, DeviceContacts};
+use crate::notes::NotesState;
+
+// Days of calendar events considered by search
+const CALENDAR_WINDOW_DAYS: i64 = 30;
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SearchHit {
+    Contact { title: String, subtitle: Option<String>, id: String },
+    Note { title: String, subtitle: Option<String>, id: u64 },
+    Event { title: String, subtitle: Option<String>, uid: String, start: i64 },
+    File { title: String, subtitle: Option<String>, mount_id: u64, href: String },
+    Scan { title: String, subtitle: Option<String>, id: u64 },
+}
+
+fn search_notes(app_handle: &AppHandle, query: &str) -> Vec<SearchHit> {
+    let notes = crate::notes::list_notes(app_handle.state::<NotesState>()).unwrap_or_default();
+    notes
+        .into_iter()
+        .filter(|note| {
+            note.title.to_lowercase().contains(query) || note.body.to_lowercase().contains(query)
+        })
+        .map(|note| SearchHit::Note {
+            title: note.title,
+            subtitle: note.body.lines().next().map(|line| line.to_string()),
+            id: note.id,
+        })
+        .collect()
+}
+
+fn search_events(app_handle: &AppHandle, query: &str) -> Vec<SearchHit> {
+    crate::calendar::upcoming_events(app_handle, CALENDAR_WINDOW_DAYS)
+        .into_iter()
+        .filter(|event| event.summary.to_lowercase().contains(query))
+        .map(|event| SearchHit::Event {
+            title: event.summary,
+            subtitle: event.location,
+            uid: event.uid,
+            start: event.start,
+        })
+        .collect()
+}
+
+// Search contacts, notes, calendar events, indexed files and scans at once
+pub fn universal_search(app_handle: &AppHandle, query: &str) -> Vec<SearchHit> {
+    let lowered = query.trim().to_lowercase();
+    if lowered.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hits: Vec<SearchHit> = crate::contacts::search_contacts(
+        query.to_string(),
+        app_handle.state::<DeviceContacts>(),
+        app_handle.state::<ContactsState>(),
+    )
+    .unwrap_or_default()
+    .into_iter()
+    .map(|contact| SearchHit::Contact {
+        subtitle: contact.phones.first().or(contact.emails.first()).cloned(),
+        title: contact.name,
+        id: contact.id,
+    })
+    .collect();
+
+    hits.extend(search_notes(app_handle, &lowered));
+    hits.extend(search_events(app_handle, &lowered));
+    hits.extend(
+        crate::files::search(app_handle, query, 10)
+            .into_iter()
+            .map(|hit| SearchHit::File {
+                title: hit.entry.name,
+                subtitle: Some(hit.entry.path),
+                mount_id: hit.entry.mount_id,
+                href: hit.entry.href,
+            }),
+    );
+    hits.extend(crate::scans::search(app_handle, &lowered).into_iter().map(|scan| SearchHit::Scan {
+        subtitle: scan.pages.first().and_then(|page| page.text.lines().next()).map(|line| line.to_string()),
+        title: scan.title,
+        id: scan.id,
+    }));
+    hits
+}
+
+// Command behind the search bar and "find ..." voice queries
+#[tauri::command]
+pub fn search_everything(query: String, app_handle: AppHandle) -> Vec<SearchHit> {
+    universal_search(&app_handle, &query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_are_tagged_by_kind() {
+        let hit = SearchHit::File {
+            title: "tax.pdf".to_string(),
+            subtitle: Some("Taxes/tax.pdf".to_string()),
+            mount_id: 2,
+            href: "https://cloud.example.com/dav/Taxes/tax.pdf".to_string(),
+        };
+        let json = serde_json::to_value(&hit).unwrap();
+        assert_eq!(json["kind"], "file");
+        assert_eq!(json["mount_id"], 2);
+        assert_eq!(json["title"], "tax.pdf");
+
+        let note = SearchHit::Note {
+            title: "Groceries".to_string(),
+            subtitle: None,
+            id: 7,
+        };
+        let note = serde_json::to_value(&note).unwrap();
+        assert_eq!(note["kind"], "note");
+        assert!(note["subtitle"].is_null());
+    }
+}
@@ -0,0 +1,689 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::endpoints::Endpoint;
+use crate::storage;
+use crate::usage::Provider;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineProvider {
+    #[default]
+    Gemini,
+    Ollama,
+    OpenAi,
+    Anthropic,
+    // A downloaded model run in the app
+    OnDevice,
+    // Gemini or Ollama, whichever has been faster and healthy recently, when local models are enabled;
+    // the on-device model while offline
+    Auto,
+}
+
+// A child profile enforces output moderation on every response
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Adult,
+    Child,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OllamaSettings {
+    pub base_url: Option<String>,
+    // Model to use; the first installed model when unset
+    pub model: Option<String>,
+    // Answer with Ollama when the cloud provider fails
+    pub use_as_fallback: bool,
+}
+
+impl Default for OllamaSettings {
+    fn default() -> Self {
+        OllamaSettings {
+            base_url: None,
+            model: None,
+            use_as_fallback: true,
+        }
+    }
+}
+
+// How much of the context budget older turns get squeezed into the rolling summary
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionLevel {
+    Light,
+    #[default]
+    Balanced,
+    Aggressive,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ContextSettings {
+    // Estimated tokens of conversation history sent with each prompt
+    pub token_budget: usize,
+    pub compression: CompressionLevel,
+}
+
+impl Default for ContextSettings {
+    fn default() -> Self {
+        ContextSettings {
+            token_budget: 4000,
+            compression: CompressionLevel::default(),
+        }
+    }
+}
+
+// Self-hosted services found on the LAN or entered by hand
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ServiceSettings {
+    pub home_assistant_url: Option<String>,
+    pub searxng_url: Option<String>,
+    // OpenAI compatible transcription server, e.g. faster-whisper-server
+    pub whisper_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct EarconSettings {
+    pub enabled: bool,
+    // 0.0 to 1.0
+    pub volume: f32,
+    pub respect_do_not_disturb: bool,
+}
+
+impl Default for EarconSettings {
+    fn default() -> Self {
+        EarconSettings {
+            enabled: true,
+            volume: 0.5,
+            respect_do_not_disturb: true,
+        }
+    }
+}
+
+// Cleanup applied to recordings before transcription
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AudioProcessingSettings {
+    pub noise_suppression: bool,
+    pub gain_control: bool,
+    // RMS level, 0.0 to 1.0, that gain control brings speech towards
+    pub target_level: f32,
+}
+
+impl Default for AudioProcessingSettings {
+    fn default() -> Self {
+        AudioProcessingSettings {
+            noise_suppression: true,
+            gain_control: true,
+            target_level: 0.1,
+        }
+    }
+}
+
+// Voice activity detection: recording stops on its own once the speaker goes quiet
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VadConfig {
+    pub enabled: bool,
+    // RMS level, 0.0 to 1.0, below which audio counts as silence
+    pub threshold: f32,
+    // Silence after speech that ends the recording
+    pub silence_ms: u64,
+    // Speech needed first, so the recording doesn't end before the user starts talking
+    pub min_speech_ms: u64,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        VadConfig {
+            enabled: true,
+            threshold: 0.015,
+            silence_ms: 1500,
+            min_speech_ms: 300,
+        }
+    }
+}
+
+// Always-listening hotword detection, transcribed on device with the offline model
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WakeWordSettings {
+    pub enabled: bool,
+    pub phrase: String,
+    // 0.0 to 1.0; higher accepts quieter speech and looser matches
+    pub sensitivity: f32,
+}
+
+impl Default for WakeWordSettings {
+    fn default() -> Self {
+        WakeWordSettings {
+            enabled: false,
+            phrase: "hey plates".to_string(),
+            sensitivity: 0.5,
+        }
+    }
+}
+
+// How long on-device models stay loaded without being used
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MemorySettings {
+    // 0 keeps models loaded until memory pressure is reported
+    pub idle_unload_secs: u64,
+}
+
+impl Default for MemorySettings {
+    fn default() -> Self {
+        MemorySettings { idle_unload_secs: 300 }
+    }
+}
+
+// Cloud providers whose text has emails, phone numbers and addresses masked
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PrivacySettings {
+    pub redacted_providers: Vec<Provider>,
+}
+
+// Limits on the cached recordings; 0 means no limit
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AudioCacheSettings {
+    pub max_age_days: u64,
+    pub max_size_mb: u64,
+}
+
+impl Default for AudioCacheSettings {
+    fn default() -> Self {
+        AudioCacheSettings {
+            max_age_days: 7,
+            max_size_mb: 500,
+        }
+    }
+}
+
+// Spoken replies; backend and voice are chosen automatically when unset
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TtsSettings {
+    // Backend id from list_tts_backends
+    pub backend: Option<String>,
+    pub voice: Option<String>,
+    // Speaking speed, 1.0 being normal
+    pub rate: f32,
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        TtsSettings {
+            backend: None,
+            voice: None,
+            rate: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PersonaTone {
+    #[default]
+    Friendly,
+    Professional,
+    Playful,
+    Calm,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PersonaBrevity {
+    Brief,
+    #[default]
+    Balanced,
+    Detailed,
+}
+
+// Things the assistant may offer to do; web search also turns search grounding off
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PersonaAction {
+    WebSearch,
+    Timers,
+    Notes,
+    Calendar,
+    Expenses,
+    Recipes,
+    OpenLinks,
+}
+
+impl PersonaAction {
+    pub const ALL: [PersonaAction; 7] = [
+        PersonaAction::WebSearch,
+        PersonaAction::Timers,
+        PersonaAction::Notes,
+        PersonaAction::Calendar,
+        PersonaAction::Expenses,
+        PersonaAction::Recipes,
+        PersonaAction::OpenLinks,
+    ];
+}
+
+// How the assistant presents itself; sent as the system instruction with
+// every answer
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PersonaSettings {
+    // Id of the built-in preset this started from, if any
+    pub preset: Option<String>,
+    pub name: String,
+    pub tone: PersonaTone,
+    pub brevity: PersonaBrevity,
+    pub allowed_actions: Vec<PersonaAction>,
+    // Extra instructions written by the user
+    pub instructions: String,
+}
+
+impl Default for PersonaSettings {
+    fn default() -> Self {
+        PersonaSettings {
+            preset: Some("default".to_string()),
+            name: "Plates".to_string(),
+            tone: PersonaTone::Friendly,
+            brevity: PersonaBrevity::Balanced,
+            allowed_actions: PersonaAction::ALL.to_vec(),
+            instructions: String::new(),
+        }
+    }
+}
+
+// Gemini's blocking threshold, applied to every harm category
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyThreshold {
+    BlockNone,
+    BlockOnlyHigh,
+    BlockMediumAndAbove,
+    BlockLowAndAbove,
+}
+
+// Sampling parameters sent with chat prompts; each provider's own default when unset
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GenerationSettings {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+    pub safety_threshold: Option<SafetyThreshold>,
+}
+
+// Where spoken replies are played and recordings are taken from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioRoute {
+    #[default]
+    Speaker,
+    Earpiece,
+    Bluetooth,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AudioOutputSettings {
+    pub route: AudioRoute,
+    // Lower other apps' media while the assistant speaks or listens
+    pub duck_media: bool,
+}
+
+impl Default for AudioOutputSettings {
+    fn default() -> Self {
+        AudioOutputSettings {
+            route: AudioRoute::Speaker,
+            duck_media: true,
+        }
+    }
+}
+
+// Days each kind of personal data is kept; 0 keeps it forever
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RetentionSettings {
+    pub transcripts_days: u64,
+    pub conversations_days: u64,
+    pub location_days: u64,
+    pub logs_days: u64,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        RetentionSettings {
+            transcripts_days: 30,
+            conversations_days: 90,
+            location_days: 7,
+            logs_days: 14,
+        }
+    }
+}
+
+// Requests that may be in flight at once to each hosted provider
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ConcurrencyLimits {
+    pub gemini: usize,
+    pub open_ai: usize,
+    pub open_weather: usize,
+    pub deepgram: usize,
+    pub anthropic: usize,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        ConcurrencyLimits {
+            gemini: 4,
+            open_ai: 2,
+            open_weather: 4,
+            deepgram: 2,
+            anthropic: 2,
+        }
+    }
+}
+
+impl ConcurrencyLimits {
+    pub fn get(&self, endpoint: Endpoint) -> usize {
+        match endpoint {
+            Endpoint::Gemini => self.gemini,
+            Endpoint::OpenAi => self.open_ai,
+            Endpoint::OpenWeather => self.open_weather,
+            Endpoint::Deepgram => self.deepgram,
+            Endpoint::Anthropic => self.anthropic,
+        }
+    }
+
+    pub fn set(&mut self, endpoint: Endpoint, limit: usize) {
+        match endpoint {
+            Endpoint::Gemini => self.gemini = limit,
+            Endpoint::OpenAi => self.open_ai = limit,
+            Endpoint::OpenWeather => self.open_weather = limit,
+            Endpoint::Deepgram => self.deepgram = limit,
+            Endpoint::Anthropic => self.anthropic = limit,
+        }
+    }
+}
+
+// What to try when the transcription backend fails
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SttFallbackSettings {
+    // Backend ids tried in order after the active one
+    pub chain: Vec<String>,
+    // Retries per backend before moving on, with exponential backoff
+    pub retries: u32,
+    pub backoff_ms: u64,
+}
+
+impl Default for SttFallbackSettings {
+    fn default() -> Self {
+        SttFallbackSettings {
+            chain: Vec::new(),
+            retries: 2,
+            backoff_ms: 500,
+        }
+    }
+}
+
+// Deepgram live transcription; the API key is stored with the provider keys
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct DeepgramSettings {
+    pub model: String,
+    // Punctuation, casing and number formatting
+    pub smart_format: bool,
+}
+
+impl Default for DeepgramSettings {
+    fn default() -> Self {
+        DeepgramSettings {
+            model: "nova-2".to_string(),
+            smart_format: true,
+        }
+    }
+}
+
+// Hardware a local model runs on
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum InferenceBackend {
+    Cpu,
+    Metal,
+    Cuda,
+}
+
+// How hard local inference may work; normally follows the device's thermal state
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PerformanceMode {
+    Full,
+    Reduced,
+    Minimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PerformanceSettings {
+    // Fixed mode that ignores the thermal state
+    pub mode: Option<PerformanceMode>,
+    // Offline model used while throttled, if installed
+    pub reduced_model: String,
+}
+
+impl Default for PerformanceSettings {
+    fn default() -> Self {
+        PerformanceSettings {
+            mode: None,
+            reduced_model: "whisper-tiny-en-q80".to_string(),
+        }
+    }
+}
+
+// When background work such as prefetching is allowed to run
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PowerPolicy {
+    pub prefetch: bool,
+    pub require_charging: bool,
+    // Minimum battery percentage when not charging
+    pub min_battery: u8,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        PowerPolicy {
+            prefetch: true,
+            require_charging: true,
+            min_battery: 50,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AppSettings {
+    // Rewrite assistant responses so they read well with a screen reader
+    pub screen_reader_mode: bool,
+    pub engine_provider: EngineProvider,
+    // Model picked per chat provider id; Ollama's lives in its own settings
+    pub llm_models: HashMap<String, String>,
+    pub generation: GenerationSettings,
+    pub ollama: OllamaSettings,
+    pub profile: Profile,
+    // Also check responses with the OpenAI moderation endpoint when a key is available
+    pub use_moderation_api: bool,
+    pub context: ContextSettings,
+    pub power: PowerPolicy,
+    pub services: ServiceSettings,
+    // Microphone name from list_input_devices; the system default when unset
+    pub input_device: Option<String>,
+    pub earcons: EarconSettings,
+    pub vad: VadConfig,
+    pub audio_processing: AudioProcessingSettings,
+    pub audio_cache: AudioCacheSettings,
+    pub tts: TtsSettings,
+    pub audio_output: AudioOutputSettings,
+    pub persona: PersonaSettings,
+    pub wake_word: WakeWordSettings,
+    pub memory: MemorySettings,
+    pub concurrency: ConcurrencyLimits,
+    pub performance: PerformanceSettings,
+    // Backend pinned per local model id; the fastest available one otherwise
+    pub model_backends: HashMap<String, InferenceBackend>,
+    // Transcribe on device only, with the installed model below
+    pub offline_transcription: bool,
+    pub offline_model: String,
+    // ISO code of the spoken language; detected per transcription when unset
+    pub stt_language: Option<String>,
+    // Transcription backend id from list_stt_backends; chosen automatically when unset
+    pub stt_backend: Option<String>,
+    pub stt_fallback: SttFallbackSettings,
+    // Names and terms transcription is biased towards
+    pub stt_vocabulary: Vec<String>,
+    // Label segments by speaker on backends that support it
+    pub stt_diarization: bool,
+    pub deepgram: DeepgramSettings,
+    pub retention: RetentionSettings,
+    pub privacy: PrivacySettings,
+    // Label what photos show, on device, while indexing them for photo search
+    pub photo_captions: bool,
+    // Allow the debug console; turning this off stops a running console
+    pub developer_mode: bool,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            screen_reader_mode: false,
+            engine_provider: EngineProvider::default(),
+            llm_models: HashMap::new(),
+            generation: GenerationSettings::default(),
+            ollama: OllamaSettings::default(),
+            profile: Profile::default(),
+            use_moderation_api: true,
+            context: ContextSettings::default(),
+            power: PowerPolicy::default(),
+            services: ServiceSettings::default(),
+            input_device: None,
+            earcons: EarconSettings::default(),
+            vad: VadConfig::default(),
+            audio_processing: AudioProcessingSettings::default(),
+            audio_cache: AudioCacheSettings::default(),
+            tts: TtsSettings::default(),
+            audio_output: AudioOutputSettings::default(),
+            persona: PersonaSettings::default(),
+            wake_word: WakeWordSettings::default(),
+            memory: MemorySettings::default(),
+            concurrency: ConcurrencyLimits::default(),
+            performance: PerformanceSettings::default(),
+            model_backends: HashMap::new(),
+            offline_transcription: false,
+            offline_model: "whisper-tiny-en-q80".to_string(),
+            stt_language: None,
+            stt_backend: None,
+            stt_fallback: SttFallbackSettings::default(),
+            stt_vocabulary: Vec::new(),
+            stt_diarization: false,
+            deepgram: DeepgramSettings::default(),
+            retention: RetentionSettings::default(),
+            privacy: PrivacySettings::default(),
+            photo_captions: false,
+            developer_mode: false,
+        }
+    }
+}
+
+pub type SettingsState = Mutex<AppSettings>;
+
+pub fn load(app_handle: &AppHandle) -> AppSettings {
+    storage::load_json(app_handle, SETTINGS_FILE)
+}
+
+// Snapshot of the current settings for use outside a command
+pub fn current(state: &SettingsState) -> AppSettings {
+    state.lock().map(|s| s.clone()).unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<'_, SettingsState>) -> Result<AppSettings, String> {
+    let settings = state.lock().map_err(|e| e.to_string())?;
+    Ok(settings.clone())
+}
+
+#[tauri::command]
+pub fn update_settings(
+    settings: AppSettings,
+    app_handle: AppHandle,
+    state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    storage::save_json(&app_handle, SETTINGS_FILE, &settings)?;
+    if !settings.developer_mode {
+        crate::devtools::stop(&app_handle)?;
+        crate::endpoints::set_simulated_offline(false);
+        crate::faults::clear();
+    }
+    crate::limits::configure(&settings.concurrency);
+    let mut current = state.lock().map_err(|e| e.to_string())?;
+    *current = settings;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_saved_by_older_versions_get_defaults() {
+        let settings: AppSettings = serde_json::from_str(r#"{"screen_reader_mode":true}"#).unwrap();
+        assert!(settings.screen_reader_mode);
+        assert_eq!(settings.engine_provider, EngineProvider::Gemini);
+        assert_eq!(settings.profile, Profile::Adult);
+        assert!(settings.use_moderation_api);
+        assert_eq!(settings.offline_model, "whisper-tiny-en-q80");
+
+        // Nested sections fill in their own missing fields
+        let settings: AppSettings = serde_json::from_str(r#"{"vad":{"silence_ms":800}}"#).unwrap();
+        assert_eq!(settings.vad.silence_ms, 800);
+        assert!(settings.vad.enabled);
+        assert_eq!(settings.vad.min_speech_ms, 300);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = AppSettings {
+            engine_provider: EngineProvider::OnDevice,
+            profile: Profile::Child,
+            stt_vocabulary: vec!["Plates".to_string()],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&settings).unwrap();
+        assert_eq!(json["engine_provider"], "ondevice");
+        assert_eq!(json["profile"], "child");
+        let parsed: AppSettings = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.engine_provider, EngineProvider::OnDevice);
+        assert_eq!(parsed.stt_vocabulary, vec!["Plates"]);
+    }
+
+    #[test]
+    fn concurrency_limits_are_per_endpoint() {
+        let mut limits = ConcurrencyLimits::default();
+        assert_eq!(limits.get(Endpoint::Gemini), 4);
+        assert_eq!(limits.get(Endpoint::OpenAi), 2);
+        limits.set(Endpoint::Deepgram, 7);
+        assert_eq!(limits.get(Endpoint::Deepgram), 7);
+        assert_eq!(limits.get(Endpoint::Anthropic), 2);
+    }
+}
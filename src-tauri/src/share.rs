@@ -0,0 +1,317 @@
+use serde::{Serialize, Deserialize};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use url::{Host, Url};
+
+use crate::endpoints;
+use crate::engine::GeminiClient;
+use crate::untrusted;
+use crate::notes::{self, Note};
+
+// Longest page text we send off for summarization
+const MAX_PAGE_CHARS: usize = 20000;
+// Bigger pages are cut off here before they are converted to text
+const MAX_PAGE_BYTES: usize = 2 * 1024 * 1024;
+const PAGE_TIMEOUT: Duration = Duration::from_secs(15);
+// Redirects followed, each checked like the shared link itself
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SharedContent {
+    Text { text: String },
+    Url { url: String },
+    Image { path: String, mime_type: String },
+}
+
+#[derive(Serialize, Clone)]
+#[serde(tag = "screen", rename_all = "lowercase")]
+pub enum ShareResult {
+    Summary { url: String, summary: String },
+    Ocr { path: String, text: String },
+    Notes { note: Note },
+}
+
+// Shared text that is really just a link should be treated as a URL
+fn classify(content: SharedContent) -> SharedContent {
+    match content {
+        SharedContent::Text { text } => {
+            let trimmed = text.trim();
+            if !trimmed.contains(char::is_whitespace)
+                && (trimmed.starts_with("http://") || trimmed.starts_with("https://"))
+            {
+                SharedContent::Url { url: trimmed.to_string() }
+            } else {
+                SharedContent::Text { text }
+            }
+        }
+        other => other,
+    }
+}
+
+// Very small HTML to text conversion: drops tags, scripts and styles
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    let mut in_tag = false;
+    let mut skip_until: Option<&str> = None;
+    let lower = html.to_ascii_lowercase();
+    let mut i = 0;
+
+    while i < html.len() {
+        if let Some(end) = skip_until {
+            match lower[i..].find(end) {
+                Some(offset) => {
+                    i += offset + end.len();
+                    skip_until = None;
+                    in_tag = false;
+                }
+                None => break,
+            }
+            continue;
+        }
+
+        let c = html[i..].chars().next().unwrap_or(' ');
+        if c == '<' {
+            if lower[i..].starts_with("<script") {
+                skip_until = Some("</script>");
+            } else if lower[i..].starts_with("<style") {
+                skip_until = Some("</style>");
+            }
+            in_tag = true;
+        } else if c == '>' {
+            in_tag = false;
+            text.push(' ');
+        } else if !in_tag {
+            text.push(c);
+        }
+        i += c.len_utf8();
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Only web pages are fetched; other schemes could reach local files or apps
+fn page_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url.trim()).map_err(|e| e.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("Only web links can be summarized".to_string());
+    }
+    Ok(url)
+}
+
+// Whether an address is on the public internet rather than this device, the
+// local network or a link-local service such as cloud instance metadata
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                // Unique local fc00::/7 and link-local fe80::/10
+                !(ip.is_loopback() || ip.is_unspecified() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+// Resolve the page's host, refusing it when any of its addresses isn't public
+async fn public_addr(url: &Url) -> Result<SocketAddr, String> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|e| e.to_string())?
+            .collect(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        None => Vec::new(),
+    };
+    match addrs.first() {
+        Some(addr) if addrs.iter().all(|addr| is_public(addr.ip())) => Ok(*addr),
+        _ => Err("Links to local or private addresses can't be summarized".to_string()),
+    }
+}
+
+// The page's HTML, up to MAX_PAGE_BYTES. Redirects are followed by hand so
+// every hop is checked, and each request is pinned to the checked address.
+async fn fetch_page(mut url: Url) -> Result<String, String> {
+    endpoints::ensure_online()?;
+    let mut redirects = 0;
+    let mut response = loop {
+        let addr = public_addr(&url).await?;
+        let mut client = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+        if let Some(Host::Domain(domain)) = url.host() {
+            client = client.resolve(domain, addr);
+        }
+        let response = client
+            .build()
+            .map_err(|e| e.to_string())?
+            .get(url.clone())
+            .timeout(PAGE_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_redirection() {
+            break response.error_for_status().map_err(|e| e.to_string())?;
+        }
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            return Err("Too many redirects".to_string());
+        }
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or("Redirect without a location".to_string())?;
+        url = page_url(url.join(location).map_err(|e| e.to_string())?.as_str())?;
+    };
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_PAGE_BYTES {
+            body.truncate(MAX_PAGE_BYTES);
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+async fn summarize_url(app_handle: &AppHandle, url: &str) -> Result<String, String> {
+    let html = fetch_page(page_url(url)?).await?;
+    let page: String = html_to_text(&html).chars().take(MAX_PAGE_CHARS).collect();
+
+    let client = GeminiClient::for_app(app_handle)?;
+    client
+        .generate(&format!(
+            "Summarize the following web page in a few short paragraphs.\n\n{}",
+            untrusted::wrap("web page", &page)
+        ))
+        .await
+}
+
+// MIME type of a photo from its file name; JPEG when unknown
+pub fn image_mime_type(path: &str) -> &'static str {
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        _ => "image/jpeg",
+    }
+}
+
+pub async fn extract_image_text(app_handle: &AppHandle, path: &str, mime_type: &str) -> Result<String, String> {
+    let image = tokio::fs::read(path).await.map_err(|e| e.to_string())?;
+    let client = GeminiClient::for_app(app_handle)?;
+    client
+        .generate_with_image(
+            "Transcribe all text visible in this image. Reply with the text only.",
+            mime_type,
+            &image,
+        )
+        .await
+}
+
+// Command called when another app shares content with plates.
+// Routes it to the matching subsystem and emits `share://received` so the UI can open that screen.
+// The native share target that calls it is declared in the generated mobile
+// projects, which are not checked in.
+#[tauri::command]
+pub async fn on_shared_content(
+    content: SharedContent,
+    app_handle: AppHandle,
+) -> Result<ShareResult, String> {
+    let result = match classify(content) {
+        SharedContent::Url { url } => {
+            let summary = summarize_url(&app_handle, &url).await?;
+            ShareResult::Summary { url, summary }
+        }
+        SharedContent::Image { path, mime_type } => {
+            let text = extract_image_text(&app_handle, &path, &mime_type).await?;
+            ShareResult::Ocr { path, text }
+        }
+        SharedContent::Text { text } => {
+            let title = text.lines().next().unwrap_or_default().chars().take(60).collect();
+            let note = notes::add_note(&app_handle, title, text, Some("share".to_string()))?;
+            ShareResult::Notes { note }
+        }
+    };
+
+    app_handle
+        .emit("share://received", &result)
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn treats_a_bare_link_as_a_url() {
+        let shared = classify(SharedContent::Text {
+            text: " https://example.com/a ".to_string(),
+        });
+        assert!(matches!(shared, SharedContent::Url { url } if url == "https://example.com/a"));
+        let shared = classify(SharedContent::Text {
+            text: "see https://example.com".to_string(),
+        });
+        assert!(matches!(shared, SharedContent::Text { .. }));
+    }
+
+    #[test]
+    fn only_fetches_web_pages() {
+        assert!(page_url("https://example.com/story").is_ok());
+        assert!(page_url("file:///etc/passwd").is_err());
+        assert!(page_url("intent://scan#Intent;end").is_err());
+    }
+
+    #[test]
+    fn only_public_addresses_are_fetched() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert!(is_public(ip("93.184.216.34")));
+        assert!(is_public(ip("2606:2800:220:1::")));
+        for local in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip(local)), "{}", local);
+        }
+    }
+
+    #[tokio::test]
+    async fn local_hosts_are_refused_before_fetching() {
+        assert!(public_addr(&page_url("http://127.0.0.1:8080/").unwrap()).await.is_err());
+        assert!(public_addr(&page_url("http://[::1]/").unwrap()).await.is_err());
+        assert!(public_addr(&page_url("http://localhost/admin").unwrap()).await.is_err());
+        let addr = public_addr(&page_url("https://93.184.216.34/").unwrap()).await.unwrap();
+        assert_eq!(addr.port(), 443);
+    }
+
+    #[test]
+    fn html_to_text_drops_scripts_and_tags() {
+        let html = "<html><style>p{}</style><p>Hello <b>there</b></p><script>alert(1)</script>!</html>";
+        assert_eq!(html_to_text(html), "Hello there !");
+    }
+}
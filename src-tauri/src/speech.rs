@@ -0,0 +1,1419 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::SampleFormat;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::audio_cache;
+use crate::audio_focus::{self, FocusGuard};
+use crate::audio_processing;
+use crate::captions;
+use crate::consent::{self, ConsentScope};
+use crate::earcons::{self, EarconKind};
+use crate::endpoints::{self, Endpoint};
+use crate::faults;
+use crate::limits;
+use crate::profiler;
+use crate::provider_keys;
+use crate::settings::{self, SettingsState, VadConfig};
+use crate::stt;
+use crate::transcription_jobs;
+use crate::tts;
+use crate::usage::{self, Provider};
+use crate::thermal;
+use crate::whisper;
+
+// Sample rate expected by Whisper
+pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+// How often streaming mode transcribes newly captured audio
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(1500);
+// Minimum new audio before a partial transcription is attempted
+const PARTIAL_MIN_SECS: f32 = 2.0;
+// How often the silence detector looks at new audio, and the frame it measures
+const VAD_INTERVAL: Duration = Duration::from_millis(100);
+const VAD_FRAME_MS: u64 = 30;
+// How often captured audio is handed to live captions
+const CAPTION_FEED_INTERVAL: Duration = Duration::from_millis(250);
+// Level meter updates, about 15 per second
+const LEVEL_INTERVAL: Duration = Duration::from_millis(66);
+// Vocabulary caps; Whisper only reads the last 224 tokens of a prompt anyway
+const MAX_VOCABULARY: usize = 100;
+const MAX_PHRASE_CHARS: usize = 64;
+// Push-to-talk recordings end on their own after this long unless the caller
+// asks for less; a stuck button cannot record indefinitely
+const PTT_DEFAULT_MAX_SECS: u64 = 60;
+const PTT_MAX_SECS: u64 = 300;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TranscriptionResult {
+    pub text: String,
+    pub language: Option<String>,
+    // Timed segments when the backend provides them
+    #[serde(default)]
+    pub segments: Option<Vec<TranscriptSegment>>,
+}
+
+// Times are in seconds from the start of the audio
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TranscriptWord {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TranscriptSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    // Empty when only segment timing is known, as with on-device transcription
+    #[serde(default)]
+    pub words: Vec<TranscriptWord>,
+    // Who spoke, numbered from 0 in order of first appearance, when the
+    // backend diarized the recording
+    #[serde(default)]
+    pub speaker: Option<u32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct InputDevice {
+    pub name: String,
+    pub is_default: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Recording {
+    // 16 kHz mono WAV ready for Whisper
+    pub path: String,
+    pub duration_secs: f64,
+    // Transcript of the whole recording when it was started in streaming mode
+    pub transcript: Option<String>,
+}
+
+// Input level for VU meters, both 0.0 to 1.0
+#[derive(Serialize, Clone, Debug)]
+pub struct LevelEvent {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TranscriptEvent {
+    pub seq: u64,
+    pub text: String,
+}
+
+// Raw interleaved samples as delivered by the input device
+struct Captured {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+struct ActiveRecording {
+    stop: mpsc::Sender<()>,
+    thread: std::thread::JoinHandle<Result<Captured, String>>,
+    // Shared with the capture callback so streaming mode can read audio as it arrives
+    buffer: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+    partials: Option<tauri::async_runtime::JoinHandle<()>>,
+    vad: Option<tauri::async_runtime::JoinHandle<()>>,
+    meter: Option<tauri::async_runtime::JoinHandle<()>>,
+    // Set for push-to-talk sessions, which are transcribed when released
+    ptt_session: Option<u64>,
+    // Ends a push-to-talk session held past its maximum duration
+    ptt_guard: Option<tauri::async_runtime::JoinHandle<()>>,
+    // Keeps other media ducked until the recording is done with
+    focus: Option<FocusGuard>,
+}
+
+// Microphone capture for live captions, separate from any dictation
+struct CaptionCapture {
+    stop: mpsc::Sender<()>,
+    feed: tauri::async_runtime::JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct SpeechToTextService {
+    recording: Option<ActiveRecording>,
+    captions: Option<CaptionCapture>,
+    last_ptt_session: u64,
+}
+
+pub type SpeechState = Mutex<SpeechToTextService>;
+
+// verbose_json response; servers that ignore the format send only text
+#[derive(Deserialize)]
+struct WhisperResponse {
+    text: String,
+    #[serde(default)]
+    segments: Vec<WhisperSegment>,
+    #[serde(default)]
+    words: Vec<TranscriptWord>,
+    // Full name, such as "english"
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WhisperSegment {
+    start: f64,
+    end: f64,
+    text: String,
+}
+
+impl WhisperResponse {
+    // Words come back as one flat list; each goes to the segment it starts in.
+    // `requested` is the language asked for, reported when the server names none.
+    fn into_result(self, requested: Option<String>) -> TranscriptionResult {
+        let segments = if self.segments.is_empty() && self.words.is_empty() {
+            None
+        } else if self.segments.is_empty() {
+            Some(vec![TranscriptSegment {
+                start: self.words.first().map(|word| word.start).unwrap_or(0.0),
+                end: self.words.last().map(|word| word.end).unwrap_or(0.0),
+                text: self.text.trim().to_string(),
+                words: self.words,
+                speaker: None,
+            }])
+        } else {
+            let count = self.segments.len();
+            Some(
+                self.segments
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, segment)| TranscriptSegment {
+                        words: self
+                            .words
+                            .iter()
+                            .filter(|word| word.start >= segment.start && (word.start < segment.end || i + 1 == count))
+                            .cloned()
+                            .collect(),
+                        start: segment.start,
+                        end: segment.end,
+                        text: segment.text.trim().to_string(),
+                        speaker: None,
+                    })
+                    .collect(),
+            )
+        };
+        TranscriptionResult {
+            text: self.text.trim().to_string(),
+            language: self
+                .language
+                .as_deref()
+                .and_then(whisper::language_code)
+                .map(str::to_string)
+                .or(requested),
+            segments,
+        }
+    }
+}
+
+// Vocabulary as a Whisper prompt; prompts written in the style of the
+// expected transcript steer the model towards its spellings
+pub(crate) fn vocabulary_prompt(vocabulary: &[String]) -> Option<String> {
+    (!vocabulary.is_empty()).then(|| format!("{}.", vocabulary.join(", ")))
+}
+
+// Multipart request asking for segment and word timestamps; without a
+// language the server detects it
+fn transcription_form(
+    wav: Vec<u8>,
+    language: Option<&str>,
+    prompt: Option<&str>,
+) -> Result<reqwest::multipart::Form, String> {
+    let file = reqwest::multipart::Part::bytes(wav)
+        .file_name("audio.wav")
+        .mime_str("audio/wav")
+        .map_err(|e| e.to_string())?;
+    let form = reqwest::multipart::Form::new()
+        .part("file", file)
+        .text("model", "whisper-1")
+        .text("response_format", "verbose_json")
+        .text("timestamp_granularities[]", "segment")
+        .text("timestamp_granularities[]", "word");
+    let form = match language {
+        Some(language) => form.text("language", language.to_string()),
+        None => form,
+    };
+    Ok(match prompt {
+        Some(prompt) => form.text("prompt", prompt.to_string()),
+        None => form,
+    })
+}
+
+// Encode mono 16-bit PCM samples as an in-memory WAV file
+pub fn encode_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    // Sizes saturate for audio too long for a WAV header to describe
+    let data_len = u32::try_from(samples.len().saturating_mul(2)).unwrap_or(u32::MAX);
+    let mut wav = Vec::with_capacity(44 + samples.len() * 2);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&data_len.saturating_add(36).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.saturating_mul(2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+// Length of a PCM WAV file in seconds, read from its header
+pub(crate) fn wav_duration_secs(wav: &[u8]) -> f64 {
+    if wav.len() <= 44 {
+        return 0.0;
+    }
+    let byte_rate = u32::from_le_bytes([wav[28], wav[29], wav[30], wav[31]]);
+    if byte_rate == 0 {
+        return 0.0;
+    }
+    (wav.len() - 44) as f64 / byte_rate as f64
+}
+
+// Send a WAV file to the OpenAI Whisper API
+pub async fn transcribe_with_whisper_api(app_handle: &AppHandle, wav: Vec<u8>) -> Result<TranscriptionResult, String> {
+    consent::require(app_handle, ConsentScope::OpenAiAudio)?;
+    let duration = wav_duration_secs(&wav);
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let language = stt::language(&app_settings);
+    let prompt = vocabulary_prompt(&app_settings.stt_vocabulary);
+    let _permit = limits::acquire(Endpoint::OpenAi).await?;
+    let client = reqwest::Client::new();
+
+    // Rotate to the next stored key when one is rejected or rate limited
+    let response = loop {
+        let key = provider_keys::select_key(app_handle, Provider::Whisper)?;
+        let form = transcription_form(wav.clone(), language.as_deref(), prompt.as_deref())?;
+
+        let response = client
+            .post(format!("{}/v1/audio/transcriptions", endpoints::base_url(Endpoint::OpenAi)))
+            .bearer_auth(&key.secret)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status().as_u16();
+        provider_keys::report(app_handle, &key.id, status);
+        if provider_keys::should_rotate(status) && key.id != provider_keys::ENV_KEY_ID {
+            continue;
+        }
+        if !response.status().is_success() {
+            return Err(format!("Whisper API error: {}", response.status()));
+        }
+        break response;
+    };
+
+    let whisper: WhisperResponse = faults::json(Endpoint::OpenAi, response).await?;
+    usage::record(app_handle, Provider::Whisper, "whisper-1", 0, 0, duration);
+    Ok(whisper.into_result(language))
+}
+
+// Send a WAV file to a self-hosted OpenAI compatible server such as faster-whisper-server
+pub(crate) async fn transcribe_with_server(
+    base_url: &str,
+    wav: Vec<u8>,
+    language: Option<String>,
+    prompt: Option<String>,
+) -> Result<TranscriptionResult, String> {
+    let form = transcription_form(wav, language.as_deref(), prompt.as_deref())?;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/audio/transcriptions", base_url.trim_end_matches('/')))
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Whisper server error: {}", response.status()));
+    }
+
+    let whisper: WhisperResponse = response.json().await.map_err(|e| e.to_string())?;
+    Ok(whisper.into_result(language))
+}
+
+// Read mono or multichannel 16-bit PCM from a WAV file, downmixed to mono f32
+pub(crate) fn decode_wav(wav: &[u8]) -> Result<(Vec<f32>, u32), String> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err("Not a WAV file".to_string());
+    }
+    let mut format: Option<(u16, u32, u16)> = None;
+    let mut offset = 12;
+    while offset + 8 <= wav.len() {
+        let id = &wav[offset..offset + 4];
+        let len = u32::from_le_bytes([wav[offset + 4], wav[offset + 5], wav[offset + 6], wav[offset + 7]]) as usize;
+        let body = &wav[offset + 8..(offset + 8 + len).min(wav.len())];
+        if id == b"fmt " && body.len() >= 16 {
+            let channels = u16::from_le_bytes([body[2], body[3]]);
+            let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+            let bits = u16::from_le_bytes([body[14], body[15]]);
+            format = Some((channels, sample_rate, bits));
+        } else if id == b"data" {
+            let (channels, sample_rate, bits) = format.ok_or("WAV data before format".to_string())?;
+            if bits != 16 {
+                return Err("Only 16-bit PCM WAV files are supported".to_string());
+            }
+            let samples: Vec<f32> = body
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                .collect();
+            let channels = channels.max(1) as usize;
+            let mono = samples
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+                .collect();
+            return Ok((mono, sample_rate));
+        }
+        // Chunks are padded to an even length
+        offset += 8 + len + len % 2;
+    }
+    Err("WAV file has no audio data".to_string())
+}
+
+// Decode any supported audio file (MP3, AAC/M4A, FLAC, Ogg Vorbis, WAV) to mono f32
+fn decode_audio_file(bytes: Vec<u8>, extension: Option<String>) -> Result<(Vec<f32>, u32), String> {
+    let source = MediaSourceStream::new(Box::new(std::io::Cursor::new(bytes)), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = &extension {
+        hint.with_extension(extension);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| format!("Unsupported audio file: {}", e))?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or("File has no audio track".to_string())?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| format!("Unsupported audio codec: {}", e))?;
+
+    let mut mono = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.to_string()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Skip a damaged packet rather than failing the whole file
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+        let spec = *decoded.spec();
+        sample_rate.get_or_insert(spec.rate);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        let channels = spec.channels.count().max(1);
+        mono.extend(
+            buffer
+                .samples()
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32),
+        );
+    }
+    Ok((mono, sample_rate.ok_or("Unknown sample rate".to_string())?))
+}
+
+// Transcribe mono samples with the installed Whisper model; never touches the network
+pub async fn transcribe_with_whisper_offline(
+    app_handle: &AppHandle,
+    samples: &[f32],
+    sample_rate: u32,
+) -> Result<TranscriptionResult, String> {
+    let model = thermal::offline_model(app_handle);
+    let pcm = resample(samples, sample_rate, WHISPER_SAMPLE_RATE);
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = vocabulary_prompt(&app_settings.stt_vocabulary);
+    let transcription = whisper::transcribe(
+        app_handle,
+        &model,
+        pcm,
+        thermal::inference_threads(app_handle),
+        stt::language(&app_settings),
+        prompt,
+    )
+    .await?;
+    Ok(TranscriptionResult {
+        text: whisper::join(&transcription.segments),
+        language: transcription.language,
+        segments: Some(
+            transcription
+                .segments
+                .into_iter()
+                .map(|segment| TranscriptSegment {
+                    start: segment.start,
+                    end: segment.end,
+                    text: segment.text,
+                    words: Vec::new(),
+                    speaker: None,
+                })
+                .collect(),
+        ),
+    })
+}
+
+// Transcribe with the active backend and its fallbacks; see stt::transcribe
+pub(crate) async fn transcribe_wav(app_handle: &AppHandle, wav: Vec<u8>) -> Result<TranscriptionResult, String> {
+    let started = Instant::now();
+    let result = stt::transcribe(app_handle, wav).await;
+    if result.is_ok() {
+        profiler::record_first(app_handle, "first_transcription", started.elapsed());
+    }
+    result
+}
+
+// Transcribe raw PCM samples, optionally refusing to leave the device
+pub async fn transcribe_samples(
+    app_handle: &AppHandle,
+    samples: Vec<i16>,
+    sample_rate: u32,
+    local_only: bool,
+) -> Result<TranscriptionResult, String> {
+    if local_only {
+        let samples: Vec<f32> = samples.iter().map(|s| *s as f32 / 32768.0).collect();
+        return transcribe_with_whisper_offline(app_handle, &samples, sample_rate).await;
+    }
+    transcribe_wav(app_handle, encode_wav(&samples, sample_rate)).await
+}
+
+// Transcribe an audio file from disk. It is decoded and converted to the
+// 16 kHz mono WAV every backend accepts; long recordings are transcribed in
+// resumable chunks.
+#[tauri::command]
+pub async fn transcribe_audio(path: String, app_handle: AppHandle) -> Result<TranscriptionResult, String> {
+    let bytes = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+    let job = transcription_jobs::job_id(&bytes);
+    let (samples, sample_rate) = match decode_wav(&bytes) {
+        Ok(decoded) => decoded,
+        Err(_) => {
+            let extension = Path::new(&path)
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase());
+            tauri::async_runtime::spawn_blocking(move || decode_audio_file(bytes, extension))
+                .await
+                .map_err(|e| e.to_string())??
+        }
+    };
+    let pcm = to_whisper_pcm(&samples, sample_rate, 1);
+    if pcm.len() as f64 / WHISPER_SAMPLE_RATE as f64 > transcription_jobs::CHUNK_THRESHOLD_SECS {
+        return transcription_jobs::transcribe(&app_handle, &job, &pcm).await;
+    }
+    transcribe_wav(&app_handle, encode_wav(&pcm, WHISPER_SAMPLE_RATE)).await
+}
+
+pub(crate) fn find_input_device(name: Option<&str>) -> Result<cpal::Device, String> {
+    let host = cpal::default_host();
+    match name {
+        Some(name) => host
+            .input_devices()
+            .map_err(|e| e.to_string())?
+            .find(|device| device.name().map(|n| n == name).unwrap_or(false))
+            .ok_or(format!("Input device not found: {}", name)),
+        None => host.default_input_device().ok_or("No microphone available".to_string()),
+    }
+}
+
+// Open an input stream that appends samples, converted to f32, to `buffer`
+pub(crate) fn open_stream(
+    app_handle: &AppHandle,
+    device: &cpal::Device,
+    buffer: Arc<Mutex<Vec<f32>>>,
+) -> Result<(cpal::Stream, u32, u16), String> {
+    let config = device.default_input_config().map_err(|e| e.to_string())?;
+    let sample_rate = config.sample_rate().0;
+    let channels = config.channels();
+    let format = config.sample_format();
+
+    let handle = app_handle.clone();
+    let on_error = move |e: cpal::StreamError| {
+        let _ = handle.emit("stt://error", e.to_string());
+    };
+    let stream = match format {
+        SampleFormat::F32 => device.build_input_stream(
+            &config.into(),
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.extend_from_slice(data);
+                }
+            },
+            on_error,
+            None,
+        ),
+        SampleFormat::I16 => device.build_input_stream(
+            &config.into(),
+            move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.extend(data.iter().map(|s| *s as f32 / i16::MAX as f32));
+                }
+            },
+            on_error,
+            None,
+        ),
+        SampleFormat::U16 => device.build_input_stream(
+            &config.into(),
+            move |data: &[u16], _: &cpal::InputCallbackInfo| {
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.extend(data.iter().map(|s| (*s as f32 - 32768.0) / 32768.0));
+                }
+            },
+            on_error,
+            None,
+        ),
+        other => return Err(format!("Unsupported sample format: {:?}", other)),
+    }
+    .map_err(|e| e.to_string())?;
+    stream.play().map_err(|e| e.to_string())?;
+    Ok((stream, sample_rate, channels))
+}
+
+// cpal streams are not Send, so each recording owns a capture thread that
+// keeps the stream alive until told to stop
+fn spawn_capture(app_handle: &AppHandle, device_name: Option<String>) -> Result<ActiveRecording, String> {
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(u32, u16), String>>();
+    let handle = app_handle.clone();
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let shared = buffer.clone();
+
+    let thread = std::thread::spawn(move || {
+        let opened = find_input_device(device_name.as_deref())
+            .and_then(|device| open_stream(&handle, &device, buffer.clone()));
+        let (stream, sample_rate, channels) = match opened {
+            Ok(opened) => {
+                let _ = ready_tx.send(Ok((opened.1, opened.2)));
+                opened
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e.clone()));
+                return Err(e);
+            }
+        };
+
+        // Either an explicit stop or the service being dropped ends the recording
+        let _ = stop_rx.recv();
+        drop(stream);
+        let samples = std::mem::take(&mut *buffer.lock().map_err(|e| e.to_string())?);
+        Ok(Captured {
+            samples,
+            sample_rate,
+            channels,
+        })
+    });
+
+    let (sample_rate, channels) = ready_rx
+        .recv()
+        .map_err(|_| "Audio capture thread exited".to_string())??;
+    Ok(ActiveRecording {
+        stop: stop_tx,
+        thread,
+        buffer: shared,
+        sample_rate,
+        channels,
+        partials: None,
+        vad: None,
+        meter: None,
+        ptt_session: None,
+        ptt_guard: None,
+        focus: None,
+    })
+}
+
+// Streaming mode: transcribe audio as it is captured and emit the running
+// transcript as `stt://partial`
+fn spawn_partials(
+    app_handle: &AppHandle,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+) -> tauri::async_runtime::JoinHandle<()> {
+    let handle = app_handle.clone();
+    let min_new = (sample_rate as f32 * channels.max(1) as f32 * PARTIAL_MIN_SECS) as usize;
+    tauri::async_runtime::spawn(async move {
+        let mut consumed = 0;
+        let mut transcript = String::new();
+        let mut seq = 0;
+        loop {
+            tokio::time::sleep(PARTIAL_INTERVAL).await;
+            let chunk = {
+                let Ok(buffer) = buffer.lock() else {
+                    return;
+                };
+                // Stay on frame boundaries so channels don't swap
+                let end = buffer.len() - buffer.len() % channels.max(1) as usize;
+                if end.saturating_sub(consumed) < min_new {
+                    continue;
+                }
+                let chunk = buffer[consumed..end].to_vec();
+                consumed = end;
+                chunk
+            };
+
+            let pcm = captured_to_whisper_pcm(&handle, &chunk, sample_rate, channels);
+            let Ok(result) = transcribe_samples(&handle, pcm, WHISPER_SAMPLE_RATE, false).await else {
+                continue;
+            };
+            if result.text.is_empty() {
+                continue;
+            }
+            if !transcript.is_empty() {
+                transcript.push(' ');
+            }
+            transcript.push_str(&result.text);
+            seq += 1;
+            let _ = handle.emit("stt://partial", TranscriptEvent { seq, text: transcript.clone() });
+        }
+    })
+}
+
+// Hand captured audio to live captions as it arrives. The buffer is drained
+// as it goes, so a long captioning session does not keep its audio.
+fn spawn_caption_feed(
+    app_handle: &AppHandle,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+) -> tauri::async_runtime::JoinHandle<()> {
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(CAPTION_FEED_INTERVAL).await;
+            let chunk: Vec<f32> = {
+                let Ok(mut buffer) = buffer.lock() else {
+                    return;
+                };
+                // Stay on frame boundaries so channels don't swap
+                let end = buffer.len() - buffer.len() % channels.max(1) as usize;
+                buffer.drain(..end).collect()
+            };
+            if chunk.is_empty() {
+                continue;
+            }
+            let pcm = captured_to_whisper_pcm(&handle, &chunk, sample_rate, channels);
+            if let Err(e) = captions::feed(&handle, &pcm, WHISPER_SAMPLE_RATE).await {
+                tracing::debug!(error = %e, "caption transcription failed");
+            }
+        }
+    })
+}
+
+// Open the microphone for live captions; does nothing if it is already open
+pub(crate) fn start_caption_capture(app_handle: &AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<SpeechState>();
+    let mut service = state.lock().map_err(|e| e.to_string())?;
+    if service.captions.is_some() {
+        return Ok(());
+    }
+    let device = settings::current(&app_handle.state::<SettingsState>()).input_device;
+    let recording = spawn_capture(app_handle, device)?;
+    let feed = spawn_caption_feed(app_handle, recording.buffer, recording.sample_rate, recording.channels);
+    // The capture thread ends by itself once told to stop
+    service.captions = Some(CaptionCapture {
+        stop: recording.stop,
+        feed,
+    });
+    Ok(())
+}
+
+pub(crate) fn stop_caption_capture(app_handle: &AppHandle) {
+    let state = app_handle.state::<SpeechState>();
+    let Ok(mut service) = state.lock() else {
+        return;
+    };
+    if let Some(capture) = service.captions.take() {
+        capture.feed.abort();
+        let _ = capture.stop.send(());
+    }
+}
+
+pub(crate) fn rms(frame: &[f32]) -> f32 {
+    if frame.is_empty() {
+        return 0.0;
+    }
+    (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt()
+}
+
+// Emit `stt://level` with the level of the audio captured since the last update
+fn spawn_meter(app_handle: &AppHandle, buffer: Arc<Mutex<Vec<f32>>>) -> tauri::async_runtime::JoinHandle<()> {
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut consumed = 0;
+        loop {
+            tokio::time::sleep(LEVEL_INTERVAL).await;
+            let level = {
+                let Ok(buffer) = buffer.lock() else {
+                    return;
+                };
+                let recent = &buffer[consumed.min(buffer.len())..];
+                consumed = buffer.len();
+                LevelEvent {
+                    rms: rms(recent).min(1.0),
+                    peak: recent.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())).min(1.0),
+                }
+            };
+            let _ = handle.emit("stt://level", level);
+        }
+    })
+}
+
+// Speech and trailing silence heard so far by the silence detector
+#[derive(Default)]
+struct VadState {
+    speech_ms: u64,
+    silence_ms: u64,
+}
+
+// Feed frame levels to the detector; true once enough speech has been followed
+// by enough silence. Silence only counts after `min_speech_ms` of speech.
+fn vad_step(state: &mut VadState, levels: &[f32], config: &VadConfig) -> bool {
+    for &level in levels {
+        if level >= config.threshold {
+            state.speech_ms += VAD_FRAME_MS;
+            state.silence_ms = 0;
+        } else if state.speech_ms >= config.min_speech_ms {
+            state.silence_ms += VAD_FRAME_MS;
+        }
+    }
+    state.silence_ms >= config.silence_ms
+}
+
+// Energy based voice activity detection. Once enough speech has been heard,
+// a long enough run of quiet frames emits `stt://silence-detected` and stops
+// the recording; the result arrives as `stt://auto-stopped`.
+fn spawn_vad(
+    app_handle: &AppHandle,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+    config: VadConfig,
+) -> tauri::async_runtime::JoinHandle<()> {
+    let handle = app_handle.clone();
+    let frame_len = ((sample_rate as u64 * VAD_FRAME_MS / 1000) as usize * channels.max(1) as usize).max(1);
+    tauri::async_runtime::spawn(async move {
+        let mut consumed = 0;
+        let mut vad = VadState::default();
+        loop {
+            tokio::time::sleep(VAD_INTERVAL).await;
+            let levels: Vec<f32> = {
+                let Ok(buffer) = buffer.lock() else {
+                    return;
+                };
+                let frames = (buffer.len() - consumed) / frame_len;
+                let levels = buffer[consumed..consumed + frames * frame_len]
+                    .chunks(frame_len)
+                    .map(rms)
+                    .collect();
+                consumed += frames * frame_len;
+                levels
+            };
+            if !vad_step(&mut vad, &levels, &config) {
+                continue;
+            }
+
+            let _ = handle.emit("stt://silence-detected", vad.silence_ms);
+            let recording = {
+                let state = handle.state::<SpeechState>();
+                let Ok(mut service) = state.lock() else {
+                    return;
+                };
+                service.recording.take()
+            };
+            if let Some(mut recording) = recording {
+                // Detach rather than abort: this task is the one finishing the recording
+                recording.vad = None;
+                let result = complete_recording(&handle, recording).await;
+                let _ = match result {
+                    Ok(recording) => handle.emit("stt://auto-stopped", recording),
+                    Err(e) => handle.emit("stt://error", e),
+                };
+            }
+            return;
+        }
+    })
+}
+
+// Resample with linear interpolation, averaging over each output step when downsampling
+pub(crate) fn resample(input: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || input.is_empty() || from == 0 {
+        return input.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    let len = (input.len() as f64 / step) as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let index = pos as usize;
+            if step > 1.0 {
+                let end = ((pos + step) as usize).clamp(index + 1, input.len());
+                input[index..end].iter().sum::<f32>() / (end - index) as f32
+            } else {
+                let frac = (pos - index as f64) as f32;
+                let a = input[index];
+                let b = input.get(index + 1).copied().unwrap_or(a);
+                a + (b - a) * frac
+            }
+        })
+        .collect()
+}
+
+fn downmix(samples: &[f32], channels: u16) -> Vec<f32> {
+    let channels = channels.max(1) as usize;
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+fn to_pcm16(mono: &[f32], sample_rate: u32) -> Vec<i16> {
+    resample(mono, sample_rate, WHISPER_SAMPLE_RATE)
+        .into_iter()
+        .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+        .collect()
+}
+
+// Downmix interleaved samples to mono and convert to 16 kHz 16-bit PCM
+pub(crate) fn to_whisper_pcm(samples: &[f32], sample_rate: u32, channels: u16) -> Vec<i16> {
+    to_pcm16(&downmix(samples, channels), sample_rate)
+}
+
+// As to_whisper_pcm, with the noise suppression and gain control enabled in settings
+fn captured_to_whisper_pcm(app_handle: &AppHandle, samples: &[f32], sample_rate: u32, channels: u16) -> Vec<i16> {
+    let config = settings::current(&app_handle.state::<SettingsState>()).audio_processing;
+    let (mono, rate) = audio_processing::process(downmix(samples, channels), sample_rate, &config);
+    to_pcm16(&mono, rate)
+}
+
+// How long a push-to-talk session may be held, from the caller's `max_secs`
+fn ptt_max_duration(max_secs: Option<u64>) -> Result<Duration, String> {
+    let max_secs = max_secs.unwrap_or(PTT_DEFAULT_MAX_SECS);
+    if !(1..=PTT_MAX_SECS).contains(&max_secs) {
+        return Err(format!("Push-to-talk can last between 1 and {} seconds", PTT_MAX_SECS));
+    }
+    Ok(Duration::from_secs(max_secs))
+}
+
+// Take the recording if it belongs to push-to-talk `session`. A session that
+// timed out has already been transcribed, so neither its guard nor its release
+// may stop whatever came after it.
+fn take_ptt_recording(service: &mut SpeechToTextService, session: u64) -> Option<ActiveRecording> {
+    let current = service.recording.as_ref().and_then(|recording| recording.ptt_session);
+    (current == Some(session)).then(|| service.recording.take()).flatten()
+}
+
+// End a push-to-talk session once it has been held for the maximum duration;
+// the transcribed result arrives as `ptt://timeout`
+fn spawn_ptt_guard(app_handle: &AppHandle, session: u64, max: Duration) -> tauri::async_runtime::JoinHandle<()> {
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(max).await;
+        let recording = {
+            let state = handle.state::<SpeechState>();
+            let Ok(mut service) = state.lock() else {
+                return;
+            };
+            take_ptt_recording(&mut service, session)
+        };
+        if let Some(mut recording) = recording {
+            // Detach rather than abort: this task is the one finishing the recording
+            recording.ptt_guard = None;
+            let result = complete_recording(&handle, recording).await;
+            let _ = match result {
+                Ok(recording) => handle.emit("ptt://timeout", recording),
+                Err(e) => handle.emit("stt://error", e),
+            };
+        }
+    })
+}
+
+// Open the microphone with the start cue and level meter
+fn begin_capture(app_handle: &AppHandle, settings_state: &SettingsState) -> Result<ActiveRecording, String> {
+    // The user is talking again, so stop reading the last reply
+    tts::interrupt(app_handle);
+    // Taken before the microphone opens so a Bluetooth or earpiece route applies to it
+    let focus = audio_focus::acquire(app_handle);
+    let device = settings::current(settings_state).input_device;
+    let mut recording = match spawn_capture(app_handle, device) {
+        Ok(recording) => recording,
+        Err(e) => {
+            earcons::play(app_handle, EarconKind::Error);
+            return Err(e);
+        }
+    };
+    earcons::play(app_handle, EarconKind::StartListening);
+    recording.meter = Some(spawn_meter(app_handle, recording.buffer.clone()));
+    recording.focus = Some(focus);
+    Ok(recording)
+}
+
+// Whether a dictation recording is in progress; the wake word listener pauses meanwhile
+pub(crate) fn is_recording(app_handle: &AppHandle) -> bool {
+    app_handle
+        .state::<SpeechState>()
+        .lock()
+        .map(|service| service.recording.is_some())
+        .unwrap_or(false)
+}
+
+#[tauri::command]
+pub fn list_input_devices() -> Result<Vec<InputDevice>, String> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+    Ok(host
+        .input_devices()
+        .map_err(|e| e.to_string())?
+        .filter_map(|device| device.name().ok())
+        .map(|name| InputDevice {
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+        })
+        .collect())
+}
+
+// Command to choose the microphone; None goes back to the system default
+#[tauri::command]
+pub fn select_input_device(
+    name: Option<String>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    if let Some(name) = &name {
+        find_input_device(Some(name))?;
+    }
+    let mut updated = settings::current(&settings_state);
+    updated.input_device = name;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+// Command to start recording; with `stream` set, partial transcripts are emitted while recording
+#[tauri::command]
+pub fn start_recording(
+    stream: Option<bool>,
+    app_handle: AppHandle,
+    state: State<'_, SpeechState>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let mut service = state.lock().map_err(|e| e.to_string())?;
+    if service.recording.is_some() {
+        return Err("Already recording".to_string());
+    }
+    let mut recording = begin_capture(&app_handle, &settings_state)?;
+    if stream.unwrap_or(false) {
+        recording.partials = Some(spawn_partials(
+            &app_handle,
+            recording.buffer.clone(),
+            recording.sample_rate,
+            recording.channels,
+        ));
+    }
+    let vad = settings::current(&settings_state).vad;
+    if vad.enabled {
+        recording.vad = Some(spawn_vad(
+            &app_handle,
+            recording.buffer.clone(),
+            recording.sample_rate,
+            recording.channels,
+            vad,
+        ));
+    }
+    service.recording = Some(recording);
+    Ok(())
+}
+
+// Command to stop recording and write a 16 kHz mono WAV to the cache directory
+#[tauri::command]
+pub async fn stop_recording(app_handle: AppHandle, state: State<'_, SpeechState>) -> Result<Recording, String> {
+    let recording = {
+        let mut service = state.lock().map_err(|e| e.to_string())?;
+        service.recording.take().ok_or("Not recording".to_string())?
+    };
+    complete_recording(&app_handle, recording).await
+}
+
+// Command to start a push-to-talk recording while the voice button is held.
+// Returns the session id to pass to ptt_end; the session ends by itself after
+// `max_secs` (60 by default).
+#[tauri::command]
+pub fn ptt_begin(
+    max_secs: Option<u64>,
+    app_handle: AppHandle,
+    state: State<'_, SpeechState>,
+    settings_state: State<'_, SettingsState>,
+) -> Result<u64, String> {
+    let max = ptt_max_duration(max_secs)?;
+    let mut service = state.lock().map_err(|e| e.to_string())?;
+    if service.recording.is_some() {
+        return Err("Already recording".to_string());
+    }
+    let mut recording = begin_capture(&app_handle, &settings_state)?;
+    service.last_ptt_session += 1;
+    let session = service.last_ptt_session;
+    recording.ptt_session = Some(session);
+    recording.ptt_guard = Some(spawn_ptt_guard(&app_handle, session, max));
+    service.recording = Some(recording);
+    Ok(session)
+}
+
+// Command for releasing the voice button: stops the session and returns the
+// recording with its transcript
+#[tauri::command]
+pub async fn ptt_end(session: u64, app_handle: AppHandle, state: State<'_, SpeechState>) -> Result<Recording, String> {
+    let recording = {
+        let mut service = state.lock().map_err(|e| e.to_string())?;
+        take_ptt_recording(&mut service, session).ok_or("Push-to-talk session has already ended".to_string())?
+    };
+    complete_recording(&app_handle, recording).await
+}
+
+// Command to change silence detection; applies from the next recording
+#[tauri::command]
+pub fn set_vad_config(
+    config: VadConfig,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let mut updated = settings::current(&settings_state);
+    updated.vad = config;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+// Command to force the spoken language by ISO code or name; None detects it each time
+#[tauri::command]
+pub fn set_stt_language(
+    language: Option<String>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let language = match language {
+        Some(language) => Some(
+            whisper::language_code(&language)
+                .ok_or(format!("Unsupported language: {}", language))?
+                .to_string(),
+        ),
+        None => None,
+    };
+    let mut updated = settings::current(&settings_state);
+    updated.stt_language = language;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+// Command to replace the names and terms transcription is biased towards.
+// Blank and repeated entries are dropped.
+#[tauri::command]
+pub fn set_stt_vocabulary(
+    phrases: Vec<String>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<String>, String> {
+    let vocabulary = clean_vocabulary(phrases)?;
+    let mut updated = settings::current(&settings_state);
+    updated.stt_vocabulary = vocabulary.clone();
+    settings::update_settings(updated, app_handle, settings_state)?;
+    Ok(vocabulary)
+}
+
+// Phrases with their whitespace collapsed, without blanks or repeats
+fn clean_vocabulary(phrases: Vec<String>) -> Result<Vec<String>, String> {
+    let mut vocabulary: Vec<String> = Vec::new();
+    for phrase in phrases {
+        let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+        if phrase.chars().count() > MAX_PHRASE_CHARS {
+            return Err(format!("Phrases can be at most {} characters: {}", MAX_PHRASE_CHARS, phrase));
+        }
+        if !phrase.is_empty() && !vocabulary.iter().any(|known| known.eq_ignore_ascii_case(&phrase)) {
+            vocabulary.push(phrase);
+        }
+    }
+    if vocabulary.len() > MAX_VOCABULARY {
+        return Err(format!("The vocabulary can hold at most {} phrases", MAX_VOCABULARY));
+    }
+    Ok(vocabulary)
+}
+
+// Finish a recording and play the matching cue
+async fn complete_recording(app_handle: &AppHandle, recording: ActiveRecording) -> Result<Recording, String> {
+    let result = finish_recording(app_handle, recording).await;
+    let earcon = if result.is_ok() { EarconKind::Success } else { EarconKind::Error };
+    earcons::play(app_handle, earcon);
+    result
+}
+
+async fn finish_recording(app_handle: &AppHandle, mut recording: ActiveRecording) -> Result<Recording, String> {
+    let streaming = match recording.partials.take() {
+        Some(partials) => {
+            partials.abort();
+            true
+        }
+        None => false,
+    };
+    if let Some(vad) = recording.vad.take() {
+        vad.abort();
+    }
+    if let Some(meter) = recording.meter.take() {
+        meter.abort();
+    }
+    if let Some(guard) = recording.ptt_guard.take() {
+        guard.abort();
+    }
+    let push_to_talk = recording.ptt_session.is_some();
+    let _ = recording.stop.send(());
+    let captured = tauri::async_runtime::spawn_blocking(move || recording.thread.join())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|_| "Audio capture thread panicked".to_string())??;
+
+    let pcm = captured_to_whisper_pcm(app_handle, &captured.samples, captured.sample_rate, captured.channels);
+    let wav = encode_wav(&pcm, WHISPER_SAMPLE_RATE);
+    let duration_secs = wav_duration_secs(&wav);
+
+    let dir = audio_cache::recordings_dir(app_handle)?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = dir.join(format!("{}.wav", stamp));
+    std::fs::write(&path, &wav).map_err(|e| e.to_string())?;
+
+    // The final transcript covers the whole recording, not the stitched partials
+    let mut transcript = None;
+    if streaming || push_to_talk {
+        let result = transcribe_wav(app_handle, wav).await?;
+        let _ = app_handle.emit("stt://final", &result);
+        transcript = Some(result.text);
+    }
+
+    Ok(Recording {
+        path: path.to_string_lossy().into_owned(),
+        duration_secs,
+        transcript,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_wav_writes_a_mono_pcm_header() {
+        let wav = encode_wav(&[1, -2], 16000);
+        assert_eq!(wav.len(), 48);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 40);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 1);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 16000);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 32000);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 4);
+        assert_eq!(&wav[44..], &[1, 0, 0xfe, 0xff]);
+    }
+
+    #[test]
+    fn wav_duration_comes_from_the_byte_rate() {
+        assert_eq!(wav_duration_secs(&encode_wav(&vec![0; 8000], 16000)), 0.5);
+        assert_eq!(wav_duration_secs(&encode_wav(&[], 16000)), 0.0);
+        assert_eq!(wav_duration_secs(&encode_wav(&[0; 100], 0)), 0.0);
+        assert_eq!(wav_duration_secs(b"RIFF"), 0.0);
+    }
+
+    #[test]
+    fn resample_changes_the_rate() {
+        assert_eq!(resample(&[0.25; 48000], 48000, 16000), vec![0.25; 16000]);
+        assert_eq!(resample(&[0.0, 1.0], 1, 2), vec![0.0, 0.5, 1.0, 1.0]);
+        assert_eq!(resample(&[0.5, 0.5], 16000, 16000), vec![0.5, 0.5]);
+        assert!(resample(&[0.5], 16000, 0).is_empty());
+    }
+
+    #[test]
+    fn whisper_pcm_is_clamped_to_16_bits() {
+        assert_eq!(to_whisper_pcm(&[2.0, -2.0, 0.0], 16000, 1), vec![i16::MAX, -i16::MAX, 0]);
+    }
+
+    #[test]
+    fn decodes_audio_files_to_mono() {
+        let samples: Vec<i16> = (0..1600).map(|i| if i % 2 == 0 { 8192 } else { -8192 }).collect();
+        let (mono, sample_rate) = decode_audio_file(encode_wav(&samples, 8000), Some("wav".to_string())).unwrap();
+        assert_eq!(sample_rate, 8000);
+        assert_eq!(mono.len(), 1600);
+        assert!((mono[0] - 0.25).abs() < 1e-3 && (mono[1] + 0.25).abs() < 1e-3);
+        assert!(decode_audio_file(b"not audio".to_vec(), None).is_err());
+    }
+
+    fn response(json: serde_json::Value) -> WhisperResponse {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn words_go_to_the_segment_they_start_in() {
+        let result = response(serde_json::json!({
+            "text": " Hello there. Bye. ",
+            "segments": [
+                { "start": 0.0, "end": 1.5, "text": " Hello there." },
+                { "start": 1.5, "end": 2.0, "text": " Bye." },
+            ],
+            "words": [
+                { "word": "Hello", "start": 0.0, "end": 0.5 },
+                { "word": "there", "start": 0.6, "end": 1.4 },
+                { "word": "Bye", "start": 1.5, "end": 1.9 },
+                { "word": "late", "start": 2.1, "end": 2.2 },
+            ],
+        }))
+        .into_result(None);
+        assert_eq!(result.text, "Hello there. Bye.");
+        let segments = result.segments.unwrap();
+        let words = |i: usize| segments[i].words.iter().map(|word| word.word.as_str()).collect::<Vec<_>>();
+        assert_eq!(segments[0].text, "Hello there.");
+        assert_eq!(words(0), ["Hello", "there"]);
+        // The last segment takes words past its end
+        assert_eq!(words(1), ["Bye", "late"]);
+    }
+
+    #[test]
+    fn words_without_segments_make_one_segment() {
+        let result = response(serde_json::json!({
+            "text": "Hi you",
+            "words": [{ "word": "Hi", "start": 0.2, "end": 0.4 }, { "word": "you", "start": 0.5, "end": 0.9 }],
+        }))
+        .into_result(None);
+        let segments = result.segments.unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].start, segments[0].end), (0.2, 0.9));
+        assert!(response(serde_json::json!({ "text": "Hi" })).into_result(None).segments.is_none());
+    }
+
+    #[test]
+    fn reported_language_becomes_a_code() {
+        let detected = response(serde_json::json!({ "text": "Hola", "language": "spanish" }));
+        assert_eq!(detected.into_result(Some("fr".to_string())).language.as_deref(), Some("es"));
+        let unnamed = response(serde_json::json!({ "text": "Salut" }));
+        assert_eq!(unnamed.into_result(Some("fr".to_string())).language.as_deref(), Some("fr"));
+        let unknown = response(serde_json::json!({ "text": "?", "language": "klingon" }));
+        assert_eq!(unknown.into_result(None).language, None);
+    }
+
+    #[test]
+    fn vocabulary_is_cleaned_and_capped() {
+        let phrases = ["  Acme   Corp ", "acme corp", "", "Zed"].map(str::to_string).to_vec();
+        assert_eq!(clean_vocabulary(phrases).unwrap(), ["Acme Corp", "Zed"]);
+        assert!(clean_vocabulary(vec!["x".repeat(MAX_PHRASE_CHARS + 1)]).is_err());
+        assert!(clean_vocabulary((0..=MAX_VOCABULARY).map(|i| format!("term {}", i)).collect()).is_err());
+        assert_eq!(vocabulary_prompt(&["Acme".to_string(), "Zed".to_string()]).as_deref(), Some("Acme, Zed."));
+        assert_eq!(vocabulary_prompt(&[]), None);
+    }
+
+    #[test]
+    fn downmix_averages_each_frame() {
+        assert_eq!(downmix(&[1.0, 0.0, 0.5, -0.5], 2), vec![0.5, 0.0]);
+        assert_eq!(downmix(&[0.25, 0.75], 0), vec![0.25, 0.75]);
+    }
+
+    fn vad_config() -> VadConfig {
+        VadConfig {
+            enabled: true,
+            threshold: 0.1,
+            silence_ms: 90,
+            min_speech_ms: 60,
+        }
+    }
+
+    #[test]
+    fn vad_waits_for_speech_before_counting_silence() {
+        let config = vad_config();
+        let mut state = VadState::default();
+        assert!(!vad_step(&mut state, &[0.0; 10], &config));
+        // One frame of speech is short of min_speech_ms, so the quiet after it doesn't count
+        assert!(!vad_step(&mut state, &[0.5, 0.0, 0.0, 0.0, 0.0], &config));
+        assert_eq!(state.silence_ms, 0);
+    }
+
+    #[test]
+    fn vad_stops_after_enough_silence() {
+        let config = vad_config();
+        let mut state = VadState::default();
+        assert!(!vad_step(&mut state, &[0.5, 0.5, 0.0, 0.0], &config));
+        assert!(vad_step(&mut state, &[0.0], &config));
+        assert_eq!(state.silence_ms, 90);
+    }
+
+    #[test]
+    fn vad_silence_resets_on_speech() {
+        let config = vad_config();
+        let mut state = VadState::default();
+        assert!(!vad_step(&mut state, &[0.5, 0.5, 0.0, 0.0, 0.5], &config));
+        assert_eq!(state.silence_ms, 0);
+        assert!(!vad_step(&mut state, &[0.0, 0.0], &config));
+        assert!(vad_step(&mut state, &[0.0], &config));
+    }
+
+    #[test]
+    fn ptt_duration_is_bounded() {
+        assert_eq!(ptt_max_duration(None).unwrap(), Duration::from_secs(PTT_DEFAULT_MAX_SECS));
+        assert_eq!(ptt_max_duration(Some(1)).unwrap(), Duration::from_secs(1));
+        assert_eq!(ptt_max_duration(Some(PTT_MAX_SECS)).unwrap(), Duration::from_secs(PTT_MAX_SECS));
+        assert!(ptt_max_duration(Some(0)).is_err());
+        assert!(ptt_max_duration(Some(PTT_MAX_SECS + 1)).is_err());
+    }
+
+    fn ptt_recording(session: Option<u64>) -> ActiveRecording {
+        ActiveRecording {
+            stop: mpsc::channel().0,
+            thread: std::thread::spawn(|| Err("stopped".to_string())),
+            buffer: Arc::new(Mutex::new(Vec::new())),
+            sample_rate: 16000,
+            channels: 1,
+            partials: None,
+            vad: None,
+            meter: None,
+            ptt_session: session,
+            ptt_guard: None,
+            focus: None,
+        }
+    }
+
+    #[test]
+    fn ptt_only_ends_its_own_session() {
+        let mut service = SpeechToTextService {
+            recording: Some(ptt_recording(Some(1))),
+            ..Default::default()
+        };
+        // The guard times session 1 out, so releasing the button afterwards finds nothing
+        assert!(take_ptt_recording(&mut service, 1).is_some());
+        assert!(take_ptt_recording(&mut service, 1).is_none());
+
+        // A newer session outlives the old session's guard and release
+        service.recording = Some(ptt_recording(Some(2)));
+        assert!(take_ptt_recording(&mut service, 1).is_none());
+        assert!(service.recording.is_some());
+        assert!(take_ptt_recording(&mut service, 2).is_some());
+
+        // Nor do they stop plain dictation
+        service.recording = Some(ptt_recording(None));
+        assert!(take_ptt_recording(&mut service, 2).is_none());
+        assert!(service.recording.is_some());
+    }
+}
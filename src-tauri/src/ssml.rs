@@ -0,0 +1,470 @@
+// A provider-neutral subset of SSML for speech. None of the speech backends
+// take SSML themselves, so markup is turned into plain text parts, each with
+// its own speaking rate, and pauses that the player fills with silence:
+//
+//   <speak>, <p>, <s>            paragraphs and sentences, with a short pause after
+//   <break time="500ms"/>        a pause, by time or strength
+//   <emphasis level="strong">    spoken a little slower
+//   <prosody rate="slow">        x-slow to x-fast, or a percentage
+//   <say-as interpret-as="...">  cardinal, ordinal, digits, telephone, characters, date
+//
+// Other tags are ignored and their text is kept. Numbers and dates are read in English.
+
+use std::time::Duration;
+
+const MAX_PAUSE: Duration = Duration::from_secs(5);
+const PARAGRAPH_PAUSE: Duration = Duration::from_millis(400);
+const SENTENCE_PAUSE: Duration = Duration::from_millis(200);
+// Tags may nest at most this deep
+const MAX_DEPTH: usize = 32;
+
+const ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+    "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+const SCALES: [(u64, &str); 4] = [
+    (1_000_000_000_000, "trillion"),
+    (1_000_000_000, "billion"),
+    (1_000_000, "million"),
+    (1_000, "thousand"),
+];
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November",
+    "December",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpeechPart {
+    // Text with a speaking rate relative to the requested one
+    Text { text: String, rate: f32 },
+    Pause(Duration),
+}
+
+struct Element {
+    name: String,
+    rate: f32,
+    // say-as: (interpret-as, format) and the text collected inside
+    say_as: Option<(String, Option<String>, String)>,
+}
+
+pub fn is_ssml(text: &str) -> bool {
+    text.trim_start().starts_with("<speak")
+}
+
+// Plain text of the parts, for length limits and usage
+pub fn plain_text(parts: &[SpeechPart]) -> String {
+    parts
+        .iter()
+        .filter_map(|part| match part {
+            SpeechPart::Text { text, .. } => Some(text.as_str()),
+            SpeechPart::Pause(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// Attributes of a tag body such as `break time="500ms"`
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{}=", name);
+    let mut rest = tag;
+    while let Some(index) = rest.find(&pattern) {
+        let preceded = index == 0 || rest[..index].ends_with(char::is_whitespace);
+        let after = &rest[index + pattern.len()..];
+        if preceded {
+            let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let value = &after[1..];
+            return value.find(quote).map(|end| unescape(&value[..end]));
+        }
+        rest = after;
+    }
+    None
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, millis) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1000.0)
+    } else {
+        return None;
+    };
+    let millis = number.trim().parse::<f64>().ok()? * millis;
+    (millis >= 0.0).then(|| Duration::from_millis(millis as u64).min(MAX_PAUSE))
+}
+
+fn break_pause(tag: &str) -> Duration {
+    if let Some(duration) = attribute(tag, "time").and_then(|time| parse_duration(&time)) {
+        return duration;
+    }
+    let millis = match attribute(tag, "strength").as_deref() {
+        Some("none") => 0,
+        Some("x-weak") => 100,
+        Some("weak") => 200,
+        Some("strong") => 700,
+        Some("x-strong") => 1000,
+        _ => 400,
+    };
+    Duration::from_millis(millis)
+}
+
+fn prosody_rate(value: &str) -> Option<f32> {
+    match value.trim() {
+        "x-slow" => Some(0.6),
+        "slow" => Some(0.8),
+        "medium" | "default" => Some(1.0),
+        "fast" => Some(1.25),
+        "x-fast" => Some(1.5),
+        other => other
+            .strip_suffix('%')
+            .and_then(|percent| percent.trim().parse::<f32>().ok())
+            .map(|percent| percent / 100.0)
+            .filter(|rate| *rate > 0.0),
+    }
+}
+
+fn under_thousand(n: u64) -> String {
+    let mut words = Vec::new();
+    if n >= 100 {
+        words.push(format!("{} hundred", ONES[(n / 100) as usize]));
+    }
+    let rest = n % 100;
+    if rest >= 20 {
+        let tens = TENS[(rest / 10) as usize];
+        words.push(match rest % 10 {
+            0 => tens.to_string(),
+            ones => format!("{}-{}", tens, ONES[ones as usize]),
+        });
+    } else if rest > 0 || n == 0 {
+        words.push(ONES[rest as usize].to_string());
+    }
+    words.join(" ")
+}
+
+fn cardinal(n: u64) -> String {
+    let mut words = Vec::new();
+    let mut rest = n;
+    for (scale, name) in SCALES {
+        if rest >= scale {
+            words.push(format!("{} {}", cardinal(rest / scale), name));
+            rest %= scale;
+        }
+    }
+    if rest > 0 || words.is_empty() {
+        words.push(under_thousand(rest));
+    }
+    words.join(" ")
+}
+
+fn ordinal(n: u64) -> String {
+    let words = cardinal(n);
+    let irregular = [
+        ("one", "first"),
+        ("two", "second"),
+        ("three", "third"),
+        ("five", "fifth"),
+        ("eight", "eighth"),
+        ("nine", "ninth"),
+        ("twelve", "twelfth"),
+    ];
+    for (word, replacement) in irregular {
+        if let Some(stem) = words.strip_suffix(word) {
+            return format!("{}{}", stem, replacement);
+        }
+    }
+    match words.strip_suffix('y') {
+        Some(stem) => format!("{}ieth", stem),
+        None => format!("{}th", words),
+    }
+}
+
+// Read a number, with an optional sign, thousands separators and decimals
+fn number_words(text: &str) -> Option<String> {
+    let text = text.trim().replace(',', "");
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.to_string()),
+        None => (false, text),
+    };
+    let (whole, fraction) = match text.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (text.as_str(), None),
+    };
+    let mut words = cardinal(whole.parse::<u64>().ok()?);
+    if let Some(fraction) = fraction {
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        words.push_str(" point ");
+        words.push_str(&digits(fraction));
+    }
+    Some(if negative { format!("minus {}", words) } else { words })
+}
+
+fn digits(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| ONES[digit as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Dates in the given field order (ymd by default), with -, / or . between fields
+fn date_words(text: &str, format: Option<&str>) -> Option<String> {
+    let fields: Vec<u64> = text
+        .trim()
+        .split(['-', '/', '.'])
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+    let [a, b, c] = fields[..] else {
+        return None;
+    };
+    let (year, month, day) = match format.unwrap_or("ymd") {
+        "mdy" => (c, a, b),
+        "dmy" => (c, b, a),
+        _ => (a, b, c),
+    };
+    let month = MONTHS.get((month as usize).checked_sub(1)?)?;
+    if !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(format!("{} {}, {}", month, ordinal(day), year_words(year)))
+}
+
+// Years are read in pairs, as people say them: 1984 is nineteen eighty-four
+fn year_words(year: u64) -> String {
+    match year {
+        1100..=1999 | 2100..=9999 if !year.is_multiple_of(100) => {
+            let rest = year % 100;
+            let rest = if rest < 10 { format!("oh {}", ONES[rest as usize]) } else { under_thousand(rest) };
+            format!("{} {}", under_thousand(year / 100), rest)
+        }
+        _ => cardinal(year),
+    }
+}
+
+fn say_as(text: &str, interpret_as: &str, format: Option<&str>) -> String {
+    let spoken = match interpret_as {
+        "cardinal" | "number" => number_words(text),
+        "ordinal" => text.trim().trim_end_matches(['s', 't', 'n', 'd', 'r', 'h']).parse().ok().map(ordinal),
+        "digits" | "telephone" => Some(digits(text)),
+        "characters" | "spell-out" => Some(
+            text.chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        "date" => date_words(text, format),
+        _ => None,
+    };
+    spoken.unwrap_or_else(|| text.to_string())
+}
+
+fn push_text(parts: &mut Vec<SpeechPart>, text: &str, rate: f32) {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return;
+    }
+    if let Some(SpeechPart::Text { text: previous, rate: previous_rate }) = parts.last_mut() {
+        if *previous_rate == rate {
+            previous.push(' ');
+            previous.push_str(&text);
+            return;
+        }
+    }
+    parts.push(SpeechPart::Text { text, rate });
+}
+
+fn push_pause(parts: &mut Vec<SpeechPart>, duration: Duration) {
+    if duration.is_zero() {
+        return;
+    }
+    match parts.last_mut() {
+        Some(SpeechPart::Pause(previous)) => *previous = (*previous + duration).min(MAX_PAUSE),
+        // Nothing has been said yet, so there is nothing to pause after
+        None => {}
+        Some(_) => parts.push(SpeechPart::Pause(duration)),
+    }
+}
+
+// Split SSML into text and pauses; text outside any markup is one part
+pub fn parse(input: &str) -> Result<Vec<SpeechPart>, String> {
+    let mut parts = Vec::new();
+    let mut stack: Vec<Element> = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            add_text(&mut parts, &mut stack, &unescape(rest));
+            break;
+        };
+        add_text(&mut parts, &mut stack, &unescape(&rest[..open]));
+        let close = rest[open..]
+            .find('>')
+            .map(|close| open + close)
+            .ok_or("Unclosed tag in speech markup".to_string())?;
+        let tag = rest[open + 1..close].trim();
+        rest = &rest[close + 1..];
+
+        // Comments, declarations and processing instructions say nothing
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            let element = stack.pop().ok_or(format!("Unexpected </{}> in speech markup", name))?;
+            if element.name != name {
+                return Err(format!("Expected </{}> but found </{}> in speech markup", element.name, name));
+            }
+            close_element(&mut parts, &mut stack, element);
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/').trim();
+        let name = tag.split_whitespace().next().unwrap_or_default().to_string();
+        if name == "break" {
+            push_pause(&mut parts, break_pause(tag));
+            continue;
+        }
+        if self_closing {
+            continue;
+        }
+        if stack.len() >= MAX_DEPTH {
+            return Err("Speech markup is nested too deeply".to_string());
+        }
+        let inherited = stack.last().map(|element| element.rate).unwrap_or(1.0);
+        let rate = match name.as_str() {
+            "emphasis" => match attribute(tag, "level").as_deref() {
+                Some("reduced") => inherited * 1.1,
+                Some("none") => inherited,
+                Some("strong") => inherited * 0.85,
+                _ => inherited * 0.9,
+            },
+            "prosody" => inherited * attribute(tag, "rate").and_then(|rate| prosody_rate(&rate)).unwrap_or(1.0),
+            _ => inherited,
+        };
+        let say_as = (name == "say-as").then(|| {
+            (
+                attribute(tag, "interpret-as").unwrap_or_default(),
+                attribute(tag, "format"),
+                String::new(),
+            )
+        });
+        stack.push(Element { name, rate, say_as });
+    }
+    if let Some(element) = stack.last() {
+        return Err(format!("<{}> is never closed in speech markup", element.name));
+    }
+    if matches!(parts.last(), Some(SpeechPart::Pause(_))) {
+        parts.pop();
+    }
+    Ok(parts)
+}
+
+// Text inside say-as is held until the element closes, so it is read as a whole
+fn add_text(parts: &mut Vec<SpeechPart>, stack: &mut [Element], text: &str) {
+    let Some(element) = stack.last_mut() else {
+        push_text(parts, text, 1.0);
+        return;
+    };
+    match &mut element.say_as {
+        Some((_, _, collected)) => collected.push_str(text),
+        None => push_text(parts, text, element.rate),
+    }
+}
+
+fn close_element(parts: &mut Vec<SpeechPart>, stack: &mut [Element], element: Element) {
+    if let Some((interpret_as, format, text)) = element.say_as {
+        let spoken = say_as(&text, &interpret_as, format.as_deref());
+        add_text(parts, stack, &spoken);
+        return;
+    }
+    match element.name.as_str() {
+        "p" => push_pause(parts, PARAGRAPH_PAUSE),
+        "s" => push_pause(parts, SENTENCE_PAUSE),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, rate: f32) -> SpeechPart {
+        SpeechPart::Text { text: text.to_string(), rate }
+    }
+
+    #[test]
+    fn numbers_are_read_in_words() {
+        assert_eq!(cardinal(0), "zero");
+        assert_eq!(cardinal(42), "forty-two");
+        assert_eq!(cardinal(1_000_000), "one million");
+        assert_eq!(cardinal(2_305_017), "two million three hundred five thousand seventeen");
+        assert_eq!(ordinal(1), "first");
+        assert_eq!(ordinal(12), "twelfth");
+        assert_eq!(ordinal(20), "twentieth");
+        assert_eq!(ordinal(23), "twenty-third");
+        assert_eq!(number_words("-1,204.05").unwrap(), "minus one thousand two hundred four point zero five");
+        assert_eq!(number_words("12a"), None);
+    }
+
+    #[test]
+    fn dates_and_years_are_read_as_spoken() {
+        assert_eq!(date_words("1984-07-04", None).unwrap(), "July fourth, nineteen eighty-four");
+        assert_eq!(date_words("12/25/2005", Some("mdy")).unwrap(), "December twenty-fifth, two thousand five");
+        assert_eq!(date_words("01.03.2101", Some("dmy")).unwrap(), "March first, twenty-one oh one");
+        assert_eq!(date_words("2024-13-01", None), None);
+        assert_eq!(year_words(2000), "two thousand");
+    }
+
+    #[test]
+    fn markup_becomes_text_and_pauses() {
+        let parts = parse(
+            r#"<speak><p>Hello &amp; welcome.</p><break time="2s"/><emphasis level="strong">Now</emphasis> go</speak>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parts,
+            [
+                text("Hello & welcome.", 1.0),
+                SpeechPart::Pause(PARAGRAPH_PAUSE + Duration::from_secs(2)),
+                text("Now", 0.85),
+                text("go", 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn prosody_and_say_as_apply_to_their_text() {
+        let parts = parse(
+            r#"<speak><prosody rate="slow">Call <say-as interpret-as="telephone">555-0100</say-as></prosody></speak>"#,
+        )
+        .unwrap();
+        assert_eq!(parts, [text("Call five five five zero one zero zero", 0.8)]);
+        assert_eq!(plain_text(&parts), "Call five five five zero one zero zero");
+    }
+
+    #[test]
+    fn pauses_are_capped() {
+        let parts = parse(r#"<speak>Wait<break time="30s"/><break time="4000ms"/>done</speak>"#).unwrap();
+        assert_eq!(parts, [text("Wait", 1.0), SpeechPart::Pause(MAX_PAUSE), text("done", 1.0)]);
+    }
+
+    #[test]
+    fn malformed_markup_is_refused() {
+        assert!(parse("<speak>Hello").is_err());
+        assert!(parse("<speak>Hello</p>").is_err());
+        assert!(parse("<speak>Hello <break").is_err());
+        let deep = "<s>".repeat(MAX_DEPTH + 1);
+        assert!(parse(&deep).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+// Suffixes of an in-progress write and of a file set aside as unreadable
+const TEMP_SUFFIX: &str = ".tmp";
+const CORRUPT_SUFFIX: &str = ".corrupt";
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    // A finished write was found that had not been moved into place
+    RestoredFromTemp,
+    // The file could not be parsed and was moved aside; defaults are used instead
+    Quarantined,
+    // An unfinished write was discarded
+    RemovedTemp,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RecoveredFile {
+    pub file: String,
+    pub action: RecoveryAction,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RecoveryReport {
+    pub checked: usize,
+    pub recovered: Vec<RecoveredFile>,
+}
+
+pub type RecoveryState = Mutex<RecoveryReport>;
+
+// Resolve a file inside the app data directory, creating its directory if needed
+pub fn data_file(app_handle: &AppHandle, name: &str) -> Result<PathBuf, String> {
+    let path = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(name);
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(path)
+}
+
+// Load a JSON file from the app data directory, falling back to the default value
+pub fn load_json<T: DeserializeOwned + Default>(app_handle: &AppHandle, name: &str) -> T {
+    data_file(app_handle, name)
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Save a value as JSON in the app data directory. The file is written beside
+// the target, synced and renamed over it, so a kill mid-write never leaves a
+// half written file behind.
+pub fn save_json<T: Serialize>(app_handle: &AppHandle, name: &str, value: &T) -> Result<(), String> {
+    let path = data_file(app_handle, name)?;
+    let contents = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let temp = with_suffix(&path, TEMP_SUFFIX);
+    let mut file = std::fs::File::create(&temp).map_err(|e| e.to_string())?;
+    file.write_all(contents.as_bytes()).map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    std::fs::rename(&temp, &path).map_err(|e| e.to_string())
+}
+
+fn parses(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .is_some()
+}
+
+fn record(report: &mut RecoveryReport, name: &str, action: RecoveryAction) {
+    tracing::warn!(file = %name, ?action, "recovered state file");
+    report.recovered.push(RecoveredFile {
+        file: name.to_string(),
+        action,
+    });
+}
+
+// Check every state file at startup, before anything is loaded: finish
+// interrupted writes, drop partial ones and move unreadable files aside
+pub fn recover_state(app_handle: &AppHandle) -> RecoveryReport {
+    match app_handle.path().app_data_dir() {
+        Ok(dir) => recover_dir(&dir),
+        Err(_) => RecoveryReport::default(),
+    }
+}
+
+fn recover_dir(dir: &Path) -> RecoveryReport {
+    let mut report = RecoveryReport::default();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return report;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+        // Leftover writes: a complete one replaces a missing or broken target
+        if let Some(target) = name.strip_suffix(TEMP_SUFFIX).filter(|target| target.ends_with(".json")) {
+            let target = dir.join(target);
+            if parses(&path) && !parses(&target) && std::fs::rename(&path, &target).is_ok() {
+                record(&mut report, &name, RecoveryAction::RestoredFromTemp);
+            } else if std::fs::remove_file(&path).is_ok() {
+                record(&mut report, &name, RecoveryAction::RemovedTemp);
+            }
+            continue;
+        }
+        if !name.ends_with(".json") || !path.is_file() {
+            continue;
+        }
+
+        report.checked += 1;
+        if !parses(&path) && std::fs::rename(&path, with_suffix(&path, CORRUPT_SUFFIX)).is_ok() {
+            record(&mut report, &name, RecoveryAction::Quarantined);
+        }
+    }
+    report
+}
+
+// Command to show what was repaired at the last startup
+#[tauri::command]
+pub fn get_recovery_report(state: State<'_, RecoveryState>) -> Result<RecoveryReport, String> {
+    let report = state.lock().map_err(|e| e.to_string())?;
+    Ok(report.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions(report: &RecoveryReport) -> Vec<(&str, RecoveryAction)> {
+        report
+            .recovered
+            .iter()
+            .map(|recovered| (recovered.file.as_str(), recovered.action))
+            .collect()
+    }
+
+    #[test]
+    fn suffixes_are_appended() {
+        assert_eq!(with_suffix(Path::new("data/notes.json"), TEMP_SUFFIX), PathBuf::from("data/notes.json.tmp"));
+    }
+
+    #[test]
+    fn interrupted_writes_are_recovered() {
+        let dir = std::env::temp_dir().join(format!("plates-recovery-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Finished write that never replaced the missing target
+        std::fs::write(dir.join("a.json.tmp"), r#"{"notes": []}"#).unwrap();
+        // Half written update of a good file
+        std::fs::write(dir.join("b.json"), "{}").unwrap();
+        std::fs::write(dir.join("b.json.tmp"), r#"{"no"#).unwrap();
+        std::fs::write(dir.join("c.json"), "not json").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not json either").unwrap();
+
+        let report = recover_dir(&dir);
+        assert_eq!(
+            actions(&report),
+            vec![
+                ("a.json.tmp", RecoveryAction::RestoredFromTemp),
+                ("b.json.tmp", RecoveryAction::RemovedTemp),
+                ("c.json", RecoveryAction::Quarantined),
+            ]
+        );
+        assert_eq!(report.checked, 2);
+        assert_eq!(std::fs::read_to_string(dir.join("a.json")).unwrap(), r#"{"notes": []}"#);
+        assert_eq!(std::fs::read_to_string(dir.join("b.json")).unwrap(), "{}");
+        assert!(dir.join("c.json.corrupt").exists());
+        assert!(dir.join("notes.txt").exists());
+
+        // Nothing left to do the second time
+        let again = recover_dir(&dir);
+        assert!(again.recovered.is_empty());
+        assert_eq!(again.checked, 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+// Bedtime stories for child profiles. The engine writes a short chaptered
+// story under a fixed brief, every chapter goes through moderation, and the
+// result is saved as a note so it can be read aloud again later. Playback
+// reads one chapter after another and emits story://chapter and
+// story://finished; pause_speaking and resume_speaking hold it mid-chapter.
+
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, Manager, State};
+
+use crate::engine;
+use crate::moderation::{self, Verdict};
+use crate::notes::{self, Note};
+use crate::settings::{self, Profile, SettingsState};
+use crate::structured;
+use crate::tts;
+use crate::untrusted;
+
+const STORY_SOURCE: &str = "story";
+// Chapters are separated by a scene break in the note body
+const CHAPTER_BREAK: &str = "\n\n* * *\n\n";
+const DEFAULT_CHAPTERS: usize = 3;
+const MAX_CHAPTERS: usize = 6;
+const DEFAULT_AGE: u8 = 6;
+const MAX_IDEA_CHARS: usize = 200;
+// A story moderation blocks is written again once before giving up
+const MAX_ATTEMPTS: usize = 2;
+
+#[derive(Deserialize)]
+struct GeneratedStory {
+    title: String,
+    chapters: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StoryChapter {
+    pub story: u64,
+    // 0-based
+    pub chapter: usize,
+    pub total: usize,
+}
+
+#[derive(Default)]
+pub struct StoryPlayer {
+    task: Option<JoinHandle<()>>,
+}
+
+pub type StoryState = Mutex<StoryPlayer>;
+
+fn require_child_profile(app_handle: &AppHandle) -> Result<(), String> {
+    if settings::current(&app_handle.state::<SettingsState>()).profile != Profile::Child {
+        return Err("Story mode is only available in child profiles".to_string());
+    }
+    Ok(())
+}
+
+fn chapters(note: &Note) -> Vec<String> {
+    note.body
+        .split(CHAPTER_BREAK)
+        .map(|chapter| chapter.trim().to_string())
+        .filter(|chapter| !chapter.is_empty())
+        .collect()
+}
+
+async fn write_story(app_handle: &AppHandle, idea: &str, age: u8, count: usize) -> Result<GeneratedStory, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let prompt = format!(
+        "Write a gentle bedtime story for a {}-year-old in {} short chapters of about 150 words each. \
+         Use simple words, a warm tone and a calm, happy ending. Nothing scary, violent or sad, \
+         no grown-up themes, and no real people or brands. If the idea below doesn't fit these \
+         rules, write about something cosy instead.\n\n{}\n\n\
+         Reply with only a JSON object with a \"title\" string and a \"chapters\" array of strings.",
+        age,
+        count,
+        untrusted::wrap("story idea", idea)
+    );
+    let response = engine::generate(app_handle, &prompt, &app_settings).await?;
+    parse_story(&response, count)
+}
+
+// The story in the engine's reply, with empty chapters dropped and at most `count` kept
+fn parse_story(response: &str, count: usize) -> Result<GeneratedStory, String> {
+    let json = structured::extract_json(response, '{', '}').ok_or("The story could not be written".to_string())?;
+    let mut story: GeneratedStory = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    story.chapters.retain(|chapter| !chapter.trim().is_empty());
+    story.chapters.truncate(count);
+    if story.chapters.is_empty() {
+        return Err("The story could not be written".to_string());
+    }
+    Ok(story)
+}
+
+// Moderate the title and every chapter; None when any part is blocked
+async fn moderate_story(app_handle: &AppHandle, story: GeneratedStory) -> Option<GeneratedStory> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let mut parts = Vec::with_capacity(story.chapters.len() + 1);
+    for text in std::iter::once(story.title).chain(story.chapters) {
+        match moderation::classify(app_handle, &text, &app_settings).await {
+            Verdict::Allow => parts.push(text),
+            Verdict::Rewrite { text } => parts.push(text),
+            Verdict::Block { categories } => {
+                tracing::info!(?categories, "generated story blocked by moderation");
+                return None;
+            }
+        }
+    }
+    let title = parts.remove(0);
+    Some(GeneratedStory { title, chapters: parts })
+}
+
+// Command to write and save a story about an idea; `age` (3 to 12) sets the
+// reading level
+#[tauri::command]
+pub async fn generate_story(
+    idea: String,
+    chapters: Option<usize>,
+    age: Option<u8>,
+    app_handle: AppHandle,
+) -> Result<Note, String> {
+    require_child_profile(&app_handle)?;
+    let idea = idea.trim().to_string();
+    if idea.chars().count() > MAX_IDEA_CHARS {
+        return Err(format!("The story idea can be at most {} characters", MAX_IDEA_CHARS));
+    }
+    if matches!(moderation::classify_locally(&idea), Verdict::Block { .. }) {
+        return Err("Let's pick a different idea for tonight's story".to_string());
+    }
+    let count = chapters.unwrap_or(DEFAULT_CHAPTERS);
+    if count == 0 || count > MAX_CHAPTERS {
+        return Err(format!("A story has between 1 and {} chapters", MAX_CHAPTERS));
+    }
+    let age = age.unwrap_or(DEFAULT_AGE).clamp(3, 12);
+    let idea = if idea.is_empty() { "a surprise".to_string() } else { idea };
+
+    for _ in 0..MAX_ATTEMPTS {
+        let story = write_story(&app_handle, &idea, age, count).await?;
+        if let Some(story) = moderate_story(&app_handle, story).await {
+            let body = story.chapters.join(CHAPTER_BREAK);
+            return notes::add_note(&app_handle, story.title, body, Some(STORY_SOURCE.to_string()));
+        }
+    }
+    Err("Couldn't write a suitable story; try another idea".to_string())
+}
+
+#[tauri::command]
+pub fn list_stories(app_handle: AppHandle) -> Result<Vec<Note>, String> {
+    notes::notes_from(&app_handle, STORY_SOURCE)
+}
+
+// Command to read a saved story aloud from a chapter onwards, replacing any
+// story being read. Talking over it or stop_story ends playback.
+#[tauri::command]
+pub fn play_story(
+    id: u64,
+    chapter: Option<usize>,
+    app_handle: AppHandle,
+    state: State<'_, StoryState>,
+) -> Result<(), String> {
+    require_child_profile(&app_handle)?;
+    let note = notes::get_note(&app_handle, id)?;
+    if note.source.as_deref() != Some(STORY_SOURCE) {
+        return Err("That note is not a story".to_string());
+    }
+    let chapters = chapters(&note);
+    let first = chapter.unwrap_or(0);
+    if first >= chapters.len() {
+        return Err("The story has no such chapter".to_string());
+    }
+
+    let handle = app_handle.clone();
+    let task = tauri::async_runtime::spawn(async move {
+        let total = chapters.len();
+        for (chapter, text) in chapters.iter().enumerate().skip(first) {
+            let _ = handle.emit("story://chapter", StoryChapter { story: id, chapter, total });
+            let utterance = match tts::say(&handle, &engine::spoken_markup(text), None, None).await {
+                Ok(utterance) => utterance,
+                Err(e) => {
+                    tracing::warn!(error = %e, "reading story failed");
+                    return;
+                }
+            };
+            if !utterance.finished().await {
+                return;
+            }
+        }
+        let _ = handle.emit("story://finished", id);
+    });
+    let mut player = state.lock().map_err(|e| e.to_string())?;
+    if let Some(previous) = player.task.replace(task) {
+        previous.abort();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_story(app_handle: AppHandle, state: State<'_, StoryState>) -> Result<(), String> {
+    if let Some(task) = state.lock().map_err(|e| e.to_string())?.task.take() {
+        task.abort();
+    }
+    tts::interrupt(&app_handle);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_are_trimmed_to_the_chapters_asked_for() {
+        let reply = r#"Here it is: {"title": "Luna's Lantern", "chapters": ["One", " ", "Two", "Three"]}"#;
+        let story = parse_story(reply, 2).unwrap();
+        assert_eq!(story.title, "Luna's Lantern");
+        assert_eq!(story.chapters, vec!["One", "Two"]);
+        assert!(parse_story(r#"{"title": "Empty", "chapters": [""]}"#, 3).is_err());
+        assert!(parse_story("Once upon a time", 3).is_err());
+        assert!(parse_story("} {", 3).is_err());
+    }
+
+    #[test]
+    fn saved_stories_split_back_into_chapters() {
+        let note = Note {
+            id: 1,
+            title: "Luna's Lantern".to_string(),
+            body: ["Luna found a lantern.", "It glowed softly.\n\nShe smiled.", "Goodnight."].join(CHAPTER_BREAK),
+            created_at: 0,
+            source: Some(STORY_SOURCE.to_string()),
+        };
+        assert_eq!(chapters(&note), vec!["Luna found a lantern.", "It glowed softly.\n\nShe smiled.", "Goodnight."]);
+    }
+}
@@ -0,0 +1,329 @@
+// Structured answers: the model replies with JSON constrained to a schema,
+// which is checked here before it reaches the frontend as a typed result.
+// Schemas use the OpenAPI subset Gemini's responseSchema accepts, which
+// OpenAI's json_schema response format also understands.
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use crate::intents::Intent;
+use crate::untrusted;
+
+// Schema keys Gemini rejects
+const UNSUPPORTED_GEMINI_KEYS: &[&str] = &["$schema", "additionalProperties", "default", "title"];
+
+// What the answer should be
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StructuredRequest {
+    // The action the text asks the assistant for, as a plates:// intent
+    Intent,
+    // People, places, dates and the like mentioned in the text
+    Entities,
+    // Any JSON matching the given schema; the text is the whole prompt
+    Json { schema: Value },
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StructuredOutput {
+    Intent { intent: Intent },
+    Entities { entities: Vec<Entity> },
+    Json { value: Value },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Person,
+    Organization,
+    Place,
+    Date,
+    Time,
+    Amount,
+    Email,
+    Phone,
+    Url,
+    Other,
+}
+
+impl EntityKind {
+    const ALL: [&'static str; 10] = [
+        "person", "organization", "place", "date", "time", "amount", "email", "phone", "url", "other",
+    ];
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct EntityList {
+    entities: Vec<Entity>,
+}
+
+pub fn schema(request: &StructuredRequest) -> Result<Value, String> {
+    match request {
+        StructuredRequest::Intent => Ok(json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["ask", "timer", "note", "expense", "water", "photos", "open", "wifi"]
+                },
+                "query": { "type": "string" },
+                "minutes": { "type": "integer" },
+                "label": { "type": "string" },
+                "text": { "type": "string" },
+                "ml": { "type": "integer" },
+                "screen": { "type": "string" }
+            },
+            "required": ["action"]
+        })),
+        StructuredRequest::Entities => Ok(json!({
+            "type": "object",
+            "properties": {
+                "entities": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "kind": { "type": "string", "enum": EntityKind::ALL },
+                            "text": { "type": "string" }
+                        },
+                        "required": ["kind", "text"]
+                    }
+                }
+            },
+            "required": ["entities"]
+        })),
+        StructuredRequest::Json { schema } => {
+            if schema.get("type").and_then(Value::as_str).is_none() {
+                return Err("The schema needs a type".to_string());
+            }
+            Ok(schema.clone())
+        }
+    }
+}
+
+pub fn prompt(request: &StructuredRequest, text: &str) -> String {
+    match request {
+        StructuredRequest::Intent => format!(
+            "Turn this request to a voice assistant into one action: ask (a question, in query), timer \
+             (minutes and an optional label), note (text), expense (text), water (ml), photos (a search \
+             query), open (a screen) or wifi (show the home Wi-Fi code).\n\nRequest: {}",
+            text
+        ),
+        StructuredRequest::Entities => format!(
+            "List the people, organizations, places, dates, times, amounts, emails, phone numbers and links \
+             mentioned in this text, each as written.\n\n{}",
+            untrusted::wrap("text", text)
+        ),
+        StructuredRequest::Json { .. } => text.to_string(),
+    }
+}
+
+// The schema in the form Gemini's responseSchema takes: upper case type names
+// and no keys it doesn't know
+pub fn for_gemini(schema: &Value) -> Value {
+    match schema {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .filter(|(key, _)| !UNSUPPORTED_GEMINI_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| {
+                    let value = match (key.as_str(), value) {
+                        ("type", Value::String(kind)) => Value::String(kind.to_uppercase()),
+                        // Property names are left alone, whatever they are called
+                        ("properties", Value::Object(properties)) => Value::Object(
+                            properties
+                                .iter()
+                                .map(|(name, property)| (name.clone(), for_gemini(property)))
+                                .collect(),
+                        ),
+                        _ => for_gemini(value),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(for_gemini).collect()),
+        other => other.clone(),
+    }
+}
+
+// Models like to wrap JSON in code fences or a sentence; take the outermost
+// value, and nothing when the reply closes before it opens
+pub fn extract_json(text: &str, open: char, close: char) -> Option<&str> {
+    let start = text.find(open)?;
+    let end = text.rfind(close)?;
+    (end > start).then(|| &text[start..=end])
+}
+
+// The JSON in an answer; providers without a JSON mode may wrap it in prose
+// or a code fence
+pub fn parse(answer: &str) -> Result<Value, String> {
+    let start = answer
+        .find(['{', '['])
+        .ok_or("The answer was not JSON".to_string())?;
+    let end = answer.rfind(['}', ']']).filter(|end| *end > start).ok_or("The answer was not JSON".to_string())?;
+    serde_json::from_str(&answer[start..=end]).map_err(|e| format!("The answer was not valid JSON: {}", e))
+}
+
+// Check a value against the schema subset: types, enums, required and
+// nested properties and array items. Optional properties may be null.
+pub fn validate(value: &Value, schema: &Value) -> Result<(), String> {
+    check(value, schema, "$")
+}
+
+fn check(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            return Err(format!("{} is not one of the allowed values", path));
+        }
+    }
+    let kind = schema["type"].as_str().unwrap_or_default().to_lowercase();
+    let matches = match kind.as_str() {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        _ => true,
+    };
+    if !matches {
+        return Err(format!("{} should be of type {}", path, kind));
+    }
+
+    if let Some(object) = value.as_object() {
+        for name in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            if object.get(name).is_none_or(Value::is_null) {
+                return Err(format!("{}.{} is missing", path, name));
+            }
+        }
+        for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+            if let Some(field) = object.get(name).filter(|field| !field.is_null()) {
+                check(field, property, &format!("{}.{}", path, name))?;
+            }
+        }
+    }
+    if let Some(items) = value.as_array() {
+        for (i, item) in items.iter().enumerate() {
+            check(item, &schema["items"], &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+// The checked value as the result type the request asked for
+pub fn typed(request: StructuredRequest, value: Value) -> Result<StructuredOutput, String> {
+    match request {
+        StructuredRequest::Intent => {
+            let intent = serde_json::from_value(value).map_err(|e| format!("The answer was not an action: {}", e))?;
+            Ok(StructuredOutput::Intent { intent })
+        }
+        StructuredRequest::Entities => {
+            let list: EntityList = serde_json::from_value(value).map_err(|e| e.to_string())?;
+            Ok(StructuredOutput::Entities { entities: list.entities })
+        }
+        StructuredRequest::Json { .. } => Ok(StructuredOutput::Json { value }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_is_taken_from_around_the_reply() {
+        assert_eq!(extract_json("Here you go:\n```json\n[{\"a\": 1}]\n```", '[', ']'), Some("[{\"a\": 1}]"));
+        assert_eq!(extract_json("{\"correct\": true}", '{', '}'), Some("{\"correct\": true}"));
+        assert_eq!(extract_json("] nothing [", '[', ']'), None);
+        assert_eq!(extract_json("} x {", '{', '}'), None);
+        assert_eq!(extract_json("no json", '{', '}'), None);
+    }
+
+    #[test]
+    fn gemini_schemas_use_upper_case_types_and_known_keys() {
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "type": { "type": "string", "default": "a" },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["type"]
+        });
+        assert_eq!(
+            for_gemini(&schema),
+            json!({
+                "type": "OBJECT",
+                "properties": {
+                    "type": { "type": "STRING" },
+                    "tags": { "type": "ARRAY", "items": { "type": "STRING" } }
+                },
+                "required": ["type"]
+            })
+        );
+    }
+
+    #[test]
+    fn json_is_found_inside_prose_and_code_fences() {
+        assert_eq!(parse("Sure! ```json\n{\"a\": [1, 2]}\n``` Anything else?").unwrap(), json!({ "a": [1, 2] }));
+        assert_eq!(parse("[1, 2]").unwrap(), json!([1, 2]));
+        assert!(parse("No JSON here").is_err());
+        assert!(parse("} backwards {").is_err());
+        assert!(parse("{ not json }").is_err());
+    }
+
+    #[test]
+    fn validation_checks_types_enums_and_required_fields() {
+        let intent = schema(&StructuredRequest::Intent).unwrap();
+        assert!(validate(&json!({ "action": "timer", "minutes": 5, "label": null }), &intent).is_ok());
+        assert_eq!(validate(&json!({ "minutes": 5 }), &intent).unwrap_err(), "$.action is missing");
+        assert_eq!(
+            validate(&json!({ "action": "launch" }), &intent).unwrap_err(),
+            "$.action is not one of the allowed values"
+        );
+        assert_eq!(
+            validate(&json!({ "action": "timer", "minutes": 2.5 }), &intent).unwrap_err(),
+            "$.minutes should be of type integer"
+        );
+
+        let entities = schema(&StructuredRequest::Entities).unwrap();
+        let value = json!({ "entities": [{ "kind": "person", "text": "Ada" }, { "kind": "planet", "text": "Mars" }] });
+        assert_eq!(
+            validate(&value, &entities).unwrap_err(),
+            "$.entities[1].kind is not one of the allowed values"
+        );
+    }
+
+    #[test]
+    fn custom_schemas_need_a_type() {
+        let request = StructuredRequest::Json { schema: json!({ "properties": {} }) };
+        assert!(schema(&request).is_err());
+    }
+
+    #[test]
+    fn checked_values_become_typed_results() {
+        let output = typed(StructuredRequest::Intent, json!({ "action": "timer", "minutes": 5 })).unwrap();
+        assert!(matches!(
+            output,
+            StructuredOutput::Intent { intent: Intent::Timer { minutes: 5, label: None } }
+        ));
+        let output = typed(
+            StructuredRequest::Entities,
+            json!({ "entities": [{ "kind": "place", "text": "Lisbon" }] }),
+        )
+        .unwrap();
+        let StructuredOutput::Entities { entities } = output else {
+            panic!("expected entities");
+        };
+        assert_eq!(entities[0].kind, EntityKind::Place);
+        assert!(typed(StructuredRequest::Intent, json!({ "action": "timer" })).is_err());
+    }
+}
@@ -0,0 +1,524 @@
+// Speech-to-text backends. Each provider implements SttBackend and is listed
+// in BACKENDS; transcription picks one from settings, so adding a provider
+// does not touch the recording or file transcription code. A failing backend
+// is retried and then the configured fallbacks are tried in turn.
+
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio_util::sync::CancellationToken;
+
+use crate::consent::{self, ConsentScope};
+use crate::deepgram;
+use crate::endpoints;
+use crate::gemini_live;
+use crate::provider_keys;
+use crate::routing::{self, Service};
+use crate::settings::{self, AppSettings, SettingsState, SttFallbackSettings};
+use crate::speech::{self, TranscriptionResult};
+use crate::thermal;
+use crate::usage::Provider;
+use crate::whisper;
+
+pub trait SttBackend: Send + Sync {
+    // Stable id stored in settings
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    // Whether audio stays on the device
+    fn local(&self) -> bool;
+    // Whether segments come back labelled by speaker
+    fn diarizes(&self) -> bool {
+        false
+    }
+    // Why the backend cannot be used right now, such as a missing key or model
+    fn unavailable(&self, app_handle: &AppHandle, app_settings: &AppSettings) -> Option<String>;
+    // Transcribe a mono 16-bit PCM WAV file
+    fn transcribe<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        wav: Vec<u8>,
+    ) -> BoxFuture<'a, Result<TranscriptionResult, String>>;
+}
+
+struct WhisperApi;
+
+impl SttBackend for WhisperApi {
+    fn id(&self) -> &'static str {
+        "openai_whisper"
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI Whisper"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        if !consent::granted(app_handle, ConsentScope::OpenAiAudio) {
+            return Some("Sending audio to OpenAI has not been consented to".to_string());
+        }
+        if let Err(e) = endpoints::ensure_online() {
+            return Some(e);
+        }
+        provider_keys::select_key(app_handle, Provider::Whisper).err()
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        wav: Vec<u8>,
+    ) -> BoxFuture<'a, Result<TranscriptionResult, String>> {
+        speech::transcribe_with_whisper_api(app_handle, wav).boxed()
+    }
+}
+
+struct Deepgram;
+
+impl SttBackend for Deepgram {
+    fn id(&self) -> &'static str {
+        "deepgram"
+    }
+
+    fn name(&self) -> &'static str {
+        "Deepgram"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn diarizes(&self) -> bool {
+        true
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        if !consent::granted(app_handle, ConsentScope::DeepgramAudio) {
+            return Some("Sending audio to Deepgram has not been consented to".to_string());
+        }
+        if let Err(e) = endpoints::ensure_online() {
+            return Some(e);
+        }
+        provider_keys::select_key(app_handle, Provider::Deepgram).err()
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        wav: Vec<u8>,
+    ) -> BoxFuture<'a, Result<TranscriptionResult, String>> {
+        deepgram::transcribe(app_handle, wav).boxed()
+    }
+}
+
+// Gemini's input transcription over a Live session
+struct GeminiLive;
+
+impl SttBackend for GeminiLive {
+    fn id(&self) -> &'static str {
+        "gemini_live"
+    }
+
+    fn name(&self) -> &'static str {
+        "Gemini Live"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        if !consent::granted(app_handle, ConsentScope::GeminiAudio) {
+            return Some("Sending audio to Gemini has not been consented to".to_string());
+        }
+        if let Err(e) = endpoints::ensure_online() {
+            return Some(e);
+        }
+        provider_keys::select_key(app_handle, Provider::Gemini).err()
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        wav: Vec<u8>,
+    ) -> BoxFuture<'a, Result<TranscriptionResult, String>> {
+        gemini_live::transcribe(app_handle, wav).boxed()
+    }
+}
+
+// A self-hosted OpenAI compatible server, such as faster-whisper-server
+struct WhisperServer;
+
+impl SttBackend for WhisperServer {
+    fn id(&self) -> &'static str {
+        "whisper_server"
+    }
+
+    fn name(&self) -> &'static str {
+        "Self-hosted Whisper server"
+    }
+
+    fn local(&self) -> bool {
+        false
+    }
+
+    fn unavailable(&self, _app_handle: &AppHandle, app_settings: &AppSettings) -> Option<String> {
+        match app_settings.services.whisper_url {
+            Some(_) => None,
+            None => Some("No Whisper server is configured".to_string()),
+        }
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        wav: Vec<u8>,
+    ) -> BoxFuture<'a, Result<TranscriptionResult, String>> {
+        async move {
+            let app_settings = settings::current(&app_handle.state::<SettingsState>());
+            let language = language(&app_settings);
+            let url = app_settings
+                .services
+                .whisper_url
+                .ok_or("No Whisper server is configured".to_string())?;
+            let prompt = speech::vocabulary_prompt(&app_settings.stt_vocabulary);
+            speech::transcribe_with_server(&url, wav, language, prompt).await
+        }
+        .boxed()
+    }
+}
+
+// On-device Whisper with Candle
+struct LocalWhisper;
+
+impl SttBackend for LocalWhisper {
+    fn id(&self) -> &'static str {
+        "local_whisper"
+    }
+
+    fn name(&self) -> &'static str {
+        "On-device Whisper"
+    }
+
+    fn local(&self) -> bool {
+        true
+    }
+
+    // Checks the model transcription will actually load, which may be the
+    // smaller one while the device is throttled
+    fn unavailable(&self, app_handle: &AppHandle, _app_settings: &AppSettings) -> Option<String> {
+        let model = thermal::offline_model(app_handle);
+        match whisper::model_dir(app_handle, &model) {
+            Ok(dir) if dir.exists() => None,
+            Ok(_) => Some(format!("Offline model {} is not installed", model)),
+            Err(e) => Some(e),
+        }
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        wav: Vec<u8>,
+    ) -> BoxFuture<'a, Result<TranscriptionResult, String>> {
+        async move {
+            let (samples, sample_rate) = speech::decode_wav(&wav)?;
+            speech::transcribe_with_whisper_offline(app_handle, &samples, sample_rate).await
+        }
+        .boxed()
+    }
+}
+
+// Every backend, in the order they are listed to the user
+static BACKENDS: &[&dyn SttBackend] = &[&WhisperApi, &Deepgram, &GeminiLive, &WhisperServer, &LocalWhisper];
+
+// Error for a transcription stopped by cancel_transcription; it is not retried
+pub const CANCELLED: &str = "Transcription cancelled";
+
+// Shared by every transcription in flight; cancelling swaps in a fresh token
+pub type CancelState = Mutex<CancellationToken>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SttAttempt {
+    pub backend: String,
+    // 0 when the backend was skipped as unavailable
+    pub attempt: u32,
+    pub error: String,
+}
+
+// Emitted as stt://failed when every backend in the chain has failed
+#[derive(Serialize, Clone, Debug)]
+pub struct SttFailure {
+    pub attempts: Vec<SttAttempt>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SttBackendInfo {
+    pub id: String,
+    pub name: String,
+    pub local: bool,
+    pub available: bool,
+    pub unavailable_reason: Option<String>,
+    pub selected: bool,
+    pub diarization: bool,
+}
+
+fn find(id: &str) -> Option<&'static dyn SttBackend> {
+    BACKENDS.iter().copied().find(|backend| backend.id() == id)
+}
+
+// Offline mode always stays on the device; otherwise the chosen backend, or
+// the self-hosted server when one is configured and the Whisper API if not
+pub fn active(app_settings: &AppSettings) -> &'static dyn SttBackend {
+    if app_settings.offline_transcription {
+        return &LocalWhisper;
+    }
+    if let Some(backend) = app_settings.stt_backend.as_deref().and_then(find) {
+        return backend;
+    }
+    match app_settings.services.whisper_url {
+        Some(_) => &WhisperServer,
+        None => &WhisperApi,
+    }
+}
+
+// The active backend followed by the configured fallbacks; offline mode
+// never falls back to a network backend. When no backend is chosen, every
+// usable backend is considered and the fastest healthy one goes first.
+fn chain(app_handle: &AppHandle, app_settings: &AppSettings) -> Vec<&'static dyn SttBackend> {
+    let mut chain = vec![active(app_settings)];
+    if app_settings.offline_transcription {
+        return chain;
+    }
+    for backend in app_settings.stt_fallback.chain.iter().filter_map(|id| find(id)) {
+        if !chain.iter().any(|tried| tried.id() == backend.id()) {
+            chain.push(backend);
+        }
+    }
+    if app_settings.stt_backend.is_some() {
+        return chain;
+    }
+    for backend in BACKENDS {
+        if !chain.iter().any(|tried| tried.id() == backend.id()) && backend.unavailable(app_handle, app_settings).is_none() {
+            chain.push(*backend);
+        }
+    }
+    let ids: Vec<&str> = chain.iter().map(|backend| backend.id()).collect();
+    routing::order(app_handle, Service::Stt, &ids)
+        .into_iter()
+        .filter_map(find)
+        .collect()
+}
+
+tokio::task_local! {
+    // Spoken language fixed for the transcription running in this task; see transcribe_in
+    static FORCED_LANGUAGE: String;
+}
+
+// The language backends should expect: the one forced by transcribe_in, otherwise the setting
+pub fn language(app_settings: &AppSettings) -> Option<String> {
+    FORCED_LANGUAGE
+        .try_with(|language| language.clone())
+        .ok()
+        .or_else(|| app_settings.stt_language.clone())
+}
+
+// Transcribe speech known to be in a language, whatever the language setting says
+pub async fn transcribe_in(app_handle: &AppHandle, wav: Vec<u8>, language: &str) -> Result<TranscriptionResult, String> {
+    FORCED_LANGUAGE.scope(language.to_string(), transcribe(app_handle, wav)).await
+}
+
+// Transcribe a WAV with each backend in the chain until one succeeds or the
+// transcription is cancelled. Cancelling drops the in-flight HTTP request or
+// WebSocket; on-device decoding runs to the end and its result is dropped.
+pub async fn transcribe(app_handle: &AppHandle, wav: Vec<u8>) -> Result<TranscriptionResult, String> {
+    let token = app_handle
+        .state::<CancelState>()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .clone();
+    tokio::select! {
+        result = transcribe_with_fallback(app_handle, wav) => result,
+        _ = token.cancelled() => Err(CANCELLED.to_string()),
+    }
+}
+
+// Exponential backoff before retry number `attempt`, capped at 64 times the base
+fn backoff(policy: &SttFallbackSettings, attempt: u32) -> Duration {
+    Duration::from_millis(policy.backoff_ms.saturating_mul(1 << attempt.saturating_sub(1).min(6)))
+}
+
+async fn transcribe_with_fallback(app_handle: &AppHandle, wav: Vec<u8>) -> Result<TranscriptionResult, String> {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let policy = &app_settings.stt_fallback;
+    let mut attempts = Vec::new();
+    for backend in chain(app_handle, &app_settings) {
+        if let Some(reason) = backend.unavailable(app_handle, &app_settings) {
+            attempts.push(SttAttempt {
+                backend: backend.id().to_string(),
+                attempt: 0,
+                error: reason,
+            });
+            continue;
+        }
+        for attempt in 1..=policy.retries.saturating_add(1) {
+            let started = Instant::now();
+            let result = backend.transcribe(app_handle, wav.clone()).await;
+            routing::record(app_handle, Service::Stt, backend.id(), started.elapsed(), result.is_ok());
+            match result {
+                Ok(result) => return Ok(result),
+                Err(error) => attempts.push(SttAttempt {
+                    backend: backend.id().to_string(),
+                    attempt,
+                    error,
+                }),
+            }
+            if attempt <= policy.retries {
+                tokio::time::sleep(backoff(policy, attempt)).await;
+            }
+        }
+    }
+
+    let summary = attempts
+        .iter()
+        .map(|attempt| format!("{} ({}): {}", attempt.backend, attempt.attempt, attempt.error))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let _ = app_handle.emit("stt://failed", SttFailure { attempts });
+    Err(format!("Transcription failed with every backend: {}", summary))
+}
+
+fn list(app_handle: &AppHandle, app_settings: &AppSettings) -> Vec<SttBackendInfo> {
+    let selected = active(app_settings).id();
+    BACKENDS
+        .iter()
+        .map(|backend| {
+            let reason = backend.unavailable(app_handle, app_settings);
+            SttBackendInfo {
+                id: backend.id().to_string(),
+                name: backend.name().to_string(),
+                local: backend.local(),
+                available: reason.is_none(),
+                unavailable_reason: reason,
+                selected: backend.id() == selected,
+                diarization: backend.diarizes(),
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn list_stt_backends(app_handle: AppHandle, settings_state: State<'_, SettingsState>) -> Vec<SttBackendInfo> {
+    list(&app_handle, &settings::current(&settings_state))
+}
+
+// Command to choose the transcription backend by id; None picks automatically
+#[tauri::command]
+pub fn set_stt_backend(
+    id: Option<String>,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<SttBackendInfo>, String> {
+    if let Some(id) = &id {
+        find(id).ok_or(format!("Unknown transcription backend: {}", id))?;
+    }
+    let mut updated = settings::current(&settings_state);
+    updated.stt_backend = id;
+    settings::update_settings(updated.clone(), app_handle.clone(), settings_state)?;
+    Ok(list(&app_handle, &updated))
+}
+
+// Command to turn speaker labels on or off; only backends listed with
+// diarization support apply it
+#[tauri::command]
+pub fn set_stt_diarization(
+    enabled: bool,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    let mut updated = settings::current(&settings_state);
+    updated.stt_diarization = enabled;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+// Command to stop every transcription in flight; each fails with CANCELLED
+#[tauri::command]
+pub fn cancel_transcription(app_handle: AppHandle, state: State<'_, CancelState>) -> Result<(), String> {
+    let mut token = state.lock().map_err(|e| e.to_string())?;
+    token.cancel();
+    *token = CancellationToken::new();
+    let _ = app_handle.emit("stt://cancelled", ());
+    Ok(())
+}
+
+// Command to set the backends tried after the active one, and how often each is retried
+#[tauri::command]
+pub fn set_stt_fallback(
+    fallback: SttFallbackSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<(), String> {
+    if let Some(id) = fallback.chain.iter().find(|id| find(id).is_none()) {
+        return Err(format!("Unknown transcription backend: {}", id));
+    }
+    let mut updated = settings::current(&settings_state);
+    updated.stt_fallback = fallback;
+    settings::update_settings(updated, app_handle, settings_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_ids_are_unique() {
+        for (i, backend) in BACKENDS.iter().enumerate() {
+            assert!(BACKENDS[..i].iter().all(|other| other.id() != backend.id()));
+            assert_eq!(find(backend.id()).map(|found| found.id()), Some(backend.id()));
+        }
+        assert!(find("dictaphone").is_none());
+        assert!(find("local_whisper").is_some_and(|backend| backend.local()));
+    }
+
+    #[test]
+    fn active_backend_follows_settings() {
+        let mut app_settings = AppSettings::default();
+        assert_eq!(active(&app_settings).id(), "openai_whisper");
+        app_settings.services.whisper_url = Some("http://nas.local:8000".to_string());
+        assert_eq!(active(&app_settings).id(), "whisper_server");
+        app_settings.stt_backend = Some("deepgram".to_string());
+        assert_eq!(active(&app_settings).id(), "deepgram");
+        // An unknown saved id falls back to the automatic choice
+        app_settings.stt_backend = Some("removed".to_string());
+        assert_eq!(active(&app_settings).id(), "whisper_server");
+        app_settings.offline_transcription = true;
+        assert_eq!(active(&app_settings).id(), "local_whisper");
+    }
+
+    #[test]
+    fn backoff_doubles_and_saturates() {
+        let policy = SttFallbackSettings::default();
+        assert_eq!(backoff(&policy, 1), Duration::from_millis(500));
+        assert_eq!(backoff(&policy, 2), Duration::from_millis(1000));
+        assert_eq!(backoff(&policy, 20), Duration::from_millis(500 * 64));
+        let huge = SttFallbackSettings {
+            backoff_ms: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(backoff(&huge, 3), Duration::from_millis(u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn forced_language_overrides_the_setting() {
+        let app_settings = AppSettings {
+            stt_language: Some("en".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(language(&app_settings).as_deref(), Some("en"));
+        let forced = FORCED_LANGUAGE.scope("de".to_string(), async { language(&app_settings) }).await;
+        assert_eq!(forced.as_deref(), Some("de"));
+        assert!(language(&AppSettings::default()).is_none());
+    }
+}
@@ -0,0 +1,471 @@
+// Client-side encryption for synced data. Payloads are sealed with
+// XChaCha20-Poly1305 before they leave the device, so remote storage only
+// ever holds ciphertext.
+//
+// A random master key encrypts the keyring of data keys. The master key is
+// wrapped twice, with a key derived by Argon2id from the passphrase and with
+// one derived from a recovery code, so either can unlock it. Rotating adds a
+// data key and keeps the old ones, so earlier payloads still open.
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key as CipherKey, XChaCha20Poly1305, XNonce};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+use crate::conversations;
+use crate::storage;
+
+const KEYS_FILE: &str = "sync_keys.json";
+const ENVELOPE_VERSION: u32 = 1;
+const MIN_PASSPHRASE_CHARS: usize = 10;
+// Without 0, 1, I, L and O, so codes survive being written down
+const RECOVERY_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const RECOVERY_CHARS: usize = 25;
+
+type Key = [u8; 32];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncPayload {
+    Settings,
+    Conversations,
+    Notes,
+}
+
+impl SyncPayload {
+    // Conversations are in a database rather than a file, see conversations::export
+    fn file(self) -> Option<&'static str> {
+        match self {
+            SyncPayload::Settings => Some("settings.json"),
+            SyncPayload::Conversations => None,
+            SyncPayload::Notes => Some("notes.json"),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SyncPayload::Settings => "settings",
+            SyncPayload::Conversations => "conversations",
+            SyncPayload::Notes => "notes",
+        }
+    }
+}
+
+// What is uploaded in place of a payload
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncEnvelope {
+    pub version: u32,
+    pub kind: SyncPayload,
+    pub key_id: u32,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct WrappedKey {
+    salt: String,
+    sealed: Sealed,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct KeyFile {
+    passphrase: Option<WrappedKey>,
+    recovery: Option<WrappedKey>,
+    // The keyring, sealed with the master key
+    keyring: Option<Sealed>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Keyring {
+    current: u32,
+    keys: BTreeMap<u32, String>,
+}
+
+// The master key and keyring, held only while unlocked
+#[derive(Default)]
+pub struct SyncKeys {
+    unlocked: Option<(Key, Keyring)>,
+}
+
+pub type SyncKeyState = Mutex<SyncKeys>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SyncEncryptionStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+    pub current_key: Option<u32>,
+    pub keys: usize,
+}
+
+fn random_key() -> Key {
+    rand::thread_rng().gen()
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, String> {
+    BASE64.decode(value).map_err(|e| e.to_string())
+}
+
+fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<Sealed, String> {
+    let cipher = XChaCha20Poly1305::new(CipherKey::from_slice(key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| "Encryption failed".to_string())?;
+    Ok(Sealed {
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn open(key: &Key, sealed: &Sealed, aad: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce = decode(&sealed.nonce)?;
+    if nonce.len() != 24 {
+        return Err("Invalid nonce".to_string());
+    }
+    XChaCha20Poly1305::new(CipherKey::from_slice(key))
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &decode(&sealed.ciphertext)?, aad })
+        .map_err(|_| "Decryption failed: wrong key or tampered data".to_string())
+}
+
+// Argon2id with its default cost; run off the async runtime since it is slow on purpose
+async fn derive(secret: String, salt: Vec<u8>) -> Result<Key, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(secret.as_bytes(), &salt, &mut key)
+            .map_err(|e| e.to_string())?;
+        Ok(key)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+async fn wrap_key(master: &Key, secret: String, purpose: &str) -> Result<WrappedKey, String> {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    let key = derive(secret, salt.to_vec()).await?;
+    Ok(WrappedKey {
+        salt: BASE64.encode(salt),
+        sealed: seal(&key, master, purpose.as_bytes())?,
+    })
+}
+
+async fn unwrap_key(wrapped: &WrappedKey, secret: String, purpose: &str) -> Result<Key, String> {
+    let key = derive(secret, decode(&wrapped.salt)?).await?;
+    let master = open(&key, &wrapped.sealed, purpose.as_bytes())
+        .map_err(|_| format!("Incorrect {}", purpose.replace('_', " ")))?;
+    master.try_into().map_err(|_| "Invalid master key".to_string())
+}
+
+fn new_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: Vec<char> = (0..RECOVERY_CHARS)
+        .map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char)
+        .collect();
+    chars
+        .chunks(5)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Accept codes typed in lower case or without the dashes
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("The passphrase must be at least {} characters", MIN_PASSPHRASE_CHARS));
+    }
+    Ok(())
+}
+
+fn load_keyring(master: &Key, key_file: &KeyFile) -> Result<Keyring, String> {
+    let sealed = key_file.keyring.as_ref().ok_or("Sync encryption is not set up".to_string())?;
+    serde_json::from_slice(&open(master, sealed, b"keyring")?).map_err(|e| e.to_string())
+}
+
+fn save_keyring(app_handle: &AppHandle, master: &Key, key_file: &mut KeyFile, keyring: &Keyring) -> Result<(), String> {
+    let plaintext = serde_json::to_vec(keyring).map_err(|e| e.to_string())?;
+    key_file.keyring = Some(seal(master, &plaintext, b"keyring")?);
+    storage::save_json(app_handle, KEYS_FILE, key_file)
+}
+
+fn status(key_file: &KeyFile, keys: &SyncKeys) -> SyncEncryptionStatus {
+    let keyring = keys.unlocked.as_ref().map(|(_, keyring)| keyring);
+    SyncEncryptionStatus {
+        enabled: key_file.keyring.is_some(),
+        unlocked: keyring.is_some(),
+        current_key: keyring.map(|keyring| keyring.current),
+        keys: keyring.map(|keyring| keyring.keys.len()).unwrap_or(0),
+    }
+}
+
+fn data_key(keyring: &Keyring, id: u32) -> Result<Key, String> {
+    let key = keyring.keys.get(&id).ok_or(format!("Unknown sync key {}", id))?;
+    decode(key)?.try_into().map_err(|_| "Invalid sync key".to_string())
+}
+
+fn unlocked(state: &SyncKeyState) -> Result<(Key, Keyring), String> {
+    state
+        .lock()
+        .map_err(|e| e.to_string())?
+        .unlocked
+        .clone()
+        .ok_or("Sync encryption is locked".to_string())
+}
+
+// Encrypt a payload with the current data key; the kind is authenticated so
+// one payload cannot be passed off as another
+pub fn seal_payload(state: &SyncKeyState, kind: SyncPayload, plaintext: &[u8]) -> Result<SyncEnvelope, String> {
+    let (_, keyring) = unlocked(state)?;
+    let sealed = seal(&data_key(&keyring, keyring.current)?, plaintext, kind.name().as_bytes())?;
+    Ok(SyncEnvelope {
+        version: ENVELOPE_VERSION,
+        kind,
+        key_id: keyring.current,
+        nonce: sealed.nonce,
+        ciphertext: sealed.ciphertext,
+    })
+}
+
+pub fn open_payload(state: &SyncKeyState, envelope: &SyncEnvelope) -> Result<Vec<u8>, String> {
+    if envelope.version != ENVELOPE_VERSION {
+        return Err(format!("Unsupported sync envelope version {}", envelope.version));
+    }
+    let (_, keyring) = unlocked(state)?;
+    let sealed = Sealed {
+        nonce: envelope.nonce.clone(),
+        ciphertext: envelope.ciphertext.clone(),
+    };
+    open(&data_key(&keyring, envelope.key_id)?, &sealed, envelope.kind.name().as_bytes())
+}
+
+#[tauri::command]
+pub fn get_sync_encryption_status(
+    app_handle: AppHandle,
+    state: State<'_, SyncKeyState>,
+) -> Result<SyncEncryptionStatus, String> {
+    let key_file: KeyFile = storage::load_json(&app_handle, KEYS_FILE);
+    let keys = state.lock().map_err(|e| e.to_string())?;
+    Ok(status(&key_file, &keys))
+}
+
+// Command to set up encryption; returns the recovery code, which is shown once and never stored
+#[tauri::command]
+pub async fn enable_sync_encryption(
+    passphrase: String,
+    app_handle: AppHandle,
+    state: State<'_, SyncKeyState>,
+) -> Result<String, String> {
+    check_passphrase(&passphrase)?;
+    let mut key_file: KeyFile = storage::load_json(&app_handle, KEYS_FILE);
+    if key_file.keyring.is_some() {
+        return Err("Sync encryption is already set up".to_string());
+    }
+    let master = random_key();
+    let recovery_code = new_recovery_code();
+    key_file.passphrase = Some(wrap_key(&master, passphrase, "passphrase").await?);
+    key_file.recovery = Some(wrap_key(&master, normalize_recovery_code(&recovery_code), "recovery_code").await?);
+    let keyring = Keyring {
+        current: 1,
+        keys: BTreeMap::from([(1, BASE64.encode(random_key()))]),
+    };
+    save_keyring(&app_handle, &master, &mut key_file, &keyring)?;
+    state.lock().map_err(|e| e.to_string())?.unlocked = Some((master, keyring));
+    Ok(recovery_code)
+}
+
+#[tauri::command]
+pub async fn unlock_sync_encryption(
+    passphrase: String,
+    app_handle: AppHandle,
+    state: State<'_, SyncKeyState>,
+) -> Result<SyncEncryptionStatus, String> {
+    let key_file: KeyFile = storage::load_json(&app_handle, KEYS_FILE);
+    let wrapped = key_file.passphrase.as_ref().ok_or("Sync encryption is not set up".to_string())?;
+    let master = unwrap_key(wrapped, passphrase, "passphrase").await?;
+    let keyring = load_keyring(&master, &key_file)?;
+    let mut keys = state.lock().map_err(|e| e.to_string())?;
+    keys.unlocked = Some((master, keyring));
+    Ok(status(&key_file, &keys))
+}
+
+#[tauri::command]
+pub fn lock_sync_encryption(state: State<'_, SyncKeyState>) -> Result<(), String> {
+    state.lock().map_err(|e| e.to_string())?.unlocked = None;
+    Ok(())
+}
+
+// Command to change the passphrase; the keys themselves are unchanged
+#[tauri::command]
+pub async fn change_sync_passphrase(
+    new_passphrase: String,
+    app_handle: AppHandle,
+    state: State<'_, SyncKeyState>,
+) -> Result<(), String> {
+    check_passphrase(&new_passphrase)?;
+    let (master, _) = unlocked(&state)?;
+    let mut key_file: KeyFile = storage::load_json(&app_handle, KEYS_FILE);
+    key_file.passphrase = Some(wrap_key(&master, new_passphrase, "passphrase").await?);
+    storage::save_json(&app_handle, KEYS_FILE, &key_file)
+}
+
+// Command to start encrypting with a new data key. Older keys are kept so
+// payloads already uploaded still open until they are synced again.
+#[tauri::command]
+pub fn rotate_sync_key(app_handle: AppHandle, state: State<'_, SyncKeyState>) -> Result<SyncEncryptionStatus, String> {
+    let (master, mut keyring) = unlocked(&state)?;
+    let id = keyring.keys.keys().max().copied().unwrap_or(0) + 1;
+    keyring.keys.insert(id, BASE64.encode(random_key()));
+    keyring.current = id;
+    let mut key_file: KeyFile = storage::load_json(&app_handle, KEYS_FILE);
+    save_keyring(&app_handle, &master, &mut key_file, &keyring)?;
+    let mut keys = state.lock().map_err(|e| e.to_string())?;
+    keys.unlocked = Some((master, keyring));
+    Ok(status(&key_file, &keys))
+}
+
+// Command to set a new passphrase with the recovery code. The used code is
+// replaced and the new one returned.
+#[tauri::command]
+pub async fn recover_sync_encryption(
+    recovery_code: String,
+    new_passphrase: String,
+    app_handle: AppHandle,
+    state: State<'_, SyncKeyState>,
+) -> Result<String, String> {
+    check_passphrase(&new_passphrase)?;
+    let mut key_file: KeyFile = storage::load_json(&app_handle, KEYS_FILE);
+    let wrapped = key_file.recovery.as_ref().ok_or("Sync encryption is not set up".to_string())?;
+    let master = unwrap_key(wrapped, normalize_recovery_code(&recovery_code), "recovery_code").await?;
+    let keyring = load_keyring(&master, &key_file)?;
+    let new_code = new_recovery_code();
+    key_file.passphrase = Some(wrap_key(&master, new_passphrase, "passphrase").await?);
+    key_file.recovery = Some(wrap_key(&master, normalize_recovery_code(&new_code), "recovery_code").await?);
+    storage::save_json(&app_handle, KEYS_FILE, &key_file)?;
+    state.lock().map_err(|e| e.to_string())?.unlocked = Some((master, keyring));
+    Ok(new_code)
+}
+
+// Command to encrypt a stored payload for upload
+#[tauri::command]
+pub fn seal_sync_payload(
+    kind: SyncPayload,
+    app_handle: AppHandle,
+    state: State<'_, SyncKeyState>,
+) -> Result<SyncEnvelope, String> {
+    let plaintext = match kind.file() {
+        // A file not written yet holds the defaults, like load_json
+        Some(file) => std::fs::read(storage::data_file(&app_handle, file)?).unwrap_or_else(|_| b"{}".to_vec()),
+        None => conversations::export(&app_handle)?,
+    };
+    seal_payload(&state, kind, &plaintext)
+}
+
+// Command to decrypt a downloaded payload back to its JSON
+#[tauri::command]
+pub fn open_sync_payload(envelope: SyncEnvelope, state: State<'_, SyncKeyState>) -> Result<serde_json::Value, String> {
+    serde_json::from_slice(&open_payload(&state, &envelope)?).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlocked_state(keyring: Keyring) -> SyncKeyState {
+        Mutex::new(SyncKeys {
+            unlocked: Some((random_key(), keyring)),
+        })
+    }
+
+    fn keyring(current: u32, ids: &[u32]) -> Keyring {
+        Keyring {
+            current,
+            keys: ids.iter().map(|id| (*id, BASE64.encode(random_key()))).collect(),
+        }
+    }
+
+    #[test]
+    fn sealed_data_needs_the_same_key_and_context() {
+        let key = random_key();
+        let sealed = seal(&key, b"hello", b"notes").unwrap();
+        assert_eq!(open(&key, &sealed, b"notes").unwrap(), b"hello");
+        assert!(open(&key, &sealed, b"settings").is_err());
+        assert!(open(&random_key(), &sealed, b"notes").is_err());
+
+        let mut tampered = decode(&sealed.ciphertext).unwrap();
+        tampered[0] ^= 1;
+        let tampered = Sealed {
+            nonce: sealed.nonce.clone(),
+            ciphertext: BASE64.encode(tampered),
+        };
+        assert!(open(&key, &tampered, b"notes").is_err());
+    }
+
+    #[test]
+    fn payloads_open_with_the_key_they_were_sealed_with() {
+        let state = unlocked_state(keyring(1, &[1]));
+        let envelope = seal_payload(&state, SyncPayload::Notes, b"{\"notes\":[]}").unwrap();
+        assert_eq!(envelope.key_id, 1);
+
+        // After rotating, the old payload still opens with key 1
+        {
+            let mut keys = state.lock().unwrap();
+            let (_, keyring) = keys.unlocked.as_mut().unwrap();
+            keyring.keys.insert(2, BASE64.encode(random_key()));
+            keyring.current = 2;
+        }
+        assert_eq!(open_payload(&state, &envelope).unwrap(), b"{\"notes\":[]}");
+        assert_eq!(seal_payload(&state, SyncPayload::Notes, b"{}").unwrap().key_id, 2);
+    }
+
+    #[test]
+    fn payloads_cannot_be_passed_off_as_another_kind() {
+        let state = unlocked_state(keyring(1, &[1]));
+        let mut envelope = seal_payload(&state, SyncPayload::Settings, b"{}").unwrap();
+        envelope.kind = SyncPayload::Notes;
+        assert!(open_payload(&state, &envelope).is_err());
+    }
+
+    #[test]
+    fn locked_keys_and_unknown_versions_are_refused() {
+        let locked = SyncKeyState::default();
+        assert!(seal_payload(&locked, SyncPayload::Notes, b"{}").is_err());
+
+        let state = unlocked_state(keyring(1, &[1]));
+        let mut envelope = seal_payload(&state, SyncPayload::Notes, b"{}").unwrap();
+        envelope.version = ENVELOPE_VERSION + 1;
+        assert!(open_payload(&state, &envelope).is_err());
+    }
+
+    #[test]
+    fn recovery_codes_are_grouped_and_normalized() {
+        let code = new_recovery_code();
+        let groups: Vec<&str> = code.split('-').collect();
+        assert_eq!(groups.len(), RECOVERY_CHARS / 5);
+        assert!(groups.iter().all(|group| group.len() == 5));
+        assert!(code.bytes().filter(|c| *c != b'-').all(|c| RECOVERY_ALPHABET.contains(&c)));
+        assert_eq!(normalize_recovery_code(&code.to_lowercase().replace('-', " ")), code.replace('-', ""));
+    }
+
+    #[test]
+    fn short_passphrases_are_refused() {
+        assert!(check_passphrase("too short").is_err());
+        assert!(check_passphrase("long enough now").is_ok());
+    }
+}
@@ -0,0 +1,223 @@
+// Deterministic stand-ins for the hosted providers, so commands such as
+// transcribe_audio and process_text_input can run end to end without network
+// access or real keys. Only built with the test-support feature; the tests
+// below build the whole app, which needs a display on Linux (e.g. run them
+// under xvfb-run cargo test --features test-support).
+
+use serde_json::json;
+use wiremock::matchers::{body_string_contains, method, path, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::endpoints::{self, Endpoint};
+
+pub const GEMINI_REPLY: &str = "This is a mock reply.";
+pub const TRANSCRIPT: &str = "this is a mock transcript";
+pub const GROUNDED_REPLY: &str = "This is a mock grounded reply.";
+pub const SOURCE_URL: &str = "https://example.com/source";
+pub const SEARCH_TITLE: &str = "A mock search result";
+// Degrees Fahrenheit, as requested by the weather clients
+pub const TEMPERATURE: f64 = 68.0;
+
+pub struct MockProviders {
+    pub gemini: MockServer,
+    pub openai: MockServer,
+    pub openweather: MockServer,
+    // A SearXNG instance; its URL is a setting rather than an endpoint, see searxng_url
+    pub search: MockServer,
+}
+
+async fn gemini() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/v1beta/models/[^/]+:generateContent$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "candidates": [{ "content": { "parts": [{ "text": GEMINI_REPLY }] } }],
+            "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": 5 }
+        })))
+        .mount(&server)
+        .await;
+    // Search grounded requests get an answer citing one web source
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/v1beta/models/[^/]+:generateContent$"))
+        .and(body_string_contains("google_search_retrieval"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": GROUNDED_REPLY }] },
+                "groundingMetadata": {
+                    "groundingChunks": [{ "web": { "uri": SOURCE_URL, "title": "Example" } }],
+                    "groundingSupports": [{
+                        "segment": { "text": GROUNDED_REPLY },
+                        "groundingChunkIndices": [0],
+                        "confidenceScores": [0.9]
+                    }]
+                }
+            }],
+            "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": 5 }
+        })))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    // The same reply as server-sent events, split in two
+    let (first, second) = GEMINI_REPLY.split_at(GEMINI_REPLY.len() / 2);
+    let events = [
+        json!({ "candidates": [{ "content": { "parts": [{ "text": first }] } }] }),
+        json!({
+            "candidates": [{ "content": { "parts": [{ "text": second }] } }],
+            "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": 5 }
+        }),
+    ];
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/v1beta/models/[^/]+:streamGenerateContent$"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "text/event-stream")
+                .set_body_string(events.iter().map(|event| format!("data: {}\r\n\r\n", event)).collect::<String>()),
+        )
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn openai() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/audio/transcriptions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "text": TRANSCRIPT })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/moderations"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "results": [{ "flagged": false, "categories": {} }]
+        })))
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn openweather() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/data/2.5/weather"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "main": { "temp": TEMPERATURE },
+            "weather": [{ "icon": "01d" }]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/data/3.0/onecall"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "current": { "temp": TEMPERATURE },
+            "minutely": [],
+            "alerts": []
+        })))
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn search() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/search"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "results": [{
+                "title": SEARCH_TITLE,
+                "url": "https://example.com/result",
+                "content": "A snippet from the mock result."
+            }]
+        })))
+        .mount(&server)
+        .await;
+    server
+}
+
+impl MockProviders {
+    // Start the mock servers, point every provider client at them and supply
+    // placeholder keys so key selection succeeds
+    pub async fn start() -> MockProviders {
+        let providers = MockProviders {
+            gemini: gemini().await,
+            openai: openai().await,
+            openweather: openweather().await,
+            search: search().await,
+        };
+        endpoints::set_base_url(Endpoint::Gemini, Some(providers.gemini.uri()));
+        endpoints::set_base_url(Endpoint::OpenAi, Some(providers.openai.uri()));
+        endpoints::set_base_url(Endpoint::OpenWeather, Some(providers.openweather.uri()));
+        std::env::set_var("GEMINI_API_KEY", "test");
+        std::env::set_var("OPENAI_API_KEY", "test");
+        std::env::set_var("OPENWEATHER_API_KEY", "test");
+        providers
+    }
+
+    // For services.searxng_url in the settings
+    pub fn searxng_url(&self) -> String {
+        self.search.uri()
+    }
+}
+
+impl Drop for MockProviders {
+    fn drop(&mut self) {
+        for endpoint in [Endpoint::Gemini, Endpoint::OpenAi, Endpoint::OpenWeather] {
+            endpoints::set_base_url(endpoint, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::{App, Manager, Wry};
+
+    use crate::consent::{self, ConsentScope};
+    use crate::engine;
+    use crate::features::{self, Feature};
+    use crate::settings::{AppSettings, SettingsState};
+    use crate::tools::{FetchSearchResults, Tool};
+    use crate::widgets::{self, WidgetKind};
+
+    fn app() -> App<Wry> {
+        crate::builder()
+            // The test harness runs tests off the main thread
+            .any_thread()
+            .build(tauri::test::mock_context(tauri::test::noop_assets()))
+            .expect("failed to build the app")
+    }
+
+    fn grant(app: &App<Wry>, scope: ConsentScope) {
+        consent::grant_consent(scope, app.handle().clone(), app.state()).unwrap();
+    }
+
+    // One test drives every provider, as the mock base URLs are process wide
+    // and only one event loop can be created per process
+    #[tokio::test]
+    async fn commands_run_against_the_mock_providers() {
+        let providers = MockProviders::start().await;
+        let app = app();
+        let app_handle = app.handle().clone();
+        let settings = AppSettings::default();
+
+        // Consents are stored with the app's data, so a previous run may have left one
+        let _ = consent::revoke_consent(ConsentScope::WeatherLocation, app_handle.clone(), app.state());
+        assert!(widgets::render(&app_handle, WidgetKind::Weather).await.is_err());
+        grant(&app, ConsentScope::WeatherLocation);
+        let weather = crate::fetch_weather(&app_handle, 40.7, -74.0).await.unwrap();
+        assert_eq!(weather.temperature, format!("{:.0}°F", TEMPERATURE));
+        assert!(weather.icon.unwrap().ends_with("/01d@2x.png"));
+
+        grant(&app, ConsentScope::GeminiText);
+        let reply = engine::respond(&app_handle, "Hello", &settings).await.unwrap();
+        assert!(reply.contains(GEMINI_REPLY), "unexpected reply: {}", reply);
+
+        let answer = engine::respond_with_sources(&app_handle, "What is new?", &settings).await.unwrap();
+        assert!(answer.text.contains(GROUNDED_REPLY), "unexpected answer: {}", answer.text);
+        assert!(answer.citations.iter().any(|citation| citation.source == SOURCE_URL));
+
+        features::set_feature_override(Feature::MetaSearch, Some(true), app_handle.clone(), app.state()).unwrap();
+        app.state::<SettingsState>().lock().unwrap().services.searxng_url = Some(providers.searxng_url());
+        let found = FetchSearchResults.call(&app_handle, json!({ "query": "mock" })).await.unwrap();
+        assert_eq!(found["results"][0]["title"], SEARCH_TITLE);
+    }
+}
@@ -0,0 +1,162 @@
+// Thermal-aware throttling. The platform layer reports the device's thermal
+// state; while it runs hot, local inference switches to a smaller model and
+// fewer threads, and background work such as prefetching waits.
+
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::settings::{self, AppSettings, PerformanceMode, SettingsState};
+use crate::whisper;
+
+// Levels as reported by iOS ProcessInfo.thermalState; Android's
+// PowerManager thermal status is mapped onto these by the platform layer
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThermalLevel {
+    #[default]
+    Nominal,
+    Fair,
+    Serious,
+    Critical,
+}
+
+#[derive(Default)]
+pub struct ThermalStore {
+    level: ThermalLevel,
+}
+
+pub type ThermalState = Mutex<ThermalStore>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PerformanceStatus {
+    pub thermal: ThermalLevel,
+    pub mode: PerformanceMode,
+    // Whether the mode comes from settings rather than the thermal state
+    pub overridden: bool,
+    pub inference_threads: Option<usize>,
+    pub offline_model: String,
+}
+
+fn level(app_handle: &AppHandle) -> ThermalLevel {
+    app_handle
+        .state::<ThermalState>()
+        .lock()
+        .map(|store| store.level)
+        .unwrap_or_default()
+}
+
+fn resolve(level: ThermalLevel, app_settings: &AppSettings) -> PerformanceMode {
+    app_settings.performance.mode.unwrap_or(match level {
+        ThermalLevel::Nominal | ThermalLevel::Fair => PerformanceMode::Full,
+        ThermalLevel::Serious => PerformanceMode::Reduced,
+        ThermalLevel::Critical => PerformanceMode::Minimal,
+    })
+}
+
+pub fn mode(app_handle: &AppHandle) -> PerformanceMode {
+    resolve(level(app_handle), &settings::current(&app_handle.state::<SettingsState>()))
+}
+
+// Threads for local inference; None uses one per core
+fn threads_for(mode: PerformanceMode) -> Option<usize> {
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    match mode {
+        PerformanceMode::Full => None,
+        PerformanceMode::Reduced => Some((cores / 2).max(1)),
+        PerformanceMode::Minimal => Some(1),
+    }
+}
+
+pub fn inference_threads(app_handle: &AppHandle) -> Option<usize> {
+    threads_for(mode(app_handle))
+}
+
+// The offline model to use: the smaller one while throttled, when it is installed
+fn model_for(app_handle: &AppHandle, mode: PerformanceMode, app_settings: &AppSettings) -> String {
+    let reduced = &app_settings.performance.reduced_model;
+    let installed = whisper::model_dir(app_handle, reduced)
+        .map(|dir| dir.exists())
+        .unwrap_or(false);
+    if mode != PerformanceMode::Full && installed {
+        reduced.clone()
+    } else {
+        app_settings.offline_model.clone()
+    }
+}
+
+pub fn offline_model(app_handle: &AppHandle) -> String {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    model_for(app_handle, resolve(level(app_handle), &app_settings), &app_settings)
+}
+
+// Background jobs only run at full performance
+pub fn allows_background(app_handle: &AppHandle) -> bool {
+    mode(app_handle) == PerformanceMode::Full
+}
+
+fn status(app_handle: &AppHandle) -> PerformanceStatus {
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    let thermal = level(app_handle);
+    let mode = resolve(thermal, &app_settings);
+    PerformanceStatus {
+        thermal,
+        mode,
+        overridden: app_settings.performance.mode.is_some(),
+        inference_threads: threads_for(mode),
+        offline_model: model_for(app_handle, mode, &app_settings),
+    }
+}
+
+// Command the platform layer calls when the thermal state changes
+#[tauri::command]
+pub fn on_thermal_state(
+    level: ThermalLevel,
+    app_handle: AppHandle,
+    state: State<'_, ThermalState>,
+) -> Result<PerformanceStatus, String> {
+    let previous = mode(&app_handle);
+    state.lock().map_err(|e| e.to_string())?.level = level;
+    let status = status(&app_handle);
+    if status.mode != previous {
+        let _ = app_handle.emit("performance://changed", &status);
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn get_performance_mode(app_handle: AppHandle) -> PerformanceStatus {
+    status(&app_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thermal_level_sets_the_mode() {
+        let app_settings = AppSettings::default();
+        assert_eq!(resolve(ThermalLevel::Nominal, &app_settings), PerformanceMode::Full);
+        assert_eq!(resolve(ThermalLevel::Fair, &app_settings), PerformanceMode::Full);
+        assert_eq!(resolve(ThermalLevel::Serious, &app_settings), PerformanceMode::Reduced);
+        assert_eq!(resolve(ThermalLevel::Critical, &app_settings), PerformanceMode::Minimal);
+    }
+
+    #[test]
+    fn settings_override_the_thermal_state() {
+        let mut app_settings = AppSettings::default();
+        app_settings.performance.mode = Some(PerformanceMode::Full);
+        assert_eq!(resolve(ThermalLevel::Critical, &app_settings), PerformanceMode::Full);
+        app_settings.performance.mode = Some(PerformanceMode::Minimal);
+        assert_eq!(resolve(ThermalLevel::Nominal, &app_settings), PerformanceMode::Minimal);
+    }
+
+    #[test]
+    fn throttled_modes_use_fewer_threads() {
+        let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        assert_eq!(threads_for(PerformanceMode::Full), None);
+        let reduced = threads_for(PerformanceMode::Reduced).unwrap();
+        assert!(reduced >= 1 && reduced <= cores);
+        assert_eq!(threads_for(PerformanceMode::Minimal), Some(1));
+    }
+}
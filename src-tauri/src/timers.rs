@@ -0,0 +1,134 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::automations;
+use crate::haptics::{self, HapticPreset};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Timer {
+    pub id: u64,
+    pub label: Option<String>,
+    pub duration_secs: u64,
+    pub ends_at: u64,
+}
+
+#[derive(Default)]
+pub struct TimerStore {
+    next_id: u64,
+    timers: HashMap<u64, Timer>,
+}
+
+pub type TimersState = Mutex<TimerStore>;
+
+// Longer timers are refused; a week covers anything anyone means
+const MAX_DURATION_SECS: u64 = 7 * 24 * 60 * 60;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// When a timer started at `now` runs out
+fn ends_at(now: u64, duration_secs: u64) -> Result<u64, String> {
+    if duration_secs == 0 {
+        return Err("Timer duration must be greater than zero".to_string());
+    }
+    if duration_secs > MAX_DURATION_SECS {
+        return Err("Timers can run for at most a week".to_string());
+    }
+    now.checked_add(duration_secs).ok_or("The timer is too long".to_string())
+}
+
+// Start a timer that emits `timer://done` when it runs out
+pub fn start_timer(
+    app_handle: &AppHandle,
+    duration_secs: u64,
+    label: Option<String>,
+) -> Result<Timer, String> {
+    let ends_at = ends_at(now_secs(), duration_secs)?;
+
+    let state = app_handle.state::<TimersState>();
+    let timer = {
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        store.next_id += 1;
+        let timer = Timer {
+            id: store.next_id,
+            label,
+            duration_secs,
+            ends_at,
+        };
+        store.timers.insert(timer.id, timer.clone());
+        timer
+    };
+
+    let app_handle = app_handle.clone();
+    let id = timer.id;
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(duration_secs)).await;
+        let finished = {
+            let state = app_handle.state::<TimersState>();
+            let mut store = match state.lock() {
+                Ok(store) => store,
+                Err(_) => return,
+            };
+            store.timers.remove(&id)
+        };
+        // A cancelled timer is no longer in the store
+        if let Some(timer) = finished {
+            let _ = app_handle.emit("timer://done", &timer);
+            let _ = haptics::play_preset(&app_handle, HapticPreset::TimerDone);
+            automations::trigger(
+                &app_handle,
+                automations::EVENT_TIMER_DONE,
+                serde_json::to_value(&timer).unwrap_or_default(),
+            );
+        }
+    });
+
+    Ok(timer)
+}
+
+#[tauri::command]
+pub fn set_timer(seconds: u64, label: Option<String>, app_handle: AppHandle) -> Result<Timer, String> {
+    start_timer(&app_handle, seconds, label)
+}
+
+#[tauri::command]
+pub fn list_timers(state: State<'_, TimersState>) -> Result<Vec<Timer>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    let mut timers: Vec<Timer> = store.timers.values().cloned().collect();
+    timers.sort_by_key(|timer| timer.ends_at);
+    Ok(timers)
+}
+
+#[tauri::command]
+pub fn cancel_timer(id: u64, state: State<'_, TimersState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store
+        .timers
+        .remove(&id)
+        .map(|_| ())
+        .ok_or("Timer not found".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ends_after_the_duration() {
+        assert_eq!(ends_at(1_000, 90), Ok(1_090));
+    }
+
+    #[test]
+    fn refuses_empty_and_overlong_timers() {
+        assert!(ends_at(1_000, 0).is_err());
+        assert!(ends_at(1_000, MAX_DURATION_SECS + 1).is_err());
+        assert!(ends_at(u64::MAX, 60).is_err());
+    }
+}
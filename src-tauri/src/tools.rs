@@ -0,0 +1,327 @@
+// Tools the assistant can call while answering. Each is a Rust function
+// declared to Gemini with a JSON schema for its arguments; the engine runs the
+// calls the model asks for and sends back the results until it answers in
+// text. Tools that act for the user are only offered when the persona allows
+// the matching action, and a child profile never gets to open links.
+
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::consent::{self, ConsentScope};
+use crate::endpoints;
+use crate::features::{self, Feature};
+use crate::persona;
+use crate::rules;
+use crate::settings::{self, AppSettings, PersonaAction, Profile, SettingsState};
+use crate::timers;
+use crate::untrusted;
+
+const MAX_SEARCH_RESULTS: usize = 5;
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    // Arguments, in the OpenAPI schema subset Gemini accepts
+    fn parameters(&self) -> Value;
+    // The persona action the tool needs, if any
+    fn action(&self) -> Option<PersonaAction> {
+        None
+    }
+    fn call<'a>(&'a self, app_handle: &'a AppHandle, args: Value) -> BoxFuture<'a, Result<Value, String>>;
+}
+
+fn arguments<T: for<'de> Deserialize<'de>>(args: Value) -> Result<T, String> {
+    serde_json::from_value(args).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+pub struct GetWeather;
+
+#[derive(Deserialize)]
+struct WeatherArgs {
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+impl Tool for GetWeather {
+    fn name(&self) -> &'static str {
+        "get_weather"
+    }
+
+    fn description(&self) -> &'static str {
+        "Current temperature at a place, or where the user is when no coordinates are given."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "latitude": { "type": "number" },
+                "longitude": { "type": "number" }
+            }
+        })
+    }
+
+    fn call<'a>(&'a self, app_handle: &'a AppHandle, args: Value) -> BoxFuture<'a, Result<Value, String>> {
+        async move {
+            let args: WeatherArgs = arguments(args)?;
+            consent::require(app_handle, ConsentScope::WeatherLocation)?;
+            let (latitude, longitude) = match (args.latitude, args.longitude) {
+                (Some(latitude), Some(longitude)) => (latitude, longitude),
+                _ => rules::last_location(app_handle).ok_or("The user's location is not known".to_string())?,
+            };
+            let weather = crate::fetch_weather(app_handle, latitude, longitude).await?;
+            Ok(json!({ "temperature": weather.temperature }))
+        }
+        .boxed()
+    }
+}
+
+pub struct FetchSearchResults;
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl Tool for FetchSearchResults {
+    fn name(&self) -> &'static str {
+        "fetch_search_results"
+    }
+
+    fn description(&self) -> &'static str {
+        "Search the web and return the top results' titles, links and snippets."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "query": { "type": "string" } },
+            "required": ["query"]
+        })
+    }
+
+    fn action(&self) -> Option<PersonaAction> {
+        Some(PersonaAction::WebSearch)
+    }
+
+    // Searches go to the user's SearXNG instance
+    fn call<'a>(&'a self, app_handle: &'a AppHandle, args: Value) -> BoxFuture<'a, Result<Value, String>> {
+        async move {
+            let args: SearchArgs = arguments(args)?;
+            features::require(app_handle, Feature::MetaSearch)?;
+            endpoints::ensure_online()?;
+            let base_url = settings::current(&app_handle.state::<SettingsState>())
+                .services
+                .searxng_url
+                .ok_or("No search service is configured".to_string())?;
+            let response = reqwest::Client::new()
+                .get(format!("{}/search", base_url.trim_end_matches('/')))
+                .query(&[("q", args.query.as_str()), ("format", "json")])
+                .timeout(SEARCH_TIMEOUT)
+                .send()
+                .await
+                .map_err(|e| e.to_string())?
+                .error_for_status()
+                .map_err(|e| e.to_string())?;
+            let found: SearxngResponse = response.json().await.map_err(|e| e.to_string())?;
+            let results: Vec<Value> = found
+                .results
+                .into_iter()
+                .take(MAX_SEARCH_RESULTS)
+                .map(|result| {
+                    json!({
+                        "title": untrusted::sanitize(&result.title),
+                        "url": untrusted::sanitize(&result.url),
+                        "snippet": untrusted::sanitize(&result.content),
+                    })
+                })
+                .collect();
+            Ok(json!({ "results": results }))
+        }
+        .boxed()
+    }
+}
+
+pub struct SetTimer;
+
+#[derive(Deserialize)]
+struct TimerArgs {
+    seconds: u64,
+    label: Option<String>,
+}
+
+impl Tool for SetTimer {
+    fn name(&self) -> &'static str {
+        "set_timer"
+    }
+
+    fn description(&self) -> &'static str {
+        "Start a countdown timer that alerts the user when it runs out."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "seconds": { "type": "integer", "description": "Length of the timer in seconds" },
+                "label": { "type": "string" }
+            },
+            "required": ["seconds"]
+        })
+    }
+
+    fn action(&self) -> Option<PersonaAction> {
+        Some(PersonaAction::Timers)
+    }
+
+    fn call<'a>(&'a self, app_handle: &'a AppHandle, args: Value) -> BoxFuture<'a, Result<Value, String>> {
+        async move {
+            let args: TimerArgs = arguments(args)?;
+            let timer = timers::start_timer(app_handle, args.seconds, args.label)?;
+            serde_json::to_value(timer).map_err(|e| e.to_string())
+        }
+        .boxed()
+    }
+}
+
+pub struct OpenLink;
+
+#[derive(Deserialize)]
+struct LinkArgs {
+    url: String,
+}
+
+impl Tool for OpenLink {
+    fn name(&self) -> &'static str {
+        "open_link"
+    }
+
+    fn description(&self) -> &'static str {
+        "Open a web page in the user's browser."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "url": { "type": "string" } },
+            "required": ["url"]
+        })
+    }
+
+    fn action(&self) -> Option<PersonaAction> {
+        Some(PersonaAction::OpenLinks)
+    }
+
+    // Only web pages; other schemes could start calls or launch apps
+    fn call<'a>(&'a self, app_handle: &'a AppHandle, args: Value) -> BoxFuture<'a, Result<Value, String>> {
+        async move {
+            let args: LinkArgs = arguments(args)?;
+            let url = url::Url::parse(&args.url).map_err(|e| e.to_string())?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err("Only web links can be opened".to_string());
+            }
+            app_handle
+                .opener()
+                .open_url(url.as_str(), None::<&str>)
+                .map_err(|e| e.to_string())?;
+            Ok(json!({ "opened": url.as_str() }))
+        }
+        .boxed()
+    }
+}
+
+static TOOLS: &[&dyn Tool] = &[&GetWeather, &FetchSearchResults, &SetTimer, &OpenLink];
+
+// The tools the persona lets the assistant use
+pub fn available(settings: &AppSettings) -> Vec<&'static dyn Tool> {
+    TOOLS
+        .iter()
+        .copied()
+        .filter(|tool| match tool.action() {
+            Some(PersonaAction::OpenLinks) if settings.profile == Profile::Child => false,
+            Some(action) => persona::allows(&settings.persona, action),
+            None => true,
+        })
+        .collect()
+}
+
+// The request's `tools` field declaring them to Gemini
+pub fn declarations(tools: &[&dyn Tool]) -> Value {
+    let functions: Vec<Value> = tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "name": tool.name(),
+                "description": tool.description(),
+                "parameters": tool.parameters(),
+            })
+        })
+        .collect();
+    json!([{ "function_declarations": functions }])
+}
+
+// Run a call the model made, as the response to send back; failures go back
+// to the model too so it can tell the user
+pub async fn call(app_handle: &AppHandle, tools: &[&dyn Tool], name: &str, args: Value) -> Value {
+    let Some(tool) = tools.iter().find(|tool| tool.name() == name) else {
+        return json!({ "error": format!("Unknown tool: {}", name) });
+    };
+    match tool.call(app_handle, args).await {
+        Ok(result) => json!({ "result": result }),
+        Err(e) => {
+            tracing::debug!(tool = name, error = %e, "tool call failed");
+            json!({ "error": e })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(settings: &AppSettings) -> Vec<&'static str> {
+        available(settings).iter().map(|tool| tool.name()).collect()
+    }
+
+    #[test]
+    fn offers_every_tool_by_default() {
+        assert_eq!(
+            names(&AppSettings::default()),
+            ["get_weather", "fetch_search_results", "set_timer", "open_link"]
+        );
+    }
+
+    #[test]
+    fn leaves_out_tools_the_persona_does_not_allow() {
+        let mut settings = AppSettings::default();
+        settings.persona.allowed_actions = vec![PersonaAction::Timers];
+        assert_eq!(names(&settings), ["get_weather", "set_timer"]);
+    }
+
+    #[test]
+    fn never_offers_links_to_a_child_profile() {
+        let mut settings = AppSettings::default();
+        settings.profile = Profile::Child;
+        assert!(!names(&settings).contains(&"open_link"));
+        assert!(names(&settings).contains(&"set_timer"));
+    }
+}
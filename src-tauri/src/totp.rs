@@ -0,0 +1,412 @@
+// Two-factor codes (RFC 6238). Accounts are added from the otpauth:// links in
+// setup QR codes; their metadata is kept in totp_accounts.json and each secret
+// in the keystore under totp:<id>. Codes are generated on the device. Reading
+// a code or removing an account needs a biometric check on phones, which then
+// holds for a short while so several codes can be read in a row.
+
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use serde::{Serialize, Deserialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager, State};
+use url::Url;
+
+#[cfg(mobile)]
+use tauri_plugin_biometric::{AuthOptions, BiometricExt};
+
+use crate::keystore::Keystore;
+use crate::storage;
+
+const ACCOUNTS_FILE: &str = "totp_accounts.json";
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD_SECS: u64 = 30;
+// How long a biometric check unlocks the vault
+const UNLOCK_SECS: u64 = 30;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TotpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+// Metadata for an account; the secret lives in the keystore under totp:<id>
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TotpAccount {
+    pub id: String,
+    pub issuer: Option<String>,
+    pub label: String,
+    pub algorithm: TotpAlgorithm,
+    pub digits: u32,
+    pub period_secs: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TotpCode {
+    pub id: String,
+    pub code: String,
+    // Until the next code
+    pub remaining_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct TotpStore {
+    next_id: u64,
+    accounts: Vec<TotpAccount>,
+    #[serde(skip)]
+    unlocked_until: u64,
+}
+
+pub type TotpState = Mutex<TotpStore>;
+
+// Accounts a hand-edited or damaged file left unusable are dropped, so they
+// can't break code generation
+pub fn load(app_handle: &AppHandle) -> TotpStore {
+    let mut store: TotpStore = storage::load_json(app_handle, ACCOUNTS_FILE);
+    store.accounts.retain(|account| check(account.digits, account.period_secs).is_ok());
+    store
+}
+
+fn check(digits: u32, period_secs: u64) -> Result<(), String> {
+    if !(6..=8).contains(&digits) {
+        return Err("Codes must have 6 to 8 digits".to_string());
+    }
+    if period_secs == 0 {
+        return Err("The code period must be greater than zero".to_string());
+    }
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn secret_name(id: &str) -> String {
+    format!("totp:{}", id)
+}
+
+// RFC 4648 base32, as secrets are written; case, spaces and padding are ignored
+fn decode_base32(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=' && *c != '-') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return Err("The secret is not valid base32".to_string()),
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err("The secret is empty".to_string());
+    }
+    Ok(bytes)
+}
+
+fn sign<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = <M as KeyInit>::new_from_slice(key).map_err(|e| e.to_string())?;
+    mac.update(message);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+// The code for the time step containing `at`, with HOTP's dynamic truncation
+fn generate(secret: &[u8], account: &TotpAccount, at: u64) -> Result<String, String> {
+    check(account.digits, account.period_secs)?;
+    let counter = (at / account.period_secs).to_be_bytes();
+    let hash = match account.algorithm {
+        TotpAlgorithm::Sha1 => sign::<Hmac<Sha1>>(secret, &counter)?,
+        TotpAlgorithm::Sha256 => sign::<Hmac<Sha256>>(secret, &counter)?,
+        TotpAlgorithm::Sha512 => sign::<Hmac<Sha512>>(secret, &counter)?,
+    };
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    let code = binary as u64 % 10u64.pow(account.digits);
+    Ok(format!("{:0width$}", code, width = account.digits as usize))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// An account and its base32 secret from a link such as
+// otpauth://totp/Example:jane@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example
+fn parse_uri(uri: &str) -> Result<(TotpAccount, String), String> {
+    let url = Url::parse(uri.trim()).map_err(|e| e.to_string())?;
+    if url.scheme() != "otpauth" {
+        return Err("Not a two-factor setup code".to_string());
+    }
+    if url.host_str() != Some("totp") {
+        return Err("Only time-based codes are supported".to_string());
+    }
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.into_owned())
+    };
+
+    let secret: String = param("secret")
+        .ok_or("The code has no secret".to_string())?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    decode_base32(&secret)?;
+    // The label is "Issuer:account" or just the account
+    let path = percent_decode(url.path().trim_start_matches('/'));
+    let (label_issuer, label) = match path.split_once(':') {
+        Some((issuer, label)) => (Some(issuer.trim().to_string()), label.trim().to_string()),
+        None => (None, path.trim().to_string()),
+    };
+    let algorithm = match param("algorithm").unwrap_or_default().to_uppercase().as_str() {
+        "" | "SHA1" => TotpAlgorithm::Sha1,
+        "SHA256" => TotpAlgorithm::Sha256,
+        "SHA512" => TotpAlgorithm::Sha512,
+        other => return Err(format!("Unsupported algorithm: {}", other)),
+    };
+    let digits = match param("digits") {
+        Some(digits) => digits.parse::<u32>().map_err(|e| e.to_string())?,
+        None => DEFAULT_DIGITS,
+    };
+    let period_secs = match param("period") {
+        Some(period) => period.parse::<u64>().map_err(|e| e.to_string())?,
+        None => DEFAULT_PERIOD_SECS,
+    };
+    check(digits, period_secs)?;
+
+    let account = TotpAccount {
+        id: String::new(),
+        issuer: param("issuer").or(label_issuer).filter(|issuer| !issuer.is_empty()),
+        label,
+        algorithm,
+        digits,
+        period_secs,
+    };
+    Ok((account, secret))
+}
+
+// Ask for a fingerprint or face unless the vault was unlocked moments ago
+async fn unlock(app_handle: &AppHandle) -> Result<(), String> {
+    {
+        let state = app_handle.state::<TotpState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        if now_secs() < store.unlocked_until {
+            return Ok(());
+        }
+    }
+    #[cfg(mobile)]
+    {
+        let handle = app_handle.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            let options = AuthOptions {
+                allow_device_credential: true,
+                ..Default::default()
+            };
+            handle
+                .biometric()
+                .authenticate("Unlock two-factor codes".to_string(), options)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+    }
+    // Desktop secrets are already behind the OS keychain
+    let state = app_handle.state::<TotpState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.unlocked_until = now_secs() + UNLOCK_SECS;
+    Ok(())
+}
+
+// Command to add an account from a scanned otpauth:// setup code
+#[tauri::command]
+pub fn import_totp_uri(
+    uri: String,
+    app_handle: AppHandle,
+    keystore: State<'_, Keystore>,
+    state: State<'_, TotpState>,
+) -> Result<TotpAccount, String> {
+    let (mut account, secret) = parse_uri(&uri)?;
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.next_id += 1;
+    account.id = store.next_id.to_string();
+    keystore.set(&secret_name(&account.id), &secret)?;
+    store.accounts.push(account.clone());
+    storage::save_json(&app_handle, ACCOUNTS_FILE, &*store)?;
+    Ok(account)
+}
+
+// Accounts without their secrets or codes
+#[tauri::command]
+pub fn list_totp_accounts(state: State<'_, TotpState>) -> Result<Vec<TotpAccount>, String> {
+    let store = state.lock().map_err(|e| e.to_string())?;
+    Ok(store.accounts.clone())
+}
+
+// Command for an account's current code
+#[tauri::command]
+pub async fn get_code(id: String, app_handle: AppHandle) -> Result<TotpCode, String> {
+    unlock(&app_handle).await?;
+    let account = {
+        let state = app_handle.state::<TotpState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store
+            .accounts
+            .iter()
+            .find(|account| account.id == id)
+            .cloned()
+            .ok_or("Account not found".to_string())?
+    };
+    let secret = app_handle
+        .state::<Keystore>()
+        .get(&secret_name(&id))?
+        .ok_or("The account's secret is missing".to_string())?;
+    let secret = decode_base32(&secret)?;
+    let now = now_secs();
+    let code = generate(&secret, &account, now)?;
+    Ok(TotpCode {
+        code,
+        remaining_secs: account.period_secs - now % account.period_secs,
+        id,
+    })
+}
+
+#[tauri::command]
+pub async fn remove_totp_account(id: String, app_handle: AppHandle) -> Result<(), String> {
+    unlock(&app_handle).await?;
+    app_handle.state::<Keystore>().delete(&secret_name(&id))?;
+    let state = app_handle.state::<TotpState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.accounts.retain(|account| account.id != id);
+    storage::save_json(&app_handle, ACCOUNTS_FILE, &*store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(algorithm: TotpAlgorithm, digits: u32) -> TotpAccount {
+        TotpAccount {
+            id: "1".to_string(),
+            issuer: None,
+            label: "test".to_string(),
+            algorithm,
+            digits,
+            period_secs: DEFAULT_PERIOD_SECS,
+        }
+    }
+
+    // The test vectors of RFC 6238 appendix B, with the seed repeated to the
+    // hash's block size for SHA-256 and SHA-512
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        let seed = b"12345678901234567890";
+        let cases = [
+            (
+                TotpAlgorithm::Sha1,
+                seed.to_vec(),
+                [
+                    (59, "94287082"),
+                    (1111111109, "07081804"),
+                    (1111111111, "14050471"),
+                    (1234567890, "89005924"),
+                    (2000000000, "69279037"),
+                    (20000000000, "65353130"),
+                ],
+            ),
+            (
+                TotpAlgorithm::Sha256,
+                seed.repeat(2)[..32].to_vec(),
+                [
+                    (59, "46119246"),
+                    (1111111109, "68084774"),
+                    (1111111111, "67062674"),
+                    (1234567890, "91819424"),
+                    (2000000000, "90698825"),
+                    (20000000000, "77737706"),
+                ],
+            ),
+            (
+                TotpAlgorithm::Sha512,
+                seed.repeat(4)[..64].to_vec(),
+                [
+                    (59, "90693936"),
+                    (1111111109, "25091201"),
+                    (1111111111, "99943326"),
+                    (1234567890, "93441116"),
+                    (2000000000, "38618901"),
+                    (20000000000, "47863826"),
+                ],
+            ),
+        ];
+        for (algorithm, secret, vectors) in cases {
+            for (at, expected) in vectors {
+                assert_eq!(generate(&secret, &account(algorithm, 8), at).unwrap(), expected, "{:?} at {}", algorithm, at);
+            }
+        }
+    }
+
+    #[test]
+    fn six_digit_codes_keep_leading_zeros() {
+        let secret = b"12345678901234567890";
+        assert_eq!(generate(secret, &account(TotpAlgorithm::Sha1, 6), 1111111109).unwrap(), "081804");
+    }
+
+    #[test]
+    fn invalid_accounts_are_refused() {
+        let secret = b"12345678901234567890";
+        let mut zero_period = account(TotpAlgorithm::Sha1, 6);
+        zero_period.period_secs = 0;
+        assert!(generate(secret, &zero_period, 59).is_err());
+        assert!(generate(secret, &account(TotpAlgorithm::Sha1, 10), 59).is_err());
+    }
+
+    #[test]
+    fn parses_otpauth_uris() {
+        let (account, secret) = parse_uri(
+            "otpauth://totp/Example:jane%40example.com?secret=jbsw%20y3dp%20ehpk3pxp&issuer=Example&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(account.issuer.as_deref(), Some("Example"));
+        assert_eq!(account.label, "jane@example.com");
+        assert_eq!(account.algorithm, TotpAlgorithm::Sha256);
+        assert_eq!((account.digits, account.period_secs), (8, 60));
+        assert_eq!(decode_base32(&secret).unwrap(), b"Hello!\xde\xad\xbe\xef");
+
+        let (defaults, _) = parse_uri("otpauth://totp/jane?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(defaults.issuer, None);
+        assert_eq!((defaults.algorithm, defaults.digits, defaults.period_secs), (TotpAlgorithm::Sha1, 6, 30));
+
+        assert!(parse_uri("otpauth://hotp/jane?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(parse_uri("otpauth://totp/jane?secret=JBSWY3DPEHPK3PXP&period=0").is_err());
+        assert!(parse_uri("otpauth://totp/jane?secret=JBSWY3DPEHPK3PXP&digits=4").is_err());
+        assert!(parse_uri("otpauth://totp/jane?secret=not-base32!").is_err());
+    }
+}
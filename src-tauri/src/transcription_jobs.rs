@@ -0,0 +1,278 @@
+// Long recordings are transcribed in chunks of about a minute, each sent on
+// its own so a dropped connection only costs that chunk. Failed chunks are
+// retried, finished ones are saved, and transcribing the same file again
+// resumes with the chunks that are still missing, including after a cancel.
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::speech::{self, TranscriptSegment, TranscriptionResult, WHISPER_SAMPLE_RATE};
+use crate::storage;
+use crate::stt;
+
+// Audio longer than this is chunked
+pub const CHUNK_THRESHOLD_SECS: f64 = 120.0;
+const CHUNK_SECS: usize = 60;
+// How far back from a chunk's end to look for a pause to cut at
+const SEARCH_SECS: usize = 5;
+const MAX_ATTEMPTS: u32 = 3;
+pub const JOBS_DIR: &str = "transcription_jobs";
+
+#[derive(Serialize, Deserialize, Default)]
+struct JobState {
+    // Results so far, with times already relative to the whole file
+    chunks: Vec<Option<TranscriptionResult>>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct JobProgress {
+    pub job: String,
+    pub completed: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChunkFailure {
+    pub job: String,
+    pub chunk: usize,
+    pub attempt: u32,
+    pub error: String,
+    pub retrying: bool,
+}
+
+// Identifies a file by its contents, so a resumed job finds its saved chunks
+pub fn job_id(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn job_file(job: &str) -> String {
+    format!("{}/{}.json", JOBS_DIR, job)
+}
+
+fn energy(frame: &[i16]) -> u64 {
+    frame.iter().map(|sample| (*sample as i64 * *sample as i64) as u64).sum()
+}
+
+// Chunk boundaries, each moved back to the quietest 100 ms near the nominal
+// cut so words are not split between chunks
+fn split(pcm: &[i16]) -> Vec<Range<usize>> {
+    let rate = WHISPER_SAMPLE_RATE as usize;
+    let frame = rate / 10;
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < pcm.len() {
+        let mut end = (start + CHUNK_SECS * rate).min(pcm.len());
+        if end < pcm.len() {
+            end = (end - SEARCH_SECS * rate..end)
+                .step_by(frame)
+                .min_by_key(|at| energy(&pcm[*at..*at + frame]))
+                .map(|at| at + frame / 2)
+                .unwrap_or(end);
+        }
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+// Move a chunk's times to the whole file's timeline; servers that return no
+// segments get one spanning the chunk
+fn offset(mut result: TranscriptionResult, range: &Range<usize>) -> TranscriptionResult {
+    let rate = WHISPER_SAMPLE_RATE as f64;
+    let start = range.start as f64 / rate;
+    let segments = result.segments.take().unwrap_or_else(|| {
+        vec![TranscriptSegment {
+            start: 0.0,
+            end: range.len() as f64 / rate,
+            text: result.text.clone(),
+            words: Vec::new(),
+            speaker: None,
+        }]
+    });
+    result.segments = Some(
+        segments
+            .into_iter()
+            .map(|mut segment| {
+                segment.start += start;
+                segment.end += start;
+                for word in &mut segment.words {
+                    word.start += start;
+                    word.end += start;
+                }
+                segment
+            })
+            .collect(),
+    );
+    result
+}
+
+fn merge(chunks: Vec<TranscriptionResult>) -> TranscriptionResult {
+    let language = chunks.iter().find_map(|chunk| chunk.language.clone());
+    let text = chunks
+        .iter()
+        .map(|chunk| chunk.text.trim())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let segments = chunks
+        .into_iter()
+        .flat_map(|chunk| chunk.segments.unwrap_or_default())
+        .filter(|segment| !segment.text.trim().is_empty())
+        .collect();
+    TranscriptionResult {
+        text,
+        language,
+        segments: Some(segments),
+    }
+}
+
+// Transcribe mono 16 kHz audio chunk by chunk with the active backend,
+// emitting `stt://job-progress` after each chunk and `stt://chunk-failed` on errors
+pub async fn transcribe(app_handle: &AppHandle, job: &str, pcm: &[i16]) -> Result<TranscriptionResult, String> {
+    let ranges = split(pcm);
+    let file = job_file(job);
+    let mut state: JobState = storage::load_json(app_handle, &file);
+    if state.chunks.len() != ranges.len() {
+        state.chunks = vec![None; ranges.len()];
+    }
+    let progress = |state: &JobState| JobProgress {
+        job: job.to_string(),
+        completed: state.chunks.iter().filter(|chunk| chunk.is_some()).count(),
+        total: ranges.len(),
+    };
+    let _ = app_handle.emit("stt://job-progress", progress(&state));
+
+    for (index, range) in ranges.iter().enumerate() {
+        if state.chunks[index].is_some() {
+            continue;
+        }
+        let wav = speech::encode_wav(&pcm[range.clone()], WHISPER_SAMPLE_RATE);
+        let mut attempt = 0;
+        let result = loop {
+            attempt += 1;
+            match speech::transcribe_wav(app_handle, wav.clone()).await {
+                Ok(result) => break result,
+                Err(error) if error == stt::CANCELLED => return Err(error),
+                Err(error) => {
+                    let retrying = attempt < MAX_ATTEMPTS;
+                    let _ = app_handle.emit("stt://chunk-failed", ChunkFailure {
+                        job: job.to_string(),
+                        chunk: index,
+                        attempt,
+                        error: error.clone(),
+                        retrying,
+                    });
+                    if !retrying {
+                        return Err(format!(
+                            "Chunk {} of {} failed: {}. Transcribe the file again to resume.",
+                            index + 1,
+                            ranges.len(),
+                            error
+                        ));
+                    }
+                    tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                }
+            }
+        };
+        state.chunks[index] = Some(offset(result, range));
+        storage::save_json(app_handle, &file, &state)?;
+        let _ = app_handle.emit("stt://job-progress", progress(&state));
+    }
+
+    if let Ok(path) = storage::data_file(app_handle, &file) {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(merge(state.chunks.into_iter().flatten().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speech::TranscriptWord;
+
+    const RATE: usize = WHISPER_SAMPLE_RATE as usize;
+
+    fn result(text: &str, segments: Option<Vec<TranscriptSegment>>) -> TranscriptionResult {
+        TranscriptionResult {
+            text: text.to_string(),
+            language: None,
+            segments,
+        }
+    }
+
+    fn segment(start: f64, end: f64, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start,
+            end,
+            text: text.to_string(),
+            words: Vec::new(),
+            speaker: None,
+        }
+    }
+
+    #[test]
+    fn job_id_depends_only_on_contents() {
+        assert_eq!(job_id(b"audio"), job_id(b"audio"));
+        assert_ne!(job_id(b"audio"), job_id(b"other"));
+        assert_eq!(job_id(b"").len(), 32);
+    }
+
+    #[test]
+    fn short_audio_is_one_chunk() {
+        assert_eq!(split(&vec![1000; 10 * RATE]), vec![0..10 * RATE]);
+        assert!(split(&[]).is_empty());
+    }
+
+    #[test]
+    fn chunks_cut_at_the_quietest_frame_near_the_boundary() {
+        let mut pcm = vec![8000i16; 150 * RATE];
+        // A pause two seconds before the first nominal cut
+        let pause = 58 * RATE;
+        pcm[pause..pause + RATE / 10].fill(0);
+        let ranges = split(&pcm);
+        assert_eq!(ranges[0], 0..pause + RATE / 20);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges.last().unwrap().end, pcm.len());
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+
+    #[test]
+    fn offset_moves_times_to_the_file_timeline() {
+        let mut timed = segment(1.0, 2.0, "hello");
+        timed.words.push(TranscriptWord {
+            word: "hello".to_string(),
+            start: 1.0,
+            end: 1.5,
+        });
+        let moved = offset(result("hello", Some(vec![timed])), &(60 * RATE..120 * RATE));
+        let segment = &moved.segments.unwrap()[0];
+        assert_eq!((segment.start, segment.end), (61.0, 62.0));
+        assert_eq!((segment.words[0].start, segment.words[0].end), (61.0, 61.5));
+
+        // Without segments the chunk becomes one
+        let spanned = offset(result("hi", None), &(RATE..3 * RATE)).segments.unwrap();
+        assert_eq!(spanned.len(), 1);
+        assert_eq!((spanned[0].start, spanned[0].end), (1.0, 3.0));
+        assert_eq!(spanned[0].text, "hi");
+    }
+
+    #[test]
+    fn merge_joins_text_and_drops_empty_segments() {
+        let mut first = result(" one ", Some(vec![segment(0.0, 1.0, "one"), segment(1.0, 2.0, " ")]));
+        first.language = Some("en".to_string());
+        let merged = merge(vec![
+            first,
+            result("", Some(Vec::new())),
+            result("two", Some(vec![segment(60.0, 61.0, "two")])),
+        ]);
+        assert_eq!(merged.text, "one two");
+        assert_eq!(merged.language.as_deref(), Some("en"));
+        let texts: Vec<_> = merged.segments.unwrap().into_iter().map(|segment| segment.text).collect();
+        assert_eq!(texts, vec!["one", "two"]);
+    }
+}
@@ -0,0 +1,694 @@
+// Text to speech. Each provider implements TtsBackend and is listed in
+// BACKENDS, as with transcription; a downloaded Piper voice takes over while
+// the device is offline. Audio is played on the chosen route (audio_focus.rs) as
+// it streams in, and stops as soon as the user starts talking again: a new
+// recording or the wake word interrupts it. Playback emits tts://started and
+// tts://finished.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, Manager, State};
+
+use crate::audio_focus;
+use crate::consent::{self, ConsentScope};
+use crate::earcons::{self, SampleQueue};
+use crate::endpoints::{self, Endpoint};
+use crate::faults;
+use crate::limits;
+use crate::models;
+use crate::piper;
+use crate::provider_keys;
+use crate::rules;
+use crate::settings::{self, AppSettings, SettingsState, TtsSettings};
+use crate::speech;
+use crate::ssml::{self, SpeechPart};
+use crate::usage::{self, Provider};
+
+// Both providers answer with 16-bit mono PCM at this rate
+const PCM_RATE: u32 = 24000;
+const MAX_TEXT_CHARS: usize = 4096;
+const OPENAI_MODEL: &str = "tts-1";
+const GEMINI_TTS_MODEL: &str = "gemini-2.5-flash-preview-tts";
+
+pub trait TtsBackend: Send + Sync {
+    // Stable id stored in settings
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn default_voice(&self) -> &'static str;
+    fn voices(&self) -> &'static [&'static str];
+    // Why the backend cannot be used right now, such as a missing key
+    fn unavailable(&self, app_handle: &AppHandle) -> Option<String>;
+    // Hand 16-bit mono PCM at PCM_RATE to `sink` as it arrives, until it returns
+    // false; `rate` is the speaking speed, 1.0 being normal
+    fn synthesize<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        text: &'a str,
+        voice: &'a str,
+        rate: f32,
+        sink: &'a PcmSink,
+    ) -> BoxFuture<'a, Result<(), String>>;
+}
+
+pub type PcmSink = dyn Fn(&[u8]) -> bool + Send + Sync;
+
+struct OpenAiTts;
+
+impl TtsBackend for OpenAiTts {
+    fn id(&self) -> &'static str {
+        "openai_tts"
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn default_voice(&self) -> &'static str {
+        "alloy"
+    }
+
+    fn voices(&self) -> &'static [&'static str] {
+        &["alloy", "echo", "fable", "onyx", "nova", "shimmer"]
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle) -> Option<String> {
+        if rules::is_offline(app_handle) {
+            return Some("No network connection".to_string());
+        }
+        if !consent::granted(app_handle, ConsentScope::OpenAiSpeech) {
+            return Some("Sending replies to OpenAI has not been consented to".to_string());
+        }
+        provider_keys::select_key(app_handle, Provider::Whisper).err()
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        text: &'a str,
+        voice: &'a str,
+        rate: f32,
+        sink: &'a PcmSink,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            consent::require(app_handle, ConsentScope::OpenAiSpeech)?;
+            let body = json!({
+                "model": OPENAI_MODEL,
+                "input": text,
+                "voice": voice,
+                "speed": rate,
+                "response_format": "pcm",
+            });
+            let _permit = limits::acquire(Endpoint::OpenAi).await?;
+            let client = reqwest::Client::new();
+            // Rotate to the next stored key when one is rejected or rate limited
+            let response = loop {
+                let key = provider_keys::select_key(app_handle, Provider::Whisper)?;
+                let response = client
+                    .post(format!("{}/v1/audio/speech", endpoints::base_url(Endpoint::OpenAi)))
+                    .bearer_auth(&key.secret)
+                    .json(&body)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let status = response.status().as_u16();
+                provider_keys::report(app_handle, &key.id, status);
+                if provider_keys::should_rotate(status) && key.id != provider_keys::ENV_KEY_ID {
+                    continue;
+                }
+                if !response.status().is_success() {
+                    return Err(format!("OpenAI speech error: {}", response.status()));
+                }
+                break response;
+            };
+            // Priced per character; counted as input
+            usage::record(app_handle, Provider::Whisper, OPENAI_MODEL, text.chars().count() as u64, 0, 0.0);
+            let mut response = response;
+            // Stop downloading once playback has been interrupted
+            while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+                if !sink(&chunk) {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+struct GeminiTts;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiSpeechResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiSpeechCandidate>,
+    usage_metadata: Option<GeminiSpeechUsage>,
+}
+
+#[derive(Deserialize)]
+struct GeminiSpeechCandidate {
+    content: GeminiSpeechContent,
+}
+
+#[derive(Deserialize)]
+struct GeminiSpeechContent {
+    #[serde(default)]
+    parts: Vec<GeminiSpeechPart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiSpeechPart {
+    inline_data: Option<InlineData>,
+}
+
+#[derive(Deserialize)]
+struct InlineData {
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiSpeechUsage {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+impl TtsBackend for GeminiTts {
+    fn id(&self) -> &'static str {
+        "gemini_tts"
+    }
+
+    fn name(&self) -> &'static str {
+        "Google Gemini"
+    }
+
+    fn default_voice(&self) -> &'static str {
+        "Kore"
+    }
+
+    fn voices(&self) -> &'static [&'static str] {
+        &["Kore", "Puck", "Charon", "Fenrir", "Aoede", "Leda", "Orus", "Zephyr"]
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle) -> Option<String> {
+        if rules::is_offline(app_handle) {
+            return Some("No network connection".to_string());
+        }
+        if !consent::granted(app_handle, ConsentScope::GeminiText) {
+            return Some("Sending text to Gemini has not been consented to".to_string());
+        }
+        provider_keys::select_key(app_handle, Provider::Gemini).err()
+    }
+
+    fn synthesize<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        text: &'a str,
+        voice: &'a str,
+        rate: f32,
+        sink: &'a PcmSink,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            consent::require(app_handle, ConsentScope::GeminiText)?;
+            let prompt = paced_prompt(text, rate);
+            let body = json!({
+                "contents": [{ "parts": [{ "text": prompt }] }],
+                "generationConfig": {
+                    "responseModalities": ["AUDIO"],
+                    "speechConfig": { "voiceConfig": { "prebuiltVoiceConfig": { "voiceName": voice } } },
+                },
+            });
+            let _permit = limits::acquire(Endpoint::Gemini).await?;
+            let client = reqwest::Client::new();
+            let response = loop {
+                let key = provider_keys::select_key(app_handle, Provider::Gemini)?;
+                let response = client
+                    .post(format!(
+                        "{}/v1beta/models/{}:generateContent",
+                        endpoints::base_url(Endpoint::Gemini),
+                        GEMINI_TTS_MODEL
+                    ))
+                    .query(&[("key", &key.secret)])
+                    .json(&body)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let status = response.status().as_u16();
+                provider_keys::report(app_handle, &key.id, status);
+                if provider_keys::should_rotate(status) && key.id != provider_keys::ENV_KEY_ID {
+                    continue;
+                }
+                if !response.status().is_success() {
+                    return Err(format!("Gemini speech error: {}", response.status()));
+                }
+                break response;
+            };
+            let speech: GeminiSpeechResponse = faults::json(Endpoint::Gemini, response).await?;
+            if let Some(metadata) = &speech.usage_metadata {
+                usage::record(
+                    app_handle,
+                    Provider::Gemini,
+                    GEMINI_TTS_MODEL,
+                    metadata.prompt_token_count,
+                    metadata.candidates_token_count,
+                    0.0,
+                );
+            }
+            let data = speech
+                .candidates
+                .into_iter()
+                .flat_map(|candidate| candidate.content.parts)
+                .find_map(|part| part.inline_data)
+                .ok_or("Gemini returned no audio".to_string())?;
+            // generateContent has no streaming form for audio, so it arrives in one piece
+            sink(&BASE64.decode(data.data).map_err(|e| e.to_string())?);
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+// Gemini voices have no speed setting; pace is asked for in the prompt
+fn paced_prompt(text: &str, rate: f32) -> String {
+    if rate < 0.9 {
+        format!("Say slowly: {}", text)
+    } else if rate > 1.1 {
+        format!("Say quickly: {}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+struct PiperTts;
+
+impl TtsBackend for PiperTts {
+    fn id(&self) -> &'static str {
+        "piper"
+    }
+
+    fn name(&self) -> &'static str {
+        "On-device voice"
+    }
+
+    fn default_voice(&self) -> &'static str {
+        models::PIPER_VOICES[0]
+    }
+
+    fn voices(&self) -> &'static [&'static str] {
+        models::PIPER_VOICES
+    }
+
+    fn unavailable(&self, app_handle: &AppHandle) -> Option<String> {
+        if !models::PIPER_VOICES.iter().any(|id| models::is_model_installed(app_handle, id)) {
+            return Some("No voice has been downloaded".to_string());
+        }
+        None
+    }
+
+    // Piper voices have a fixed pace, so `rate` is not applied
+    fn synthesize<'a>(
+        &'a self,
+        app_handle: &'a AppHandle,
+        text: &'a str,
+        voice: &'a str,
+        _rate: f32,
+        sink: &'a PcmSink,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        async move {
+            // Fall back to any downloaded voice when the chosen one is missing
+            let voice = std::iter::once(voice)
+                .chain(models::PIPER_VOICES.iter().copied())
+                .find(|id| models::is_model_installed(app_handle, id))
+                .ok_or("No voice has been downloaded".to_string())?
+                .to_string();
+            let handle = app_handle.clone();
+            let text = text.to_string();
+            let (samples, sample_rate) =
+                tauri::async_runtime::spawn_blocking(move || piper::synthesize(&handle, &voice, &text))
+                    .await
+                    .map_err(|e| e.to_string())??;
+            let pcm: Vec<u8> = speech::resample(&samples, sample_rate, PCM_RATE)
+                .iter()
+                .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+                .collect();
+            sink(&pcm);
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+static BACKENDS: &[&dyn TtsBackend] = &[&OpenAiTts, &GeminiTts, &PiperTts];
+
+fn find(id: &str) -> Option<&'static dyn TtsBackend> {
+    BACKENDS.iter().copied().find(|backend| backend.id() == id)
+}
+
+// The chosen backend, or the first usable one; offline, the on-device voice
+// whenever one has been downloaded
+fn active(app_handle: &AppHandle, config: &TtsSettings) -> Result<&'static dyn TtsBackend, String> {
+    if rules::is_offline(app_handle) && PiperTts.unavailable(app_handle).is_none() {
+        return Ok(&PiperTts);
+    }
+    if let Some(id) = &config.backend {
+        let backend = find(id).ok_or(format!("Unknown speech backend: {}", id))?;
+        return match backend.unavailable(app_handle) {
+            Some(reason) => Err(reason),
+            None => Ok(backend),
+        };
+    }
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|backend| backend.unavailable(app_handle).is_none())
+        .ok_or("No speech backend is available".to_string())
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TtsBackendInfo {
+    pub id: String,
+    pub name: String,
+    pub voices: Vec<String>,
+    pub default_voice: String,
+    pub available: bool,
+    pub unavailable_reason: Option<String>,
+    pub selected: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpeechEvent {
+    pub id: u64,
+    // Set on tts://finished when stop_speaking or a newer utterance cut it short
+    pub interrupted: bool,
+}
+
+// Controls for the utterance playing now
+#[derive(Default)]
+struct Playback {
+    stop: AtomicBool,
+    // Read by the output stream itself
+    paused: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+pub struct TtsPlayer {
+    last_id: u64,
+    playing: Option<Arc<Playback>>,
+}
+
+pub type TtsState = Mutex<TtsPlayer>;
+
+// An utterance that has been fully synthesized and may still be playing
+pub struct Utterance {
+    pub id: u64,
+    playback: JoinHandle<bool>,
+}
+
+impl Utterance {
+    // Wait for playback to end; false when it was interrupted
+    pub async fn finished(self) -> bool {
+        self.playback.await.map(|interrupted| !interrupted).unwrap_or(false)
+    }
+}
+
+fn pcm_to_samples(pcm: &[u8]) -> Vec<f32> {
+    pcm.chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / i16::MAX as f32)
+        .collect()
+}
+
+// Chunks can end halfway through a sample; the odd byte waits in `partial`
+fn take_samples(partial: &mut Vec<u8>, chunk: &[u8]) -> Vec<f32> {
+    partial.extend_from_slice(chunk);
+    let whole = partial.len() / 2 * 2;
+    let samples = pcm_to_samples(&partial[..whole]);
+    partial.drain(..whole);
+    samples
+}
+
+// The requested voice, then the saved one, when the backend has it
+fn pick_voice(backend: &dyn TtsBackend, requested: Option<String>, saved: Option<String>) -> String {
+    requested
+        .or(saved)
+        .filter(|voice| backend.voices().contains(&voice.as_str()))
+        .unwrap_or_else(|| backend.default_voice().to_string())
+}
+
+// Stop whatever is being said; used for barge-in when the user starts talking
+pub fn interrupt(app_handle: &AppHandle) {
+    let state = app_handle.state::<TtsState>();
+    let Ok(player) = state.lock() else {
+        return;
+    };
+    if let Some(playing) = &player.playing {
+        playing.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn set_paused(app_handle: &AppHandle, paused: bool) -> Result<(), String> {
+    let state = app_handle.state::<TtsState>();
+    let player = state.lock().map_err(|e| e.to_string())?;
+    let playing = player.playing.as_ref().ok_or("Nothing is being said".to_string())?;
+    playing.paused.store(paused, Ordering::Relaxed);
+    Ok(())
+}
+
+// Read text, or SSML wrapped in <speak>, aloud, interrupting anything already
+// being spoken. Playback starts with the first audio received; this returns
+// once synthesis has finished. Voice and rate default to the speech settings.
+pub async fn say(
+    app_handle: &AppHandle,
+    text: &str,
+    voice: Option<String>,
+    rate: Option<f32>,
+) -> Result<Utterance, String> {
+    let parts = if ssml::is_ssml(text) {
+        ssml::parse(text)?
+    } else {
+        vec![SpeechPart::Text { text: text.trim().to_string(), rate: 1.0 }]
+    };
+    let text = ssml::plain_text(&parts);
+    if text.trim().is_empty() {
+        return Err("Nothing to say".to_string());
+    }
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(format!("Text can be at most {} characters", MAX_TEXT_CHARS));
+    }
+    let config = settings::current(&app_handle.state::<SettingsState>()).tts;
+    let rate = rate.unwrap_or(config.rate);
+    if !(0.25..=4.0).contains(&rate) {
+        return Err("The speaking rate must be between 0.25 and 4".to_string());
+    }
+    let backend = active(app_handle, &config)?;
+    let voice = pick_voice(backend, voice, config.voice.clone());
+
+    let controls = Arc::new(Playback::default());
+    let id = {
+        let state = app_handle.state::<TtsState>();
+        let mut player = state.lock().map_err(|e| e.to_string())?;
+        if let Some(previous) = player.playing.replace(controls.clone()) {
+            previous.stop.store(true, Ordering::Relaxed);
+        }
+        player.last_id += 1;
+        player.last_id
+    };
+    let queue = Arc::new(Mutex::new(SampleQueue::new(PCM_RATE)));
+    let started = AtomicBool::new(false);
+    let partial = Mutex::new(Vec::new());
+    let sink = |chunk: &[u8]| {
+        if controls.stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        let Ok(mut partial) = partial.lock() else {
+            return false;
+        };
+        let samples = take_samples(&mut partial, chunk);
+        if let Ok(mut queue) = queue.lock() {
+            queue.push(&samples);
+        }
+        if !started.swap(true, Ordering::Relaxed) {
+            let _ = app_handle.emit("tts://started", SpeechEvent { id, interrupted: false });
+        }
+        true
+    };
+
+    let handle = app_handle.clone();
+    let playing = controls.clone();
+    let source = queue.clone();
+    let focus = audio_focus::acquire(app_handle);
+    let playback = tauri::async_runtime::spawn_blocking(move || {
+        let _focus = focus;
+        if let Err(e) = earcons::play_stream(&source, &playing.stop, &playing.paused) {
+            tracing::warn!(error = %e, "speech playback failed");
+        }
+        let interrupted = playing.stop.load(Ordering::Relaxed);
+        if let Ok(mut player) = handle.state::<TtsState>().lock() {
+            if player.playing.as_ref().is_some_and(|current| Arc::ptr_eq(current, &playing)) {
+                player.playing = None;
+            }
+        }
+        let _ = handle.emit("tts://finished", SpeechEvent { id, interrupted });
+        interrupted
+    });
+
+    // Each part is synthesized on its own, at its own rate; pauses are silence
+    let mut result = Ok(());
+    for part in &parts {
+        if controls.stop.load(Ordering::Relaxed) {
+            break;
+        }
+        result = match part {
+            SpeechPart::Text { text, rate: scale } => {
+                backend
+                    .synthesize(app_handle, text, &voice, (rate * scale).clamp(0.25, 4.0), &sink)
+                    .await
+            }
+            SpeechPart::Pause(duration) => {
+                let samples = (PCM_RATE as f64 * duration.as_secs_f64()) as usize;
+                sink(&vec![0; samples * 2]);
+                Ok(())
+            }
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+    if let Ok(mut queue) = queue.lock() {
+        queue.finish();
+    }
+    if result.is_err() {
+        controls.stop.store(true, Ordering::Relaxed);
+    }
+    result.map(|_| Utterance { id, playback })
+}
+
+// Command to read text aloud; returns the utterance id used in the tts:// events.
+// Text starting with <speak> is read as SSML (see ssml.rs).
+#[tauri::command]
+pub async fn speak(text: String, voice: Option<String>, rate: Option<f32>, app_handle: AppHandle) -> Result<u64, String> {
+    Ok(say(&app_handle, &text, voice, rate).await?.id)
+}
+
+#[tauri::command]
+pub fn stop_speaking(app_handle: AppHandle) {
+    interrupt(&app_handle);
+}
+
+// Command to hold playback where it is; resume_speaking carries on from there
+#[tauri::command]
+pub fn pause_speaking(app_handle: AppHandle) -> Result<(), String> {
+    set_paused(&app_handle, true)
+}
+
+#[tauri::command]
+pub fn resume_speaking(app_handle: AppHandle) -> Result<(), String> {
+    set_paused(&app_handle, false)
+}
+
+fn list(app_handle: &AppHandle, app_settings: &AppSettings) -> Vec<TtsBackendInfo> {
+    let selected = active(app_handle, &app_settings.tts).ok().map(|backend| backend.id());
+    BACKENDS
+        .iter()
+        .map(|backend| {
+            let reason = backend.unavailable(app_handle);
+            TtsBackendInfo {
+                id: backend.id().to_string(),
+                name: backend.name().to_string(),
+                voices: backend.voices().iter().map(|voice| voice.to_string()).collect(),
+                default_voice: backend.default_voice().to_string(),
+                available: reason.is_none(),
+                unavailable_reason: reason,
+                selected: Some(backend.id()) == selected,
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn list_tts_backends(app_handle: AppHandle, settings_state: State<'_, SettingsState>) -> Vec<TtsBackendInfo> {
+    list(&app_handle, &settings::current(&settings_state))
+}
+
+// Command to choose the speech backend, voice and rate; None picks automatically
+#[tauri::command]
+pub fn set_tts_settings(
+    config: TtsSettings,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<Vec<TtsBackendInfo>, String> {
+    if let Some(id) = &config.backend {
+        find(id).ok_or(format!("Unknown speech backend: {}", id))?;
+    }
+    if !(0.25..=4.0).contains(&config.rate) {
+        return Err("The speaking rate must be between 0.25 and 4".to_string());
+    }
+    let mut updated = settings::current(&settings_state);
+    updated.tts = config;
+    settings::update_settings(updated.clone(), app_handle.clone(), settings_state)?;
+    Ok(list(&app_handle, &updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_have_unique_ids_and_list_their_default_voice() {
+        for (i, backend) in BACKENDS.iter().enumerate() {
+            assert!(BACKENDS[..i].iter().all(|other| other.id() != backend.id()));
+            assert!(backend.voices().contains(&backend.default_voice()));
+        }
+        assert!(find("piper").is_some());
+        assert!(find("espeak").is_none());
+    }
+
+    #[test]
+    fn picks_a_voice_the_backend_has() {
+        let voice = |requested: Option<&str>, saved: Option<&str>| {
+            pick_voice(&OpenAiTts, requested.map(str::to_string), saved.map(str::to_string))
+        };
+        assert_eq!(voice(Some("nova"), Some("echo")), "nova");
+        assert_eq!(voice(None, Some("echo")), "echo");
+        assert_eq!(voice(None, None), "alloy");
+        // A voice from another backend falls back to the default
+        assert_eq!(voice(Some("Kore"), None), "alloy");
+    }
+
+    #[test]
+    fn gemini_pace_is_asked_for_in_the_prompt() {
+        assert_eq!(paced_prompt("Hello", 1.0), "Hello");
+        assert_eq!(paced_prompt("Hello", 0.5), "Say slowly: Hello");
+        assert_eq!(paced_prompt("Hello", 1.5), "Say quickly: Hello");
+    }
+
+    #[test]
+    fn split_samples_are_joined_across_chunks() {
+        let pcm: Vec<u8> = [i16::MAX, 0, -i16::MAX].iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        let mut partial = Vec::new();
+        assert_eq!(take_samples(&mut partial, &pcm[..3]), vec![1.0]);
+        assert_eq!(partial.len(), 1);
+        assert_eq!(take_samples(&mut partial, &pcm[3..]), vec![0.0, -1.0]);
+        assert!(partial.is_empty());
+        assert!(take_samples(&mut partial, &[]).is_empty());
+    }
+
+    #[test]
+    fn reads_gemini_audio_responses() {
+        let raw = r#"{"candidates":[{"content":{"parts":[{"inlineData":{"mimeType":"audio/L16","data":"AAE="}}]}}],
+            "usageMetadata":{"promptTokenCount":5,"candidatesTokenCount":40}}"#;
+        let speech: GeminiSpeechResponse = serde_json::from_str(raw).unwrap();
+        let usage = speech.usage_metadata.as_ref().unwrap();
+        assert_eq!((usage.prompt_token_count, usage.candidates_token_count), (5, 40));
+        let data = speech.candidates[0].content.parts[0].inline_data.as_ref().unwrap();
+        assert_eq!(BASE64.decode(&data.data).unwrap(), vec![0, 1]);
+        let empty: GeminiSpeechResponse = serde_json::from_str("{}").unwrap();
+        assert!(empty.candidates.is_empty());
+    }
+}
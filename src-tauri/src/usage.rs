@@ -0,0 +1,425 @@
+use chrono::{Datelike, Local};
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::storage;
+
+const USAGE_FILE: &str = "usage.json";
+// Months of past usage kept for the history view
+const HISTORY_MONTHS: usize = 12;
+
+// Estimated list prices in USD; tokens are priced per million
+const GEMINI_PRO_PRICE: (f64, f64) = (0.50, 1.50);
+const GEMINI_FLASH_PRICE: (f64, f64) = (0.075, 0.30);
+const WHISPER_PRICE_PER_MINUTE: f64 = 0.006;
+// Text to speech is priced per million characters, recorded as input tokens
+const OPENAI_TTS_PRICE: f64 = 15.0;
+const OPENAI_MINI_PRICE: (f64, f64) = (0.15, 0.60);
+const OPENAI_CHAT_PRICE: (f64, f64) = (2.50, 10.0);
+const ANTHROPIC_HAIKU_PRICE: (f64, f64) = (0.80, 4.0);
+const ANTHROPIC_SONNET_PRICE: (f64, f64) = (3.0, 15.0);
+// Streaming Nova pay-as-you-go rate
+const DEEPGRAM_PRICE_PER_MINUTE: f64 = 0.0059;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Gemini,
+    Whisper,
+    Ollama,
+    Deepgram,
+    Anthropic,
+}
+
+impl Provider {
+    fn label(self) -> &'static str {
+        match self {
+            Provider::Gemini => "Gemini",
+            Provider::Whisper => "Whisper",
+            Provider::Ollama => "Ollama",
+            Provider::Deepgram => "Deepgram",
+            Provider::Anthropic => "Anthropic",
+        }
+    }
+}
+
+// What to do once a provider's monthly cap has been reached
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DowngradePolicy {
+    // Keep going and only alert
+    #[default]
+    None,
+    // Switch to the cheaper flash model
+    Flash,
+    // Route requests to the local Ollama server
+    Local,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Budget {
+    pub provider: Provider,
+    pub monthly_limit_usd: f64,
+    // Percentages of the limit that raise a notification
+    #[serde(default = "default_thresholds")]
+    pub alert_thresholds: Vec<u8>,
+    #[serde(default)]
+    pub policy: DowngradePolicy,
+}
+
+fn default_thresholds() -> Vec<u8> {
+    vec![80, 100]
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProviderUsage {
+    pub provider: Provider,
+    pub model: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub audio_seconds: f64,
+    pub estimated_cost_usd: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MonthUsage {
+    // e.g. "2025-03"
+    pub month: String,
+    pub providers: Vec<ProviderUsage>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BudgetStatus {
+    pub provider: Provider,
+    pub spent_usd: f64,
+    pub monthly_limit_usd: f64,
+    pub percent_used: f64,
+    pub policy: DowngradePolicy,
+    pub downgraded: bool,
+}
+
+#[derive(Serialize, Clone)]
+struct BudgetAlert {
+    provider: Provider,
+    threshold: u8,
+    spent_usd: f64,
+    monthly_limit_usd: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct UsageStore {
+    current: MonthUsage,
+    #[serde(default)]
+    history: Vec<MonthUsage>,
+    #[serde(default)]
+    budgets: Vec<Budget>,
+    // Thresholds already announced this month
+    #[serde(default)]
+    alerted: Vec<(Provider, u8)>,
+}
+
+pub type UsageState = Mutex<UsageStore>;
+
+pub fn load(app_handle: &AppHandle) -> UsageStore {
+    storage::load_json(app_handle, USAGE_FILE)
+}
+
+fn current_month() -> String {
+    let now = Local::now();
+    format!("{}-{:02}", now.year(), now.month())
+}
+
+fn estimate_cost(provider: Provider, model: &str, input_tokens: u64, output_tokens: u64, audio_seconds: f64) -> f64 {
+    match provider {
+        Provider::Gemini => {
+            let (input, output) = if model.contains("flash") {
+                GEMINI_FLASH_PRICE
+            } else {
+                GEMINI_PRO_PRICE
+            };
+            (input_tokens as f64 * input + output_tokens as f64 * output) / 1_000_000.0
+        }
+        Provider::Whisper if model.starts_with("tts") => input_tokens as f64 * OPENAI_TTS_PRICE / 1_000_000.0,
+        Provider::Whisper if model.starts_with("gpt") => {
+            let (input, output) = if model.contains("mini") {
+                OPENAI_MINI_PRICE
+            } else {
+                OPENAI_CHAT_PRICE
+            };
+            (input_tokens as f64 * input + output_tokens as f64 * output) / 1_000_000.0
+        }
+        Provider::Whisper => audio_seconds / 60.0 * WHISPER_PRICE_PER_MINUTE,
+        Provider::Ollama => 0.0,
+        Provider::Deepgram => audio_seconds / 60.0 * DEEPGRAM_PRICE_PER_MINUTE,
+        Provider::Anthropic => {
+            let (input, output) = if model.contains("haiku") {
+                ANTHROPIC_HAIKU_PRICE
+            } else {
+                ANTHROPIC_SONNET_PRICE
+            };
+            (input_tokens as f64 * input + output_tokens as f64 * output) / 1_000_000.0
+        }
+    }
+}
+
+impl UsageStore {
+    fn roll_month(&mut self) {
+        let month = current_month();
+        if self.current.month == month {
+            return;
+        }
+        if !self.current.month.is_empty() {
+            let finished = std::mem::take(&mut self.current);
+            self.history.push(finished);
+            if self.history.len() > HISTORY_MONTHS {
+                self.history.remove(0);
+            }
+        }
+        self.current.month = month;
+        self.alerted.clear();
+    }
+
+    fn spent(&self, provider: Provider) -> f64 {
+        self.current
+            .providers
+            .iter()
+            .filter(|usage| usage.provider == provider)
+            .map(|usage| usage.estimated_cost_usd)
+            .sum()
+    }
+
+    fn status(&self, budget: &Budget) -> BudgetStatus {
+        let spent = self.spent(budget.provider);
+        let percent = if budget.monthly_limit_usd > 0.0 {
+            spent / budget.monthly_limit_usd * 100.0
+        } else {
+            0.0
+        };
+        BudgetStatus {
+            provider: budget.provider,
+            spent_usd: spent,
+            monthly_limit_usd: budget.monthly_limit_usd,
+            percent_used: percent,
+            policy: budget.policy,
+            downgraded: budget.policy != DowngradePolicy::None && percent >= 100.0,
+        }
+    }
+
+    // Add one call to this month's usage; returns the budget thresholds it crosses
+    fn add(
+        &mut self,
+        provider: Provider,
+        model: &str,
+        input_tokens: u64,
+        output_tokens: u64,
+        audio_seconds: f64,
+    ) -> Vec<BudgetAlert> {
+        let cost = estimate_cost(provider, model, input_tokens, output_tokens, audio_seconds);
+        let index = match self
+            .current
+            .providers
+            .iter()
+            .position(|usage| usage.provider == provider && usage.model == model)
+        {
+            Some(index) => index,
+            None => {
+                self.current.providers.push(ProviderUsage {
+                    provider,
+                    model: model.to_string(),
+                    requests: 0,
+                    input_tokens: 0,
+                    output_tokens: 0,
+                    audio_seconds: 0.0,
+                    estimated_cost_usd: 0.0,
+                });
+                self.current.providers.len() - 1
+            }
+        };
+        let usage = &mut self.current.providers[index];
+        usage.requests += 1;
+        usage.input_tokens += input_tokens;
+        usage.output_tokens += output_tokens;
+        usage.audio_seconds += audio_seconds;
+        usage.estimated_cost_usd += cost;
+
+        let mut alerts = Vec::new();
+        if let Some(budget) = self.budgets.iter().find(|b| b.provider == provider).cloned() {
+            let status = self.status(&budget);
+            for threshold in budget.alert_thresholds {
+                if status.percent_used >= threshold as f64 && !self.alerted.contains(&(provider, threshold)) {
+                    self.alerted.push((provider, threshold));
+                    alerts.push(BudgetAlert {
+                        provider,
+                        threshold,
+                        spent_usd: status.spent_usd,
+                        monthly_limit_usd: status.monthly_limit_usd,
+                    });
+                }
+            }
+        }
+        alerts
+    }
+}
+
+// Record one provider call and raise any budget alerts it crosses
+pub fn record(
+    app_handle: &AppHandle,
+    provider: Provider,
+    model: &str,
+    input_tokens: u64,
+    output_tokens: u64,
+    audio_seconds: f64,
+) {
+    let state = app_handle.state::<UsageState>();
+    let alerts = {
+        let Ok(mut store) = state.lock() else { return };
+        store.roll_month();
+        let alerts = store.add(provider, model, input_tokens, output_tokens, audio_seconds);
+        let _ = storage::save_json(app_handle, USAGE_FILE, &*store);
+        alerts
+    };
+
+    for alert in alerts {
+        let _ = crate::notifications::push(
+            app_handle,
+            "usage",
+            format!("{}% of your {} budget used", alert.threshold, alert.provider.label()),
+            format!(
+                "About ${:.2} of ${:.2} spent this month.",
+                alert.spent_usd, alert.monthly_limit_usd
+            ),
+            None,
+        );
+        let _ = app_handle.emit("usage://budget-alert", &alert);
+    }
+}
+
+// The downgrade in effect for a provider, if its cap has been hit
+pub fn active_downgrade(app_handle: &AppHandle, provider: Provider) -> DowngradePolicy {
+    let state = app_handle.state::<UsageState>();
+    let Ok(mut store) = state.lock() else {
+        return DowngradePolicy::None;
+    };
+    store.roll_month();
+    store
+        .budgets
+        .iter()
+        .find(|budget| budget.provider == provider)
+        .map(|budget| store.status(budget))
+        .filter(|status| status.downgraded)
+        .map(|status| status.policy)
+        .unwrap_or_default()
+}
+
+// Command for the usage screen: this month's usage per provider and model
+#[tauri::command]
+pub fn get_usage(history: Option<bool>, state: State<'_, UsageState>) -> Result<Vec<MonthUsage>, String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.roll_month();
+    let mut months = vec![store.current.clone()];
+    if history.unwrap_or(false) {
+        months.extend(store.history.iter().rev().cloned());
+    }
+    Ok(months)
+}
+
+#[tauri::command]
+pub fn get_budgets(state: State<'_, UsageState>) -> Result<Vec<BudgetStatus>, String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.roll_month();
+    Ok(store.budgets.iter().map(|budget| store.status(budget)).collect())
+}
+
+// Command to set or replace the monthly budget for a provider
+#[tauri::command]
+pub fn set_budget(budget: Budget, app_handle: AppHandle, state: State<'_, UsageState>) -> Result<BudgetStatus, String> {
+    if budget.monthly_limit_usd <= 0.0 {
+        return Err("Budget must be greater than zero".to_string());
+    }
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.budgets.retain(|b| b.provider != budget.provider);
+    // Let thresholds already passed under the old limit alert again
+    store.alerted.retain(|(provider, _)| *provider != budget.provider);
+    let status = store.status(&budget);
+    store.budgets.push(budget);
+    storage::save_json(&app_handle, USAGE_FILE, &*store)?;
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn remove_budget(provider: Provider, app_handle: AppHandle, state: State<'_, UsageState>) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.budgets.retain(|budget| budget.provider != provider);
+    storage::save_json(&app_handle, USAGE_FILE, &*store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn budget(provider: Provider, limit: f64, policy: DowngradePolicy) -> Budget {
+        Budget {
+            provider,
+            monthly_limit_usd: limit,
+            alert_thresholds: default_thresholds(),
+            policy,
+        }
+    }
+
+    #[test]
+    fn costs_follow_the_model_price() {
+        assert!(close(estimate_cost(Provider::Gemini, "gemini-1.5-pro", 1_000_000, 1_000_000, 0.0), 2.0));
+        assert!(close(estimate_cost(Provider::Gemini, "gemini-1.5-flash", 1_000_000, 0, 0.0), 0.075));
+        assert!(close(estimate_cost(Provider::Whisper, "whisper-1", 0, 0, 120.0), 0.012));
+        assert!(close(estimate_cost(Provider::Whisper, "tts-1", 1_000_000, 0, 0.0), 15.0));
+        assert!(close(estimate_cost(Provider::Whisper, "gpt-4o-mini", 0, 1_000_000, 0.0), 0.60));
+        assert!(close(estimate_cost(Provider::Anthropic, "claude-3-5-haiku", 1_000_000, 0, 0.0), 0.80));
+        assert_eq!(estimate_cost(Provider::Ollama, "llama3", 1_000_000, 1_000_000, 60.0), 0.0);
+    }
+
+    #[test]
+    fn usage_is_summed_per_model_and_alerts_fire_once() {
+        let mut store = UsageStore::default();
+        store.budgets.push(budget(Provider::Whisper, 0.012, DowngradePolicy::Local));
+
+        // 96 seconds of transcription is 80% of the budget
+        let alerts = store.add(Provider::Whisper, "whisper-1", 0, 0, 96.0);
+        assert_eq!(alerts.iter().map(|alert| alert.threshold).collect::<Vec<_>>(), vec![80]);
+        assert!(store.add(Provider::Whisper, "whisper-1", 0, 0, 1.0).is_empty());
+        let alerts = store.add(Provider::Whisper, "whisper-1", 0, 0, 30.0);
+        assert_eq!(alerts.iter().map(|alert| alert.threshold).collect::<Vec<_>>(), vec![100]);
+        store.add(Provider::Whisper, "tts-1", 10, 0, 0.0);
+
+        assert_eq!(store.current.providers.len(), 2);
+        assert_eq!(store.current.providers[0].requests, 3);
+        assert_eq!(store.current.providers[0].audio_seconds, 127.0);
+        let status = store.status(&store.budgets[0]);
+        assert!(status.percent_used > 100.0);
+        assert!(status.downgraded);
+        assert!(!store.status(&budget(Provider::Whisper, 0.012, DowngradePolicy::None)).downgraded);
+    }
+
+    #[test]
+    fn a_new_month_moves_usage_to_history() {
+        let mut store = UsageStore::default();
+        store.roll_month();
+        assert_eq!(store.current.month, current_month());
+        assert!(store.history.is_empty());
+
+        store.current.month = "2000-01".to_string();
+        store.add(Provider::Gemini, "gemini-1.5-pro", 10, 10, 0.0);
+        store.alerted.push((Provider::Gemini, 80));
+        store.history = (0..HISTORY_MONTHS).map(|_| MonthUsage::default()).collect();
+        store.roll_month();
+        assert_eq!(store.history.len(), HISTORY_MONTHS);
+        assert_eq!(store.history.last().unwrap().month, "2000-01");
+        assert!(store.current.providers.is_empty());
+        assert!(store.alerted.is_empty());
+    }
+}
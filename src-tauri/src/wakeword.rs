@@ -0,0 +1,332 @@
+// Hands-free activation. A capture thread keeps the last few seconds of
+// microphone audio; whenever an utterance ends it is transcribed on device
+// with the offline Whisper model and matched against the wake phrase.
+
+use serde::Serialize;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, Manager, State};
+
+use crate::automations;
+use crate::features::{self, Feature};
+use crate::haptics::{self, HapticPreset};
+use crate::settings::{self, PerformanceMode, SettingsState, WakeWordSettings};
+use crate::speech;
+use crate::thermal;
+use crate::tts;
+use crate::whisper;
+
+// Audio kept for matching; long enough for the phrase with some lead-in
+const WINDOW_SECS: f32 = 2.5;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Trailing quiet that marks the end of an utterance
+const TAIL_MS: u64 = 300;
+// Ignore further matches right after a detection
+const COOLDOWN: Duration = Duration::from_secs(2);
+
+struct Listener {
+    stop: mpsc::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct WakeWordStore {
+    listener: Option<Listener>,
+}
+
+pub type WakeWordState = Mutex<WakeWordStore>;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WakeWordEvent {
+    pub phrase: String,
+    pub transcript: String,
+    pub score: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct WakeWordStatus {
+    pub enabled: bool,
+    pub listening: bool,
+    pub phrase: String,
+    pub sensitivity: f32,
+}
+
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(current)
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+// Best similarity, 0.0 to 1.0, between the phrase and any run of as many words in the transcript
+fn match_score(transcript: &str, phrase: &str) -> f32 {
+    let phrase = normalize(phrase);
+    let transcript = normalize(transcript);
+    let words: Vec<&str> = transcript.split(' ').filter(|word| !word.is_empty()).collect();
+    let len = phrase.split(' ').count().min(words.len()).max(1);
+    words
+        .windows(len)
+        .map(|window| {
+            let candidate = window.join(" ");
+            let longest = candidate.chars().count().max(phrase.chars().count()).max(1);
+            1.0 - levenshtein(&candidate, &phrase) as f32 / longest as f32
+        })
+        .fold(0.0, f32::max)
+}
+
+// Higher sensitivity lowers the speech level and match score needed
+fn energy_threshold(sensitivity: f32) -> f32 {
+    0.03 - 0.025 * sensitivity.clamp(0.0, 1.0)
+}
+
+fn min_score(sensitivity: f32) -> f32 {
+    0.9 - 0.3 * sensitivity.clamp(0.0, 1.0)
+}
+
+async fn detect(
+    app_handle: AppHandle,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+    config: WakeWordSettings,
+    model: String,
+) {
+    let channels_len = channels.max(1) as usize;
+    let tail_len = ((sample_rate as u64 * TAIL_MS / 1000) as usize * channels_len).max(1);
+    let window_len = (sample_rate as f32 * WINDOW_SECS) as usize * channels_len;
+    let threshold = energy_threshold(config.sensitivity);
+    let mut heard_speech = false;
+    let mut last_detection: Option<Instant> = None;
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let window = {
+            let Ok(mut buffer) = buffer.lock() else {
+                return;
+            };
+            // Dictation owns the microphone's audio while it runs, and a hot device gets a rest
+            if speech::is_recording(&app_handle) || thermal::mode(&app_handle) == PerformanceMode::Minimal {
+                buffer.clear();
+                heard_speech = false;
+                continue;
+            }
+            if buffer.len() > window_len {
+                let excess = buffer.len() - window_len;
+                buffer.drain(..excess - excess % channels_len);
+            }
+            let tail = &buffer[buffer.len().saturating_sub(tail_len)..];
+            if speech::rms(tail) >= threshold {
+                heard_speech = true;
+                continue;
+            }
+            if !heard_speech {
+                continue;
+            }
+            heard_speech = false;
+            std::mem::take(&mut *buffer)
+        };
+        if last_detection.is_some_and(|at| at.elapsed() < COOLDOWN) {
+            continue;
+        }
+
+        let pcm: Vec<f32> = speech::to_whisper_pcm(&window, sample_rate, channels)
+            .into_iter()
+            .map(|sample| sample as f32 / i16::MAX as f32)
+            .collect();
+        let language = settings::current(&app_handle.state::<SettingsState>()).stt_language;
+        let Ok(transcription) =
+            whisper::transcribe(&app_handle, &model, pcm, thermal::inference_threads(&app_handle), language, None).await
+        else {
+            continue;
+        };
+        let transcript = whisper::join(&transcription.segments);
+        let score = match_score(&transcript, &config.phrase);
+        if score < min_score(config.sensitivity) {
+            continue;
+        }
+
+        last_detection = Some(Instant::now());
+        tts::interrupt(&app_handle);
+        let event = WakeWordEvent {
+            phrase: config.phrase.clone(),
+            transcript,
+            score,
+        };
+        let _ = haptics::play_preset(&app_handle, HapticPreset::WakeWordDetected);
+        automations::trigger(&app_handle, "wakeword.detected", serde_json::json!(&event));
+        let _ = app_handle.emit("wakeword://detected", event);
+    }
+}
+
+// cpal streams are not Send, so the microphone is held open on its own thread
+fn start_listener(app_handle: &AppHandle) -> Result<Listener, String> {
+    features::require(app_handle, Feature::WakeWord)?;
+    let app_settings = settings::current(&app_handle.state::<SettingsState>());
+    if !whisper::model_dir(app_handle, &app_settings.offline_model)?.exists() {
+        return Err("Download an offline transcription model to use the wake word".to_string());
+    }
+
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+    let (ready_tx, ready_rx) = mpsc::channel::<Result<(u32, u16), String>>();
+    let handle = app_handle.clone();
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let shared = buffer.clone();
+    let device_name = app_settings.input_device.clone();
+    std::thread::spawn(move || {
+        let opened = speech::find_input_device(device_name.as_deref())
+            .and_then(|device| speech::open_stream(&handle, &device, shared));
+        match opened {
+            Ok((stream, sample_rate, channels)) => {
+                let _ = ready_tx.send(Ok((sample_rate, channels)));
+                let _ = stop_rx.recv();
+                drop(stream);
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+            }
+        }
+    });
+
+    let (sample_rate, channels) = ready_rx
+        .recv()
+        .map_err(|_| "Audio capture thread exited".to_string())??;
+    let task = tauri::async_runtime::spawn(detect(
+        app_handle.clone(),
+        buffer,
+        sample_rate,
+        channels,
+        app_settings.wake_word,
+        app_settings.offline_model,
+    ));
+    Ok(Listener { stop: stop_tx, task })
+}
+
+// Stop any running listener and start a new one when the wake word is enabled
+fn restart(app_handle: &AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<WakeWordState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if let Some(listener) = store.listener.take() {
+        let _ = listener.stop.send(());
+        listener.task.abort();
+    }
+    if settings::current(&app_handle.state::<SettingsState>()).wake_word.enabled {
+        store.listener = Some(start_listener(app_handle)?);
+    }
+    Ok(())
+}
+
+// Start listening at launch when the wake word was left on
+pub fn spawn_listener(app_handle: AppHandle) {
+    let _ = restart(&app_handle);
+}
+
+fn status(app_handle: &AppHandle) -> Result<WakeWordStatus, String> {
+    let config = settings::current(&app_handle.state::<SettingsState>()).wake_word;
+    let listening = app_handle
+        .state::<WakeWordState>()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .listener
+        .is_some();
+    Ok(WakeWordStatus {
+        enabled: config.enabled,
+        listening,
+        phrase: config.phrase,
+        sensitivity: config.sensitivity,
+    })
+}
+
+#[tauri::command]
+pub fn set_wake_word_enabled(
+    enabled: bool,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<WakeWordStatus, String> {
+    let mut updated = settings::current(&settings_state);
+    updated.wake_word.enabled = enabled;
+    settings::update_settings(updated, app_handle.clone(), settings_state)?;
+    restart(&app_handle)?;
+    status(&app_handle)
+}
+
+// Command to tune detection, 0.0 (strict) to 1.0 (eager); applied immediately
+#[tauri::command]
+pub fn set_wake_word_sensitivity(
+    sensitivity: f32,
+    app_handle: AppHandle,
+    settings_state: State<'_, SettingsState>,
+) -> Result<WakeWordStatus, String> {
+    let mut updated = settings::current(&settings_state);
+    updated.wake_word.sensitivity = sensitivity.clamp(0.0, 1.0);
+    settings::update_settings(updated, app_handle.clone(), settings_state)?;
+    restart(&app_handle)?;
+    status(&app_handle)
+}
+
+#[tauri::command]
+pub fn get_wake_word_status(app_handle: AppHandle) -> Result<WakeWordStatus, String> {
+    status(&app_handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_drops_case_and_punctuation() {
+        assert_eq!(normalize("  Hey, Plates!  "), "hey plates");
+        assert_eq!(normalize("OK...plates?"), "ok plates");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein("plates", "plates"), 0);
+        assert_eq!(levenshtein("plates", "plate"), 1);
+        assert_eq!(levenshtein("plates", "blades"), 2);
+        assert_eq!(levenshtein("", "hey"), 3);
+        assert_eq!(levenshtein("héy", "hey"), 1);
+    }
+
+    #[test]
+    fn match_score_finds_the_phrase_in_a_transcript() {
+        assert_eq!(match_score("Hey Plates, what's the weather?", "hey plates"), 1.0);
+        let close = match_score("um hey plate", "hey plates");
+        assert!(close > 0.85 && close < 1.0);
+        assert!(match_score("turn the lights off", "hey plates") < 0.6);
+        assert_eq!(match_score("", "hey plates"), 0.0);
+        // A transcript shorter than the phrase is compared whole
+        assert!(match_score("plates", "hey plates") > 0.5);
+    }
+
+    #[test]
+    fn sensitivity_loosens_thresholds() {
+        assert!(energy_threshold(1.0) < energy_threshold(0.0));
+        assert!(min_score(1.0) < min_score(0.0));
+        assert_eq!(min_score(5.0), min_score(1.0));
+        assert_eq!(energy_threshold(-1.0), energy_threshold(0.0));
+        assert!((min_score(0.0) - 0.9).abs() < 1e-6);
+        assert!(energy_threshold(1.0) > 0.0);
+    }
+}
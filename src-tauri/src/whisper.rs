@@ -0,0 +1,531 @@
+// On-device Whisper inference with Candle. Models live in the app data dir
+// under models/<id>/ as config.json, tokenizer.json and either a quantized
+// model.gguf or a full model.safetensors.
+
+use candle_core::{Device, IndexOp, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::whisper::{self as m, audio, Config};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tokenizers::Tokenizer;
+
+use crate::acceleration;
+use crate::settings::InferenceBackend;
+
+const MODELS_DIR: &str = "models";
+const N_FFT: usize = 400;
+
+// Languages Whisper was trained on, as ISO codes and the names the API reports
+pub const LANGUAGES: [(&str, &str); 100] = [
+    ("en", "english"), ("zh", "chinese"), ("de", "german"), ("es", "spanish"), ("ru", "russian"),
+    ("ko", "korean"), ("fr", "french"), ("ja", "japanese"), ("pt", "portuguese"), ("tr", "turkish"),
+    ("pl", "polish"), ("ca", "catalan"), ("nl", "dutch"), ("ar", "arabic"), ("sv", "swedish"),
+    ("it", "italian"), ("id", "indonesian"), ("hi", "hindi"), ("fi", "finnish"), ("vi", "vietnamese"),
+    ("he", "hebrew"), ("uk", "ukrainian"), ("el", "greek"), ("ms", "malay"), ("cs", "czech"),
+    ("ro", "romanian"), ("da", "danish"), ("hu", "hungarian"), ("ta", "tamil"), ("no", "norwegian"),
+    ("th", "thai"), ("ur", "urdu"), ("hr", "croatian"), ("bg", "bulgarian"), ("lt", "lithuanian"),
+    ("la", "latin"), ("mi", "maori"), ("ml", "malayalam"), ("cy", "welsh"), ("sk", "slovak"),
+    ("te", "telugu"), ("fa", "persian"), ("lv", "latvian"), ("bn", "bengali"), ("sr", "serbian"),
+    ("az", "azerbaijani"), ("sl", "slovenian"), ("kn", "kannada"), ("et", "estonian"), ("mk", "macedonian"),
+    ("br", "breton"), ("eu", "basque"), ("is", "icelandic"), ("hy", "armenian"), ("ne", "nepali"),
+    ("mn", "mongolian"), ("bs", "bosnian"), ("kk", "kazakh"), ("sq", "albanian"), ("sw", "swahili"),
+    ("gl", "galician"), ("mr", "marathi"), ("pa", "punjabi"), ("si", "sinhala"), ("km", "khmer"),
+    ("sn", "shona"), ("yo", "yoruba"), ("so", "somali"), ("af", "afrikaans"), ("oc", "occitan"),
+    ("ka", "georgian"), ("be", "belarusian"), ("tg", "tajik"), ("sd", "sindhi"), ("gu", "gujarati"),
+    ("am", "amharic"), ("yi", "yiddish"), ("lo", "lao"), ("uz", "uzbek"), ("fo", "faroese"),
+    ("ht", "haitian creole"), ("ps", "pashto"), ("tk", "turkmen"), ("nn", "nynorsk"), ("mt", "maltese"),
+    ("sa", "sanskrit"), ("lb", "luxembourgish"), ("my", "myanmar"), ("bo", "tibetan"), ("tl", "tagalog"),
+    ("mg", "malagasy"), ("as", "assamese"), ("tt", "tatar"), ("haw", "hawaiian"), ("ln", "lingala"),
+    ("ha", "hausa"), ("ba", "bashkir"), ("jw", "javanese"), ("su", "sundanese"), ("yue", "cantonese"),
+];
+
+enum Model {
+    Full(m::model::Whisper),
+    Quantized(m::quantized_model::Whisper),
+}
+
+impl Model {
+    fn encoder_forward(&mut self, x: &Tensor, flush: bool) -> candle_core::Result<Tensor> {
+        match self {
+            Model::Full(model) => model.encoder.forward(x, flush),
+            Model::Quantized(model) => model.encoder.forward(x, flush),
+        }
+    }
+
+    fn decoder_forward(&mut self, x: &Tensor, audio_features: &Tensor, flush: bool) -> candle_core::Result<Tensor> {
+        match self {
+            Model::Full(model) => model.decoder.forward(x, audio_features, flush),
+            Model::Quantized(model) => model.decoder.forward(x, audio_features, flush),
+        }
+    }
+
+    fn decoder_final_linear(&self, x: &Tensor) -> candle_core::Result<Tensor> {
+        match self {
+            Model::Full(model) => model.decoder.final_linear(x),
+            Model::Quantized(model) => model.decoder.final_linear(x),
+        }
+    }
+}
+
+pub struct LoadedModel {
+    id: String,
+    backend: InferenceBackend,
+    device: Device,
+    model: Model,
+    config: Config,
+    tokenizer: Tokenizer,
+    mel_filters: Vec<f32>,
+    // Size of the weights on disk, a close estimate of resident memory
+    bytes: u64,
+    last_used: Instant,
+}
+
+// The loaded model is kept between transcriptions; loading takes seconds
+#[derive(Default)]
+pub struct WhisperStore {
+    loaded: Option<LoadedModel>,
+}
+
+pub type WhisperState = Mutex<WhisperStore>;
+
+// Text decoded from one window of audio, with its position in seconds
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Transcription {
+    // ISO code, forced or detected; unknown when there was no audio
+    pub language: Option<String>,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ResidentModel {
+    pub id: String,
+    pub bytes: u64,
+    pub idle_secs: u64,
+}
+
+pub fn model_dir(app_handle: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    Ok(app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(MODELS_DIR)
+        .join(id))
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    // Slaney scale: linear below 1 kHz, logarithmic above
+    let f_sp = 200.0 / 3.0;
+    let log_step = 6.4f64.ln() / 27.0;
+    if hz < 1000.0 {
+        hz / f_sp
+    } else {
+        1000.0 / f_sp + (hz / 1000.0).ln() / log_step
+    }
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    let f_sp = 200.0 / 3.0;
+    let log_step = 6.4f64.ln() / 27.0;
+    let min_log_mel = 1000.0 / f_sp;
+    if mel < min_log_mel {
+        mel * f_sp
+    } else {
+        1000.0 * (log_step * (mel - min_log_mel)).exp()
+    }
+}
+
+// Slaney-normalized mel filterbank, the same as the one Whisper was trained with
+fn mel_filters(n_mels: usize) -> Vec<f32> {
+    let n_freqs = N_FFT / 2 + 1;
+    let nyquist = m::SAMPLE_RATE as f64 / 2.0;
+    let fft_freqs: Vec<f64> = (0..n_freqs).map(|i| i as f64 * nyquist / (n_freqs - 1) as f64).collect();
+    let (min_mel, max_mel) = (hz_to_mel(0.0), hz_to_mel(nyquist));
+    let mel_freqs: Vec<f64> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(min_mel + i as f64 * (max_mel - min_mel) / (n_mels + 1) as f64))
+        .collect();
+
+    let mut filters = vec![0f32; n_mels * n_freqs];
+    for i in 0..n_mels {
+        let (left, center, right) = (mel_freqs[i], mel_freqs[i + 1], mel_freqs[i + 2]);
+        let norm = 2.0 / (right - left);
+        for (j, freq) in fft_freqs.iter().enumerate() {
+            let lower = (freq - left) / (center - left);
+            let upper = (right - freq) / (right - center);
+            filters[i * n_freqs + j] = (lower.min(upper).max(0.0) * norm) as f32;
+        }
+    }
+    filters
+}
+
+fn load(app_handle: &AppHandle, id: &str, backend: InferenceBackend) -> Result<LoadedModel, String> {
+    let dir = model_dir(app_handle, id)?;
+    if !dir.exists() {
+        return Err(format!("Offline model {} is not installed", id));
+    }
+    load_dir(&dir, id, backend)
+}
+
+fn load_dir(dir: &Path, id: &str, backend: InferenceBackend) -> Result<LoadedModel, String> {
+    let device = acceleration::device(backend)?;
+    let config: Config = serde_json::from_str(
+        &std::fs::read_to_string(dir.join("config.json")).map_err(|e| e.to_string())?,
+    )
+    .map_err(|e| e.to_string())?;
+    let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| e.to_string())?;
+
+    let quantized = dir.join("model.gguf");
+    let weights = if quantized.exists() { quantized.clone() } else { dir.join("model.safetensors") };
+    let bytes = std::fs::metadata(&weights).map(|metadata| metadata.len()).unwrap_or(0);
+    let model = if quantized.exists() {
+        let vb = candle_transformers::quantized_var_builder::VarBuilder::from_gguf(&quantized, &device)
+            .map_err(|e| e.to_string())?;
+        Model::Quantized(m::quantized_model::Whisper::load(&vb, config.clone()).map_err(|e| e.to_string())?)
+    } else {
+        // Safety: the weights file is not modified while mapped
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], m::DTYPE, &device) }
+            .map_err(|e| e.to_string())?;
+        Model::Full(m::model::Whisper::load(&vb, config.clone()).map_err(|e| e.to_string())?)
+    };
+
+    Ok(LoadedModel {
+        id: id.to_string(),
+        backend,
+        device,
+        model,
+        mel_filters: mel_filters(config.num_mel_bins),
+        config,
+        tokenizer,
+        bytes,
+        last_used: Instant::now(),
+    })
+}
+
+// ISO code for a language given by code or by name, as the Whisper API reports it
+pub fn language_code(language: &str) -> Option<&'static str> {
+    let language = language.trim().to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(code, name)| *code == language || *name == language)
+        .map(|(code, _)| *code)
+}
+
+fn token_id(tokenizer: &Tokenizer, token: &str) -> Result<u32, String> {
+    tokenizer
+        .token_to_id(token)
+        .ok_or(format!("Tokenizer has no {} token", token))
+}
+
+// The most likely of the candidate language tokens after the start token,
+// judged from the first window of audio
+fn detect_language(
+    model: &mut Model,
+    mel: &Tensor,
+    sot: u32,
+    candidates: &[(&'static str, u32)],
+    device: &Device,
+) -> Result<&'static str, String> {
+    let frames = mel.dim(2).map_err(|e| e.to_string())?;
+    let window = mel.narrow(2, 0, frames.min(m::N_FRAMES)).map_err(|e| e.to_string())?;
+    let audio_features = model.encoder_forward(&window, true).map_err(|e| e.to_string())?;
+    let ids: Vec<u32> = candidates.iter().map(|(_, id)| *id).collect();
+    let best = Tensor::new(&[sot], device)
+        .and_then(|t| t.unsqueeze(0))
+        .and_then(|tokens| model.decoder_forward(&tokens, &audio_features, true))
+        .and_then(|ys| model.decoder_final_linear(&ys.i(..1)?))
+        .and_then(|logits| logits.i(0)?.i(0))
+        .and_then(|logits| logits.index_select(&Tensor::new(ids.as_slice(), device)?, 0))
+        .and_then(|logits| logits.argmax(0)?.to_scalar::<u32>())
+        .map_err(|e| e.to_string())?;
+    Ok(candidates[best as usize].0)
+}
+
+// Greedy decoding over 30 second windows of mono 16 kHz audio, in the given
+// language or the one detected from the first window. A prompt is fed in as
+// previous text, which biases the model towards its words.
+fn decode(
+    loaded: &mut LoadedModel,
+    pcm: &[f32],
+    language: Option<&str>,
+    prompt: Option<&str>,
+) -> Result<Transcription, String> {
+    let device = loaded.device.clone();
+    let config = &loaded.config;
+    let tokenizer = &loaded.tokenizer;
+
+    let sot = token_id(tokenizer, m::SOT_TOKEN)?;
+    let transcribe = token_id(tokenizer, m::TRANSCRIBE_TOKEN)?;
+    let no_timestamps = token_id(tokenizer, m::NO_TIMESTAMPS_TOKEN)?;
+    let eot = token_id(tokenizer, m::EOT_TOKEN)?;
+    // English-only models have no language tokens
+    let candidates: Vec<(&'static str, u32)> = LANGUAGES
+        .iter()
+        .filter_map(|(code, _)| tokenizer.token_to_id(&format!("<|{}|>", code)).map(|id| (*code, id)))
+        .collect();
+
+    let vocab_size = config.vocab_size;
+    let suppress: Vec<f32> = (0..vocab_size as u32)
+        .map(|token| {
+            if config.suppress_tokens.contains(&token) || token == no_timestamps {
+                f32::NEG_INFINITY
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let suppress = Tensor::new(suppress.as_slice(), &device).map_err(|e| e.to_string())?;
+
+    let mel = audio::pcm_to_mel(config, pcm, &loaded.mel_filters);
+    let frames = mel.len() / config.num_mel_bins;
+    let mel = Tensor::from_vec(mel, (1, config.num_mel_bins, frames), &device).map_err(|e| e.to_string())?;
+
+    let language = match language {
+        Some(language) if candidates.is_empty() && language != "en" => {
+            return Err(format!("Offline model {} only transcribes English", loaded.id));
+        }
+        Some(language) => candidates
+            .iter()
+            .find(|(code, _)| *code == language)
+            .map(|(code, _)| *code)
+            .or(candidates.is_empty().then_some("en"))
+            .ok_or(format!("Unsupported language: {}", language))?,
+        None if candidates.is_empty() => "en",
+        None if frames == 0 => {
+            return Ok(Transcription {
+                language: None,
+                segments: Vec::new(),
+            })
+        }
+        None => detect_language(&mut loaded.model, &mel, sot, &candidates, &device)?,
+    };
+
+    let mut prefix = Vec::new();
+    if let Some(prompt) = prompt {
+        let encoded = tokenizer
+            .encode(format!(" {}", prompt.trim()), false)
+            .map_err(|e| e.to_string())?;
+        let ids = encoded.get_ids();
+        // Keep the end of a long prompt, leaving room for the transcript
+        let keep = ids.len().min(config.max_target_positions / 4);
+        prefix.push(token_id(tokenizer, "<|startofprev|>")?);
+        prefix.extend_from_slice(&ids[ids.len() - keep..]);
+    }
+    prefix.push(sot);
+    prefix.extend(candidates.iter().find(|(code, _)| *code == language).map(|(_, id)| *id));
+    prefix.extend([transcribe, no_timestamps]);
+
+    let frame_secs = m::HOP_LENGTH as f64 / m::SAMPLE_RATE as f64;
+    let mut segments = Vec::new();
+    let mut seek = 0;
+    while seek < frames {
+        let size = usize::min(frames - seek, m::N_FRAMES);
+        let segment = mel.narrow(2, seek, size).map_err(|e| e.to_string())?;
+        let start = seek as f64 * frame_secs;
+        seek += size;
+
+        let audio_features = loaded.model.encoder_forward(&segment, true).map_err(|e| e.to_string())?;
+        let mut tokens = prefix.clone();
+        for i in 0..config.max_target_positions / 2 {
+            let tokens_t = Tensor::new(tokens.as_slice(), &device)
+                .and_then(|t| t.unsqueeze(0))
+                .map_err(|e| e.to_string())?;
+            let ys = loaded
+                .model
+                .decoder_forward(&tokens_t, &audio_features, i == 0)
+                .map_err(|e| e.to_string())?;
+            let (_, seq_len, _) = ys.dims3().map_err(|e| e.to_string())?;
+            let next = ys
+                .i((..1, seq_len - 1..))
+                .and_then(|last| loaded.model.decoder_final_linear(&last))
+                .and_then(|logits| logits.i(0)?.i(0))
+                .and_then(|logits| logits.broadcast_add(&suppress))
+                .and_then(|logits| logits.argmax(0)?.to_scalar::<u32>())
+                .map_err(|e| e.to_string())?;
+            if next == eot {
+                break;
+            }
+            tokens.push(next);
+        }
+
+        let segment_text = tokenizer
+            .decode(&tokens[prefix.len()..], true)
+            .map_err(|e| e.to_string())?;
+        let segment_text = segment_text.trim();
+        if !segment_text.is_empty() {
+            segments.push(Segment {
+                start,
+                end: seek as f64 * frame_secs,
+                text: segment_text.to_string(),
+            });
+        }
+    }
+    Ok(Transcription {
+        language: Some(language.to_string()),
+        segments,
+    })
+}
+
+// The segments' text as one transcript
+pub fn join(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Drop the cached model if it is the given one, before its files are replaced or deleted
+pub fn unload(app_handle: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app_handle.state::<WhisperState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    if store.loaded.as_ref().is_some_and(|loaded| loaded.id == id) {
+        store.loaded = None;
+    }
+    Ok(())
+}
+
+// The cached model, if any, for memory reporting
+pub fn resident(app_handle: &AppHandle) -> Option<ResidentModel> {
+    let state = app_handle.state::<WhisperState>();
+    let store = state.lock().ok()?;
+    store.loaded.as_ref().map(|loaded| ResidentModel {
+        id: loaded.id.clone(),
+        bytes: loaded.bytes,
+        idle_secs: loaded.last_used.elapsed().as_secs(),
+    })
+}
+
+// Drop the cached model once it has been unused for `max_idle`; returns what was unloaded
+pub fn unload_idle(app_handle: &AppHandle, max_idle: Duration) -> Option<ResidentModel> {
+    let state = app_handle.state::<WhisperState>();
+    let mut store = state.lock().ok()?;
+    if store.loaded.as_ref()?.last_used.elapsed() < max_idle {
+        return None;
+    }
+    store.loaded.take().map(|loaded| ResidentModel {
+        idle_secs: loaded.last_used.elapsed().as_secs(),
+        id: loaded.id,
+        bytes: loaded.bytes,
+    })
+}
+
+// Time loading a model on a backend and transcribing `pcm` with it, in
+// milliseconds; the cached model is left alone. Blocks for the duration.
+pub fn benchmark(
+    app_handle: &AppHandle,
+    model_id: &str,
+    backend: InferenceBackend,
+    pcm: &[f32],
+) -> Result<(u64, u64), String> {
+    let started = Instant::now();
+    let mut loaded = load(app_handle, model_id, backend)?;
+    let load_ms = started.elapsed().as_millis() as u64;
+    let started = Instant::now();
+    decode(&mut loaded, pcm, None, None)?;
+    Ok((load_ms, started.elapsed().as_millis() as u64))
+}
+
+// Load the model files in `dir` on the CPU and decode a second of audio, to
+// check downloaded files before they replace an installed model. Blocks.
+pub fn smoke_test(dir: &Path) -> Result<(), String> {
+    let mut loaded = load_dir(dir, "smoke-test", InferenceBackend::Cpu)?;
+    let pcm = vec![0.0f32; m::SAMPLE_RATE];
+    decode(&mut loaded, &pcm, Some("en"), None).map(|_| ())
+}
+
+// Transcribe mono 16 kHz samples with the given installed model, entirely on device,
+// using at most `threads` threads when given; the language is detected when not given
+pub async fn transcribe(
+    app_handle: &AppHandle,
+    model_id: &str,
+    pcm: Vec<f32>,
+    threads: Option<usize>,
+    language: Option<String>,
+    prompt: Option<String>,
+) -> Result<Transcription, String> {
+    let handle = app_handle.clone();
+    let model_id = model_id.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        let backend = acceleration::backend_for(&handle, &model_id);
+        let state = handle.state::<WhisperState>();
+        let mut store = state.lock().map_err(|e| e.to_string())?;
+        let stale = store
+            .loaded
+            .as_ref()
+            .map(|loaded| loaded.id != model_id || loaded.backend != backend)
+            .unwrap_or(true);
+        if stale {
+            store.loaded = None;
+            store.loaded = Some(load(&handle, &model_id, backend)?);
+        }
+        let loaded = store.loaded.as_mut().ok_or("Offline model is not loaded".to_string())?;
+        // Candle's CPU kernels run on the current rayon pool, so a smaller pool caps their threads
+        let language = language.as_deref();
+        let prompt = prompt.as_deref();
+        let transcription = match threads {
+            Some(threads) => rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| e.to_string())?
+                .install(|| decode(loaded, &pcm, language, prompt)),
+            None => decode(loaded, &pcm, language, prompt),
+        };
+        loaded.last_used = Instant::now();
+        transcription
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mel_scale_round_trips() {
+        for hz in [0.0, 440.0, 999.0, 1000.0, 4000.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 1e-6);
+        }
+        assert!((hz_to_mel(1000.0) - 15.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mel_filters_are_triangles_over_the_spectrum() {
+        let n_freqs = N_FFT / 2 + 1;
+        let filters = mel_filters(80);
+        assert_eq!(filters.len(), 80 * n_freqs);
+        assert!(filters.iter().all(|weight| weight.is_finite() && *weight >= 0.0));
+        for row in filters.chunks(n_freqs) {
+            assert!(row.iter().any(|weight| *weight > 0.0));
+        }
+    }
+
+    #[test]
+    fn language_code_accepts_codes_and_names() {
+        assert_eq!(language_code("fr"), Some("fr"));
+        assert_eq!(language_code(" French "), Some("fr"));
+        assert_eq!(language_code("Haitian Creole"), Some("ht"));
+        assert_eq!(language_code("klingon"), None);
+        assert_eq!(language_code(""), None);
+    }
+
+    #[test]
+    fn join_spaces_segment_text() {
+        let segment = |start: f64, text: &str| Segment {
+            start,
+            end: start + 30.0,
+            text: text.to_string(),
+        };
+        assert_eq!(join(&[segment(0.0, "Hello there."), segment(30.0, "Bye.")]), "Hello there. Bye.");
+        assert_eq!(join(&[]), "");
+    }
+}
@@ -0,0 +1,566 @@
+use serde::{Serialize, Deserialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{
+    plugin::{Builder, TauriPlugin},
+    AppHandle, Manager, Runtime, State,
+};
+
+use crate::consent::{self, ConsentScope};
+use crate::conversations::PinnedItem;
+use crate::notes::NotesState;
+use crate::recipes;
+use crate::storage;
+use crate::timers::TimersState;
+
+#[cfg(android_bridges)]
+use tauri::plugin::PluginHandle;
+
+const WIDGET_CONFIG_FILE: &str = "widgets.json";
+// Native widgets read their snapshots from this directory in app data
+const SNAPSHOT_DIR: &str = "widgets";
+// How often the refresh loop wakes up to check for stale widgets
+const REFRESH_TICK_SECS: u64 = 60;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum WidgetKind {
+    Weather,
+    Agenda,
+    Notes,
+    Pinned,
+    Spending,
+    Activity,
+}
+
+impl WidgetKind {
+    pub const ALL: [WidgetKind; 6] = [
+        WidgetKind::Weather,
+        WidgetKind::Agenda,
+        WidgetKind::Notes,
+        WidgetKind::Pinned,
+        WidgetKind::Spending,
+        WidgetKind::Activity,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            WidgetKind::Weather => "weather",
+            WidgetKind::Agenda => "agenda",
+            WidgetKind::Notes => "notes",
+            WidgetKind::Pinned => "pinned",
+            WidgetKind::Spending => "spending",
+            WidgetKind::Activity => "activity",
+        }
+    }
+
+    // Seconds between refreshes of this widget
+    fn refresh_secs(self) -> u64 {
+        match self {
+            WidgetKind::Weather => 30 * 60,
+            WidgetKind::Agenda => 60,
+            WidgetKind::Notes => 15 * 60,
+            WidgetKind::Pinned => 15 * 60,
+            WidgetKind::Spending => 15 * 60,
+            WidgetKind::Activity => 15 * 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WidgetLine {
+    pub text: String,
+    pub detail: Option<String>,
+    pub icon: Option<String>,
+}
+
+// Structured data a native widget needs to draw itself
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WidgetSnapshot {
+    pub kind: WidgetKind,
+    pub title: String,
+    pub lines: Vec<WidgetLine>,
+    pub updated_at: u64,
+    pub refresh_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WidgetConfig {
+    // Last known location used for the weather widget
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // When the location was last changed, for the retention policy
+    pub located_at: Option<u64>,
+    pub enabled: Vec<WidgetKind>,
+}
+
+impl Default for WidgetConfig {
+    fn default() -> Self {
+        WidgetConfig {
+            latitude: None,
+            longitude: None,
+            located_at: None,
+            enabled: WidgetKind::ALL.to_vec(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WidgetStore {
+    config: WidgetConfig,
+    last_refresh: Vec<(WidgetKind, u64)>,
+}
+
+pub type WidgetsState = Mutex<WidgetStore>;
+
+impl WidgetStore {
+    // Enabled widgets never refreshed or older than their refresh interval
+    fn stale(&self, now: u64) -> Vec<WidgetKind> {
+        self.config
+            .enabled
+            .iter()
+            .copied()
+            .filter(|kind| {
+                self.last_refresh
+                    .iter()
+                    .find(|(k, _)| k == kind)
+                    .map(|(_, at)| now.saturating_sub(*at) >= kind.refresh_secs())
+                    .unwrap_or(true)
+            })
+            .collect()
+    }
+
+    fn refreshed(&mut self, kind: WidgetKind, at: u64) {
+        self.last_refresh.retain(|(k, _)| *k != kind);
+        self.last_refresh.push((kind, at));
+    }
+
+    // Clear a location set before the cutoff. None when nothing changed,
+    // otherwise whether the location was cleared
+    fn expire_location(&mut self, cutoff: u64, now: u64) -> Option<bool> {
+        self.config.latitude?;
+        // A location saved before it was timestamped starts its clock now
+        let Some(located_at) = self.config.located_at else {
+            self.config.located_at = Some(now);
+            return Some(false);
+        };
+        if located_at >= cutoff {
+            return None;
+        }
+        self.config.latitude = None;
+        self.config.longitude = None;
+        self.config.located_at = None;
+        self.last_refresh.clear();
+        Some(true)
+    }
+}
+
+// When a newly configured location was set: unchanged places keep their time
+fn located_at(previous: &WidgetConfig, config: &WidgetConfig, now: u64) -> Option<u64> {
+    let location = (config.latitude, config.longitude);
+    if location == (None, None) {
+        None
+    } else if location == (previous.latitude, previous.longitude) {
+        previous.located_at
+    } else {
+        Some(now)
+    }
+}
+
+pub struct WidgetBridge<R: Runtime> {
+    #[cfg(android_bridges)]
+    handle: PluginHandle<R>,
+    #[cfg(not(android_bridges))]
+    _runtime: std::marker::PhantomData<fn() -> R>,
+}
+
+impl<R: Runtime> WidgetBridge<R> {
+    // Ask the OS to redraw home screen widgets from their snapshots
+    fn notify_updated(&self) {
+        #[cfg(android_bridges)]
+        {
+            let _ = self.handle.run_mobile_plugin::<()>("refreshWidgets", ());
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn line(text: String, detail: Option<String>) -> WidgetLine {
+    WidgetLine { text, detail, icon: None }
+}
+
+// The saved location is only read once the user has agreed to share it; the
+// snapshot and spoken or remote briefings all come through here
+async fn render_weather(app_handle: &AppHandle, config: &WidgetConfig) -> Result<Vec<WidgetLine>, String> {
+    consent::require(app_handle, ConsentScope::WeatherLocation)?;
+    let (Some(lat), Some(lon)) = (config.latitude, config.longitude) else {
+        return Ok(vec![line("Location unavailable".to_string(), None)]);
+    };
+    let weather = crate::fetch_weather(app_handle, lat, lon).await?;
+    Ok(vec![WidgetLine {
+        text: weather.temperature,
+        detail: None,
+        icon: weather.icon,
+    }])
+}
+
+fn render_agenda(app_handle: &AppHandle) -> Result<Vec<WidgetLine>, String> {
+    let state = app_handle.state::<TimersState>();
+    let now = now_secs();
+    let mut lines: Vec<WidgetLine> = crate::timers::list_timers(state)?
+        .into_iter()
+        .map(|timer| {
+            let remaining = timer.ends_at.saturating_sub(now);
+            line(
+                timer.label.unwrap_or_else(|| "Timer".to_string()),
+                Some(format!("{}:{:02} left", remaining / 60, remaining % 60)),
+            )
+        })
+        .collect();
+
+    for event in crate::calendar::upcoming_events(app_handle, 1) {
+        let starts_in = (event.start - now as i64).max(0) / 60;
+        let detail = if event.all_day {
+            "All day".to_string()
+        } else if starts_in == 0 {
+            "Now".to_string()
+        } else {
+            format!("In {}h {:02}m", starts_in / 60, starts_in % 60)
+        };
+        lines.push(line(event.summary, Some(detail)));
+    }
+
+    lines.truncate(4);
+    Ok(lines)
+}
+
+fn render_notes(app_handle: &AppHandle) -> Result<Vec<WidgetLine>, String> {
+    let state = app_handle.state::<NotesState>();
+    let notes = crate::notes::list_notes(state)?;
+    Ok(notes
+        .into_iter()
+        .take(3)
+        .map(|note| line(note.title, None))
+        .collect())
+}
+
+fn render_pinned(app_handle: &AppHandle) -> Vec<WidgetLine> {
+    crate::conversations::pinned_items(app_handle)
+        .into_iter()
+        .take(3)
+        .map(|item| match item {
+            PinnedItem::Conversation { title, .. } => WidgetLine {
+                text: title,
+                detail: None,
+                icon: Some("pin".to_string()),
+            },
+            PinnedItem::Message { text, .. } => WidgetLine {
+                text: text.lines().next().unwrap_or_default().to_string(),
+                detail: None,
+                icon: Some("star".to_string()),
+            },
+        })
+        .collect()
+}
+
+// Produce the data for one widget
+pub async fn render(app_handle: &AppHandle, kind: WidgetKind) -> Result<WidgetSnapshot, String> {
+    let config = {
+        let state = app_handle.state::<WidgetsState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store.config.clone()
+    };
+
+    let (title, lines) = match kind {
+        WidgetKind::Weather => ("Weather", render_weather(app_handle, &config).await?),
+        WidgetKind::Agenda => ("Up next", render_agenda(app_handle)?),
+        WidgetKind::Notes => ("Notes", render_notes(app_handle)?),
+        WidgetKind::Pinned => ("Pinned", render_pinned(app_handle)),
+        WidgetKind::Spending => ("This week", render_spending(app_handle)?),
+        WidgetKind::Activity => ("Today", render_activity(app_handle)),
+    };
+
+    Ok(WidgetSnapshot {
+        kind,
+        title: title.to_string(),
+        lines,
+        updated_at: now_secs(),
+        refresh_secs: kind.refresh_secs(),
+    })
+}
+
+fn render_spending(app_handle: &AppHandle) -> Result<Vec<WidgetLine>, String> {
+    let summary = crate::expenses::spending_summary(app_handle, 7)?;
+    if summary.count == 0 {
+        return Ok(vec![line("Nothing spent".to_string(), None)]);
+    }
+    let mut lines: Vec<WidgetLine> = summary
+        .totals
+        .into_iter()
+        .map(|total| line(format!("{:.2} {}", total.amount, total.currency), Some("Spent".to_string())))
+        .collect();
+    lines.extend(
+        summary
+            .by_category
+            .into_iter()
+            .take(2)
+            .map(|total| line(total.category, Some(format!("{:.2} {}", total.amount, total.currency)))),
+    );
+    lines.truncate(4);
+    Ok(lines)
+}
+
+fn render_activity(app_handle: &AppHandle) -> Vec<WidgetLine> {
+    let activity = crate::health::daily_activity(app_handle);
+    let mut lines = Vec::new();
+    if let Some(steps) = activity.steps {
+        lines.push(WidgetLine {
+            text: format!("{} steps", steps),
+            detail: None,
+            icon: Some("steps".to_string()),
+        });
+    }
+    lines.push(WidgetLine {
+        text: format!("{} ml of water", activity.water_ml),
+        detail: None,
+        icon: Some("water".to_string()),
+    });
+    lines
+}
+
+// Short text lines summarizing the widgets, used for spoken and remote briefings
+pub async fn briefing_snippets(app_handle: &AppHandle) -> Vec<String> {
+    let mut snippets = Vec::new();
+    for kind in WidgetKind::ALL {
+        if let Ok(snapshot) = render(app_handle, kind).await {
+            for line in snapshot.lines {
+                match line.detail {
+                    Some(detail) => snippets.push(format!("{}: {} ({})", snapshot.title, line.text, detail)),
+                    None => snippets.push(format!("{}: {}", snapshot.title, line.text)),
+                }
+            }
+        }
+    }
+    for meal in recipes::todays_meals(app_handle) {
+        snippets.push(format!("Meal plan: {}", meal));
+    }
+    snippets
+}
+
+// Render a widget and write its snapshot where the native widget can read it
+async fn refresh(app_handle: &AppHandle, kind: WidgetKind) -> Result<WidgetSnapshot, String> {
+    let snapshot = render(app_handle, kind).await?;
+    storage::save_json(
+        app_handle,
+        &format!("{}/{}.json", SNAPSHOT_DIR, kind.name()),
+        &snapshot,
+    )?;
+
+    let state = app_handle.state::<WidgetsState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    store.refreshed(kind, snapshot.updated_at);
+    Ok(snapshot)
+}
+
+// Refresh widgets ahead of schedule, e.g. before a predicted interaction
+pub async fn refresh_now(app_handle: &AppHandle, kinds: &[WidgetKind]) -> usize {
+    let mut updated = 0;
+    for kind in kinds {
+        if refresh(app_handle, *kind).await.is_ok() {
+            updated += 1;
+        }
+    }
+    if updated > 0 {
+        app_handle.state::<WidgetBridge<tauri::Wry>>().notify_updated();
+    }
+    updated
+}
+
+fn stale_widgets(app_handle: &AppHandle) -> Vec<WidgetKind> {
+    let state = app_handle.state::<WidgetsState>();
+    let Ok(store) = state.lock() else {
+        return Vec::new();
+    };
+    store.stale(now_secs())
+}
+
+// Keep widget snapshots fresh; started once app state is managed
+pub fn spawn_refresh_loop(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let stale = stale_widgets(&app_handle);
+            let mut updated = false;
+            for kind in stale {
+                updated |= refresh(&app_handle, kind).await.is_ok();
+            }
+            if updated {
+                app_handle.state::<WidgetBridge<tauri::Wry>>().notify_updated();
+            }
+            tokio::time::sleep(Duration::from_secs(REFRESH_TICK_SECS)).await;
+        }
+    });
+}
+
+pub fn init() -> TauriPlugin<tauri::Wry> {
+    Builder::new("widgets")
+        .setup(|app, api| {
+            #[cfg(android_bridges)]
+            let bridge = WidgetBridge {
+                handle: api.register_android_plugin("company.atechnology.plates", "WidgetBridgePlugin")?,
+            };
+            #[cfg(not(android_bridges))]
+            let bridge = {
+                let _ = api;
+                WidgetBridge::<tauri::Wry> { _runtime: std::marker::PhantomData }
+            };
+            app.manage(bridge);
+
+            let config: WidgetConfig = storage::load_json(app, WIDGET_CONFIG_FILE);
+            app.manage(WidgetsState::new(WidgetStore {
+                config,
+                last_refresh: Vec::new(),
+            }));
+            Ok(())
+        })
+        .build()
+}
+
+// Clear a location set before the cutoff; returns whether one was cleared
+pub fn forget_location(app_handle: &AppHandle, cutoff: u64) -> Result<bool, String> {
+    let state = app_handle.state::<WidgetsState>();
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    let Some(cleared) = store.expire_location(cutoff, now_secs()) else {
+        return Ok(false);
+    };
+    storage::save_json(app_handle, WIDGET_CONFIG_FILE, &store.config)?;
+    Ok(cleared)
+}
+
+// Command used by the native widget bridges on their refresh cycle
+#[tauri::command]
+pub async fn get_widget_data(kind: WidgetKind, app_handle: AppHandle) -> Result<WidgetSnapshot, String> {
+    refresh(&app_handle, kind).await
+}
+
+// Command to choose widgets and the location used for weather
+#[tauri::command]
+pub fn configure_widgets(
+    mut config: WidgetConfig,
+    app_handle: AppHandle,
+    state: State<'_, WidgetsState>,
+) -> Result<(), String> {
+    let mut store = state.lock().map_err(|e| e.to_string())?;
+    config.located_at = located_at(&store.config, &config, now_secs());
+    storage::save_json(&app_handle, WIDGET_CONFIG_FILE, &config)?;
+    store.config = config;
+    store.last_refresh.clear();
+    Ok(())
+}
+
+// Command to refresh every enabled widget immediately
+#[tauri::command]
+pub async fn refresh_widgets(
+    app_handle: AppHandle,
+    bridge: State<'_, WidgetBridge<tauri::Wry>>,
+) -> Result<Vec<WidgetSnapshot>, String> {
+    let enabled = {
+        let state = app_handle.state::<WidgetsState>();
+        let store = state.lock().map_err(|e| e.to_string())?;
+        store.config.enabled.clone()
+    };
+
+    let mut snapshots = Vec::new();
+    for kind in enabled {
+        snapshots.push(refresh(&app_handle, kind).await?);
+    }
+    bridge.notify_updated();
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(enabled: Vec<WidgetKind>) -> WidgetStore {
+        WidgetStore {
+            config: WidgetConfig {
+                enabled,
+                ..Default::default()
+            },
+            last_refresh: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn config_enables_every_widget_by_default() {
+        let config: WidgetConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.enabled, WidgetKind::ALL.to_vec());
+        assert!(config.latitude.is_none());
+        let kinds: Vec<WidgetKind> = serde_json::from_str(r#"["weather","pinned"]"#).unwrap();
+        assert_eq!(kinds, vec![WidgetKind::Weather, WidgetKind::Pinned]);
+    }
+
+    #[test]
+    fn never_refreshed_widgets_are_stale() {
+        assert!(store(Vec::new()).stale(0).is_empty());
+        let store = store(vec![WidgetKind::Agenda, WidgetKind::Notes]);
+        assert_eq!(store.stale(0), vec![WidgetKind::Agenda, WidgetKind::Notes]);
+    }
+
+    #[test]
+    fn widgets_go_stale_after_their_interval() {
+        let mut store = store(vec![WidgetKind::Agenda, WidgetKind::Weather]);
+        store.refreshed(WidgetKind::Agenda, 1_000);
+        store.refreshed(WidgetKind::Weather, 1_000);
+        assert!(store.stale(1_059).is_empty());
+        assert_eq!(store.stale(1_060), vec![WidgetKind::Agenda]);
+        assert_eq!(store.stale(1_000 + 30 * 60), vec![WidgetKind::Agenda, WidgetKind::Weather]);
+
+        // Refreshing again replaces the old time, and a clock change does not underflow
+        store.refreshed(WidgetKind::Agenda, 2_000);
+        assert_eq!(store.last_refresh.len(), 2);
+        assert!(store.stale(1_500).is_empty());
+    }
+
+    fn located(latitude: f64, located_at: Option<u64>) -> WidgetConfig {
+        WidgetConfig {
+            latitude: Some(latitude),
+            longitude: Some(13.4),
+            located_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn locations_expire_after_the_cutoff() {
+        let mut store = store(WidgetKind::ALL.to_vec());
+        assert_eq!(store.expire_location(100, 50), None);
+
+        store.config = located(52.5, Some(100));
+        store.refreshed(WidgetKind::Weather, 100);
+        assert_eq!(store.expire_location(100, 200), None);
+        assert_eq!(store.expire_location(101, 200), Some(true));
+        assert!(store.config.latitude.is_none() && store.config.longitude.is_none());
+        assert!(store.last_refresh.is_empty());
+
+        // An untimestamped location is kept and starts its clock
+        store.config = located(52.5, None);
+        assert_eq!(store.expire_location(u64::MAX, 300), Some(false));
+        assert_eq!(store.config.located_at, Some(300));
+    }
+
+    #[test]
+    fn location_time_changes_only_with_the_place() {
+        let previous = located(52.5, Some(10));
+        assert_eq!(located_at(&previous, &located(52.5, None), 99), Some(10));
+        assert_eq!(located_at(&previous, &located(48.1, None), 99), Some(99));
+        assert_eq!(located_at(&previous, &WidgetConfig::default(), 99), None);
+    }
+}
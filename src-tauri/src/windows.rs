@@ -0,0 +1,166 @@
+// Auxiliary windows: an always-on-top mini assistant and a dashboard for an
+// external display. Each panel has its own label, so events can be sent to it
+// alone. Mobile platforms only have the main window.
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager};
+
+#[cfg(desktop)]
+use tauri::{WebviewUrl, WebviewWindowBuilder};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PanelKind {
+    MiniAssistant,
+    Dashboard,
+}
+
+impl PanelKind {
+    const ALL: [PanelKind; 2] = [PanelKind::MiniAssistant, PanelKind::Dashboard];
+
+    fn name(self) -> &'static str {
+        match self {
+            PanelKind::MiniAssistant => "mini-assistant",
+            PanelKind::Dashboard => "dashboard",
+        }
+    }
+
+    // Window label; the capability file grants panel-* windows the app commands
+    fn label(self) -> String {
+        format!("panel-{}", self.name())
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Display {
+    pub index: usize,
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct PanelInfo {
+    pub kind: PanelKind,
+    pub label: String,
+    pub open: bool,
+}
+
+#[cfg(desktop)]
+fn build_panel(app_handle: &AppHandle, kind: PanelKind, display: Option<usize>) -> Result<(), String> {
+    let url = WebviewUrl::App(format!("/panel/{}", kind.name()).into());
+    let builder = WebviewWindowBuilder::new(app_handle, kind.label(), url).title("plates");
+    let window = match kind {
+        PanelKind::MiniAssistant => builder
+            .inner_size(360.0, 480.0)
+            .always_on_top(true)
+            .decorations(false)
+            .skip_taskbar(true)
+            .resizable(false)
+            .build(),
+        PanelKind::Dashboard => builder.inner_size(1280.0, 720.0).build(),
+    }
+    .map_err(|e| e.to_string())?;
+
+    // Move the dashboard onto the requested display and fill it
+    if let Some(index) = display {
+        let monitors = window.available_monitors().map_err(|e| e.to_string())?;
+        let monitor = monitors.get(index).ok_or("Display not found".to_string())?;
+        window.set_position(*monitor.position()).map_err(|e| e.to_string())?;
+        if kind == PanelKind::Dashboard {
+            window.set_fullscreen(true).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(mobile)]
+fn build_panel(_app_handle: &AppHandle, _kind: PanelKind, _display: Option<usize>) -> Result<(), String> {
+    Err("Panels are not available on this device".to_string())
+}
+
+#[tauri::command]
+pub fn list_displays(app_handle: AppHandle) -> Result<Vec<Display>, String> {
+    let primary = app_handle.primary_monitor().map_err(|e| e.to_string())?;
+    let monitors = app_handle.available_monitors().map_err(|e| e.to_string())?;
+    Ok(monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| Display {
+            index,
+            name: monitor.name().cloned(),
+            width: monitor.size().width,
+            height: monitor.size().height,
+            primary: primary
+                .as_ref()
+                .map(|primary| primary.name() == monitor.name() && primary.position() == monitor.position())
+                .unwrap_or(false),
+        })
+        .collect())
+}
+
+// Command to open a panel, or focus it when already open
+#[tauri::command]
+pub fn open_panel(kind: PanelKind, display: Option<usize>, app_handle: AppHandle) -> Result<PanelInfo, String> {
+    if let Some(window) = app_handle.get_webview_window(&kind.label()) {
+        window.set_focus().map_err(|e| e.to_string())?;
+    } else {
+        build_panel(&app_handle, kind, display)?;
+    }
+    let _ = app_handle.emit("panels://opened", kind);
+    Ok(PanelInfo {
+        kind,
+        label: kind.label(),
+        open: true,
+    })
+}
+
+#[tauri::command]
+pub fn close_panel(kind: PanelKind, app_handle: AppHandle) -> Result<(), String> {
+    let Some(window) = app_handle.get_webview_window(&kind.label()) else {
+        return Ok(());
+    };
+    window.close().map_err(|e| e.to_string())?;
+    let _ = app_handle.emit("panels://closed", kind);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_panels(app_handle: AppHandle) -> Vec<PanelInfo> {
+    PanelKind::ALL
+        .iter()
+        .map(|kind| PanelInfo {
+            kind: *kind,
+            label: kind.label(),
+            open: app_handle.get_webview_window(&kind.label()).is_some(),
+        })
+        .collect()
+}
+
+// Command to deliver an event to one panel only, as `panel://<event>`
+#[tauri::command]
+pub fn send_to_panel(kind: PanelKind, event: String, payload: Value, app_handle: AppHandle) -> Result<(), String> {
+    if app_handle.get_webview_window(&kind.label()).is_none() {
+        return Err("Panel is not open".to_string());
+    }
+    app_handle
+        .emit_to(kind.label().as_str(), &format!("panel://{}", event), payload)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_match_the_panel_capability() {
+        for kind in PanelKind::ALL {
+            assert!(kind.label().starts_with("panel-"));
+            // The frontend route and the serialized kind use the same name
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.name());
+        }
+        assert_eq!(PanelKind::MiniAssistant.label(), "panel-mini-assistant");
+    }
+}
@@ -4,7 +4,7 @@ import { getCurrentPosition } from '@tauri-apps/plugin-geolocation';
 // Weather data interface
 export interface WeatherData {
   temperature: string;
-  icon: string;
+  icon: string | null;
 }
 
 // Default weather data